@@ -1,8 +1,8 @@
 use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoClient};
 use aws_sdk_s3::Client as S3Client;
 use doxle_shared::{
-    annotations, auth, blocks, classes, cloudfront, image_proxy, images, invites, projects,
-    s3_multipart, users, AppState,
+    activity, annotations, audit, auth, block_move, block_stats, blocks, class_stats, classes, cloudfront, comments, duplicate, email, export, export_jobs, feed, groups, history, image_proxy, images, import, integrity, invites, locks, members, metrics, migrations, ordering, pagination, projects, region, reimport,
+    s3_multipart, sample, schema, sessions, settings, storage, storage_usage, takeoff, templates, users, AppState,
 };
 use lambda_http::{
     http::{Method, StatusCode},
@@ -46,21 +46,49 @@ pub(crate) async fn function_handler(
             )
             .header(
                 "Access-Control-Allow-Headers",
-                "Content-Type,Authorization,X-User-Id",
+                "Content-Type,Authorization,X-User-Id,Idempotency-Key",
             )
             .body(Body::Empty)
             .map_err(Box::new)?);
     }
 
+    // Known path, wrong method: 405 with Allow header
+    if let Some(rejection) = crate::routes::check_method(method, path)? {
+        return Ok(rejection);
+    }
+
+    // Read-only maintenance: writes get 503 + Retry-After
+    if let Some(rejection) = crate::middleware::check_maintenance(
+        method,
+        path,
+        doxle_shared::maintenance::retry_after(),
+    )? {
+        return Ok(rejection);
+    }
+
+    // Reject oversized or malformed bodies before any handler deserializes them
+    if let Some(rejection) = crate::middleware::check_body(method, path, body)? {
+        return Ok(rejection);
+    }
+
     // Route to auth endpoints (no JWT validation)
     if path.starts_with("/login") {
         let client_id = env::var("COGNITO_CLIENT_ID").expect("COGNITO_CLIENT_ID must be set");
         let client_secret =
             env::var("COGNITO_CLIENT_SECRET").expect("COGNITO_CLIENT_SECRET must be set");
+        let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "doxle-annotations".to_string());
 
         return match method {
             &Method::POST => {
-                auth::login(&state.cognito_client, &client_id, &client_secret, body).await
+                auth::login(
+                    &state.cognito_client,
+                    &state.dynamo_client,
+                    &table_name,
+                    &client_id,
+                    &client_secret,
+                    body,
+                )
+                .await
             }
             _ => {
                 let resp = Response::builder()
@@ -116,10 +144,19 @@ pub(crate) async fn function_handler(
         let client_id = env::var("COGNITO_CLIENT_ID").expect("COGNITO_CLIENT_ID must be set");
         let client_secret =
             env::var("COGNITO_CLIENT_SECRET").expect("COGNITO_CLIENT_SECRET must be set");
+        let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "doxle-annotations".to_string());
 
         return match method {
             &Method::POST => {
-                auth::refresh_token(&state.cognito_client, &client_id, &client_secret, body).await
+                auth::refresh_token(
+                    &state.cognito_client,
+                    &state.dynamo_client,
+                    &table_name,
+                    &client_id,
+                    &client_secret,
+                    body,
+                )
+                .await
             }
             _ => {
                 let resp = Response::builder()
@@ -139,7 +176,7 @@ pub(crate) async fn function_handler(
 
     // CloudFront signed cookies endpoint (requires JWT auth)
     if path == "/auth/cloudfront-cookies" {
-        if method != &Method::POST {
+        if method != Method::POST {
             return Ok(Response::builder()
                 .status(StatusCode::METHOD_NOT_ALLOWED)
                 .header("Content-Type", "application/json")
@@ -185,14 +222,72 @@ pub(crate) async fn function_handler(
 
         // Issue CloudFront signed cookies (valid for 12 hours)
         let origin_header = event.headers().get("Origin").and_then(|v| v.to_str().ok());
-        return cloudfront::issue_signed_cookies_response(&user_id, 43200, origin_header);
+        return cloudfront::issue_signed_cookies_response(&state.secrets_client, &user_id, 43200, origin_header).await;
     }
 
-    // Image proxy route (public - serves images from S3)
+    // Image proxy route (serves images from S3 to members of their project)
     if path.starts_with("/proxy-image/") {
         // URL format: /proxy-image/projects/{pid}/blocks/{bid}/{image}.ext
-        let image_path = path.strip_prefix("/proxy-image/").unwrap_or("");
-        return image_proxy::proxy_image(&state.s3_client, "doxle-annotations", image_path).await;
+        // ?w=800&format=webp for a resized or transcoded variant
+        let Some(object) = storage::parse_object_url(path) else {
+            return not_found();
+        };
+        let Some(project_id) = storage::s3_config()
+            .project_path(&object.key)
+            .and_then(|p| p.split('/').next())
+            .filter(|p| !p.is_empty())
+        else {
+            return not_found();
+        };
+        let param = |name: &str| event.query_string_parameters_ref().and_then(|params| params.first(name));
+
+        // The route is public so <img> tags can use ?token= (from POST
+        // /projects/{id}/image-token); a JWT counts only once API Gateway has
+        // validated it, so no X-User-Id override here
+        let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "doxle-annotations".to_string());
+        let user_id = event
+            .request_context()
+            .authorizer()
+            .and_then(|auth| auth.jwt.as_ref())
+            .and_then(|jwt| jwt.claims.get("sub"))
+            .map(|s| s.to_string());
+        if let Some(denied) = image_proxy::authorize(
+            &state.dynamo_client,
+            &table_name,
+            project_id,
+            user_id.as_deref(),
+            param("token"),
+        )
+        .await?
+        {
+            return Ok(denied);
+        }
+        let variant = match image_proxy::parse_variant(param("w"), param("format")) {
+            Ok(variant) => variant,
+            Err(e) => return pagination::invalid_page(e),
+        };
+        return image_proxy::proxy_image(
+            &state.s3_client,
+            &state.s3_config.bucket,
+            &object.key,
+            event.headers(),
+            variant,
+        )
+        .await;
+    }
+
+    // Serving region and replica reachability (public, used by failover checks)
+    if path == "/health" && method == Method::GET {
+        let table_name = env::var("TABLE_NAME").unwrap_or_else(|_| "doxle-annotations".to_string());
+        return region::get_health(&state.dynamo_client, &state.s3_client, &table_name).await;
+    }
+
+    // Model schema for client codegen (public)
+    if path == "/schema" && method == Method::GET {
+        let format = event
+            .query_string_parameters_ref()
+            .and_then(|params| params.first("format"));
+        return schema::get_schema(format);
     }
 
     // Invites routes (public GET, authenticated POST)
@@ -266,16 +361,61 @@ pub(crate) async fn function_handler(
 
         tracing::info!("User ID from JWT: {}", user_id);
 
-        return match (method, path) {
-            (&Method::POST, "/users") => {
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        return match (method, parts.as_slice()) {
+            (&Method::POST, ["users"]) => {
                 users::create_user(&state.dynamo_client, &table_name, &user_id, body).await
             }
-            (&Method::GET, "/users/me") => {
+            (&Method::GET, ["users", "me"]) => {
                 users::get_user(&state.dynamo_client, &table_name, &user_id).await
             }
-            (&Method::PATCH, "/users/me") => {
+            (&Method::PATCH, ["users", "me"]) => {
                 users::update_user(&state.dynamo_client, &table_name, &user_id, body).await
             }
+            // Onboarding (guided setup)
+            (&Method::GET, ["users", "me", "onboarding"]) => {
+                users::get_onboarding(&state.dynamo_client, &table_name, &user_id).await
+            }
+            (&Method::POST, ["users", "me", "onboarding", step]) => {
+                users::complete_onboarding_step(&state.dynamo_client, &table_name, &user_id, step)
+                    .await
+            }
+            (&Method::DELETE, ["users", "me", "onboarding"]) => {
+                users::reset_onboarding(&state.dynamo_client, &table_name, &user_id).await
+            }
+            // Sessions (signed-in devices)
+            (&Method::GET, ["users", "me", "sessions"]) => {
+                sessions::list_sessions(&state.dynamo_client, &table_name, &user_id).await
+            }
+            (&Method::DELETE, ["users", "me", "sessions"]) => {
+                sessions::revoke_all_sessions(
+                    &state.cognito_client,
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                )
+                .await
+            }
+            (&Method::DELETE, ["users", "me", "sessions", session_id]) => {
+                let client_id = env::var("COGNITO_CLIENT_ID").expect("COGNITO_CLIENT_ID must be set");
+                let client_secret =
+                    env::var("COGNITO_CLIENT_SECRET").expect("COGNITO_CLIENT_SECRET must be set");
+                let app = sessions::AppClient {
+                    client_id: &client_id,
+                    client_secret: &client_secret,
+                };
+                sessions::revoke_session(
+                    &state.cognito_client,
+                    &state.dynamo_client,
+                    &table_name,
+                    &app,
+                    &user_id,
+                    session_id,
+                    body,
+                )
+                .await
+            }
             _ => {
                 let resp = Response::builder()
                     .status(StatusCode::NOT_FOUND)
@@ -307,6 +447,71 @@ pub(crate) async fn function_handler(
         })
         .unwrap_or_else(|| "test-user-123".to_string());
 
+    // Admin routes (admin role required)
+    if path.starts_with("/admin") {
+        if !users::is_admin(&state.dynamo_client, &table_name, &user_id).await? {
+            tracing::warn!("Non-admin user {} attempted {} {}", user_id, method, path);
+            return forbidden();
+        }
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        return match (method, parts.as_slice()) {
+            // GET /admin/settings - org settings
+            (&Method::GET, ["admin", "settings"]) => {
+                settings::get_org_settings(&state.dynamo_client, &table_name).await
+            }
+            // PATCH /admin/settings - update org settings (e.g. invite domain allowlist)
+            (&Method::PATCH, ["admin", "settings"]) => {
+                settings::update_org_settings(&state.dynamo_client, &table_name, &user_id, body)
+                    .await
+            }
+            // GET /admin/emails - emails captured while EMAIL_MODE=capture
+            (&Method::GET, ["admin", "emails"]) => {
+                email::list_captured_emails(&state.dynamo_client, &table_name).await
+            }
+            // GET /admin/key-schema - single-table key layout
+            (&Method::GET, ["admin", "key-schema"]) => integrity::get_key_schema(),
+            // POST /admin/integrity-check?project_id=&apply=true - find (and repair) orphans, missing links, count drift
+            (&Method::POST, ["admin", "integrity-check"]) => {
+                let params = event.query_string_parameters_ref();
+                let project_id = params.and_then(|p| p.first("project_id"));
+                let apply = params.and_then(|p| p.first("apply")) == Some("true");
+                integrity::run_integrity_check(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    project_id,
+                    apply,
+                )
+                .await
+            }
+            // GET /admin/metrics/summary - connections, broadcasts, uploads in flight for the ops dashboard
+            (&Method::GET, ["admin", "metrics", "summary"]) => {
+                metrics::get_metrics_summary(&state.dynamo_client, &state.s3_client, &table_name).await
+            }
+            // POST /admin/annotations/purge?older_than_days= - permanently remove old deleted annotations
+            (&Method::POST, ["admin", "annotations", "purge"]) => {
+                let older_than_days = event
+                    .query_string_parameters_ref()
+                    .and_then(|p| p.first("older_than_days"));
+                annotations::purge_deleted_annotations(&state.dynamo_client, &table_name, older_than_days)
+                    .await
+            }
+            // GET /admin/migrations - registered data migrations and their progress
+            (&Method::GET, ["admin", "migrations"]) => {
+                migrations::list_migrations(&state.dynamo_client, &table_name).await
+            }
+            // POST /admin/migrations/{version}/run?max_pages= - run or resume a migration
+            (&Method::POST, ["admin", "migrations", version, "run"]) => {
+                let max_pages = event
+                    .query_string_parameters_ref()
+                    .and_then(|p| p.first("max_pages"));
+                migrations::run_migration(&state.dynamo_client, &table_name, version, max_pages).await
+            }
+            _ => not_found(),
+        };
+    }
+
     // Projects routes
     if path.starts_with("/projects") {
         let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
@@ -327,7 +532,7 @@ pub(crate) async fn function_handler(
             }
             // PATCH /projects/{id} - update project
             (&Method::PATCH, ["projects", project_id]) => {
-                projects::update_project(&state.dynamo_client, &table_name, project_id, body).await
+                projects::update_project(&state.dynamo_client, &table_name, &user_id, project_id, body).await
             }
             // DELETE /projects/{id} - delete project
             (&Method::DELETE, ["projects", project_id]) => {
@@ -341,14 +546,148 @@ pub(crate) async fn function_handler(
                 .await
             }
 
+            // POST /projects/{id}/members/batch - add existing users, invite unknown emails
+            (&Method::POST, ["projects", project_id, "members", "batch"]) => {
+                members::batch_add_members(
+                    &state.dynamo_client,
+                    &state.ses_client,
+                    &table_name,
+                    project_id,
+                    &user_id,
+                    body,
+                )
+                .await
+            }
+            // GET /projects/{id}/tree - project, settings, classes, blocks and images
+            (&Method::GET, ["projects", project_id, "tree"]) => {
+                projects::get_project_tree(&state.dynamo_client, &table_name, project_id).await
+            }
+            // GET /projects/{id}/takeoff - quantity takeoff for estimation (read-only)
+            (&Method::GET, ["projects", project_id, "takeoff"]) => {
+                takeoff::get_project_takeoff(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                )
+                .await
+            }
+            // GET /projects/{id}/annotations/sample?n=50&class_id=&seed= - random annotations for QA spot checks
+            (&Method::GET, ["projects", project_id, "annotations", "sample"]) => {
+                let params = event.query_string_parameters_ref();
+                sample::sample_project_annotations(
+                    &state.dynamo_client,
+                    &table_name,
+                    project_id,
+                    params.and_then(|p| p.first("n")),
+                    params.and_then(|p| p.first("class_id")),
+                    params.and_then(|p| p.first("seed")),
+                )
+                .await
+            }
+            // POST /projects/{id}/annotations/reassign-class - move every annotation of a class to another
+            (&Method::POST, ["projects", project_id, "annotations", "reassign-class"]) => {
+                annotations::reassign_class(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    body,
+                )
+                .await
+            }
+            // GET /projects/{id}/export?format=coco|yolo|csv|crops|huggingface&split=80/10/10&seed=&classes=&block_state=&created_after=&images=original|preview&destination=s3://bucket/prefix - export archive (presigned download), or a dataset written to S3
+            (&Method::GET, ["projects", project_id, "export"]) => {
+                export::export_project(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    project_id,
+                    export_options(&event),
+                )
+                .await
+            }
+            // GET /projects/{id}/exports/{export_id} - progress of an export the image worker is building, with its download link once ready
+            (&Method::GET, ["projects", project_id, "exports", export_id]) => {
+                export_jobs::get_export(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    project_id,
+                    export_id,
+                )
+                .await
+            }
+            // POST /projects/{id}/reimport?format=coco|csv&dry_run=true&delete_missing=false - apply an edited export as creates/updates/deletes
+            (&Method::POST, ["projects", project_id, "reimport"]) => {
+                let param = |name: &str| {
+                    event
+                        .query_string_parameters_ref()
+                        .and_then(|params| params.first(name))
+                };
+                reimport::reimport_export(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    body,
+                    reimport::ReimportOptions {
+                        format: param("format"),
+                        dry_run: param("dry_run"),
+                        delete_missing: param("delete_missing"),
+                    },
+                )
+                .await
+            }
+            // GET /projects/{id}/activity?granularity=hour|day - annotation activity, last 30 days
+            (&Method::GET, ["projects", project_id, "activity"]) => {
+                let granularity = event
+                    .query_string_parameters_ref()
+                    .and_then(|params| params.first("granularity"));
+                activity::get_project_activity(&state.dynamo_client, &table_name, project_id, granularity)
+                    .await
+            }
+            // POST /projects/{id}/image-token - token for loading the project's images through /proxy-image/
+            (&Method::POST, ["projects", project_id, "image-token"]) => {
+                image_proxy::issue_token(&state.dynamo_client, &table_name, project_id, &user_id).await
+            }
+            // GET /projects/{id}/storage - bytes the project's objects take up in S3
+            (&Method::GET, ["projects", project_id, "storage"]) => {
+                storage_usage::get_project_storage(&state.dynamo_client, &table_name, project_id).await
+            }
+            // GET /projects/{id}/audit - audit trail, oldest first (?entity_type&entity_id&actor&limit&cursor)
+            (&Method::GET, ["projects", project_id, "audit"]) => {
+                let param = |name: &str| event.query_string_parameters_ref().and_then(|params| params.first(name));
+                audit::list_project_audit(
+                    &state.dynamo_client,
+                    &table_name,
+                    project_id,
+                    page_params(&event),
+                    audit::AuditQuery {
+                        entity_type: param("entity_type"),
+                        entity_id: param("entity_id"),
+                        actor: param("actor"),
+                    },
+                )
+                .await
+            }
+
             // --- BLOCKS ---
-            // GET /projects/{id}/blocks - list project blocks
+            // GET /projects/{id}/blocks - list project blocks (?limit&cursor)
             (&Method::GET, ["projects", project_id, "blocks"]) => {
-                blocks::list_project_blocks(&state.dynamo_client, &table_name, project_id).await
+                blocks::list_project_blocks(
+                    &state.dynamo_client,
+                    &table_name,
+                    project_id,
+                    page_params(&event),
+                )
+                .await
             }
             // POST /projects/{id}/blocks - create block
             (&Method::POST, ["projects", project_id, "blocks"]) => {
-                blocks::create_block(&state.dynamo_client, &table_name, project_id, body).await
+                blocks::create_block(&state.dynamo_client, &table_name, &user_id, project_id, body).await
             }
             //GET /projects/{pid}/blocks/{bid} - get specific block
             (&Method::GET, ["projects", project_id, "blocks", block_id]) => {
@@ -359,42 +698,225 @@ pub(crate) async fn function_handler(
                 blocks::update_block(
                     &state.dynamo_client,
                     &table_name,
+                    &user_id,
                     project_id,
                     block_id,
                     body,
                 )
                 .await
             }
+            // POST /projects/{pid}/blocks/{bid}/claim - assign an unassigned block to the caller
+            (&Method::POST, ["projects", project_id, "blocks", block_id, "claim"]) => {
+                blocks::claim_block(&state.dynamo_client, &table_name, &user_id, project_id, block_id)
+                    .await
+            }
+            // POST /projects/{pid}/blocks/{bid}/duplicate?include_images=&include_annotations= - deep-copy a block
+            (&Method::POST, ["projects", project_id, "blocks", block_id, "duplicate"]) => {
+                let param = |name: &str| {
+                    event
+                        .query_string_parameters_ref()
+                        .and_then(|params| params.first(name))
+                };
+                duplicate::duplicate_block(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    block_id,
+                    duplicate::DuplicateOptions {
+                        include_images: param("include_images"),
+                        include_annotations: param("include_annotations"),
+                    },
+                )
+                .await
+            }
+            // POST /projects/{pid}/blocks/{bid}/move - move a block with its images and annotations to another project
+            (&Method::POST, ["projects", project_id, "blocks", block_id, "move"]) => {
+                block_move::move_block(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    block_id,
+                    body,
+                )
+                .await
+            }
+            // POST /projects/{pid}/blocks/{bid}/reviewer - assign or unassign the block's reviewer
+            (&Method::POST, ["projects", project_id, "blocks", block_id, "reviewer"]) => {
+                blocks::set_reviewer(&state.dynamo_client, &table_name, &user_id, project_id, block_id, body)
+                    .await
+            }
+            // GET /projects/{pid}/blocks/{bid}/activity?since=&limit=&cursor= - audit entries of the block, its images and annotations
+            (&Method::GET, ["projects", project_id, "blocks", block_id, "activity"]) => {
+                let since = event
+                    .query_string_parameters_ref()
+                    .and_then(|params| params.first("since"));
+                audit::list_block_activity(
+                    &state.dynamo_client,
+                    &table_name,
+                    project_id,
+                    block_id,
+                    page_params(&event),
+                    since,
+                )
+                .await
+            }
+            // GET /projects/{pid}/blocks/{bid}/feed - comments, state changes and assignments
+            (&Method::GET, ["projects", project_id, "blocks", block_id, "feed"]) => {
+                feed::get_block_feed(&state.dynamo_client, &table_name, &user_id, project_id, block_id)
+                    .await
+            }
+            // POST /projects/{pid}/blocks/{bid}/import/cvat - import CVAT 1.1 XML annotations
+            (&Method::POST, ["projects", project_id, "blocks", block_id, "import", "cvat"]) => {
+                import::import_cvat(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    block_id,
+                    body,
+                )
+                .await
+            }
+            // GET /projects/{pid}/blocks/{bid}/export?format=...&split=&seed=&classes=&block_state=&created_after=&images=&destination= - export one block
+            (&Method::GET, ["projects", project_id, "blocks", block_id, "export"]) => {
+                export::export_block(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    project_id,
+                    block_id,
+                    export_options(&event),
+                )
+                .await
+            }
             // DELETE /projects/{pid}/blocks/{bid} - delete block
             (&Method::DELETE, ["projects", project_id, "blocks", block_id]) => {
                 blocks::delete_block(
                     &state.dynamo_client,
                     &state.s3_client,
                     &table_name,
+                    &user_id,
                     project_id,
                     block_id,
                 )
                 .await
             }
 
+            // GET /projects/{pid}/blocks/{bid}/stats - image and annotation progress of a block
+            (&Method::GET, ["projects", project_id, "blocks", block_id, "stats"]) => {
+                block_stats::get_block_stats(&state.dynamo_client, &table_name, project_id, block_id).await
+            }
+
             // --- IMAGES ---
-            // GET /projects/{pid}/blocks/{bid}/images - list images for a block
-            (&Method::GET, ["projects", _project_id, "blocks", block_id, "images"]) => {
-                images::list_block_images(&state.dynamo_client, &table_name, block_id).await
+            // GET /projects/{pid}/blocks/{bid}/images - list images for a block (?tag&status&limit&cursor)
+            (&Method::GET, ["projects", project_id, "blocks", block_id, "images"]) => match image_filter(&event) {
+                Ok(filter) => {
+                    images::list_block_images(
+                        &state.dynamo_client,
+                        &table_name,
+                        project_id,
+                        block_id,
+                        filter,
+                        page_params(&event),
+                    )
+                    .await
+                }
+                Err(e) => pagination::invalid_page(e),
             }
             // POST /projects/{pid}/blocks/{bid}/images - create image in  block
-            (&Method::POST, ["projects", _project_id, "blocks", block_id, "images"]) => {
-                images::create_image(&state.dynamo_client, &table_name, block_id, body).await
+            (&Method::POST, ["projects", project_id, "blocks", block_id, "images"]) => {
+                images::create_image(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    block_id,
+                    body,
+                )
+                .await
+            }
+            // POST /projects/{pid}/blocks/{bid}/images/reorder - re-apply EXIF/filename ordering
+            (&Method::POST, ["projects", project_id, "blocks", block_id, "images", "reorder"]) => {
+                ordering::reorder_block_images(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    block_id,
+                )
+                .await
+            }
+            // PATCH /projects/{pid}/blocks/{bid}/images/order - set the order of the block's images in one go
+            (&Method::PATCH, ["projects", project_id, "blocks", block_id, "images", "order"]) => {
+                ordering::set_image_order(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    project_id,
+                    block_id,
+                    body,
+                )
+                .await
             }
 
             // --- CLASSES ---
-            // GET /projects/{id}/classes - list project classes
+            // GET /projects/{id}/annotations - annotations across the project (?class_id&tag&created_by&source&min_confidence&since&limit&cursor)
+            (&Method::GET, ["projects", project_id, "annotations"]) => {
+                let param = |name: &str| {
+                    event
+                        .query_string_parameters_ref()
+                        .and_then(|params| params.first(name))
+                };
+                match annotation_filter(&event) {
+                    Ok(filter) => {
+                        annotations::list_project_annotations(
+                            &state.dynamo_client,
+                            &table_name,
+                            project_id,
+                            filter,
+                            param("since"),
+                            page_params(&event),
+                        )
+                        .await
+                    }
+                    Err(e) => pagination::invalid_page(e),
+                }
+            }
+            // GET /projects/{id}/classes - list project classes (?limit&cursor)
             (&Method::GET, ["projects", project_id, "classes"]) => {
-                classes::list_project_classes(&state.dynamo_client, &table_name, project_id).await
+                classes::list_project_classes(
+                    &state.dynamo_client,
+                    &table_name,
+                    project_id,
+                    page_params(&event),
+                )
+                .await
             }
             // POST /projects/{id}/classes - create class
             (&Method::POST, ["projects", project_id, "classes"]) => {
-                classes::create_class(&state.dynamo_client, &table_name, project_id, body).await
+                classes::create_class(&state.dynamo_client, &table_name, &user_id, project_id, body).await
+            }
+            // POST /projects/{id}/classes/batch - create many classes at once
+            (&Method::POST, ["projects", project_id, "classes", "batch"]) => {
+                classes::batch_create_classes(&state.dynamo_client, &table_name, &user_id, project_id, body).await
+            }
+            // POST /projects/{id}/classes/reorder - set the display order of classes
+            (&Method::POST, ["projects", project_id, "classes", "reorder"]) => {
+                classes::reorder_classes(&state.dynamo_client, &table_name, &user_id, project_id, body).await
+            }
+            // POST /projects/{id}/classes/apply-template/{tid} - add a template's missing classes
+            (&Method::POST, ["projects", project_id, "classes", "apply-template", template_id]) => {
+                templates::apply_template(&state.dynamo_client, &table_name, &user_id, project_id, template_id).await
+            }
+            // GET /projects/{pid}/classes/{cid}/stats - annotation counts of a class
+            (&Method::GET, ["projects", project_id, "classes", class_id, "stats"]) => {
+                class_stats::get_class_stats(&state.dynamo_client, &table_name, project_id, class_id).await
             }
             // GET /projects/{pid}/classes/{cid} - get class
             (&Method::GET, ["projects", project_id, "classes", class_id]) => {
@@ -405,6 +927,7 @@ pub(crate) async fn function_handler(
                 classes::update_class(
                     &state.dynamo_client,
                     &table_name,
+                    &user_id,
                     project_id,
                     class_id,
                     body,
@@ -413,7 +936,7 @@ pub(crate) async fn function_handler(
             }
             // DELETE /projects/{pid}/classes/{cid} - delete class
             (&Method::DELETE, ["projects", project_id, "classes", class_id]) => {
-                classes::delete_class(&state.dynamo_client, &table_name, project_id, class_id).await
+                classes::delete_class(&state.dynamo_client, &table_name, &user_id, project_id, class_id).await
             }
             _ => not_found(),
         };
@@ -430,10 +953,37 @@ pub(crate) async fn function_handler(
                 let request: s3_multipart::InitiateUploadRequest = serde_json::from_slice(body)?;
                 s3_multipart::initiate_upload(&state.s3_client, request).await
             }
+            // POST /annotate/upload/initiate-batch - initiate every upload of a manifest
+            (&Method::POST, ["annotate", "upload", "initiate-batch"]) => {
+                let request: s3_multipart::InitiateBatchRequest = serde_json::from_slice(body)?;
+                s3_multipart::initiate_batch_upload(&state.s3_client, request).await
+            }
             // POST /annotate/upload/complete - complete multipart upload
             (&Method::POST, ["annotate", "upload", "complete"]) => {
                 let request: s3_multipart::CompleteMultipartRequest = serde_json::from_slice(body)?;
-                s3_multipart::complete_multipart_upload(&state.s3_client, request).await
+                s3_multipart::complete_multipart_upload(&state.dynamo_client, &state.s3_client, &table_name, &user_id, request).await
+            }
+            // GET /annotate/upload/{upload_id}/parts?project_id&block_id&image_id&extension&file_size - parts uploaded so far, with fresh URLs for the rest
+            (&Method::GET, ["annotate", "upload", upload_id, "parts"]) => {
+                let params = event.query_string_parameters_ref();
+                let param = |name: &str| params.and_then(|params| params.first(name));
+                let project_id = param("project_id").ok_or("Missing project id query parameter")?;
+                let block_id = param("block_id").ok_or("Missing block id query parameter")?;
+                let image_id = param("image_id").ok_or("Missing image id query parameter")?;
+                let extension = param("extension").ok_or("Missing extension query parameter")?;
+                let file_size = param("file_size")
+                    .map(|size| size.parse::<usize>().map_err(|_| "file_size must be a whole number of bytes"))
+                    .transpose()?;
+                s3_multipart::list_upload_parts(
+                    &state.s3_client,
+                    project_id,
+                    block_id,
+                    image_id,
+                    upload_id,
+                    extension,
+                    file_size,
+                )
+                .await
             }
             // DELETE /annotate/upload/abort - abort multipart upload
             (&Method::DELETE, ["annotate", "upload", "abort"]) => {
@@ -471,7 +1021,7 @@ pub(crate) async fn function_handler(
                     .query_string_parameters_ref()
                     .and_then(|params| params.first("block_id"))
                     .ok_or("Missing block id query parameter")?;
-                images::update_image(&state.dynamo_client, &table_name, block_id, image_id, body)
+                images::update_image(&state.dynamo_client, &table_name, &user_id, block_id, image_id, body)
                     .await
             }
             // DELETE /images/{id} - delete image
@@ -480,41 +1030,120 @@ pub(crate) async fn function_handler(
                     .query_string_parameters_ref()
                     .and_then(|params| params.first("block_id"))
                     .ok_or("Missing block id query parameter")?;
-                images::delete_image(&state.dynamo_client, &table_name, block_id, image_id).await
+                images::delete_image(&state.dynamo_client, &state.s3_client, &table_name, &user_id, block_id, image_id).await
             }
-            // GET /images/{id}/annotations - list image annotations
-            (&Method::GET, ["images", image_id, "annotations"]) => {
-                annotations::list_image_annotations(&state.dynamo_client, &table_name, image_id)
+            // POST /images/{id}/replace - swap the file, rescaling annotations (?block_id&project_id)
+            (&Method::POST, ["images", image_id, "replace"]) => {
+                let params = event.query_string_parameters_ref();
+                let block_id = params
+                    .and_then(|params| params.first("block_id"))
+                    .ok_or("Missing block id query parameter")?;
+                let project_id = params
+                    .and_then(|params| params.first("project_id"))
+                    .ok_or("Missing project id query parameter")?;
+                images::replace_image(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    &images::ImageLocation {
+                        project_id: project_id.to_string(),
+                        block_id: block_id.to_string(),
+                    },
+                    image_id,
+                    body,
+                )
+                .await
+            }
+            // POST /images/{id}/move - move (or copy) the image with its annotations to another block
+            (&Method::POST, ["images", image_id, "move"]) => {
+                block_move::move_image(
+                    &state.dynamo_client,
+                    &state.s3_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
+                    body,
+                )
+                .await
+            }
+            // GET /images/{id}/download-url?level=full|preview|thumb - short-lived presigned S3 link
+            (&Method::GET, ["images", image_id, "download-url"]) => {
+                let level = event
+                    .query_string_parameters_ref()
+                    .and_then(|params| params.first("level"));
+                images::get_download_url(&state.dynamo_client, &state.s3_client, &table_name, image_id, level).await
+            }
+            // GET /images/{id}/annotations - list image annotations (?class_id&tag&created_by&source&min_confidence&limit&cursor)
+            (&Method::GET, ["images", image_id, "annotations"]) => match annotation_filter(&event) {
+                Ok(filter) => {
+                    annotations::list_image_annotations(
+                        &state.dynamo_client,
+                        &table_name,
+                        image_id,
+                        filter,
+                        page_params(&event),
+                    )
                     .await
+                }
+                Err(e) => pagination::invalid_page(e),
             }
-            // POST /images/{id}/annotations - create annotation (requires ?project_id)
+            // POST /images/{id}/annotations - create annotation
             (&Method::POST, ["images", image_id, "annotations"]) => {
-                let project_id = event
-                    .query_string_parameters_ref()
-                    .and_then(|params| params.first("project_id"))
-                    .unwrap_or("unknown");
                 annotations::create_annotation(
                     &state.dynamo_client,
                     &table_name,
                     &user_id,
                     image_id,
-                    project_id,
+                    body,
+                    annotation_write_options(&event),
+                )
+                .await
+            }
+            // POST /images/{iid}/annotations/propagate - copy annotations to other images in the block
+            (&Method::POST, ["images", image_id, "annotations", "propagate"]) => {
+                annotations::propagate_annotations(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
                     body,
                 )
                 .await
             }
             // POST /images/{id}/annotations/batch - batch create annotations
             (&Method::POST, ["images", image_id, "annotations", "batch"]) => {
-                let project_id = event
-                    .query_string_parameters_ref()
-                    .and_then(|params| params.first("project_id"))
-                    .unwrap_or("unknown");
                 annotations::batch_create_annotations(
                     &state.dynamo_client,
                     &table_name,
                     &user_id,
                     image_id,
-                    project_id,
+                    body,
+                    annotation_write_options(&event),
+                )
+                .await
+            }
+            // GET /images/{iid}/annotations/deleted - deleted annotations awaiting restore or purge (?limit&cursor)
+            (&Method::GET, ["images", image_id, "annotations", "deleted"]) => {
+                annotations::list_deleted_annotations(
+                    &state.dynamo_client,
+                    &table_name,
+                    image_id,
+                    page_params(&event),
+                )
+                .await
+            }
+            // POST /images/{iid}/annotations/reorder - set draw order (body {"annotation_ids"}, bottom to top)
+            (&Method::POST, ["images", image_id, "annotations", "reorder"]) => {
+                annotations::reorder_annotations(&state.dynamo_client, &table_name, &user_id, image_id, body).await
+            }
+            // POST /images/{iid}/annotations/review - set the review status of many annotations
+            (&Method::POST, ["images", image_id, "annotations", "review"]) => {
+                annotations::bulk_review_annotations(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
                     body,
                 )
                 .await
@@ -529,34 +1158,196 @@ pub(crate) async fn function_handler(
                 )
                 .await
             }
-            // PATCH /images/{iid}/annotations/{aid} - update annotation
+            // PATCH /images/{iid}/annotations/{aid} - update annotation (409 while another user holds its lock)
             (&Method::PATCH, ["images", image_id, "annotations", annotation_id]) => {
-                let project_id = event
-                    .query_string_parameters_ref()
-                    .and_then(|params| params.first("project_id"))
-                    .unwrap_or("unknown");
                 annotations::update_annotation(
                     &state.dynamo_client,
                     &table_name,
+                    &user_id,
                     image_id,
                     annotation_id,
-                    project_id,
                     body,
+                    annotation_write_options(&event),
                 )
                 .await
             }
             // DELETE /images/{iid}/annotations/{aid} - delete annotation
             (&Method::DELETE, ["images", image_id, "annotations", annotation_id]) => {
-                let project_id = event
-                    .query_string_parameters_ref()
-                    .and_then(|params| params.first("project_id"))
-                    .unwrap_or("unknown");
                 annotations::delete_annotation(
                     &state.dynamo_client,
                     &table_name,
+                    &user_id,
                     image_id,
                     annotation_id,
-                    project_id,
+                )
+                .await
+            }
+            // POST /images/{iid}/annotations/{aid}/restore - undo a delete
+            (&Method::POST, ["images", image_id, "annotations", annotation_id, "restore"]) => {
+                annotations::restore_annotation(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
+                    annotation_id,
+                )
+                .await
+            }
+            // GET /images/{iid}/annotations/{aid}/history - recorded versions, oldest first
+            (&Method::GET, ["images", image_id, "annotations", annotation_id, "history"]) => {
+                history::get_history(&state.dynamo_client, &table_name, image_id, annotation_id)
+                    .await
+            }
+            // POST /images/{iid}/annotations/{aid}/approve | reject - review one annotation (body {"reason"})
+            (&Method::POST, ["images", image_id, "annotations", annotation_id, action @ ("approve" | "reject")]) => {
+                let status = if *action == "approve" { "approved" } else { "rejected" };
+                annotations::review_annotation(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
+                    annotation_id,
+                    status,
+                    body,
+                )
+                .await
+            }
+            // POST /images/{iid}/annotations/{aid}/revert?to_version=n - restore an earlier version
+            (&Method::POST, ["images", image_id, "annotations", annotation_id, "revert"]) => {
+                let params = event.query_string_parameters_ref();
+                annotations::revert_annotation(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
+                    annotation_id,
+                    params.and_then(|params| params.first("to_version")),
+                )
+                .await
+            }
+
+            // --- LOCKS ---
+            // GET/POST/DELETE /images/{id}/lock - image edit lock (POST also heartbeats)
+            (&Method::GET, ["images", image_id, "lock"]) => {
+                locks::get_lock_response(&state.dynamo_client, &table_name, "image", image_id)
+                    .await
+            }
+            (&Method::POST, ["images", image_id, "lock"]) => {
+                locks::acquire_lock_response(
+                    &state.dynamo_client,
+                    &table_name,
+                    "image",
+                    image_id,
+                    image_id,
+                    &user_id,
+                    None,
+                )
+                .await
+            }
+            (&Method::DELETE, ["images", image_id, "lock"]) => {
+                locks::release_lock_response(
+                    &state.dynamo_client,
+                    &table_name,
+                    "image",
+                    image_id,
+                    &user_id,
+                )
+                .await
+            }
+            // GET/POST/DELETE /images/{iid}/annotations/{aid}/lock - annotation edit lock
+            (&Method::GET, ["images", _image_id, "annotations", annotation_id, "lock"]) => {
+                locks::get_lock_response(
+                    &state.dynamo_client,
+                    &table_name,
+                    "annotation",
+                    annotation_id,
+                )
+                .await
+            }
+            (&Method::POST, ["images", image_id, "annotations", annotation_id, "lock"]) => {
+                locks::acquire_lock_response(
+                    &state.dynamo_client,
+                    &table_name,
+                    "annotation",
+                    annotation_id,
+                    image_id,
+                    &user_id,
+                    None,
+                )
+                .await
+            }
+            (&Method::DELETE, ["images", _image_id, "annotations", annotation_id, "lock"]) => {
+                locks::release_lock_response(
+                    &state.dynamo_client,
+                    &table_name,
+                    "annotation",
+                    annotation_id,
+                    &user_id,
+                )
+                .await
+            }
+
+            // --- GROUPS ---
+            // GET /images/{iid}/groups - groups on an image with their member ids
+            (&Method::GET, ["images", image_id, "groups"]) => {
+                groups::list_groups(&state.dynamo_client, &table_name, image_id).await
+            }
+            // POST /images/{iid}/groups - group annotations (body {"annotation_ids", "group_id"})
+            (&Method::POST, ["images", image_id, "groups"]) => {
+                groups::create_group(&state.dynamo_client, &table_name, &user_id, image_id, body).await
+            }
+            // GET /images/{iid}/groups/{gid} - annotations in a group
+            (&Method::GET, ["images", image_id, "groups", group_id]) => {
+                groups::get_group(&state.dynamo_client, &table_name, image_id, group_id).await
+            }
+            // DELETE /images/{iid}/groups/{gid} - break a group up
+            (&Method::DELETE, ["images", image_id, "groups", group_id]) => {
+                groups::delete_group(&state.dynamo_client, &table_name, &user_id, image_id, group_id).await
+            }
+
+            // --- COMMENTS ---
+            // GET /images/{id}/bundle - annotations plus review comments
+            (&Method::GET, ["images", image_id, "bundle"]) => {
+                comments::get_image_bundle(&state.dynamo_client, &table_name, image_id).await
+            }
+            // GET /images/{id}/comments - list comments (optional ?annotation_id)
+            (&Method::GET, ["images", image_id, "comments"]) => {
+                let annotation_id = event
+                    .query_string_parameters_ref()
+                    .and_then(|params| params.first("annotation_id"));
+                comments::list_image_comments(
+                    &state.dynamo_client,
+                    &table_name,
+                    image_id,
+                    annotation_id,
+                )
+                .await
+            }
+            // POST /images/{id}/comments - create comment
+            (&Method::POST, ["images", image_id, "comments"]) => {
+                comments::create_comment(&state.dynamo_client, &table_name, &user_id, image_id, body)
+                    .await
+            }
+            // PATCH /images/{iid}/comments/{cid} - edit or resolve comment
+            (&Method::PATCH, ["images", image_id, "comments", comment_id]) => {
+                comments::update_comment(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
+                    comment_id,
+                    body,
+                )
+                .await
+            }
+            // DELETE /images/{iid}/comments/{cid} - delete comment
+            (&Method::DELETE, ["images", image_id, "comments", comment_id]) => {
+                comments::delete_comment(
+                    &state.dynamo_client,
+                    &table_name,
+                    &user_id,
+                    image_id,
+                    comment_id,
                 )
                 .await
             }
@@ -564,36 +1355,139 @@ pub(crate) async fn function_handler(
         };
     }
 
+    // Class template routes (label sets shared across projects)
+    if path.starts_with("/class-templates") {
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        return match (method, parts.as_slice()) {
+            // GET /class-templates - list templates (?limit&cursor)
+            (&Method::GET, ["class-templates"]) => {
+                templates::list_templates(&state.dynamo_client, &table_name, page_params(&event)).await
+            }
+            // POST /class-templates - create template
+            (&Method::POST, ["class-templates"]) => {
+                templates::create_template(&state.dynamo_client, &table_name, &user_id, body).await
+            }
+            // GET /class-templates/{tid} - get template
+            (&Method::GET, ["class-templates", template_id]) => {
+                templates::get_template(&state.dynamo_client, &table_name, template_id).await
+            }
+            // PATCH /class-templates/{tid} - update template (creator or admin)
+            (&Method::PATCH, ["class-templates", template_id]) => {
+                templates::update_template(&state.dynamo_client, &table_name, &user_id, template_id, body).await
+            }
+            // DELETE /class-templates/{tid} - delete template (creator or admin)
+            (&Method::DELETE, ["class-templates", template_id]) => {
+                templates::delete_template(&state.dynamo_client, &table_name, &user_id, template_id).await
+            }
+            _ => not_found(),
+        };
+    }
+
     // No matching route
     tracing::warn!("⚠️ No route matched - Method: {} Path: {}", method, path);
     not_found()
 }
 
-// Helper: parse bucket and key from an S3 URL like https://bucket.s3.amazonaws.com/key or https://s3.<region>.amazonaws.com/bucket/key
-fn _parse_bucket_and_key(url: &str) -> Option<(String, String)> {
-    let no_scheme = url
-        .strip_prefix("https://")
-        .or_else(|| url.strip_prefix("http://"))
-        .unwrap_or(url);
-    let (host, path) = no_scheme.split_once('/')?;
-
-    // Handle both formats:
-    // 1. bucket.s3.amazonaws.com/key
-    // 2. s3.region.amazonaws.com/bucket/key
-    let (bucket, key) = if host.starts_with("s3.") {
-        // Format: s3.region.amazonaws.com/bucket/key
-        let parts: Vec<&str> = path.splitn(2, '/').collect();
-        if parts.len() == 2 {
-            (parts[0].to_string(), parts[1].to_string())
-        } else {
-            return None;
-        }
-    } else {
-        // Format: bucket.s3.amazonaws.com/key
-        (host.split(".s3").next()?.to_string(), path.to_string())
+// Helper: annotation write options from the query string
+fn annotation_write_options(event: &Request) -> annotations::WriteOptions<'_> {
+    let param = |name: &str| {
+        event
+            .query_string_parameters_ref()
+            .and_then(|params| params.first(name))
+    };
+    annotations::WriteOptions {
+        simplify: param("simplify"),
+        coordinates: param("coordinates"),
+        image_width: param("image_width"),
+        image_height: param("image_height"),
+        idempotency_key: event
+            .headers()
+            .get("idempotency-key")
+            .and_then(|v| v.to_str().ok()),
+    }
+}
+
+// Helper: ?class_id=&tag=&created_by=&source=&min_confidence= of an annotation listing
+fn annotation_filter(event: &Request) -> Result<annotations::AnnotationFilter<'_>, String> {
+    let param = |name: &str| {
+        event
+            .query_string_parameters_ref()
+            .and_then(|params| params.first(name))
+    };
+    if let Some(source) = param("source").filter(|s| !annotations::ANNOTATION_SOURCES.contains(s)) {
+        return Err(format!(
+            "Unknown source '{}', expected one of: {}",
+            source,
+            annotations::ANNOTATION_SOURCES.join(", ")
+        ));
+    }
+    let min_confidence = param("min_confidence")
+        .map(|c| c.parse::<f32>().map_err(|_| "min_confidence must be a number".to_string()))
+        .transpose()?;
+    Ok(annotations::AnnotationFilter {
+        class_id: param("class_id"),
+        tag: param("tag"),
+        created_by: param("created_by"),
+        source: param("source"),
+        min_confidence,
+    })
+}
+
+// Helper: ?tag=&status= of a block's image listing
+fn image_filter(event: &Request) -> Result<images::ImageFilter<'_>, String> {
+    let param = |name: &str| {
+        event
+            .query_string_parameters_ref()
+            .and_then(|params| params.first(name))
     };
+    if let Some(status) = param("status").filter(|s| !images::IMAGE_STATUSES.contains(s)) {
+        return Err(format!(
+            "Unknown status '{}', expected one of: {}",
+            status,
+            images::IMAGE_STATUSES.join(", ")
+        ));
+    }
+    Ok(images::ImageFilter {
+        tag: param("tag"),
+        status: param("status"),
+    })
+}
 
-    Some((bucket, key))
+// Helper: ?limit=&cursor= of a listing
+fn page_params(event: &Request) -> pagination::PageParams<'_> {
+    let param = |name: &str| {
+        event
+            .query_string_parameters_ref()
+            .and_then(|params| params.first(name))
+    };
+    pagination::PageParams {
+        limit: param("limit"),
+        cursor: param("cursor"),
+    }
+}
+
+// Helper: export options from the query string
+fn export_options(event: &Request) -> export::ExportOptions<'_> {
+    let param = |name: &str| {
+        event
+            .query_string_parameters_ref()
+            .and_then(|params| params.first(name))
+    };
+    export::ExportOptions {
+        format: param("format"),
+        split: param("split"),
+        seed: param("seed"),
+        classes: param("classes"),
+        block_state: param("block_state"),
+        created_after: param("created_after"),
+        images: param("images"),
+        destination: param("destination"),
+        include_rejected: param("include_rejected"),
+        source: param("source"),
+        min_confidence: param("min_confidence"),
+        image_status: param("image_status"),
+    }
 }
 
 async fn _list_block_images_signed(
@@ -625,11 +1519,10 @@ async fn _list_block_images_signed(
                     .unwrap_or_default();
 
                 // Generate Lambda proxy URL
-                let final_url = if let Some((_bucket, key)) = _parse_bucket_and_key(&url_str) {
+                let final_url = match storage::object_key(&url_str) {
                     // Return URL that goes through Lambda proxy
-                    format!("https://api.doxle.ai/proxy-image/{}", key)
-                } else {
-                    url_str.clone()
+                    Some(key) => storage::proxy_url(&key),
+                    None => url_str.clone(),
                 };
 
                 let locked = item
@@ -687,3 +1580,12 @@ fn not_found() -> Result<Response<Body>, Error> {
         .body(serde_json::json!({"error": "Not found"}).to_string().into())
         .map_err(Box::new)?)
 }
+
+fn forbidden() -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": "Forbidden"}).to_string().into())
+        .map_err(Box::new)?)
+}
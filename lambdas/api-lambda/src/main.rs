@@ -4,10 +4,13 @@ use aws_sdk_dynamodb::Client as DynamoClient;
 use aws_sdk_apigatewaymanagement::Client as ApiGatewayManagementClient;
 use aws_sdk_s3::Client as S3Client;
 use aws_sdk_sesv2::Client as SesClient;
+use aws_sdk_secretsmanager::Client as SecretsClient;
 use doxle_shared::AppState;
 use std::sync::Arc;
 
 mod http_handler;
+mod middleware;
+mod routes;
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
@@ -29,6 +32,7 @@ async fn main() -> Result<(), Error> {
         DynamoClient::new(&config),
         S3Client::new(&config),
         SesClient::new(&config),
+        SecretsClient::new(&config),
         api_gateway_client,
     );
     
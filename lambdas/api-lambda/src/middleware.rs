@@ -0,0 +1,326 @@
+use lambda_http::{
+    http::{Method, StatusCode},
+    Body, Error, Response,
+};
+use std::env;
+
+/// Body size classes; each has its own limit so bulk endpoints can accept
+/// larger payloads without raising the ceiling for everything else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RouteClass {
+    Default,
+    Batch,
+    Import,
+}
+
+impl RouteClass {
+    pub(crate) fn for_path(path: &str) -> Self {
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if parts.contains(&"import") || parts.last() == Some(&"reimport") {
+            RouteClass::Import
+        } else if parts.last() == Some(&"batch") {
+            RouteClass::Batch
+        } else {
+            RouteClass::Default
+        }
+    }
+
+    /// Limit in bytes, overridable with MAX_BODY_BYTES / MAX_BATCH_BODY_BYTES /
+    /// MAX_IMPORT_BODY_BYTES. Lambda itself caps synchronous payloads at 6 MB.
+    pub(crate) fn max_body_bytes(self) -> usize {
+        let (var, default) = match self {
+            RouteClass::Default => ("MAX_BODY_BYTES", 256 * 1024),
+            RouteClass::Batch => ("MAX_BATCH_BODY_BYTES", 2 * 1024 * 1024),
+            RouteClass::Import => ("MAX_IMPORT_BODY_BYTES", 6 * 1024 * 1024),
+        };
+        env::var(var)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    }
+}
+
+/// Deepest object/array nesting accepted before deserialization
+const DEFAULT_MAX_JSON_DEPTH: usize = 32;
+
+fn max_json_depth() -> usize {
+    env::var("MAX_JSON_DEPTH")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_JSON_DEPTH)
+}
+
+/// Nesting depth of a JSON document, stopping early once `limit` is exceeded.
+/// Brackets inside string literals are ignored.
+pub(crate) fn json_depth(body: &[u8], limit: usize) -> usize {
+    let mut depth = 0usize;
+    let mut max_depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for &b in body {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+                if max_depth > limit {
+                    break;
+                }
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max_depth
+}
+
+fn reject(status: StatusCode, body: serde_json::Value) -> Result<Option<Response<Body>>, Error> {
+    Ok(Some(
+        Response::builder()
+            .status(status)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(body.to_string().into())
+            .map_err(Box::new)?,
+    ))
+}
+
+/// Check the request body before it reaches a handler.
+/// Returns a 413/400 response when the body is too large, too deeply nested
+/// or not valid JSON; `None` lets the request through.
+pub(crate) fn check_body(
+    method: &Method,
+    path: &str,
+    body: &[u8],
+) -> Result<Option<Response<Body>>, Error> {
+    if body.is_empty() || method == Method::GET || method == Method::HEAD {
+        return Ok(None);
+    }
+
+    let class = RouteClass::for_path(path);
+    let limit = class.max_body_bytes();
+    if body.len() > limit {
+        tracing::warn!(
+            "Rejected {} {}: body {} bytes exceeds {:?} limit {}",
+            method,
+            path,
+            body.len(),
+            class,
+            limit
+        );
+        return reject(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            serde_json::json!({
+                "error": "Request body too large",
+                "code": "payload_too_large",
+                "limit_bytes": limit,
+                "received_bytes": body.len(),
+            }),
+        );
+    }
+
+    // Importers also accept raw XML and CSV documents; only JSON is checked here
+    let first = body.iter().find(|b| !b.is_ascii_whitespace());
+    if class == RouteClass::Import && !matches!(first, Some(b'{') | Some(b'[')) {
+        return Ok(None);
+    }
+
+    let max_depth = max_json_depth();
+    if json_depth(body, max_depth) > max_depth {
+        return reject(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({
+                "error": "JSON nested too deeply",
+                "code": "json_too_deep",
+                "max_depth": max_depth,
+            }),
+        );
+    }
+
+    if let Err(e) = serde_json::from_slice::<serde::de::IgnoredAny>(body) {
+        return reject(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({
+                "error": "Invalid JSON body",
+                "code": "invalid_json",
+                "line": e.line(),
+                "column": e.column(),
+                "detail": e.to_string(),
+            }),
+        );
+    }
+
+    Ok(None)
+}
+
+/// Auth endpoints stay open during maintenance so users can still sign in and read
+const MAINTENANCE_EXEMPT: &[&str] = &["/login", "/refresh", "/auth/cloudfront-cookies"];
+/// Migrations are what maintenance windows are usually for
+const MAINTENANCE_EXEMPT_PREFIX: &str = "/admin/migrations/";
+
+/// GETs that still write: project and block exports enqueue a job and write
+/// their archive or dataset to S3
+fn writes_on_get(path: &str) -> bool {
+    path.starts_with("/projects/") && path.trim_end_matches('/').ends_with("/export")
+}
+
+/// Reject mutating requests with 503 + Retry-After while maintenance mode is on
+/// (`retry_after` is `Some`). Reads other than exports, preflight and sign-in
+/// pass through.
+pub(crate) fn check_maintenance(
+    method: &Method,
+    path: &str,
+    retry_after: Option<u64>,
+) -> Result<Option<Response<Body>>, Error> {
+    let Some(retry_after) = retry_after else {
+        return Ok(None);
+    };
+    let read = match *method {
+        Method::GET => !writes_on_get(path),
+        Method::HEAD | Method::OPTIONS => true,
+        _ => false,
+    };
+    if read || MAINTENANCE_EXEMPT.contains(&path) || path.starts_with(MAINTENANCE_EXEMPT_PREFIX) {
+        return Ok(None);
+    }
+    tracing::warn!("Rejected {} {}: maintenance mode", method, path);
+    Ok(Some(doxle_shared::maintenance::unavailable(retry_after)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_class() {
+        assert_eq!(
+            RouteClass::for_path("/images/i1/annotations/batch"),
+            RouteClass::Batch
+        );
+        assert_eq!(
+            RouteClass::for_path("/projects/p1/blocks/b1/import/cvat"),
+            RouteClass::Import
+        );
+        assert_eq!(
+            RouteClass::for_path("/projects/p1/reimport"),
+            RouteClass::Import
+        );
+        assert_eq!(RouteClass::for_path("/projects/p1"), RouteClass::Default);
+    }
+
+    #[test]
+    fn test_json_depth() {
+        assert_eq!(json_depth(br#"{"a": 1}"#, 32), 1);
+        assert_eq!(json_depth(br#"{"a": [{"b": []}]}"#, 32), 4);
+        // Brackets inside strings don't count
+        assert_eq!(json_depth(br#"{"a": "[[[{\"\\"}"#, 32), 1);
+        // Stops counting past the limit
+        assert_eq!(json_depth(&[b'['; 10_000], 32), 33);
+    }
+
+    #[test]
+    fn test_check_body() {
+        let ok = check_body(&Method::POST, "/projects", br#"{"name": "x"}"#).unwrap();
+        assert!(ok.is_none());
+
+        let invalid = check_body(&Method::POST, "/projects", b"{name")
+            .unwrap()
+            .unwrap();
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+
+        let deep = "[".repeat(100) + &"]".repeat(100);
+        let deep = check_body(&Method::POST, "/projects", deep.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(deep.status(), StatusCode::BAD_REQUEST);
+
+        let large = vec![b' '; 300 * 1024];
+        let large = check_body(&Method::PATCH, "/projects/p1", &large)
+            .unwrap()
+            .unwrap();
+        assert_eq!(large.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let xml = check_body(
+            &Method::POST,
+            "/projects/p/blocks/b/import/cvat",
+            b"<annotations/>",
+        )
+        .unwrap();
+        assert!(xml.is_none());
+
+        let csv = check_body(
+            &Method::POST,
+            "/projects/p/reimport",
+            b"annotation_id,image_id,class_id\na1,i1,c1\n",
+        )
+        .unwrap();
+        assert!(csv.is_none());
+
+        // COCO re-imports are still checked as JSON, at the import limit
+        let coco = check_body(&Method::POST, "/projects/p/reimport", b"{\"images\": [")
+            .unwrap()
+            .unwrap();
+        assert_eq!(coco.status(), StatusCode::BAD_REQUEST);
+        let coco = vec![b' '; 300 * 1024];
+        assert!(check_body(&Method::POST, "/projects/p/reimport", &coco)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_check_maintenance() {
+        assert!(check_maintenance(&Method::POST, "/projects", None)
+            .unwrap()
+            .is_none());
+
+        let rejected = check_maintenance(&Method::PATCH, "/projects/p1", Some(120))
+            .unwrap()
+            .unwrap();
+        assert_eq!(rejected.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(rejected.headers()["Retry-After"], "120");
+
+        assert!(check_maintenance(&Method::GET, "/projects/p1", Some(120))
+            .unwrap()
+            .is_none());
+        assert!(check_maintenance(&Method::POST, "/login", Some(120))
+            .unwrap()
+            .is_none());
+        assert!(
+            check_maintenance(&Method::POST, "/admin/migrations/1/run", Some(120))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            check_maintenance(&Method::DELETE, "/users/me/sessions/s1", Some(120))
+                .unwrap()
+                .is_some()
+        );
+
+        // Exports write jobs and files; polling one doesn't
+        assert!(
+            check_maintenance(&Method::GET, "/projects/p1/export", Some(120))
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            check_maintenance(&Method::GET, "/projects/p1/blocks/b1/export", Some(120))
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            check_maintenance(&Method::GET, "/projects/p1/exports/e1", Some(120))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use lambda_http::{
+    http::{Method, StatusCode},
+    Body, Error, Response,
+};
+
+/// A path pattern and the methods it accepts.
+/// `{name}` matches one path segment; a trailing `*` matches the rest.
+pub(crate) struct Route {
+    pub pattern: &'static str,
+    pub methods: &'static [&'static str],
+}
+
+const fn route(pattern: &'static str, methods: &'static [&'static str]) -> Route {
+    Route { pattern, methods }
+}
+
+/// Every HTTP route served by this lambda. The first matching pattern wins,
+/// so literal segments must be listed before `{param}` siblings
+/// (e.g. `annotations/batch` before `annotations/{aid}`).
+/// New endpoints must be added here as well as to the dispatcher.
+pub(crate) static ROUTES: &[Route] = &[
+    // --- AUTH (public) ---
+    route("/login", &["POST"]),
+    route("/signup", &["POST"]),
+    route("/refresh", &["POST"]),
+    route("/auth/cloudfront-cookies", &["POST"]),
+    route("/proxy-image/*", &["GET"]),
+    route("/schema", &["GET"]),
+    route("/health", &["GET"]),
+    // --- INVITES ---
+    route("/invites", &["POST"]),
+    route("/invites/{code}", &["GET"]),
+    // --- USERS ---
+    route("/users", &["POST"]),
+    route("/users/me", &["GET", "PATCH"]),
+    route("/users/me/onboarding", &["GET", "DELETE"]),
+    route("/users/me/onboarding/{step}", &["POST"]),
+    route("/users/me/sessions", &["GET", "DELETE"]),
+    route("/users/me/sessions/{sid}", &["DELETE"]),
+    // --- ADMIN ---
+    route("/admin/settings", &["GET", "PATCH"]),
+    route("/admin/emails", &["GET"]),
+    route("/admin/key-schema", &["GET"]),
+    route("/admin/integrity-check", &["POST"]),
+    route("/admin/metrics/summary", &["GET"]),
+    route("/admin/annotations/purge", &["POST"]),
+    route("/admin/migrations", &["GET"]),
+    route("/admin/migrations/{version}/run", &["POST"]),
+    // --- PROJECTS ---
+    route("/projects", &["GET", "POST"]),
+    route("/projects/{pid}", &["GET", "PATCH", "DELETE"]),
+    route("/projects/{pid}/members/batch", &["POST"]),
+    route("/projects/{pid}/tree", &["GET"]),
+    route("/projects/{pid}/takeoff", &["GET"]),
+    route("/projects/{pid}/annotations/sample", &["GET"]),
+    route("/projects/{pid}/annotations/reassign-class", &["POST"]),
+    route("/projects/{pid}/export", &["GET"]),
+    route("/projects/{pid}/exports/{eid}", &["GET"]),
+    route("/projects/{pid}/reimport", &["POST"]),
+    route("/projects/{pid}/activity", &["GET"]),
+    route("/projects/{pid}/image-token", &["POST"]),
+    route("/projects/{pid}/storage", &["GET"]),
+    route("/projects/{pid}/audit", &["GET"]),
+    route("/projects/{pid}/blocks", &["GET", "POST"]),
+    route("/projects/{pid}/blocks/{bid}", &["GET", "PATCH", "DELETE"]),
+    route("/projects/{pid}/blocks/{bid}/activity", &["GET"]),
+    route("/projects/{pid}/blocks/{bid}/claim", &["POST"]),
+    route("/projects/{pid}/blocks/{bid}/duplicate", &["POST"]),
+    route("/projects/{pid}/blocks/{bid}/move", &["POST"]),
+    route("/projects/{pid}/blocks/{bid}/reviewer", &["POST"]),
+    route("/projects/{pid}/blocks/{bid}/feed", &["GET"]),
+    route("/projects/{pid}/blocks/{bid}/stats", &["GET"]),
+    route("/projects/{pid}/blocks/{bid}/export", &["GET"]),
+    route("/projects/{pid}/blocks/{bid}/import/cvat", &["POST"]),
+    route("/projects/{pid}/blocks/{bid}/images", &["GET", "POST"]),
+    route("/projects/{pid}/blocks/{bid}/images/reorder", &["POST"]),
+    route("/projects/{pid}/blocks/{bid}/images/order", &["PATCH"]),
+    route("/projects/{pid}/annotations", &["GET"]),
+    route("/projects/{pid}/classes", &["GET", "POST"]),
+    route("/projects/{pid}/classes/batch", &["POST"]),
+    route("/projects/{pid}/classes/reorder", &["POST"]),
+    route("/projects/{pid}/classes/{cid}/stats", &["GET"]),
+    route("/projects/{pid}/classes/apply-template/{tid}", &["POST"]),
+    route("/projects/{pid}/classes/{cid}", &["GET", "PATCH", "DELETE"]),
+    // --- UPLOADS ---
+    route("/annotate/upload/initiate", &["POST"]),
+    route("/annotate/upload/initiate-batch", &["POST"]),
+    route("/annotate/upload/complete", &["POST"]),
+    route("/annotate/upload/abort", &["DELETE"]),
+    route("/annotate/upload/{upload_id}/parts", &["GET"]),
+    // --- IMAGES ---
+    route("/images/{iid}", &["GET", "PATCH", "DELETE"]),
+    route("/images/{iid}/annotations", &["GET", "POST"]),
+    route("/images/{iid}/annotations/batch", &["POST"]),
+    route("/images/{iid}/annotations/deleted", &["GET"]),
+    route("/images/{iid}/annotations/review", &["POST"]),
+    route("/images/{iid}/annotations/reorder", &["POST"]),
+    route("/images/{iid}/annotations/propagate", &["POST"]),
+    route(
+        "/images/{iid}/annotations/{aid}",
+        &["GET", "PATCH", "DELETE"],
+    ),
+    route(
+        "/images/{iid}/annotations/{aid}/lock",
+        &["GET", "POST", "DELETE"],
+    ),
+    route("/images/{iid}/annotations/{aid}/restore", &["POST"]),
+    route("/images/{iid}/annotations/{aid}/history", &["GET"]),
+    route("/images/{iid}/annotations/{aid}/revert", &["POST"]),
+    route("/images/{iid}/annotations/{aid}/approve", &["POST"]),
+    route("/images/{iid}/annotations/{aid}/reject", &["POST"]),
+    route("/images/{iid}/groups", &["GET", "POST"]),
+    route("/images/{iid}/groups/{gid}", &["GET", "DELETE"]),
+    route("/images/{iid}/lock", &["GET", "POST", "DELETE"]),
+    route("/images/{iid}/replace", &["POST"]),
+    route("/images/{iid}/move", &["POST"]),
+    route("/images/{iid}/download-url", &["GET"]),
+    route("/images/{iid}/bundle", &["GET"]),
+    route("/images/{iid}/comments", &["GET", "POST"]),
+    route("/images/{iid}/comments/{cid}", &["PATCH", "DELETE"]),
+    route("/class-templates", &["GET", "POST"]),
+    route("/class-templates/{tid}", &["GET", "PATCH", "DELETE"]),
+];
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+fn matches(pattern: &str, path: &str) -> bool {
+    let mut path_parts = segments(path);
+    for expected in segments(pattern) {
+        if expected == "*" {
+            return path_parts.next().is_some();
+        }
+        match path_parts.next() {
+            Some(_) if expected.starts_with('{') => {}
+            Some(actual) if actual == expected => {}
+            _ => return false,
+        }
+    }
+    path_parts.next().is_none()
+}
+
+/// The route serving `path`, if any
+pub(crate) fn find(path: &str) -> Option<&'static Route> {
+    ROUTES.iter().find(|r| matches(r.pattern, path))
+}
+
+/// Allow header value for a route (OPTIONS is answered for every path)
+fn allow_header(route: &Route) -> String {
+    let mut methods = route.methods.to_vec();
+    methods.push("OPTIONS");
+    methods.join(", ")
+}
+
+/// 405 with an Allow header when the path exists but not for this method.
+/// Unknown paths return `None` and fall through to the dispatcher's 404.
+pub(crate) fn check_method(method: &Method, path: &str) -> Result<Option<Response<Body>>, Error> {
+    let Some(route) = find(path) else {
+        return Ok(None);
+    };
+    if route.methods.contains(&method.as_str()) {
+        return Ok(None);
+    }
+
+    tracing::warn!(
+        "Method {} not allowed on {} ({})",
+        method,
+        path,
+        route.pattern
+    );
+    Ok(Some(
+        Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Allow", allow_header(route))
+            .body(
+                serde_json::json!({
+                    "error": "Method not allowed",
+                    "allowed": route.methods,
+                })
+                .to_string()
+                .into(),
+            )
+            .map_err(Box::new)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("/projects/{pid}", "/projects/p1"));
+        assert!(matches("/projects/{pid}", "/projects/p1/"));
+        assert!(!matches("/projects/{pid}", "/projects"));
+        assert!(!matches("/projects/{pid}", "/projects/p1/tree"));
+        assert!(matches("/proxy-image/*", "/proxy-image/projects/p/a.png"));
+        assert!(!matches("/proxy-image/*", "/proxy-image"));
+    }
+
+    #[test]
+    fn test_literal_segments_win() {
+        let route = find("/images/i1/annotations/batch").unwrap();
+        assert_eq!(route.pattern, "/images/{iid}/annotations/batch");
+        let route = find("/images/i1/annotations/a1").unwrap();
+        assert_eq!(route.pattern, "/images/{iid}/annotations/{aid}");
+    }
+
+    #[test]
+    fn test_check_method() {
+        assert!(check_method(&Method::GET, "/projects/p1")
+            .unwrap()
+            .is_none());
+        assert!(check_method(&Method::GET, "/nowhere").unwrap().is_none());
+
+        let resp = check_method(&Method::PUT, "/projects/p1").unwrap().unwrap();
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(
+            resp.headers().get("Allow").unwrap(),
+            "GET, PATCH, DELETE, OPTIONS"
+        );
+    }
+}
@@ -0,0 +1,44 @@
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use doxle_shared::cleanup::{self, CleanupReport};
+use lambda_runtime::{run, service_fn, Error, LambdaEvent};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .without_time()
+        .init();
+
+    run(service_fn(function_handler)).await
+}
+
+/// Reclaim orphaned S3 objects and stale multipart uploads. Runs on an
+/// EventBridge schedule; invoke it by hand with `{"dry_run": true}` to see
+/// what a run would delete.
+async fn function_handler(event: LambdaEvent<serde_json::Value>) -> Result<CleanupReport, Error> {
+    let dry_run = event
+        .payload
+        .get("dry_run")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let config = aws_config::load_from_env().await;
+    let dynamo_client = DynamoClient::new(&config);
+    let s3_client = S3Client::new(&config);
+    let table_name =
+        std::env::var("TABLE_NAME").unwrap_or_else(|_| "doxle-annotations".to_string());
+
+    let report = cleanup::run(&dynamo_client, &s3_client, &table_name, dry_run).await?;
+    tracing::info!(
+        "Cleanup{}: scanned {} objects, deleted {} ({} bytes), aborted {} multipart uploads, reconciled {} projects",
+        if dry_run { " (dry run)" } else { "" },
+        report.objects_scanned,
+        report.objects_deleted,
+        report.bytes_reclaimed,
+        report.uploads_aborted,
+        report.projects_reconciled
+    );
+    Ok(report)
+}
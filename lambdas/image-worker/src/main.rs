@@ -0,0 +1,95 @@
+use aws_lambda_events::event::s3::S3Event;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use doxle_shared::{export_jobs, s3_multipart};
+use lambda_runtime::{run, service_fn, Error, LambdaEvent};
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .without_time()
+        .init();
+
+    run(service_fn(function_handler)).await
+}
+
+/// Process uploads off the request path: S3 sends ObjectCreated events for
+/// the bucket's `projects/` prefix, and each upload's image goes from
+/// `processing` to `ready` once its size, pyramid and thumbnail are done.
+/// The stream lambda broadcasts the flip. Export job documents (`exports/`,
+/// `.job.json`) have their archive built.
+async fn function_handler(event: LambdaEvent<S3Event>) -> Result<(), Error> {
+    tracing::info!("S3 event received with {} records", event.payload.records.len());
+
+    let config = aws_config::load_from_env().await;
+    let dynamo_client = DynamoClient::new(&config);
+    let s3_client = S3Client::new(&config);
+    let table_name = std::env::var("TABLE_NAME").unwrap_or_else(|_| "doxle-annotations".to_string());
+
+    // A failed record fails the invocation so Lambda retries it; records
+    // already done skip themselves on the retry
+    let mut failed = 0;
+    for record in event.payload.records {
+        if !record.event_name.as_deref().unwrap_or_default().starts_with("ObjectCreated:") {
+            continue;
+        }
+        let Some(key) = record.s3.object.key.as_deref().map(decode_key) else {
+            continue;
+        };
+        let processed = if key.starts_with("exports/") {
+            export_jobs::run_job(&dynamo_client, &s3_client, &table_name, &key).await
+        } else {
+            s3_multipart::process_upload(&dynamo_client, &s3_client, &table_name, &key).await
+        };
+        if let Err(e) = processed {
+            tracing::error!("Failed to process {}: {}", key, e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} uploads failed to process", failed).into());
+    }
+    Ok(())
+}
+
+/// Object keys arrive URL-encoded, with spaces as `+`
+fn decode_key(raw: &str) -> String {
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_key() {
+        assert_eq!(decode_key("projects/p/blocks/b/i.png"), "projects/p/blocks/b/i.png");
+        assert_eq!(decode_key("projects/p/blocks/b/a+b%281%29.png"), "projects/p/blocks/b/a b(1).png");
+        // Not an escape
+        assert_eq!(decode_key("50%"), "50%");
+        assert_eq!(decode_key("%zz"), "%zz");
+    }
+}
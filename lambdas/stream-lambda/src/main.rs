@@ -1,8 +1,9 @@
-use aws_config;
 use aws_lambda_events::event::dynamodb::{Event, EventRecord};
 use aws_sdk_apigatewaymanagement::Client as ApiGatewayManagementClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
-use doxle_shared::sockets::broadcast::_broadcast_to_all;
+use doxle_shared::images;
+use doxle_shared::sockets::broadcast::{_broadcast_to_project, _broadcast_to_user};
+use doxle_shared::sockets::{connections, subscriptions};
 use doxle_shared::sockets::messages::BroadcastMessage;
 use lambda_runtime::{run, service_fn, Error, LambdaEvent};
 
@@ -64,20 +65,61 @@ async fn process_record(
     };
     
     let pk = image.get("PK")
-        .and_then(|attr| {
-            // Convert to string - the AttributeValue should be a String variant
-            serde_json::to_value(attr).ok()
-                .and_then(|v| v.as_str().map(|s| s.to_string()))
-        })
+        .and_then(attr_string)
         .ok_or("Missing PK")?;
     
     let pk_str = pk.as_str();
+    let sk = image.get("SK").and_then(attr_string).unwrap_or_default();
 
-    // Skip connection records (they're not data changes)
-    if pk_str.starts_with("CONNECTION#") {
+    if is_bookkeeping(pk_str, &sk) {
         return Ok(());
     }
 
+    // Lock items: announce acquisitions and releases (explicit, disconnect or TTL expiry)
+    if pk_str.starts_with("LOCK#") {
+        let message = match event_name.as_str() {
+            "INSERT" => create_lock_broadcast(&record.change.new_image, "lock_acquired", None),
+            "REMOVE" => {
+                // TTL deletions are attributed to the DynamoDB service principal
+                let expired = record
+                    .user_identity
+                    .as_ref()
+                    .map(|identity| identity.type_ == "Service")
+                    .unwrap_or(false);
+                let reason = if expired { "expired" } else { "released" };
+                create_lock_broadcast(&record.change.old_image, "lock_released", Some(reason))
+            }
+            _ => return Ok(()), // heartbeats
+        };
+        broadcast_change(dynamo_client, api_gateway_client, table_name, pk_str, &sk, image, &message).await?;
+        tracing::info!("Broadcast sent: {}", message.r#type);
+        return Ok(());
+    }
+
+    // Invite consumed by a signup: tell the admin who sent it (invite.accepted)
+    if pk_str.starts_with("INVITE#") {
+        if event_name != "MODIFY" {
+            return Ok(());
+        }
+        let old_status = record.change.old_image.get("status").and_then(attr_string);
+        let Some((inviter, message)) = create_invite_accepted(&record.change.new_image, old_status.as_deref()) else {
+            return Ok(());
+        };
+        _broadcast_to_user(dynamo_client, api_gateway_client, table_name, &inviter, &message).await?;
+        tracing::info!("Broadcast sent: {} to {}", message.r#type, inviter);
+        return Ok(());
+    }
+
+    // Upload worker done with an image: image_ready (or image_processing_failed)
+    if pk_str.starts_with("BLOCK#") && event_name == "MODIFY" {
+        let old_processing = record.change.old_image.get("processing").and_then(attr_string);
+        if let Some(message) = create_image_processed(&record.change.new_image, old_processing.as_deref()) {
+            broadcast_change(dynamo_client, api_gateway_client, table_name, pk_str, &sk, image, &message).await?;
+            tracing::info!("Broadcast sent: {}", message.r#type);
+            return Ok(());
+        }
+    }
+
     // Determine entity type and create appropriate broadcast message
     let message = match event_name.as_str() {
         "INSERT" => {
@@ -132,14 +174,103 @@ async fn process_record(
         _ => return Ok(()),
     };
 
-    // Broadcast to all connected WebSocket clients
-    _broadcast_to_all(dynamo_client, api_gateway_client, table_name, &message).await?;
+    // Broadcast to the connections following the row's project
+    broadcast_change(dynamo_client, api_gateway_client, table_name, pk_str, &sk, image, &message).await?;
 
     tracing::info!("Broadcast sent: {}", message.r#type);
 
     Ok(())
 }
 
+/// Sort key prefixes of rows kept alongside entities that aren't entities
+/// themselves: activity counters, audit entries and export jobs under PROJECT#, block
+/// events and counters under BLOCK#, annotation versions and comments under
+/// IMAGE#. Broadcasting them would announce them as the entity they sit with,
+/// to every project's sockets.
+const BOOKKEEPING_SK_PREFIXES: [&str; 7] = ["ACTIVITY#", "AUDIT#", "EXPORT#", "EVENT#", "COUNT#", "HISTORY#", "COMMENT#"];
+
+/// Whether a changed row is bookkeeping rather than a data change: connection
+/// records, their index, image viewers, project subscribers, image locations
+/// and the rows above
+fn is_bookkeeping(pk: &str, sk: &str) -> bool {
+    pk.starts_with("CONNECTION#")
+        || pk.starts_with(connections::INDEX_PREFIX)
+        || pk.starts_with("VIEWERS#")
+        || pk.starts_with(subscriptions::SUBSCRIBERS_PREFIX)
+        || (pk.starts_with("IMAGE#") && sk == "METADATA")
+        || BOOKKEEPING_SK_PREFIXES.iter().any(|prefix| sk.starts_with(prefix))
+}
+
+/// Where the project of a changed row is found
+#[derive(Debug, PartialEq)]
+enum ProjectSource {
+    Project(String),
+    /// Looked up from the image's location
+    Image(String),
+    /// The project the connection holding a lock follows
+    Connection(String),
+    Unknown,
+}
+
+/// Rows that carry `project_id` name it; the rest are under their project,
+/// their image, or (locks on annotations) the socket that took them
+fn project_source(pk: &str, sk: &str, field: impl Fn(&str) -> Option<String>) -> ProjectSource {
+    if let Some(project_id) = field("project_id").filter(|id| !id.is_empty()) {
+        return ProjectSource::Project(project_id);
+    }
+    if let Some(project_id) = pk.strip_prefix("PROJECT#") {
+        return ProjectSource::Project(project_id.to_string());
+    }
+    if let Some(image_id) = pk.strip_prefix("IMAGE#") {
+        return ProjectSource::Image(image_id.to_string());
+    }
+    if let (true, Some(image_id)) = (pk.starts_with("BLOCK#"), sk.strip_prefix("IMAGE#")) {
+        return ProjectSource::Image(image_id.to_string());
+    }
+    if pk.starts_with("LOCK#") {
+        if field("resource_type").as_deref() == Some("image") {
+            if let Some(image_id) = field("resource_id") {
+                return ProjectSource::Image(image_id);
+            }
+        }
+        if let Some(connection_id) = field("connection_id") {
+            return ProjectSource::Connection(connection_id);
+        }
+    }
+    ProjectSource::Unknown
+}
+
+/// Send a message about a changed row to the connections following its
+/// project, and to the user a membership row is about. Rows whose project
+/// can't be found are logged and not sent.
+async fn broadcast_change(
+    dynamo_client: &DynamoClient,
+    api_gateway_client: &ApiGatewayManagementClient,
+    table_name: &str,
+    pk: &str,
+    sk: &str,
+    image: &std::collections::HashMap<String, impl serde::Serialize>,
+    message: &BroadcastMessage,
+) -> Result<(), Error> {
+    let field = |name: &str| image.get(name).and_then(attr_string);
+    let project_id = match project_source(pk, sk, field) {
+        ProjectSource::Project(project_id) => Some(project_id),
+        ProjectSource::Image(image_id) => images::image_location(dynamo_client, table_name, &image_id)
+            .await?
+            .map(|location| location.project_id),
+        ProjectSource::Connection(connection_id) => {
+            subscriptions::subscribed_project(dynamo_client, table_name, &connection_id).await?
+        }
+        ProjectSource::Unknown => None,
+    };
+    let Some(project_id) = project_id else {
+        tracing::warn!("No project found for {} / {}, not sending {}", pk, sk, message.r#type);
+        return Ok(());
+    };
+    let member = pk.strip_prefix("PROJECT#").and(sk.strip_prefix("USER#"));
+    _broadcast_to_project(dynamo_client, api_gateway_client, table_name, &project_id, member, message).await
+}
+
 fn create_project_broadcast(record: &EventRecord, message_type: &str) -> Result<BroadcastMessage, Error> {
     let new_image = &record.change.new_image;
 
@@ -157,6 +288,196 @@ fn create_entity_broadcast(record: &EventRecord, message_type: &str) -> Result<B
     Ok(BroadcastMessage::_new(message_type, json_data))
 }
 
+/// Read a string attribute from a stream image. Attributes serialize in DynamoDB
+/// JSON form (`{"S": "..."}`); plain strings are accepted too.
+fn attr_string<T: serde::Serialize>(attr: &T) -> Option<String> {
+    let value = serde_json::to_value(attr).ok()?;
+    value
+        .as_str()
+        .or_else(|| value.get("S").and_then(|s| s.as_str()))
+        .map(|s| s.to_string())
+}
+
+fn create_lock_broadcast(
+    image: &std::collections::HashMap<String, impl serde::Serialize>,
+    message_type: &str,
+    reason: Option<&str>,
+) -> BroadcastMessage {
+    let field = |name: &str| image.get(name).and_then(attr_string);
+    let mut data = serde_json::json!({
+        "resource_type": field("resource_type"),
+        "resource_id": field("resource_id"),
+        "holder": field("holder"),
+        "expires_at": field("expires_at"),
+    });
+    if let Some(reason) = reason {
+        data["reason"] = serde_json::json!(reason);
+    }
+    BroadcastMessage::_new(message_type, data)
+}
+
+/// The inviter and an `invite_accepted` message, when this change is the
+/// invite flipping to used
+fn create_invite_accepted(
+    image: &std::collections::HashMap<String, impl serde::Serialize>,
+    old_status: Option<&str>,
+) -> Option<(String, BroadcastMessage)> {
+    let field = |name: &str| image.get(name).and_then(attr_string);
+    if field("status").as_deref() != Some("used") || old_status == Some("used") {
+        return None;
+    }
+    let inviter = field("created_by")?;
+    let message = BroadcastMessage::_new(
+        "invite_accepted",
+        serde_json::json!({
+            "invite_code": field("invite_code"),
+            "email": field("email"),
+            "user_id": field("user_id"),
+            "accepted_at": field("used_at"),
+        }),
+    );
+    Some((inviter, message))
+}
+
+/// An `image_ready` or `image_processing_failed` message, when this change is
+/// an image leaving `processing`
+fn create_image_processed(
+    image: &std::collections::HashMap<String, impl serde::Serialize>,
+    old_processing: Option<&str>,
+) -> Option<BroadcastMessage> {
+    let field = |name: &str| image.get(name).and_then(attr_string);
+    if old_processing != Some("processing") {
+        return None;
+    }
+    let message_type = match field("processing").as_deref() {
+        Some("ready") => "image_ready",
+        Some("failed") => "image_processing_failed",
+        _ => return None,
+    };
+    let pk = field("PK")?;
+    let sk = field("SK")?;
+    Some(BroadcastMessage::_new(
+        message_type,
+        serde_json::json!({
+            "block_id": extract_id_from_pk(&pk),
+            "image_id": sk.strip_prefix("IMAGE#")?,
+            "url": field("url"),
+            "thumbnail_url": field("thumbnail_url"),
+            "tiles_url": field("tiles_url"),
+            "width": image.get("width").and_then(attr_number),
+            "height": image.get("height").and_then(attr_number),
+            "error": field("processing_error"),
+        }),
+    ))
+}
+
+/// Read a number attribute from a stream image (`{"N": "..."}`)
+fn attr_number<T: serde::Serialize>(attr: &T) -> Option<u64> {
+    let value = serde_json::to_value(attr).ok()?;
+    value
+        .as_u64()
+        .or_else(|| value.get("N").and_then(|n| n.as_str()).and_then(|n| n.parse().ok()))
+}
+
 fn extract_id_from_pk(pk: &str) -> String {
     pk.split('#').nth(1).unwrap_or(pk).to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entities_are_broadcast() {
+        assert!(!is_bookkeeping("PROJECT#p1", "PROJECT#p1"));
+        assert!(!is_bookkeeping("PROJECT#p1", "BLOCK#b1"));
+        assert!(!is_bookkeeping("PROJECT#p1", "CLASS#c1"));
+        assert!(!is_bookkeeping("BLOCK#b1", "IMAGE#i1"));
+        assert!(!is_bookkeeping("IMAGE#i1", "ANNOTATION#a1"));
+    }
+
+    #[test]
+    fn test_audit_entries_are_skipped() {
+        assert!(is_bookkeeping("PROJECT#p1", "AUDIT#2026-01-01T00:00:00Z#e1"));
+    }
+
+    #[test]
+    fn test_activity_counters_are_skipped() {
+        assert!(is_bookkeeping("PROJECT#p1", "ACTIVITY#2026-01-01T00"));
+    }
+
+    #[test]
+    fn test_export_jobs_are_skipped() {
+        assert!(is_bookkeeping("PROJECT#p1", "EXPORT#e1"));
+    }
+
+    #[test]
+    fn test_block_events_are_skipped() {
+        assert!(is_bookkeeping("BLOCK#b1", "EVENT#2026-01-01T00:00:00Z#e1"));
+    }
+
+    #[test]
+    fn test_block_counters_are_skipped() {
+        assert!(is_bookkeeping("BLOCK#b1", "COUNT#status#done"));
+        assert!(is_bookkeeping("BLOCK#b1", "COUNT#class#c1"));
+    }
+
+    #[test]
+    fn test_annotation_versions_are_skipped() {
+        assert!(is_bookkeeping("IMAGE#i1", "HISTORY#a1#V000001"));
+    }
+
+    #[test]
+    fn test_comments_and_image_locations_are_skipped() {
+        assert!(is_bookkeeping("IMAGE#i1", "COMMENT#c1"));
+        assert!(is_bookkeeping("IMAGE#i1", "METADATA"));
+    }
+
+    #[test]
+    fn test_connections_and_viewers_are_skipped() {
+        assert!(is_bookkeeping("CONNECTION#c1", "CONNECTION#c1"));
+        assert!(is_bookkeeping("CONNECTION#c1", "VIEWING"));
+        assert!(is_bookkeeping("CONNECTIONS", "USER#u1#CONNECTION#c1"));
+        assert!(is_bookkeeping("CONNECTIONS#7", "USER#u1#CONNECTION#c1"));
+        assert!(is_bookkeeping("VIEWERS#i1", "CONNECTION#c1"));
+        assert!(is_bookkeeping("SUBSCRIBERS#p1", "CONNECTION#c1"));
+        assert!(is_bookkeeping("CONNECTION#c1", "SUBSCRIBED"));
+    }
+
+    fn fields<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| pairs.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string())
+    }
+
+    #[test]
+    fn test_project_source() {
+        use ProjectSource::*;
+        assert_eq!(project_source("PROJECT#p1", "BLOCK#b1", fields(&[])), Project("p1".to_string()));
+        assert_eq!(project_source("PROJECT#p1", "USER#u1", fields(&[])), Project("p1".to_string()));
+        // Annotations carry their project; older ones are found by image
+        assert_eq!(
+            project_source("IMAGE#i1", "ANNOTATION#a1", fields(&[("project_id", "p2")])),
+            Project("p2".to_string())
+        );
+        assert_eq!(
+            project_source("IMAGE#i1", "ANNOTATION#a1", fields(&[("project_id", "")])),
+            Image("i1".to_string())
+        );
+        assert_eq!(project_source("BLOCK#b1", "IMAGE#i1", fields(&[])), Image("i1".to_string()));
+        assert_eq!(project_source("BLOCK#b1", "STATS", fields(&[])), Unknown);
+    }
+
+    #[test]
+    fn test_lock_project_source() {
+        use ProjectSource::*;
+        let image_lock = [("resource_type", "image"), ("resource_id", "i1"), ("connection_id", "c1")];
+        assert_eq!(project_source("LOCK#image#i1", "LOCK", fields(&image_lock)), Image("i1".to_string()));
+        let annotation_lock = [("resource_type", "annotation"), ("resource_id", "a1"), ("connection_id", "c1")];
+        assert_eq!(
+            project_source("LOCK#annotation#a1", "LOCK", fields(&annotation_lock)),
+            Connection("c1".to_string())
+        );
+        // Taken over HTTP, with no socket to follow
+        let http_lock = [("resource_type", "annotation"), ("resource_id", "a1")];
+        assert_eq!(project_source("LOCK#annotation#a1", "LOCK", fields(&http_lock)), Unknown);
+    }
+}
@@ -0,0 +1,260 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How far back the activity endpoint looks
+const WINDOW_DAYS: i64 = 30;
+
+/// Counter rows outlive the window slightly, then DynamoDB TTL removes them
+/// (this also cleans up after deleted projects)
+const RETENTION_DAYS: i64 = 35;
+
+/// Hour bucket key, e.g. "2026-10-14T08"
+fn hour_key(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%dT%H").to_string()
+}
+
+/// Bump the hourly counter for a project
+/// (PK=PROJECT#{pid}, SK=ACTIVITY#{yyyy-mm-ddThh}). `action` is one of
+/// created | updated | deleted. Counting is best-effort:
+/// failures are logged and never fail the write that triggered them.
+pub async fn record_activity(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    action: &str,
+    count: usize,
+) {
+    if count == 0 || project_id.is_empty() {
+        return;
+    }
+    let now = Utc::now();
+    let expires = (now + Duration::days(RETENTION_DAYS)).timestamp();
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("PROJECT#{}", project_id)))
+        .key(
+            "SK",
+            AttributeValue::S(format!("ACTIVITY#{}", hour_key(now))),
+        )
+        .update_expression("ADD #total :n, #action :n SET #ttl = if_not_exists(#ttl, :ttl)")
+        .expression_attribute_names("#total", "total")
+        .expression_attribute_names("#action", action)
+        .expression_attribute_names("#ttl", "ttl")
+        .expression_attribute_values(":n", AttributeValue::N(count.to_string()))
+        .expression_attribute_values(":ttl", AttributeValue::N(expires.to_string()))
+        .send()
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(
+            "Failed to record activity for project {}: {}",
+            project_id,
+            e
+        );
+    }
+}
+
+/// Counts for one hour or day
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct ActivityBucket {
+    pub start: String, // RFC 3339, UTC
+    pub total: u64,
+    pub created: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
+impl ActivityBucket {
+    fn add(&mut self, other: &ActivityBucket) {
+        self.total += other.total;
+        self.created += other.created;
+        self.updated += other.updated;
+        self.deleted += other.deleted;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivitySeries {
+    pub project_id: String,
+    pub granularity: String, // hour | day
+    pub from: String,
+    pub to: String,
+    pub total: u64,
+    pub buckets: Vec<ActivityBucket>,
+}
+
+/// Lay stored hourly counters (keyed by `hour_key`) onto a dense, zero-filled
+/// series of `granularity` buckets covering the window that ends at `now`
+pub fn bucket_series(
+    hourly: &HashMap<String, ActivityBucket>,
+    now: DateTime<Utc>,
+    granularity: &str,
+) -> Vec<ActivityBucket> {
+    let (step, count) = match granularity {
+        "day" => (Duration::days(1), WINDOW_DAYS),
+        _ => (Duration::hours(1), WINDOW_DAYS * 24),
+    };
+    let last = now.duration_trunc(step).unwrap_or(now);
+    let first = last - step * (count as i32 - 1);
+
+    (0..count)
+        .map(|i| {
+            let start = first + step * i as i32;
+            let mut bucket = ActivityBucket {
+                start: start.to_rfc3339(),
+                ..Default::default()
+            };
+            let mut hour = start;
+            while hour < start + step {
+                if let Some(counts) = hourly.get(&hour_key(hour)) {
+                    bucket.add(counts);
+                }
+                hour += Duration::hours(1);
+            }
+            bucket
+        })
+        .collect()
+}
+
+/// Hourly or daily annotation activity for a project over the last 30 days
+pub async fn get_project_activity(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    granularity: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let granularity = granularity.unwrap_or("day");
+    if granularity != "hour" && granularity != "day" {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({
+                    "error": format!("Unsupported granularity '{}', expected hour or day", granularity)
+                })
+                .to_string()
+                .into(),
+            )
+            .map_err(Box::new)?);
+    }
+
+    let now = Utc::now();
+    let from = now - Duration::days(WINDOW_DAYS);
+    let mut hourly = HashMap::new();
+    let mut last_key = None;
+    loop {
+        let result = client
+            .query()
+            .table_name(table_name)
+            .key_condition_expression("PK = :pk AND SK BETWEEN :from AND :to")
+            .expression_attribute_values(
+                ":pk",
+                AttributeValue::S(format!("PROJECT#{}", project_id)),
+            )
+            .expression_attribute_values(
+                ":from",
+                AttributeValue::S(format!("ACTIVITY#{}", hour_key(from))),
+            )
+            .expression_attribute_values(
+                ":to",
+                AttributeValue::S(format!("ACTIVITY#{}", hour_key(now))),
+            )
+            .set_exclusive_start_key(last_key)
+            .send()
+            .await?;
+
+        for item in result.items() {
+            let Some(hour) = item
+                .get("SK")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|sk| sk.strip_prefix("ACTIVITY#"))
+            else {
+                continue;
+            };
+            let number = |name: &str| {
+                item.get(name)
+                    .and_then(|v| v.as_n().ok())
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(0)
+            };
+            hourly.insert(
+                hour.to_string(),
+                ActivityBucket {
+                    start: String::new(),
+                    total: number("total"),
+                    created: number("created"),
+                    updated: number("updated"),
+                    deleted: number("deleted"),
+                },
+            );
+        }
+
+        last_key = result.last_evaluated_key().cloned();
+        if last_key.is_none() {
+            break;
+        }
+    }
+
+    let buckets = bucket_series(&hourly, now, granularity);
+    let series = ActivitySeries {
+        project_id: project_id.to_string(),
+        granularity: granularity.to_string(),
+        from: buckets.first().map(|b| b.start.clone()).unwrap_or_default(),
+        to: now.to_rfc3339(),
+        total: buckets.iter().map(|b| b.total).sum(),
+        buckets,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&series)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn counts(total: u64) -> ActivityBucket {
+        ActivityBucket {
+            total,
+            created: total,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bucket_series() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 14, 8, 30, 0).unwrap();
+        let mut hourly = HashMap::new();
+        hourly.insert("2026-10-14T08".to_string(), counts(2));
+        hourly.insert("2026-10-14T01".to_string(), counts(3));
+        hourly.insert("2026-10-13T23".to_string(), counts(5));
+        // Outside the window
+        hourly.insert("2026-09-01T00".to_string(), counts(7));
+
+        let hours = bucket_series(&hourly, now, "hour");
+        assert_eq!(hours.len(), 720);
+        assert_eq!(hours[719].start, "2026-10-14T08:00:00+00:00");
+        assert_eq!(hours[719].total, 2);
+        assert_eq!(hours[712].total, 3);
+        assert_eq!(hours[710].total, 5);
+        assert_eq!(hours.iter().map(|b| b.total).sum::<u64>(), 10);
+
+        let days = bucket_series(&hourly, now, "day");
+        assert_eq!(days.len(), 30);
+        assert_eq!(days[29].start, "2026-10-14T00:00:00+00:00");
+        assert_eq!(days[29].total, 5);
+        assert_eq!(days[29].created, 5);
+        assert_eq!(days[28].total, 5);
+        assert_eq!(days[0].start, "2026-09-15T00:00:00+00:00");
+    }
+}
@@ -1,323 +1,2795 @@
 use lambda_http::{Body, Error, Response, http::StatusCode};
 use aws_sdk_dynamodb::Client as DynamoClient;
-use crate::types::{Annotation, CreateAnnotationRequest, UpdateAnnotationRequest, Geometry, BatchCreateAnnotationsRequest};
+use crate::types::{Annotation, Class, CreateAnnotationRequest, UpdateAnnotationRequest, Geometry, BatchCreateAnnotationsRequest, BoundingBox, BulkReviewRequest, PropagateAnnotationsRequest, ReassignClassRequest, ReorderAnnotationsRequest, ReviewAnnotationRequest};
+use crate::pagination::{Filter, Page, PageParams, PageRequest};
+use crate::images::ImageLocation;
+use std::collections::HashMap;
 
-/// Create a new annotation for an image
-pub async fn create_annotation(
-    client: &DynamoClient,
-    table_name: &str,
+/// Deleted annotations keep their item with a `deleted_at` tombstone until
+/// they are restored or purged; reads skip them with this filter
+const LIVE_FILTER: &str = "attribute_not_exists(deleted_at)";
+
+/// Tombstones older than this are purged unless the purge says otherwise
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+pub(crate) fn is_deleted(item: &HashMap<String, aws_sdk_dynamodb::types::AttributeValue>) -> bool {
+    item.contains_key("deleted_at")
+}
+
+/// Derived `area` and `bounding_box` attributes, written alongside the geometry
+pub(crate) fn derived_attributes(geometry: &Geometry) -> Result<Vec<(&'static str, aws_sdk_dynamodb::types::AttributeValue)>, Error> {
+    let mut attributes = vec![(
+        "area",
+        aws_sdk_dynamodb::types::AttributeValue::N(crate::geometry::area(geometry).to_string()),
+    )];
+    if let Some(bounding_box) = crate::geometry::bounding_box(geometry) {
+        attributes.push((
+            "bounding_box",
+            aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&bounding_box)?),
+        ));
+    }
+    Ok(attributes)
+}
+
+/// Build an annotation from its item. Items written before the derived fields
+/// existed get them computed from the geometry.
+pub(crate) fn annotation_from_item(
+    annotation_id: &str,
+    image_id: &str,
+    item: &HashMap<String, aws_sdk_dynamodb::types::AttributeValue>,
+) -> Annotation {
+    let geometry_str = item.get("geometry").and_then(|v| v.as_s().ok()).map(|s| s.as_str()).unwrap_or("{}");
+    let geometry: Geometry = serde_json::from_str(geometry_str).unwrap_or(Geometry::Polygon { points: vec![] });
+    let area = item
+        .get("area")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse().ok())
+        .unwrap_or_else(|| crate::geometry::area(&geometry));
+    let bounding_box = item
+        .get("bounding_box")
+        .and_then(|v| v.as_s().ok())
+        .and_then(|s| serde_json::from_str::<BoundingBox>(s).ok())
+        .or_else(|| crate::geometry::bounding_box(&geometry));
+
+    Annotation {
+        annotation_id: annotation_id.to_string(),
+        image_id: image_id.to_string(),
+        project_id: item.get("project_id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
+        block_id: item.get("block_id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
+        class_id: item.get("class_id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
+        geometry,
+        area,
+        bounding_box,
+        created_by: item.get("created_by").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
+        created_at: item.get("created_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
+        updated_at: item.get("updated_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
+        version: item.get("version").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0),
+        tags: item.get("tags").and_then(|v| v.as_ss().ok()).map(|tags| {
+            let mut tags = tags.clone();
+            tags.sort();
+            tags
+        }).unwrap_or_default(),
+        review_status: item.get("review_status").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_else(|| "pending".to_string()),
+        reviewed_by: item.get("reviewed_by").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
+        reviewed_at: item.get("reviewed_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
+        review_reason: item.get("review_reason").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
+        source: item.get("source").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_else(|| "human".to_string()),
+        confidence: item.get("confidence").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()),
+        group_id: item.get("group_id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
+        z_index: item.get("z_index").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0),
+        attributes: item.get("attributes").and_then(|v| v.as_s().ok()).and_then(|s| serde_json::from_str(s).ok()).unwrap_or_default(),
+    }
+}
+
+/// Review states an annotation can be in; new and edited annotations are pending
+pub const REVIEW_STATUSES: [&str; 3] = ["pending", "approved", "rejected"];
+
+/// Attributes recording a review, removed when an annotation goes back to pending
+const REVIEW_FIELDS: [&str; 4] = ["review_status", "reviewed_by", "reviewed_at", "review_reason"];
+
+/// Most annotations one bulk review can change
+const MAX_BULK_REVIEW: usize = 500;
+
+/// Where an annotation came from: drawn by a person, or proposed by a model
+pub const ANNOTATION_SOURCES: [&str; 2] = ["human", "model"];
+
+/// Check a create's `source` and `confidence`
+fn check_provenance(source: Option<&str>, confidence: Option<f32>) -> Result<(), String> {
+    if let Some(source) = source.filter(|s| !ANNOTATION_SOURCES.contains(s)) {
+        return Err(format!("Unknown source '{}', expected one of: {}", source, ANNOTATION_SOURCES.join(", ")));
+    }
+    if confidence.is_some_and(|c| !(0.0..=1.0).contains(&c)) {
+        return Err("confidence must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// Most tags one annotation can carry
+const MAX_TAGS: usize = 20;
+
+/// Longest tag, in characters
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Trim, de-duplicate and sort tags, dropping blank ones
+pub(crate) fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let tags: std::collections::BTreeSet<String> = tags.iter().map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+    if let Some(tag) = tags.iter().find(|t| t.chars().count() > MAX_TAG_LENGTH) {
+        return Err(format!("Tag '{}' is longer than {} characters", tag, MAX_TAG_LENGTH));
+    }
+    if tags.len() > MAX_TAGS {
+        return Err(format!("At most {} tags are allowed", MAX_TAGS));
+    }
+    Ok(tags.into_iter().collect())
+}
+
+/// Project index keys of an annotation (see `pagination::PROJECT_INDEX`).
+/// Writes that don't know their project leave the annotation unindexed.
+pub(crate) fn project_index_attributes(project_id: &str, created_at: &str, annotation_id: &str) -> Vec<(&'static str, aws_sdk_dynamodb::types::AttributeValue)> {
+    if project_id.is_empty() || project_id == "unknown" {
+        return Vec::new();
+    }
+    vec![
+        ("GSI1PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("PROJECT#{}", project_id))),
+        ("GSI1SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}#{}", created_at, annotation_id))),
+    ]
+}
+
+/// A new annotation (with a fresh id unless `req` has one) and the item that
+/// stores it. `req`'s tags must already be normalized.
+fn new_annotation_item(
     user_id: &str,
     image_id: &str,
-    project_id: &str,
-    body: &[u8],
-) -> Result<Response<Body>, Error> {
-    let req: CreateAnnotationRequest = serde_json::from_slice(body)?;
-    
-    let annotation_id = uuid::Uuid::new_v4().to_string();
+    location: &ImageLocation,
+    req: CreateAnnotationRequest,
+) -> Result<(Annotation, HashMap<String, aws_sdk_dynamodb::types::AttributeValue>), Error> {
+    let CreateAnnotationRequest { class_id, geometry, tags, annotation_id, source, confidence, z_index, attributes } = req;
+    let source = source.unwrap_or_else(|| "human".to_string());
+    let z_index = z_index.unwrap_or(0);
+    let annotation_id = annotation_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
     let now = chrono::Utc::now().to_rfc3339();
     let pk = format!("IMAGE#{}", image_id);
     let sk = format!("ANNOTATION#{}", annotation_id);
     
-    // Serialize geometry to JSON string
-    let geometry_json = serde_json::to_string(&req.geometry)?;
-    
-    // Store annotation
-    client
-        .put_item()
-        .table_name(table_name)
-        .item("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .item("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
-        .item("class_id", aws_sdk_dynamodb::types::AttributeValue::S(req.class_id.clone()))
-        .item("geometry", aws_sdk_dynamodb::types::AttributeValue::S(geometry_json))
-        .item("created_by", aws_sdk_dynamodb::types::AttributeValue::S(format!("USER#{}", user_id)))
-        .item("created_at", aws_sdk_dynamodb::types::AttributeValue::S(now.clone()))
-        .send()
-        .await?;
-    
-    // Increment class count
-    let _ = crate::classes::increment_class_count(client, table_name, project_id, &req.class_id, 1).await;
+    let mut item = HashMap::from([
+        ("PK".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(pk)),
+        ("SK".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(sk)),
+        ("project_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(location.project_id.clone())),
+        ("block_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(location.block_id.clone())),
+        ("class_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(class_id.clone())),
+        ("geometry".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&geometry)?)),
+        ("created_by".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(format!("USER#{}", user_id))),
+        ("created_at".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(now.clone())),
+        ("version".to_string(), aws_sdk_dynamodb::types::AttributeValue::N("1".to_string())),
+        ("source".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(source.clone())),
+        ("z_index".to_string(), aws_sdk_dynamodb::types::AttributeValue::N(z_index.to_string())),
+    ]);
+    if let Some(confidence) = confidence {
+        item.insert("confidence".to_string(), aws_sdk_dynamodb::types::AttributeValue::N(confidence.to_string()));
+    }
+    for (name, value) in derived_attributes(&geometry)?.into_iter().chain(project_index_attributes(&location.project_id, &now, &annotation_id)) {
+        item.insert(name.to_string(), value);
+    }
+    // String sets can't be empty, so untagged annotations have no attribute
+    if !tags.is_empty() {
+        item.insert("tags".to_string(), aws_sdk_dynamodb::types::AttributeValue::Ss(tags.clone()));
+    }
+    if !attributes.is_empty() {
+        item.insert("attributes".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&attributes)?));
+    }
     
     let annotation = Annotation {
-        annotation_id: annotation_id.clone(),
+        annotation_id,
         image_id: image_id.to_string(),
-        class_id: req.class_id,
-        geometry: req.geometry,
+        project_id: location.project_id.clone(),
+        block_id: location.block_id.clone(),
+        class_id,
+        area: crate::geometry::area(&geometry),
+        bounding_box: crate::geometry::bounding_box(&geometry),
+        geometry,
         created_by: format!("USER#{}", user_id),
         created_at: now,
         updated_at: None,
+        version: 1,
+        tags,
+        review_status: "pending".to_string(),
+        reviewed_by: None,
+        reviewed_at: None,
+        review_reason: None,
+        source,
+        confidence,
+        group_id: None,
+        z_index,
+        attributes,
     };
+    Ok((annotation, item))
+}
+
+/// Write a new annotation item and bump its class, image and block counts in
+/// one transaction.
+/// Returns None (writing nothing) when an annotation with `req`'s id exists.
+pub async fn put_annotation(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    location: &ImageLocation,
+    req: CreateAnnotationRequest,
+) -> Result<Option<Annotation>, Error> {
+    let (annotation, item) = new_annotation_item(user_id, image_id, location, req)?;
+    let put = aws_sdk_dynamodb::types::Put::builder()
+        .table_name(table_name)
+        .set_item(Some(item))
+        .condition_expression("attribute_not_exists(PK)")
+        .build()?;
+    let mut writes = vec![aws_sdk_dynamodb::types::TransactWriteItem::builder().put(put).build()];
+    writes.extend(crate::classes::class_count_update(table_name, &location.project_id, &annotation.class_id, 1)?);
+    writes.extend(crate::images::annotation_count_updates(table_name, image_id, location, 1)?);
+    writes.extend(crate::block_stats::counter_update(table_name, &location.block_id, &crate::block_stats::class_counter(&annotation.class_id), 1)?);
+    let result = client
+        .transact_write_items()
+        .set_transact_items(Some(writes))
+        .send()
+        .await;
+    if let Err(e) = result {
+        let exists = e
+            .as_service_error()
+            .and_then(|se| match se {
+                aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError::TransactionCanceledException(canceled) => canceled.cancellation_reasons().first(),
+                _ => None,
+            })
+            .and_then(|reason| reason.code())
+            == Some("ConditionalCheckFailed");
+        if exists {
+            return Ok(None);
+        }
+        return Err(e.into());
+    }
     
+    crate::history::record(client, table_name, &annotation, "created", Some(&annotation.created_by)).await;
+    crate::audit::record(client, table_name, audit_entry(&annotation, user_id, "created").after(&crate::audit::annotation_summary(&annotation))).await;
+    
+    Ok(Some(annotation))
+}
+
+/// The id a create gives its annotation: the client's own UUID, one derived
+/// from the `Idempotency-Key` (per image, and per position in a batch), or
+/// None for a fresh one
+fn requested_annotation_id(image_id: &str, annotation_id: Option<&str>, idempotency_key: Option<&str>, index: usize) -> Result<Option<String>, String> {
+    if let Some(id) = annotation_id {
+        return uuid::Uuid::parse_str(id.trim())
+            .map(|id| Some(id.to_string()))
+            .map_err(|_| format!("annotation_id '{}' is not a UUID", id));
+    }
+    let Some(key) = idempotency_key.map(|k| k.trim()).filter(|k| !k.is_empty()) else {
+        return Ok(None);
+    };
+    use sha2::Digest;
+    let digest = sha2::Sha256::digest(format!("{}\n{}\n{}", image_id, key, index).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    Ok(Some(uuid::Uuid::from_bytes(bytes).to_string()))
+}
+
+/// Annotations of an image with the given ids, tombstoned ones included,
+/// keyed by id with whether they're deleted
+async fn annotations_by_id(client: &DynamoClient, table_name: &str, image_id: &str, ids: &[&str]) -> Result<HashMap<String, (Annotation, bool)>, Error> {
+    let mut found = HashMap::new();
+    if ids.is_empty() {
+        return Ok(found);
+    }
+    if let [id] = ids {
+        let result = client
+            .get_item()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", image_id)))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", id)))
+            .send()
+            .await?;
+        if let Some(item) = result.item() {
+            found.insert(id.to_string(), (annotation_from_item(id, image_id, item), is_deleted(item)));
+        }
+        return Ok(found);
+    }
+    let page = crate::pagination::query_prefix(client, table_name, &format!("IMAGE#{}", image_id), "ANNOTATION#", None, &PageRequest::default()).await?;
+    for item in &page.items {
+        let Some(id) = item.get("SK").and_then(|v| v.as_s().ok()).and_then(|sk| sk.strip_prefix("ANNOTATION#")) else {
+            continue;
+        };
+        if ids.contains(&id) {
+            found.insert(id.to_string(), (annotation_from_item(id, image_id, item), is_deleted(item)));
+        }
+    }
+    Ok(found)
+}
+
+/// Response to a create that names an existing annotation: the annotation
+/// itself, or 409 when it has been deleted since
+fn existing_annotation_response(annotation: &Annotation, deleted: bool) -> Result<Response<Body>, Error> {
+    if deleted {
+        return Ok(Response::builder()
+            .status(StatusCode::CONFLICT)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": format!("Annotation {} was deleted", annotation.annotation_id)}).to_string().into())
+            .map_err(Box::new)?);
+    }
     Ok(Response::builder()
-        .status(StatusCode::CREATED)
+        .status(StatusCode::OK)
         .header("Content-Type", "application/json")
         .header("Access-Control-Allow-Origin", "*")
-        .body(serde_json::to_string(&annotation)?.into())
+        .body(serde_json::to_string(annotation)?.into())
         .map_err(Box::new)?)
 }
 
-/// Batch create annotations (for performance)
-pub async fn batch_create_annotations(
+/// Project and block of a stored annotation. Items from before annotations
+/// carried them fall back to the image's location, or "" when it has none.
+async fn annotation_location(client: &DynamoClient, table_name: &str, annotation: &Annotation) -> Result<ImageLocation, Error> {
+    let stored = ImageLocation { project_id: annotation.project_id.clone(), block_id: annotation.block_id.clone() };
+    if !stored.project_id.is_empty() && !stored.block_id.is_empty() {
+        return Ok(stored);
+    }
+    Ok(crate::images::image_location(client, table_name, &annotation.image_id).await?.unwrap_or(stored))
+}
+
+fn image_not_found() -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": "Image not found"}).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Items per BatchWriteItem request (the DynamoDB maximum)
+const BATCH_WRITE_SIZE: usize = 25;
+
+/// Attempts per chunk before unprocessed items are given up on
+const BATCH_WRITE_ATTEMPTS: u32 = 5;
+
+/// Send write requests 25 at a time, retrying unprocessed ones with backoff.
+/// Returns the requests still unprocessed after the last attempt.
+async fn batch_write(
+    client: &DynamoClient,
+    table_name: &str,
+    requests: Vec<aws_sdk_dynamodb::types::WriteRequest>,
+) -> Result<Vec<aws_sdk_dynamodb::types::WriteRequest>, Error> {
+    let mut failed = Vec::new();
+    for chunk in requests.chunks(BATCH_WRITE_SIZE) {
+        let mut requests = chunk.to_vec();
+        let mut attempts = 0;
+        while !requests.is_empty() {
+            attempts += 1;
+            if attempts > BATCH_WRITE_ATTEMPTS {
+                failed.extend(requests);
+                break;
+            }
+            if attempts > 1 {
+                tokio::time::sleep(tokio::time::Duration::from_millis(50 << attempts)).await;
+            }
+            let result = client
+                .batch_write_item()
+                .request_items(table_name, requests)
+                .send()
+                .await?;
+            requests = result
+                .unprocessed_items()
+                .and_then(|items| items.get(table_name))
+                .cloned()
+                .unwrap_or_default();
+        }
+    }
+    Ok(failed)
+}
+
+/// Put items with `batch_write`, returning those that couldn't be written
+pub(crate) async fn batch_put_items(
+    client: &DynamoClient,
+    table_name: &str,
+    items: Vec<HashMap<String, aws_sdk_dynamodb::types::AttributeValue>>,
+) -> Result<Vec<HashMap<String, aws_sdk_dynamodb::types::AttributeValue>>, Error> {
+    let mut requests = Vec::new();
+    for item in items {
+        requests.push(
+            aws_sdk_dynamodb::types::WriteRequest::builder()
+                .put_request(aws_sdk_dynamodb::types::PutRequest::builder().set_item(Some(item)).build()?)
+                .build(),
+        );
+    }
+    Ok(batch_write(client, table_name, requests)
+        .await?
+        .into_iter()
+        .filter_map(|r| r.put_request.map(|p| p.item))
+        .collect())
+}
+
+/// Delete items by key with `batch_write`, returning the keys that couldn't be deleted
+pub(crate) async fn batch_delete_keys(
+    client: &DynamoClient,
+    table_name: &str,
+    keys: Vec<HashMap<String, aws_sdk_dynamodb::types::AttributeValue>>,
+) -> Result<Vec<HashMap<String, aws_sdk_dynamodb::types::AttributeValue>>, Error> {
+    let mut requests = Vec::new();
+    for key in keys {
+        requests.push(
+            aws_sdk_dynamodb::types::WriteRequest::builder()
+                .delete_request(aws_sdk_dynamodb::types::DeleteRequest::builder().set_key(Some(key)).build()?)
+                .build(),
+        );
+    }
+    Ok(batch_write(client, table_name, requests)
+        .await?
+        .into_iter()
+        .filter_map(|r| r.delete_request.map(|d| d.key))
+        .collect())
+}
+
+/// Whether a key is among those `batch_delete_keys` couldn't delete
+pub(crate) fn delete_failed(
+    failed: &[HashMap<String, aws_sdk_dynamodb::types::AttributeValue>],
+    pk: &str,
+    sk: &str,
+) -> bool {
+    failed.iter().any(|key| {
+        key.get("PK").and_then(|v| v.as_s().ok()).map(String::as_str) == Some(pk)
+            && key.get("SK").and_then(|v| v.as_s().ok()).map(String::as_str) == Some(sk)
+    })
+}
+
+/// Annotations whose rows a batch delete removed, so a partial delete only
+/// stops counting what is actually gone
+pub(crate) fn deleted_annotations<'a>(
+    annotations: &'a [Annotation],
+    failed: &[HashMap<String, aws_sdk_dynamodb::types::AttributeValue>],
+) -> Vec<&'a Annotation> {
+    annotations
+        .iter()
+        .filter(|a| !delete_failed(failed, &format!("IMAGE#{}", a.image_id), &format!("ANNOTATION#{}", a.annotation_id)))
+        .collect()
+}
+
+/// Net class-count change per class, one entry per class in first-seen order
+pub(crate) fn class_deltas<K: PartialEq>(class_ids: impl IntoIterator<Item = K>) -> Vec<(K, i32)> {
+    let mut deltas: Vec<(K, i32)> = Vec::new();
+    for class_id in class_ids {
+        match deltas.iter_mut().find(|(id, _)| *id == class_id) {
+            Some((_, delta)) => *delta += 1,
+            None => deltas.push((class_id, 1)),
+        }
+    }
+    deltas
+}
+
+/// `SET` clause bumping an annotation's history version (items written
+/// before history was kept start from 0)
+const NEXT_VERSION: &str = "#version = if_not_exists(#version, :zero) + :one";
+
+/// Record the history and audit entry of an update from the item before and
+/// after it. The first tracked update of an older annotation also records the
+/// state it started from, as version 0.
+async fn record_update(
+    client: &DynamoClient,
+    table_name: &str,
+    old: &Annotation,
+    new: &Annotation,
+    action: &str,
+    user_id: &str,
+) {
+    if old.version == 0 {
+        crate::history::record(client, table_name, old, "snapshot", None).await;
+    }
+    crate::history::record(client, table_name, new, action, Some(&format!("USER#{}", user_id))).await;
+    crate::audit::record(client, table_name, audit_entry(new, user_id, action).before(&crate::audit::annotation_summary(old)).after(&crate::audit::annotation_summary(new))).await;
+}
+
+/// Audit entry about an annotation, filed under its project
+fn audit_entry(annotation: &Annotation, user_id: &str, action: &str) -> crate::audit::AuditEntry {
+    crate::audit::AuditEntry::new(&annotation.project_id, user_id, action, "annotation", &annotation.annotation_id).block(&annotation.block_id)
+}
+
+/// Transaction items moving one annotation from a class to another in the
+/// project's class counts and its block's counters
+fn class_move_items(table_name: &str, location: &ImageLocation, from_class_id: &str, to_class_id: &str) -> Result<Vec<aws_sdk_dynamodb::types::TransactWriteItem>, Error> {
+    let mut writes = Vec::new();
+    writes.extend(crate::classes::class_count_update(table_name, &location.project_id, from_class_id, -1)?);
+    writes.extend(crate::classes::class_count_update(table_name, &location.project_id, to_class_id, 1)?);
+    writes.extend(crate::block_stats::counter_update(table_name, &location.block_id, &crate::block_stats::class_counter(from_class_id), -1)?);
+    writes.extend(crate::block_stats::counter_update(table_name, &location.block_id, &crate::block_stats::class_counter(to_class_id), 1)?);
+    Ok(writes)
+}
+
+/// Apply an update expression to an annotation, returning it as written.
+/// With `counts` to move, the update goes in one transaction with them and
+/// only applies while the annotation is live and still of `old`'s class;
+/// None when it no longer is.
+async fn send_annotation_update(
+    client: &DynamoClient,
+    table_name: &str,
+    old: &Annotation,
+    expression: String,
+    mut names: HashMap<String, String>,
+    mut values: HashMap<String, aws_sdk_dynamodb::types::AttributeValue>,
+    counts: Vec<aws_sdk_dynamodb::types::TransactWriteItem>,
+) -> Result<Option<Annotation>, Error> {
+    let pk = aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", old.image_id));
+    let sk = aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", old.annotation_id));
+    if counts.is_empty() {
+        let output = client
+            .update_item()
+            .table_name(table_name)
+            .key("PK", pk)
+            .key("SK", sk)
+            .update_expression(expression)
+            .set_expression_attribute_names(Some(names))
+            .set_expression_attribute_values(Some(values))
+            .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew)
+            .send()
+            .await?;
+        return Ok(output.attributes().map(|item| annotation_from_item(&old.annotation_id, &old.image_id, item)));
+    }
+
+    names.insert("#class_id".to_string(), "class_id".to_string());
+    names.insert("#deleted_at".to_string(), "deleted_at".to_string());
+    values.insert(":old_class_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(old.class_id.clone()));
+    let update = aws_sdk_dynamodb::types::Update::builder()
+        .table_name(table_name)
+        .key("PK", pk.clone())
+        .key("SK", sk.clone())
+        .update_expression(expression)
+        .condition_expression("#class_id = :old_class_id AND attribute_not_exists(#deleted_at)")
+        .set_expression_attribute_names(Some(names))
+        .set_expression_attribute_values(Some(values))
+        .build()?;
+    let mut writes = vec![aws_sdk_dynamodb::types::TransactWriteItem::builder().update(update).build()];
+    writes.extend(counts);
+    match client.transact_write_items().set_transact_items(Some(writes)).send().await {
+        Ok(_) => {}
+        Err(e) if e.as_service_error().map(|se| se.is_transaction_canceled_exception()).unwrap_or(false) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", pk)
+        .key("SK", sk)
+        .send()
+        .await?;
+    Ok(result.item().map(|item| annotation_from_item(&old.annotation_id, &old.image_id, item)))
+}
+
+/// Write an annotation's class and geometry (and derived fields) as its next
+/// version, moving its class counts in the same transaction when the class
+/// changes. Returns the annotation as written, or None when it was deleted
+/// or reclassified meanwhile.
+async fn set_class_and_geometry(
+    client: &DynamoClient,
+    table_name: &str,
+    old: &Annotation,
+    location: &ImageLocation,
+    class_id: &str,
+    geometry: &Geometry,
+) -> Result<Option<Annotation>, Error> {
+    let mut sets = vec!["#class_id = :class_id".to_string(), "#geometry = :geometry".to_string(), "#updated_at = :updated_at".to_string(), NEXT_VERSION.to_string()];
+    let mut values = HashMap::from([
+        (":class_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(class_id.to_string())),
+        (":geometry".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(geometry)?)),
+        (":updated_at".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(chrono::Utc::now().to_rfc3339())),
+        (":zero".to_string(), aws_sdk_dynamodb::types::AttributeValue::N("0".to_string())),
+        (":one".to_string(), aws_sdk_dynamodb::types::AttributeValue::N("1".to_string())),
+    ]);
+    let names = ["class_id", "geometry", "updated_at", "area", "bounding_box", "version"]
+        .into_iter()
+        .chain(REVIEW_FIELDS)
+        .map(|name| (format!("#{}", name), name.to_string()))
+        .collect();
+    for (name, value) in derived_attributes(geometry)? {
+        sets.push(format!("#{0} = :{0}", name));
+        values.insert(format!(":{}", name), value);
+    }
+    // A changed annotation needs reviewing again
+    let mut removes: Vec<String> = REVIEW_FIELDS.iter().map(|name| format!("#{}", name)).collect();
+    if crate::geometry::bounding_box(geometry).is_none() {
+        removes.push("#bounding_box".to_string());
+    }
+    let expression = format!("SET {} REMOVE {}", sets.join(", "), removes.join(", "));
+    let counts = if old.class_id != class_id {
+        class_move_items(table_name, location, &old.class_id, class_id)?
+    } else {
+        Vec::new()
+    };
+    send_annotation_update(client, table_name, old, expression, names, values, counts).await
+}
+
+/// Overwrite an annotation's class and geometry, moving class counts when the
+/// class changes. An annotation deleted or reclassified meanwhile is left be.
+pub(crate) async fn replace_annotation(
     client: &DynamoClient,
     table_name: &str,
     user_id: &str,
-    image_id: &str,
     project_id: &str,
-    body: &[u8],
-) -> Result<Response<Body>, Error> {
-    let req: BatchCreateAnnotationsRequest = serde_json::from_slice(body)?;
-    
-    let mut annotations = Vec::new();
-    let now = chrono::Utc::now().to_rfc3339();
-    
-    for ann_req in req.annotations {
-        let annotation_id = uuid::Uuid::new_v4().to_string();
-        let pk = format!("IMAGE#{}", image_id);
-        let sk = format!("ANNOTATION#{}", annotation_id);
-        let geometry_json = serde_json::to_string(&ann_req.geometry)?;
-        
-        client
-            .put_item()
+    old: &Annotation,
+    class_id: &str,
+    geometry: &Geometry,
+) -> Result<(), Error> {
+    let mut location = annotation_location(client, table_name, old).await?;
+    if location.project_id.is_empty() {
+        location.project_id = project_id.to_string();
+    }
+    if let Some(new) = set_class_and_geometry(client, table_name, old, &location, class_id, geometry).await? {
+        record_update(client, table_name, old, &new, "updated", user_id).await;
+    }
+    Ok(())
+}
+
+/// Transaction item rewriting an annotation's geometry and derived fields as
+/// history `version`, conditional on the annotation not having been edited
+/// since it was read
+pub(crate) fn geometry_update(table_name: &str, annotation: &Annotation, geometry: &Geometry, updated_at: &str, version: u32) -> Result<aws_sdk_dynamodb::types::TransactWriteItem, Error> {
+    let mut sets = vec!["#geometry = :geometry".to_string(), "#updated_at = :updated_at".to_string(), "#version = :version".to_string()];
+    let mut builder = aws_sdk_dynamodb::types::Update::builder()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", annotation.image_id)))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", annotation.annotation_id)))
+        .expression_attribute_values(":geometry", aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(geometry)?))
+        .expression_attribute_values(":updated_at", aws_sdk_dynamodb::types::AttributeValue::S(updated_at.to_string()))
+        .expression_attribute_values(":version", aws_sdk_dynamodb::types::AttributeValue::N(version.to_string()));
+    for name in ["geometry", "updated_at", "area", "bounding_box", "version"] {
+        builder = builder.expression_attribute_names(format!("#{}", name), name);
+    }
+    for (name, value) in derived_attributes(geometry)? {
+        sets.push(format!("#{0} = :{0}", name));
+        builder = builder.expression_attribute_values(format!(":{}", name), value);
+    }
+    let expression = if crate::geometry::bounding_box(geometry).is_some() {
+        format!("SET {}", sets.join(", "))
+    } else {
+        format!("SET {} REMOVE #bounding_box", sets.join(", "))
+    };
+    builder = match &annotation.updated_at {
+        Some(seen) => builder
+            .condition_expression("#updated_at = :seen")
+            .expression_attribute_values(":seen", aws_sdk_dynamodb::types::AttributeValue::S(seen.clone())),
+        None => builder.condition_expression("attribute_exists(PK) AND attribute_not_exists(#updated_at)"),
+    };
+    let update = builder.update_expression(expression).build()?;
+    Ok(aws_sdk_dynamodb::types::TransactWriteItem::builder().update(update).build())
+}
+
+/// Delete an annotation item and decrement its class, image and block counts
+/// in one transaction
+pub(crate) async fn remove_annotation(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    annotation: &Annotation,
+) -> Result<(), Error> {
+    let delete = aws_sdk_dynamodb::types::Delete::builder()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", annotation.image_id)))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", annotation.annotation_id)))
+        .build()?;
+    let mut writes = vec![aws_sdk_dynamodb::types::TransactWriteItem::builder().delete(delete).build()];
+    writes.extend(crate::classes::class_count_update(table_name, project_id, &annotation.class_id, -1)?);
+    let location = ImageLocation { project_id: project_id.to_string(), block_id: annotation.block_id.clone() };
+    writes.extend(crate::images::annotation_count_updates(table_name, &annotation.image_id, &location, -1)?);
+    writes.extend(crate::block_stats::counter_update(table_name, &annotation.block_id, &crate::block_stats::class_counter(&annotation.class_id), -1)?);
+    client
+        .transact_write_items()
+        .set_transact_items(Some(writes))
+        .send()
+        .await?;
+    crate::audit::record(client, table_name, crate::audit::AuditEntry::new(project_id, user_id, "deleted", "annotation", &annotation.annotation_id).block(&annotation.block_id).before(&crate::audit::annotation_summary(annotation))).await;
+    Ok(())
+}
+
+fn invalid_geometry(message: String) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": message}).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Annotations allowed on one image unless MAX_ANNOTATIONS_PER_IMAGE says otherwise.
+/// Guards against runaway clients writing the same shape over and over.
+const DEFAULT_MAX_ANNOTATIONS_PER_IMAGE: usize = 5000;
+
+/// Share of the cap at which writes start logging a warning
+const ANNOTATION_CAP_WARN_RATIO: f64 = 0.8;
+
+/// Per-image annotation cap
+pub fn max_annotations_per_image() -> usize {
+    std::env::var("MAX_ANNOTATIONS_PER_IMAGE")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&limit: &usize| limit > 0)
+        .unwrap_or(DEFAULT_MAX_ANNOTATIONS_PER_IMAGE)
+}
+
+/// Whether adding `adding` annotations to an image holding `existing` stays
+/// within `limit`. `Ok(true)` means the write is allowed but close to the cap.
+pub fn check_annotation_cap(existing: usize, adding: usize, limit: usize) -> Result<bool, String> {
+    let total = existing + adding;
+    if total > limit {
+        return Err(format!(
+            "Image already has {} annotations; adding {} would exceed the limit of {} per image",
+            existing, adding, limit
+        ));
+    }
+    Ok(total as f64 >= limit as f64 * ANNOTATION_CAP_WARN_RATIO)
+}
+
+/// Number of annotations stored on an image
+pub(crate) async fn count_image_annotations(client: &DynamoClient, table_name: &str, image_id: &str) -> Result<usize, Error> {
+    let mut count = 0;
+    let mut last_key = None;
+    loop {
+        let result = client
+            .query()
             .table_name(table_name)
-            .item("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-            .item("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
-            .item("class_id", aws_sdk_dynamodb::types::AttributeValue::S(ann_req.class_id.clone()))
-            .item("geometry", aws_sdk_dynamodb::types::AttributeValue::S(geometry_json))
-            .item("created_by", aws_sdk_dynamodb::types::AttributeValue::S(format!("USER#{}", user_id)))
-            .item("created_at", aws_sdk_dynamodb::types::AttributeValue::S(now.clone()))
+            .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
+            .expression_attribute_values(":pk", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", image_id)))
+            .expression_attribute_values(":sk_prefix", aws_sdk_dynamodb::types::AttributeValue::S("ANNOTATION#".to_string()))
+            .filter_expression(LIVE_FILTER)
+            .select(aws_sdk_dynamodb::types::Select::Count)
+            .set_exclusive_start_key(last_key)
             .send()
             .await?;
-        
-        // Increment class count
-        let _ = crate::classes::increment_class_count(client, table_name, project_id, &ann_req.class_id, 1).await;
-        
-        annotations.push(Annotation {
-            annotation_id,
-            image_id: image_id.to_string(),
-            class_id: ann_req.class_id,
-            geometry: ann_req.geometry,
-            created_by: format!("USER#{}", user_id),
-            created_at: now.clone(),
-            updated_at: None,
-        });
+        count += result.count() as usize;
+        last_key = result.last_evaluated_key().cloned();
+        if last_key.is_none() {
+            return Ok(count);
+        }
     }
-    
+}
+
+/// Check the per-image cap before writing `adding` annotations. Returns the
+/// 422 to send back when the write would exceed it; approaching the cap is
+/// logged and counted in the ops metrics.
+pub(crate) async fn enforce_annotation_cap(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    adding: usize,
+) -> Result<Option<Response<Body>>, Error> {
+    let limit = max_annotations_per_image();
+    let existing = count_image_annotations(client, table_name, image_id).await?;
+    match check_annotation_cap(existing, adding, limit) {
+        Ok(false) => Ok(None),
+        Ok(true) => {
+            tracing::warn!("Image {} is near its annotation cap: {} + {} of {}", image_id, existing, adding, limit);
+            crate::metrics::record(client, table_name, &[(crate::metrics::ANNOTATION_CAP_WARNINGS, 1)]).await;
+            Ok(None)
+        }
+        Err(message) => {
+            tracing::error!("Rejected {} annotations on image {}: {} already (limit {})", adding, image_id, existing, limit);
+            crate::metrics::record(client, table_name, &[(crate::metrics::ANNOTATION_CAP_REJECTIONS, 1)]).await;
+            Ok(Some(Response::builder()
+                .status(StatusCode::UNPROCESSABLE_ENTITY)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(
+                    serde_json::json!({
+                        "error": message,
+                        "code": "annotation_limit",
+                        "limit": limit,
+                        "count": existing,
+                    })
+                    .to_string()
+                    .into(),
+                )
+                .map_err(Box::new)?))
+        }
+    }
+}
+
+/// Query options accepted by annotation writes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WriteOptions<'a> {
+    /// `?simplify=<tolerance>`, in the project's coordinate units
+    pub simplify: Option<&'a str>,
+    /// `?coordinates=pixel|normalized`: units the geometry was sent in,
+    /// defaulting to the project's coordinate mode
+    pub coordinates: Option<&'a str>,
+    /// `?image_width=&image_height=`, needed to convert between modes
+    pub image_width: Option<&'a str>,
+    pub image_height: Option<&'a str>,
+    /// `Idempotency-Key` header: retrying a create with the same key returns
+    /// what the first attempt created
+    pub idempotency_key: Option<&'a str>,
+}
+
+/// Scale factors that bring geometry sent in `options.coordinates` into the
+/// project's `mode`, or `None` when no conversion is needed
+fn coordinate_conversion(mode: &str, options: &WriteOptions) -> Result<Option<(f64, f64)>, String> {
+    let sent = options.coordinates.unwrap_or(mode);
+    if !crate::geometry::COORDINATE_MODES.contains(&sent) {
+        return Err(format!(
+            "coordinates must be one of: {}",
+            crate::geometry::COORDINATE_MODES.join(", ")
+        ));
+    }
+    if sent == mode {
+        return Ok(None);
+    }
+    let dimension = |v: Option<&str>| v.and_then(|v| v.parse::<f64>().ok()).filter(|v| v.is_finite() && *v > 0.0);
+    let (Some(width), Some(height)) = (dimension(options.image_width), dimension(options.image_height)) else {
+        return Err(format!(
+            "converting {} coordinates to this project's {} mode needs image_width and image_height",
+            sent, mode
+        ));
+    };
+    Ok(Some(if mode == "normalized" {
+        (1.0 / width, 1.0 / height)
+    } else {
+        (width, height)
+    }))
+}
+
+/// Bring a geometry into the project's coordinate mode and check it fits
+fn apply_coordinate_mode(geometry: &mut Geometry, mode: &str, conversion: Option<(f64, f64)>) -> Result<(), String> {
+    if let Some((sx, sy)) = conversion {
+        crate::geometry::scale(geometry, sx, sy)?;
+    }
+    if mode == "normalized" {
+        crate::geometry::check_normalized(geometry)?;
+    }
+    Ok(())
+}
+
+/// Coordinate mode of the project an annotation write belongs to
+async fn project_coordinate_mode(client: &DynamoClient, table_name: &str, project_id: &str) -> Result<String, Error> {
+    let project = crate::projects::fetch_project(client, table_name, project_id).await?;
+    Ok(crate::projects::coordinate_mode(project.as_ref()).to_string())
+}
+
+/// Parse the optional `?simplify=<tolerance>` for polygon/polyline writes
+fn simplify_tolerance(param: Option<&str>) -> Result<Option<f64>, String> {
+    let Some(raw) = param else {
+        return Ok(None);
+    };
+    match raw.parse::<f64>() {
+        Ok(t) if t > 0.0 && t <= crate::geometry::MAX_SIMPLIFY_TOLERANCE => Ok(Some(t)),
+        _ => Err(format!(
+            "simplify must be a tolerance between 0 and {}",
+            crate::geometry::MAX_SIMPLIFY_TOLERANCE
+        )),
+    }
+}
+
+/// Apply Douglas–Peucker simplification when a tolerance was requested
+fn simplify_geometry(geometry: &mut crate::types::Geometry, tolerance: Option<f64>) {
+    if let Some(tolerance) = tolerance {
+        let removed = crate::geometry::simplify(geometry, tolerance);
+        if removed > 0 {
+            tracing::info!("Simplified {}: removed {} points (tolerance {})", crate::geometry::kind(geometry), removed, tolerance);
+        }
+    }
+}
+
+/// Keypoints must use the names declared by their class skeleton, and
+/// attribute values must fit the class's attribute schema. Annotations of
+/// classes that don't exist (yet) aren't checked.
+fn check_class(class: Option<&Class>, geometry: &Geometry, attributes: &std::collections::BTreeMap<String, serde_json::Value>) -> Result<(), String> {
+    let Some(class) = class else {
+        return Ok(());
+    };
+    if matches!(geometry, Geometry::Keypoints { .. }) {
+        if let Some(skeleton) = crate::classes::skeleton(class.properties.as_ref())? {
+            crate::geometry::validate_keypoints(geometry, &skeleton)?;
+        }
+    }
+    let schema = crate::classes::attribute_schema(class.properties.as_ref())?;
+    crate::classes::validate_attributes(&schema, attributes)
+}
+
+/// Create a new annotation for an image. A create naming an annotation that
+/// already exists (by `annotation_id` or `Idempotency-Key`) returns it with
+/// 200 instead of writing a duplicate.
+pub async fn create_annotation(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    body: &[u8],
+    options: WriteOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    let tolerance = match simplify_tolerance(options.simplify) {
+        Ok(tolerance) => tolerance,
+        Err(e) => return invalid_geometry(e),
+    };
+    let Some(location) = crate::images::image_location(client, table_name, image_id).await? else {
+        return image_not_found();
+    };
+    let project_id = location.project_id.as_str();
+    if let Some(response) = crate::locks::check_unlocked(client, table_name, "image", image_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_assignee(client, table_name, project_id, &location.block_id, user_id).await? {
+        return Ok(response);
+    }
+    let mode = project_coordinate_mode(client, table_name, project_id).await?;
+    let conversion = match coordinate_conversion(&mode, &options) {
+        Ok(conversion) => conversion,
+        Err(e) => return invalid_geometry(e),
+    };
+    let mut req: CreateAnnotationRequest = serde_json::from_slice(body)?;
+    req.annotation_id = match requested_annotation_id(image_id, req.annotation_id.as_deref(), options.idempotency_key, 0) {
+        Ok(id) => id,
+        Err(e) => return invalid_geometry(e),
+    };
+    if let Some(id) = req.annotation_id.as_deref() {
+        if let Some((annotation, deleted)) = annotations_by_id(client, table_name, image_id, &[id]).await?.remove(id) {
+            return existing_annotation_response(&annotation, deleted);
+        }
+    }
+    if let Err(e) = crate::geometry::validate(&req.geometry) {
+        return invalid_geometry(e);
+    }
+    if let Err(e) = check_provenance(req.source.as_deref(), req.confidence) {
+        return invalid_geometry(e);
+    }
+    req.tags = match normalize_tags(std::mem::take(&mut req.tags)) {
+        Ok(tags) => tags,
+        Err(e) => return invalid_geometry(e),
+    };
+    if let Err(e) = apply_coordinate_mode(&mut req.geometry, &mode, conversion) {
+        return invalid_geometry(e);
+    }
+    let class = crate::classes::fetch_class(client, table_name, project_id, &req.class_id).await?;
+    if let Err(e) = check_class(class.as_ref(), &req.geometry, &req.attributes) {
+        return invalid_geometry(e);
+    }
+    simplify_geometry(&mut req.geometry, tolerance);
+    if let Some(response) = enforce_annotation_cap(client, table_name, image_id, 1).await? {
+        return Ok(response);
+    }
+    
+    let requested_id = req.annotation_id.clone();
+    let Some(annotation) = put_annotation(client, table_name, user_id, image_id, &location, req).await? else {
+        // A concurrent retry created it first
+        let id = requested_id.unwrap_or_default();
+        return match annotations_by_id(client, table_name, image_id, &[&id]).await?.remove(&id) {
+            Some((annotation, deleted)) => existing_annotation_response(&annotation, deleted),
+            None => Err(format!("Annotation {} could not be written", id).into()),
+        };
+    };
+    crate::activity::record_activity(client, table_name, project_id, "created", 1).await;
+    
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&annotation)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Batch create annotations (for performance)
+pub async fn batch_create_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    body: &[u8],
+    options: WriteOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    let tolerance = match simplify_tolerance(options.simplify) {
+        Ok(tolerance) => tolerance,
+        Err(e) => return invalid_geometry(e),
+    };
+    let Some(location) = crate::images::image_location(client, table_name, image_id).await? else {
+        return image_not_found();
+    };
+    let project_id = location.project_id.as_str();
+    if let Some(response) = crate::locks::check_unlocked(client, table_name, "image", image_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_assignee(client, table_name, project_id, &location.block_id, user_id).await? {
+        return Ok(response);
+    }
+    let mode = project_coordinate_mode(client, table_name, project_id).await?;
+    let conversion = match coordinate_conversion(&mode, &options) {
+        Ok(conversion) => conversion,
+        Err(e) => return invalid_geometry(e),
+    };
+    let mut req: BatchCreateAnnotationsRequest = serde_json::from_slice(body)?;
+    let classes = crate::classes::fetch_project_classes(client, table_name, project_id).await?;
+    for (i, ann_req) in req.annotations.iter_mut().enumerate() {
+        ann_req.annotation_id = match requested_annotation_id(image_id, ann_req.annotation_id.as_deref(), options.idempotency_key, i) {
+            Ok(id) => id,
+            Err(e) => return invalid_geometry(format!("annotations[{}]: {}", i, e)),
+        };
+    }
+    for (i, ann_req) in req.annotations.iter_mut().enumerate() {
+        if let Err(e) = crate::geometry::validate(&ann_req.geometry) {
+            return invalid_geometry(format!("annotations[{}]: {}", i, e));
+        }
+        if let Err(e) = check_provenance(ann_req.source.as_deref(), ann_req.confidence) {
+            return invalid_geometry(format!("annotations[{}]: {}", i, e));
+        }
+        ann_req.tags = match normalize_tags(std::mem::take(&mut ann_req.tags)) {
+            Ok(tags) => tags,
+            Err(e) => return invalid_geometry(format!("annotations[{}]: {}", i, e)),
+        };
+        if let Err(e) = apply_coordinate_mode(&mut ann_req.geometry, &mode, conversion) {
+            return invalid_geometry(format!("annotations[{}]: {}", i, e));
+        }
+        let class = classes.iter().find(|c| c.class_id == ann_req.class_id);
+        if let Err(e) = check_class(class, &ann_req.geometry, &ann_req.attributes) {
+            return invalid_geometry(format!("annotations[{}]: {}", i, e));
+        }
+    }
+    
+    for ann_req in req.annotations.iter_mut() {
+        simplify_geometry(&mut ann_req.geometry, tolerance);
+    }
+    // Annotations a retried batch already created are returned, not rewritten
+    let requested: Vec<String> = req.annotations.iter().filter_map(|a| a.annotation_id.clone()).collect();
+    let requested: Vec<&str> = requested.iter().map(|id| id.as_str()).collect();
+    let mut existing = annotations_by_id(client, table_name, image_id, &requested).await?;
+    req.annotations.retain(|a| a.annotation_id.as_ref().map(|id| !existing.contains_key(id)).unwrap_or(true));
+    if let Some(response) = enforce_annotation_cap(client, table_name, image_id, req.annotations.len()).await? {
+        return Ok(response);
+    }
+    
+    let mut annotations = Vec::new();
+    let mut items = Vec::new();
+    for ann_req in req.annotations {
+        let (annotation, item) = new_annotation_item(user_id, image_id, &location, ann_req)?;
+        annotations.push(annotation);
+        items.push(item);
+    }
+    let created = annotations.len();
+    let mut annotations = write_new_annotations(client, table_name, user_id, project_id, annotations, items, !requested.is_empty()).await?;
+    if annotations.len() < created {
+        // Created by a concurrent retry between the check and the write
+        let written: Vec<&str> = annotations.iter().map(|a| a.annotation_id.as_str()).collect();
+        let raced: Vec<&str> = requested.iter().copied().filter(|id| !written.contains(id) && !existing.contains_key(*id)).collect();
+        existing.extend(annotations_by_id(client, table_name, image_id, &raced).await?);
+    }
+    let status = if annotations.is_empty() && !existing.is_empty() { StatusCode::OK } else { StatusCode::CREATED };
+    annotations.extend(existing.into_values().filter(|(_, deleted)| !deleted).map(|(annotation, _)| annotation));
+    
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&annotations)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Batch-write new annotations with their history versions, then update
+/// class counts and activity once for the lot. Fails (after counting what
+/// was written) if any annotation couldn't be written. `conditional` writes
+/// go in transactions of `BATCH_WRITE_SIZE` that only create ids not taken
+/// yet, leaving out (without failing) the ones that already exist.
+async fn write_new_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    mut annotations: Vec<Annotation>,
+    items: Vec<HashMap<String, aws_sdk_dynamodb::types::AttributeValue>>,
+    conditional: bool,
+) -> Result<Vec<Annotation>, Error> {
+    let annotation_id = |item: &HashMap<String, aws_sdk_dynamodb::types::AttributeValue>| item.get("SK")?.as_s().ok()?.strip_prefix("ANNOTATION#").map(|id| id.to_string());
+    let mut failed: Vec<String> = Vec::new();
+    if conditional {
+        let mut existing = Vec::new();
+        for chunk in items.chunks(BATCH_WRITE_SIZE) {
+            let mut pending = chunk.to_vec();
+            let mut attempts = 0;
+            while !pending.is_empty() {
+                attempts += 1;
+                if attempts > BATCH_WRITE_ATTEMPTS {
+                    failed.extend(pending.iter().filter_map(annotation_id));
+                    break;
+                }
+                if attempts > 1 {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(50 << attempts)).await;
+                }
+                let mut writes = Vec::new();
+                for item in &pending {
+                    let put = aws_sdk_dynamodb::types::Put::builder()
+                        .table_name(table_name)
+                        .set_item(Some(item.clone()))
+                        .condition_expression("attribute_not_exists(PK)")
+                        .build()?;
+                    writes.push(aws_sdk_dynamodb::types::TransactWriteItem::builder().put(put).build());
+                }
+                let Err(e) = client.transact_write_items().set_transact_items(Some(writes)).send().await else {
+                    break;
+                };
+                let reasons = match e.as_service_error() {
+                    Some(aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError::TransactionCanceledException(canceled)) => canceled.cancellation_reasons().to_vec(),
+                    _ => return Err(e.into()),
+                };
+                // Ids created meanwhile drop out and the rest go again; a
+                // transaction that only conflicted goes again as it was
+                let taken: Vec<usize> = reasons.iter().enumerate().filter(|(_, reason)| reason.code() == Some("ConditionalCheckFailed")).map(|(i, _)| i).collect();
+                for i in taken.into_iter().rev() {
+                    existing.extend(annotation_id(&pending.remove(i)));
+                }
+            }
+        }
+        annotations.retain(|a| !existing.contains(&a.annotation_id) && !failed.contains(&a.annotation_id));
+    } else {
+        failed = batch_put_items(client, table_name, items).await?.iter().filter_map(annotation_id).collect();
+        annotations.retain(|a| !failed.contains(&a.annotation_id));
+    }
+    
+    let mut history = Vec::new();
+    for annotation in &annotations {
+        history.push(crate::history::history_item(annotation, "created", Some(&annotation.created_by))?);
+    }
+    let unrecorded = batch_put_items(client, table_name, history).await?.len();
+    if unrecorded > 0 {
+        tracing::warn!("{} created annotations have no history version", unrecorded);
+    }
+    let entries = annotations.iter().map(|a| crate::audit::AuditEntry::new(project_id, user_id, "created", "annotation", &a.annotation_id).block(&a.block_id).after(&crate::audit::annotation_summary(a))).collect();
+    crate::audit::record_all(client, table_name, entries).await;
+    
+    // One count update per class and per image rather than one per annotation
+    for (class_id, delta) in class_deltas(annotations.iter().map(|a| a.class_id.as_str())) {
+        let _ = crate::classes::increment_class_count(client, table_name, project_id, class_id, delta).await;
+    }
+    let mut block_deltas: HashMap<&str, Vec<(String, i64)>> = HashMap::new();
+    for ((block_id, class_id), delta) in class_deltas(annotations.iter().map(|a| (a.block_id.as_str(), a.class_id.as_str()))) {
+        block_deltas.entry(block_id).or_default().push((crate::block_stats::class_counter(class_id), delta as i64));
+    }
+    for (block_id, changes) in block_deltas {
+        crate::block_stats::increment(client, table_name, block_id, &changes).await;
+    }
+    let mut per_image: HashMap<&str, (ImageLocation, i64)> = HashMap::new();
+    for annotation in &annotations {
+        let location = ImageLocation { project_id: project_id.to_string(), block_id: annotation.block_id.clone() };
+        per_image.entry(annotation.image_id.as_str()).or_insert((location, 0)).1 += 1;
+    }
+    for (image_id, (location, delta)) in per_image {
+        crate::images::increment_annotation_counts(client, table_name, image_id, &location, delta).await;
+    }
+    crate::activity::record_activity(client, table_name, project_id, "created", annotations.len()).await;
+    if !failed.is_empty() {
+        return Err(format!("{} of {} annotations could not be written", failed.len(), failed.len() + annotations.len()).into());
+    }
+    Ok(annotations)
+}
+
+/// Most images one propagation can copy annotations to
+const MAX_PROPAGATE_IMAGES: usize = 100;
+
+/// Images a propagation from `source` writes to, given the block's images in
+/// order: the listed ids, or the `count` images after the source
+fn propagation_targets(block_images: &[String], source: &str, image_ids: Option<&[String]>, count: Option<usize>) -> Result<Vec<String>, String> {
+    let Some(position) = block_images.iter().position(|id| id == source) else {
+        return Err("Source image is not in this block".to_string());
+    };
+    let targets: Vec<String> = match (image_ids, count) {
+        (Some(ids), None) => {
+            if let Some(id) = ids.iter().find(|id| !block_images.contains(id)) {
+                return Err(format!("Image {} is not in this block", id));
+            }
+            let mut seen = std::collections::HashSet::new();
+            ids.iter().filter(|id| *id != source && seen.insert(id.as_str())).cloned().collect()
+        }
+        (None, Some(count)) => block_images[position + 1..].iter().take(count).cloned().collect(),
+        _ => return Err("Give either image_ids or count".to_string()),
+    };
+    if targets.is_empty() {
+        return Err("No target images".to_string());
+    }
+    if targets.len() > MAX_PROPAGATE_IMAGES {
+        return Err(format!("At most {} images can be propagated to at once", MAX_PROPAGATE_IMAGES));
+    }
+    Ok(targets)
+}
+
+/// Copy annotations from an image onto others in its block
+/// (POST /images/{iid}/annotations/propagate), scaled
+/// about the image origin and then offset, for time-lapse and scan sequences
+/// where the same objects drift between frames. Images whose cap the copies
+/// would exceed are skipped and reported.
+pub async fn propagate_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: PropagateAnnotationsRequest = serde_json::from_slice(body)?;
+    let scale = req.scale.unwrap_or(1.0);
+    if !(scale > 0.0 && scale.is_finite()) {
+        return invalid_geometry("scale must be positive".to_string());
+    }
+    let Some(location) = crate::images::image_location(client, table_name, image_id).await? else {
+        return image_not_found();
+    };
+    let project_id = location.project_id.as_str();
+    if let Some(response) = crate::blocks::check_assignee(client, table_name, project_id, &location.block_id, user_id).await? {
+        return Ok(response);
+    }
+    let block_images: Vec<String> = crate::images::fetch_block_images(client, table_name, &location.block_id)
+        .await?
+        .into_iter()
+        .map(|image| image.image_id)
+        .collect();
+    let targets = match propagation_targets(&block_images, image_id, req.image_ids.as_deref(), req.count) {
+        Ok(targets) => targets,
+        Err(e) => return invalid_geometry(e),
+    };
+    let target_ids: Vec<&str> = targets.iter().map(String::as_str).collect();
+    if let Some(response) = crate::locks::check_all_unlocked(client, table_name, "image", &target_ids, user_id).await? {
+        return Ok(response);
+    }
+    let mut sources = fetch_image_annotations(client, table_name, image_id).await?;
+    if let Some(ids) = &req.annotation_ids {
+        sources.retain(|a| ids.contains(&a.annotation_id));
+    }
+    if sources.is_empty() {
+        return invalid_geometry("No annotations to propagate".to_string());
+    }
+    
+    // Every target gets the same copies, so transform once
+    let mode = project_coordinate_mode(client, table_name, project_id).await?;
+    let mut copies = Vec::new();
+    for source in &sources {
+        let mut geometry = source.geometry.clone();
+        if scale != 1.0 {
+            if let Err(e) = crate::geometry::resize(&mut geometry, scale, scale) {
+                return invalid_geometry(format!("annotation {}: {}", source.annotation_id, e));
+            }
+        }
+        if let Some(offset) = &req.offset {
+            crate::geometry::translate(&mut geometry, offset.x, offset.y);
+        }
+        let checked = if mode == "normalized" { crate::geometry::check_normalized(&geometry) } else { crate::geometry::validate(&geometry) };
+        if let Err(e) = checked {
+            return invalid_geometry(format!("annotation {}: {}", source.annotation_id, e));
+        }
+        copies.push((source, geometry));
+    }
+    
+    let limit = max_annotations_per_image();
+    let mut annotations = Vec::new();
+    let mut items = Vec::new();
+    let mut skipped = Vec::new();
+    for target in &targets {
+        let existing = count_image_annotations(client, table_name, target).await?;
+        if let Err(reason) = check_annotation_cap(existing, copies.len(), limit) {
+            skipped.push(serde_json::json!({"image_id": target, "reason": reason}));
+            continue;
+        }
+        for (source, geometry) in &copies {
+            let (annotation, item) = new_annotation_item(user_id, target, &location, CreateAnnotationRequest {
+                class_id: source.class_id.clone(),
+                geometry: geometry.clone(),
+                tags: source.tags.clone(),
+                annotation_id: None,
+                source: Some(source.source.clone()),
+                confidence: source.confidence,
+                z_index: Some(source.z_index),
+                attributes: source.attributes.clone(),
+            })?;
+            annotations.push(annotation);
+            items.push(item);
+        }
+    }
+    let annotations = write_new_annotations(client, table_name, user_id, project_id, annotations, items, false).await?;
+    
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({
+            "source_image_id": image_id,
+            "created": annotations.len(),
+            "annotations": annotations,
+            "skipped": skipped,
+        }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Copy annotations onto another image as new annotations (fresh ids, version
+/// 1, pending review) and count them there. Groups are per image, so copies
+/// keep their group ids.
+pub(crate) async fn copy_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    sources: &[Annotation],
+    image_id: &str,
+    location: &ImageLocation,
+) -> Result<Vec<Annotation>, Error> {
+    let mut annotations = Vec::new();
+    let mut items = Vec::new();
+    for source in sources {
+        let (mut annotation, mut item) = new_annotation_item(user_id, image_id, location, CreateAnnotationRequest {
+            class_id: source.class_id.clone(),
+            geometry: source.geometry.clone(),
+            tags: source.tags.clone(),
+            annotation_id: None,
+            source: Some(source.source.clone()),
+            confidence: source.confidence,
+            z_index: Some(source.z_index),
+            attributes: source.attributes.clone(),
+        })?;
+        if let Some(group_id) = &source.group_id {
+            item.insert("group_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(group_id.clone()));
+            annotation.group_id = Some(group_id.clone());
+        }
+        annotations.push(annotation);
+        items.push(item);
+    }
+    write_new_annotations(client, table_name, user_id, &location.project_id, annotations, items, false).await
+}
+
+/// Annotations per reassignment transaction: an update and a history version
+/// each, plus the two class counts, within DynamoDB's 100 item limit
+const REASSIGN_CHUNK: usize = 49;
+
+/// Transaction items moving an annotation to `to_class_id` as its next
+/// version, conditional on it still being the live annotation that was read
+fn reassign_items(table_name: &str, user_id: &str, old: &Annotation, to_class_id: &str, updated_at: &str) -> Result<Vec<aws_sdk_dynamodb::types::TransactWriteItem>, Error> {
+    let moved = Annotation {
+        class_id: to_class_id.to_string(),
+        updated_at: Some(updated_at.to_string()),
+        version: old.version + 1,
+        ..old.clone()
+    };
+    let mut builder = aws_sdk_dynamodb::types::Update::builder()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", old.image_id)))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", old.annotation_id)))
+        .update_expression("SET #class_id = :to_class_id, #updated_at = :updated_at, #version = :version")
+        .expression_attribute_names("#class_id", "class_id")
+        .expression_attribute_names("#updated_at", "updated_at")
+        .expression_attribute_names("#version", "version")
+        .expression_attribute_names("#deleted_at", "deleted_at")
+        .expression_attribute_values(":from_class_id", aws_sdk_dynamodb::types::AttributeValue::S(old.class_id.clone()))
+        .expression_attribute_values(":to_class_id", aws_sdk_dynamodb::types::AttributeValue::S(to_class_id.to_string()))
+        .expression_attribute_values(":updated_at", aws_sdk_dynamodb::types::AttributeValue::S(updated_at.to_string()))
+        .expression_attribute_values(":version", aws_sdk_dynamodb::types::AttributeValue::N(moved.version.to_string()));
+    let unchanged = "#class_id = :from_class_id AND attribute_not_exists(#deleted_at)";
+    builder = match &old.updated_at {
+        Some(seen) => builder
+            .condition_expression(format!("{} AND #updated_at = :seen", unchanged))
+            .expression_attribute_values(":seen", aws_sdk_dynamodb::types::AttributeValue::S(seen.clone())),
+        None => builder.condition_expression(format!("{} AND attribute_not_exists(#updated_at)", unchanged)),
+    };
+    let history = crate::history::history_item(&moved, "updated", Some(&format!("USER#{}", user_id)))?;
+    Ok(vec![
+        aws_sdk_dynamodb::types::TransactWriteItem::builder().update(builder.build()?).build(),
+        aws_sdk_dynamodb::types::TransactWriteItem::builder()
+            .put(aws_sdk_dynamodb::types::Put::builder().table_name(table_name).set_item(Some(history)).build()?)
+            .build(),
+    ])
+}
+
+/// Move annotations of one class to another in a single transaction along
+/// with both class counts. Returns false when the transaction was cancelled
+/// because an annotation changed since it was read.
+async fn reassign_transaction(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    annotations: &[Annotation],
+    to_class_id: &str,
+) -> Result<bool, Error> {
+    let Some(from_class_id) = annotations.first().map(|a| a.class_id.as_str()) else {
+        return Ok(true);
+    };
+    let updated_at = chrono::Utc::now().to_rfc3339();
+    let mut writes = Vec::new();
+    for annotation in annotations {
+        writes.extend(reassign_items(table_name, user_id, annotation, to_class_id, &updated_at)?);
+    }
+    let moved = annotations.len() as i32;
+    writes.extend(crate::classes::class_count_update(table_name, project_id, from_class_id, -moved)?);
+    writes.extend(crate::classes::class_count_update(table_name, project_id, to_class_id, moved)?);
+    match client.transact_write_items().set_transact_items(Some(writes)).send().await {
+        Ok(_) => {}
+        Err(e) if e.as_service_error().map(|se| se.is_transaction_canceled_exception()).unwrap_or(false) => return Ok(false),
+        Err(e) => return Err(e.into()),
+    }
+    // A full chunk leaves no room in the transaction for the block counters
+    for (block_id, moved) in class_deltas(annotations.iter().map(|a| a.block_id.as_str())) {
+        crate::block_stats::move_class(client, table_name, block_id, from_class_id, to_class_id, moved as i64).await;
+    }
+    Ok(true)
+}
+
+/// Move every annotation of one class to another
+/// (POST /projects/{pid}/annotations/reassign-class), for taxonomies
+/// restructured mid-project. Annotations are rewritten `REASSIGN_CHUNK` at a
+/// time with the class counts moved in the same transaction; a chunk that
+/// fails because one of its annotations was edited meanwhile is retried one
+/// annotation at a time, and the edited ones are reported as skipped.
+pub async fn reassign_class(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: ReassignClassRequest = serde_json::from_slice(body)?;
+    if req.from_class_id == req.to_class_id {
+        return crate::responses::json_response(StatusCode::BAD_REQUEST, serde_json::json!({"error": "from_class_id and to_class_id are the same class"}));
+    }
+    // The old class may already be gone; the new one has to exist
+    if crate::classes::fetch_class(client, table_name, project_id, &req.to_class_id).await?.is_none() {
+        return crate::responses::json_response(StatusCode::BAD_REQUEST, serde_json::json!({"error": format!("Class {} is not in this project", req.to_class_id)}));
+    }
+
+    let filter = AnnotationFilter { class_id: Some(&req.from_class_id), ..Default::default() }.expression();
+    let mut annotations = Vec::new();
+    match &req.image_ids {
+        Some(image_ids) => {
+            let mut seen = std::collections::HashSet::new();
+            for image_id in image_ids.iter().filter(|id| seen.insert(id.as_str())) {
+                let location = crate::images::image_location(client, table_name, image_id).await?;
+                let Some(location) = location.filter(|l| l.project_id == project_id) else {
+                    return crate::responses::json_response(StatusCode::BAD_REQUEST, serde_json::json!({"error": format!("Image {} is not in this project", image_id)}));
+                };
+                if let Some(response) = crate::blocks::check_assignee(client, table_name, project_id, &location.block_id, user_id).await? {
+                    return Ok(response);
+                }
+                let page = crate::pagination::query_prefix(client, table_name, &format!("IMAGE#{}", image_id), "ANNOTATION#", Some(&filter), &PageRequest::default()).await?;
+                annotations.extend(page.items.iter().filter_map(|item| {
+                    let annotation_id = item.get("SK")?.as_s().ok()?.strip_prefix("ANNOTATION#")?;
+                    Some(annotation_from_item(annotation_id, image_id, item))
+                }));
+            }
+        }
+        None => {
+            let filter = AnnotationFilter { class_id: Some(&req.from_class_id), ..Default::default() };
+            annotations.extend(fetch_project_annotations(client, table_name, project_id, filter).await?);
+            // Annotations from before blocks were recorded on them are placed via their image
+            let mut block_ids = Vec::new();
+            for annotation in &annotations {
+                if !annotation.block_id.is_empty() {
+                    block_ids.push(annotation.block_id.clone());
+                } else if let Some(location) = crate::images::image_location(client, table_name, &annotation.image_id).await? {
+                    block_ids.push(location.block_id);
+                }
+            }
+            if let Some(response) = crate::blocks::check_blocks_assignee(client, table_name, project_id, &block_ids.iter().map(String::as_str).collect::<Vec<_>>(), user_id).await? {
+                return Ok(response);
+            }
+        }
+    }
+    
+    let annotation_ids: Vec<&str> = annotations.iter().map(|a| a.annotation_id.as_str()).collect();
+    if let Some(response) = crate::locks::check_all_unlocked(client, table_name, "annotation", &annotation_ids, user_id).await? {
+        return Ok(response);
+    }
+    
+    let mut reassigned = 0;
+    let mut skipped = Vec::new();
+    for chunk in annotations.chunks(REASSIGN_CHUNK) {
+        for annotation in chunk.iter().filter(|a| a.version == 0) {
+            crate::history::record(client, table_name, annotation, "snapshot", None).await;
+        }
+        if reassign_transaction(client, table_name, user_id, project_id, chunk, &req.to_class_id).await? {
+            reassigned += chunk.len();
+            continue;
+        }
+        for annotation in chunk {
+            if reassign_transaction(client, table_name, user_id, project_id, std::slice::from_ref(annotation), &req.to_class_id).await? {
+                reassigned += 1;
+            } else {
+                skipped.push(&annotation.annotation_id);
+            }
+        }
+    }
+    crate::activity::record_activity(client, table_name, project_id, "updated", reassigned).await;
+    let summary = serde_json::json!({"to_class_id": req.to_class_id, "reassigned": reassigned});
+    crate::audit::record(client, table_name, crate::audit::AuditEntry::new(project_id, user_id, "reassigned", "class", &req.from_class_id).after(&summary)).await;
+    
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({
+            "from_class_id": req.from_class_id,
+            "to_class_id": req.to_class_id,
+            "matched": annotations.len(),
+            "reassigned": reassigned,
+            "skipped": skipped,
+        }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Get a specific annotation
+pub async fn get_annotation(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    annotation_id: &str,
+) -> Result<Response<Body>, Error> {
+    let pk = format!("IMAGE#{}", image_id);
+    let sk = format!("ANNOTATION#{}", annotation_id);
+    
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
+        .send()
+        .await?;
+    
+    if let Some(item) = result.item().filter(|item| !is_deleted(item)) {
+        let annotation = annotation_from_item(annotation_id, image_id, item);
+        
+        Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::to_string(&annotation)?.into())
+            .map_err(Box::new)?)
+    } else {
+        Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": "Annotation not found"}).to_string().into())
+            .map_err(Box::new)?)
+    }
+}
+
+/// Which of an image's annotations a listing returns
+/// (`?class_id=&tag=&created_by=&source=&min_confidence=`); unset fields
+/// match everything
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AnnotationFilter<'a> {
+    pub class_id: Option<&'a str>,
+    pub tag: Option<&'a str>,
+    pub created_by: Option<&'a str>, // user id, with or without the USER# prefix
+    pub source: Option<&'a str>,
+    pub min_confidence: Option<f32>, // annotations without a confidence always pass
+}
+
+impl AnnotationFilter<'_> {
+    /// FilterExpression matching live annotations that pass every set field.
+    /// Filtering happens after the partition is read, which is cheap next to
+    /// a GSI for the few hundred annotations an image holds.
+    fn expression(&self) -> Filter {
+        let mut filter = Filter::new(LIVE_FILTER);
+        if let Some(class_id) = self.class_id {
+            filter = filter.equals("class_id", class_id);
+        }
+        if let Some(tag) = self.tag {
+            filter = filter.and("contains(#tags, :tags)", "tags", tag.trim());
+        }
+        if let Some(created_by) = self.created_by {
+            let user = format!("USER#{}", created_by.strip_prefix("USER#").unwrap_or(created_by));
+            filter = filter.equals("created_by", &user);
+        }
+        if let Some(source) = self.source {
+            // Annotations from before sources were recorded are human ones
+            filter = match source {
+                "human" => filter.and("(#source = :source OR attribute_not_exists(#source))", "source", source),
+                _ => filter.equals("source", source),
+            };
+        }
+        if let Some(min_confidence) = self.min_confidence {
+            filter = filter.and_number("(attribute_not_exists(#confidence) OR #confidence >= :confidence)", "confidence", min_confidence as f64);
+        }
+        filter
+    }
+}
+
+/// Fetch a page of an image's annotations matching `filter` (deleted ones are left out)
+pub async fn fetch_image_annotations_page(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    filter: AnnotationFilter<'_>,
+    request: &PageRequest,
+) -> Result<Page<Annotation>, Error> {
+    let pk = format!("IMAGE#{}", image_id);
+    let page = crate::pagination::query_prefix(client, table_name, &pk, "ANNOTATION#", Some(&filter.expression()), request).await?;
+    
+    let mut annotations = Vec::new();
+    
+    for item in &page.items {
+            if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
+                if let Some(annotation_id) = sk.strip_prefix("ANNOTATION#") {
+                    annotations.push(annotation_from_item(annotation_id, image_id, item));
+                }
+            }
+    }
+    
+    Ok(Page { items: annotations, next_cursor: page.next_cursor })
+}
+
+/// Fetch all annotations for an image
+pub async fn fetch_image_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+) -> Result<Vec<Annotation>, Error> {
+    Ok(fetch_image_annotations_page(client, table_name, image_id, AnnotationFilter::default(), &PageRequest::default()).await?.items)
+}
+
+/// List an image's annotations matching `filter`: all of them, or a page with `?limit=&cursor=`
+pub async fn list_image_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    filter: AnnotationFilter<'_>,
+    params: PageParams<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match crate::pagination::page_request(params, "ANNOTATION#") {
+        Ok(request) => request,
+        Err(e) => return crate::pagination::invalid_page(e),
+    };
+    let page = fetch_image_annotations_page(client, table_name, image_id, filter, &request).await?;
+
+    crate::pagination::page_response(&page)
+}
+
+/// List a project's annotations across all its images, oldest first
+/// (GET /projects/{id}/annotations?class_id=&tag=&created_by=&since=), from
+/// the project index rather than a walk over every image. `since` (RFC 3339)
+/// keeps annotations created at or after it.
+pub async fn list_project_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    filter: AnnotationFilter<'_>,
+    since: Option<&str>,
+    params: PageParams<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match crate::pagination::project_index_request(params) {
+        Ok(request) => request,
+        Err(e) => return crate::pagination::invalid_page(e),
+    };
+    let sk_from = match since.map(chrono::DateTime::parse_from_rfc3339) {
+        None => "ANNOTATION#".to_string(),
+        Some(Ok(since)) => format!("ANNOTATION#{}", since.with_timezone(&chrono::Utc).to_rfc3339()),
+        Some(Err(_)) => return crate::pagination::invalid_page("since must be an RFC 3339 timestamp".to_string()),
+    };
+    let page = crate::pagination::query_project_index(client, table_name, &format!("PROJECT#{}", project_id), &sk_from, Some(&filter.expression()), &request).await?;
+    
+    let text = |item: &HashMap<String, aws_sdk_dynamodb::types::AttributeValue>, name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    let annotations = page.items.iter().filter_map(|item| {
+        let image_id = text(item, "PK")?.strip_prefix("IMAGE#")?.to_string();
+        let annotation_id = text(item, "SK")?.strip_prefix("ANNOTATION#")?.to_string();
+        Some(annotation_from_item(&annotation_id, &image_id, item))
+    }).collect();
+    
+    crate::pagination::page_response(&Page { items: annotations, next_cursor: page.next_cursor })
+}
+
+/// Fetch every live annotation of a project matching `filter`, oldest first,
+/// from the project index
+pub async fn fetch_project_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    filter: AnnotationFilter<'_>,
+) -> Result<Vec<Annotation>, Error> {
+    let page = crate::pagination::query_project_index(client, table_name, &format!("PROJECT#{}", project_id), "ANNOTATION#", Some(&filter.expression()), &PageRequest::default()).await?;
+    Ok(page.items.iter().filter_map(|item| {
+        let image_id = item.get("PK")?.as_s().ok()?.strip_prefix("IMAGE#")?;
+        let annotation_id = item.get("SK")?.as_s().ok()?.strip_prefix("ANNOTATION#")?;
+        Some(annotation_from_item(annotation_id, image_id, item))
+    }).collect())
+}
+
+/// SET backfilled attributes on an image's annotations that lack `missing`.
+/// Returns how many annotations were updated.
+async fn backfill_image_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    missing: &str,
+    attributes: impl Fn(&HashMap<String, aws_sdk_dynamodb::types::AttributeValue>) -> Vec<(&'static str, aws_sdk_dynamodb::types::AttributeValue)>,
+) -> Result<u64, Error> {
+    let mut updated = 0;
+    let unset = Filter::new(&format!("attribute_not_exists({})", missing));
+    let page = crate::pagination::query_prefix(client, table_name, &format!("IMAGE#{}", image_id), "ANNOTATION#", Some(&unset), &PageRequest::default()).await?;
+    for item in &page.items {
+        let (Some(pk), Some(sk)) = (item.get("PK"), item.get("SK")) else {
+            continue;
+        };
+        let attributes = attributes(item);
+        if attributes.is_empty() {
+            continue;
+        }
+        let sets: Vec<String> = attributes.iter().map(|(name, _)| format!("{} = :{}", name, name.to_lowercase())).collect();
+        let mut builder = client
+            .update_item()
+            .table_name(table_name)
+            .key("PK", pk.clone())
+            .key("SK", sk.clone())
+            .update_expression(format!("SET {}", sets.join(", ")))
+            .condition_expression("attribute_exists(PK)");
+        for (name, value) in attributes {
+            builder = builder.expression_attribute_values(format!(":{}", name.to_lowercase()), value);
+        }
+        match builder.send().await {
+            Ok(_) => updated += 1,
+            Err(e) if e.as_service_error().map(|se| se.is_conditional_check_failed_exception()).unwrap_or(false) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(updated)
+}
+
+/// Backfill project index keys on the annotations of a block's images that
+/// predate the index. Returns how many annotations were indexed.
+pub(crate) async fn index_block_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<u64, Error> {
+    let mut indexed = 0;
+    for image in crate::images::fetch_block_images(client, table_name, block_id).await? {
+        indexed += backfill_image_annotations(client, table_name, &image.image_id, "GSI1PK", |item| {
+            let annotation_id = item.get("SK").and_then(|v| v.as_s().ok()).and_then(|sk| sk.strip_prefix("ANNOTATION#")).unwrap_or_default();
+            let created_at = item.get("created_at").and_then(|v| v.as_s().ok()).map(|s| s.as_str()).unwrap_or_default();
+            project_index_attributes(project_id, created_at, annotation_id)
+        }).await?;
+    }
+    Ok(indexed)
+}
+
+/// Record the location of a block's images and copy it onto their
+/// annotations written before annotations stored their project and block.
+/// Returns how many annotations were updated.
+pub(crate) async fn locate_block_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<u64, Error> {
+    let location = ImageLocation { project_id: project_id.to_string(), block_id: block_id.to_string() };
+    let mut located = 0;
+    for image in crate::images::fetch_block_images(client, table_name, block_id).await? {
+        crate::images::put_image_location(client, table_name, &image.image_id, &location).await?;
+        located += backfill_image_annotations(client, table_name, &image.image_id, "project_id", |_| {
+            vec![
+                ("project_id", aws_sdk_dynamodb::types::AttributeValue::S(location.project_id.clone())),
+                ("block_id", aws_sdk_dynamodb::types::AttributeValue::S(location.block_id.clone())),
+            ]
+        }).await?;
+    }
+    Ok(located)
+}
+
+/// Point an image's annotations, deleted ones included, at another block or
+/// project: its ids, its project index and the class there with the same
+/// name (`class_ids`, by old id; classes without one keep theirs). Returns
+/// the old class ids of the live annotations moved.
+pub(crate) async fn rehome_image_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    location: &ImageLocation,
+    class_ids: &HashMap<String, String>,
+) -> Result<Vec<String>, Error> {
+    let project_id = location.project_id.as_str();
+    let mut moved = Vec::new();
+    let page = crate::pagination::query_prefix(client, table_name, &format!("IMAGE#{}", image_id), "ANNOTATION#", None, &PageRequest::default()).await?;
+    for item in &page.items {
+        let (Some(pk), Some(sk)) = (item.get("PK"), item.get("SK")) else {
+            continue;
+        };
+        let annotation_id = sk.as_s().ok().and_then(|sk| sk.strip_prefix("ANNOTATION#")).unwrap_or_default();
+        let created_at = item.get("created_at").and_then(|v| v.as_s().ok()).map(|s| s.as_str()).unwrap_or_default();
+        let class_id = item.get("class_id").and_then(|v| v.as_s().ok()).cloned().unwrap_or_default();
+        let mut attributes = vec![
+            ("project_id", aws_sdk_dynamodb::types::AttributeValue::S(project_id.to_string())),
+            ("block_id", aws_sdk_dynamodb::types::AttributeValue::S(location.block_id.clone())),
+            ("class_id", aws_sdk_dynamodb::types::AttributeValue::S(class_ids.get(&class_id).unwrap_or(&class_id).clone())),
+        ];
+        attributes.extend(project_index_attributes(project_id, created_at, annotation_id));
+        let sets: Vec<String> = attributes.iter().map(|(name, _)| format!("{} = :{}", name, name.to_lowercase())).collect();
+        let mut builder = client
+            .update_item()
+            .table_name(table_name)
+            .key("PK", pk.clone())
+            .key("SK", sk.clone())
+            .update_expression(format!("SET {}", sets.join(", ")))
+            .condition_expression("attribute_exists(PK)");
+        for (name, value) in attributes {
+            builder = builder.expression_attribute_values(format!(":{}", name.to_lowercase()), value);
+        }
+        match builder.send().await {
+            Ok(_) if !is_deleted(item) => moved.push(class_id),
+            Ok(_) => {}
+            Err(e) if e.as_service_error().map(|se| se.is_conditional_check_failed_exception()).unwrap_or(false) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(moved)
+}
+
+/// Count the live annotations of a block's images and store the totals on
+/// the image and block items. Returns how many images were counted.
+pub(crate) async fn count_block_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<u64, Error> {
+    let mut counted = 0;
+    let mut total = 0;
+    for image in crate::images::fetch_block_images(client, table_name, block_id).await? {
+        let count = count_image_annotations(client, table_name, &image.image_id).await? as u64;
+        total += count;
+        if crate::images::set_annotation_count(client, table_name, format!("BLOCK#{}", block_id), format!("IMAGE#{}", image.image_id), count).await? {
+            counted += 1;
+        }
+    }
+    crate::images::set_annotation_count(client, table_name, format!("PROJECT#{}", project_id), format!("BLOCK#{}", block_id), total).await?;
+    Ok(counted)
+}
+
+/// A deleted annotation awaiting restore or purge
+#[derive(Debug, serde::Serialize)]
+pub struct DeletedAnnotation {
+    #[serde(flatten)]
+    pub annotation: Annotation,
+    pub deleted_at: String,
+    pub deleted_by: Option<String>, // USER#123
+}
+
+/// List an image's deleted annotations (GET /images/{id}/annotations/deleted),
+/// paged like the live listing
+pub async fn list_deleted_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    params: PageParams<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match crate::pagination::page_request(params, "ANNOTATION#") {
+        Ok(request) => request,
+        Err(e) => return crate::pagination::invalid_page(e),
+    };
+    let pk = format!("IMAGE#{}", image_id);
+    let page = crate::pagination::query_prefix(client, table_name, &pk, "ANNOTATION#", Some(&Filter::new("attribute_exists(deleted_at)")), &request).await?;
+    
+    let mut deleted = Vec::new();
+    for item in &page.items {
+        let text = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).map(|s| s.to_string());
+        if let (Some(annotation_id), Some(deleted_at)) = (text("SK").as_deref().and_then(|sk| sk.strip_prefix("ANNOTATION#")), text("deleted_at")) {
+            deleted.push(DeletedAnnotation {
+                annotation: annotation_from_item(annotation_id, image_id, item),
+                deleted_at,
+                deleted_by: text("deleted_by"),
+            });
+        }
+    }
+    
+    crate::pagination::page_response(&Page { items: deleted, next_cursor: page.next_cursor })
+}
+
+/// Update an annotation, recording the new state in its history
+pub async fn update_annotation(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    annotation_id: &str,
+    body: &[u8],
+    options: WriteOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    let tolerance = match simplify_tolerance(options.simplify) {
+        Ok(tolerance) => tolerance,
+        Err(e) => return invalid_geometry(e),
+    };
+    let mut req: UpdateAnnotationRequest = serde_json::from_slice(body)?;
+    if let Some(Err(e)) = req.geometry.as_ref().map(crate::geometry::validate) {
+        return invalid_geometry(e);
+    }
+    let tags = match req.tags.take().map(normalize_tags).transpose() {
+        Ok(tags) => tags,
+        Err(e) => return invalid_geometry(e),
+    };
+    if let Some(response) = crate::locks::check_unlocked(client, table_name, "annotation", annotation_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await? {
+        return Ok(response);
+    }
+    let pk = format!("IMAGE#{}", image_id);
+    let sk = format!("ANNOTATION#{}", annotation_id);
+    
+    // Get old annotation to check class change
+    let old_result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
+        .send()
+        .await?;
+    
+    let Some(old_item) = old_result.item().filter(|item| !is_deleted(item)) else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": "Annotation not found"}).to_string().into())
+            .map_err(Box::new)?);
+    };
+    let old = annotation_from_item(annotation_id, image_id, old_item);
+    let location = annotation_location(client, table_name, &old).await?;
+    let project_id = location.project_id.as_str();
+    if let Some(geometry) = req.geometry.as_mut() {
+        let mode = project_coordinate_mode(client, table_name, project_id).await?;
+        let converted = coordinate_conversion(&mode, &options)
+            .and_then(|conversion| apply_coordinate_mode(geometry, &mode, conversion));
+        if let Err(e) = converted {
+            return invalid_geometry(e);
+        }
+        simplify_geometry(geometry, tolerance);
+    }
+    if req.class_id.is_some() || req.geometry.is_some() || req.attributes.is_some() {
+        let class_id = req.class_id.as_deref().unwrap_or(&old.class_id);
+        let class = crate::classes::fetch_class(client, table_name, project_id, class_id).await?;
+        if let Err(e) = check_class(class.as_ref(), req.geometry.as_ref().unwrap_or(&old.geometry), req.attributes.as_ref().unwrap_or(&old.attributes)) {
+            return invalid_geometry(e);
+        }
+    }
+    
+    let mut update_expr = vec!["#updated_at = :updated_at", NEXT_VERSION];
+    let mut remove_expr: Vec<&str> = Vec::new();
+    let mut counts = Vec::new();
+    let mut expr_names = std::collections::HashMap::new();
+    let mut expr_values = std::collections::HashMap::new();
+    
+    expr_names.insert("#updated_at".to_string(), "updated_at".to_string());
+    expr_names.insert("#version".to_string(), "version".to_string());
+    expr_values.insert(":zero".to_string(), aws_sdk_dynamodb::types::AttributeValue::N("0".to_string()));
+    expr_values.insert(":one".to_string(), aws_sdk_dynamodb::types::AttributeValue::N("1".to_string()));
+    expr_values.insert(":updated_at".to_string(), 
+        aws_sdk_dynamodb::types::AttributeValue::S(chrono::Utc::now().to_rfc3339()));
+    
+    if let Some(class_id) = &req.class_id {
+        update_expr.push("#class_id = :class_id");
+        expr_names.insert("#class_id".to_string(), "class_id".to_string());
+        expr_values.insert(":class_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(class_id.clone()));
+        
+        // Move the class counts with the annotation if its class changes
+        if &old.class_id != class_id {
+            counts = class_move_items(table_name, &location, &old.class_id, class_id)?;
+        }
+    }
+    
+    if req.class_id.is_some() || req.geometry.is_some() {
+        for name in REVIEW_FIELDS {
+            expr_names.insert(format!("#{}", name), name.to_string());
+        }
+        remove_expr.extend(["#review_status", "#reviewed_by", "#reviewed_at", "#review_reason"]);
+    }
+    
+    if let Some(tags) = tags {
+        expr_names.insert("#tags".to_string(), "tags".to_string());
+        if tags.is_empty() {
+            remove_expr.push("#tags");
+        } else {
+            update_expr.push("#tags = :tags");
+            expr_values.insert(":tags".to_string(), aws_sdk_dynamodb::types::AttributeValue::Ss(tags));
+        }
+    }
+    
+    if let Some(attributes) = &req.attributes {
+        expr_names.insert("#attributes".to_string(), "attributes".to_string());
+        if attributes.is_empty() {
+            remove_expr.push("#attributes");
+        } else {
+            update_expr.push("#attributes = :attributes");
+            expr_values.insert(":attributes".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(attributes)?));
+        }
+    }
+    
+    if let Some(z_index) = req.z_index {
+        update_expr.push("#z_index = :z_index");
+        expr_names.insert("#z_index".to_string(), "z_index".to_string());
+        expr_values.insert(":z_index".to_string(), aws_sdk_dynamodb::types::AttributeValue::N(z_index.to_string()));
+    }
+    
+    if let Some(geometry) = req.geometry {
+        update_expr.push("#geometry = :geometry");
+        expr_names.insert("#geometry".to_string(), "geometry".to_string());
+        expr_values.insert(":geometry".to_string(), 
+            aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&geometry)?));
+        // Keep derived fields in step with the new geometry
+        update_expr.push("#area = :area");
+        expr_names.insert("#area".to_string(), "area".to_string());
+        expr_values.insert(":area".to_string(),
+            aws_sdk_dynamodb::types::AttributeValue::N(crate::geometry::area(&geometry).to_string()));
+        expr_names.insert("#bounding_box".to_string(), "bounding_box".to_string());
+        match crate::geometry::bounding_box(&geometry) {
+            Some(bounding_box) => {
+                update_expr.push("#bounding_box = :bounding_box");
+                expr_values.insert(":bounding_box".to_string(),
+                    aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&bounding_box)?));
+            }
+            None => remove_expr.push("#bounding_box"),
+        }
+    }
+    
+    let expression = if remove_expr.is_empty() {
+        format!("SET {}", update_expr.join(", "))
+    } else {
+        format!("SET {} REMOVE {}", update_expr.join(", "), remove_expr.join(", "))
+    };
+    let Some(new) = send_annotation_update(client, table_name, &old, expression, expr_names, expr_values, counts).await? else {
+        return Ok(Response::builder()
+            .status(StatusCode::CONFLICT)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": "Annotation was deleted or reclassified meanwhile; reload it and try again"}).to_string().into())
+            .map_err(Box::new)?);
+    };
+    record_update(client, table_name, &old, &new, "updated", user_id).await;
+    crate::activity::record_activity(client, table_name, project_id, "updated", 1).await;
+    
+    get_annotation(client, table_name, image_id, annotation_id).await
+}
+
+/// Put an annotation back to the class and geometry of an earlier history
+/// version (POST /images/{iid}/annotations/{aid}/revert?to_version=n). The
+/// revert is recorded as a new version, so undoing it is another revert.
+pub async fn revert_annotation(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    annotation_id: &str,
+    to_version: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let Some(to_version) = to_version.and_then(|v| v.parse::<u32>().ok()) else {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": "to_version must be a version number"}).to_string().into())
+            .map_err(Box::new)?);
+    };
+    if let Some(response) = crate::locks::check_unlocked(client, table_name, "annotation", annotation_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await? {
+        return Ok(response);
+    }
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", annotation_id)))
+        .send()
+        .await?;
+    let Some(item) = result.item().filter(|item| !is_deleted(item)) else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": "Annotation not found"}).to_string().into())
+            .map_err(Box::new)?);
+    };
+    let old = annotation_from_item(annotation_id, image_id, item);
+    let Some(target) = crate::history::fetch_version(client, table_name, image_id, annotation_id, to_version).await? else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": format!("Version {} not found", to_version)}).to_string().into())
+            .map_err(Box::new)?);
+    };
+    if target.version == old.version {
+        return get_annotation(client, table_name, image_id, annotation_id).await;
+    }
+
+    let location = annotation_location(client, table_name, &old).await?;
+    let Some(new) = set_class_and_geometry(client, table_name, &old, &location, &target.class_id, &target.geometry).await? else {
+        return get_annotation(client, table_name, image_id, annotation_id).await;
+    };
+    record_update(client, table_name, &old, &new, "reverted", user_id).await;
+    crate::activity::record_activity(client, table_name, &location.project_id, "updated", 1).await;
+
     Ok(Response::builder()
-        .status(StatusCode::CREATED)
+        .status(StatusCode::OK)
         .header("Content-Type", "application/json")
         .header("Access-Control-Allow-Origin", "*")
-        .body(serde_json::to_string(&annotations)?.into())
+        .body(serde_json::to_string(&new)?.into())
         .map_err(Box::new)?)
 }
 
-/// Get a specific annotation
-pub async fn get_annotation(
+/// Record a review of a live annotation. Going back to "pending" clears the
+/// reviewer and reason. Returns None when there's no live annotation.
+async fn set_review(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     image_id: &str,
     annotation_id: &str,
-) -> Result<Response<Body>, Error> {
-    let pk = format!("IMAGE#{}", image_id);
-    let sk = format!("ANNOTATION#{}", annotation_id);
-    
-    let result = client
-        .get_item()
+    status: &str,
+    reason: Option<&str>,
+) -> Result<Option<Annotation>, Error> {
+    let mut builder = client
+        .update_item()
         .table_name(table_name)
-        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
-        .send()
-        .await?;
-    
-    if let Some(item) = result.item() {
-        let geometry_str = item.get("geometry").and_then(|v| v.as_s().ok()).map(|s| s.as_str()).unwrap_or("{}");
-        let geometry: Geometry = serde_json::from_str(geometry_str).unwrap_or(Geometry::Polygon { points: vec![] });
-        
-        let annotation = Annotation {
-            annotation_id: annotation_id.to_string(),
-            image_id: image_id.to_string(),
-            class_id: item.get("class_id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            geometry,
-            created_by: item.get("created_by").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            created_at: item.get("created_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            updated_at: item.get("updated_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
-        };
-        
-        Ok(Response::builder()
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", annotation_id)))
+        .condition_expression("attribute_exists(PK) AND attribute_not_exists(#deleted_at)")
+        .expression_attribute_names("#deleted_at", "deleted_at")
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew);
+    for name in REVIEW_FIELDS {
+        builder = builder.expression_attribute_names(format!("#{}", name), name);
+    }
+    let reason = reason.map(|r| r.trim()).filter(|r| !r.is_empty());
+    let expression = if status == "pending" {
+        "REMOVE #review_status, #reviewed_by, #reviewed_at, #review_reason".to_string()
+    } else {
+        builder = builder
+            .expression_attribute_values(":review_status", aws_sdk_dynamodb::types::AttributeValue::S(status.to_string()))
+            .expression_attribute_values(":reviewed_by", aws_sdk_dynamodb::types::AttributeValue::S(format!("USER#{}", user_id)))
+            .expression_attribute_values(":reviewed_at", aws_sdk_dynamodb::types::AttributeValue::S(chrono::Utc::now().to_rfc3339()));
+        let sets = "SET #review_status = :review_status, #reviewed_by = :reviewed_by, #reviewed_at = :reviewed_at";
+        match reason {
+            Some(reason) => {
+                builder = builder.expression_attribute_values(":review_reason", aws_sdk_dynamodb::types::AttributeValue::S(reason.to_string()));
+                format!("{}, #review_reason = :review_reason", sets)
+            }
+            None => format!("{} REMOVE #review_reason", sets),
+        }
+    };
+    match builder.update_expression(expression).send().await {
+        Ok(output) => {
+            let annotation = output.attributes().map(|item| annotation_from_item(annotation_id, image_id, item));
+            if let Some(annotation) = &annotation {
+                let review = serde_json::json!({"review_status": status, "review_reason": reason});
+                crate::audit::record(client, table_name, audit_entry(annotation, user_id, "reviewed").after(&review)).await;
+            }
+            Ok(annotation)
+        }
+        Err(e) if e.as_service_error().map(|se| se.is_conditional_check_failed_exception()).unwrap_or(false) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Approve or reject one annotation
+/// (POST /images/{iid}/annotations/{aid}/approve | reject, body `{"reason"}`)
+pub async fn review_annotation(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    annotation_id: &str,
+    status: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: ReviewAnnotationRequest = if body.is_empty() { ReviewAnnotationRequest::default() } else { serde_json::from_slice(body)? };
+    if let Some(response) = crate::locks::check_unlocked(client, table_name, "annotation", annotation_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_reviewer(client, table_name, image_id, user_id).await? {
+        return Ok(response);
+    }
+    match set_review(client, table_name, user_id, image_id, annotation_id, status, req.reason.as_deref()).await? {
+        Some(annotation) => Ok(Response::builder()
             .status(StatusCode::OK)
             .header("Content-Type", "application/json")
             .header("Access-Control-Allow-Origin", "*")
             .body(serde_json::to_string(&annotation)?.into())
-            .map_err(Box::new)?)
-    } else {
-        Ok(Response::builder()
+            .map_err(Box::new)?),
+        None => Ok(Response::builder()
             .status(StatusCode::NOT_FOUND)
             .header("Content-Type", "application/json")
             .header("Access-Control-Allow-Origin", "*")
             .body(serde_json::json!({"error": "Annotation not found"}).to_string().into())
-            .map_err(Box::new)?)
+            .map_err(Box::new)?),
     }
 }
 
-/// List all annotations for an image
-pub async fn list_image_annotations(
+/// Set the review status of many of an image's annotations at once
+/// (POST /images/{iid}/annotations/review). Ids that aren't live annotations
+/// are reported as missing rather than failing the request.
+pub async fn bulk_review_annotations(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     image_id: &str,
+    body: &[u8],
 ) -> Result<Response<Body>, Error> {
-    let pk = format!("IMAGE#{}", image_id);
+    let req: BulkReviewRequest = serde_json::from_slice(body)?;
+    let invalid = if !REVIEW_STATUSES.contains(&req.status.as_str()) {
+        Some(format!("Unknown status '{}', expected one of: {}", req.status, REVIEW_STATUSES.join(", ")))
+    } else if req.annotation_ids.len() > MAX_BULK_REVIEW {
+        Some(format!("At most {} annotations can be reviewed at once", MAX_BULK_REVIEW))
+    } else {
+        None
+    };
+    if let Some(message) = invalid {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": message}).to_string().into())
+            .map_err(Box::new)?);
+    }
+    let annotation_ids: Vec<&str> = req.annotation_ids.iter().map(String::as_str).collect();
+    if let Some(response) = crate::locks::check_all_unlocked(client, table_name, "annotation", &annotation_ids, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_reviewer(client, table_name, image_id, user_id).await? {
+        return Ok(response);
+    }
     
-    let result = client
-        .query()
-        .table_name(table_name)
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
-        .expression_attribute_values(":pk", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .expression_attribute_values(":sk_prefix", aws_sdk_dynamodb::types::AttributeValue::S("ANNOTATION#".to_string()))
-        .send()
-        .await?;
+    let mut reviewed = Vec::new();
+    let mut missing = Vec::new();
+    for annotation_id in &req.annotation_ids {
+        match set_review(client, table_name, user_id, image_id, annotation_id, &req.status, req.reason.as_deref()).await? {
+            Some(_) => reviewed.push(annotation_id),
+            None => missing.push(annotation_id),
+        }
+    }
     
-    let mut annotations = Vec::new();
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({
+            "status": req.status,
+            "reviewed": reviewed,
+            "missing": missing,
+        }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Most annotations one reorder request can place
+const MAX_REORDER: usize = 500;
+
+/// z_index for each id of a bottom-to-top order: its position in the list
+fn reorder_positions(annotation_ids: &[String]) -> Result<Vec<(&str, i32)>, String> {
+    if annotation_ids.is_empty() {
+        return Err("annotation_ids must not be empty".to_string());
+    }
+    if annotation_ids.len() > MAX_REORDER {
+        return Err(format!("At most {} annotations can be reordered at once", MAX_REORDER));
+    }
+    let mut seen = std::collections::HashSet::new();
+    if let Some(duplicate) = annotation_ids.iter().find(|id| !seen.insert(id.as_str())) {
+        return Err(format!("Annotation '{}' is listed more than once", duplicate));
+    }
+    Ok(annotation_ids.iter().enumerate().map(|(i, id)| (id.as_str(), i as i32)).collect())
+}
+
+/// Set the draw order of an image's annotations
+/// (POST /images/{iid}/annotations/reorder, body `{"annotation_ids"}` bottom to
+/// top). Annotations left out keep their z_index. Ordering isn't an edit of
+/// the shape, so it doesn't bump versions or reset reviews.
+pub async fn reorder_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: ReorderAnnotationsRequest = serde_json::from_slice(body)?;
+    let positions = match reorder_positions(&req.annotation_ids) {
+        Ok(positions) => positions,
+        Err(e) => return invalid_geometry(e),
+    };
+    let annotation_ids: Vec<&str> = positions.iter().map(|(id, _)| *id).collect();
+    if let Some(response) = crate::locks::check_all_unlocked(client, table_name, "annotation", &annotation_ids, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await? {
+        return Ok(response);
+    }
     
-    for item in result.items() {
-            if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
-                if let Some(annotation_id) = sk.strip_prefix("ANNOTATION#") {
-                    let geometry_str = item.get("geometry").and_then(|v| v.as_s().ok()).map(|s| s.as_str()).unwrap_or("{}");
-                    let geometry: Geometry = serde_json::from_str(geometry_str).unwrap_or(Geometry::Polygon { points: vec![] });
-                    
-                    let annotation = Annotation {
-                        annotation_id: annotation_id.to_string(),
-                        image_id: image_id.to_string(),
-                        class_id: item.get("class_id").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-                        geometry,
-                        created_by: item.get("created_by").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-                        created_at: item.get("created_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-                        updated_at: item.get("updated_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
-                    };
-                    annotations.push(annotation);
-                }
-            }
+    let mut reordered = Vec::new();
+    let mut missing = Vec::new();
+    for (annotation_id, z_index) in positions {
+        let result = client
+            .update_item()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", image_id)))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("ANNOTATION#{}", annotation_id)))
+            .condition_expression("attribute_exists(PK) AND attribute_not_exists(#deleted_at)")
+            .update_expression("SET #z_index = :z_index")
+            .expression_attribute_names("#deleted_at", "deleted_at")
+            .expression_attribute_names("#z_index", "z_index")
+            .expression_attribute_values(":z_index", aws_sdk_dynamodb::types::AttributeValue::N(z_index.to_string()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => reordered.push(serde_json::json!({"annotation_id": annotation_id, "z_index": z_index})),
+            Err(e) if e.as_service_error().map(|se| se.is_conditional_check_failed_exception()).unwrap_or(false) => missing.push(annotation_id),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if !reordered.is_empty() {
+        let location = crate::images::image_location(client, table_name, image_id).await?.unwrap_or_default();
+        let summary = serde_json::json!({"reordered": reordered});
+        crate::audit::record(client, table_name, crate::audit::AuditEntry::new(&location.project_id, user_id, "reordered", "image", image_id).block(&location.block_id).after(&summary)).await;
     }
     
     Ok(Response::builder()
         .status(StatusCode::OK)
         .header("Content-Type", "application/json")
         .header("Access-Control-Allow-Origin", "*")
-        .body(serde_json::to_string(&annotations)?.into())
+        .body(serde_json::json!({
+            "image_id": image_id,
+            "reordered": reordered,
+            "missing": missing,
+        }).to_string().into())
         .map_err(Box::new)?)
 }
 
-/// Update an annotation
-pub async fn update_annotation(
+/// Reads and transactions a delete or restore tries before giving up on an
+/// annotation that keeps changing under it
+const DELETE_ATTEMPTS: u32 = 3;
+
+/// Delete an annotation. The item is kept with a `deleted_at` tombstone so
+/// it can be restored until the purge removes it. The tombstone and the
+/// class count decrement are written in one transaction, conditional on the
+/// annotation still having the class that was read.
+pub async fn delete_annotation(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     image_id: &str,
     annotation_id: &str,
-    project_id: &str,
-    body: &[u8],
 ) -> Result<Response<Body>, Error> {
-    let req: UpdateAnnotationRequest = serde_json::from_slice(body)?;
+    if let Some(response) = crate::locks::check_unlocked(client, table_name, "annotation", annotation_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await? {
+        return Ok(response);
+    }
     let pk = format!("IMAGE#{}", image_id);
     let sk = format!("ANNOTATION#{}", annotation_id);
     
-    // Get old annotation to check class change
-    let old_result = client
-        .get_item()
-        .table_name(table_name)
-        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
-        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
-        .send()
-        .await?;
-    
-    let old_class_id = old_result
-        .item()
-        .and_then(|item| item.get("class_id"))
-        .and_then(|v| v.as_s().ok())
-        .map(|s| s.to_string());
-    
-    let mut update_expr = vec!["#updated_at = :updated_at"];
-    let mut expr_names = std::collections::HashMap::new();
-    let mut expr_values = std::collections::HashMap::new();
-    
-    expr_names.insert("#updated_at".to_string(), "updated_at".to_string());
-    expr_values.insert(":updated_at".to_string(), 
-        aws_sdk_dynamodb::types::AttributeValue::S(chrono::Utc::now().to_rfc3339()));
-    
-    if let Some(class_id) = &req.class_id {
-        update_expr.push("#class_id = :class_id");
-        expr_names.insert("#class_id".to_string(), "class_id".to_string());
-        expr_values.insert(":class_id".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(class_id.clone()));
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let result = client
+            .get_item()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
+            .send()
+            .await?;
+        // Already deleted (or never existed): deleting stays idempotent
+        let Some(item) = result.item().filter(|item| !is_deleted(item)) else {
+            break;
+        };
+        let old = annotation_from_item(annotation_id, image_id, item);
+        let location = annotation_location(client, table_name, &old).await?;
+        let project_id = location.project_id.clone();
         
-        // Update class counts if class changed
-        if let Some(old_id) = &old_class_id {
-            if old_id != class_id {
-                let _ = crate::classes::increment_class_count(client, table_name, project_id, old_id, -1).await;
-                let _ = crate::classes::increment_class_count(client, table_name, project_id, class_id, 1).await;
+        let tombstone = aws_sdk_dynamodb::types::Update::builder()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
+            .update_expression("SET #deleted_at = :now, #deleted_by = :user")
+            .condition_expression("attribute_exists(PK) AND attribute_not_exists(#deleted_at) AND #class_id = :class_id")
+            .expression_attribute_names("#deleted_at", "deleted_at")
+            .expression_attribute_names("#deleted_by", "deleted_by")
+            .expression_attribute_names("#class_id", "class_id")
+            .expression_attribute_values(":now", aws_sdk_dynamodb::types::AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+            .expression_attribute_values(":user", aws_sdk_dynamodb::types::AttributeValue::S(format!("USER#{}", user_id)))
+            .expression_attribute_values(":class_id", aws_sdk_dynamodb::types::AttributeValue::S(old.class_id.clone()))
+            .build()?;
+        let mut writes = vec![aws_sdk_dynamodb::types::TransactWriteItem::builder().update(tombstone).build()];
+        writes.extend(crate::classes::class_count_update(table_name, &project_id, &old.class_id, -1)?);
+        writes.extend(crate::images::annotation_count_updates(table_name, image_id, &location, -1)?);
+        writes.extend(crate::block_stats::counter_update(table_name, &location.block_id, &crate::block_stats::class_counter(&old.class_id), -1)?);
+        match client.transact_write_items().set_transact_items(Some(writes)).send().await {
+            Ok(_) => {
+                crate::activity::record_activity(client, table_name, &project_id, "deleted", 1).await;
+                crate::audit::record(client, table_name, crate::audit::AuditEntry::new(&project_id, user_id, "deleted", "annotation", annotation_id).block(&old.block_id).before(&crate::audit::annotation_summary(&old))).await;
+                break;
             }
+            // Deleted or reclassified since it was read: read it again
+            Err(e) if attempt < DELETE_ATTEMPTS && e.as_service_error().map(|se| se.is_transaction_canceled_exception()).unwrap_or(false) => {}
+            Err(e) => return Err(e.into()),
         }
     }
     
-    if let Some(geometry) = req.geometry {
-        update_expr.push("#geometry = :geometry");
-        expr_names.insert("#geometry".to_string(), "geometry".to_string());
-        expr_values.insert(":geometry".to_string(), 
-            aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&geometry)?));
-    }
-    
-    let mut builder = client
-        .update_item()
-        .table_name(table_name)
-        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
-        .update_expression(format!("SET {}", update_expr.join(", ")));
-    
-    for (k, v) in expr_names {
-        builder = builder.expression_attribute_names(k, v);
-    }
-    
-    for (k, v) in expr_values {
-        builder = builder.expression_attribute_values(k, v);
-    }
-    
-    builder.send().await?;
-    
-    get_annotation(client, table_name, image_id, annotation_id).await
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Access-Control-Allow-Origin", "*")
+        .body(Body::Empty)
+        .map_err(Box::new)?)
 }
 
-/// Delete an annotation
-pub async fn delete_annotation(
+/// Bring back a deleted annotation (POST /images/{iid}/annotations/{aid}/restore).
+/// Subject to the image's annotation cap like any other create. The
+/// annotation comes back in one transaction with its class count and the
+/// image, block and block stats counts it left.
+pub async fn restore_annotation(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     image_id: &str,
     annotation_id: &str,
-    project_id: &str,
 ) -> Result<Response<Body>, Error> {
+    if let Some(response) = crate::locks::check_unlocked(client, table_name, "annotation", annotation_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await? {
+        return Ok(response);
+    }
+    if let Some(response) = enforce_annotation_cap(client, table_name, image_id, 1).await? {
+        return Ok(response);
+    }
     let pk = format!("IMAGE#{}", image_id);
     let sk = format!("ANNOTATION#{}", annotation_id);
+
+    let mut attempt = 0;
+    let (annotation, location) = loop {
+        attempt += 1;
+        let result = client
+            .get_item()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
+            .send()
+            .await?;
+        let Some(item) = result.item().filter(|item| is_deleted(item)) else {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(serde_json::json!({"error": "No deleted annotation to restore"}).to_string().into())
+                .map_err(Box::new)?);
+        };
+        let deleted = annotation_from_item(annotation_id, image_id, item);
+        let location = annotation_location(client, table_name, &deleted).await?;
+        let now = chrono::Utc::now().to_rfc3339();
+
+        let restore = aws_sdk_dynamodb::types::Update::builder()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
+            .update_expression("SET #updated_at = :now REMOVE #deleted_at, #deleted_by")
+            .condition_expression("attribute_exists(#deleted_at) AND #class_id = :class_id")
+            .expression_attribute_names("#updated_at", "updated_at")
+            .expression_attribute_names("#deleted_at", "deleted_at")
+            .expression_attribute_names("#deleted_by", "deleted_by")
+            .expression_attribute_names("#class_id", "class_id")
+            .expression_attribute_values(":now", aws_sdk_dynamodb::types::AttributeValue::S(now.clone()))
+            .expression_attribute_values(":class_id", aws_sdk_dynamodb::types::AttributeValue::S(deleted.class_id.clone()))
+            .build()?;
+        let mut writes = vec![aws_sdk_dynamodb::types::TransactWriteItem::builder().update(restore).build()];
+        writes.extend(crate::classes::class_count_update(table_name, &location.project_id, &deleted.class_id, 1)?);
+        writes.extend(crate::images::annotation_count_updates(table_name, image_id, &location, 1)?);
+        writes.extend(crate::block_stats::counter_update(table_name, &location.block_id, &crate::block_stats::class_counter(&deleted.class_id), 1)?);
+        match client.transact_write_items().set_transact_items(Some(writes)).send().await {
+            Ok(_) => break (Annotation { updated_at: Some(now), ..deleted }, location),
+            // Restored or purged since it was read: read it again
+            Err(e) if attempt < DELETE_ATTEMPTS && e.as_service_error().map(|se| se.is_transaction_canceled_exception()).unwrap_or(false) => {}
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let project_id = location.project_id.as_str();
+    crate::activity::record_activity(client, table_name, project_id, "created", 1).await;
+    crate::audit::record(client, table_name, crate::audit::AuditEntry::new(project_id, user_id, "restored", "annotation", annotation_id).block(&annotation.block_id).after(&crate::audit::annotation_summary(&annotation))).await;
     
-    // Get annotation to decrement class count
-    let result = client
-        .get_item()
-        .table_name(table_name)
-        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
-        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
-        .send()
-        .await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&annotation)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Tombstone cutoff for a purge: `?older_than_days=`, or the default retention
+fn purge_cutoff(older_than_days: Option<&str>, now: chrono::DateTime<chrono::Utc>) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    let days = match older_than_days {
+        None => DEFAULT_RETENTION_DAYS,
+        Some(raw) => raw.parse::<i64>().ok().filter(|d| (0..=3650).contains(d)).ok_or("older_than_days must be a whole number from 0 to 3650")?,
+    };
+    Ok(now - chrono::Duration::days(days))
+}
+
+/// Permanently remove annotations deleted before the retention cutoff
+/// (POST /admin/annotations/purge?older_than_days=). Scans the whole table,
+/// so run it off-peak, e.g. from a nightly schedule.
+pub async fn purge_deleted_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    older_than_days: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let cutoff = match purge_cutoff(older_than_days, chrono::Utc::now()) {
+        Ok(cutoff) => cutoff,
+        Err(e) => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(serde_json::json!({"error": e}).to_string().into())
+                .map_err(Box::new)?);
+        }
+    };
     
-    if let Some(item) = result.item() {
-        if let Some(class_id) = item.get("class_id").and_then(|v| v.as_s().ok()) {
-            let _ = crate::classes::increment_class_count(client, table_name, project_id, class_id, -1).await;
+    let mut keys = Vec::new();
+    let mut last_key = None;
+    loop {
+        let result = client
+            .scan()
+            .table_name(table_name)
+            .filter_expression("begins_with(SK, :sk_prefix) AND #deleted_at < :cutoff")
+            .projection_expression("PK, SK")
+            .expression_attribute_names("#deleted_at", "deleted_at")
+            .expression_attribute_values(":sk_prefix", aws_sdk_dynamodb::types::AttributeValue::S("ANNOTATION#".to_string()))
+            .expression_attribute_values(":cutoff", aws_sdk_dynamodb::types::AttributeValue::S(cutoff.to_rfc3339()))
+            .set_exclusive_start_key(last_key)
+            .send()
+            .await?;
+        keys.extend(result.items().iter().cloned());
+        last_key = result.last_evaluated_key().cloned();
+        if last_key.is_none() {
+            break;
         }
     }
     
-    client
-        .delete_item()
-        .table_name(table_name)
-        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
-        .send()
-        .await?;
+    let mut requests = Vec::new();
+    for key in &keys {
+        requests.push(
+            aws_sdk_dynamodb::types::WriteRequest::builder()
+                .delete_request(aws_sdk_dynamodb::types::DeleteRequest::builder().set_key(Some(key.clone())).build()?)
+                .build(),
+        );
+    }
+    let failed = batch_write(client, table_name, requests).await?.len();
+    tracing::info!("Purged {} deleted annotations older than {} ({} failed)", keys.len() - failed, cutoff.to_rfc3339(), failed);
     
     Ok(Response::builder()
-        .status(StatusCode::NO_CONTENT)
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
         .header("Access-Control-Allow-Origin", "*")
-        .body(Body::Empty)
+        .body(serde_json::json!({
+            "purged": keys.len() - failed,
+            "failed": failed,
+            "cutoff": cutoff.to_rfc3339(),
+        }).to_string().into())
         .map_err(Box::new)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coordinate_conversion() {
+        let options = WriteOptions::default();
+        assert_eq!(coordinate_conversion("normalized", &options), Ok(None));
+
+        let pixels = WriteOptions {
+            coordinates: Some("pixel"),
+            image_width: Some("200"),
+            image_height: Some("100"),
+            ..Default::default()
+        };
+        assert_eq!(coordinate_conversion("normalized", &pixels), Ok(Some((0.005, 0.01))));
+        assert_eq!(coordinate_conversion("pixel", &pixels), Ok(None));
+
+        let normalized = WriteOptions {
+            coordinates: Some("normalized"),
+            ..pixels
+        };
+        assert_eq!(coordinate_conversion("pixel", &normalized), Ok(Some((200.0, 100.0))));
+
+        // Converting needs the image size
+        let no_size = WriteOptions {
+            coordinates: Some("normalized"),
+            ..Default::default()
+        };
+        assert!(coordinate_conversion("pixel", &no_size).is_err());
+        let unknown = WriteOptions {
+            coordinates: Some("percent"),
+            ..Default::default()
+        };
+        assert!(coordinate_conversion("pixel", &unknown).is_err());
+    }
+
+    #[test]
+    fn test_purge_cutoff() {
+        let now = chrono::Utc::now();
+        assert_eq!(purge_cutoff(None, now), Ok(now - chrono::Duration::days(DEFAULT_RETENTION_DAYS)));
+        assert_eq!(purge_cutoff(Some("0"), now), Ok(now));
+        assert!(purge_cutoff(Some("-1"), now).is_err());
+        assert!(purge_cutoff(Some("soon"), now).is_err());
+    }
+
+    #[test]
+    fn test_normalize_tags() {
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        assert_eq!(normalize_tags(tags(&[" review", "door", "", "review "])), Ok(tags(&["door", "review"])));
+        assert!(normalize_tags(vec!["x".repeat(MAX_TAG_LENGTH + 1)]).is_err());
+        assert!(normalize_tags((0..=MAX_TAGS).map(|i| i.to_string()).collect()).is_err());
+    }
+
+    #[test]
+    fn test_annotation_filter() {
+        assert_eq!(AnnotationFilter::default().expression().expression, LIVE_FILTER);
+        let filter = AnnotationFilter { tag: Some("review"), created_by: Some("42"), ..Default::default() }.expression();
+        assert_eq!(filter.expression, format!("{} AND contains(#tags, :tags) AND #created_by = :created_by", LIVE_FILTER));
+        assert_eq!(filter.values[":created_by"], aws_sdk_dynamodb::types::AttributeValue::S("USER#42".to_string()));
+        let filter = AnnotationFilter { created_by: Some("USER#42"), ..Default::default() }.expression();
+        assert_eq!(filter.values[":created_by"], aws_sdk_dynamodb::types::AttributeValue::S("USER#42".to_string()));
+        let filter = AnnotationFilter { source: Some("human"), min_confidence: Some(0.5), ..Default::default() }.expression();
+        assert!(filter.expression.contains("attribute_not_exists(#source)"));
+        assert_eq!(filter.values[":confidence"], aws_sdk_dynamodb::types::AttributeValue::N("0.5".to_string()));
+        let filter = AnnotationFilter { source: Some("model"), ..Default::default() }.expression();
+        assert!(filter.expression.ends_with("#source = :source"));
+    }
+
+    #[test]
+    fn test_check_provenance() {
+        assert!(check_provenance(None, None).is_ok());
+        assert!(check_provenance(Some("model"), Some(0.87)).is_ok());
+        assert!(check_provenance(Some("robot"), None).is_err());
+        assert!(check_provenance(None, Some(1.5)).is_err());
+        assert!(check_provenance(None, Some(f32::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_reorder_positions() {
+        let ids: Vec<String> = ["a3", "a1", "a2"].iter().map(|id| id.to_string()).collect();
+        assert_eq!(reorder_positions(&ids), Ok(vec![("a3", 0), ("a1", 1), ("a2", 2)]));
+        assert!(reorder_positions(&[]).is_err());
+        assert!(reorder_positions(&["a1".to_string(), "a1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_propagation_targets() {
+        let block: Vec<String> = ["i1", "i2", "i3", "i4"].iter().map(|id| id.to_string()).collect();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        assert_eq!(propagation_targets(&block, "i2", None, Some(5)), Ok(ids(&["i3", "i4"])));
+        assert_eq!(propagation_targets(&block, "i2", None, Some(1)), Ok(ids(&["i3"])));
+        // Listed targets may come before the source; the source and repeats are dropped
+        assert_eq!(propagation_targets(&block, "i2", Some(&ids(&["i1", "i2", "i4", "i1"])), None), Ok(ids(&["i1", "i4"])));
+        assert!(propagation_targets(&block, "i4", None, Some(3)).is_err());
+        assert!(propagation_targets(&block, "i2", Some(&ids(&["x9"])), None).is_err());
+        assert!(propagation_targets(&block, "x9", None, Some(1)).is_err());
+        assert!(propagation_targets(&block, "i2", None, None).is_err());
+    }
+
+    #[test]
+    fn test_project_index_attributes() {
+        let attributes = project_index_attributes("p1", "2026-01-01T00:00:00+00:00", "a1");
+        assert_eq!(attributes[0], ("GSI1PK", aws_sdk_dynamodb::types::AttributeValue::S("PROJECT#p1".to_string())));
+        assert_eq!(attributes[1], ("GSI1SK", aws_sdk_dynamodb::types::AttributeValue::S("ANNOTATION#2026-01-01T00:00:00+00:00#a1".to_string())));
+        assert!(project_index_attributes("unknown", "2026-01-01T00:00:00+00:00", "a1").is_empty());
+    }
+
+    #[test]
+    fn test_requested_annotation_id() {
+        let id = "0B9B5C3E-6A0F-4C34-9C5D-1D2E3F405162";
+        assert_eq!(requested_annotation_id("i1", Some(id), Some("key"), 0), Ok(Some(id.to_lowercase())));
+        assert!(requested_annotation_id("i1", Some("a1"), None, 0).is_err());
+        assert_eq!(requested_annotation_id("i1", None, None, 0), Ok(None));
+        assert_eq!(requested_annotation_id("i1", None, Some("  "), 0), Ok(None));
+        // The same key always gives the same id, per image and batch position
+        let keyed = requested_annotation_id("i1", None, Some("retry-1"), 0).unwrap();
+        assert_eq!(requested_annotation_id("i1", None, Some("retry-1"), 0).unwrap(), keyed);
+        assert_ne!(requested_annotation_id("i2", None, Some("retry-1"), 0).unwrap(), keyed);
+        assert_ne!(requested_annotation_id("i1", None, Some("retry-1"), 1).unwrap(), keyed);
+        assert!(uuid::Uuid::parse_str(&keyed.unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_class_deltas() {
+        assert_eq!(class_deltas(["walls", "doors", "walls", "walls"]), vec![("walls", 3), ("doors", 1)]);
+        assert!(class_deltas::<&str>([]).is_empty());
+    }
+
+    #[test]
+    fn test_delete_failed() {
+        let key = |pk: &str, sk: &str| {
+            HashMap::from([
+                ("PK".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(pk.to_string())),
+                ("SK".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(sk.to_string())),
+            ])
+        };
+        let failed = vec![key("IMAGE#i1", "ANNOTATION#a2"), key("BLOCK#b1", "IMAGE#i1")];
+        assert!(delete_failed(&failed, "IMAGE#i1", "ANNOTATION#a2"));
+        assert!(!delete_failed(&failed, "IMAGE#i1", "ANNOTATION#a1"));
+        assert!(!delete_failed(&failed, "IMAGE#i2", "ANNOTATION#a2"));
+        assert!(!delete_failed(&[], "IMAGE#i1", "ANNOTATION#a2"));
+    }
+
+    #[test]
+    fn test_check_annotation_cap() {
+        assert_eq!(check_annotation_cap(0, 10, 100), Ok(false));
+        assert_eq!(check_annotation_cap(70, 10, 100), Ok(true));
+        assert_eq!(check_annotation_cap(99, 1, 100), Ok(true));
+        assert!(check_annotation_cap(100, 1, 100).is_err());
+        assert!(check_annotation_cap(50, 60, 100).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reassign_class_requires_assignee() {
+        let (client, calls) = crate::test_util::fake_dynamo(vec![
+            serde_json::json!({"PK": {"S": "PROJECT#p"}, "SK": {"S": "CLASS#c2"}, "name": {"S": "door"}}),
+            crate::test_util::image_in_block("p", "b", "img"),
+            crate::test_util::assigned_block("p", "b", "u2"),
+        ]);
+        let body = serde_json::json!({"from_class_id": "c1", "to_class_id": "c2", "image_ids": ["img"]});
+        let response = reassign_class(&client, "table", "u1", "p", body.to_string().as_bytes()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(calls.lock().unwrap().iter().all(|call| call == "GetItem"));
+    }
+
+    #[tokio::test]
+    async fn test_annotation_writes_respect_locks() {
+        let (client, calls) = crate::test_util::fake_dynamo(vec![crate::test_util::lock("annotation", "a2", "u2")]);
+        let response = delete_annotation(&client, "table", "u1", "img", "a2").await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(*calls.lock().unwrap(), ["GetItem"]);
+
+        let body = serde_json::json!({"annotation_ids": ["a1", "a2"]});
+        let response = reorder_annotations(&client, "table", "u1", "img", body.to_string().as_bytes()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(*calls.lock().unwrap(), ["GetItem", "BatchGetItem"]);
+    }
+
+    #[tokio::test]
+    async fn test_reclassify_moves_counts_in_transaction() {
+        let annotation = serde_json::json!({
+            "PK": { "S": "IMAGE#img" },
+            "SK": { "S": "ANNOTATION#a" },
+            "project_id": { "S": "p" },
+            "block_id": { "S": "b" },
+            "class_id": { "S": "c1" },
+        });
+        let (client, calls) = crate::test_util::fake_dynamo(vec![annotation]);
+        let body = br#"{"class_id": "c2"}"#;
+        // The test client serves no writes, so the transaction fails
+        assert!(update_annotation(&client, "table", "u1", "img", "a", body, WriteOptions::default()).await.is_err());
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.last().map(String::as_str), Some("TransactWriteItems"));
+        assert!(!calls.iter().any(|call| call == "UpdateItem"));
+    }
+
+    #[tokio::test]
+    async fn test_restore_live_annotation() {
+        let annotation = serde_json::json!({
+            "PK": { "S": "IMAGE#img" },
+            "SK": { "S": "ANNOTATION#a" },
+            "class_id": { "S": "c1" },
+        });
+        let (client, calls) = crate::test_util::fake_dynamo(vec![annotation]);
+        let response = restore_annotation(&client, "table", "u1", "img", "a").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(!calls.lock().unwrap().iter().any(|call| call == "TransactWriteItems"));
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_batch_create_writes_in_transactions() {
+        let (client, calls) = crate::test_util::fake_dynamo(vec![crate::test_util::image_in_block("p", "b", "img")]);
+        let body = serde_json::json!({"annotations": [
+            {"class_id": "c1", "geometry": {"type": "point", "point": {"x": 1.0, "y": 2.0}}},
+            {"class_id": "c1", "geometry": {"type": "point", "point": {"x": 3.0, "y": 4.0}}},
+        ]});
+        let options = WriteOptions { idempotency_key: Some("retry-1"), ..Default::default() };
+        // The test client serves no writes, so the transaction fails
+        assert!(batch_create_annotations(&client, "table", "u1", "img", body.to_string().as_bytes(), options).await.is_err());
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.iter().filter(|call| *call == "TransactWriteItems").count(), 1);
+        assert!(!calls.iter().any(|call| call == "PutItem"));
+    }
+
+    #[tokio::test]
+    async fn test_reassign_class_to_unknown_class() {
+        let (client, _) = crate::test_util::fake_dynamo(Vec::new());
+        let body = br#"{"from_class_id": "c1", "to_class_id": "c2"}"#;
+        let response = reassign_class(&client, "table", "u1", "p", body).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let Body::Text(text) = response.body() else { panic!("expected a JSON body") };
+        let error: serde_json::Value = serde_json::from_str(text).unwrap();
+        assert_eq!(error["error"], "Class c2 is not in this project");
+    }
+}
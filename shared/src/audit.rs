@@ -0,0 +1,383 @@
+use crate::pagination::{self, Filter, PageParams};
+use crate::types::Annotation;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Audit entries live in the project partition, in the order they happened:
+/// PK=PROJECT#{pid}, SK=AUDIT#{at}#{audit_id}
+pub const AUDIT_PREFIX: &str = "AUDIT#";
+
+/// Kinds of entity an entry can be about
+pub const AUDIT_ENTITIES: [&str; 5] = ["project", "block", "image", "annotation", "class"];
+
+/// One recorded mutation
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct AuditEntry {
+    pub audit_id: String,
+    pub project_id: String,
+    pub actor: String,  // USER#123
+    pub action: String, // created | updated | deleted | restored | reverted | reviewed | ...
+    pub entity_type: String,
+    pub entity_id: String,
+    pub block_id: Option<String>, // block the entity belongs to, for its activity feed
+    pub before: Option<serde_json::Value>, // summary of the entity before the change
+    pub after: Option<serde_json::Value>, // ...and after it (or the fields changed)
+    pub at: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        project_id: &str,
+        user_id: &str,
+        action: &str,
+        entity_type: &str,
+        entity_id: &str,
+    ) -> Self {
+        AuditEntry {
+            audit_id: uuid::Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            actor: format!("USER#{}", user_id),
+            action: action.to_string(),
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.to_string(),
+            block_id: (entity_type == "block").then(|| entity_id.to_string()),
+            before: None,
+            after: None,
+            // Millisecond UTC timestamps sort as strings
+            at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        }
+    }
+
+    /// File the entry under a block too. Entries about a block already are.
+    pub fn block(mut self, block_id: &str) -> Self {
+        self.block_id = (!block_id.is_empty()).then(|| block_id.to_string());
+        self
+    }
+
+    pub fn before<T: Serialize>(mut self, before: &T) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self
+    }
+
+    pub fn after<T: Serialize>(mut self, after: &T) -> Self {
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+
+    /// `after` from the raw body of an update request: the fields it set
+    pub fn changes(mut self, body: &[u8]) -> Self {
+        self.after = serde_json::from_slice(body).ok();
+        self
+    }
+}
+
+/// What an entry keeps of an annotation. Geometry is left to the
+/// annotation's own history, which `version` points into.
+pub fn annotation_summary(annotation: &Annotation) -> serde_json::Value {
+    serde_json::json!({
+        "image_id": annotation.image_id,
+        "class_id": annotation.class_id,
+        "version": annotation.version,
+        "area": annotation.area,
+        "review_status": annotation.review_status,
+        "tags": annotation.tags,
+        "source": annotation.source,
+        "group_id": annotation.group_id,
+        "z_index": annotation.z_index,
+        "attributes": annotation.attributes,
+    })
+}
+
+fn audit_sk(entry: &AuditEntry) -> String {
+    format!("{}{}#{}", AUDIT_PREFIX, entry.at, entry.audit_id)
+}
+
+pub(crate) fn audit_item(entry: &AuditEntry) -> Item {
+    let mut item = HashMap::from([
+        (
+            "PK".to_string(),
+            AttributeValue::S(format!("PROJECT#{}", entry.project_id)),
+        ),
+        ("SK".to_string(), AttributeValue::S(audit_sk(entry))),
+        (
+            "entity_type".to_string(),
+            AttributeValue::S("audit_entry".to_string()),
+        ),
+        (
+            "entity".to_string(),
+            AttributeValue::S(entry.entity_type.clone()),
+        ),
+        (
+            "audit_id".to_string(),
+            AttributeValue::S(entry.audit_id.clone()),
+        ),
+        ("actor".to_string(), AttributeValue::S(entry.actor.clone())),
+        (
+            "action".to_string(),
+            AttributeValue::S(entry.action.clone()),
+        ),
+        (
+            "entity_id".to_string(),
+            AttributeValue::S(entry.entity_id.clone()),
+        ),
+        ("at".to_string(), AttributeValue::S(entry.at.clone())),
+    ]);
+    if let Some(block_id) = &entry.block_id {
+        item.insert("block_id".to_string(), AttributeValue::S(block_id.clone()));
+    }
+    for (name, value) in [("before", &entry.before), ("after", &entry.after)] {
+        if let Some(value) = value {
+            item.insert(name.to_string(), AttributeValue::S(value.to_string()));
+        }
+    }
+    item
+}
+
+fn entry_from_item(project_id: &str, item: &Item) -> Option<AuditEntry> {
+    let text = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    let json = |name: &str| text(name).and_then(|s| serde_json::from_str(&s).ok());
+    Some(AuditEntry {
+        audit_id: text("audit_id")?,
+        project_id: project_id.to_string(),
+        actor: text("actor").unwrap_or_default(),
+        action: text("action").unwrap_or_default(),
+        entity_type: text("entity").unwrap_or_default(),
+        entity_id: text("entity_id").unwrap_or_default(),
+        block_id: text("block_id"),
+        before: json("before"),
+        after: json("after"),
+        at: text("at").unwrap_or_default(),
+    })
+}
+
+/// Write audit entries. Best-effort like `history::record`: the audited write
+/// has already happened, so failures are only logged. Entries without a
+/// project have nowhere to go and are dropped.
+pub async fn record_all(client: &DynamoClient, table_name: &str, entries: Vec<AuditEntry>) {
+    let items: Vec<Item> = entries
+        .iter()
+        .filter(|e| !e.project_id.is_empty())
+        .map(audit_item)
+        .collect();
+    let result = match items.len() {
+        0 => Ok(0),
+        1 => client
+            .put_item()
+            .table_name(table_name)
+            .set_item(items.into_iter().next())
+            .send()
+            .await
+            .map(|_| 0)
+            .map_err(Error::from),
+        _ => crate::annotations::batch_put_items(client, table_name, items)
+            .await
+            .map(|unwritten| unwritten.len()),
+    };
+    match result {
+        Ok(0) => {}
+        Ok(unwritten) => tracing::warn!("{} audit entries could not be written", unwritten),
+        Err(e) => tracing::warn!("Failed to record audit entries: {}", e),
+    }
+}
+
+/// Write one audit entry, see `record_all`
+pub async fn record(client: &DynamoClient, table_name: &str, entry: AuditEntry) {
+    record_all(client, table_name, vec![entry]).await
+}
+
+/// Optional `?entity_type=&entity_id=&actor=` filters of the audit listing
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AuditQuery<'a> {
+    pub entity_type: Option<&'a str>,
+    pub entity_id: Option<&'a str>,
+    pub actor: Option<&'a str>, // user id, with or without USER#
+}
+
+impl AuditQuery<'_> {
+    fn filter(&self) -> Result<Option<Filter>, String> {
+        if let Some(entity_type) = self.entity_type.filter(|t| !AUDIT_ENTITIES.contains(t)) {
+            return Err(format!(
+                "Unknown entity_type '{}', expected one of: {}",
+                entity_type,
+                AUDIT_ENTITIES.join(", ")
+            ));
+        }
+        let actor = self.actor.map(|a| {
+            if a.starts_with("USER#") {
+                a.to_string()
+            } else {
+                format!("USER#{}", a)
+            }
+        });
+        let mut filter = Filter::default();
+        for (attribute, value) in [
+            ("entity", self.entity_type),
+            ("entity_id", self.entity_id),
+            ("actor", actor.as_deref()),
+        ] {
+            if let Some(value) = value {
+                filter = filter.equals(attribute, value);
+            }
+        }
+        Ok((!filter.expression.is_empty()).then_some(filter))
+    }
+}
+
+/// A project's audit trail, oldest first, paged with `?limit=&cursor=`
+/// (GET /projects/{pid}/audit)
+pub async fn list_project_audit(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    params: PageParams<'_>,
+    query: AuditQuery<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match pagination::page_request(params, AUDIT_PREFIX) {
+        Ok(request) => request,
+        Err(e) => return pagination::invalid_page(e),
+    };
+    let filter = match query.filter() {
+        Ok(filter) => filter,
+        Err(e) => return pagination::invalid_page(e),
+    };
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        &format!("PROJECT#{}", project_id),
+        AUDIT_PREFIX,
+        filter.as_ref(),
+        &request,
+    )
+    .await?;
+    pagination::page_response(&pagination::Page {
+        items: page
+            .items
+            .iter()
+            .filter_map(|item| entry_from_item(project_id, item))
+            .collect::<Vec<_>>(),
+        next_cursor: page.next_cursor,
+    })
+}
+
+/// Filter of a block's activity: its entries, after `since` if given (any
+/// RFC 3339 time, compared in the UTC form entries are stamped with)
+fn block_filter(block_id: &str, since: Option<&str>) -> Result<Filter, String> {
+    let filter = Filter::default().equals("block_id", block_id);
+    match since {
+        None => Ok(filter),
+        Some(since) => {
+            let since = chrono::DateTime::parse_from_rfc3339(since)
+                .map_err(|_| "since must be an RFC 3339 timestamp".to_string())?
+                .with_timezone(&chrono::Utc)
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+            Ok(filter.and("#at > :at", "at", &since))
+        }
+    }
+}
+
+/// What changed in a block, oldest first and paged with `?limit=&cursor=`:
+/// the audit entries of the block and of its images and annotations, after
+/// `?since=` if given (GET /projects/{pid}/blocks/{bid}/activity). Entries
+/// recorded before they were filed under blocks don't show up.
+pub async fn list_block_activity(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+    params: PageParams<'_>,
+    since: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let request = match pagination::page_request(params, AUDIT_PREFIX) {
+        Ok(request) => request,
+        Err(e) => return pagination::invalid_page(e),
+    };
+    let filter = match block_filter(block_id, since) {
+        Ok(filter) => filter,
+        Err(e) => return pagination::invalid_page(e),
+    };
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        &format!("PROJECT#{}", project_id),
+        AUDIT_PREFIX,
+        Some(&filter),
+        &request,
+    )
+    .await?;
+    pagination::page_response(&pagination::Page {
+        items: page
+            .items
+            .iter()
+            .filter_map(|item| entry_from_item(project_id, item))
+            .collect::<Vec<_>>(),
+        next_cursor: page.next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_item_round_trip() {
+        let entry = AuditEntry::new("p1", "u1", "updated", "class", "c1")
+            .before(&serde_json::json!({"name": "Wall"}))
+            .changes(br#"{"name": "Walls"}"#);
+        let item = audit_item(&entry);
+        let sk = item["SK"].as_s().unwrap();
+        assert!(sk.starts_with(&format!("AUDIT#{}#", entry.at)));
+        assert!(sk.ends_with(&entry.audit_id));
+        assert_eq!(entry_from_item("p1", &item), Some(entry));
+    }
+
+    #[test]
+    fn test_block_entries() {
+        let entry = AuditEntry::new("p1", "u1", "deleted", "block", "b1");
+        assert_eq!(entry.block_id.as_deref(), Some("b1"));
+        let entry = AuditEntry::new("p1", "u1", "created", "image", "i1").block("b2");
+        assert_eq!(entry_from_item("p1", &audit_item(&entry)), Some(entry));
+        assert_eq!(
+            AuditEntry::new("p1", "u1", "created", "image", "i1")
+                .block("")
+                .block_id,
+            None
+        );
+
+        let filter = block_filter("b1", Some("2026-10-14T10:00:00+10:00")).unwrap();
+        assert_eq!(filter.expression, "#block_id = :block_id AND #at > :at");
+        assert_eq!(
+            filter.values[":at"],
+            AttributeValue::S("2026-10-14T00:00:00.000Z".to_string())
+        );
+        assert!(block_filter("b1", Some("yesterday")).is_err());
+    }
+
+    #[test]
+    fn test_audit_query_filter() {
+        assert!(AuditQuery::default().filter().unwrap().is_none());
+        let filter = AuditQuery {
+            entity_type: Some("annotation"),
+            actor: Some("u1"),
+            ..Default::default()
+        }
+        .filter()
+        .unwrap()
+        .unwrap();
+        assert_eq!(filter.expression, "#entity = :entity AND #actor = :actor");
+        assert_eq!(
+            filter.values[":actor"],
+            AttributeValue::S("USER#u1".to_string())
+        );
+        assert!(AuditQuery {
+            entity_type: Some("comment"),
+            ..Default::default()
+        }
+        .filter()
+        .is_err());
+    }
+}
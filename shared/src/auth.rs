@@ -1,6 +1,8 @@
+use crate::sessions::{self, SessionCheck};
 use aws_sdk_cognitoidentityprovider::{
     types::AuthFlowType::RefreshTokenAuth, Client as CognitoClient,
 };
+use aws_sdk_dynamodb::Client as DynamoClient;
 use base64::{engine::general_purpose, Engine as _};
 use hmac::{Hmac, Mac};
 use lambda_http::{http::StatusCode, Body, Error, Response};
@@ -12,6 +14,8 @@ use std::str::from_utf8;
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub device_name: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -27,11 +31,16 @@ pub struct LoginResponse {
     pub access_token: String,
     pub refresh_token: String,
     pub expires_in: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct RefreshRequest {
     pub refresh_token: String,
+    // Cognito username; only needed for tokens from before session tracking
+    #[serde(default)]
+    pub username: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -55,6 +64,8 @@ fn compute_secret_hash(username: &str, client_id: &str, client_secret: &str) ->
 /// Handle user login with Cognito
 pub async fn login(
     cognito_client: &CognitoClient,
+    dynamo_client: &DynamoClient,
+    table_name: &str,
     client_id: &str,
     client_secret: &str,
     body: &Body,
@@ -109,11 +120,37 @@ pub async fn login(
                     login_request.email
                 );
 
+                let id_token = auth_result.id_token().unwrap_or_default();
+                let refresh_token = auth_result.refresh_token().unwrap_or_default();
+
+                // Track the refresh token as a device session; login still succeeds if this fails
+                let session_id = match sessions::subject_from_id_token(id_token) {
+                    Some(user_id) if !refresh_token.is_empty() => {
+                        match sessions::record_session(
+                            dynamo_client,
+                            table_name,
+                            &user_id,
+                            refresh_token,
+                            login_request.device_name.as_deref(),
+                        )
+                        .await
+                        {
+                            Ok(session_id) => Some(session_id),
+                            Err(e) => {
+                                tracing::error!("Failed to record session: {}", e);
+                                None
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+
                 let login_response = LoginResponse {
-                    id_token: auth_result.id_token().unwrap_or_default().to_string(),
+                    id_token: id_token.to_string(),
                     access_token: auth_result.access_token().unwrap_or_default().to_string(),
-                    refresh_token: auth_result.refresh_token().unwrap_or_default().to_string(),
+                    refresh_token: refresh_token.to_string(),
                     expires_in: auth_result.expires_in(),
+                    session_id,
                 };
 
                 Ok(Response::builder()
@@ -244,7 +281,7 @@ pub async fn signup(
         .await;
 
     match signup_result {
-        Ok(_response) => {
+        Ok(response) => {
             tracing::info!("Signup successful for user: {}", signup_request.email);
 
             // Auto-confirm user since they used a valid invite (email already verified)
@@ -270,6 +307,7 @@ pub async fn signup(
                 dynamo_client,
                 table_name,
                 &signup_request.invite_code,
+                response.user_sub(),
             )
             .await
             {
@@ -320,6 +358,8 @@ pub async fn signup(
 /// Handle token refresh with Cognitio
 pub async fn refresh_token(
     cognitio_client: &CognitoClient,
+    dynamo_client: &DynamoClient,
+    table_name: &str,
     client_id: &str,
     client_secret: &str,
     body: &Body,
@@ -349,14 +389,50 @@ pub async fn refresh_token(
         }
     };
 
-    tracing::info!("Refreshing token using REFRESH_TOKEN_AUTH flow");
-    let auth_result = cognitio_client
+    // Revoked sessions must not be able to mint new tokens
+    let session =
+        match sessions::touch_session(dynamo_client, table_name, &refresh_request.refresh_token)
+            .await?
+        {
+            SessionCheck::Active {
+                session_id,
+                user_id,
+            } => Some((session_id, user_id)),
+            SessionCheck::Untracked => None,
+            SessionCheck::Revoked => {
+                let error = ErrorResponse {
+                    error: "RefreshFailed".to_string(),
+                    message: "Session has been revoked. Please login again".to_string(),
+                };
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .header("Content-Type", "application/json")
+                    .header("Access-Control-Allow-Origin", "*")
+                    .body(serde_json::to_string(&error)?.into())
+                    .map_err(Box::new)?);
+            }
+        };
+
+    // The app client has a secret, so Cognito wants SECRET_HASH of the
+    // user the token belongs to (their sub, as recorded on the session)
+    let (session_id, username) = match session {
+        Some((session_id, user_id)) => (Some(session_id), Some(user_id)),
+        None => (None, refresh_request.username.clone()),
+    };
+    let mut request = cognitio_client
         .initiate_auth()
         .auth_flow(RefreshTokenAuth)
         .client_id(client_id)
-        .auth_parameters("REFRESH_TOKEN", &refresh_request.refresh_token)
-        .send()
-        .await;
+        .auth_parameters("REFRESH_TOKEN", &refresh_request.refresh_token);
+    if let Some(username) = &username {
+        request = request.auth_parameters(
+            "SECRET_HASH",
+            compute_secret_hash(username, client_id, client_secret),
+        );
+    }
+
+    tracing::info!("Refreshing token using REFRESH_TOKEN_AUTH flow");
+    let auth_result = request.send().await;
 
     match auth_result {
         Ok(response) => {
@@ -370,6 +446,7 @@ pub async fn refresh_token(
                         .unwrap_or(&refresh_request.refresh_token)
                         .to_string(),
                     expires_in: auth_result.expires_in(),
+                    session_id,
                 };
                 let response = Response::builder()
                     .status(StatusCode::OK)
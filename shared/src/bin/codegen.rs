@@ -0,0 +1,36 @@
+//! Writes the generated client models:
+//! `cargo run -p doxle-shared --bin codegen -- [out_dir]` (default `clients/`)
+use std::path::PathBuf;
+
+fn main() -> std::io::Result<()> {
+    let out_dir = PathBuf::from(
+        std::env::args()
+            .nth(1)
+            .unwrap_or_else(|| "clients".to_string()),
+    );
+    let schema = doxle_shared::schema::schema();
+
+    let files = [
+        (
+            "schema.json",
+            serde_json::to_string_pretty(&schema).unwrap_or_default() + "\n",
+        ),
+        (
+            "typescript/types.ts",
+            doxle_shared::schema::typescript(&schema),
+        ),
+        (
+            "python/doxle_client.py",
+            doxle_shared::schema::python(&schema),
+        ),
+    ];
+    for (name, contents) in files {
+        let path = out_dir.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+        println!("wrote {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,724 @@
+use crate::audit::AuditEntry;
+use crate::images::ImageLocation;
+use crate::responses::json_response;
+use crate::types::{Class, CreateClassRequest, Image, MoveBlockRequest, MoveImageRequest};
+use crate::{
+    annotations, block_stats, blocks, classes, duplicate, images, members, projects, region,
+    storage, users,
+};
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put, TransactWriteItem};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize)]
+pub struct MoveReport {
+    pub block_id: String,
+    pub from_project_id: String,
+    pub to_project_id: String,
+    pub images_moved: usize,
+    pub annotations_moved: usize,
+    pub objects_moved: usize,
+    pub classes_created: Vec<Class>, // target classes added for names it lacked
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImageMoveReport {
+    pub image: Image, // where it is now; a new image when copied
+    pub copied: bool,
+    pub from_block_id: String,
+    pub to_block_id: String,
+    pub from_project_id: String,
+    pub to_project_id: String,
+    pub annotations_moved: usize,
+    pub objects_moved: usize,
+    pub classes_created: Vec<Class>,
+}
+
+/// Target class ids of the source classes, matched by name ignoring case and
+/// surrounding whitespace, and the classes to create for names the target
+/// doesn't have
+fn map_classes(
+    source: &[Class],
+    target: &[Class],
+) -> (HashMap<String, String>, Vec<CreateClassRequest>) {
+    let same = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+    let mut mapped = HashMap::new();
+    let mut missing: Vec<CreateClassRequest> = Vec::new();
+    for class in source {
+        match target.iter().find(|t| same(&t.name, &class.name)) {
+            Some(existing) => {
+                mapped.insert(class.class_id.clone(), existing.class_id.clone());
+            }
+            None if missing.iter().any(|m| same(&m.name, &class.name)) => {}
+            None => missing.push(CreateClassRequest {
+                name: class.name.clone(),
+                color: class.color.clone(),
+                properties: class.properties.clone(),
+                sort_order: None,
+                hotkey: class.hotkey.clone(),
+            }),
+        }
+    }
+    (mapped, missing)
+}
+
+/// Target project class ids of a project's classes, by name, creating those
+/// the target lacks. Returns them with the classes created.
+async fn target_classes(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    target_id: &str,
+) -> Result<(HashMap<String, String>, Vec<Class>), Error> {
+    let source_classes = classes::fetch_project_classes(client, table_name, project_id).await?;
+    let target_classes = classes::fetch_project_classes(client, table_name, target_id).await?;
+    let (mut class_ids, missing) = map_classes(&source_classes, &target_classes);
+    let classes_created = if missing.is_empty() {
+        Vec::new()
+    } else {
+        classes::insert_classes(
+            client,
+            table_name,
+            user_id,
+            target_id,
+            &target_classes,
+            missing,
+        )
+        .await?
+    };
+    for class in &source_classes {
+        if let Some(created) = classes_created
+            .iter()
+            .find(|c| c.name.trim().eq_ignore_ascii_case(class.name.trim()))
+        {
+            class_ids.insert(class.class_id.clone(), created.class_id.clone());
+        }
+    }
+    Ok((class_ids, classes_created))
+}
+
+/// Where an object of the block goes in the target project, for keys under
+/// the block's folder in the source
+fn moved_key(key: &str, from_prefix: &str, to_prefix: &str) -> Option<String> {
+    key.strip_prefix(from_prefix)
+        .map(|rest| format!("{}{}", to_prefix, rest))
+}
+
+/// Copy every object under `from_prefix` to `to_prefix`, returning the keys
+/// copied so they can be deleted once nothing points at them
+async fn copy_prefix(
+    s3_client: &S3Client,
+    from_prefix: &str,
+    to_prefix: &str,
+) -> Result<Vec<String>, Error> {
+    let bucket = region::bucket_name();
+    let mut copied = Vec::new();
+    let mut continuation: Option<String> = None;
+    loop {
+        let resp = s3_client
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(from_prefix)
+            .set_continuation_token(continuation.take())
+            .send()
+            .await
+            .map_err(|e| format!("S3 list failed: {}", e))?;
+        for key in resp.contents().iter().filter_map(|o| o.key()) {
+            let Some(target) = moved_key(key, from_prefix, to_prefix) else {
+                continue;
+            };
+            s3_client
+                .copy_object()
+                .bucket(bucket)
+                .copy_source(storage::copy_source(bucket, key))
+                .key(target)
+                .send()
+                .await
+                .map_err(|e| format!("S3 copy of {} failed: {}", key, e))?;
+            copied.push(key.to_string());
+        }
+        match resp.next_continuation_token() {
+            Some(token) if resp.is_truncated().unwrap_or(false) => {
+                continuation = Some(token.to_string())
+            }
+            _ => break,
+        }
+    }
+    Ok(copied)
+}
+
+/// Best-effort removal of the source objects after a move
+async fn delete_objects(s3_client: &S3Client, keys: Vec<String>) {
+    for chunk in keys.chunks(1000) {
+        let objects = chunk
+            .iter()
+            .filter_map(|key| {
+                aws_sdk_s3::types::ObjectIdentifier::builder()
+                    .key(key)
+                    .build()
+                    .ok()
+            })
+            .collect();
+        let result = match aws_sdk_s3::types::Delete::builder()
+            .set_objects(Some(objects))
+            .build()
+        {
+            Ok(delete) => s3_client
+                .delete_objects()
+                .bucket(region::bucket_name())
+                .delete(delete)
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to delete moved S3 objects: {}", e);
+        }
+    }
+}
+
+/// Move a block with its images and annotations to another project
+/// (POST /projects/{pid}/blocks/{bid}/move, body `{"target_project_id"}`),
+/// for work started under the wrong project. Ids stay the same; annotations
+/// switch to the target's classes by name, with classes it lacks created
+/// there, and the block's S3 objects move to the target's prefix. The caller
+/// must be a member of the target project, or an admin.
+pub async fn move_block(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: MoveBlockRequest = serde_json::from_slice(body)?;
+    let target_id = req.target_project_id.trim();
+    if target_id.is_empty() || target_id == project_id {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({"error": "target_project_id must name another project"}),
+        );
+    }
+    if projects::fetch_project(client, table_name, target_id)
+        .await?
+        .is_none()
+    {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Target project not found"}),
+        );
+    }
+    if !members::is_member(client, table_name, target_id, user_id).await?
+        && !users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(
+            StatusCode::FORBIDDEN,
+            serde_json::json!({"error": "Not a member of the target project"}),
+        );
+    }
+    let block_sk = format!("BLOCK#{}", block_id);
+    let source_pk = format!("PROJECT#{}", project_id);
+    let block_item = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(source_pk.clone()))
+        .key("SK", AttributeValue::S(block_sk.clone()))
+        .send()
+        .await?
+        .item()
+        .cloned();
+    let Some(mut block_item) = block_item else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Block not found"}),
+        );
+    };
+    if let Some(response) =
+        blocks::check_assignee(client, table_name, project_id, block_id, user_id).await?
+    {
+        return Ok(response);
+    }
+
+    // Classes first, so every annotation has one to switch to
+    let (class_ids, classes_created) =
+        target_classes(client, table_name, user_id, project_id, target_id).await?;
+
+    // Objects are copied before anything points at them and deleted after
+    let from_prefix = storage::block_prefix(project_id, block_id);
+    let to_prefix = storage::block_prefix(target_id, block_id);
+    let copied = copy_prefix(s3_client, &from_prefix, &to_prefix).await?;
+
+    let location = images::ImageLocation {
+        project_id: target_id.to_string(),
+        block_id: block_id.to_string(),
+    };
+    let block_images = images::fetch_block_images(client, table_name, block_id).await?;
+    let mut moved_classes = Vec::new();
+    let moved_url = |url: &str| {
+        storage::object_key(url)
+            .and_then(|key| moved_key(&key, &from_prefix, &to_prefix))
+            .map(|key| storage::object_url(&key))
+    };
+    for image in &block_images {
+        let moved = [
+            ("url", moved_url(&image.url)),
+            (
+                "thumbnail_url",
+                image.thumbnail_url.as_deref().and_then(moved_url),
+            ),
+            ("tiles_url", image.tiles_url.as_deref().and_then(moved_url)),
+        ];
+        let mut update = client
+            .update_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(format!("BLOCK#{}", block_id)))
+            .key("SK", AttributeValue::S(format!("IMAGE#{}", image.image_id)));
+        let mut sets = Vec::new();
+        for (name, url) in moved {
+            if let Some(url) = url {
+                sets.push(format!("#{0} = :{0}", name));
+                update = update
+                    .expression_attribute_names(format!("#{}", name), name)
+                    .expression_attribute_values(format!(":{}", name), AttributeValue::S(url));
+            }
+        }
+        if !sets.is_empty() {
+            update
+                .update_expression(format!("SET {}", sets.join(", ")))
+                .send()
+                .await?;
+        }
+        images::put_image_location(client, table_name, &image.image_id, &location).await?;
+        moved_classes.extend(
+            annotations::rehome_image_annotations(
+                client,
+                table_name,
+                &image.image_id,
+                &location,
+                &class_ids,
+            )
+            .await?,
+        );
+    }
+
+    // The block row changes partition in one step
+    block_item.insert(
+        "PK".to_string(),
+        AttributeValue::S(format!("PROJECT#{}", target_id)),
+    );
+    let put = Put::builder()
+        .table_name(table_name)
+        .set_item(Some(block_item))
+        .build()?;
+    let delete = Delete::builder()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(source_pk))
+        .key("SK", AttributeValue::S(block_sk))
+        .build()?;
+    client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().put(put).build())
+        .transact_items(TransactWriteItem::builder().delete(delete).build())
+        .send()
+        .await?;
+
+    for (class_id, delta) in annotations::class_deltas(moved_classes.iter()) {
+        let target_class = class_ids.get(class_id).unwrap_or(class_id);
+        for (project, class, delta) in [
+            (project_id, class_id, -delta),
+            (target_id, target_class, delta),
+        ] {
+            if let Err(e) =
+                classes::increment_class_count(client, table_name, project, class, delta).await
+            {
+                tracing::warn!("Failed to update the count of class {}: {}", class, e);
+            }
+        }
+    }
+    // Its class counters now count the target's classes
+    if let Err(e) = block_stats::recount_block(client, table_name, block_id).await {
+        tracing::warn!("Failed to recount block {} after its move: {}", block_id, e);
+    }
+    let objects_moved = copied.len();
+    delete_objects(s3_client, copied).await;
+
+    let moved = serde_json::json!({"from_project_id": project_id, "to_project_id": target_id});
+    let entries = [project_id, target_id]
+        .into_iter()
+        .map(|project| AuditEntry::new(project, user_id, "moved", "block", block_id).after(&moved))
+        .collect();
+    crate::audit::record_all(client, table_name, entries).await;
+
+    let report = MoveReport {
+        block_id: block_id.to_string(),
+        from_project_id: project_id.to_string(),
+        to_project_id: target_id.to_string(),
+        images_moved: block_images.len(),
+        annotations_moved: moved_classes.len(),
+        objects_moved,
+        classes_created,
+    };
+    json_response(StatusCode::OK, serde_json::to_value(&report)?)
+}
+
+/// Move an image with its annotations to another block, of this project or
+/// another (POST /images/{iid}/move, body `{"target_block_id",
+/// "target_project_id"?, "copy"?}`), when it was uploaded to the wrong one.
+/// The id stays the same: its S3 objects move to the target block's prefix,
+/// it goes last in that block, and across projects its annotations switch
+/// classes by name as with a block move. With `copy` the image stays and a
+/// copy with new ids goes to the target instead. The caller must be able to
+/// edit both blocks.
+pub async fn move_image(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: MoveImageRequest = serde_json::from_slice(body)?;
+    let Some(from) = images::image_location(client, table_name, image_id).await? else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Image not found"}),
+        );
+    };
+    let to = ImageLocation {
+        project_id: req
+            .target_project_id
+            .as_deref()
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .unwrap_or(&from.project_id)
+            .to_string(),
+        block_id: req.target_block_id.trim().to_string(),
+    };
+    if to.block_id.is_empty() || (to.block_id == from.block_id && !req.copy) {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({"error": "target_block_id must name another block"}),
+        );
+    }
+    if blocks::fetch_block(client, table_name, &to.project_id, &to.block_id)
+        .await?
+        .is_none()
+    {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Target block not found"}),
+        );
+    }
+    let across = to.project_id != from.project_id;
+    if across
+        && !members::is_member(client, table_name, &to.project_id, user_id).await?
+        && !users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(
+            StatusCode::FORBIDDEN,
+            serde_json::json!({"error": "Not a member of the target project"}),
+        );
+    }
+    // A copy only reads the source block
+    let check = [(!req.copy).then_some(&from), Some(&to)];
+    for location in check.into_iter().flatten() {
+        if let Some(response) = blocks::check_assignee(
+            client,
+            table_name,
+            &location.project_id,
+            &location.block_id,
+            user_id,
+        )
+        .await?
+        {
+            return Ok(response);
+        }
+    }
+    let image_sk = format!("IMAGE#{}", image_id);
+    let item = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("BLOCK#{}", from.block_id)))
+        .key("SK", AttributeValue::S(image_sk.clone()))
+        .send()
+        .await?
+        .item()
+        .cloned();
+    let Some(mut item) = item else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Image not found"}),
+        );
+    };
+
+    let (class_ids, classes_created) = if across {
+        target_classes(
+            client,
+            table_name,
+            user_id,
+            &from.project_id,
+            &to.project_id,
+        )
+        .await?
+    } else {
+        (HashMap::new(), Vec::new())
+    };
+    let order = images::fetch_block_images(client, table_name, &to.block_id)
+        .await?
+        .iter()
+        .filter_map(|image| image.order)
+        .max()
+        .map_or(0, |order| order + 1);
+    item.insert("order".to_string(), AttributeValue::N(order.to_string()));
+    let report = |image_id: &str, item: &HashMap<String, AttributeValue>| {
+        let mut image = images::image_from_item(&to.block_id, image_id, item);
+        image.url = storage::public_url(&image.url);
+        image.thumbnail_url = image.thumbnail_url.as_deref().map(storage::public_url);
+        image.tiles_url = image.tiles_url.as_deref().map(storage::public_url);
+        ImageMoveReport {
+            image,
+            copied: req.copy,
+            from_block_id: from.block_id.clone(),
+            to_block_id: to.block_id.clone(),
+            from_project_id: from.project_id.clone(),
+            to_project_id: to.project_id.clone(),
+            annotations_moved: 0,
+            objects_moved: 0,
+            classes_created: classes_created.clone(),
+        }
+    };
+
+    if req.copy {
+        let copied = duplicate::copy_image(
+            client,
+            s3_client,
+            table_name,
+            user_id,
+            item,
+            &to,
+            Some(&class_ids),
+        )
+        .await?
+        .map_err(|reason| format!("Failed to copy image {}: {}", image_id, reason))?;
+        let entry = AuditEntry::new(
+            &to.project_id,
+            user_id,
+            "created",
+            "image",
+            &copied.image_id,
+        )
+        .block(&to.block_id)
+        .after(&serde_json::json!({"copied_from": image_id}));
+        crate::audit::record(client, table_name, entry).await;
+        let report = ImageMoveReport {
+            annotations_moved: copied.annotations_copied,
+            ..report(&copied.image_id, &copied.item)
+        };
+        return json_response(StatusCode::CREATED, serde_json::to_value(&report)?);
+    }
+
+    // Objects are copied before anything points at them and deleted after:
+    // the upload and its folder of levels, thumbnail and tiles
+    let from_prefix = storage::block_prefix(&from.project_id, &from.block_id);
+    let to_prefix = storage::block_prefix(&to.project_id, &to.block_id);
+    let mut copied = Vec::new();
+    for own in [format!("{}.", image_id), format!("{}/", image_id)] {
+        copied.extend(
+            copy_prefix(
+                s3_client,
+                &format!("{}{}", from_prefix, own),
+                &format!("{}{}", to_prefix, own),
+            )
+            .await?,
+        );
+    }
+    for name in ["url", "thumbnail_url", "tiles_url"] {
+        let moved = item
+            .get(name)
+            .and_then(|v| v.as_s().ok())
+            .and_then(|url| storage::object_key(url))
+            .and_then(|key| moved_key(&key, &from_prefix, &to_prefix));
+        if let Some(key) = moved {
+            item.insert(
+                name.to_string(),
+                AttributeValue::S(storage::object_url(&key)),
+            );
+        }
+    }
+    item.insert(
+        "PK".to_string(),
+        AttributeValue::S(format!("BLOCK#{}", to.block_id)),
+    );
+
+    // The image row changes partition in one step, with the counts of both
+    // blocks
+    let status = item
+        .get("status")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_else(|| "unannotated".to_string());
+    let annotation_count = images::stored_annotation_count(&item) as i64;
+    let put = Put::builder()
+        .table_name(table_name)
+        .set_item(Some(item.clone()))
+        .condition_expression("attribute_not_exists(PK)")
+        .build()?;
+    let delete = Delete::builder()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("BLOCK#{}", from.block_id)))
+        .key("SK", AttributeValue::S(image_sk))
+        .condition_expression("attribute_exists(PK)")
+        .build()?;
+    let mut writes = vec![
+        TransactWriteItem::builder().put(put).build(),
+        TransactWriteItem::builder().delete(delete).build(),
+    ];
+    for (location, delta) in [(&from, -1), (&to, 1)] {
+        let counter = block_stats::status_counter(&status);
+        writes.extend(block_stats::counter_update(
+            table_name,
+            &location.block_id,
+            &counter,
+            delta,
+        )?);
+        if annotation_count > 0 {
+            writes.push(images::annotation_count_update(
+                table_name,
+                format!("PROJECT#{}", location.project_id),
+                format!("BLOCK#{}", location.block_id),
+                delta * annotation_count,
+            )?);
+        }
+    }
+    let result = client
+        .transact_write_items()
+        .set_transact_items(Some(writes))
+        .send()
+        .await;
+    match result {
+        Ok(_) => {}
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_transaction_canceled_exception())
+                .unwrap_or(false) =>
+        {
+            let copies = copied
+                .iter()
+                .filter_map(|key| moved_key(key, &from_prefix, &to_prefix))
+                .collect();
+            delete_objects(s3_client, copies).await;
+            return json_response(
+                StatusCode::CONFLICT,
+                serde_json::json!({"error": "The image changed while it was being moved"}),
+            );
+        }
+        Err(e) => return Err(e.into()),
+    }
+    images::put_image_location(client, table_name, image_id, &to).await?;
+    let moved_classes =
+        annotations::rehome_image_annotations(client, table_name, image_id, &to, &class_ids)
+            .await?;
+
+    for (class_id, delta) in annotations::class_deltas(moved_classes.iter()) {
+        let target_class = class_ids.get(class_id).unwrap_or(class_id);
+        for (block_id, class, delta) in [
+            (&from.block_id, class_id, -delta),
+            (&to.block_id, target_class, delta),
+        ] {
+            let changes = [(block_stats::class_counter(class), i64::from(delta))];
+            block_stats::increment(client, table_name, block_id, &changes).await;
+        }
+        if !across {
+            continue;
+        }
+        for (project, class, delta) in [
+            (from.project_id.as_str(), class_id, -delta),
+            (to.project_id.as_str(), target_class, delta),
+        ] {
+            if let Err(e) =
+                classes::increment_class_count(client, table_name, project, class, delta).await
+            {
+                tracing::warn!("Failed to update the count of class {}: {}", class, e);
+            }
+        }
+    }
+    let objects_moved = copied.len();
+    delete_objects(s3_client, copied).await;
+
+    let moved = serde_json::json!({
+        "from_block_id": from.block_id,
+        "to_block_id": to.block_id,
+        "from_project_id": from.project_id,
+        "to_project_id": to.project_id,
+    });
+    let mut projects = vec![from.project_id.as_str()];
+    if across {
+        projects.push(&to.project_id);
+    }
+    let entries = projects
+        .into_iter()
+        .map(|project| {
+            AuditEntry::new(project, user_id, "moved", "image", image_id)
+                .block(&to.block_id)
+                .after(&moved)
+        })
+        .collect();
+    crate::audit::record_all(client, table_name, entries).await;
+
+    let report = ImageMoveReport {
+        annotations_moved: moved_classes.len(),
+        objects_moved,
+        ..report(image_id, &item)
+    };
+    json_response(StatusCode::OK, serde_json::to_value(&report)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(class_id: &str, name: &str) -> Class {
+        Class {
+            class_id: class_id.to_string(),
+            name: name.to_string(),
+            ..Class::default()
+        }
+    }
+
+    #[test]
+    fn test_map_classes() {
+        let source = [
+            class("s1", "Door"),
+            class("s2", "Window"),
+            class("s3", "window "),
+        ];
+        let target = [class("t1", " door")];
+        let (mapped, missing) = map_classes(&source, &target);
+        assert_eq!(
+            mapped,
+            HashMap::from([("s1".to_string(), "t1".to_string())])
+        );
+        assert_eq!(
+            missing.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            vec!["Window"]
+        );
+    }
+
+    #[test]
+    fn test_moved_key() {
+        let from = "projects/p1/blocks/b/";
+        let to = "projects/p2/blocks/b/";
+        assert_eq!(
+            moved_key("projects/p1/blocks/b/i.png", from, to),
+            Some("projects/p2/blocks/b/i.png".to_string())
+        );
+        assert_eq!(moved_key("projects/p1/blocks/b2/i.png", from, to), None);
+    }
+}
@@ -0,0 +1,332 @@
+use crate::pagination::{self, PageRequest};
+use crate::types::{Block, Class};
+use crate::{annotations, blocks, classes, images};
+use aws_sdk_dynamodb::types::{AttributeValue, TransactWriteItem, Update};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Rollup counters of a block live in its partition next to its images, so
+/// its stats are one query: PK=BLOCK#{bid}, SK=COUNT#status#{status} (images
+/// in that labelling status) and SK=COUNT#class#{cid} (live annotations of
+/// the class), each with a `count`
+pub const COUNTER_PREFIX: &str = "COUNT#";
+
+/// Statuses that count as done; skipped images are out of the work
+const DONE_STATUSES: [&str; 2] = ["annotated", "no_objects"];
+
+pub(crate) fn status_counter(status: &str) -> String {
+    format!("{}status#{}", COUNTER_PREFIX, status)
+}
+
+pub(crate) fn class_counter(class_id: &str) -> String {
+    format!("{}class#{}", COUNTER_PREFIX, class_id)
+}
+
+/// Update moving a block counter by `delta`, as a transaction item. Counters
+/// start from zero, so unlike the item counts they don't need a row first.
+/// None when there's no block to count in.
+pub(crate) fn counter_update(
+    table_name: &str,
+    block_id: &str,
+    counter: &str,
+    delta: i64,
+) -> Result<Option<TransactWriteItem>, Error> {
+    if block_id.is_empty() || delta == 0 {
+        return Ok(None);
+    }
+    let update = Update::builder()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("BLOCK#{}", block_id)))
+        .key("SK", AttributeValue::S(counter.to_string()))
+        .update_expression("SET #count = if_not_exists(#count, :zero) + :delta")
+        .expression_attribute_names("#count", "count")
+        .expression_attribute_values(":zero", AttributeValue::N("0".to_string()))
+        .expression_attribute_values(":delta", AttributeValue::N(delta.to_string()))
+        .build()?;
+    Ok(Some(TransactWriteItem::builder().update(update).build()))
+}
+
+/// Move block counters on their own, for writes that can't share a
+/// transaction with them. Best-effort like the class counts.
+pub(crate) async fn increment(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+    changes: &[(String, i64)],
+) {
+    for (counter, delta) in changes {
+        let result = match counter_update(table_name, block_id, counter, *delta) {
+            Ok(None) => continue,
+            Ok(Some(update)) => client
+                .transact_write_items()
+                .transact_items(update)
+                .send()
+                .await
+                .map(|_| ())
+                .map_err(Error::from),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to move counter {} of block {} by {}: {}",
+                counter,
+                block_id,
+                delta,
+                e
+            );
+        }
+    }
+}
+
+/// Move `count` of a block's annotations from one class counter to another
+pub(crate) async fn move_class(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+    from_class_id: &str,
+    to_class_id: &str,
+    count: i64,
+) {
+    let changes = [
+        (class_counter(from_class_id), -count),
+        (class_counter(to_class_id), count),
+    ];
+    increment(client, table_name, block_id, &changes).await
+}
+
+/// Recount a block's images by status and live annotations by class, and
+/// overwrite its counters with the result. Returns how many counters were
+/// written.
+pub(crate) async fn recount_block(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+) -> Result<u64, Error> {
+    let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+    for image in images::fetch_block_images(client, table_name, block_id).await? {
+        *counts.entry(status_counter(&image.status)).or_default() += 1;
+        for annotation in
+            annotations::fetch_image_annotations(client, table_name, &image.image_id).await?
+        {
+            *counts
+                .entry(class_counter(&annotation.class_id))
+                .or_default() += 1;
+        }
+    }
+    // Counters nothing counts any more drop to zero
+    let pk = format!("BLOCK#{}", block_id);
+    let existing = pagination::query_prefix(
+        client,
+        table_name,
+        &pk,
+        COUNTER_PREFIX,
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+    for item in &existing.items {
+        if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
+            counts.entry(sk.clone()).or_default();
+        }
+    }
+
+    let mut written = 0;
+    for (counter, count) in counts {
+        client
+            .put_item()
+            .table_name(table_name)
+            .item("PK", AttributeValue::S(pk.clone()))
+            .item("SK", AttributeValue::S(counter))
+            .item(
+                "entity_type",
+                AttributeValue::S("block_counter".to_string()),
+            )
+            .item("count", AttributeValue::N(count.to_string()))
+            .send()
+            .await?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ClassCount {
+    pub class_id: String,
+    pub class_name: Option<String>, // None once the class is gone
+    pub color: Option<String>,
+    pub count: u32,
+}
+
+/// Progress of a block for the dashboard
+#[derive(Debug, Serialize)]
+pub struct BlockStats {
+    pub project_id: String,
+    pub block_id: String,
+    pub block_name: String,
+    pub state: String,
+    pub assigned_to: Option<String>,
+    pub image_count: u32,
+    pub images_by_status: BTreeMap<String, u32>, // every status, zero or not
+    pub annotation_count: u32,
+    pub annotations_by_class: Vec<ClassCount>, // in the project's class order
+    pub percent_complete: f64,                 // done images of those not skipped
+}
+
+fn summarize(block: &Block, classes: &[Class], counters: &HashMap<String, i64>) -> BlockStats {
+    let count = |counter: String| counters.get(&counter).map_or(0, |n| (*n).max(0) as u32);
+    let images_by_status: BTreeMap<String, u32> = images::IMAGE_STATUSES
+        .iter()
+        .map(|status| (status.to_string(), count(status_counter(status))))
+        .collect();
+    let image_count: u32 = images_by_status.values().sum();
+    let done: u32 = DONE_STATUSES.iter().map(|s| images_by_status[*s]).sum();
+    let in_scope = image_count - images_by_status["skipped"];
+    let percent_complete = match in_scope {
+        0 => 0.0,
+        n => (done as f64 * 1000.0 / n as f64).round() / 10.0,
+    };
+
+    let mut annotations_by_class: Vec<ClassCount> = classes
+        .iter()
+        .map(|class| ClassCount {
+            class_id: class.class_id.clone(),
+            class_name: Some(class.name.clone()),
+            color: class.color.clone(),
+            count: count(class_counter(&class.class_id)),
+        })
+        .collect();
+    let prefix = class_counter("");
+    let mut orphans: Vec<&str> = counters
+        .iter()
+        .filter(|(_, n)| **n > 0)
+        .filter_map(|(counter, _)| counter.strip_prefix(&prefix))
+        .filter(|class_id| !classes.iter().any(|c| c.class_id == *class_id))
+        .collect();
+    orphans.sort();
+    annotations_by_class.extend(orphans.into_iter().map(|class_id| ClassCount {
+        class_id: class_id.to_string(),
+        class_name: None,
+        color: None,
+        count: count(class_counter(class_id)),
+    }));
+
+    BlockStats {
+        project_id: block.project_id.clone(),
+        block_id: block.block_id.clone(),
+        block_name: block.name.clone(),
+        state: block.state.clone(),
+        assigned_to: block.assigned_to.clone(),
+        image_count,
+        images_by_status,
+        annotation_count: block.annotation_count,
+        annotations_by_class,
+        percent_complete,
+    }
+}
+
+/// Image counts by status, annotation counts by class and percent complete
+/// of a block (GET /projects/{pid}/blocks/{bid}/stats), read from its rollup
+/// counters
+pub async fn get_block_stats(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<Response<Body>, Error> {
+    let Some(block) = blocks::fetch_block(client, table_name, project_id, block_id).await? else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "Block not found"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?);
+    };
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        &format!("BLOCK#{}", block_id),
+        COUNTER_PREFIX,
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+    let counters: HashMap<String, i64> = page
+        .items
+        .iter()
+        .filter_map(|item| {
+            let counter = item.get("SK")?.as_s().ok()?.clone();
+            let count = item.get("count")?.as_n().ok()?.parse().ok()?;
+            Some((counter, count))
+        })
+        .collect();
+    let project_classes = classes::fetch_project_classes(client, table_name, project_id).await?;
+
+    let stats = summarize(&block, &project_classes, &counters);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&stats)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let block: Block = serde_json::from_value(serde_json::json!({
+            "block_id": "b1",
+            "project_id": "p1",
+            "name": "Level 1",
+            "state": "current",
+            "locked": false,
+            "assigned_to": "USER#u1",
+            "created_at": "2026-10-01T00:00:00Z",
+            "annotation_count": 7,
+        }))
+        .unwrap();
+        let classes = vec![
+            Class {
+                class_id: "c2".to_string(),
+                name: "Window".to_string(),
+                ..Class::default()
+            },
+            Class {
+                class_id: "c1".to_string(),
+                name: "Door".to_string(),
+                ..Class::default()
+            },
+        ];
+        let counters = HashMap::from([
+            (status_counter("annotated"), 2),
+            (status_counter("no_objects"), 1),
+            (status_counter("in_progress"), 2),
+            (status_counter("skipped"), 1),
+            (class_counter("c1"), 5),
+            (class_counter("gone"), 2),
+            (class_counter("also-gone"), 0),
+        ]);
+        let stats = summarize(&block, &classes, &counters);
+        assert_eq!(stats.image_count, 6);
+        assert_eq!(stats.images_by_status["unannotated"], 0);
+        // 3 of the 5 images not skipped are done
+        assert_eq!(stats.percent_complete, 60.0);
+        let by_class: Vec<_> = stats
+            .annotations_by_class
+            .iter()
+            .map(|c| (c.class_id.as_str(), c.class_name.is_some(), c.count))
+            .collect();
+        assert_eq!(
+            by_class,
+            vec![("c2", true, 0), ("c1", true, 5), ("gone", false, 2)]
+        );
+    }
+}
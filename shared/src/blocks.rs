@@ -1,17 +1,12 @@
-async fn delete_s3_prefix(
-    s3_client: &S3Client,
-    project_id: &str,
-    block_id: &str,
-) -> Result<(), Error> {
-    const BUCKET_NAME: &str = "doxle-annotations";
-    let prefix = format!("projects/{}/blocks/{}/", project_id, block_id);
-
+/// Delete every object under an S3 prefix, returning the bytes freed
+pub(crate) async fn delete_s3_prefix(s3_client: &S3Client, prefix: &str) -> Result<i64, Error> {
+    let mut freed = 0;
     let mut continuation: Option<String> = None;
     loop {
         let mut req = s3_client
             .list_objects_v2()
-            .bucket(BUCKET_NAME)
-            .prefix(&prefix);
+            .bucket(region::bucket_name())
+            .prefix(prefix);
         if let Some(token) = continuation.as_ref() {
             req = req.continuation_token(token);
         }
@@ -45,12 +40,26 @@ async fn delete_s3_prefix(
             .build()
             .map_err(|e| format!("Failed to build S3 delete payload: {:?}", e))?;
 
-        let _ = s3_client
+        let deleted = s3_client
             .delete_objects()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .delete(delete_payload)
             .send()
-            .await;
+            .await
+            .map_err(|e| format!("S3 delete failed: {}", e))?;
+        for error in deleted.errors() {
+            tracing::warn!(
+                "Failed to delete S3 object {}: {}",
+                error.key().unwrap_or_default(),
+                error.message().unwrap_or_default()
+            );
+        }
+        let failed: Vec<&str> = deleted.errors().iter().filter_map(|e| e.key()).collect();
+        freed += contents
+            .iter()
+            .filter(|o| o.key().is_some_and(|k| !failed.contains(&k)))
+            .map(|o| o.size().unwrap_or(0))
+            .sum::<i64>();
 
         if resp.is_truncated().unwrap_or(false) {
             continuation = resp.next_continuation_token().map(|s| s.to_string());
@@ -58,52 +67,89 @@ async fn delete_s3_prefix(
             break;
         }
     }
-    Ok(())
+    Ok(freed)
 }
 
-use crate::types::{Block, CreateBlockRequest, UpdateBlockRequest};
+use crate::audit::AuditEntry;
+use crate::pagination::{Page, PageParams, PageRequest};
+use crate::region;
+use crate::responses::json_response;
+use crate::types::{Block, CreateBlockRequest, SetReviewerRequest, UpdateBlockRequest};
 use aws_sdk_dynamodb::Client as DynamoClient;
 use aws_sdk_s3::Client as S3Client;
 use lambda_http::{http::StatusCode, Body, Error, Response};
 
-/// Create a new block in a project
-pub async fn create_block(
-    client: &DynamoClient,
-    table_name: &str,
-    project_id: &str,
-    body: &[u8],
-) -> Result<Response<Body>, Error> {
-    let req: CreateBlockRequest = serde_json::from_slice(body)?;
+/// Workflow states a block moves through
+pub const BLOCK_STATES: [&str; 5] = ["draft", "current", "review", "complete", "paid"];
 
-    let block_id = uuid::Uuid::new_v4().to_string();
-    let now = chrono::Utc::now().to_rfc3339();
-    let pk = format!("PROJECT#{}", project_id);
-    let sk = format!("BLOCK#{}", block_id);
+/// A fresh unlocked, unassigned draft block
+pub(crate) fn new_block(project_id: &str, name: String) -> Block {
+    Block {
+        block_id: uuid::Uuid::new_v4().to_string(),
+        project_id: project_id.to_string(),
+        name,
+        state: "draft".to_string(),
+        locked: false,
+        assigned_to: None,
+        reviewer: None,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        annotation_count: 0,
+    }
+}
 
-    // Store block
+/// Write a new block's PROJECT#→BLOCK# row
+pub(crate) async fn put_block(
+    client: &DynamoClient,
+    table_name: &str,
+    block: &Block,
+) -> Result<(), Error> {
     client
         .put_item()
         .table_name(table_name)
-        .item("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
-        .item("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
+        .item(
+            "PK",
+            aws_sdk_dynamodb::types::AttributeValue::S(format!("PROJECT#{}", block.project_id)),
+        )
+        .item(
+            "SK",
+            aws_sdk_dynamodb::types::AttributeValue::S(format!("BLOCK#{}", block.block_id)),
+        )
         .item(
             "name",
-            aws_sdk_dynamodb::types::AttributeValue::S(req.name.clone()),
+            aws_sdk_dynamodb::types::AttributeValue::S(block.name.clone()),
         )
         .item(
             "state",
-            aws_sdk_dynamodb::types::AttributeValue::S("draft".to_string()),
+            aws_sdk_dynamodb::types::AttributeValue::S(block.state.clone()),
         )
         .item(
             "locked",
-            aws_sdk_dynamodb::types::AttributeValue::Bool(false),
+            aws_sdk_dynamodb::types::AttributeValue::Bool(block.locked),
         )
         .item(
             "created_at",
-            aws_sdk_dynamodb::types::AttributeValue::S(now.clone()),
+            aws_sdk_dynamodb::types::AttributeValue::S(block.created_at.clone()),
         )
         .send()
         .await?;
+    Ok(())
+}
+
+/// Create a new block in a project
+pub async fn create_block(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: CreateBlockRequest = serde_json::from_slice(body)?;
+
+    let block = new_block(project_id, req.name);
+    let block_id = block.block_id.clone();
+
+    // Store block
+    put_block(client, table_name, &block).await?;
 
     // Also store with BLOCK as PK for easy lookups
     // client
@@ -119,15 +165,7 @@ pub async fn create_block(
     //     .send()
     //     .await?;
 
-    let block = Block {
-        block_id: block_id.clone(),
-        project_id: project_id.to_string(),
-        name: req.name,
-        state: "draft".to_string(),
-        locked: false,
-        assigned_to: None,
-        created_at: now,
-    };
+    crate::audit::record(client, table_name, AuditEntry::new(project_id, user_id, "created", "block", &block_id).after(&block)).await;
 
     Ok(Response::builder()
         .status(StatusCode::CREATED)
@@ -137,13 +175,13 @@ pub async fn create_block(
         .map_err(Box::new)?)
 }
 
-/// Get a specific block
-pub async fn get_block(
+/// Fetch a single block, if it exists
+pub async fn fetch_block(
     client: &DynamoClient,
     table_name: &str,
     project_id: &str,
     block_id: &str,
-) -> Result<Response<Body>, Error> {
+) -> Result<Option<Block>, Error> {
     let pk = format!("PROJECT#{}", project_id);
     let sk = format!("BLOCK#{}", block_id);
 
@@ -155,46 +193,49 @@ pub async fn get_block(
         .send()
         .await?;
 
-    if let Some(item) = result.item() {
-        // let project_id_raw = item
-        //     .get("project_id")
-        //     .and_then(|v| v.as_s().ok())
-        //     .map(|s| s.to_string())
-        //     .unwrap_or_default();
-        // let project_id = project_id_raw
-        //     .strip_prefix("PROJECT#")
-        //     .unwrap_or(&project_id_raw)
-        //     .to_string();
-
-        let block = Block {
-            block_id: block_id.to_string(),
-            project_id: project_id.to_string(),
-            name: item
-                .get("name")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-            state: item
-                .get("state")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-            locked: item
-                .get("locked")
-                .and_then(|v| v.as_bool().ok())
-                .copied()
-                .unwrap_or(false),
-            assigned_to: item
-                .get("assigned_to")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string()),
-            created_at: item
-                .get("created_at")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-        };
+    Ok(result.item().map(|item| Block {
+        block_id: block_id.to_string(),
+        project_id: project_id.to_string(),
+        name: item
+            .get("name")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        state: item
+            .get("state")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        locked: item
+            .get("locked")
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(false),
+        assigned_to: item
+            .get("assigned_to")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        reviewer: item
+            .get("reviewer")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        created_at: item
+            .get("created_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        annotation_count: crate::images::stored_annotation_count(item),
+    }))
+}
 
+/// Get a specific block
+pub async fn get_block(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<Response<Body>, Error> {
+    if let Some(block) = fetch_block(client, table_name, project_id, block_id).await? {
         Ok(Response::builder()
             .status(StatusCode::OK)
             .header("Content-Type", "application/json")
@@ -215,29 +256,20 @@ pub async fn get_block(
     }
 }
 
-/// List all blocks for a project
-pub async fn list_project_blocks(
+/// Fetch a page of a project's blocks
+pub async fn fetch_project_blocks_page(
     client: &DynamoClient,
     table_name: &str,
     project_id: &str,
-) -> Result<Response<Body>, Error> {
+    request: &PageRequest,
+) -> Result<Page<Block>, Error> {
     let pk = format!("PROJECT#{}", project_id);
-
-    let result = client
-        .query()
-        .table_name(table_name)
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
-        .expression_attribute_values(":pk", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .expression_attribute_values(
-            ":sk_prefix",
-            aws_sdk_dynamodb::types::AttributeValue::S("BLOCK#".to_string()),
-        )
-        .send()
-        .await?;
+    let page =
+        crate::pagination::query_prefix(client, table_name, &pk, "BLOCK#", None, request).await?;
 
     let mut blocks = Vec::new();
 
-    for item in result.items() {
+    for item in &page.items {
         if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
             if let Some(block_id) = sk.strip_prefix("BLOCK#") {
                 let block = Block {
@@ -262,34 +294,88 @@ pub async fn list_project_blocks(
                         .get("assigned_to")
                         .and_then(|v| v.as_s().ok())
                         .map(|s| s.to_string()),
+                    reviewer: item
+                        .get("reviewer")
+                        .and_then(|v| v.as_s().ok())
+                        .map(|s| s.to_string()),
                     created_at: item
                         .get("created_at")
                         .and_then(|v| v.as_s().ok())
                         .map(|s| s.to_string())
                         .unwrap_or_default(),
+                    annotation_count: crate::images::stored_annotation_count(item),
                 };
                 blocks.push(block);
             }
         }
     }
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
-        .body(serde_json::to_string(&blocks)?.into())
-        .map_err(Box::new)?)
+    Ok(Page {
+        items: blocks,
+        next_cursor: page.next_cursor,
+    })
+}
+
+/// Fetch all blocks for a project
+pub async fn fetch_project_blocks(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+) -> Result<Vec<Block>, Error> {
+    Ok(
+        fetch_project_blocks_page(client, table_name, project_id, &PageRequest::default())
+            .await?
+            .items,
+    )
+}
+
+/// List a project's blocks: all of them, or a page with `?limit=&cursor=`
+pub async fn list_project_blocks(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    params: PageParams<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match crate::pagination::page_request(params, "BLOCK#") {
+        Ok(request) => request,
+        Err(e) => return crate::pagination::invalid_page(e),
+    };
+    let page = fetch_project_blocks_page(client, table_name, project_id, &request).await?;
+
+    crate::pagination::page_response(&page)
 }
 
 /// Update a block
 pub async fn update_block(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     project_id: &str,
     block_id: &str,
     body: &[u8],
 ) -> Result<Response<Body>, Error> {
     let req: UpdateBlockRequest = serde_json::from_slice(body)?;
+    // The annotator and the reviewer of a block are different people
+    if let Some(assignee) = req.assigned_to.as_deref().filter(|a| !a.is_empty()) {
+        let block = fetch_block(client, table_name, project_id, block_id).await?;
+        if block
+            .and_then(|b| b.reviewer)
+            .is_some_and(|reviewer| same_user(&reviewer, assignee))
+        {
+            return Ok(Response::builder()
+                .status(StatusCode::CONFLICT)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(
+                    serde_json::json!({"error": "The block's reviewer can't also annotate it"})
+                        .to_string()
+                        .into(),
+                )
+                .map_err(Box::new)?);
+        }
+    }
+    let new_state = req.state.clone();
+    let new_assignee = req.assigned_to.clone();
     let pk = format!("PROJECT#{}", project_id);
     let sk = format!("BLOCK#{}", block_id);
 
@@ -325,6 +411,8 @@ pub async fn update_block(
     }
 
     if let Some(assigned_to) = req.assigned_to {
+        // Stored as USER#{uid} like claims, so assignee checks compare like with like
+        let assigned_to = user_reference(&assigned_to);
         update_expr.push("#assigned_to = :assigned_to");
         expr_names.insert("#assigned_to".to_string(), "assigned_to".to_string());
         expr_values.insert(
@@ -339,7 +427,8 @@ pub async fn update_block(
             .table_name(table_name)
             .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
             .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
-            .update_expression(format!("SET {}", update_expr.join(", ")));
+            .update_expression(format!("SET {}", update_expr.join(", ")))
+            .return_values(aws_sdk_dynamodb::types::ReturnValue::UpdatedOld);
 
         for (k, v) in expr_names {
             builder = builder.expression_attribute_names(k, v);
@@ -349,194 +438,617 @@ pub async fn update_block(
             builder = builder.expression_attribute_values(k, v);
         }
 
-        builder.send().await?;
+        let output = builder.send().await?;
+        crate::audit::record(client, table_name, AuditEntry::new(project_id, user_id, "updated", "block", block_id).changes(body)).await;
+
+        // Record state transitions and assignment changes for the block feed
+        let old = |name: &str| {
+            output
+                .attributes()
+                .and_then(|attrs| attrs.get(name))
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+        };
+        let changes = [
+            ("state_changed", old("state"), new_state),
+            ("assigned", old("assigned_to"), new_assignee),
+        ];
+        for (kind, from, to) in changes {
+            if to.is_some() && from != to {
+                if let Err(e) =
+                    crate::feed::record_block_event(client, table_name, block_id, kind, from, to, user_id)
+                        .await
+                {
+                    tracing::error!("Failed to record {} event for block {}: {}", kind, block_id, e);
+                }
+            }
+        }
     }
 
     get_block(client, table_name, project_id, block_id).await
 }
 
-/// Delete a block and associated records (images, annotations, links)
-pub async fn delete_block(
+/// Claim an unassigned block for the caller (self-service, pull-based work).
+/// The conditional write makes concurrent claims safe: exactly one wins and
+/// the rest get 409. Re-claiming a block you already hold is a no-op.
+pub async fn claim_block(
     client: &DynamoClient,
-    s3_client: &S3Client,
     table_name: &str,
+    user_id: &str,
     project_id: &str,
     block_id: &str,
 ) -> Result<Response<Body>, Error> {
-    use std::collections::HashMap;
+    let pk = format!("PROJECT#{}", project_id);
+    let sk = format!("BLOCK#{}", block_id);
+    let assignee = format!("USER#{}", user_id);
 
-    let _block_pk = format!("BLOCK#{}", block_id);
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
+        .update_expression("SET #assigned_to = :me")
+        .condition_expression(
+            "attribute_exists(PK) \
+             AND (attribute_not_exists(#assigned_to) OR #assigned_to = :empty OR #assigned_to = :me) \
+             AND (attribute_not_exists(#locked) OR #locked = :unlocked) \
+             AND (attribute_not_exists(#reviewer) OR #reviewer <> :me)",
+        )
+        .expression_attribute_names("#assigned_to", "assigned_to")
+        .expression_attribute_names("#locked", "locked")
+        .expression_attribute_names("#reviewer", "reviewer")
+        .expression_attribute_values(":me", aws_sdk_dynamodb::types::AttributeValue::S(assignee.clone()))
+        .expression_attribute_values(":empty", aws_sdk_dynamodb::types::AttributeValue::S(String::new()))
+        .expression_attribute_values(":unlocked", aws_sdk_dynamodb::types::AttributeValue::Bool(false))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::UpdatedOld)
+        .send()
+        .await;
 
-    // 1) Fetch BLOCK# -> BLOCK# record to get project_id (stored as PK string e.g. "PROJECT#<pid>")
-    // let block_item = client
-    //     .get_item()
-    //     .table_name(table_name)
-    //     .key(
-    //         "PK",
-    //         aws_sdk_dynamodb::types::AttributeValue::S(block_pk.clone()),
-    //     )
-    //     .key(
-    //         "SK",
-    //         aws_sdk_dynamodb::types::AttributeValue::S(block_pk.clone()),
-    //     )
-    //     .send()
-    //     .await?;
+    match result {
+        Ok(output) => {
+            let previous = output
+                .attributes()
+                .and_then(|attrs| attrs.get("assigned_to"))
+                .and_then(|v| v.as_s().ok())
+                .filter(|s| !s.is_empty())
+                .cloned();
+            if previous.as_deref() != Some(assignee.as_str()) {
+                tracing::info!("Block {} claimed by {}", block_id, user_id);
+                let entry = AuditEntry::new(project_id, user_id, "claimed", "block", block_id)
+                    .before(&serde_json::json!({ "assigned_to": previous }))
+                    .after(&serde_json::json!({ "assigned_to": assignee }));
+                crate::audit::record(client, table_name, entry).await;
+                if let Err(e) = crate::feed::record_block_event(
+                    client,
+                    table_name,
+                    block_id,
+                    "assigned",
+                    previous,
+                    Some(assignee),
+                    user_id,
+                )
+                .await
+                {
+                    tracing::error!("Failed to record assigned event for block {}: {}", block_id, e);
+                }
+            }
+            get_block(client, table_name, project_id, block_id).await
+        }
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            // Work out why: missing, locked, or someone else got there first
+            let current = client
+                .get_item()
+                .table_name(table_name)
+                .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+                .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
+                .send()
+                .await?;
+            let (status, body) = match current.item() {
+                None => (
+                    StatusCode::NOT_FOUND,
+                    serde_json::json!({"error": "Block not found"}),
+                ),
+                Some(item)
+                    if item
+                        .get("locked")
+                        .and_then(|v| v.as_bool().ok())
+                        .copied()
+                        .unwrap_or(false) =>
+                {
+                    (
+                        StatusCode::CONFLICT,
+                        serde_json::json!({"error": "Block is locked"}),
+                    )
+                }
+                Some(item)
+                    if item
+                        .get("reviewer")
+                        .and_then(|v| v.as_s().ok())
+                        .is_some_and(|reviewer| *reviewer == assignee) =>
+                {
+                    (
+                        StatusCode::CONFLICT,
+                        serde_json::json!({"error": "You are this block's reviewer"}),
+                    )
+                }
+                Some(item) => (
+                    StatusCode::CONFLICT,
+                    serde_json::json!({
+                        "error": "Block is already assigned",
+                        "assigned_to": item.get("assigned_to").and_then(|v| v.as_s().ok()),
+                    }),
+                ),
+            };
+            Ok(Response::builder()
+                .status(status)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(body.to_string().into())
+                .map_err(Box::new)?)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
 
-    // let project_pk = block_item
-    //     .item()
-    //     .and_then(|it| it.get("project_id"))
-    //     .and_then(|v| v.as_s().ok())
-    //     .map(|s| s.to_string())
-    //     .unwrap_or_default();
+/// Whether a stored user reference (`USER#123` or a bare id) is the user
+pub(crate) fn same_user(reference: &str, user_id: &str) -> bool {
+    reference.strip_prefix("USER#").unwrap_or(reference)
+        == user_id.strip_prefix("USER#").unwrap_or(user_id)
+}
 
-    // Derive project_id (strip prefix) for S3 deletion
-    // let project_id_for_s3 = project_pk.strip_prefix("PROJECT#").map(|s| s.to_string());
+/// A user as blocks store them, `USER#{uid}`; blank stays blank (unassigned)
+fn user_reference(user: &str) -> String {
+    let user = user.trim();
+    if user.is_empty() || user.starts_with("USER#") {
+        user.to_string()
+    } else {
+        format!("USER#{}", user)
+    }
+}
 
-    let project_pk = format!("PROJECT#{}", project_id);
-    let block_sk = format!("BLOCK#{}", block_id);
-    // Collect all keys to delete
-    let mut delete_keys: Vec<HashMap<String, aws_sdk_dynamodb::types::AttributeValue>> = Vec::new();
+/// Unassigned blocks are open to everyone, assigned ones to their assignee
+fn may_write(assigned_to: Option<&str>, user_id: &str) -> bool {
+    match assigned_to.filter(|a| !a.is_empty()) {
+        Some(assignee) => same_user(assignee, user_id),
+        None => true,
+    }
+}
 
-    // 2) Query images under the block (PK=BLOCK#bid, SK begins_with IMAGE#)
-    let block_pk = format!("BLOCK#{}", block_id);
-    let images_result = client
-        .query()
+/// Reviews of a block's annotations are the reviewer's, or anyone's while it
+/// has none, but never its annotator's own
+fn may_review(
+    assigned_to: Option<&str>,
+    reviewer: Option<&str>,
+    user_id: &str,
+) -> Result<(), &'static str> {
+    if assigned_to.is_some_and(|assignee| same_user(assignee, user_id)) {
+        return Err("Annotators can't review their own block");
+    }
+    match reviewer.filter(|r| !r.is_empty()) {
+        Some(reviewer) if !same_user(reviewer, user_id) => {
+            Err("Block is assigned to another reviewer")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes to an assigned block's images and annotations are only allowed
+/// for the assignee and admins. Returns the 403 to send otherwise, or None
+/// when the caller may write.
+pub async fn check_assignee(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+    user_id: &str,
+) -> Result<Option<Response<Body>>, Error> {
+    let pk = format!("PROJECT#{}", project_id);
+    let sk = format!("BLOCK#{}", block_id);
+
+    let result = client
+        .get_item()
         .table_name(table_name)
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
-        .expression_attribute_values(
-            ":pk",
-            aws_sdk_dynamodb::types::AttributeValue::S(block_pk.clone()),
-        )
-        .expression_attribute_values(
-            ":sk_prefix",
-            aws_sdk_dynamodb::types::AttributeValue::S("IMAGE#".to_string()),
-        )
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
+        .projection_expression("#assigned_to")
+        .expression_attribute_names("#assigned_to", "assigned_to")
         .send()
         .await?;
+    let assigned_to = result
+        .item()
+        .and_then(|item| item.get("assigned_to"))
+        .and_then(|v| v.as_s().ok());
+    if may_write(assigned_to.map(|s| s.as_str()), user_id)
+        || crate::users::is_admin(client, table_name, user_id).await?
+    {
+        return Ok(None);
+    }
+    Ok(Some(
+        Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({
+                    "error": "Block is assigned to another user",
+                    "assigned_to": assigned_to,
+                })
+                .to_string()
+                .into(),
+            )
+            .map_err(Box::new)?,
+    ))
+}
 
-    let mut image_ids: Vec<String> = Vec::new();
-    for item in images_result.items() {
-        if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
-            if let Some(image_id) = sk.strip_prefix("IMAGE#") {
-                image_ids.push(image_id.to_string());
-                // Add BLOCK# -> IMAGE# record to delete
-                let mut key = HashMap::new();
-                key.insert(
-                    "PK".to_string(),
-                    aws_sdk_dynamodb::types::AttributeValue::S(block_pk.clone()),
-                );
-                key.insert(
-                    "SK".to_string(),
-                    aws_sdk_dynamodb::types::AttributeValue::S(sk.to_string()),
-                );
-                delete_keys.push(key);
-            }
+/// `check_assignee` for a write to an image, through the block it lives in.
+/// Images without a recorded location have no block to check.
+pub async fn check_image_assignee(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    user_id: &str,
+) -> Result<Option<Response<Body>>, Error> {
+    match crate::images::image_location(client, table_name, image_id).await? {
+        Some(location) => {
+            check_assignee(
+                client,
+                table_name,
+                &location.project_id,
+                &location.block_id,
+                user_id,
+            )
+            .await
         }
+        None => Ok(None),
     }
+}
 
-    // 3) For each image, delete annotations and IMAGE self record
-    for image_id in &image_ids {
-        let image_pk = format!("IMAGE#{}", image_id);
+/// `check_assignee` for changes spanning several blocks: the first block the
+/// user may not write decides the 403. Admins skip the lookups.
+pub async fn check_blocks_assignee(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_ids: &[&str],
+    user_id: &str,
+) -> Result<Option<Response<Body>>, Error> {
+    if crate::users::is_admin(client, table_name, user_id).await? {
+        return Ok(None);
+    }
+    let mut checked = std::collections::HashSet::new();
+    for &block_id in block_ids {
+        if !checked.insert(block_id) {
+            continue;
+        }
+        if let Some(response) = check_assignee(client, table_name, project_id, block_id, user_id).await? {
+            return Ok(Some(response));
+        }
+    }
+    Ok(None)
+}
 
-        // Annotations under image
-        let annotations_result = client
-            .query()
-            .table_name(table_name)
-            .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
-            .expression_attribute_values(
-                ":pk",
-                aws_sdk_dynamodb::types::AttributeValue::S(image_pk.clone()),
+/// Reviews of an image's annotations are only allowed for the reviewer of
+/// its block (see `may_review`) and admins. Returns the 403 to send
+/// otherwise, or None when the caller may review.
+pub async fn check_reviewer(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    user_id: &str,
+) -> Result<Option<Response<Body>>, Error> {
+    let Some(location) = crate::images::image_location(client, table_name, image_id).await? else {
+        return Ok(None);
+    };
+    let Some(block) =
+        fetch_block(client, table_name, &location.project_id, &location.block_id).await?
+    else {
+        return Ok(None);
+    };
+    let allowed = may_review(
+        block.assigned_to.as_deref(),
+        block.reviewer.as_deref(),
+        user_id,
+    );
+    let Err(message) = allowed else {
+        return Ok(None);
+    };
+    if crate::users::is_admin(client, table_name, user_id).await? {
+        return Ok(None);
+    }
+    Ok(Some(
+        Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({
+                    "error": message,
+                    "reviewer": block.reviewer,
+                })
+                .to_string()
+                .into(),
             )
+            .map_err(Box::new)?,
+    ))
+}
+
+/// Assign or unassign a block's reviewer (POST /projects/{pid}/blocks/{bid}/reviewer,
+/// body `{"reviewer"}`), separately from its annotator. Only the project's
+/// managers and admins assign reviewers, and the reviewer can't be the
+/// block's annotator.
+pub async fn set_reviewer(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: SetReviewerRequest = serde_json::from_slice(body)?;
+    let reviewer = req
+        .reviewer
+        .map(|r| user_reference(&r))
+        .filter(|r| !r.is_empty());
+    if !crate::members::is_manager(client, table_name, project_id, user_id).await?
+        && !crate::users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(
+            StatusCode::FORBIDDEN,
+            serde_json::json!({"error": "Only the project's managers assign reviewers"}),
+        );
+    }
+    let Some(block) = fetch_block(client, table_name, project_id, block_id).await? else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Block not found"}),
+        );
+    };
+    let conflict = || {
+        json_response(
+            StatusCode::CONFLICT,
+            serde_json::json!({"error": "The block's annotator can't also review it"}),
+        )
+    };
+    // Assignees written before they were normalized may lack the USER# prefix
+    if let (Some(reviewer), Some(assignee)) = (&reviewer, block.assigned_to.as_deref()) {
+        if same_user(assignee, reviewer) {
+            return conflict();
+        }
+    }
+
+    let mut builder = client
+        .update_item()
+        .table_name(table_name)
+        .key(
+            "PK",
+            aws_sdk_dynamodb::types::AttributeValue::S(format!("PROJECT#{}", project_id)),
+        )
+        .key(
+            "SK",
+            aws_sdk_dynamodb::types::AttributeValue::S(format!("BLOCK#{}", block_id)),
+        )
+        .expression_attribute_names("#reviewer", "reviewer");
+    builder = match &reviewer {
+        // Conditional on the annotator, so a concurrent assignment can't slip past
+        Some(reviewer) => builder
+            .update_expression("SET #reviewer = :reviewer")
+            .condition_expression(
+                "attribute_exists(PK) AND (attribute_not_exists(#assigned_to) OR (#assigned_to <> :reviewer AND #assigned_to <> :raw))",
+            )
+            .expression_attribute_names("#assigned_to", "assigned_to")
             .expression_attribute_values(
-                ":sk_prefix",
-                aws_sdk_dynamodb::types::AttributeValue::S("ANNOTATION#".to_string()),
+                ":reviewer",
+                aws_sdk_dynamodb::types::AttributeValue::S(reviewer.clone()),
             )
-            .send()
-            .await?;
-
-        for item in annotations_result.items() {
-            if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
-                let mut key = HashMap::new();
-                key.insert(
-                    "PK".to_string(),
-                    aws_sdk_dynamodb::types::AttributeValue::S(image_pk.clone()),
-                );
-                key.insert(
-                    "SK".to_string(),
-                    aws_sdk_dynamodb::types::AttributeValue::S(sk.to_string()),
-                );
-                delete_keys.push(key);
-            }
+            .expression_attribute_values(
+                ":raw",
+                aws_sdk_dynamodb::types::AttributeValue::S(
+                    reviewer.trim_start_matches("USER#").to_string(),
+                ),
+            ),
+        None => builder
+            .update_expression("REMOVE #reviewer")
+            .condition_expression("attribute_exists(PK)"),
+    };
+    match builder.send().await {
+        Ok(_) => {}
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            return conflict();
         }
+        Err(e) => return Err(e.into()),
+    }
 
-        // IMAGE# -> IMAGE# self record
-        let mut key = HashMap::new();
-        key.insert(
+    if block.reviewer != reviewer {
+        let entry = AuditEntry::new(project_id, user_id, "updated", "block", block_id)
+            .before(&serde_json::json!({ "reviewer": block.reviewer }))
+            .after(&serde_json::json!({ "reviewer": reviewer }));
+        crate::audit::record(client, table_name, entry).await;
+        if let Err(e) = crate::feed::record_block_event(
+            client,
+            table_name,
+            block_id,
+            "reviewer_assigned",
+            block.reviewer,
+            reviewer,
+            user_id,
+        )
+        .await
+        {
+            tracing::error!(
+                "Failed to record reviewer_assigned event for block {}: {}",
+                block_id,
+                e
+            );
+        }
+    }
+    get_block(client, table_name, project_id, block_id).await
+}
+
+/// Rows of the block partition removed with the block: its images, feed
+/// events, rollup counters and legacy self row
+const BLOCK_ROW_PREFIXES: [&str; 4] = [
+    "IMAGE#",
+    "EVENT#",
+    crate::block_stats::COUNTER_PREFIX,
+    "BLOCK#",
+];
+
+/// Rows of an image partition removed with the image
+pub(crate) const IMAGE_ROW_PREFIXES: [&str; 4] = [
+    "ANNOTATION#",
+    crate::history::HISTORY_PREFIX,
+    "COMMENT#",
+    "IMAGE#",
+];
+
+pub(crate) fn row_key(
+    pk: &str,
+    sk: &str,
+) -> std::collections::HashMap<String, aws_sdk_dynamodb::types::AttributeValue> {
+    std::collections::HashMap::from([
+        (
             "PK".to_string(),
-            aws_sdk_dynamodb::types::AttributeValue::S(image_pk.clone()),
-        );
-        key.insert(
+            aws_sdk_dynamodb::types::AttributeValue::S(pk.to_string()),
+        ),
+        (
             "SK".to_string(),
-            aws_sdk_dynamodb::types::AttributeValue::S(image_pk),
+            aws_sdk_dynamodb::types::AttributeValue::S(sk.to_string()),
+        ),
+    ])
+}
+
+/// Keys of every row in `pk` under one of `prefixes`, all pages of them
+pub(crate) async fn partition_keys(
+    client: &DynamoClient,
+    table_name: &str,
+    pk: &str,
+    prefixes: &[&str],
+) -> Result<Vec<String>, Error> {
+    let mut sort_keys = Vec::new();
+    for prefix in prefixes {
+        let page = crate::pagination::query_prefix(
+            client,
+            table_name,
+            pk,
+            prefix,
+            None,
+            &PageRequest::default(),
+        )
+        .await?;
+        sort_keys.extend(
+            page.items
+                .iter()
+                .filter_map(|item| item.get("SK")?.as_s().ok().cloned()),
         );
-        delete_keys.push(key);
     }
+    Ok(sort_keys)
+}
 
-    let mut key = HashMap::new();
-    key.insert(
-        "PK".to_string(),
-        aws_sdk_dynamodb::types::AttributeValue::S(project_pk),
-    );
-    key.insert(
-        "SK".to_string(),
-        aws_sdk_dynamodb::types::AttributeValue::S(block_sk),
-    );
-    delete_keys.push(key);
-
-    // 5) Batch delete with retries (25 items per request)
-    for chunk in delete_keys.chunks(25) {
-        let write_reqs: Vec<_> = chunk
-            .iter()
-            .map(|k| {
-                aws_sdk_dynamodb::types::WriteRequest::builder()
-                    .delete_request(
-                        aws_sdk_dynamodb::types::DeleteRequest::builder()
-                            .set_key(Some(k.clone()))
-                            .build()
-                            .unwrap(),
-                    )
-                    .build()
-            })
-            .collect();
+/// Delete a block and everything under it: the PROJECT#→BLOCK# link, the
+/// rows of its partition, its images with their annotations, history and
+/// comments, and its S3 objects. The classes stop counting its annotations.
+pub async fn delete_block(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<Response<Body>, Error> {
+    let block_pk = format!("BLOCK#{}", block_id);
+    let mut image_keys = Vec::new();
+    let mut block_keys = Vec::new();
+    let mut annotations = Vec::new();
 
-        let mut unprocessed = Some(write_reqs);
-        let mut attempts = 0;
-        while let Some(reqs) = unprocessed {
-            attempts += 1;
-            let result = client
-                .batch_write_item()
-                .request_items(table_name, reqs)
-                .send()
-                .await?;
+    for sk in partition_keys(client, table_name, &block_pk, &BLOCK_ROW_PREFIXES).await? {
+        if let Some(image_id) = sk.strip_prefix("IMAGE#") {
+            let image_pk = format!("IMAGE#{}", image_id);
+            let image_rows =
+                partition_keys(client, table_name, &image_pk, &IMAGE_ROW_PREFIXES).await?;
+            image_keys.extend(image_rows.iter().map(|sk| row_key(&image_pk, sk)));
+            annotations.extend(
+                crate::annotations::fetch_image_annotations(client, table_name, image_id).await?,
+            );
+            let (location_pk, location_sk) = crate::images::location_key(image_id);
+            block_keys.push(std::collections::HashMap::from([
+                ("PK".to_string(), location_pk),
+                ("SK".to_string(), location_sk),
+            ]));
+        }
+        block_keys.push(row_key(&block_pk, &sk));
+    }
+    let link = vec![row_key(&format!("PROJECT#{}", project_id), &block_pk)];
+    let total = image_keys.len() + block_keys.len() + link.len();
 
-            unprocessed = result
-                .unprocessed_items()
-                .and_then(|m| m.get(table_name))
-                .map(|v| v.clone());
+    let failed = crate::annotations::batch_delete_keys(client, table_name, image_keys).await?;
+    // The classes stop counting the annotations that are gone, even when
+    // others are left for a retry
+    let deleted = crate::annotations::deleted_annotations(&annotations, &failed);
+    for (class_id, count) in crate::annotations::class_deltas(deleted.iter().map(|a| &a.class_id)) {
+        if let Err(e) =
+            crate::classes::increment_class_count(client, table_name, project_id, class_id, -count)
+                .await
+        {
+            tracing::warn!("Failed to update the count of class {}: {}", class_id, e);
+        }
+    }
 
-            if unprocessed.is_some() && attempts < 5 {
-                tokio::time::sleep(tokio::time::Duration::from_millis(100 * attempts)).await;
-            } else {
-                break;
-            }
+    // Each level goes only once everything under it is gone: a retried delete
+    // finds the images left through the block's rows, and the block through
+    // its link
+    let remaining = if !failed.is_empty() {
+        failed.len() + block_keys.len() + link.len()
+    } else {
+        let failed = crate::annotations::batch_delete_keys(client, table_name, block_keys).await?;
+        if !failed.is_empty() {
+            failed.len() + link.len()
+        } else {
+            crate::annotations::batch_delete_keys(client, table_name, link).await?.len()
         }
+    };
+    if remaining > 0 {
+        tracing::error!(
+            "Deleting block {} left {} of {} records",
+            block_id,
+            remaining,
+            total
+        );
+        return Ok(Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({
+                    "error": "Block was only partly deleted, retry the delete",
+                    "remaining": remaining,
+                })
+                .to_string()
+                .into(),
+            )
+            .map_err(Box::new)?);
     }
 
-    // 6) Delete S3 objects under this block prefix: projects/{project_id}/blocks/{block_id}/
+    // Objects under projects/{project_id}/blocks/{block_id}/
+    let prefix = crate::storage::block_prefix(project_id, block_id);
+    match delete_s3_prefix(s3_client, &prefix).await {
+        Ok(freed) => crate::storage_usage::record(client, table_name, project_id, -freed).await,
+        Err(e) => tracing::error!("Failed to delete S3 objects of block {}: {}", block_id, e),
+    }
 
-    delete_s3_prefix(s3_client, &project_id, block_id)
-        .await
-        .ok();
+    crate::audit::record(
+        client,
+        table_name,
+        AuditEntry::new(project_id, user_id, "deleted", "block", block_id),
+    )
+    .await;
 
     Ok(Response::builder()
         .status(StatusCode::NO_CONTENT)
@@ -544,3 +1056,34 @@ pub async fn delete_block(
         .body(Body::Empty)
         .map_err(Box::new)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_may_write() {
+        assert!(may_write(None, "u1"));
+        assert!(may_write(Some(""), "u1"));
+        assert!(may_write(Some("USER#u1"), "u1"));
+        assert!(!may_write(Some("USER#u2"), "u1"));
+    }
+
+    #[test]
+    fn test_may_review() {
+        assert!(may_review(None, None, "u1").is_ok());
+        assert!(may_review(Some("USER#u2"), Some("USER#u1"), "u1").is_ok());
+        assert!(may_review(Some("USER#u1"), None, "u1").is_err());
+        assert!(may_review(Some("USER#u1"), Some("USER#u1"), "u1").is_err());
+        assert!(may_review(None, Some("USER#u3"), "u1").is_err());
+        assert!(may_review(None, Some(""), "u1").is_ok());
+    }
+
+    #[test]
+    fn test_user_reference() {
+        assert_eq!(user_reference("u1"), "USER#u1");
+        assert_eq!(user_reference(" USER#u1 "), "USER#u1");
+        assert_eq!(user_reference(""), "");
+        assert!(same_user(&user_reference("u1"), "u1"));
+    }
+}
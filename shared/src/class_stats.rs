@@ -0,0 +1,224 @@
+use crate::annotations::{self, AnnotationFilter};
+use crate::types::{Annotation, Block, Class};
+use crate::{blocks, classes};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct BlockCount {
+    pub block_id: String,
+    pub block_name: Option<String>, // None once the block is gone
+    pub count: u32,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct AnnotatorCount {
+    pub user_id: String, // USER#123
+    pub count: u32,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct DayCount {
+    pub date: String, // YYYY-MM-DD (UTC) the annotations were created on
+    pub count: u32,
+}
+
+/// Where a class's live annotations are, who made them and when
+#[derive(Debug, Serialize)]
+pub struct ClassStats {
+    pub project_id: String,
+    pub class_id: String,
+    pub class_name: String,
+    pub generated_at: String,
+    pub total: u32,
+    pub by_block: Vec<BlockCount>,         // most annotations first
+    pub by_annotator: Vec<AnnotatorCount>, // most annotations first
+    pub by_day: Vec<DayCount>,             // oldest first, days without any left out
+}
+
+/// Count the annotations per key, most first and ties by key
+fn tally<'a>(keys: impl Iterator<Item = &'a str>) -> Vec<(&'a str, u32)> {
+    let mut counts: BTreeMap<&str, u32> = BTreeMap::new();
+    for key in keys {
+        *counts.entry(key).or_default() += 1;
+    }
+    let mut counts: Vec<(&str, u32)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+}
+
+fn summarize(class: &Class, blocks: &[Block], annotations: &[Annotation]) -> ClassStats {
+    let by_block = tally(annotations.iter().map(|a| a.block_id.as_str()))
+        .into_iter()
+        .map(|(block_id, count)| BlockCount {
+            block_id: block_id.to_string(),
+            block_name: blocks
+                .iter()
+                .find(|b| b.block_id == block_id)
+                .map(|b| b.name.clone()),
+            count,
+        })
+        .collect();
+    let by_annotator = tally(annotations.iter().map(|a| a.created_by.as_str()))
+        .into_iter()
+        .map(|(user_id, count)| AnnotatorCount {
+            user_id: user_id.to_string(),
+            count,
+        })
+        .collect();
+    let mut days: BTreeMap<String, u32> = BTreeMap::new();
+    for annotation in annotations {
+        let date = chrono::DateTime::parse_from_rfc3339(&annotation.created_at)
+            .map(|at| {
+                at.with_timezone(&chrono::Utc)
+                    .format("%Y-%m-%d")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| annotation.created_at.chars().take(10).collect());
+        *days.entry(date).or_default() += 1;
+    }
+
+    ClassStats {
+        project_id: class.project_id.clone(),
+        class_id: class.class_id.clone(),
+        class_name: class.name.clone(),
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        total: annotations.len() as u32,
+        by_block,
+        by_annotator,
+        by_day: days
+            .into_iter()
+            .map(|(date, count)| DayCount { date, count })
+            .collect(),
+    }
+}
+
+/// Annotation counts of one class per block, per annotator and per day
+/// (GET /projects/{pid}/classes/{cid}/stats), computed from the project index
+/// so imbalanced label distributions show up without an export
+pub async fn get_class_stats(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    class_id: &str,
+) -> Result<Response<Body>, Error> {
+    let Some(class) = classes::fetch_class(client, table_name, project_id, class_id).await? else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "Class not found"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?);
+    };
+    let filter = AnnotationFilter {
+        class_id: Some(class_id),
+        ..Default::default()
+    };
+    let class_annotations =
+        annotations::fetch_project_annotations(client, table_name, project_id, filter).await?;
+    let project_blocks = blocks::fetch_project_blocks(client, table_name, project_id).await?;
+
+    let stats = summarize(&class, &project_blocks, &class_annotations);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&stats)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let annotation = |id: &str, block_id: &str, user: &str, created_at: &str| {
+            let mut annotation: Annotation = serde_json::from_value(serde_json::json!({
+                "annotation_id": id,
+                "image_id": "i1",
+                "class_id": "c1",
+                "geometry": {"type": "point", "point": {"x": 1.0, "y": 1.0}},
+                "created_by": user,
+                "created_at": created_at,
+            }))
+            .unwrap();
+            annotation.block_id = block_id.to_string();
+            annotation
+        };
+        let class = Class {
+            class_id: "c1".to_string(),
+            project_id: "p1".to_string(),
+            name: "Door".to_string(),
+            ..Class::default()
+        };
+        let blocks: Vec<Block> = vec![serde_json::from_value(serde_json::json!({
+            "block_id": "b1",
+            "project_id": "p1",
+            "name": "Level 1",
+            "state": "current",
+            "locked": false,
+            "assigned_to": null,
+            "created_at": "2026-10-01T00:00:00Z",
+        }))
+        .unwrap()];
+        let stats = summarize(
+            &class,
+            &blocks,
+            &[
+                annotation("a1", "b1", "USER#u1", "2026-10-14T01:30:00+02:00"),
+                annotation("a2", "b2", "USER#u2", "2026-10-14T08:00:00Z"),
+                annotation("a3", "b2", "USER#u1", "2026-10-14T09:00:00Z"),
+            ],
+        );
+        assert_eq!(stats.total, 3);
+        assert_eq!(
+            stats.by_block,
+            vec![
+                BlockCount {
+                    block_id: "b2".to_string(),
+                    block_name: None,
+                    count: 2
+                },
+                BlockCount {
+                    block_id: "b1".to_string(),
+                    block_name: Some("Level 1".to_string()),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(
+            stats.by_annotator,
+            vec![
+                AnnotatorCount {
+                    user_id: "USER#u1".to_string(),
+                    count: 2
+                },
+                AnnotatorCount {
+                    user_id: "USER#u2".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        // Days are UTC ones
+        assert_eq!(
+            stats.by_day,
+            vec![
+                DayCount {
+                    date: "2026-10-13".to_string(),
+                    count: 1
+                },
+                DayCount {
+                    date: "2026-10-14".to_string(),
+                    count: 2
+                },
+            ]
+        );
+    }
+}
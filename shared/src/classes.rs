@@ -1,47 +1,244 @@
 use lambda_http::{Body, Error, Response, http::StatusCode};
 use aws_sdk_dynamodb::Client as DynamoClient;
-use crate::types::{Class, CreateClassRequest, UpdateClassRequest};
+use crate::types::{AttributeDef, BatchCreateClassesRequest, Class, CreateClassRequest, ReorderClassesRequest, Skeleton, UpdateClassRequest};
+use crate::pagination::{Page, PageParams, PageRequest};
+use crate::audit::AuditEntry;
 
-/// Create a new class for a project
-pub async fn create_class(
+/// Default class colors: high-contrast hues that stay distinct on drawings
+const DEFAULT_PALETTE: [&str; 20] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4",
+    "#46f0f0", "#f032e6", "#bcf60c", "#fabebe", "#008080",
+    "#e6beff", "#9a6324", "#fffac8", "#800000", "#aaffc3",
+    "#808000", "#ffd8b1", "#000075", "#808080", "#ffe119",
+];
+
+/// Palette used for classes created without a color.
+/// Override with CLASS_PALETTE as comma-separated hex colors.
+pub fn palette() -> Vec<String> {
+    let configured: Vec<String> = std::env::var("CLASS_PALETTE")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(normalize_color)
+        .collect();
+    if configured.is_empty() {
+        DEFAULT_PALETTE.iter().map(|c| c.to_string()).collect()
+    } else {
+        configured
+    }
+}
+
+/// Normalize "#RGB" / "#RRGGBB" (with or without '#') to lowercase "#rrggbb"
+pub fn normalize_color(color: &str) -> Option<String> {
+    let hex = color.trim().trim_start_matches('#');
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        6 => Some(format!("#{}", hex.to_ascii_lowercase())),
+        3 => Some(format!(
+            "#{}",
+            hex.chars().flat_map(|c| [c, c]).collect::<String>().to_ascii_lowercase()
+        )),
+        _ => None,
+    }
+}
+
+/// First palette color not already used in the project. Once the palette is
+/// exhausted, colors are generated by golden-angle hue rotation until one is free.
+pub fn pick_color(palette: &[String], used: &[String]) -> String {
+    let is_used = |c: &str| used.iter().any(|u| normalize_color(u).as_deref() == Some(c));
+
+    if let Some(color) = palette.iter().find(|c| !is_used(c)) {
+        return color.clone();
+    }
+
+    // Each pass around the wheel steps the lightness down a little
+    let mut color = String::new();
+    for i in 1..10_000u32 {
+        let hue = (i as f64 * 137.508) % 360.0;
+        let lightness = 0.5 - 0.03 * ((i / 360) % 8) as f64;
+        color = hsl_to_hex(hue, 0.65, lightness);
+        if !is_used(&color) {
+            break;
+        }
+    }
+    color
+}
+
+fn hsl_to_hex(hue: f64, saturation: f64, lightness: f64) -> String {
+    let c = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = c * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = lightness - c / 2.0;
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let to_byte = |v: f64| ((v + m) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", to_byte(r), to_byte(g), to_byte(b))
+}
+
+/// Class already using a color in this project, if any
+fn color_owner<'a>(classes: &'a [Class], color: &str, except: Option<&str>) -> Option<&'a Class> {
+    let color = normalize_color(color)?;
+    classes.iter().find(|c| {
+        Some(c.class_id.as_str()) != except
+            && c.color.as_deref().and_then(normalize_color).as_deref() == Some(color.as_str())
+    })
+}
+
+fn class_error(status: StatusCode, message: String) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": message}).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Hotkeys are single letters or digits, stored lowercase
+pub fn normalize_hotkey(hotkey: &str) -> Option<String> {
+    let mut chars = hotkey.trim().chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_alphanumeric() => Some(c.to_ascii_lowercase().to_string()),
+        _ => None,
+    }
+}
+
+/// Class already using a hotkey in this project, if any
+fn hotkey_owner<'a>(classes: &'a [Class], hotkey: &str, except: Option<&str>) -> Option<&'a Class> {
+    let hotkey = normalize_hotkey(hotkey)?;
+    classes.iter().find(|c| Some(c.class_id.as_str()) != except && c.hotkey.as_deref() == Some(hotkey.as_str()))
+}
+
+/// Fill in what new classes for a project with the `existing` ones leave
+/// out, or ask for but can't have: a free color (requested ones are settled
+/// before the palette hands any out), a sort order after every class so far,
+/// and no hotkey if theirs is invalid or taken
+fn settle_requests(existing: &[Class], requests: &mut [CreateClassRequest]) {
+    let mut used: Vec<String> = existing.iter().filter_map(|c| c.color.as_deref().and_then(normalize_color)).collect();
+    let mut hotkeys: Vec<String> = existing.iter().filter_map(|c| c.hotkey.clone()).collect();
+    let mut next_order = existing.iter().filter_map(|c| c.sort_order).max().map_or(0, |max| max + 1);
+    for req in requests.iter_mut() {
+        req.color = req.color.as_deref().and_then(normalize_color).filter(|c| !used.contains(c));
+        used.extend(req.color.clone());
+        req.hotkey = req.hotkey.as_deref().and_then(normalize_hotkey).filter(|h| !hotkeys.contains(h));
+        hotkeys.extend(req.hotkey.clone());
+    }
+    let palette = palette();
+    for req in requests.iter_mut() {
+        if req.color.is_none() {
+            let color = pick_color(&palette, &used);
+            used.push(color.clone());
+            req.color = Some(color);
+        }
+        let sort_order = *req.sort_order.get_or_insert(next_order);
+        next_order = next_order.max(sort_order + 1);
+    }
+}
+
+/// Write a new class item and return it.
+/// Classes without a color (or with one already taken in the project) get
+/// the next free palette color, so colors stay unique per project; see
+/// `settle_requests`.
+pub async fn put_class(
     client: &DynamoClient,
     table_name: &str,
     project_id: &str,
-    body: &[u8],
-) -> Result<Response<Body>, Error> {
-    let req: CreateClassRequest = serde_json::from_slice(body)?;
-    
-    let class_id = uuid::Uuid::new_v4().to_string();
-    let pk = format!("PROJECT#{}", project_id);
-    let sk = format!("CLASS#{}", class_id);
-    
-    // Store class
-    let mut builder = client
+    mut req: CreateClassRequest,
+) -> Result<Class, Error> {
+    let existing = fetch_project_classes(client, table_name, project_id).await?;
+    settle_requests(&existing, std::slice::from_mut(&mut req));
+
+    let (class, item) = new_class_item(project_id, req)?;
+    client
         .put_item()
         .table_name(table_name)
-        .item("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .item("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
-        .item("name", aws_sdk_dynamodb::types::AttributeValue::S(req.name.clone()))
-        .item("count", aws_sdk_dynamodb::types::AttributeValue::N("0".to_string()));
+        .set_item(Some(item))
+        .send()
+        .await?;
+    Ok(class)
+}
+
+/// A new class with a fresh id and the item that stores it. `req` is stored
+/// as is, see `settle_requests`.
+fn new_class_item(project_id: &str, req: CreateClassRequest) -> Result<(Class, std::collections::HashMap<String, aws_sdk_dynamodb::types::AttributeValue>), Error> {
+    let class_id = uuid::Uuid::new_v4().to_string();
+    let mut item = std::collections::HashMap::from([
+        ("PK".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(format!("PROJECT#{}", project_id))),
+        ("SK".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(format!("CLASS#{}", class_id))),
+        ("name".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(req.name.clone())),
+        ("count".to_string(), aws_sdk_dynamodb::types::AttributeValue::N("0".to_string())),
+    ]);
     
     if let Some(color) = &req.color {
-        builder = builder.item("color", aws_sdk_dynamodb::types::AttributeValue::S(color.clone()));
+        item.insert("color".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(color.clone()));
     }
     
     if let Some(properties) = &req.properties {
-        builder = builder.item("properties", aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(properties)?));
+        item.insert("properties".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(properties)?));
+    }
+    
+    if let Some(sort_order) = req.sort_order {
+        item.insert("sort_order".to_string(), aws_sdk_dynamodb::types::AttributeValue::N(sort_order.to_string()));
     }
     
-    builder.send().await?;
+    if let Some(hotkey) = &req.hotkey {
+        item.insert("hotkey".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(hotkey.clone()));
+    }
     
     let class = Class {
-        class_id: class_id.clone(),
+        class_id,
         project_id: project_id.to_string(),
         name: req.name,
         color: req.color,
         properties: req.properties,
         count: 0,
+        sort_order: req.sort_order,
+        hotkey: req.hotkey,
     };
+    Ok((class, item))
+}
+
+/// Create a new class for a project
+pub async fn create_class(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: CreateClassRequest = serde_json::from_slice(body)?;
+
+    if let Err(e) = skeleton(req.properties.as_ref()).and_then(|_| attribute_schema(req.properties.as_ref())) {
+        return class_error(StatusCode::BAD_REQUEST, e);
+    }
+
+    if let Some(color) = &req.color {
+        if normalize_color(color).is_none() {
+            return class_error(StatusCode::BAD_REQUEST, format!("Invalid color '{}', expected #rrggbb", color));
+        }
+        let existing = fetch_project_classes(client, table_name, project_id).await?;
+        if let Some(owner) = color_owner(&existing, color, None) {
+            return class_error(StatusCode::CONFLICT, format!("Color {} is already used by class '{}'", color, owner.name));
+        }
+    }
+
+    if let Some(hotkey) = &req.hotkey {
+        if normalize_hotkey(hotkey).is_none() {
+            return class_error(StatusCode::BAD_REQUEST, format!("Invalid hotkey '{}', expected a single letter or digit", hotkey));
+        }
+        let existing = fetch_project_classes(client, table_name, project_id).await?;
+        if let Some(owner) = hotkey_owner(&existing, hotkey, None) {
+            return class_error(StatusCode::CONFLICT, format!("Hotkey {} is already used by class '{}'", hotkey, owner.name));
+        }
+    }
+
+    let class = put_class(client, table_name, project_id, req).await?;
+    crate::audit::record(client, table_name, AuditEntry::new(project_id, user_id, "created", "class", &class.class_id).after(&class)).await;
     
     Ok(Response::builder()
         .status(StatusCode::CREATED)
@@ -51,13 +248,136 @@ pub async fn create_class(
         .map_err(Box::new)?)
 }
 
-/// Get a specific class
-pub async fn get_class(
+/// Check a class definition: a name, a valid color if any, and a valid
+/// skeleton and attribute schema if its properties declare them
+pub(crate) fn check_definition(name: &str, color: Option<&str>, properties: Option<&serde_json::Value>) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("name is required".to_string());
+    }
+    if let Some(color) = color.filter(|c| normalize_color(c).is_none()) {
+        return Err(format!("Invalid color '{}', expected #rrggbb", color));
+    }
+    skeleton(properties)?;
+    attribute_schema(properties)?;
+    Ok(())
+}
+
+/// Write new classes to a project that has the `existing` ones, in one batch
+/// write, settled as with `put_class`
+pub(crate) async fn insert_classes(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     project_id: &str,
-    class_id: &str,
+    existing: &[Class],
+    mut requests: Vec<CreateClassRequest>,
+) -> Result<Vec<Class>, Error> {
+    settle_requests(existing, &mut requests);
+    let mut classes = Vec::new();
+    let mut items = Vec::new();
+    for req in requests {
+        let (class, item) = new_class_item(project_id, req)?;
+        classes.push(class);
+        items.push(item);
+    }
+
+    let unwritten = crate::annotations::batch_put_items(client, table_name, items).await?;
+    if !unwritten.is_empty() {
+        return Err(format!("{} of {} classes could not be written", unwritten.len(), classes.len()).into());
+    }
+    let entries = classes.iter().map(|class| AuditEntry::new(project_id, user_id, "created", "class", &class.class_id).after(class)).collect();
+    crate::audit::record_all(client, table_name, entries).await;
+    Ok(classes)
+}
+
+/// Most classes one batch can create
+pub(crate) const MAX_CLASS_BATCH: usize = 200;
+
+/// Names in `names` that repeat an earlier one or one from `existing`,
+/// ignoring case and surrounding whitespace
+pub(crate) fn duplicate_names<'a>(existing: &[Class], names: &[&'a str]) -> Vec<&'a str> {
+    let same = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+    names
+        .iter()
+        .enumerate()
+        .filter(|(i, name)| existing.iter().any(|c| same(&c.name, name)) || names[..*i].iter().any(|n| same(n, name)))
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Create many classes at once, e.g. a whole taxonomy
+/// (POST /projects/{pid}/classes/batch, body `{"classes": [{"name", "color",
+/// "properties"}]}` with attribute schemas in `properties.attributes`).
+/// Nothing is written if a name is already taken, in the project or earlier
+/// in the batch, or a color or hotkey is invalid or taken. Classes without a
+/// color get free palette colors.
+pub async fn batch_create_classes(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    body: &[u8],
 ) -> Result<Response<Body>, Error> {
+    let req: BatchCreateClassesRequest = serde_json::from_slice(body)?;
+    if req.classes.is_empty() || req.classes.len() > MAX_CLASS_BATCH {
+        return class_error(StatusCode::BAD_REQUEST, format!("A batch needs between 1 and {} classes", MAX_CLASS_BATCH));
+    }
+    for (i, class) in req.classes.iter().enumerate() {
+        if let Err(e) = check_definition(&class.name, class.color.as_deref(), class.properties.as_ref()) {
+            return class_error(StatusCode::BAD_REQUEST, format!("classes[{}]: {}", i, e));
+        }
+    }
+
+    let existing = fetch_project_classes(client, table_name, project_id).await?;
+    let names: Vec<&str> = req.classes.iter().map(|c| c.name.as_str()).collect();
+    let duplicates = duplicate_names(&existing, &names);
+    if !duplicates.is_empty() {
+        return Ok(Response::builder()
+            .status(StatusCode::CONFLICT)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": "Class names must be unique in the project", "duplicates": duplicates}).to_string().into())
+            .map_err(Box::new)?);
+    }
+
+    let mut taken = existing.clone();
+    for class in &req.classes {
+        if let Some(color) = class.color.as_deref().and_then(normalize_color) {
+            if let Some(owner) = color_owner(&taken, &color, None) {
+                return class_error(StatusCode::CONFLICT, format!("Color {} is already used by class '{}'", color, owner.name));
+            }
+            taken.push(Class { color: Some(color), name: class.name.clone(), ..Class::default() });
+        }
+    }
+    let mut taken = existing.clone();
+    for (i, class) in req.classes.iter().enumerate() {
+        if let Some(hotkey) = &class.hotkey {
+            let Some(hotkey) = normalize_hotkey(hotkey) else {
+                return class_error(StatusCode::BAD_REQUEST, format!("classes[{}]: Invalid hotkey '{}', expected a single letter or digit", i, hotkey));
+            };
+            if let Some(owner) = hotkey_owner(&taken, &hotkey, None) {
+                return class_error(StatusCode::CONFLICT, format!("Hotkey {} is already used by class '{}'", hotkey, owner.name));
+            }
+            taken.push(Class { hotkey: Some(hotkey), name: class.name.clone(), ..Class::default() });
+        }
+    }
+    let classes = insert_classes(client, table_name, user_id, project_id, &existing, req.classes).await?;
+    
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&classes)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Fetch a single class, if it exists
+pub async fn fetch_class(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    class_id: &str,
+) -> Result<Option<Class>, Error> {
     let pk = format!("PROJECT#{}", project_id);
     let sk = format!("CLASS#{}", class_id);
     
@@ -69,18 +389,39 @@ pub async fn get_class(
         .send()
         .await?;
     
-    if let Some(item) = result.item() {
-        let class = Class {
-            class_id: class_id.to_string(),
-            project_id: project_id.to_string(),
-            name: item.get("name").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-            color: item.get("color").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
-            properties: item.get("properties")
-                .and_then(|v| v.as_s().ok())
-                .and_then(|s| serde_json::from_str(s).ok()),
-            count: item.get("count").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0),
-        };
-        
+    Ok(result.item().map(|item| class_from_item(project_id, class_id, item)))
+}
+
+fn class_from_item(project_id: &str, class_id: &str, item: &std::collections::HashMap<String, aws_sdk_dynamodb::types::AttributeValue>) -> Class {
+    Class {
+        class_id: class_id.to_string(),
+        project_id: project_id.to_string(),
+        name: item.get("name").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
+        color: item.get("color").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
+        properties: item.get("properties")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| serde_json::from_str(s).ok()),
+        count: item.get("count").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0),
+        sort_order: item.get("sort_order").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()),
+        hotkey: item.get("hotkey").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
+    }
+}
+
+/// Display order: by sort_order, classes without one last, ties by name
+pub fn sort_classes(classes: &mut [Class]) {
+    classes.sort_by(|a, b| {
+        (a.sort_order.is_none(), a.sort_order, &a.name).cmp(&(b.sort_order.is_none(), b.sort_order, &b.name))
+    });
+}
+
+/// Get a specific class
+pub async fn get_class(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    class_id: &str,
+) -> Result<Response<Body>, Error> {
+    if let Some(class) = fetch_class(client, table_name, project_id, class_id).await? {
         Ok(Response::builder()
             .status(StatusCode::OK)
             .header("Content-Type", "application/json")
@@ -97,55 +438,127 @@ pub async fn get_class(
     }
 }
 
-/// List all classes for a project
-pub async fn list_project_classes(
+/// Keypoint skeleton declared in a class's `properties.skeleton`, if any
+pub fn skeleton(properties: Option<&serde_json::Value>) -> Result<Option<Skeleton>, String> {
+    let Some(value) = properties.and_then(|p| p.get("skeleton")) else {
+        return Ok(None);
+    };
+    let skeleton: Skeleton = serde_json::from_value(value.clone())
+        .map_err(|e| format!("Invalid properties.skeleton: {}", e))?;
+    crate::geometry::validate_skeleton(&skeleton)?;
+    Ok(Some(skeleton))
+}
+
+/// Kinds of value an attribute can hold
+pub const ATTRIBUTE_TYPES: [&str; 4] = ["enum", "bool", "number", "text"];
+
+/// Attribute schema declared in a class's `properties.attributes`, if any
+pub fn attribute_schema(properties: Option<&serde_json::Value>) -> Result<Vec<AttributeDef>, String> {
+    let Some(value) = properties.and_then(|p| p.get("attributes")) else {
+        return Ok(Vec::new());
+    };
+    let schema: Vec<AttributeDef> = serde_json::from_value(value.clone())
+        .map_err(|e| format!("Invalid properties.attributes: {}", e))?;
+    for (i, def) in schema.iter().enumerate() {
+        if def.name.trim().is_empty() {
+            return Err(format!("properties.attributes[{}] needs a name", i));
+        }
+        if schema[..i].iter().any(|d| d.name == def.name) {
+            return Err(format!("Attribute '{}' is declared twice", def.name));
+        }
+        if !ATTRIBUTE_TYPES.contains(&def.kind.as_str()) {
+            return Err(format!("Attribute '{}' has unknown type '{}', expected one of: {}", def.name, def.kind, ATTRIBUTE_TYPES.join(", ")));
+        }
+        if (def.kind == "enum") == def.options.is_empty() {
+            return Err(format!("Attribute '{}': options are required for enums and only allowed for them", def.name));
+        }
+    }
+    Ok(schema)
+}
+
+/// Check an annotation's attribute values against its class's schema: every
+/// value must be declared and of the declared type, and required attributes
+/// must be set
+pub fn validate_attributes(schema: &[AttributeDef], attributes: &std::collections::BTreeMap<String, serde_json::Value>) -> Result<(), String> {
+    for (name, value) in attributes {
+        let Some(def) = schema.iter().find(|d| &d.name == name) else {
+            return Err(format!("Unknown attribute '{}'", name));
+        };
+        let valid = match def.kind.as_str() {
+            "enum" => value.as_str().is_some_and(|v| def.options.iter().any(|o| o == v)),
+            "bool" => value.is_boolean(),
+            "number" => value.is_number(),
+            _ => value.is_string(),
+        };
+        if !valid {
+            return Err(match def.kind.as_str() {
+                "enum" => format!("Attribute '{}' must be one of: {}", name, def.options.join(", ")),
+                "bool" => format!("Attribute '{}' must be true or false", name),
+                "number" => format!("Attribute '{}' must be a number", name),
+                _ => format!("Attribute '{}' must be text", name),
+            });
+        }
+    }
+    if let Some(missing) = schema.iter().find(|d| d.required && !attributes.contains_key(&d.name)) {
+        return Err(format!("Attribute '{}' is required", missing.name));
+    }
+    Ok(())
+}
+
+/// Fetch a page of a project's classes, in display order within the page
+pub async fn fetch_project_classes_page(
     client: &DynamoClient,
     table_name: &str,
     project_id: &str,
-) -> Result<Response<Body>, Error> {
+    request: &PageRequest,
+) -> Result<Page<Class>, Error> {
     let pk = format!("PROJECT#{}", project_id);
-    
-    let result = client
-        .query()
-        .table_name(table_name)
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
-        .expression_attribute_values(":pk", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .expression_attribute_values(":sk_prefix", aws_sdk_dynamodb::types::AttributeValue::S("CLASS#".to_string()))
-        .send()
-        .await?;
+    let page = crate::pagination::query_prefix(client, table_name, &pk, "CLASS#", None, request).await?;
     
     let mut classes = Vec::new();
     
-    for item in result.items() {
+    for item in &page.items {
             if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
                 if let Some(class_id) = sk.strip_prefix("CLASS#") {
-                    let class = Class {
-                        class_id: class_id.to_string(),
-                        project_id: project_id.to_string(),
-                        name: item.get("name").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default(),
-                        color: item.get("color").and_then(|v| v.as_s().ok()).map(|s| s.to_string()),
-                        properties: item.get("properties")
-                            .and_then(|v| v.as_s().ok())
-                            .and_then(|s| serde_json::from_str(s).ok()),
-                        count: item.get("count").and_then(|v| v.as_n().ok()).and_then(|n| n.parse().ok()).unwrap_or(0),
-                    };
-                    classes.push(class);
+                    classes.push(class_from_item(project_id, class_id, item));
                 }
             }
     }
+    sort_classes(&mut classes);
     
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
-        .body(serde_json::to_string(&classes)?.into())
-        .map_err(Box::new)?)
+    Ok(Page { items: classes, next_cursor: page.next_cursor })
+}
+
+/// Fetch all classes for a project
+pub async fn fetch_project_classes(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+) -> Result<Vec<Class>, Error> {
+    Ok(fetch_project_classes_page(client, table_name, project_id, &PageRequest::default()).await?.items)
+}
+
+/// List a project's classes: all of them, or a page with `?limit=&cursor=`
+pub async fn list_project_classes(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    params: PageParams<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match crate::pagination::page_request(params, "CLASS#") {
+        Ok(request) => request,
+        Err(e) => return crate::pagination::invalid_page(e),
+    };
+    let page = fetch_project_classes_page(client, table_name, project_id, &request).await?;
+
+    crate::pagination::page_response(&page)
 }
 
 /// Update a class
 pub async fn update_class(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     project_id: &str,
     class_id: &str,
     body: &[u8],
@@ -165,25 +578,66 @@ pub async fn update_class(
     }
     
     if let Some(color) = req.color {
+        let Some(color) = normalize_color(&color) else {
+            return class_error(StatusCode::BAD_REQUEST, format!("Invalid color '{}', expected #rrggbb", color));
+        };
+        let existing = fetch_project_classes(client, table_name, project_id).await?;
+        if let Some(owner) = color_owner(&existing, &color, Some(class_id)) {
+            return class_error(StatusCode::CONFLICT, format!("Color {} is already used by class '{}'", color, owner.name));
+        }
         update_expr.push("#color = :color");
         expr_names.insert("#color".to_string(), "color".to_string());
         expr_values.insert(":color".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(color));
     }
     
     if let Some(properties) = req.properties {
+        if let Err(e) = skeleton(Some(&properties)).and_then(|_| attribute_schema(Some(&properties))) {
+            return class_error(StatusCode::BAD_REQUEST, e);
+        }
         update_expr.push("#properties = :properties");
         expr_names.insert("#properties".to_string(), "properties".to_string());
         expr_values.insert(":properties".to_string(), 
             aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&properties)?));
     }
     
-    if !update_expr.is_empty() {
+    if let Some(sort_order) = req.sort_order {
+        update_expr.push("#sort_order = :sort_order");
+        expr_names.insert("#sort_order".to_string(), "sort_order".to_string());
+        expr_values.insert(":sort_order".to_string(), aws_sdk_dynamodb::types::AttributeValue::N(sort_order.to_string()));
+    }
+    
+    let mut remove_expr = vec![];
+    if let Some(hotkey) = req.hotkey {
+        expr_names.insert("#hotkey".to_string(), "hotkey".to_string());
+        if hotkey.trim().is_empty() {
+            remove_expr.push("#hotkey");
+        } else {
+            let Some(hotkey) = normalize_hotkey(&hotkey) else {
+                return class_error(StatusCode::BAD_REQUEST, format!("Invalid hotkey '{}', expected a single letter or digit", hotkey));
+            };
+            let existing = fetch_project_classes(client, table_name, project_id).await?;
+            if let Some(owner) = hotkey_owner(&existing, &hotkey, Some(class_id)) {
+                return class_error(StatusCode::CONFLICT, format!("Hotkey {} is already used by class '{}'", hotkey, owner.name));
+            }
+            update_expr.push("#hotkey = :hotkey");
+            expr_values.insert(":hotkey".to_string(), aws_sdk_dynamodb::types::AttributeValue::S(hotkey));
+        }
+    }
+    
+    if !update_expr.is_empty() || !remove_expr.is_empty() {
+        let mut expression = Vec::new();
+        if !update_expr.is_empty() {
+            expression.push(format!("SET {}", update_expr.join(", ")));
+        }
+        if !remove_expr.is_empty() {
+            expression.push(format!("REMOVE {}", remove_expr.join(", ")));
+        }
         let mut builder = client
             .update_item()
             .table_name(table_name)
             .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
             .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk.clone()))
-            .update_expression(format!("SET {}", update_expr.join(", ")));
+            .update_expression(expression.join(" "));
         
         for (k, v) in expr_names {
             builder = builder.expression_attribute_names(k, v);
@@ -194,28 +648,104 @@ pub async fn update_class(
         }
         
         builder.send().await?;
+        crate::audit::record(client, table_name, AuditEntry::new(project_id, user_id, "updated", "class", class_id).changes(body)).await;
     }
     
     get_class(client, table_name, project_id, class_id).await
 }
 
+fn reorder_positions(class_ids: &[String]) -> Result<Vec<(&str, i32)>, String> {
+    if class_ids.is_empty() {
+        return Err("class_ids must not be empty".to_string());
+    }
+    if class_ids.len() > MAX_CLASS_BATCH {
+        return Err(format!("At most {} classes can be reordered at once", MAX_CLASS_BATCH));
+    }
+    let mut seen = std::collections::HashSet::new();
+    if let Some(duplicate) = class_ids.iter().find(|id| !seen.insert(id.as_str())) {
+        return Err(format!("Class '{}' is listed more than once", duplicate));
+    }
+    Ok(class_ids.iter().enumerate().map(|(i, id)| (id.as_str(), i as i32)).collect())
+}
+
+/// Set the display order of a project's classes
+/// (POST /projects/{pid}/classes/reorder, body `{"class_ids"}` first to last).
+/// Classes left out keep their sort_order.
+pub async fn reorder_classes(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: ReorderClassesRequest = serde_json::from_slice(body)?;
+    let positions = match reorder_positions(&req.class_ids) {
+        Ok(positions) => positions,
+        Err(e) => return class_error(StatusCode::BAD_REQUEST, e),
+    };
+    
+    let mut reordered = Vec::new();
+    let mut missing = Vec::new();
+    for (class_id, sort_order) in positions {
+        let result = client
+            .update_item()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("PROJECT#{}", project_id)))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("CLASS#{}", class_id)))
+            .condition_expression("attribute_exists(PK)")
+            .update_expression("SET #sort_order = :sort_order")
+            .expression_attribute_names("#sort_order", "sort_order")
+            .expression_attribute_values(":sort_order", aws_sdk_dynamodb::types::AttributeValue::N(sort_order.to_string()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => reordered.push(serde_json::json!({"class_id": class_id, "sort_order": sort_order})),
+            Err(e) if e.as_service_error().map(|se| se.is_conditional_check_failed_exception()).unwrap_or(false) => missing.push(class_id),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if !reordered.is_empty() {
+        let summary = serde_json::json!({"reordered": reordered});
+        crate::audit::record(client, table_name, AuditEntry::new(project_id, user_id, "reordered", "project", project_id).after(&summary)).await;
+    }
+    
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({
+            "project_id": project_id,
+            "reordered": reordered,
+            "missing": missing,
+        }).to_string().into())
+        .map_err(Box::new)?)
+}
+
 /// Delete a class
 pub async fn delete_class(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     project_id: &str,
     class_id: &str,
 ) -> Result<Response<Body>, Error> {
     let pk = format!("PROJECT#{}", project_id);
     let sk = format!("CLASS#{}", class_id);
     
-    client
+    let deleted = client
         .delete_item()
         .table_name(table_name)
         .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
         .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
         .send()
         .await?;
+    let mut entry = AuditEntry::new(project_id, user_id, "deleted", "class", class_id);
+    if let Some(item) = deleted.attributes() {
+        let text = |name: &str| item.get(name).and_then(|v| v.as_s().ok());
+        entry = entry.before(&serde_json::json!({"name": text("name"), "color": text("color")}));
+    }
+    crate::audit::record(client, table_name, entry).await;
     
     Ok(Response::builder()
         .status(StatusCode::NO_CONTENT)
@@ -232,6 +762,10 @@ pub async fn increment_class_count(
     class_id: &str,
     delta: i32,
 ) -> Result<(), Error> {
+    // Writes that couldn't place their annotation in a project count nowhere
+    if project_id.is_empty() {
+        return Ok(());
+    }
     let pk = format!("PROJECT#{}", project_id);
     let sk = format!("CLASS#{}", class_id);
     
@@ -249,3 +783,176 @@ pub async fn increment_class_count(
     
     Ok(())
 }
+
+/// The same count change as a transaction item, for writes where the
+/// annotation and its class count must succeed or fail together. None when
+/// there's no project to count in.
+pub(crate) fn class_count_update(table_name: &str, project_id: &str, class_id: &str, delta: i32) -> Result<Option<aws_sdk_dynamodb::types::TransactWriteItem>, Error> {
+    if project_id.is_empty() {
+        return Ok(None);
+    }
+    let update = aws_sdk_dynamodb::types::Update::builder()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("PROJECT#{}", project_id)))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("CLASS#{}", class_id)))
+        .update_expression("SET #count = if_not_exists(#count, :zero) + :delta")
+        .expression_attribute_names("#count", "count")
+        .expression_attribute_values(":zero", aws_sdk_dynamodb::types::AttributeValue::N("0".to_string()))
+        .expression_attribute_values(":delta", aws_sdk_dynamodb::types::AttributeValue::N(delta.to_string()))
+        .build()?;
+    Ok(Some(aws_sdk_dynamodb::types::TransactWriteItem::builder().update(update).build()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_color() {
+        assert_eq!(normalize_color("#FF0000").as_deref(), Some("#ff0000"));
+        assert_eq!(normalize_color("0f0").as_deref(), Some("#00ff00"));
+        assert_eq!(normalize_color("#12345"), None);
+        assert_eq!(normalize_color("red"), None);
+    }
+
+    #[test]
+    fn test_skeleton() {
+        let properties = serde_json::json!({
+            "skeleton": {"keypoints": ["a", "b"], "edges": [[0, 1]]},
+            "other": true,
+        });
+        let parsed = skeleton(Some(&properties)).unwrap().unwrap();
+        assert_eq!(parsed.keypoints, vec!["a", "b"]);
+
+        assert_eq!(skeleton(Some(&serde_json::json!({"other": 1}))).unwrap(), None);
+        assert_eq!(skeleton(None).unwrap(), None);
+        let invalid = serde_json::json!({"skeleton": {"keypoints": ["a"], "edges": [[0, 2]]}});
+        assert!(skeleton(Some(&invalid)).is_err());
+    }
+
+    #[test]
+    fn test_attribute_schema() {
+        let properties = serde_json::json!({"attributes": [
+            {"name": "material", "type": "enum", "options": ["timber", "steel"], "required": true},
+            {"name": "load_bearing", "type": "bool"},
+            {"name": "span_mm", "type": "number"},
+            {"name": "note", "type": "text"},
+        ]});
+        let schema = attribute_schema(Some(&properties)).unwrap();
+        assert_eq!(schema.len(), 4);
+        assert!(schema[0].required && !schema[1].required);
+        assert!(attribute_schema(None).unwrap().is_empty());
+
+        let invalid = [
+            serde_json::json!([{"name": "a", "type": "date"}]),
+            serde_json::json!([{"name": "a", "type": "enum"}]),
+            serde_json::json!([{"name": "a", "type": "text", "options": ["x"]}]),
+            serde_json::json!([{"name": "a", "type": "bool"}, {"name": "a", "type": "text"}]),
+            serde_json::json!({"name": "a"}),
+        ];
+        for attributes in invalid {
+            assert!(attribute_schema(Some(&serde_json::json!({"attributes": attributes}))).is_err());
+        }
+    }
+
+    #[test]
+    fn test_validate_attributes() {
+        let properties = serde_json::json!({"attributes": [
+            {"name": "material", "type": "enum", "options": ["timber", "steel"], "required": true},
+            {"name": "load_bearing", "type": "bool"},
+            {"name": "span_mm", "type": "number"},
+        ]});
+        let schema = attribute_schema(Some(&properties)).unwrap();
+        let values = |value: serde_json::Value| serde_json::from_value(value).unwrap();
+
+        assert!(validate_attributes(&schema, &values(serde_json::json!({"material": "steel", "span_mm": 4200}))).is_ok());
+        // Required, unknown and mistyped values
+        assert!(validate_attributes(&schema, &values(serde_json::json!({"load_bearing": true}))).is_err());
+        assert!(validate_attributes(&schema, &values(serde_json::json!({"material": "steel", "colour": "red"}))).is_err());
+        assert!(validate_attributes(&schema, &values(serde_json::json!({"material": "brick"}))).is_err());
+        assert!(validate_attributes(&schema, &values(serde_json::json!({"material": "steel", "load_bearing": "yes"}))).is_err());
+        // Classes without a schema take no attributes
+        assert!(validate_attributes(&[], &values(serde_json::json!({}))).is_ok());
+        assert!(validate_attributes(&[], &values(serde_json::json!({"material": "steel"}))).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let existing = Class {
+            class_id: "c1".to_string(),
+            project_id: "p1".to_string(),
+            name: "Wall".to_string(),
+            color: None,
+            properties: None,
+            count: 0,
+            sort_order: None,
+            hotkey: None,
+        };
+        assert_eq!(duplicate_names(&[existing], &["Door", " wall", "Window", "door"]), vec![" wall", "door"]);
+        assert!(duplicate_names(&[], &["Door", "Window"]).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_hotkey() {
+        assert_eq!(normalize_hotkey(" W ").as_deref(), Some("w"));
+        assert_eq!(normalize_hotkey("7").as_deref(), Some("7"));
+        assert_eq!(normalize_hotkey("ww"), None);
+        assert_eq!(normalize_hotkey("?"), None);
+        assert_eq!(normalize_hotkey(""), None);
+    }
+
+    #[test]
+    fn test_settle_requests() {
+        let existing = [
+            Class { class_id: "c1".to_string(), sort_order: Some(4), hotkey: Some("w".to_string()), color: Some("#ff0000".to_string()), ..Class::default() },
+            Class { class_id: "c2".to_string(), ..Class::default() },
+        ];
+        let request = |name: &str, hotkey: Option<&str>, sort_order: Option<i32>| CreateClassRequest {
+            name: name.to_string(),
+            color: Some("#FF0000".to_string()),
+            properties: None,
+            sort_order,
+            hotkey: hotkey.map(|h| h.to_string()),
+        };
+        let mut requests = vec![request("Door", Some("W"), None), request("Window", Some("D"), Some(9)), request("Roof", Some("d"), None)];
+        settle_requests(&existing, &mut requests);
+        let settled: Vec<_> = requests.iter().map(|r| (r.sort_order, r.hotkey.as_deref())).collect();
+        // Taken hotkeys are dropped; new classes go after every class so far
+        assert_eq!(settled, vec![(Some(5), None), (Some(9), Some("d")), (Some(10), None)]);
+        assert!(requests.iter().all(|r| r.color.as_deref() != Some("#ff0000")));
+    }
+
+    #[test]
+    fn test_sort_classes() {
+        let class = |name: &str, sort_order: Option<i32>| Class { name: name.to_string(), sort_order, ..Class::default() };
+        let mut classes = vec![class("Roof", None), class("Wall", Some(2)), class("Door", None), class("Window", Some(0)), class("Beam", Some(2))];
+        sort_classes(&mut classes);
+        let names: Vec<_> = classes.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["Window", "Beam", "Wall", "Door", "Roof"]);
+    }
+
+    #[test]
+    fn test_reorder_positions() {
+        let ids = vec!["c2".to_string(), "c1".to_string()];
+        assert_eq!(reorder_positions(&ids).unwrap(), vec![("c2", 0), ("c1", 1)]);
+        assert!(reorder_positions(&[]).is_err());
+        assert!(reorder_positions(&["c1".to_string(), "c1".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_pick_color() {
+        let palette = vec!["#ff0000".to_string(), "#00ff00".to_string()];
+        assert_eq!(pick_color(&palette, &[]), "#ff0000");
+        // Used colors are compared case-insensitively
+        assert_eq!(pick_color(&palette, &["#FF0000".to_string()]), "#00ff00");
+
+        // Exhausted palette falls back to generated, still-unique colors
+        let mut used = palette.clone();
+        for _ in 0..50 {
+            let color = pick_color(&palette, &used);
+            assert!(!used.contains(&color));
+            assert!(normalize_color(&color).is_some());
+            used.push(color);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::pagination::PageRequest;
+use crate::{region, storage};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::Error;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Objects and multipart uploads younger than this are left alone: an
+/// upload, move or copy may still be writing them
+const MIN_AGE_SECS: i64 = 24 * 3600;
+
+/// Keys listed in a report; the counts cover the rest
+const REPORT_KEYS: usize = 100;
+
+/// What a cleanup run reclaimed (or, dry, would have)
+#[derive(Debug, Default, Serialize)]
+pub struct CleanupReport {
+    pub dry_run: bool,
+    pub objects_scanned: usize,
+    pub objects_deleted: usize,
+    pub bytes_reclaimed: i64,
+    pub uploads_aborted: usize,
+    pub projects_reconciled: usize, // storage usage reset from the listing
+    pub deleted: Vec<String>,       // the first REPORT_KEYS deleted keys
+}
+
+/// Project an object is stored under
+fn project_of(key: &str) -> Option<&str> {
+    let project_id = storage::s3_config().project_path(key)?.split('/').next()?;
+    (!project_id.is_empty()).then_some(project_id)
+}
+
+/// (block id, image id) an object belongs to: its upload
+/// `projects/{pid}/blocks/{bid}/{iid}.{ext}` or anything in its folder
+/// `projects/{pid}/blocks/{bid}/{iid}/`
+fn image_object(key: &str) -> Option<(&str, &str)> {
+    let rest = storage::s3_config().project_path(key)?;
+    let (_project_id, rest) = rest.split_once('/')?;
+    let rest = rest.strip_prefix("blocks/")?;
+    let (block_id, rest) = rest.split_once('/')?;
+    let image_id = rest.split(['.', '/']).next()?;
+    (!block_id.is_empty() && !image_id.is_empty()).then_some((block_id, image_id))
+}
+
+/// Image ids a block's partition still holds
+async fn live_images(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+) -> Result<HashSet<String>, Error> {
+    let pk = format!("BLOCK#{}", block_id);
+    let page = crate::pagination::query_prefix(
+        client,
+        table_name,
+        &pk,
+        "IMAGE#",
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+    Ok(page
+        .items
+        .iter()
+        .filter_map(|item| item.get("SK")?.as_s().ok()?.strip_prefix("IMAGE#"))
+        .map(|id| id.to_string())
+        .collect())
+}
+
+/// Abort multipart uploads started before `cutoff`, returning how many went
+/// and the (block, image) pairs of the ones still in progress
+async fn abort_stale_uploads(
+    s3_client: &S3Client,
+    cutoff: i64,
+    dry_run: bool,
+) -> Result<(usize, HashSet<(String, String)>), Error> {
+    let mut aborted = 0;
+    let mut pending = HashSet::new();
+    let mut key_marker: Option<String> = None;
+    let mut upload_id_marker: Option<String> = None;
+    loop {
+        let resp = s3_client
+            .list_multipart_uploads()
+            .bucket(region::bucket_name())
+            .prefix(storage::s3_config().projects_root())
+            .set_key_marker(key_marker.take())
+            .set_upload_id_marker(upload_id_marker.take())
+            .send()
+            .await
+            .map_err(|e| format!("S3 list multipart uploads failed: {}", e))?;
+        for upload in resp.uploads() {
+            let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) else {
+                continue;
+            };
+            let initiated = upload.initiated().map(|t| t.secs()).unwrap_or(0);
+            if initiated >= cutoff {
+                if let Some((block_id, image_id)) = image_object(key) {
+                    pending.insert((block_id.to_string(), image_id.to_string()));
+                }
+                continue;
+            }
+            if !dry_run {
+                s3_client
+                    .abort_multipart_upload()
+                    .bucket(region::bucket_name())
+                    .key(key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                    .map_err(|e| format!("Failed to abort multipart upload of {}: {}", key, e))?;
+            }
+            tracing::info!("Aborted stale multipart upload {} of {}", upload_id, key);
+            aborted += 1;
+        }
+        if !resp.is_truncated().unwrap_or(false) {
+            break;
+        }
+        key_marker = resp.next_key_marker().map(|s| s.to_string());
+        upload_id_marker = resp.next_upload_id_marker().map(|s| s.to_string());
+        if key_marker.is_none() {
+            break;
+        }
+    }
+    Ok((aborted, pending))
+}
+
+/// Delete up to 1000 objects, returning the keys S3 reports as deleted
+async fn delete_keys(s3_client: &S3Client, keys: &[String]) -> Result<Vec<String>, Error> {
+    let objects = keys
+        .iter()
+        .filter_map(|key| ObjectIdentifier::builder().key(key).build().ok())
+        .collect();
+    let delete = Delete::builder()
+        .set_objects(Some(objects))
+        .build()
+        .map_err(|e| format!("Failed to build S3 delete payload: {:?}", e))?;
+    let resp = s3_client
+        .delete_objects()
+        .bucket(region::bucket_name())
+        .delete(delete)
+        .send()
+        .await
+        .map_err(|e| format!("S3 delete failed: {}", e))?;
+    for error in resp.errors() {
+        tracing::warn!(
+            "Failed to delete S3 object {}: {}",
+            error.key().unwrap_or_default(),
+            error.message().unwrap_or_default()
+        );
+    }
+    let failed: HashSet<&str> = resp.errors().iter().filter_map(|e| e.key()).collect();
+    Ok(keys
+        .iter()
+        .filter(|key| !failed.contains(key.as_str()))
+        .cloned()
+        .collect())
+}
+
+/// Reclaim storage nothing refers to any more: abort multipart uploads left
+/// for over a day, then delete objects under `projects/*/blocks/*` whose
+/// image row is gone from its block. Objects that don't belong to an image
+/// and anything younger than a day are kept. What each project keeps then
+/// becomes its storage usage. With `dry_run` nothing is changed and the
+/// report says what would have been.
+pub async fn run(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    dry_run: bool,
+) -> Result<CleanupReport, Error> {
+    let cutoff = chrono::Utc::now().timestamp() - MIN_AGE_SECS;
+    let (uploads_aborted, pending) = abort_stale_uploads(s3_client, cutoff, dry_run).await?;
+    let mut report = CleanupReport {
+        dry_run,
+        uploads_aborted,
+        ..Default::default()
+    };
+
+    // Keys are listed in order, so each block's images are looked up once
+    // and the cache only grows with the number of blocks
+    let mut live: HashMap<String, HashSet<String>> = HashMap::new();
+    // Bytes and objects each project keeps, for its storage usage
+    let mut usage: HashMap<String, (i64, u64)> = HashMap::new();
+    let mut continuation: Option<String> = None;
+    loop {
+        let resp = s3_client
+            .list_objects_v2()
+            .bucket(region::bucket_name())
+            .prefix(storage::s3_config().projects_root())
+            .set_continuation_token(continuation.take())
+            .send()
+            .await
+            .map_err(|e| format!("S3 list failed: {}", e))?;
+
+        let mut orphans = Vec::new();
+        let mut sizes = HashMap::new();
+        for object in resp.contents() {
+            let Some(key) = object.key() else {
+                continue;
+            };
+            report.objects_scanned += 1;
+            if let Some(project_id) = project_of(key) {
+                let entry = usage.entry(project_id.to_string()).or_default();
+                entry.0 += object.size().unwrap_or(0);
+                entry.1 += 1;
+            }
+            let Some((block_id, image_id)) = image_object(key) else {
+                continue;
+            };
+            let modified = object.last_modified().map(|t| t.secs()).unwrap_or(i64::MAX);
+            let uploading = pending.contains(&(block_id.to_string(), image_id.to_string()));
+            if modified >= cutoff || uploading {
+                continue;
+            }
+            if !live.contains_key(block_id) {
+                let images = live_images(client, table_name, block_id).await?;
+                live.insert(block_id.to_string(), images);
+            }
+            if live[block_id].contains(image_id) {
+                continue;
+            }
+            orphans.push(key.to_string());
+            sizes.insert(key.to_string(), object.size().unwrap_or(0));
+        }
+
+        let deleted = if dry_run || orphans.is_empty() {
+            orphans
+        } else {
+            delete_keys(s3_client, &orphans).await?
+        };
+        for key in deleted {
+            let size = sizes.get(&key).copied().unwrap_or(0);
+            if let Some(entry) = project_of(&key).and_then(|p| usage.get_mut(p)) {
+                entry.0 -= size;
+                entry.1 -= 1;
+            }
+            report.objects_deleted += 1;
+            report.bytes_reclaimed += size;
+            if report.deleted.len() < REPORT_KEYS {
+                report.deleted.push(key);
+            }
+        }
+
+        if !resp.is_truncated().unwrap_or(false) {
+            break;
+        }
+        continuation = resp.next_continuation_token().map(|s| s.to_string());
+        if continuation.is_none() {
+            break;
+        }
+    }
+
+    if !dry_run {
+        for (project_id, (bytes, objects)) in &usage {
+            crate::storage_usage::reconcile(client, table_name, project_id, *bytes, *objects)
+                .await?;
+            report.projects_reconciled += 1;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_object() {
+        assert_eq!(image_object("projects/p/blocks/b/i.jpg"), Some(("b", "i")));
+        assert_eq!(
+            image_object("projects/p/blocks/b/i/6000w.jpg"),
+            Some(("b", "i"))
+        );
+        assert_eq!(
+            image_object("projects/p/blocks/b/i/tiles/dzi_files/0/0_0.jpg"),
+            Some(("b", "i"))
+        );
+        // Not an image's object
+        assert_eq!(image_object("projects/p/cover.jpg"), None);
+        assert_eq!(image_object("projects/p/blocks/b/"), None);
+        assert_eq!(image_object("exports/p/e.zip"), None);
+
+        assert_eq!(project_of("projects/p/cover.jpg"), Some("p"));
+        assert_eq!(project_of("exports/p/e.zip"), None);
+    }
+}
@@ -1,16 +1,115 @@
 use lambda_http::{Body, Error, Response, http::StatusCode};
+use aws_sdk_secretsmanager::Client as SecretsClient;
 use rsa::{RsaPrivateKey, pkcs1v15::SigningKey, signature::SignatureEncoding, signature::Signer};
 use rsa::pkcs8::DecodePrivateKey;
 use rsa::pkcs1::DecodeRsaPrivateKey;
+use serde::Deserialize;
 use sha1::Sha1;
 use base64::Engine;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const CLOUDFRONT_DOMAIN: &str = "CLOUDFRONT_DOMAIN"; // Set via env var
-const CLOUDFRONT_KEY_PAIR_ID: &str = "CLOUDFRONT_KEY_PAIR_ID"; // Set via env var
-const CLOUDFRONT_PRIVATE_KEY: &str = "CLOUDFRONT_PRIVATE_KEY"; // Set via env var (PEM format)
+const CLOUDFRONT_KEYS_SECRET: &str = "CLOUDFRONT_KEYS_SECRET"; // Secrets Manager id of the key ring
+const CLOUDFRONT_KEY_PAIR_ID: &str = "CLOUDFRONT_KEY_PAIR_ID"; // Without a key ring secret (PEM below)
+const CLOUDFRONT_PRIVATE_KEY: &str = "CLOUDFRONT_PRIVATE_KEY"; // Without a key ring secret (PEM format)
 const CLOUDFRONT_COOKIE_DOMAIN: &str = "CLOUDFRONT_COOKIE_DOMAIN"; // Optional explicit cookie domain
 
+/// How long a loaded key ring is used before the secret is read again, so a
+/// rotation reaches every warm lambda within this
+const KEY_RING_REFRESH: Duration = Duration::from_secs(300);
+
+/// A CloudFront key pair: the public key's ID in the key group and the
+/// private key (PEM) cookies are signed with
+#[derive(Clone, Deserialize)]
+pub struct KeyPair {
+    pub key_pair_id: String,
+    pub private_key: String,
+}
+
+/// Every key pair that may be signing, kept as JSON in the
+/// CLOUDFRONT_KEYS_SECRET secret:
+/// `{"active_key_pair_id": "K2...", "key_pairs": [{"key_pair_id": "K2...", "private_key": "-----BEGIN ..."}, ...]}`
+///
+/// To rotate: add the new public key to the distribution's key group and its
+/// pair to `key_pairs`, then point `active_key_pair_id` at it. Cookies
+/// signed with the previous key stay valid while it remains in the key
+/// group, so drop it from both once they have expired (12 hours).
+#[derive(Clone, Deserialize)]
+pub struct KeyRing {
+    pub active_key_pair_id: String,
+    pub key_pairs: Vec<KeyPair>,
+}
+
+impl KeyRing {
+    /// Parse and check a key ring: IDs are unique and the active one is there
+    pub fn parse(json: &str) -> Result<KeyRing, String> {
+        let ring: KeyRing = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid CloudFront key ring: {}", e))?;
+        let mut seen = HashSet::new();
+        if let Some(pair) = ring.key_pairs.iter().find(|p| !seen.insert(p.key_pair_id.as_str())) {
+            return Err(format!("CloudFront key pair {} is listed twice", pair.key_pair_id));
+        }
+        ring.active()?;
+        Ok(ring)
+    }
+
+    /// The key pair cookies are signed with
+    pub fn active(&self) -> Result<&KeyPair, String> {
+        self.key_pairs
+            .iter()
+            .find(|p| p.key_pair_id == self.active_key_pair_id)
+            .ok_or_else(|| format!("Active CloudFront key pair {} is not in the key ring", self.active_key_pair_id))
+    }
+}
+
+static KEY_RING: Mutex<Option<(Instant, Arc<KeyRing>)>> = Mutex::new(None);
+
+/// The key ring to sign with: CLOUDFRONT_KEYS_SECRET's, re-read every
+/// KEY_RING_REFRESH, or else the single pair from CLOUDFRONT_KEY_PAIR_ID and
+/// CLOUDFRONT_PRIVATE_KEY. If the secret can't be read the last ring loaded
+/// is kept, so a Secrets Manager blip doesn't stop sign-ins.
+pub async fn load_key_ring(secrets_client: &SecretsClient) -> Result<Arc<KeyRing>, String> {
+    let Ok(secret_id) = std::env::var(CLOUDFRONT_KEYS_SECRET) else {
+        let key_pair_id = std::env::var(CLOUDFRONT_KEY_PAIR_ID)
+            .map_err(|_| "CLOUDFRONT_KEY_PAIR_ID not set")?;
+        let private_key = std::env::var(CLOUDFRONT_PRIVATE_KEY)
+            .map_err(|_| "CLOUDFRONT_PRIVATE_KEY not set")?;
+        return Ok(Arc::new(KeyRing {
+            active_key_pair_id: key_pair_id.clone(),
+            key_pairs: vec![KeyPair { key_pair_id, private_key }],
+        }));
+    };
+
+    let cached = KEY_RING.lock().map_err(|_| "CloudFront key ring lock poisoned")?.clone();
+    if let Some((loaded, ring)) = &cached {
+        if loaded.elapsed() < KEY_RING_REFRESH {
+            return Ok(Arc::clone(ring));
+        }
+    }
+
+    let fetched = secrets_client
+        .get_secret_value()
+        .secret_id(&secret_id)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to read secret {}: {}", secret_id, e))
+        .and_then(|output| output.secret_string().map(KeyRing::parse).unwrap_or_else(|| Err(format!("Secret {} has no string value", secret_id))));
+    let ring = match (fetched, cached) {
+        (Ok(ring), _) => Arc::new(ring),
+        (Err(e), Some((_, stale))) => {
+            tracing::warn!("Keeping the previous CloudFront key ring: {}", e);
+            stale
+        }
+        (Err(e), None) => return Err(e),
+    };
+    if let Ok(mut slot) = KEY_RING.lock() {
+        *slot = Some((Instant::now(), Arc::clone(&ring)));
+    }
+    Ok(ring)
+}
+
 #[derive(serde::Serialize)]
 struct CloudFrontPolicy {
     #[serde(rename = "Statement")]
@@ -39,14 +138,11 @@ struct DateLessThan {
 
 /// Generate CloudFront signed cookies for the user session
 pub fn generate_signed_cookies(
+    key_pair: &KeyPair,
     duration_seconds: i64,
 ) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
     let domain = std::env::var(CLOUDFRONT_DOMAIN)
         .map_err(|_| "CLOUDFRONT_DOMAIN not set")?;
-    let key_pair_id = std::env::var(CLOUDFRONT_KEY_PAIR_ID)
-        .map_err(|_| "CLOUDFRONT_KEY_PAIR_ID not set")?;
-    let private_key_pem = std::env::var(CLOUDFRONT_PRIVATE_KEY)
-        .map_err(|_| "CLOUDFRONT_PRIVATE_KEY not set")?;
     
     // Calculate expiration time
     let now = SystemTime::now()
@@ -70,7 +166,7 @@ pub fn generate_signed_cookies(
     let policy_json = serde_json::to_string(&policy)?;
     
     // Sign the policy
-    let signature = sign_policy(&policy_json, &private_key_pem)?;
+    let signature = sign_policy(&policy_json, &key_pair.private_key)?;
     
     // Base64-encode policy and signature using CloudFront cookie-safe mapping
     // AWS requires STANDARD base64, then replace: '+' -> '-', '=' -> '_', '/' -> '~'
@@ -90,7 +186,7 @@ pub fn generate_signed_cookies(
     Ok(vec![
         ("CloudFront-Policy".to_string(), policy_b64),
         ("CloudFront-Signature".to_string(), signature_b64),
-        ("CloudFront-Key-Pair-Id".to_string(), key_pair_id),
+        ("CloudFront-Key-Pair-Id".to_string(), key_pair.key_pair_id.clone()),
     ])
 }
 
@@ -140,12 +236,14 @@ pub fn format_cookie_headers(
 }
 
 /// Issue CloudFront signed cookies on successful authentication
-pub fn issue_signed_cookies_response(
+pub async fn issue_signed_cookies_response(
+    secrets_client: &SecretsClient,
     user_id: &str,
     duration_seconds: i64,
     request_origin: Option<&str>,
 ) -> Result<Response<Body>, Error> {
-    let cookies = generate_signed_cookies(duration_seconds)
+    let ring = load_key_ring(secrets_client).await?;
+    let cookies = generate_signed_cookies(ring.active()?, duration_seconds)
         .map_err(|e| format!("Failed to generate signed cookies: {}", e))?;
     
     // Decide cookie Domain
@@ -192,3 +290,31 @@ pub fn issue_signed_cookies_response(
     
     Ok(response)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_ring() {
+        let ring = KeyRing::parse(
+            r#"{"active_key_pair_id": "K2", "key_pairs": [
+                {"key_pair_id": "K2", "private_key": "new"},
+                {"key_pair_id": "K1", "private_key": "old"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(ring.active().unwrap().private_key, "new");
+
+        // The active pair must be in the ring, and only once
+        assert!(KeyRing::parse(r#"{"active_key_pair_id": "K3", "key_pairs": [{"key_pair_id": "K1", "private_key": "old"}]}"#).is_err());
+        assert!(KeyRing::parse(
+            r#"{"active_key_pair_id": "K1", "key_pairs": [
+                {"key_pair_id": "K1", "private_key": "a"},
+                {"key_pair_id": "K1", "private_key": "b"}
+            ]}"#
+        )
+        .is_err());
+        assert!(KeyRing::parse("not json").is_err());
+    }
+}
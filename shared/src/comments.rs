@@ -0,0 +1,499 @@
+use crate::pagination::{self, PageRequest};
+use crate::types::{Comment, CreateCommentRequest, Point, UpdateCommentRequest};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use std::collections::HashMap;
+
+fn comment_from_item(
+    image_id: &str,
+    comment_id: &str,
+    item: &HashMap<String, AttributeValue>,
+) -> Comment {
+    Comment {
+        comment_id: comment_id.to_string(),
+        image_id: image_id.to_string(),
+        annotation_id: item
+            .get("annotation_id")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        anchor: item
+            .get("anchor")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| serde_json::from_str::<Point>(s).ok()),
+        user_id: item
+            .get("user_id")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        text: item
+            .get("text")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        resolved: item
+            .get("resolved")
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(false),
+        created_at: item
+            .get("created_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn error_response(status: StatusCode, message: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": message}).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Where a comment is pinned: the anchor asked for, or else the centre of the
+/// bounds of the annotation it is about. Anchors are image coordinates, so
+/// never negative.
+fn comment_anchor(
+    anchor: Option<Point>,
+    shape_bounds: Option<(Point, Point)>,
+) -> Result<Option<Point>, &'static str> {
+    match anchor {
+        Some(point) if !point.x.is_finite() || !point.y.is_finite() => {
+            Err("Anchor coordinates must be numbers")
+        }
+        Some(point) if point.x < 0.0 || point.y < 0.0 => {
+            Err("Anchor coordinates can't be negative")
+        }
+        Some(point) => Ok(Some(point)),
+        None => Ok(shape_bounds.map(|(min, max)| Point {
+            x: (min.x + max.x) / 2.0,
+            y: (min.y + max.y) / 2.0,
+        })),
+    }
+}
+
+/// Create a comment on an image, optionally anchored to an annotation
+pub async fn create_comment(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: CreateCommentRequest = serde_json::from_slice(body)?;
+
+    if req.text.trim().is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Comment text is required");
+    }
+
+    // The referenced shape must exist on this image
+    let mut shape_bounds = None;
+    if let Some(annotation_id) = &req.annotation_id {
+        let annotation = client
+            .get_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+            .key(
+                "SK",
+                AttributeValue::S(format!("ANNOTATION#{}", annotation_id)),
+            )
+            .send()
+            .await?;
+        let Some(item) = annotation
+            .item()
+            .filter(|item| !crate::annotations::is_deleted(item))
+        else {
+            return error_response(StatusCode::NOT_FOUND, "Annotation not found on this image");
+        };
+        let shape = crate::annotations::annotation_from_item(annotation_id, image_id, item);
+        shape_bounds = crate::geometry::bounds(&shape.geometry);
+    }
+    let anchor = match comment_anchor(req.anchor, shape_bounds) {
+        Ok(anchor) => anchor,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+    };
+
+    let comment_id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut builder = client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .item("SK", AttributeValue::S(format!("COMMENT#{}", comment_id)))
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item("text", AttributeValue::S(req.text.clone()))
+        .item("resolved", AttributeValue::Bool(false))
+        .item("created_at", AttributeValue::S(now.clone()));
+
+    if let Some(annotation_id) = &req.annotation_id {
+        builder = builder.item("annotation_id", AttributeValue::S(annotation_id.clone()));
+    }
+    if let Some(anchor) = &anchor {
+        builder = builder.item("anchor", AttributeValue::S(serde_json::to_string(anchor)?));
+    }
+
+    builder.send().await?;
+
+    let comment = Comment {
+        comment_id,
+        image_id: image_id.to_string(),
+        annotation_id: req.annotation_id,
+        anchor,
+        user_id: user_id.to_string(),
+        text: req.text,
+        resolved: false,
+        created_at: now,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&comment)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Fetch all comments for an image, oldest first
+pub async fn fetch_image_comments(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+) -> Result<Vec<Comment>, Error> {
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        &format!("IMAGE#{}", image_id),
+        "COMMENT#",
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+
+    let mut comments: Vec<Comment> = page
+        .items
+        .iter()
+        .filter_map(|item| {
+            let sk = item.get("SK").and_then(|v| v.as_s().ok())?;
+            let comment_id = sk.strip_prefix("COMMENT#")?;
+            Some(comment_from_item(image_id, comment_id, item))
+        })
+        .collect();
+
+    comments.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    Ok(comments)
+}
+
+/// List comments for an image (optionally only those on one annotation)
+pub async fn list_image_comments(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    annotation_id: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let mut comments = fetch_image_comments(client, table_name, image_id).await?;
+    if let Some(annotation_id) = annotation_id {
+        comments.retain(|c| c.annotation_id.as_deref() == Some(annotation_id));
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&comments)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Who may rewrite or delete a comment: its author, the reviewer of the
+/// block its image is in, and admins. Returns the 404 or 403 to send, or
+/// None when the user may.
+async fn check_comment_author(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    comment_id: &str,
+    user_id: &str,
+) -> Result<Option<Response<Body>>, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .key("SK", AttributeValue::S(format!("COMMENT#{}", comment_id)))
+        .projection_expression("user_id")
+        .send()
+        .await?;
+    let Some(item) = result.item() else {
+        return error_response(StatusCode::NOT_FOUND, "Comment not found").map(Some);
+    };
+    let author = item.get("user_id").and_then(|v| v.as_s().ok());
+    if author.is_some_and(|author| crate::blocks::same_user(author, user_id)) {
+        return Ok(None);
+    }
+
+    if let Some(location) = crate::images::image_location(client, table_name, image_id).await? {
+        let block = crate::blocks::fetch_block(
+            client,
+            table_name,
+            &location.project_id,
+            &location.block_id,
+        )
+        .await?;
+        let reviewer = block.and_then(|block| block.reviewer);
+        if reviewer.is_some_and(|reviewer| crate::blocks::same_user(&reviewer, user_id)) {
+            return Ok(None);
+        }
+    }
+    if crate::users::is_admin(client, table_name, user_id).await? {
+        return Ok(None);
+    }
+    error_response(
+        StatusCode::FORBIDDEN,
+        "Only the comment's author, the block's reviewer or an admin may change it",
+    )
+    .map(Some)
+}
+
+/// Update comment text or resolve/reopen it. Anyone may resolve or reopen;
+/// the text is up to `check_comment_author`.
+pub async fn update_comment(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    comment_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: UpdateCommentRequest = serde_json::from_slice(body)?;
+    let pk = format!("IMAGE#{}", image_id);
+    let sk = format!("COMMENT#{}", comment_id);
+
+    let mut update_expr = vec![];
+    let mut expr_names = HashMap::new();
+    let mut expr_values = HashMap::new();
+
+    if let Some(text) = req.text {
+        update_expr.push("#text = :text");
+        expr_names.insert("#text".to_string(), "text".to_string());
+        expr_values.insert(":text".to_string(), AttributeValue::S(text));
+    }
+
+    if let Some(resolved) = req.resolved {
+        update_expr.push("resolved = :resolved");
+        expr_values.insert(":resolved".to_string(), AttributeValue::Bool(resolved));
+    }
+
+    if update_expr.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Nothing to update");
+    }
+    if expr_values.contains_key(":text") {
+        if let Some(response) =
+            check_comment_author(client, table_name, image_id, comment_id, user_id).await?
+        {
+            return Ok(response);
+        }
+    }
+
+    let mut builder = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk))
+        .key("SK", AttributeValue::S(sk))
+        .update_expression(format!("SET {}", update_expr.join(", ")))
+        .condition_expression("attribute_exists(PK)")
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew);
+
+    for (k, v) in expr_names {
+        builder = builder.expression_attribute_names(k, v);
+    }
+
+    for (k, v) in expr_values {
+        builder = builder.expression_attribute_values(k, v);
+    }
+
+    match builder.send().await {
+        Ok(output) => {
+            let comment = comment_from_item(
+                image_id,
+                comment_id,
+                output.attributes().unwrap_or(&HashMap::new()),
+            );
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(serde_json::to_string(&comment)?.into())
+                .map_err(Box::new)?)
+        }
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            error_response(StatusCode::NOT_FOUND, "Comment not found")
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Delete a comment, if `check_comment_author` lets the user
+pub async fn delete_comment(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    comment_id: &str,
+) -> Result<Response<Body>, Error> {
+    if let Some(response) =
+        check_comment_author(client, table_name, image_id, comment_id, user_id).await?
+    {
+        return Ok(response);
+    }
+
+    let result = client
+        .delete_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .key("SK", AttributeValue::S(format!("COMMENT#{}", comment_id)))
+        .condition_expression("attribute_exists(PK)")
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header("Access-Control-Allow-Origin", "*")
+            .body(Body::Empty)
+            .map_err(Box::new)?),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            error_response(StatusCode::NOT_FOUND, "Comment not found")
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Annotations and review comments for an image in one round trip
+/// (GET /images/{id}/bundle)
+pub async fn get_image_bundle(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+) -> Result<Response<Body>, Error> {
+    let annotations =
+        crate::annotations::fetch_image_annotations(client, table_name, image_id).await?;
+    let comments = fetch_image_comments(client, table_name, image_id).await?;
+
+    let bundle = serde_json::json!({
+        "image_id": image_id,
+        "annotations": annotations,
+        "comments": comments,
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(bundle.to_string().into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_comment_anchor() {
+        let bounds = || Some((point(10.0, 20.0), point(30.0, 60.0)));
+
+        // An explicit anchor wins over the shape
+        let anchor = comment_anchor(Some(point(5.0, 5.0)), bounds())
+            .unwrap()
+            .unwrap();
+        assert_eq!((anchor.x, anchor.y), (5.0, 5.0));
+
+        // Without one, a comment on a shape is pinned at its centre
+        let anchor = comment_anchor(None, bounds()).unwrap().unwrap();
+        assert_eq!((anchor.x, anchor.y), (20.0, 40.0));
+
+        // Comments on the image as a whole stay unpinned
+        assert!(comment_anchor(None, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_comment_anchor_validation() {
+        assert!(comment_anchor(Some(point(-1.0, 5.0)), None).is_err());
+        assert!(comment_anchor(Some(point(5.0, -0.5)), None).is_err());
+        assert!(comment_anchor(Some(point(f64::NAN, 5.0)), None).is_err());
+        assert!(comment_anchor(Some(point(5.0, f64::INFINITY)), None).is_err());
+        // The image's own corner is a fine place for a pin
+        assert!(comment_anchor(Some(point(0.0, 0.0)), None).is_ok());
+    }
+
+    fn comment(image_id: &str, comment_id: &str, author: &str) -> serde_json::Value {
+        serde_json::json!({
+            "PK": { "S": format!("IMAGE#{}", image_id) },
+            "SK": { "S": format!("COMMENT#{}", comment_id) },
+            "user_id": { "S": author },
+            "text": { "S": "Check this corner" },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_only_author_or_reviewer_changes_comment() {
+        let mut block = crate::test_util::assigned_block("p", "b", "u1");
+        block["reviewer"] = serde_json::json!({ "S": "USER#r" });
+        let (client, calls) = crate::test_util::fake_dynamo(vec![
+            comment("img", "c", "u1"),
+            crate::test_util::image_in_block("p", "b", "img"),
+            block,
+        ]);
+
+        let body = br#"{"text": "Not mine"}"#;
+        let response = update_comment(&client, "table", "u2", "img", "c", body)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = delete_comment(&client, "table", "u2", "img", "c")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // Comment, location, block and the admin lookup, twice; nothing written
+        assert_eq!(calls.lock().unwrap().len(), 8);
+        assert!(calls.lock().unwrap().iter().all(|op| op == "GetItem"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_missing_comment() {
+        let (client, calls) = crate::test_util::fake_dynamo(Vec::new());
+        let response = delete_comment(&client, "table", "u1", "img", "c")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(*calls.lock().unwrap(), ["GetItem"]);
+    }
+
+    #[tokio::test]
+    async fn test_comment_on_deleted_annotation() {
+        let (client, calls) = crate::test_util::fake_dynamo(vec![serde_json::json!({
+            "PK": { "S": "IMAGE#img" },
+            "SK": { "S": "ANNOTATION#a" },
+            "deleted_at": { "S": "2024-01-01T00:00:00Z" },
+        })]);
+        let body = br#"{"text": "Too late", "annotation_id": "a"}"#;
+        let response = create_comment(&client, "table", "u1", "img", body)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(*calls.lock().unwrap(), ["GetItem"]);
+    }
+}
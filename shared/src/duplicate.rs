@@ -0,0 +1,364 @@
+use crate::audit::AuditEntry;
+use crate::images::ImageLocation;
+use crate::pagination::{self, PageRequest};
+use crate::types::Block;
+use crate::{annotations, block_stats, blocks, images, region, s3_multipart, storage};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// `?include_images=&include_annotations=` of a duplication
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DuplicateOptions<'a> {
+    pub include_images: Option<&'a str>,      // default true
+    pub include_annotations: Option<&'a str>, // default true when images are copied
+}
+
+impl DuplicateOptions<'_> {
+    /// Whether images and annotations are copied. Annotations need their
+    /// images, so leaving the images out leaves them out too unless asked.
+    fn resolve(&self) -> Result<(bool, bool), String> {
+        let flag = |value: Option<&str>, name: &str| match value {
+            None => Ok(None),
+            Some("true") => Ok(Some(true)),
+            Some("false") => Ok(Some(false)),
+            Some(_) => Err(format!("{} must be true or false", name)),
+        };
+        let images = flag(self.include_images, "include_images")?.unwrap_or(true);
+        match flag(self.include_annotations, "include_annotations")? {
+            Some(true) if !images => {
+                Err("include_annotations needs include_images=true".to_string())
+            }
+            annotations => Ok((images, annotations.unwrap_or(images))),
+        }
+    }
+}
+
+/// An image that couldn't be copied
+#[derive(Debug, Serialize)]
+pub struct SkippedImage {
+    pub image_id: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DuplicateReport {
+    pub source_block_id: String,
+    pub block: Block,
+    pub images_copied: usize,
+    pub annotations_copied: usize,
+    pub skipped_images: Vec<SkippedImage>,
+}
+
+/// Key of a copied image's object, in the new block's folder and keeping the
+/// original's extension
+fn copy_key(key: &str, project_id: &str, block_id: &str, image_id: &str) -> String {
+    let file = key.rsplit('/').next().unwrap_or(key);
+    let extension = file
+        .rsplit_once('.')
+        .map(|(_, ext)| format!(".{}", ext))
+        .unwrap_or_default();
+    format!(
+        "{}{}{}",
+        storage::block_prefix(project_id, block_id),
+        image_id,
+        extension
+    )
+}
+
+fn bad_request(message: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({ "error": message }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Copy an image's object to `key`, returning the URL to store for it.
+/// Images stored outside this service's bucket are shared, not copied.
+async fn copy_object(s3_client: &S3Client, url: &str, key: &str) -> Result<String, Error> {
+    let Some(source) = storage::object_key(url) else {
+        return Ok(url.to_string());
+    };
+    s3_client
+        .copy_object()
+        .bucket(region::bucket_name())
+        .copy_source(storage::copy_source(region::bucket_name(), &source))
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| format!("S3 copy failed: {}", e))?;
+    Ok(storage::object_url(key))
+}
+
+/// An image copied into another block, with the item stored for it
+pub(crate) struct CopiedImage {
+    pub image_id: String,
+    pub item: HashMap<String, AttributeValue>,
+    pub annotations_copied: usize,
+}
+
+/// Copy an image (its stored item) into `location`'s block as a new image
+/// with its own S3 objects. With `annotation_classes` its annotations come along
+/// as new annotations, on the classes it maps them to (by old id; others
+/// keep theirs). Err(reason) when its object couldn't be copied, with
+/// nothing written.
+pub(crate) async fn copy_image(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    mut item: HashMap<String, AttributeValue>,
+    location: &ImageLocation,
+    annotation_classes: Option<&HashMap<String, String>>,
+) -> Result<Result<CopiedImage, String>, Error> {
+    let Some(source_id) = item
+        .get("SK")
+        .and_then(|v| v.as_s().ok())
+        .and_then(|sk| sk.strip_prefix("IMAGE#"))
+        .map(|id| id.to_string())
+    else {
+        return Ok(Err("Not an image".to_string()));
+    };
+    let url = item
+        .get("url")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+    let image_id = uuid::Uuid::new_v4().to_string();
+    let key = copy_key(
+        &storage::object_key(&url).unwrap_or_default(),
+        &location.project_id,
+        &location.block_id,
+        &image_id,
+    );
+    let url = match copy_object(s3_client, &url, &key).await {
+        Ok(url) => url,
+        Err(e) => return Ok(Err(e.to_string())),
+    };
+    // The thumbnail is copied too; the copy goes without one if that fails
+    let thumbnail_url = match item.remove("thumbnail_url") {
+        Some(AttributeValue::S(thumbnail)) => {
+            let key =
+                s3_multipart::thumbnail_key(&location.project_id, &location.block_id, &image_id);
+            copy_object(s3_client, &thumbnail, &key)
+                .await
+                .inspect_err(|e| tracing::warn!("Copying image {}: thumbnail: {}", source_id, e))
+                .ok()
+        }
+        _ => None,
+    };
+    // Tiles stay with the source image; the copy is viewed without them
+    item.remove("tiles_url");
+    let mut sources = Vec::new();
+    let mut status = "unannotated".to_string();
+    if let Some(class_ids) = annotation_classes {
+        sources = annotations::fetch_image_annotations(client, table_name, &source_id).await?;
+        for source in &mut sources {
+            if let Some(class_id) = class_ids.get(&source.class_id) {
+                source.class_id = class_id.clone();
+            }
+        }
+        if let Some(copied) = item.get("status").and_then(|v| v.as_s().ok()) {
+            status = copied.clone();
+        }
+    }
+
+    // Everything else about the image (order, calibration, size, ...)
+    // carries over; the copy starts unlocked and counts its own annotations
+    item.remove("annotation_count");
+    let now = chrono::Utc::now().to_rfc3339();
+    for (name, value) in [
+        (
+            "PK",
+            AttributeValue::S(format!("BLOCK#{}", location.block_id)),
+        ),
+        ("SK", AttributeValue::S(format!("IMAGE#{}", image_id))),
+        ("url", AttributeValue::S(url)),
+        ("uploaded_at", AttributeValue::S(now)),
+        ("locked", AttributeValue::Bool(false)),
+        ("status", AttributeValue::S(status.clone())),
+    ] {
+        item.insert(name.to_string(), value);
+    }
+    if let Some(thumbnail_url) = thumbnail_url {
+        item.insert(
+            "thumbnail_url".to_string(),
+            AttributeValue::S(thumbnail_url),
+        );
+    }
+    client
+        .put_item()
+        .table_name(table_name)
+        .set_item(Some(item.clone()))
+        .send()
+        .await?;
+    images::put_image_location(client, table_name, &image_id, location).await?;
+    let counted = [(block_stats::status_counter(&status), 1)];
+    block_stats::increment(client, table_name, &location.block_id, &counted).await;
+
+    let mut annotations_copied = 0;
+    if !sources.is_empty() {
+        let copies = annotations::copy_annotations(
+            client, table_name, user_id, &sources, &image_id, location,
+        )
+        .await?;
+        annotations_copied = copies.len();
+    }
+    Ok(Ok(CopiedImage {
+        image_id,
+        item,
+        annotations_copied,
+    }))
+}
+
+/// Deep-copy a block into a new draft block of the same project
+/// (POST /projects/{pid}/blocks/{bid}/duplicate?include_images=
+/// &include_annotations=), for review copies and re-annotation experiments.
+/// Images get new ids and their own S3 objects; annotations are copied as new
+/// annotations. Images that fail to copy are reported and left out.
+pub async fn duplicate_block(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+    options: DuplicateOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    let (include_images, include_annotations) = match options.resolve() {
+        Ok(included) => included,
+        Err(message) => return bad_request(&message),
+    };
+    let Some(source) = blocks::fetch_block(client, table_name, project_id, block_id).await? else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "Block not found"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?);
+    };
+
+    let mut block = blocks::new_block(project_id, format!("{} (copy)", source.name));
+    blocks::put_block(client, table_name, &block).await?;
+    let location = ImageLocation {
+        project_id: project_id.to_string(),
+        block_id: block.block_id.clone(),
+    };
+
+    let source_images = if include_images {
+        pagination::query_prefix(
+            client,
+            table_name,
+            &format!("BLOCK#{}", block_id),
+            "IMAGE#",
+            None,
+            &PageRequest::default(),
+        )
+        .await?
+        .items
+    } else {
+        Vec::new()
+    };
+    let mut images_copied = 0;
+    let mut annotations_copied = 0;
+    let mut skipped_images = Vec::new();
+    let same_classes = HashMap::new();
+    for item in source_images {
+        let source_id = item
+            .get("SK")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|sk| sk.strip_prefix("IMAGE#"))
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        match copy_image(
+            client,
+            s3_client,
+            table_name,
+            user_id,
+            item,
+            &location,
+            include_annotations.then_some(&same_classes),
+        )
+        .await?
+        {
+            Ok(copied) => {
+                images_copied += 1;
+                annotations_copied += copied.annotations_copied;
+            }
+            Err(reason) => {
+                tracing::warn!(
+                    "Duplicating block {}: image {}: {}",
+                    block_id,
+                    source_id,
+                    reason
+                );
+                skipped_images.push(SkippedImage {
+                    image_id: source_id,
+                    reason,
+                });
+            }
+        }
+    }
+    block.annotation_count = annotations_copied as u32;
+
+    let copied = serde_json::json!({"block": &block, "duplicated_from": block_id});
+    let entry =
+        AuditEntry::new(project_id, user_id, "created", "block", &block.block_id).after(&copied);
+    crate::audit::record(client, table_name, entry).await;
+
+    let report = DuplicateReport {
+        source_block_id: block_id.to_string(),
+        block,
+        images_copied,
+        annotations_copied,
+        skipped_images,
+    };
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&report)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_options() {
+        let resolve = |images: Option<&'static str>, annotations: Option<&'static str>| {
+            DuplicateOptions {
+                include_images: images,
+                include_annotations: annotations,
+            }
+            .resolve()
+        };
+        assert_eq!(resolve(None, None), Ok((true, true)));
+        assert_eq!(resolve(None, Some("false")), Ok((true, false)));
+        assert_eq!(resolve(Some("false"), None), Ok((false, false)));
+        assert!(resolve(Some("false"), Some("true")).is_err());
+        assert!(resolve(Some("yes"), None).is_err());
+    }
+
+    #[test]
+    fn test_copy_key() {
+        assert_eq!(
+            copy_key("projects/p/blocks/b/i1.png", "p", "b2", "i2"),
+            "projects/p/blocks/b2/i2.png"
+        );
+        assert_eq!(
+            copy_key("projects/p.v2/blocks/b/scan", "p", "b2", "i2"),
+            "projects/p/blocks/b2/i2"
+        );
+    }
+}
@@ -1,9 +1,50 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
 use aws_sdk_sesv2::Client as SesClient;
 use aws_sdk_sesv2::types::{Body, Content, Destination, EmailContent, Message};
 
-/// Send invite email via AWS SES
+/// Captured emails expire after a week
+const CAPTURE_TTL_DAYS: i64 = 7;
+
+/// How outgoing email is delivered, from EMAIL_MODE
+#[derive(Debug, PartialEq)]
+pub enum EmailMode {
+    /// Deliver via SES (production)
+    Send,
+    /// Store in DynamoDB instead of sending (staging with unverified SES identities)
+    Capture,
+    /// Send everything to one inbox (EMAIL_REDIRECT_TO)
+    Redirect(String),
+}
+
+impl EmailMode {
+    pub fn parse(mode: Option<&str>, redirect_to: Option<&str>) -> Self {
+        match mode.map(|m| m.trim().to_lowercase()).as_deref() {
+            Some("capture") => EmailMode::Capture,
+            Some("redirect") => match redirect_to.map(str::trim).filter(|a| !a.is_empty()) {
+                Some(address) => EmailMode::Redirect(address.to_string()),
+                None => {
+                    tracing::warn!("EMAIL_MODE=redirect without EMAIL_REDIRECT_TO; capturing instead");
+                    EmailMode::Capture
+                }
+            },
+            _ => EmailMode::Send,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::parse(
+            std::env::var("EMAIL_MODE").ok().as_deref(),
+            std::env::var("EMAIL_REDIRECT_TO").ok().as_deref(),
+        )
+    }
+}
+
+/// Send invite email via AWS SES (or capture it, depending on EMAIL_MODE)
 pub async fn send_invite_email(
     ses_client: &SesClient,
+    dynamo_client: &DynamoClient,
+    table_name: &str,
     to_email: &str,
     invite_code: &str,
     frontend_url: &str,
@@ -163,12 +204,79 @@ This invitation expires in 7 days. If you didn't expect this, you can safely ign
         signup_link, invite_code
     );
 
+    deliver_email(
+        ses_client,
+        dynamo_client,
+        table_name,
+        to_email,
+        "You've been invited to join Doxle",
+        &html_body,
+        &text_body,
+    )
+    .await
+}
+
+/// Store an email in DynamoDB instead of sending it
+async fn capture_email(
+    dynamo_client: &DynamoClient,
+    table_name: &str,
+    to_email: &str,
+    subject: &str,
+    html_body: &str,
+    text_body: &str,
+) -> Result<(), String> {
+    let now = chrono::Utc::now();
+    let email_id = uuid::Uuid::new_v4().to_string();
+    let ttl = (now + chrono::Duration::days(CAPTURE_TTL_DAYS)).timestamp();
+
+    dynamo_client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S("EMAIL_CAPTURE".to_string()))
+        .item("SK", AttributeValue::S(format!("EMAIL#{}#{}", now.to_rfc3339(), email_id)))
+        .item("email_id", AttributeValue::S(email_id))
+        .item("to", AttributeValue::S(to_email.to_string()))
+        .item("subject", AttributeValue::S(subject.to_string()))
+        .item("html_body", AttributeValue::S(html_body.to_string()))
+        .item("text_body", AttributeValue::S(text_body.to_string()))
+        .item("captured_at", AttributeValue::S(now.to_rfc3339()))
+        .item("ttl", AttributeValue::N(ttl.to_string()))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to capture email: {:?}", e))?;
+
+    tracing::info!("Email to {} captured (EMAIL_MODE=capture): {}", to_email, subject);
+    Ok(())
+}
+
+/// Deliver an email according to EMAIL_MODE
+async fn deliver_email(
+    ses_client: &SesClient,
+    dynamo_client: &DynamoClient,
+    table_name: &str,
+    to_email: &str,
+    subject: &str,
+    html_body: &str,
+    text_body: &str,
+) -> Result<(), String> {
+    let (recipient, subject) = match EmailMode::from_env() {
+        EmailMode::Send => (to_email.to_string(), subject.to_string()),
+        EmailMode::Capture => {
+            return capture_email(dynamo_client, table_name, to_email, subject, html_body, text_body)
+                .await;
+        }
+        EmailMode::Redirect(address) => {
+            tracing::info!("Redirecting email for {} to {}", to_email, address);
+            (address, format!("[to: {}] {}", to_email, subject))
+        }
+    };
+
     let destination = Destination::builder()
-        .to_addresses(to_email)
+        .to_addresses(recipient)
         .build();
 
     let subject = Content::builder()
-        .data("You've been invited to join Doxle")
+        .data(subject)
         .charset("UTF-8")
         .build()
         .map_err(|e| format!("Failed to build subject: {:?}", e))?;
@@ -213,3 +321,64 @@ This invitation expires in 7 days. If you didn't expect this, you can safely ign
 
     Ok(())
 }
+
+/// List recently captured emails, newest first (GET /admin/emails)
+pub async fn list_captured_emails(
+    dynamo_client: &DynamoClient,
+    table_name: &str,
+) -> Result<lambda_http::Response<lambda_http::Body>, lambda_http::Error> {
+    let result = dynamo_client
+        .query()
+        .table_name(table_name)
+        .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
+        .expression_attribute_values(":pk", AttributeValue::S("EMAIL_CAPTURE".to_string()))
+        .expression_attribute_values(":sk_prefix", AttributeValue::S("EMAIL#".to_string()))
+        .scan_index_forward(false)
+        .limit(50)
+        .send()
+        .await?;
+
+    let field = |item: &std::collections::HashMap<String, AttributeValue>, name: &str| {
+        item.get(name).and_then(|v| v.as_s().ok()).cloned().unwrap_or_default()
+    };
+
+    let emails: Vec<serde_json::Value> = result
+        .items()
+        .iter()
+        .map(|item| {
+            serde_json::json!({
+                "email_id": field(item, "email_id"),
+                "to": field(item, "to"),
+                "subject": field(item, "subject"),
+                "text_body": field(item, "text_body"),
+                "html_body": field(item, "html_body"),
+                "captured_at": field(item, "captured_at"),
+            })
+        })
+        .collect();
+
+    Ok(lambda_http::Response::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&emails)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_mode_parse() {
+        assert_eq!(EmailMode::parse(None, None), EmailMode::Send);
+        assert_eq!(EmailMode::parse(Some("send"), None), EmailMode::Send);
+        assert_eq!(EmailMode::parse(Some("CAPTURE"), None), EmailMode::Capture);
+        assert_eq!(
+            EmailMode::parse(Some("redirect"), Some("qa@doxle.ai")),
+            EmailMode::Redirect("qa@doxle.ai".to_string())
+        );
+        // Redirect without a target never falls through to real recipients
+        assert_eq!(EmailMode::parse(Some("redirect"), Some(" ")), EmailMode::Capture);
+    }
+}
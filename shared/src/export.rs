@@ -0,0 +1,2026 @@
+use crate::types::{Annotation, Block, Class, Geometry, Image, Keypoint, Point, Skeleton};
+use crate::{
+    annotations, blocks, classes, export_jobs, geometry, image_processing, images, region, storage,
+};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+/// Presigned download links stay valid for an hour
+pub(crate) const DOWNLOAD_URL_TTL_SECS: u64 = 3600;
+
+/// Supported values for `?format=`
+pub const FORMATS: [&str; 5] = ["coco", "yolo", "csv", "crops", "huggingface"];
+
+/// Headers are read from the first bytes of an image to get its size
+const HEADER_RANGE_BYTES: usize = 512 * 1024;
+
+/// Supported values for `?images=`: bundle full-resolution sources, or the
+/// smaller preview level where the upload has one
+pub const IMAGE_BUNDLES: [&str; 2] = ["original", "preview"];
+
+/// Dataset splits, in the order `?split=` ratios are given
+pub const SPLIT_NAMES: [&str; 3] = ["train", "val", "test"];
+
+/// Split folder names the Hugging Face imagefolder loader recognises
+const IMAGEFOLDER_SPLITS: [&str; 3] = ["train", "validation", "test"];
+
+/// Datasets pushed into our own bucket go under this prefix, away from
+/// project images and export archives
+const DATASETS_PREFIX: &str = "datasets/";
+
+/// Query options of an export request
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExportOptions<'a> {
+    pub format: Option<&'a str>,
+    pub split: Option<&'a str>,   // train/val/test ratios, e.g. "80/10/10"
+    pub seed: Option<&'a str>,    // makes a split reproducible
+    pub classes: Option<&'a str>, // comma-separated class ids
+    pub block_state: Option<&'a str>, // comma-separated block states
+    pub created_after: Option<&'a str>, // RFC 3339; annotations created after it
+    pub images: Option<&'a str>,  // original | preview: put pixels in the archive too
+    pub destination: Option<&'a str>, // s3://bucket/prefix a huggingface export is written to
+    pub include_rejected: Option<&'a str>, // "true" keeps annotations rejected in review
+    pub source: Option<&'a str>,  // human | model
+    pub min_confidence: Option<&'a str>, // drops model annotations scored below it
+    pub image_status: Option<&'a str>, // comma-separated image statuses
+}
+
+/// Which blocks and annotations an export includes. Unset fields keep everything.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExportFilter {
+    pub classes: Option<Vec<String>>,
+    pub block_states: Option<Vec<String>>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub include_rejected: bool,
+    pub source: Option<String>,
+    pub min_confidence: Option<f32>,
+    pub image_statuses: Option<Vec<String>>,
+}
+
+fn comma_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+        .collect()
+}
+
+impl ExportFilter {
+    pub fn parse(options: &ExportOptions) -> Result<Self, String> {
+        let block_states = options.block_state.map(comma_list);
+        if let Some(state) = block_states
+            .iter()
+            .flatten()
+            .find(|s| !blocks::BLOCK_STATES.contains(&s.as_str()))
+        {
+            return Err(format!(
+                "Unknown block_state '{}', expected one of: {}",
+                state,
+                blocks::BLOCK_STATES.join(", ")
+            ));
+        }
+        let image_statuses = options.image_status.map(comma_list);
+        if let Some(status) = image_statuses
+            .iter()
+            .flatten()
+            .find(|s| !images::IMAGE_STATUSES.contains(&s.as_str()))
+        {
+            return Err(format!(
+                "Unknown image_status '{}', expected one of: {}",
+                status,
+                images::IMAGE_STATUSES.join(", ")
+            ));
+        }
+        let created_after = options
+            .created_after
+            .map(|t| {
+                chrono::DateTime::parse_from_rfc3339(t)
+                    .map(|t| t.with_timezone(&chrono::Utc))
+                    .map_err(|_| "created_after must be an RFC 3339 timestamp".to_string())
+            })
+            .transpose()?;
+        if let Some(source) = options
+            .source
+            .filter(|s| !annotations::ANNOTATION_SOURCES.contains(s))
+        {
+            return Err(format!(
+                "Unknown source '{}', expected one of: {}",
+                source,
+                annotations::ANNOTATION_SOURCES.join(", ")
+            ));
+        }
+        let min_confidence = options
+            .min_confidence
+            .map(|c| {
+                c.parse::<f32>()
+                    .map_err(|_| "min_confidence must be a number".to_string())
+            })
+            .transpose()?;
+        Ok(ExportFilter {
+            classes: options.classes.map(comma_list),
+            block_states,
+            created_after,
+            include_rejected: options.include_rejected == Some("true"),
+            source: options.source.map(|s| s.to_string()),
+            min_confidence,
+            image_statuses,
+        })
+    }
+
+    fn keeps_block(&self, block: &Block) -> bool {
+        self.block_states
+            .as_ref()
+            .is_none_or(|states| states.contains(&block.state))
+    }
+
+    /// `?image_status=no_objects` exports negative samples: images kept with
+    /// no annotations
+    fn keeps_image(&self, image: &Image) -> bool {
+        self.image_statuses
+            .as_ref()
+            .is_none_or(|statuses| statuses.contains(&image.status))
+    }
+
+    fn keeps_annotation(&self, annotation: &Annotation) -> bool {
+        let class_ok = self
+            .classes
+            .as_ref()
+            .is_none_or(|classes| classes.contains(&annotation.class_id));
+        // Annotations with an unreadable timestamp can't be shown to be recent
+        let time_ok = self.created_after.is_none_or(|after| {
+            chrono::DateTime::parse_from_rfc3339(&annotation.created_at)
+                .is_ok_and(|created| created > after)
+        });
+        let review_ok = self.include_rejected || annotation.review_status != "rejected";
+        let source_ok = self
+            .source
+            .as_ref()
+            .is_none_or(|source| *source == annotation.source);
+        // Human annotations carry no confidence and always pass
+        let confidence_ok = self
+            .min_confidence
+            .is_none_or(|min| annotation.confidence.is_none_or(|c| c >= min));
+        class_ok && time_ok && review_ok && source_ok && confidence_ok
+    }
+}
+
+/// An image together with everything exported for it
+pub struct ExportImage {
+    pub image: Image,
+    pub annotations: Vec<Annotation>,
+}
+
+/// A finished archive, uploaded to S3, or one the image worker is still
+/// building (see `export_jobs`)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportResult {
+    pub export_id: String,
+    pub status: String,        // ready | processing | failed
+    pub error: Option<String>, // why it failed
+    pub project_id: String,
+    pub block_id: Option<String>,
+    pub format: String,
+    pub download_url: Option<String>, // archive formats
+    pub expires_in: Option<u64>,
+    pub destination: Option<String>, // huggingface: s3:// URI of the dataset
+    pub image_count: usize,
+    pub annotation_count: usize,
+    pub skipped: Vec<SkippedAnnotation>,
+    pub split: Option<SplitSummary>,
+    pub bundled_images: usize,
+    pub skipped_images: Vec<SkippedImage>, // images left out of the bundle
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkippedImage {
+    pub image_id: String,
+    pub reason: String,
+}
+
+/// How images were partitioned, written to `splits/` in the archive
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SplitSummary {
+    pub seed: u64,
+    pub ratios: [f64; 3], // fractions, summing to 1
+    pub train: usize,
+    pub val: usize,
+    pub test: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkippedAnnotation {
+    pub annotation_id: String,
+    pub reason: String,
+}
+
+/// Gather the images and annotations of one block, or every block in the
+/// project, that pass the filter
+async fn collect_images(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: Option<&str>,
+    filter: &ExportFilter,
+) -> Result<Vec<ExportImage>, Error> {
+    let block_ids = match block_id {
+        Some(block_id) if filter.block_states.is_none() => vec![block_id.to_string()],
+        _ => blocks::fetch_project_blocks(client, table_name, project_id)
+            .await?
+            .into_iter()
+            .filter(|b| block_id.is_none_or(|id| id == b.block_id) && filter.keeps_block(b))
+            .map(|b| b.block_id)
+            .collect(),
+    };
+
+    let mut collected = Vec::new();
+    for block_id in &block_ids {
+        for image in images::fetch_block_images(client, table_name, block_id)
+            .await?
+            .into_iter()
+            .filter(|i| filter.keeps_image(i))
+        {
+            let mut annotations =
+                annotations::fetch_image_annotations(client, table_name, &image.image_id).await?;
+            annotations.retain(|a| filter.keeps_annotation(a));
+            collected.push(ExportImage { image, annotations });
+        }
+    }
+    Ok(collected)
+}
+
+async fn get_object_bytes(s3_client: &S3Client, key: &str) -> Result<Vec<u8>, String> {
+    let result = s3_client
+        .get_object()
+        .bucket(region::bucket_name())
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", key, e))?;
+    Ok(result
+        .body
+        .collect()
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", key, e))?
+        .into_bytes()
+        .to_vec())
+}
+
+/// Pyramid folder of an upload, next to where its flat file was: `{key without extension}`
+fn pyramid_base(key: &str) -> &str {
+    key.rsplit_once('.').map(|(base, _)| base).unwrap_or(key)
+}
+
+/// metadata.json written when an upload is split into a pyramid
+async fn load_metadata(
+    s3_client: &S3Client,
+    image: &Image,
+    base: &str,
+) -> Result<crate::types::ImageMetadata, String> {
+    let metadata = get_object_bytes(s3_client, &format!("{}/metadata.json", base)).await?;
+    serde_json::from_slice(&metadata)
+        .map_err(|e| format!("Invalid metadata for {}: {}", image.image_id, e))
+}
+
+/// Download the full-resolution source of an image. Large uploads are moved
+/// into a pyramid folder after processing, so fall back to its metadata.json.
+pub async fn load_source_image(s3_client: &S3Client, image: &Image) -> Result<Vec<u8>, String> {
+    let key = storage::object_key(&image.url)
+        .ok_or_else(|| format!("Unrecognised image URL: {}", image.url))?;
+    match get_object_bytes(s3_client, &key).await {
+        Ok(bytes) => Ok(bytes),
+        Err(e) => {
+            let base = pyramid_base(&key);
+            let metadata = load_metadata(s3_client, image, base).await.map_err(|_| e)?;
+            let full = metadata
+                .levels
+                .iter()
+                .find(|l| l.purpose == "full")
+                .ok_or_else(|| format!("No full resolution level for {}", image.image_id))?;
+            get_object_bytes(s3_client, &format!("{}/{}", base, full.path)).await
+        }
+    }
+}
+
+/// Pixel size of an image. Uses the size recorded on the image when there is
+/// one, and otherwise reads only the header where possible; pyramid images
+/// (whose flat upload was moved) use their metadata.json.
+pub async fn image_dimensions(s3_client: &S3Client, image: &Image) -> Result<(u32, u32), String> {
+    if let Some(size) = image.width.zip(image.height) {
+        return Ok(size);
+    }
+    let key = storage::object_key(&image.url)
+        .ok_or_else(|| format!("Unrecognised image URL: {}", image.url))?;
+
+    let header = s3_client
+        .get_object()
+        .bucket(region::bucket_name())
+        .key(&key)
+        .range(format!("bytes=0-{}", HEADER_RANGE_BYTES - 1))
+        .send()
+        .await;
+    if let Ok(header) = header {
+        let bytes = header
+            .body
+            .collect()
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", key, e))?
+            .into_bytes();
+        let dimensions = image::io::Reader::new(Cursor::new(&bytes))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok());
+        return match dimensions {
+            Some(dimensions) => Ok(dimensions),
+            // Header larger than the range (e.g. big EXIF blocks)
+            None => image_processing::get_dimensions(&get_object_bytes(s3_client, &key).await?),
+        };
+    }
+
+    let metadata = load_metadata(s3_client, image, pyramid_base(&key)).await?;
+    Ok((metadata.original_width, metadata.original_height))
+}
+
+async fn open_object(s3_client: &S3Client, key: &str) -> Result<GetObjectOutput, String> {
+    s3_client
+        .get_object()
+        .bucket(region::bucket_name())
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", key, e))
+}
+
+/// Open the file bundled for an image as (file name, object), without
+/// reading it: the source at `original`, or its preview level (uploads small
+/// enough to have no pyramid are their own preview). Previews keep their own
+/// extension, and labels stay in full-resolution pixels either way.
+pub(crate) async fn open_bundle_image(
+    s3_client: &S3Client,
+    image: &Image,
+    level: &str,
+) -> Result<(String, GetObjectOutput), String> {
+    let key = storage::object_key(&image.url)
+        .ok_or_else(|| format!("Unrecognised image URL: {}", image.url))?;
+    let base = pyramid_base(&key);
+    if level == "preview" {
+        let preview = load_metadata(s3_client, image, base)
+            .await
+            .ok()
+            .and_then(|m| m.levels.into_iter().find(|l| l.purpose == "preview"));
+        if let Some(level) = preview {
+            let extension = level
+                .path
+                .rsplit_once('.')
+                .map(|(_, ext)| ext)
+                .unwrap_or("png");
+            let object = open_object(s3_client, &format!("{}/{}", base, level.path)).await?;
+            return Ok((format!("{}.{}", image.image_id, extension), object));
+        }
+    }
+    // As in `load_source_image`
+    let object = match open_object(s3_client, &key).await {
+        Ok(object) => object,
+        Err(e) => {
+            let metadata = load_metadata(s3_client, image, base).await.map_err(|_| e)?;
+            let full = metadata
+                .levels
+                .iter()
+                .find(|l| l.purpose == "full")
+                .ok_or_else(|| format!("No full resolution level for {}", image.image_id))?;
+            open_object(s3_client, &format!("{}/{}", base, full.path)).await?
+        }
+    };
+    Ok((image_file_name(image), object))
+}
+
+/// Scale annotations stored in normalized (0–1) coordinates to pixels of
+/// their image, refreshing the derived area and bounding box
+pub async fn denormalize(
+    s3_client: &S3Client,
+    image: &Image,
+    annotations: &mut [Annotation],
+) -> Result<(), String> {
+    if annotations.is_empty() {
+        return Ok(());
+    }
+    let (width, height) = image_dimensions(s3_client, image).await?;
+    for annotation in annotations {
+        geometry::scale(&mut annotation.geometry, width as f64, height as f64)?;
+        annotation.area = geometry::area(&annotation.geometry);
+        annotation.bounding_box = geometry::bounding_box(&annotation.geometry);
+    }
+    Ok(())
+}
+
+/// Bring a normalized project's annotations into pixels before exporting.
+/// Images that can't be sized are exported without annotations.
+async fn denormalize_all(
+    s3_client: &S3Client,
+    collected: &mut [ExportImage],
+    skipped: &mut Vec<SkippedAnnotation>,
+) {
+    for export in collected {
+        if let Err(e) = denormalize(s3_client, &export.image, &mut export.annotations).await {
+            tracing::warn!(
+                "Export: cannot convert image {} to pixels: {}",
+                export.image.image_id,
+                e
+            );
+            for annotation in export.annotations.drain(..) {
+                skipped.push(SkippedAnnotation {
+                    annotation_id: annotation.annotation_id,
+                    reason: "Image dimensions unavailable".to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// File name used for an image inside an export (`{image_id}.{ext}`)
+pub fn image_file_name(image: &Image) -> String {
+    let extension = storage::object_key(&image.url)
+        .and_then(|key| key.rsplit_once('.').map(|(_, ext)| ext.to_string()))
+        .filter(|ext| !ext.contains('/'))
+        .unwrap_or_else(|| "png".to_string());
+    format!("{}.{}", image.image_id, extension)
+}
+
+/// Classes in a stable order; YOLO indices and COCO category ids follow it
+fn sorted_classes(classes: &[Class]) -> Vec<&Class> {
+    let mut sorted: Vec<&Class> = classes.iter().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name).then(a.class_id.cmp(&b.class_id)));
+    sorted
+}
+
+/// An image whose pixel size is known, as needed by COCO and YOLO
+pub struct SizedImage<'a> {
+    pub export: &'a ExportImage,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Re-encode a mask as COCO uncompressed RLE over the whole image:
+/// column-major runs starting with background, clipped to the image
+pub fn coco_rle(
+    origin: &Point,
+    width: u32,
+    height: u32,
+    counts: &[u32],
+    image_width: u32,
+    image_height: u32,
+) -> Vec<u32> {
+    let bitmap = geometry::mask_bitmap(width, height, counts);
+    let (left, top) = (origin.x.round() as i64, origin.y.round() as i64);
+    let mut runs = vec![0u32];
+    let mut push = |foreground: bool, n: u32| {
+        if n == 0 {
+            return;
+        }
+        // Even slots are background runs, odd slots foreground
+        if (runs.len() % 2 == 0) != foreground {
+            runs.push(0);
+        }
+        if let Some(last) = runs.last_mut() {
+            *last += n;
+        }
+    };
+    let image_height = image_height as i64;
+    let first_row = top.clamp(0, image_height);
+    let end_row = (top + height as i64).clamp(0, image_height);
+    for x in 0..image_width as i64 {
+        let mask_x = x - left;
+        if mask_x < 0 || mask_x >= width as i64 {
+            push(false, image_height as u32);
+            continue;
+        }
+        push(false, first_row as u32);
+        for y in first_row..end_row {
+            push(bitmap[((y - top) * width as i64 + mask_x) as usize], 1);
+        }
+        push(false, (image_height - end_row) as u32);
+    }
+    runs
+}
+
+/// COCO `[x, y, v, ...]` triplets in skeleton order (annotation order when
+/// the class has no skeleton), plus the number of labeled keypoints
+fn coco_keypoints(keypoints: &[Keypoint], skeleton: Option<&Skeleton>) -> (Vec<f64>, usize) {
+    let ordered: Vec<Option<&Keypoint>> = match skeleton {
+        Some(skeleton) => skeleton
+            .keypoints
+            .iter()
+            .map(|name| keypoints.iter().find(|k| &k.name == name))
+            .collect(),
+        None => keypoints.iter().map(Some).collect(),
+    };
+    let flat = ordered
+        .iter()
+        .flat_map(|k| match k {
+            Some(k) if k.visibility > 0 => [k.x, k.y, k.visibility as f64],
+            _ => [0.0, 0.0, 0.0],
+        })
+        .collect();
+    let labeled = ordered
+        .iter()
+        .flatten()
+        .filter(|k| k.visibility > 0)
+        .count();
+    (flat, labeled)
+}
+
+/// COCO detection/segmentation JSON, with COCO keypoints for classes that
+/// define a skeleton. Doxle ids are kept alongside the numeric COCO ids so
+/// exports can be matched back.
+pub fn coco_json(classes: &[Class], images: &[SizedImage]) -> serde_json::Value {
+    let classes = sorted_classes(classes);
+    let category_ids: HashMap<&str, usize> = classes
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (c.class_id.as_str(), i + 1))
+        .collect();
+    let skeletons: HashMap<&str, Skeleton> = classes
+        .iter()
+        .filter_map(|c| {
+            let skeleton = crate::classes::skeleton(c.properties.as_ref()).ok()??;
+            Some((c.class_id.as_str(), skeleton))
+        })
+        .collect();
+
+    let mut coco_images = Vec::new();
+    let mut coco_annotations = Vec::new();
+    for (image_index, sized) in images.iter().enumerate() {
+        let image = &sized.export.image;
+        coco_images.push(serde_json::json!({
+            "id": image_index + 1,
+            "file_name": image_file_name(image),
+            "width": sized.width,
+            "height": sized.height,
+            "doxle_image_id": image.image_id,
+            "doxle_block_id": image.block_id,
+        }));
+
+        for annotation in &sized.export.annotations {
+            let (Some(category_id), Some((min, max))) = (
+                category_ids.get(annotation.class_id.as_str()),
+                geometry::bounds(&annotation.geometry),
+            ) else {
+                continue;
+            };
+            let segmentation = match &annotation.geometry {
+                Geometry::Polygon { points } => {
+                    serde_json::json!([points.iter().flat_map(|p| [p.x, p.y]).collect::<Vec<_>>()])
+                }
+                Geometry::BBox { .. } => {
+                    serde_json::json!([[min.x, min.y, max.x, min.y, max.x, max.y, min.x, max.y]])
+                }
+                Geometry::Mask {
+                    origin,
+                    width,
+                    height,
+                    counts,
+                } if sized.width > 0 && sized.height > 0 => serde_json::json!({
+                    "counts": coco_rle(origin, *width, *height, counts, sized.width, sized.height),
+                    "size": [sized.height, sized.width],
+                }),
+                // COCO has no open-path type; bbox still locates the line
+                _ => serde_json::json!([]),
+            };
+            let mut coco_annotation = serde_json::json!({
+                "id": coco_annotations.len() + 1,
+                "image_id": image_index + 1,
+                "category_id": category_id,
+                "segmentation": segmentation,
+                "bbox": [min.x, min.y, max.x - min.x, max.y - min.y],
+                "area": geometry::area(&annotation.geometry),
+                "iscrowd": 0,
+                "doxle_annotation_id": annotation.annotation_id,
+                "doxle_geometry_type": geometry::kind(&annotation.geometry),
+                "doxle_source": annotation.source,
+            });
+            // COCO's detection-results field, for model pre-annotations
+            if let Some(confidence) = annotation.confidence {
+                coco_annotation["score"] = serde_json::json!(confidence);
+            }
+            if let Geometry::Keypoints { keypoints } = &annotation.geometry {
+                let skeleton = skeletons.get(annotation.class_id.as_str());
+                let (flat, labeled) = coco_keypoints(keypoints, skeleton);
+                coco_annotation["keypoints"] = serde_json::json!(flat);
+                coco_annotation["num_keypoints"] = serde_json::json!(labeled);
+            }
+            coco_annotations.push(coco_annotation);
+        }
+    }
+
+    serde_json::json!({
+        "info": {
+            "description": "Doxle annotations export",
+            "date_created": chrono::Utc::now().to_rfc3339(),
+        },
+        "images": coco_images,
+        "annotations": coco_annotations,
+        "categories": classes
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let mut category =
+                    serde_json::json!({"id": i + 1, "name": c.name, "supercategory": ""});
+                if let Some(skeleton) = skeletons.get(c.class_id.as_str()) {
+                    // COCO skeleton edges are 1-based keypoint indices
+                    category["keypoints"] = serde_json::json!(skeleton.keypoints);
+                    category["skeleton"] = serde_json::json!(skeleton
+                        .edges
+                        .iter()
+                        .map(|[a, b]| [a + 1, b + 1])
+                        .collect::<Vec<_>>());
+                }
+                category
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// YOLO detection labels: one `labels/{image_id}.txt` per image with
+/// `class cx cy w h` lines normalised to 0-1, plus `classes.txt`.
+pub fn yolo_files(classes: &[Class], images: &[SizedImage]) -> Vec<(String, Vec<u8>)> {
+    let classes = sorted_classes(classes);
+    let indices: HashMap<&str, usize> = classes
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (c.class_id.as_str(), i))
+        .collect();
+
+    let mut files = Vec::new();
+    for sized in images {
+        if sized.width == 0 || sized.height == 0 {
+            continue;
+        }
+        let (w, h) = (sized.width as f64, sized.height as f64);
+        let mut lines = String::new();
+        for annotation in &sized.export.annotations {
+            // Detection labels need an extent
+            if matches!(annotation.geometry, Geometry::Point { .. }) {
+                continue;
+            }
+            let (Some(index), Some((min, max))) = (
+                indices.get(annotation.class_id.as_str()),
+                geometry::bounds(&annotation.geometry),
+            ) else {
+                continue;
+            };
+            lines.push_str(&format!(
+                "{} {:.6} {:.6} {:.6} {:.6}\n",
+                index,
+                (min.x + max.x) / 2.0 / w,
+                (min.y + max.y) / 2.0 / h,
+                (max.x - min.x) / w,
+                (max.y - min.y) / h
+            ));
+        }
+        files.push((
+            format!("labels/{}.txt", sized.export.image.image_id),
+            lines.into_bytes(),
+        ));
+    }
+
+    let names: Vec<&str> = classes.iter().map(|c| c.name.as_str()).collect();
+    files.push((
+        "classes.txt".to_string(),
+        format!("{}\n", names.join("\n")).into_bytes(),
+    ));
+    files
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One row per annotation with its bounds and raw geometry JSON
+pub fn annotations_csv(classes: &[Class], collected: &[ExportImage]) -> String {
+    let class_names: HashMap<&str, &str> = classes
+        .iter()
+        .map(|c| (c.class_id.as_str(), c.name.as_str()))
+        .collect();
+
+    let mut csv = String::from(
+        "annotation_id,image_id,block_id,class_id,class_name,geometry_type,x_min,y_min,x_max,y_max,area,geometry,created_by,created_at,source,confidence\n",
+    );
+    for export_image in collected {
+        for annotation in &export_image.annotations {
+            let geometry_type = geometry::kind(&annotation.geometry);
+            let (min, max) = geometry::bounds(&annotation.geometry)
+                .map(|(min, max)| ((min.x, min.y), (max.x, max.y)))
+                .unwrap_or(((0.0, 0.0), (0.0, 0.0)));
+            let geometry_json = serde_json::to_string(&annotation.geometry).unwrap_or_default();
+            let row = [
+                annotation.annotation_id.clone(),
+                export_image.image.image_id.clone(),
+                export_image.image.block_id.clone(),
+                annotation.class_id.clone(),
+                class_names
+                    .get(annotation.class_id.as_str())
+                    .copied()
+                    .unwrap_or("unknown")
+                    .to_string(),
+                geometry_type.to_string(),
+                min.0.to_string(),
+                min.1.to_string(),
+                max.0.to_string(),
+                max.1.to_string(),
+                geometry::area(&annotation.geometry).to_string(),
+                geometry_json,
+                annotation.created_by.clone(),
+                annotation.created_at.clone(),
+                annotation.source.clone(),
+                annotation
+                    .confidence
+                    .map(|c| c.to_string())
+                    .unwrap_or_default(),
+            ];
+            let row: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+    }
+    csv
+}
+
+/// Fetch dimensions for every image; images that can't be sized are skipped
+async fn size_images<'a>(
+    s3_client: &S3Client,
+    collected: &'a [ExportImage],
+    skipped: &mut Vec<SkippedAnnotation>,
+) -> Vec<SizedImage<'a>> {
+    let mut sized = Vec::new();
+    for export in collected {
+        match image_dimensions(s3_client, &export.image).await {
+            Ok((width, height)) => sized.push(SizedImage {
+                export,
+                width,
+                height,
+            }),
+            Err(e) => {
+                tracing::warn!("Export: cannot size image {}: {}", export.image.image_id, e);
+                for annotation in &export.annotations {
+                    skipped.push(SkippedAnnotation {
+                        annotation_id: annotation.annotation_id.clone(),
+                        reason: "Image dimensions unavailable".to_string(),
+                    });
+                }
+            }
+        }
+    }
+    sized
+}
+
+/// Folder-safe version of a class name
+pub fn folder_name(name: &str) -> String {
+    let cleaned: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if cleaned.is_empty() {
+        "unnamed".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// Parse `?split=` ratios ("80/10/10", or "0.8,0.2" with no test split)
+/// into fractions summing to 1
+pub fn parse_split(split: &str) -> Result<[f64; 3], String> {
+    let parts: Vec<&str> = split.split(['/', ',', ':']).map(|p| p.trim()).collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err("split needs train/val or train/val/test ratios, e.g. 80/10/10".to_string());
+    }
+    let mut ratios = [0.0; 3];
+    for (ratio, part) in ratios.iter_mut().zip(&parts) {
+        *ratio = part
+            .parse::<f64>()
+            .ok()
+            .filter(|r| r.is_finite() && *r >= 0.0)
+            .ok_or_else(|| format!("Invalid split ratio '{}'", part))?;
+    }
+    let total: f64 = ratios.iter().sum();
+    if total <= 0.0 {
+        return Err("split ratios must not all be zero".to_string());
+    }
+    Ok(ratios.map(|r| r / total))
+}
+
+/// FNV-1a of the seed and image id: a stable shuffle key that doesn't
+/// depend on the order images were fetched in
+fn split_hash(seed: u64, image_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in seed.to_le_bytes().iter().chain(image_id.as_bytes()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Split sizes for `total` images, rounding by largest remainder so they add up
+fn split_counts(total: usize, ratios: &[f64; 3]) -> [usize; 3] {
+    let exact = ratios.map(|r| r * total as f64);
+    let mut counts = exact.map(|e| e.floor() as usize);
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| {
+        (exact[b] - exact[b].floor())
+            .total_cmp(&(exact[a] - exact[a].floor()))
+            .then(a.cmp(&b))
+    });
+    let assigned: usize = counts.iter().sum();
+    for &i in order.iter().take(total - assigned) {
+        counts[i] += 1;
+    }
+    counts
+}
+
+/// Deterministically assign each image id to a split (index into
+/// `SPLIT_NAMES`). The same ids, ratios and seed always give the same split.
+pub fn assign_splits(image_ids: &[&str], ratios: &[f64; 3], seed: u64) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..image_ids.len()).collect();
+    order.sort_by_key(|&i| (split_hash(seed, image_ids[i]), image_ids[i]));
+    let counts = split_counts(image_ids.len(), ratios);
+
+    let mut assigned = vec![0; image_ids.len()];
+    let mut position = 0;
+    for (split, count) in counts.iter().enumerate() {
+        for &i in &order[position..position + count] {
+            assigned[i] = split;
+        }
+        position += count;
+    }
+    assigned
+}
+
+/// `splits/{train,val,test}.txt` (image file names, one per line) and a
+/// `splits/splits.csv` index, for an assignment from `assign_splits`
+pub fn split_files(collected: &[ExportImage], assigned: &[usize]) -> Vec<(String, Vec<u8>)> {
+    let mut lists = [String::new(), String::new(), String::new()];
+    let mut index = String::from("image_id,block_id,file,split\n");
+    for (export_image, &split) in collected.iter().zip(assigned) {
+        let file = image_file_name(&export_image.image);
+        lists[split].push_str(&format!("{}\n", file));
+        index.push_str(&format!(
+            "{},{},{},{}\n",
+            export_image.image.image_id, export_image.image.block_id, file, SPLIT_NAMES[split]
+        ));
+    }
+    let mut files: Vec<_> = SPLIT_NAMES
+        .iter()
+        .zip(lists)
+        .map(|(name, list)| (format!("splits/{}.txt", name), list.into_bytes()))
+        .collect();
+    files.push(("splits/splits.csv".to_string(), index.into_bytes()));
+    files
+}
+
+/// Where a huggingface export is written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Destination {
+    pub bucket: String,
+    pub prefix: String, // ends with '/'
+}
+
+impl Destination {
+    pub fn uri(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+/// Parse `?destination=`: `s3://bucket/prefix`, or a bare prefix inside our
+/// bucket's `datasets/`. Other buckets must be listed in `allowed_buckets`
+/// (EXPORT_TARGET_BUCKETS), and our own only accepts `datasets/` prefixes.
+pub fn parse_destination(
+    value: &str,
+    own_bucket: &str,
+    allowed_buckets: &[String],
+) -> Result<Destination, String> {
+    let (bucket, prefix) = match value.strip_prefix("s3://") {
+        Some(rest) => rest.split_once('/').unwrap_or((rest, "")),
+        None => (own_bucket, value),
+    };
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty()
+        || prefix
+            .split('/')
+            .any(|s| s.is_empty() || s == "." || s == "..")
+    {
+        return Err("destination needs a prefix, e.g. s3://bucket/datasets/name".to_string());
+    }
+    let prefix = if bucket != own_bucket {
+        if !allowed_buckets.iter().any(|b| b == bucket) {
+            return Err(format!("Exports to bucket '{}' are not allowed", bucket));
+        }
+        format!("{}/", prefix)
+    } else if value.starts_with("s3://") {
+        if !prefix.starts_with(DATASETS_PREFIX) {
+            return Err(format!(
+                "Datasets in {} must be under {}",
+                own_bucket, DATASETS_PREFIX
+            ));
+        }
+        format!("{}/", prefix)
+    } else {
+        format!("{}{}/", DATASETS_PREFIX, prefix)
+    };
+    Ok(Destination {
+        bucket: bucket.to_string(),
+        prefix,
+    })
+}
+
+/// Folder of an image in the imagefolder layout: one per split, or the root
+fn imagefolder_dir(split: Option<usize>) -> String {
+    split
+        .map(|s| format!("{}/", IMAGEFOLDER_SPLITS[s]))
+        .unwrap_or_default()
+}
+
+/// One `metadata.jsonl` line in the Hugging Face imagefolder layout:
+/// `file_name` next to the metadata file, and `objects` holding COCO-style
+/// `[x, y, w, h]` pixel boxes with category indices in `classes.txt` order.
+/// Points have no box and are reported as skipped.
+pub fn imagefolder_record(
+    classes: &[Class],
+    sized: &SizedImage,
+    skipped: &mut Vec<SkippedAnnotation>,
+) -> serde_json::Value {
+    let indices: HashMap<&str, usize> = sorted_classes(classes)
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (c.class_id.as_str(), i))
+        .collect();
+    let (mut ids, mut areas, mut bboxes, mut categories) = (vec![], vec![], vec![], vec![]);
+    for annotation in &sized.export.annotations {
+        let (Some(&index), Some((min, max))) = (
+            indices.get(annotation.class_id.as_str()),
+            geometry::bounds(&annotation.geometry),
+        ) else {
+            skipped.push(SkippedAnnotation {
+                annotation_id: annotation.annotation_id.clone(),
+                reason: "Unknown class or empty geometry".to_string(),
+            });
+            continue;
+        };
+        if matches!(annotation.geometry, Geometry::Point { .. }) {
+            skipped.push(SkippedAnnotation {
+                annotation_id: annotation.annotation_id.clone(),
+                reason: "Point annotations have no bounding box".to_string(),
+            });
+            continue;
+        }
+        ids.push(annotation.annotation_id.clone());
+        areas.push(geometry::area(&annotation.geometry));
+        bboxes.push([min.x, min.y, max.x - min.x, max.y - min.y]);
+        categories.push(index);
+    }
+    let image = &sized.export.image;
+    serde_json::json!({
+        "file_name": image_file_name(image),
+        "image_id": image.image_id,
+        "block_id": image.block_id,
+        "width": sized.width,
+        "height": sized.height,
+        "objects": {
+            "id": ids,
+            "area": areas,
+            "bbox": bboxes,
+            "categories": categories,
+        },
+    })
+}
+
+/// How an archive entry is written: images are already compressed
+pub(crate) fn entry_options(path: &str) -> zip::write::SimpleFileOptions {
+    let method = if path.ends_with(".png") || path.ends_with(".jpg") {
+        zip::CompressionMethod::Stored
+    } else {
+        zip::CompressionMethod::Deflated
+    };
+    zip::write::SimpleFileOptions::default().compression_method(method)
+}
+
+/// Build a ZIP archive from (path, bytes) entries
+pub fn build_zip(entries: Vec<(String, Vec<u8>)>) -> Result<Vec<u8>, String> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, bytes) in entries {
+        zip.start_file(path.as_str(), entry_options(&path))
+            .map_err(|e| format!("Failed to add {}: {}", path, e))?;
+        zip.write_all(&bytes)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    }
+    let cursor = zip
+        .finish()
+        .map_err(|e| format!("Failed to finish archive: {}", e))?;
+    Ok(cursor.into_inner())
+}
+
+/// A region `format=crops` cuts out of an image, and where it goes
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Crop {
+    pub file: String, // crops/{class}/{annotation_id}.png
+    pub annotation_id: String,
+    pub class_id: String,
+    pub class_name: String,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// An image and the crops cut out of it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CropSource {
+    pub image: Image,
+    pub crops: Vec<Crop>,
+}
+
+/// The bounding region of every annotation, filed under
+/// `crops/{class}/{annotation_id}.png`. The image worker cuts them out
+/// (`export_jobs`), since that means decoding every source image.
+fn plan_crops(
+    classes: &[Class],
+    collected: &[ExportImage],
+    skipped: &mut Vec<SkippedAnnotation>,
+) -> Vec<CropSource> {
+    let class_names: HashMap<&str, &str> = classes
+        .iter()
+        .map(|c| (c.class_id.as_str(), c.name.as_str()))
+        .collect();
+    let mut sources = Vec::new();
+
+    for export_image in collected.iter().filter(|i| !i.annotations.is_empty()) {
+        let mut crops = Vec::new();
+        for annotation in &export_image.annotations {
+            if matches!(annotation.geometry, Geometry::Point { .. }) {
+                skipped.push(SkippedAnnotation {
+                    annotation_id: annotation.annotation_id.clone(),
+                    reason: "Point annotations have no region to crop".to_string(),
+                });
+                continue;
+            }
+            let Some((min, max)) = geometry::bounds(&annotation.geometry) else {
+                skipped.push(SkippedAnnotation {
+                    annotation_id: annotation.annotation_id.clone(),
+                    reason: "Empty geometry".to_string(),
+                });
+                continue;
+            };
+            let class_name = class_names
+                .get(annotation.class_id.as_str())
+                .copied()
+                .unwrap_or("unknown");
+            crops.push(Crop {
+                file: format!(
+                    "crops/{}/{}.png",
+                    folder_name(class_name),
+                    annotation.annotation_id
+                ),
+                annotation_id: annotation.annotation_id.clone(),
+                class_id: annotation.class_id.clone(),
+                class_name: class_name.to_string(),
+                x: min.x,
+                y: min.y,
+                width: max.x - min.x,
+                height: max.y - min.y,
+            });
+        }
+        if !crops.is_empty() {
+            sources.push(CropSource {
+                image: export_image.image.clone(),
+                crops,
+            });
+        }
+    }
+    sources
+}
+
+/// Header of the crop archive's `manifest.csv`
+pub(crate) const CROP_MANIFEST_HEADER: &str =
+    "file,annotation_id,class_id,class_name,image_id,block_id,x,y,width,height\n";
+
+/// `manifest.csv` row of a crop that made it into the archive
+pub(crate) fn crop_manifest_row(image: &Image, crop: &Crop) -> String {
+    format!(
+        "{},{},{},\"{}\",{},{},{},{},{},{}\n",
+        crop.file,
+        crop.annotation_id,
+        crop.class_id,
+        crop.class_name.replace('"', "\"\""),
+        image.image_id,
+        image.block_id,
+        crop.x,
+        crop.y,
+        crop.width,
+        crop.height
+    )
+}
+
+/// Upload an archive under `exports/{project_id}/` and presign a download link
+async fn store_archive(
+    s3_client: &S3Client,
+    project_id: &str,
+    export_id: &str,
+    archive: Vec<u8>,
+) -> Result<String, Error> {
+    let key = crate::export_jobs::archive_key(project_id, export_id);
+    s3_client
+        .put_object()
+        .bucket(region::bucket_name())
+        .key(&key)
+        .body(archive.into())
+        .content_type("application/zip")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to upload export: {}", e))?;
+    presign_download(s3_client, &key).await
+}
+
+/// Presign a download of an uploaded archive
+pub(crate) async fn presign_download(s3_client: &S3Client, key: &str) -> Result<String, Error> {
+    let presigned = s3_client
+        .get_object()
+        .bucket(region::bucket_name())
+        .key(key)
+        .presigned(
+            aws_sdk_s3::presigning::PresigningConfig::expires_in(std::time::Duration::from_secs(
+                DOWNLOAD_URL_TTL_SECS,
+            ))
+            .map_err(|e| format!("Failed to create presigning config: {}", e))?,
+        )
+        .await
+        .map_err(|e| format!("Failed to presign export download: {}", e))?;
+    Ok(presigned.uri().to_string())
+}
+
+pub(crate) async fn put_dataset_file(
+    s3_client: &S3Client,
+    destination: &Destination,
+    path: &str,
+    bytes: Vec<u8>,
+) -> Result<(), String> {
+    s3_client
+        .put_object()
+        .bucket(&destination.bucket)
+        .key(format!("{}{}", destination.prefix, path))
+        .body(bytes.into())
+        .send()
+        .await
+        .map_err(|e| format!("Failed to write {}{}: {}", destination.uri(), path, e))?;
+    Ok(())
+}
+
+/// An image of a Hugging Face dataset and its `metadata.jsonl` line
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatasetImage {
+    pub image: Image,
+    pub dir: String, // split folder, "" when not split
+    pub record: serde_json::Value,
+    pub annotation_ids: Vec<String>, // skipped with the image if it can't be copied
+}
+
+/// A Hugging Face imagefolder dataset for the image worker to write
+/// (`export_jobs`): images plus `metadata.jsonl`, in `train/`, `validation/`
+/// and `test/` folders when split, and `classes.txt` naming the category
+/// indices. Loads with `load_dataset("imagefolder", data_dir=...)`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dataset {
+    pub destination: Destination,
+    pub images: Vec<DatasetImage>,
+}
+
+/// Size the images of a dataset and build their metadata records
+async fn plan_imagefolder(
+    s3_client: &S3Client,
+    classes: &[Class],
+    collected: &[ExportImage],
+    assigned: Option<&[usize]>,
+    destination: &Destination,
+    skipped: &mut Vec<SkippedAnnotation>,
+) -> Dataset {
+    let splits: HashMap<&str, usize> = match assigned {
+        Some(assigned) => collected
+            .iter()
+            .map(|i| i.image.image_id.as_str())
+            .zip(assigned.iter().copied())
+            .collect(),
+        None => HashMap::new(),
+    };
+    let mut images = Vec::new();
+    for sized in size_images(s3_client, collected, skipped).await {
+        let image = &sized.export.image;
+        images.push(DatasetImage {
+            image: image.clone(),
+            dir: imagefolder_dir(splits.get(image.image_id.as_str()).copied()),
+            record: imagefolder_record(classes, &sized, skipped),
+            annotation_ids: sized
+                .export
+                .annotations
+                .iter()
+                .map(|a| a.annotation_id.clone())
+                .collect(),
+        });
+    }
+    Dataset {
+        destination: destination.clone(),
+        images,
+    }
+}
+
+/// `classes.txt` of a dataset: class names in category index order
+fn imagefolder_classes(classes: &[Class]) -> Vec<u8> {
+    let names: Vec<&str> = sorted_classes(classes)
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect();
+    format!("{}\n", names.join("\n")).into_bytes()
+}
+
+/// Copy the full-resolution source of an image into a dataset with
+/// CopyObject, so its bytes never pass through the worker. Falls back to
+/// the pyramid's full level like `load_source_image`. CopyObject takes
+/// sources up to 5GB.
+pub(crate) async fn copy_source_image(
+    s3_client: &S3Client,
+    image: &Image,
+    destination: &Destination,
+    path: &str,
+) -> Result<(), String> {
+    let key = storage::object_key(&image.url)
+        .ok_or_else(|| format!("Unrecognised image URL: {}", image.url))?;
+    let target = format!("{}{}", destination.prefix, path);
+    let copy = |source: String| {
+        s3_client
+            .copy_object()
+            .copy_source(storage::copy_source(region::bucket_name(), &source))
+            .bucket(&destination.bucket)
+            .key(&target)
+            .send()
+    };
+    let Err(e) = copy(key.clone()).await else {
+        return Ok(());
+    };
+    let e = format!(
+        "Failed to copy {} to {}{}: {}",
+        key,
+        destination.uri(),
+        path,
+        e
+    );
+    let base = pyramid_base(&key);
+    let metadata = load_metadata(s3_client, image, base).await.map_err(|_| e)?;
+    let full = metadata
+        .levels
+        .iter()
+        .find(|l| l.purpose == "full")
+        .ok_or_else(|| format!("No full resolution level for {}", image.image_id))?;
+    copy(format!("{}/{}", base, full.path)).await.map_err(|e| {
+        format!(
+            "Failed to copy {} to {}{}: {}",
+            key,
+            destination.uri(),
+            path,
+            e
+        )
+    })?;
+    Ok(())
+}
+
+fn bad_request(message: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({ "error": message }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+fn bad_format() -> Result<Response<Body>, Error> {
+    bad_request(&format!("format must be one of: {}", FORMATS.join(", ")))
+}
+
+/// Build the archive for a project or a single block and return a download link
+async fn run_export(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    project_id: &str,
+    block_id: Option<&str>,
+    options: ExportOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    let Some(format) = options.format.filter(|f| FORMATS.contains(f)) else {
+        return bad_format();
+    };
+    let split = match options.split.map(parse_split).transpose() {
+        Ok(split) => split,
+        Err(message) => return bad_request(&message),
+    };
+    let seed = match options.seed.map(|s| s.parse::<u64>()).transpose() {
+        Ok(seed) => seed.unwrap_or_else(|| uuid::Uuid::new_v4().as_u64_pair().0),
+        Err(_) => return bad_request("seed must be a non-negative integer"),
+    };
+    let filter = match ExportFilter::parse(&options) {
+        Ok(filter) => filter,
+        Err(message) => return bad_request(&message),
+    };
+    if let Some(level) = options.images.filter(|i| !IMAGE_BUNDLES.contains(i)) {
+        return bad_request(&format!(
+            "Unknown images '{}', expected one of: {}",
+            level,
+            IMAGE_BUNDLES.join(", ")
+        ));
+    }
+    let destination = match (format, options.destination) {
+        ("huggingface", Some(value)) => {
+            let allowed = std::env::var("EXPORT_TARGET_BUCKETS")
+                .map(|v| comma_list(&v))
+                .unwrap_or_default();
+            match parse_destination(value, region::bucket_name(), &allowed) {
+                Ok(destination) => Some(destination),
+                Err(message) => return bad_request(&message),
+            }
+        }
+        ("huggingface", None) => {
+            return bad_request("format=huggingface needs a destination, e.g. s3://bucket/prefix")
+        }
+        (_, Some(_)) => return bad_request("destination is only used with format=huggingface"),
+        (_, None) => None,
+    };
+    if destination.is_some() && options.images.is_some_and(|i| i != "original") {
+        // Boxes are in full-resolution pixels
+        return bad_request("huggingface datasets always use original images");
+    }
+
+    let started = std::time::Instant::now();
+    let classes = classes::fetch_project_classes(client, table_name, project_id).await?;
+    let mut collected = collect_images(client, table_name, project_id, block_id, &filter).await?;
+
+    let mut skipped = Vec::new();
+    let total_annotations: usize = collected.iter().map(|i| i.annotations.len()).sum();
+    // Every format is written in pixels
+    let project = crate::projects::fetch_project(client, table_name, project_id).await?;
+    if crate::projects::coordinate_mode(project.as_ref()) == "normalized" {
+        denormalize_all(s3_client, &mut collected, &mut skipped).await;
+    }
+    let assigned = split.map(|ratios| {
+        let ids: Vec<&str> = collected
+            .iter()
+            .map(|i| i.image.image_id.as_str())
+            .collect();
+        assign_splits(&ids, &ratios, seed)
+    });
+    let split = split.zip(assigned.as_deref()).map(|(ratios, assigned)| {
+        let count = |split| assigned.iter().filter(|&&s| s == split).count();
+        SplitSummary {
+            seed,
+            ratios,
+            train: count(0),
+            val: count(1),
+            test: count(2),
+        }
+    });
+
+    let export_id = uuid::Uuid::new_v4().to_string();
+    let mut pending = None; // label files the image worker adds the images to
+    let mut crops = Vec::new();
+    let mut dataset = None;
+    let download_url = if let Some(destination) = &destination {
+        dataset = Some(
+            plan_imagefolder(
+                s3_client,
+                &classes,
+                &collected,
+                assigned.as_deref(),
+                destination,
+                &mut skipped,
+            )
+            .await,
+        );
+        pending = Some(vec![(
+            "classes.txt".to_string(),
+            imagefolder_classes(&classes),
+        )]);
+        None
+    } else {
+        let mut entries = match format {
+            "coco" => {
+                let sized = size_images(s3_client, &collected, &mut skipped).await;
+                vec![(
+                    "annotations.json".to_string(),
+                    serde_json::to_vec_pretty(&coco_json(&classes, &sized))?,
+                )]
+            }
+            "yolo" => {
+                let sized = size_images(s3_client, &collected, &mut skipped).await;
+                yolo_files(&classes, &sized)
+            }
+            "csv" => vec![(
+                "annotations.csv".to_string(),
+                annotations_csv(&classes, &collected).into_bytes(),
+            )],
+            _ => {
+                crops = plan_crops(&classes, &collected, &mut skipped);
+                Vec::new()
+            }
+        };
+        if let Some(assigned) = &assigned {
+            entries.extend(split_files(&collected, assigned));
+        }
+        if options.images.is_some() || format == "crops" {
+            pending = Some(entries);
+            None
+        } else {
+            let archive = build_zip(entries)?;
+            Some(store_archive(s3_client, project_id, &export_id, archive).await?)
+        }
+    };
+    let annotation_count = total_annotations - skipped.len();
+
+    tracing::info!(
+        "Export {} ({}) of project {} block {:?}: {} annotations, {} skipped in {:?}",
+        export_id,
+        format,
+        project_id,
+        block_id,
+        annotation_count,
+        skipped.len(),
+        started.elapsed()
+    );
+
+    let mut result = ExportResult {
+        export_id,
+        status: "ready".to_string(),
+        error: None,
+        project_id: project_id.to_string(),
+        block_id: block_id.map(|b| b.to_string()),
+        format: format.to_string(),
+        expires_in: download_url.as_ref().map(|_| DOWNLOAD_URL_TTL_SECS),
+        download_url,
+        destination: destination.map(|d| d.uri()),
+        image_count: collected.len(),
+        annotation_count,
+        skipped,
+        split,
+        bundled_images: 0,
+        skipped_images: Vec::new(),
+    };
+
+    if let Some(entries) = pending {
+        // Too large or slow to put together here: the image worker streams
+        // the images in, cuts the crops or copies the dataset, and
+        // GET /projects/{pid}/exports/{eid} says when it's done
+        result.status = "processing".to_string();
+        let job = export_jobs::ExportJob {
+            result,
+            files: entries
+                .into_iter()
+                .map(|(path, bytes)| export_jobs::JobFile { path, bytes })
+                .collect(),
+            images: options.images.map(|level| level.to_string()),
+            bundle: match options.images {
+                Some(_) => collected.into_iter().map(|export| export.image).collect(),
+                None => Vec::new(),
+            },
+            crops,
+            dataset,
+        };
+        export_jobs::enqueue(client, s3_client, table_name, &job).await?;
+        return Ok(Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::to_string(&job.result)?.into())
+            .map_err(Box::new)?);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&result)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Export a project's annotations (GET /projects/{id}/export?format=coco|yolo|csv|crops).
+/// With `&split=80/10/10&seed=N` the images are also partitioned into
+/// train/val/test manifests under `splits/`. `classes=`, `block_state=` and
+/// `created_after=` narrow the export, e.g. to completed blocks only;
+/// `images=original|preview` adds the image files themselves; those archives,
+/// and `format=crops` ones, are built by the image worker, answered with 202
+/// and polled at GET /projects/{id}/exports/{export_id}.
+/// `format=huggingface&destination=s3://bucket/prefix` has the image worker
+/// write an imagefolder dataset to S3 instead of an archive, also answered
+/// with 202.
+/// Label-only archives are built in this lambda, so very large projects
+/// should be exported block by block.
+pub async fn export_project(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    project_id: &str,
+    options: ExportOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    run_export(client, s3_client, table_name, project_id, None, options).await
+}
+
+/// Export a single block (GET /projects/{pid}/blocks/{bid}/export?format=...&split=&seed=)
+pub async fn export_block(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+    options: ExportOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    run_export(
+        client,
+        s3_client,
+        table_name,
+        project_id,
+        Some(block_id),
+        options,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pyramid_base() {
+        assert_eq!(
+            pyramid_base("projects/p/blocks/b/i.png"),
+            "projects/p/blocks/b/i"
+        );
+    }
+
+    #[test]
+    fn test_folder_name() {
+        assert_eq!(folder_name("Door / Window"), "Door___Window");
+        assert_eq!(folder_name("wall-ext_1"), "wall-ext_1");
+        assert_eq!(folder_name("  "), "unnamed");
+    }
+
+    fn sample() -> (Vec<Class>, Vec<ExportImage>) {
+        use crate::types::Point;
+        let class = |id: &str, name: &str| Class {
+            class_id: id.to_string(),
+            project_id: "p".to_string(),
+            name: name.to_string(),
+            color: None,
+            properties: None,
+            count: 0,
+            sort_order: None,
+            hotkey: None,
+        };
+        let annotation = |id: &str, class_id: &str, geometry: Geometry| Annotation {
+            annotation_id: id.to_string(),
+            image_id: "img".to_string(),
+            project_id: "p1".to_string(),
+            block_id: "b1".to_string(),
+            class_id: class_id.to_string(),
+            area: geometry::area(&geometry),
+            bounding_box: geometry::bounding_box(&geometry),
+            geometry,
+            created_by: "u1".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            updated_at: None,
+            version: 1,
+            tags: Vec::new(),
+            review_status: "pending".to_string(),
+            reviewed_by: None,
+            reviewed_at: None,
+            review_reason: None,
+            source: "human".to_string(),
+            confidence: None,
+            group_id: None,
+            z_index: 0,
+            attributes: Default::default(),
+        };
+        let image = Image {
+            image_id: "img".to_string(),
+            block_id: "b1".to_string(),
+            url: "https://cdn.doxle.ai/projects/p/blocks/b1/img.jpg".to_string(),
+            locked: false,
+            order: None,
+            uploaded_at: String::new(),
+            calibration: None,
+            file_name: None,
+            captured_at: None,
+            annotation_count: 0,
+            status: "unannotated".to_string(),
+            width: None,
+            height: None,
+            size_bytes: None,
+            content_type: None,
+            levels: Vec::new(),
+            thumbnail_url: None,
+            tiles_url: None,
+            photo: None,
+            original_format: None,
+            processing: "ready".to_string(),
+            tags: Vec::new(),
+        };
+        let p = |x: f64, y: f64| Point { x, y };
+        (
+            vec![class("c-wall", "wall"), class("c-door", "door")],
+            vec![ExportImage {
+                image,
+                annotations: vec![
+                    annotation(
+                        "a1",
+                        "c-door",
+                        Geometry::BBox {
+                            start: p(10.0, 20.0),
+                            end: p(30.0, 60.0),
+                        },
+                    ),
+                    annotation(
+                        "a2",
+                        "c-wall",
+                        Geometry::Polygon {
+                            points: vec![p(0.0, 0.0), p(100.0, 0.0), p(100.0, 10.0)],
+                        },
+                    ),
+                ],
+            }],
+        )
+    }
+
+    #[test]
+    fn test_coco_json() {
+        let (classes, collected) = sample();
+        let sized = vec![SizedImage {
+            export: &collected[0],
+            width: 200,
+            height: 100,
+        }];
+        let coco = coco_json(&classes, &sized);
+
+        // Categories sorted by name: door=1, wall=2
+        assert_eq!(coco["categories"][0]["name"], "door");
+        assert_eq!(coco["images"][0]["file_name"], "img.jpg");
+        assert_eq!(coco["images"][0]["width"], 200);
+        let door = &coco["annotations"][0];
+        assert_eq!(door["category_id"], 1);
+        assert_eq!(door["bbox"], serde_json::json!([10.0, 20.0, 20.0, 40.0]));
+        assert_eq!(door["area"], 800.0);
+        assert_eq!(door["doxle_geometry_type"], "bbox");
+        let wall = &coco["annotations"][1];
+        assert_eq!(wall["category_id"], 2);
+        assert_eq!(
+            wall["segmentation"][0],
+            serde_json::json!([0.0, 0.0, 100.0, 0.0, 100.0, 10.0])
+        );
+    }
+
+    #[test]
+    fn test_coco_keypoints() {
+        let (mut classes, mut collected) = sample();
+        let mut pose = classes[0].clone();
+        pose.class_id = "c-pose".to_string();
+        pose.name = "pose".to_string();
+        pose.properties = Some(serde_json::json!({
+            "skeleton": {"keypoints": ["head", "neck", "hip"], "edges": [[0, 1], [1, 2]]}
+        }));
+        classes.push(pose);
+        let kp = |name: &str, x: f64, y: f64, visibility: u8| Keypoint {
+            name: name.to_string(),
+            x,
+            y,
+            visibility,
+        };
+        let mut annotation = collected[0].annotations[0].clone();
+        annotation.annotation_id = "a3".to_string();
+        annotation.class_id = "c-pose".to_string();
+        annotation.geometry = Geometry::Keypoints {
+            keypoints: vec![kp("hip", 5.0, 9.0, 1), kp("head", 4.0, 1.0, 2)],
+        };
+        collected[0].annotations.push(annotation);
+
+        let sized = vec![SizedImage {
+            export: &collected[0],
+            width: 200,
+            height: 100,
+        }];
+        let coco = coco_json(&classes, &sized);
+        // Sorted: door=1, pose=2, wall=3
+        let category = &coco["categories"][1];
+        assert_eq!(
+            category["keypoints"],
+            serde_json::json!(["head", "neck", "hip"])
+        );
+        assert_eq!(category["skeleton"], serde_json::json!([[1, 2], [2, 3]]));
+        assert!(coco["categories"][0].get("keypoints").is_none());
+
+        let pose = &coco["annotations"][2];
+        assert_eq!(
+            pose["keypoints"],
+            serde_json::json!([4.0, 1.0, 2.0, 0.0, 0.0, 0.0, 5.0, 9.0, 1.0])
+        );
+        assert_eq!(pose["num_keypoints"], 2);
+        assert_eq!(pose["bbox"], serde_json::json!([4.0, 1.0, 1.0, 8.0]));
+    }
+
+    #[test]
+    fn test_coco_rle() {
+        // 2x2 mask with the top-left and bottom-right pixels set, at (1, 0)
+        // in a 3x3 image:
+        // . # .
+        // . . #
+        // . . .
+        let origin = Point { x: 1.0, y: 0.0 };
+        assert_eq!(
+            coco_rle(&origin, 2, 2, &[0, 1, 2, 1], 3, 3),
+            vec![3, 1, 3, 1, 1]
+        );
+        // Clipped at the image edge
+        let origin = Point { x: 2.0, y: 2.0 };
+        assert_eq!(coco_rle(&origin, 2, 2, &[0, 4], 3, 3), vec![8, 1]);
+    }
+
+    #[test]
+    fn test_yolo_files() {
+        let (classes, collected) = sample();
+        let sized = vec![SizedImage {
+            export: &collected[0],
+            width: 200,
+            height: 100,
+        }];
+        let files = yolo_files(&classes, &sized);
+        assert_eq!(files[0].0, "labels/img.txt");
+        let labels = String::from_utf8(files[0].1.clone()).unwrap();
+        let lines: Vec<&str> = labels.lines().collect();
+        assert_eq!(lines[0], "0 0.100000 0.400000 0.100000 0.400000");
+        assert_eq!(lines[1], "1 0.250000 0.050000 0.500000 0.100000");
+        assert_eq!(
+            files[1],
+            ("classes.txt".to_string(), b"door\nwall\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_annotations_csv() {
+        let (classes, collected) = sample();
+        let csv = annotations_csv(&classes, &collected);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("a1,img,b1,c-door,door,bbox,10,20,30,60,800,"));
+        // Geometry JSON is quoted because it contains commas
+        assert!(lines[2].contains(",\"{\"\"type\"\":\"\"polygon\"\""));
+    }
+
+    #[test]
+    fn test_plan_crops() {
+        let (classes, mut collected) = sample();
+        let pin = Annotation {
+            annotation_id: "pin".to_string(),
+            geometry: Geometry::Point {
+                point: Point { x: 1.0, y: 1.0 },
+            },
+            ..collected[0].annotations[0].clone()
+        };
+        collected[0].annotations.push(pin);
+        let mut skipped = Vec::new();
+        let sources = plan_crops(&classes, &collected, &mut skipped);
+        assert_eq!(sources.len(), 1);
+        let crops = &sources[0].crops;
+        assert_eq!(crops.len(), 2);
+        assert_eq!(crops[0].file, "crops/door/a1.png");
+        assert_eq!(
+            (crops[0].x, crops[0].y, crops[0].width, crops[0].height),
+            (10.0, 20.0, 20.0, 40.0)
+        );
+        assert_eq!(crops[1].file, "crops/wall/a2.png");
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].annotation_id, "pin");
+
+        let row = crop_manifest_row(&sources[0].image, &crops[0]);
+        assert_eq!(
+            row,
+            "crops/door/a1.png,a1,c-door,\"door\",img,b1,10,20,20,40\n"
+        );
+        assert_eq!(
+            CROP_MANIFEST_HEADER.split(',').count(),
+            row.split(',').count()
+        );
+    }
+
+    #[test]
+    fn test_build_zip() {
+        let archive = build_zip(vec![
+            ("crops/wall/a.png".to_string(), vec![1, 2, 3]),
+            ("manifest.csv".to_string(), b"file\n".to_vec()),
+        ])
+        .unwrap();
+        let reader = zip::ZipArchive::new(Cursor::new(archive)).unwrap();
+        let names: Vec<&str> = reader.file_names().collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"crops/wall/a.png"));
+    }
+
+    #[test]
+    fn test_parse_split() {
+        assert_eq!(parse_split("80/10/10"), Ok([0.8, 0.1, 0.1]));
+        assert_eq!(parse_split("3,1"), Ok([0.75, 0.25, 0.0]));
+        assert!(parse_split("80").is_err());
+        assert!(parse_split("80/-10/30").is_err());
+        assert!(parse_split("0/0").is_err());
+        assert!(parse_split("a/b/c").is_err());
+    }
+
+    #[test]
+    fn test_assign_splits() {
+        let ids: Vec<String> = (0..10).map(|i| format!("img-{}", i)).collect();
+        let ids: Vec<&str> = ids.iter().map(|s| s.as_str()).collect();
+        let ratios = [0.7, 0.2, 0.1];
+
+        let assigned = assign_splits(&ids, &ratios, 42);
+        let count = |split| assigned.iter().filter(|&&s| s == split).count();
+        assert_eq!((count(0), count(1), count(2)), (7, 2, 1));
+
+        // Deterministic, and independent of input order
+        assert_eq!(assign_splits(&ids, &ratios, 42), assigned);
+        let mut reversed = ids.clone();
+        reversed.reverse();
+        let mut again = assign_splits(&reversed, &ratios, 42);
+        again.reverse();
+        assert_eq!(again, assigned);
+        assert_ne!(assign_splits(&ids, &ratios, 43), assigned);
+
+        // Rounding never loses an image
+        assert_eq!(split_counts(5, &[1.0 / 3.0; 3]).iter().sum::<usize>(), 5);
+        assert_eq!(split_counts(0, &ratios), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_export_filter() {
+        let (_, collected) = sample();
+        let annotations = &collected[0].annotations;
+        let parse = |classes, block_state, created_after| {
+            ExportFilter::parse(&ExportOptions {
+                classes,
+                block_state,
+                created_after,
+                ..Default::default()
+            })
+        };
+
+        let everything = parse(None, None, None).unwrap();
+        assert!(annotations.iter().all(|a| everything.keeps_annotation(a)));
+
+        let doors = parse(Some("c-door, "), None, None).unwrap();
+        assert_eq!(doors.classes, Some(vec!["c-door".to_string()]));
+        assert!(annotations
+            .iter()
+            .all(|a| doors.keeps_annotation(a) == (a.class_id == "c-door")));
+
+        // Sample annotations were created at 2026-01-01T00:00:00Z
+        let recent = parse(None, None, Some("2026-01-01T00:00:00Z")).unwrap();
+        assert!(!annotations.iter().any(|a| recent.keeps_annotation(a)));
+        let older = parse(None, None, Some("2025-12-31T23:59:59+00:00")).unwrap();
+        assert!(annotations.iter().all(|a| older.keeps_annotation(a)));
+
+        let complete = parse(None, Some("complete,paid"), None).unwrap();
+        let block = |state: &str| Block {
+            block_id: "b1".to_string(),
+            project_id: "p".to_string(),
+            name: "Level 1".to_string(),
+            state: state.to_string(),
+            locked: false,
+            assigned_to: None,
+            reviewer: None,
+            created_at: String::new(),
+            annotation_count: 0,
+        };
+        assert!(complete.keeps_block(&block("paid")));
+        assert!(!complete.keeps_block(&block("draft")));
+
+        let rejected = Annotation {
+            review_status: "rejected".to_string(),
+            ..annotations[0].clone()
+        };
+        assert!(!everything.keeps_annotation(&rejected));
+        let with_rejected = ExportFilter::parse(&ExportOptions {
+            include_rejected: Some("true"),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(with_rejected.keeps_annotation(&rejected));
+
+        let model = |confidence: f32| Annotation {
+            source: "model".to_string(),
+            confidence: Some(confidence),
+            ..annotations[0].clone()
+        };
+        let confident = ExportFilter::parse(&ExportOptions {
+            min_confidence: Some("0.8"),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(confident.keeps_annotation(&model(0.9)));
+        assert!(!confident.keeps_annotation(&model(0.5)));
+        assert!(confident.keeps_annotation(&annotations[0]));
+        let humans = ExportFilter::parse(&ExportOptions {
+            source: Some("human"),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(humans.keeps_annotation(&annotations[0]));
+        assert!(!humans.keeps_annotation(&model(0.9)));
+        assert!(ExportFilter::parse(&ExportOptions {
+            source: Some("robot"),
+            ..Default::default()
+        })
+        .is_err());
+
+        let negatives = ExportFilter::parse(&ExportOptions {
+            image_status: Some("annotated,no_objects"),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut image = collected[0].image.clone();
+        assert!(!negatives.keeps_image(&image));
+        image.status = "no_objects".to_string();
+        assert!(negatives.keeps_image(&image));
+        assert!(everything.keeps_image(&image));
+        assert!(ExportFilter::parse(&ExportOptions {
+            image_status: Some("empty"),
+            ..Default::default()
+        })
+        .is_err());
+
+        assert!(parse(None, Some("done"), None).is_err());
+        assert!(parse(None, None, Some("yesterday")).is_err());
+    }
+
+    #[test]
+    fn test_parse_destination() {
+        let allowed = vec!["ml-datasets".to_string()];
+        let parse = |value: &str| parse_destination(value, "doxle-annotations", &allowed);
+        assert_eq!(
+            parse("s3://ml-datasets/floorplans/v3/").unwrap().uri(),
+            "s3://ml-datasets/floorplans/v3/"
+        );
+        assert_eq!(
+            parse("floorplans").unwrap().uri(),
+            "s3://doxle-annotations/datasets/floorplans/"
+        );
+        assert!(parse("s3://doxle-annotations/datasets/walls").is_ok());
+        assert!(parse("s3://doxle-annotations/projects/p").is_err());
+        assert!(parse("s3://someone-else/data").is_err());
+        assert!(parse("s3://ml-datasets").is_err());
+        assert!(parse("a/../b").is_err());
+    }
+
+    #[test]
+    fn test_imagefolder_record() {
+        let (classes, mut collected) = sample();
+        let mut point = collected[0].annotations[0].clone();
+        point.annotation_id = "a3".to_string();
+        point.geometry = Geometry::Point {
+            point: Point { x: 1.0, y: 2.0 },
+        };
+        collected[0].annotations.push(point);
+        let sized = SizedImage {
+            export: &collected[0],
+            width: 200,
+            height: 100,
+        };
+        let mut skipped = Vec::new();
+        let record = imagefolder_record(&classes, &sized, &mut skipped);
+
+        assert_eq!(record["file_name"], "img.jpg");
+        assert_eq!(record["width"], 200);
+        // Sorted: door=0, wall=1
+        assert_eq!(
+            record["objects"]["bbox"],
+            serde_json::json!([[10.0, 20.0, 20.0, 40.0], [0.0, 0.0, 100.0, 10.0]])
+        );
+        assert_eq!(record["objects"]["categories"], serde_json::json!([0, 1]));
+        assert_eq!(record["objects"]["id"], serde_json::json!(["a1", "a2"]));
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].annotation_id, "a3");
+        assert_eq!(imagefolder_dir(Some(1)), "validation/");
+        assert_eq!(imagefolder_dir(None), "");
+    }
+}
@@ -0,0 +1,725 @@
+use crate::export::{self, CropSource, Dataset, ExportResult, SkippedAnnotation, SkippedImage};
+use crate::types::Image;
+use crate::{image_processing, region};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::{Deserialize, Serialize};
+use std::io::{Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+/// Archives with image files in them (`images=` bundles and `format=crops`)
+/// are built off the request path. The API writes the label files and the
+/// images to add into a job document at
+/// `exports/{pid}/{eid}.job.json`; its ObjectCreated event runs the image
+/// worker, which streams `exports/{pid}/{eid}.zip` into a multipart upload.
+/// Hugging Face datasets go through the same job, copied into their
+/// destination instead of archived.
+/// PK=PROJECT#{pid}, SK=EXPORT#{eid} tracks the job for
+/// GET /projects/{pid}/exports/{eid}.
+pub const EXPORT_PREFIX: &str = "EXPORT#";
+
+const JOB_SUFFIX: &str = ".job.json";
+
+/// Status rows outlive the download links they hand out by a week
+const STATUS_TTL_SECS: i64 = 7 * 24 * 3600;
+
+/// Part size of the archive upload; S3 wants at least 5MB for every part
+/// but the last
+const PART_BYTES: usize = 16 * 1024 * 1024;
+
+/// Bundling stops before the archive's images would pass this many bytes.
+/// Only the image being added and one part are held in memory, so this
+/// bounds how long the worker runs rather than how much memory it needs.
+const MAX_BUNDLE_BYTES: u64 = 20 << 30;
+
+/// Work the image worker does for an export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportJob {
+    pub result: ExportResult, // summary so far; the worker adds what it bundles
+    pub files: Vec<JobFile>,  // label files, already built
+    pub images: Option<String>, // original | preview
+    pub bundle: Vec<Image>,
+    #[serde(default)]
+    pub crops: Vec<CropSource>, // format=crops: regions to cut out
+    #[serde(default)]
+    pub dataset: Option<Dataset>, // format=huggingface: files go here, not in an archive
+}
+
+/// An archive entry written by the API
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobFile {
+    pub path: String,
+    #[serde(with = "base64_bytes")]
+    pub bytes: Vec<u8>,
+}
+
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Where an export's archive is uploaded
+pub fn archive_key(project_id: &str, export_id: &str) -> String {
+    format!("exports/{}/{}.zip", project_id, export_id)
+}
+
+fn job_key(project_id: &str, export_id: &str) -> String {
+    format!("exports/{}/{}{}", project_id, export_id, JOB_SUFFIX)
+}
+
+/// (project_id, export_id) of a job document key
+fn parse_job_key(key: &str) -> Option<(&str, &str)> {
+    let rest = key.strip_prefix("exports/")?.strip_suffix(JOB_SUFFIX)?;
+    let (project_id, export_id) = rest.split_once('/')?;
+    (!project_id.is_empty() && !export_id.is_empty() && !export_id.contains('/'))
+        .then_some((project_id, export_id))
+}
+
+fn status_key(project_id: &str, export_id: &str) -> [(&'static str, AttributeValue); 2] {
+    [
+        ("PK", AttributeValue::S(format!("PROJECT#{}", project_id))),
+        (
+            "SK",
+            AttributeValue::S(format!("{}{}", EXPORT_PREFIX, export_id)),
+        ),
+    ]
+}
+
+/// Record the job's summary in its status row
+async fn put_status(
+    client: &DynamoClient,
+    table_name: &str,
+    result: &ExportResult,
+) -> Result<(), Error> {
+    let ttl = chrono::Utc::now().timestamp() + STATUS_TTL_SECS;
+    let mut put = client.put_item().table_name(table_name);
+    for (name, value) in status_key(&result.project_id, &result.export_id) {
+        put = put.item(name, value);
+    }
+    put.item("entity_type", AttributeValue::S("export".to_string()))
+        .item("status", AttributeValue::S(result.status.clone()))
+        .item("result", AttributeValue::S(serde_json::to_string(result)?))
+        .item("ttl", AttributeValue::N(ttl.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+async fn get_status(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    export_id: &str,
+) -> Result<Option<ExportResult>, Error> {
+    let mut get = client.get_item().table_name(table_name);
+    for (name, value) in status_key(project_id, export_id) {
+        get = get.key(name, value);
+    }
+    let output = get.send().await?;
+    let Some(result) = output
+        .item()
+        .and_then(|item| item.get("result"))
+        .and_then(|v| v.as_s().ok())
+    else {
+        return Ok(None);
+    };
+    Ok(Some(serde_json::from_str(result)?))
+}
+
+/// Hand an export to the image worker: mark it processing and write its job
+/// document
+pub async fn enqueue(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    job: &ExportJob,
+) -> Result<(), Error> {
+    put_status(client, table_name, &job.result).await?;
+    s3_client
+        .put_object()
+        .bucket(region::bucket_name())
+        .key(job_key(&job.result.project_id, &job.result.export_id))
+        .body(serde_json::to_vec(job)?.into())
+        .content_type("application/json")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to write export job: {}", e))?;
+    Ok(())
+}
+
+/// Output of the ZIP writer. zip only seeks back into the entry it is
+/// writing (to fill in its header), so everything before that entry is
+/// final and can be uploaded and dropped.
+#[derive(Default)]
+struct Spill {
+    buffer: Vec<u8>,
+    start: u64, // archive offset of buffer[0]
+    position: u64,
+}
+
+impl Spill {
+    /// Bytes before `offset`, once there are at least `min` of them
+    fn take_before(&mut self, offset: u64, min: usize) -> Option<Vec<u8>> {
+        let ready = (offset - self.start) as usize;
+        if ready == 0 || ready < min {
+            return None;
+        }
+        self.start = offset;
+        Some(self.buffer.drain(..ready).collect())
+    }
+}
+
+#[derive(Clone, Default)]
+struct SpillWriter(Arc<Mutex<Spill>>);
+
+impl SpillWriter {
+    fn lock(&self) -> std::io::Result<std::sync::MutexGuard<'_, Spill>> {
+        self.0
+            .lock()
+            .map_err(|_| std::io::Error::other("archive buffer poisoned"))
+    }
+}
+
+impl Write for SpillWriter {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        let mut spill = self.lock()?;
+        let at = (spill.position - spill.start) as usize;
+        let end = at + bytes.len();
+        if spill.buffer.len() < end {
+            spill.buffer.resize(end, 0);
+        }
+        spill.buffer[at..end].copy_from_slice(bytes);
+        spill.position += bytes.len() as u64;
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SpillWriter {
+    fn seek(&mut self, to: SeekFrom) -> std::io::Result<u64> {
+        let mut spill = self.lock()?;
+        let end = spill.start + spill.buffer.len() as u64;
+        let target = match to {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => end.checked_add_signed(delta),
+            SeekFrom::Current(delta) => spill.position.checked_add_signed(delta),
+        };
+        match target {
+            Some(target) if (spill.start..=end).contains(&target) => {
+                spill.position = target;
+                Ok(target)
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "seek into an uploaded part of the archive",
+            )),
+        }
+    }
+}
+
+/// The multipart upload an archive is written to
+struct PartUpload<'a> {
+    s3_client: &'a S3Client,
+    key: String,
+    upload_id: String,
+    parts: Vec<CompletedPart>,
+}
+
+impl PartUpload<'_> {
+    async fn upload_part(&mut self, bytes: Vec<u8>) -> Result<(), String> {
+        let part_number = self.parts.len() as i32 + 1;
+        let part = self
+            .s3_client
+            .upload_part()
+            .bucket(region::bucket_name())
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .part_number(part_number)
+            .body(bytes.into())
+            .send()
+            .await
+            .map_err(|e| format!("Failed to upload archive part {}: {}", part_number, e))?;
+        self.parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(part.e_tag().map(|t| t.to_string()))
+                .build(),
+        );
+        Ok(())
+    }
+
+    async fn complete(self) -> Result<(), String> {
+        self.s3_client
+            .complete_multipart_upload()
+            .bucket(region::bucket_name())
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(self.parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|e| format!("Failed to complete archive upload: {}", e))?;
+        Ok(())
+    }
+
+    async fn abort(self) {
+        if let Err(e) = self
+            .s3_client
+            .abort_multipart_upload()
+            .bucket(region::bucket_name())
+            .key(&self.key)
+            .upload_id(&self.upload_id)
+            .send()
+            .await
+        {
+            tracing::warn!("Failed to abort archive upload {}: {}", self.key, e);
+        }
+    }
+}
+
+/// A ZIP archive written straight into a multipart upload
+struct ArchiveUpload<'a> {
+    upload: PartUpload<'a>,
+    output: SpillWriter,
+    zip: zip::ZipWriter<SpillWriter>,
+}
+
+impl<'a> ArchiveUpload<'a> {
+    async fn start(s3_client: &'a S3Client, key: String) -> Result<Self, String> {
+        let created = s3_client
+            .create_multipart_upload()
+            .bucket(region::bucket_name())
+            .key(&key)
+            .content_type("application/zip")
+            .send()
+            .await
+            .map_err(|e| format!("Failed to start archive upload: {}", e))?;
+        let upload_id = created
+            .upload_id()
+            .ok_or("No upload id for archive upload")?
+            .to_string();
+        let output = SpillWriter::default();
+        Ok(Self {
+            upload: PartUpload {
+                s3_client,
+                key,
+                upload_id,
+                parts: Vec::new(),
+            },
+            zip: zip::ZipWriter::new(output.clone()),
+            output,
+        })
+    }
+
+    /// Begin an entry, uploading the finished ones before it once there is
+    /// a part's worth
+    async fn start_entry(&mut self, path: &str, size: u64) -> Result<(), String> {
+        let offset = self.output.lock().map_err(|e| e.to_string())?.position;
+        let options = export::entry_options(path).large_file(size >= u32::MAX as u64);
+        self.zip
+            .start_file(path, options)
+            .map_err(|e| format!("Failed to add {}: {}", path, e))?;
+        let part = self
+            .output
+            .lock()
+            .map_err(|e| e.to_string())?
+            .take_before(offset, PART_BYTES);
+        if let Some(part) = part {
+            self.upload.upload_part(part).await?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, path: &str, bytes: &[u8]) -> Result<(), String> {
+        self.zip
+            .write_all(bytes)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))
+    }
+
+    async fn add(&mut self, path: &str, bytes: &[u8]) -> Result<(), String> {
+        self.start_entry(path, bytes.len() as u64).await?;
+        self.write(path, bytes)
+    }
+
+    /// Write the central directory and complete the upload
+    async fn finish(self) -> Result<(), String> {
+        let Self {
+            mut upload,
+            output,
+            zip,
+        } = self;
+        zip.finish()
+            .map_err(|e| format!("Failed to finish archive: {}", e))?;
+        let rest = {
+            let mut spill = output.lock().map_err(|e| e.to_string())?;
+            let end = spill.start + spill.buffer.len() as u64;
+            spill.take_before(end, 1)
+        };
+        if let Some(rest) = rest {
+            upload.upload_part(rest).await?;
+        }
+        upload.complete().await
+    }
+
+    async fn abort(self) {
+        self.upload.abort().await
+    }
+}
+
+/// Stream each image into `images/` of the archive, one at a time
+async fn bundle_images(
+    s3_client: &S3Client,
+    archive: &mut ArchiveUpload<'_>,
+    images: &[Image],
+    level: &str,
+    result: &mut ExportResult,
+) -> Result<(), String> {
+    let mut total_bytes = 0u64;
+    for image in images {
+        let (file, object) = match export::open_bundle_image(s3_client, image, level).await {
+            Ok(opened) => opened,
+            Err(e) => {
+                tracing::warn!("Export: not bundling image {}: {}", image.image_id, e);
+                result.skipped_images.push(SkippedImage {
+                    image_id: image.image_id.clone(),
+                    reason: e,
+                });
+                continue;
+            }
+        };
+        let size = object.content_length().unwrap_or_default().max(0) as u64;
+        if total_bytes + size > MAX_BUNDLE_BYTES {
+            result.skipped_images.push(SkippedImage {
+                image_id: image.image_id.clone(),
+                reason: "Archive size limit reached".to_string(),
+            });
+            continue;
+        }
+
+        let path = format!("images/{}", file);
+        archive.start_entry(&path, size).await?;
+        let mut body = object.body;
+        while let Some(chunk) = body
+            .try_next()
+            .await
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?
+        {
+            archive.write(&path, &chunk)?;
+        }
+        total_bytes += size;
+        result.bundled_images += 1;
+    }
+    Ok(())
+}
+
+/// Cut each crop out of its source image, decoding every source once, and
+/// index the ones that could be cut in `manifest.csv`
+async fn cut_crops(
+    s3_client: &S3Client,
+    archive: &mut ArchiveUpload<'_>,
+    sources: &[CropSource],
+    result: &mut ExportResult,
+) -> Result<(), String> {
+    fn skip(result: &mut ExportResult, annotation_id: &str, reason: String) {
+        result.annotation_count = result.annotation_count.saturating_sub(1);
+        result.skipped.push(SkippedAnnotation {
+            annotation_id: annotation_id.to_string(),
+            reason,
+        });
+    }
+    let mut manifest = export::CROP_MANIFEST_HEADER.to_string();
+    for source in sources {
+        let decoded = export::load_source_image(s3_client, &source.image)
+            .await
+            .and_then(|bytes| {
+                image::load_from_memory(&bytes).map_err(|e| format!("Failed to load image: {}", e))
+            });
+        let image = match decoded {
+            Ok(image) => image,
+            Err(e) => {
+                tracing::warn!(
+                    "Crop export: skipping image {}: {}",
+                    source.image.image_id,
+                    e
+                );
+                for crop in &source.crops {
+                    skip(
+                        result,
+                        &crop.annotation_id,
+                        "Source image unavailable".to_string(),
+                    );
+                }
+                continue;
+            }
+        };
+        for crop in &source.crops {
+            match image_processing::crop_image(&image, crop.x, crop.y, crop.width, crop.height) {
+                Ok(png) => {
+                    archive.add(&crop.file, &png).await?;
+                    manifest.push_str(&export::crop_manifest_row(&source.image, crop));
+                }
+                Err(e) => skip(result, &crop.annotation_id, e),
+            }
+        }
+    }
+    archive.add("manifest.csv", manifest.as_bytes()).await
+}
+
+/// Copy each image of a dataset into place, then write the `metadata.jsonl`
+/// of every folder with the images that made it, and the job's label files
+async fn write_dataset(
+    s3_client: &S3Client,
+    dataset: &Dataset,
+    files: &[JobFile],
+    result: &mut ExportResult,
+) -> Result<(), String> {
+    let destination = &dataset.destination;
+    let mut metadata: std::collections::BTreeMap<&str, String> = Default::default();
+    for entry in &dataset.images {
+        let image = &entry.image;
+        let path = format!("{}{}", entry.dir, export::image_file_name(image));
+        if let Err(e) = export::copy_source_image(s3_client, image, destination, &path).await {
+            tracing::warn!("Dataset export: skipping image {}: {}", image.image_id, e);
+            result.skipped_images.push(SkippedImage {
+                image_id: image.image_id.clone(),
+                reason: e,
+            });
+            for annotation_id in &entry.annotation_ids {
+                result.annotation_count = result.annotation_count.saturating_sub(1);
+                result.skipped.push(SkippedAnnotation {
+                    annotation_id: annotation_id.clone(),
+                    reason: "Source image unavailable".to_string(),
+                });
+            }
+            continue;
+        }
+        let lines = metadata.entry(&entry.dir).or_default();
+        lines.push_str(&entry.record.to_string());
+        lines.push('\n');
+        result.bundled_images += 1;
+    }
+
+    for (dir, lines) in metadata {
+        let path = format!("{}metadata.jsonl", dir);
+        export::put_dataset_file(s3_client, destination, &path, lines.into_bytes()).await?;
+    }
+    for file in files {
+        export::put_dataset_file(s3_client, destination, &file.path, file.bytes.clone()).await?;
+    }
+    Ok(())
+}
+
+async fn write_archive(
+    s3_client: &S3Client,
+    archive: &mut ArchiveUpload<'_>,
+    job: &mut ExportJob,
+) -> Result<(), String> {
+    for file in &job.files {
+        archive.add(&file.path, &file.bytes).await?;
+    }
+    if !job.crops.is_empty() || job.result.format == "crops" {
+        cut_crops(s3_client, archive, &job.crops, &mut job.result).await?;
+    }
+    if let Some(level) = job.images.as_deref() {
+        bundle_images(s3_client, archive, &job.bundle, level, &mut job.result).await?;
+    }
+    Ok(())
+}
+
+/// Build the archive of a job document (image worker, on the document's
+/// ObjectCreated event). Other keys under `exports/`, and jobs already done
+/// when S3 redelivers the event, are ignored. A failure is recorded on the
+/// job and returned, so Lambda retries it.
+pub async fn run_job(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    key: &str,
+) -> Result<(), Error> {
+    let Some((project_id, export_id)) = parse_job_key(key) else {
+        return Ok(());
+    };
+    if let Some(status) = get_status(client, table_name, project_id, export_id).await? {
+        if status.status == "ready" {
+            return Ok(());
+        }
+    }
+
+    let document = s3_client
+        .get_object()
+        .bucket(region::bucket_name())
+        .key(key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to read export job {}: {}", key, e))?
+        .body
+        .collect()
+        .await
+        .map_err(|e| format!("Failed to read export job {}: {}", key, e))?
+        .into_bytes();
+    let mut job: ExportJob = serde_json::from_slice(&document)?;
+
+    let started = std::time::Instant::now();
+    let written = match job.dataset.take() {
+        Some(dataset) => write_dataset(s3_client, &dataset, &job.files, &mut job.result).await,
+        None => {
+            let mut archive =
+                ArchiveUpload::start(s3_client, archive_key(project_id, export_id)).await?;
+            match write_archive(s3_client, &mut archive, &mut job).await {
+                Ok(()) => archive.finish().await,
+                Err(e) => {
+                    archive.abort().await;
+                    Err(e)
+                }
+            }
+        }
+    };
+    if let Err(e) = written {
+        job.result.status = "failed".to_string();
+        job.result.error = Some(e.clone());
+        put_status(client, table_name, &job.result).await?;
+        return Err(e.into());
+    }
+
+    job.result.status = "ready".to_string();
+    job.result.error = None;
+    put_status(client, table_name, &job.result).await?;
+    tracing::info!(
+        "Export {} of project {}: {} annotations, {} images bundled, {} skipped in {:?}",
+        export_id,
+        project_id,
+        job.result.annotation_count,
+        job.result.bundled_images,
+        job.result.skipped_images.len(),
+        started.elapsed()
+    );
+
+    if let Err(e) = s3_client
+        .delete_object()
+        .bucket(region::bucket_name())
+        .key(key)
+        .send()
+        .await
+    {
+        tracing::warn!("Failed to delete export job {}: {}", key, e);
+    }
+    Ok(())
+}
+
+/// Progress of an export (GET /projects/{pid}/exports/{eid}): the export's
+/// summary, with a fresh download link once an archive is `ready`
+pub async fn get_export(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    project_id: &str,
+    export_id: &str,
+) -> Result<Response<Body>, Error> {
+    let Some(mut result) = get_status(client, table_name, project_id, export_id).await? else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "Export not found"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?);
+    };
+    if result.status == "ready" && result.destination.is_none() {
+        let key = archive_key(project_id, export_id);
+        result.download_url = Some(export::presign_download(s3_client, &key).await?);
+        result.expires_in = Some(export::DOWNLOAD_URL_TTL_SECS);
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&result)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_job_key() {
+        let key = job_key("p1", "e1");
+        assert_eq!(key, "exports/p1/e1.job.json");
+        assert_eq!(parse_job_key(&key), Some(("p1", "e1")));
+        // The archive it produces isn't a job
+        assert_eq!(parse_job_key(&archive_key("p1", "e1")), None);
+        assert_eq!(parse_job_key("exports/p1/x/e1.job.json"), None);
+        assert_eq!(parse_job_key("projects/p1/e1.job.json"), None);
+    }
+
+    #[test]
+    fn test_spilled_archive() {
+        // Spill after every entry: the parts still make a valid archive
+        let output = SpillWriter::default();
+        let mut zip = zip::ZipWriter::new(output.clone());
+        let mut parts: Vec<u8> = Vec::new();
+        for (path, bytes) in [
+            ("annotations.json", b"{}".to_vec()),
+            ("images/a.png", vec![7u8; 4000]),
+            ("images/b.jpg", vec![9u8; 100]),
+        ] {
+            let offset = output.lock().unwrap().position;
+            zip.start_file(path, export::entry_options(path)).unwrap();
+            let part = output.lock().unwrap().take_before(offset, 1);
+            parts.extend(part.unwrap_or_default());
+            zip.write_all(&bytes).unwrap();
+        }
+        zip.finish().unwrap();
+        let mut spill = output.lock().unwrap();
+        let end = spill.position;
+        parts.extend(spill.take_before(end, 1).unwrap());
+        // Nothing uploaded is seeked into again
+        assert!(spill.buffer.is_empty());
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(parts)).unwrap();
+        assert_eq!(archive.len(), 3);
+        let mut image = Vec::new();
+        archive
+            .by_name("images/a.png")
+            .unwrap()
+            .read_to_end(&mut image)
+            .unwrap();
+        assert_eq!(image, vec![7u8; 4000]);
+    }
+
+    #[test]
+    fn test_job_file_round_trip() {
+        let file = JobFile {
+            path: "crops/wall/a1.png".to_string(),
+            bytes: vec![0x89, b'P', b'N', b'G', 0],
+        };
+        let json = serde_json::to_value(&file).unwrap();
+        assert_eq!(json["bytes"], "iVBORwA=");
+        let parsed: JobFile = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.bytes, file.bytes);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::pagination::{self, PageRequest};
+use crate::responses::json_response;
+use crate::types::{BlockEvent, Comment};
+use crate::{blocks, comments, images, members, users};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+
+/// One chronological entry in a block's feed
+#[derive(Debug, Serialize)]
+#[serde(tag = "entry_type", rename_all = "snake_case")]
+pub enum FeedEntry {
+    Event(BlockEvent),
+    Comment(Comment),
+}
+
+impl FeedEntry {
+    fn created_at(&self) -> &str {
+        match self {
+            FeedEntry::Event(e) => &e.created_at,
+            FeedEntry::Comment(c) => &c.created_at,
+        }
+    }
+}
+
+/// Append a history event under the block (PK=BLOCK#{bid}, SK=EVENT#{ts}#{id})
+pub async fn record_block_event(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+    kind: &str,
+    from: Option<String>,
+    to: Option<String>,
+    user_id: &str,
+) -> Result<BlockEvent, Error> {
+    let event_id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let mut builder = client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(format!("BLOCK#{}", block_id)))
+        .item(
+            "SK",
+            AttributeValue::S(format!("EVENT#{}#{}", now, event_id)),
+        )
+        .item("kind", AttributeValue::S(kind.to_string()))
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item("created_at", AttributeValue::S(now.clone()));
+    if let Some(from) = &from {
+        builder = builder.item("from", AttributeValue::S(from.clone()));
+    }
+    if let Some(to) = &to {
+        builder = builder.item("to", AttributeValue::S(to.clone()));
+    }
+    builder.send().await?;
+
+    Ok(BlockEvent {
+        event_id,
+        block_id: block_id.to_string(),
+        kind: kind.to_string(),
+        from,
+        to,
+        user_id: user_id.to_string(),
+        created_at: now,
+    })
+}
+
+/// Fetch a block's history events, oldest first
+pub async fn fetch_block_events(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+) -> Result<Vec<BlockEvent>, Error> {
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        &format!("BLOCK#{}", block_id),
+        "EVENT#",
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+
+    Ok(page
+        .items
+        .iter()
+        .filter_map(|item| {
+            let get = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+            let sk = get("SK")?;
+            Some(BlockEvent {
+                event_id: sk.rsplit('#').next().unwrap_or_default().to_string(),
+                block_id: block_id.to_string(),
+                kind: get("kind").unwrap_or_default(),
+                from: get("from"),
+                to: get("to"),
+                user_id: get("user_id").unwrap_or_default(),
+                created_at: get("created_at").unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+/// All comments, state transitions and assignments for a block in
+/// chronological order (GET /projects/{pid}/blocks/{bid}/feed), for the
+/// project's members and admins
+pub async fn get_block_feed(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<Response<Body>, Error> {
+    if !members::is_member(client, table_name, project_id, user_id).await?
+        && !users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(
+            StatusCode::FORBIDDEN,
+            serde_json::json!({"error": "Not a member of this project"}),
+        );
+    }
+    if blocks::fetch_block(client, table_name, project_id, block_id)
+        .await?
+        .is_none()
+    {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Block not found"}),
+        );
+    }
+
+    let mut entries: Vec<FeedEntry> = fetch_block_events(client, table_name, block_id)
+        .await?
+        .into_iter()
+        .map(FeedEntry::Event)
+        .collect();
+
+    for image in images::fetch_block_images(client, table_name, block_id).await? {
+        for comment in comments::fetch_image_comments(client, table_name, &image.image_id).await? {
+            entries.push(FeedEntry::Comment(comment));
+        }
+    }
+
+    entries.sort_by(|a, b| a.created_at().cmp(b.created_at()));
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&entries)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_block_feed_checks_project() {
+        let member = serde_json::json!({
+            "PK": { "S": "PROJECT#p" },
+            "SK": { "S": "USER#u1" },
+        });
+        let (client, calls) = crate::test_util::fake_dynamo(vec![
+            member,
+            crate::test_util::assigned_block("other", "b", "u1"),
+        ]);
+
+        // The block is another project's
+        let response = get_block_feed(&client, "table", "u1", "p", "b")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Non-members get no feed at all
+        let response = get_block_feed(&client, "table", "u2", "other", "b")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!calls.lock().unwrap().iter().any(|op| op == "Query"));
+    }
+}
@@ -0,0 +1,861 @@
+use crate::types::{BoundingBox, Geometry, Point, Skeleton};
+
+/// Shoelace area of a closed ring, in square pixels
+fn ring_area(points: &[Point]) -> f64 {
+    if points.len() < 3 {
+        return 0.0;
+    }
+    let mut sum = 0.0;
+    for (i, a) in points.iter().enumerate() {
+        let b = &points[(i + 1) % points.len()];
+        sum += a.x * b.y - b.x * a.y;
+    }
+    (sum / 2.0).abs()
+}
+
+fn distance(a: &Point, b: &Point) -> f64 {
+    ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt()
+}
+
+/// Sum of segment lengths along a path
+fn path_length<'a>(segments: impl Iterator<Item = (&'a Point, &'a Point)>) -> f64 {
+    segments.map(|(a, b)| distance(a, b)).sum()
+}
+
+/// Largest mask side, in pixels
+pub const MAX_MASK_SIDE: u32 = 8192;
+
+/// Most runs a mask may have; keeps masks inside the default request body
+/// limit and annotation items under DynamoDB's 400KB
+pub const MAX_MASK_RUNS: usize = 30_000;
+
+/// Foreground runs of a mask as (start, length) pixel offsets, row-major
+fn mask_runs(counts: &[u32]) -> impl Iterator<Item = (u64, u64)> + '_ {
+    let mut offset = 0u64;
+    counts.iter().enumerate().filter_map(move |(i, &run)| {
+        let start = offset;
+        offset += run as u64;
+        (i % 2 == 1 && run > 0).then_some((start, run as u64))
+    })
+}
+
+/// Foreground pixel bounds of a mask as (min_x, min_y, max_x, max_y), inclusive
+fn mask_extent(width: u32, counts: &[u32]) -> Option<(u64, u64, u64, u64)> {
+    let width = width as u64;
+    if width == 0 {
+        return None;
+    }
+    let mut extent: Option<(u64, u64, u64, u64)> = None;
+    for (start, run) in mask_runs(counts) {
+        let end = start + run - 1;
+        let (first_row, last_row) = (start / width, end / width);
+        let (left, right) = if first_row == last_row {
+            (start % width, end % width)
+        } else {
+            (0, width - 1)
+        };
+        extent = Some(match extent {
+            None => (left, first_row, right, last_row),
+            Some((x0, y0, x1, y1)) => (
+                x0.min(left),
+                y0.min(first_row),
+                x1.max(right),
+                y1.max(last_row),
+            ),
+        });
+    }
+    extent
+}
+
+/// Decode a mask into a row-major bitmap of `width * height` pixels
+pub fn mask_bitmap(width: u32, height: u32, counts: &[u32]) -> Vec<bool> {
+    let size = width as usize * height as usize;
+    let mut bitmap = vec![false; size];
+    for (start, run) in mask_runs(counts) {
+        let start = (start as usize).min(size);
+        let end = (start + run as usize).min(size);
+        bitmap[start..end].fill(true);
+    }
+    bitmap
+}
+
+/// Every `type` tag `kind` returns
+pub const KINDS: [&str; 6] = ["polygon", "bbox", "point", "polyline", "keypoints", "mask"];
+
+/// Serialized `type` tag of a geometry
+pub fn kind(geometry: &Geometry) -> &'static str {
+    match geometry {
+        Geometry::Polygon { .. } => "polygon",
+        Geometry::BBox { .. } => "bbox",
+        Geometry::Point { .. } => "point",
+        Geometry::Polyline { .. } => "polyline",
+        Geometry::Keypoints { .. } => "keypoints",
+        Geometry::Mask { .. } => "mask",
+    }
+}
+
+/// Every coordinate that defines a geometry (labeled keypoints only; the
+/// foreground box corners for masks)
+pub fn vertices(geometry: &Geometry) -> Vec<Point> {
+    match geometry {
+        Geometry::Polygon { points } | Geometry::Polyline { points } => points.clone(),
+        Geometry::BBox { start, end } => vec![start.clone(), end.clone()],
+        Geometry::Point { point } => vec![point.clone()],
+        Geometry::Keypoints { keypoints } => keypoints
+            .iter()
+            .filter(|k| k.visibility > 0)
+            .map(|k| Point { x: k.x, y: k.y })
+            .collect(),
+        Geometry::Mask {
+            origin,
+            width,
+            counts,
+            ..
+        } => match mask_extent(*width, counts) {
+            Some((x0, y0, x1, y1)) => vec![
+                Point {
+                    x: origin.x + x0 as f64,
+                    y: origin.y + y0 as f64,
+                },
+                Point {
+                    x: origin.x + (x1 + 1) as f64,
+                    y: origin.y + (y1 + 1) as f64,
+                },
+            ],
+            None => vec![origin.clone()],
+        },
+    }
+}
+
+/// Reject geometries that can't be stored (e.g. NaN or infinite coordinates,
+/// polylines with fewer than two points, oversized or inconsistent masks)
+pub fn validate(geometry: &Geometry) -> Result<(), String> {
+    if vertices(geometry)
+        .iter()
+        .any(|p| !p.x.is_finite() || !p.y.is_finite())
+    {
+        return Err(format!("{} has a non-finite coordinate", kind(geometry)));
+    }
+    match geometry {
+        Geometry::Polyline { points } if points.len() < 2 => {
+            return Err("polyline needs at least 2 points".to_string());
+        }
+        Geometry::Keypoints { keypoints } => {
+            if keypoints.is_empty() {
+                return Err("keypoints needs at least 1 keypoint".to_string());
+            }
+            let mut names = std::collections::HashSet::new();
+            for k in keypoints {
+                if !k.x.is_finite() || !k.y.is_finite() {
+                    return Err(format!("keypoint '{}' has a non-finite coordinate", k.name));
+                }
+                if k.visibility > 2 {
+                    return Err(format!(
+                        "keypoint '{}' visibility must be 0, 1 or 2",
+                        k.name
+                    ));
+                }
+                if k.name.trim().is_empty() || !names.insert(k.name.as_str()) {
+                    return Err(format!(
+                        "keypoint names must be unique and non-empty ('{}')",
+                        k.name
+                    ));
+                }
+            }
+        }
+        Geometry::Mask {
+            width,
+            height,
+            counts,
+            ..
+        } => {
+            if *width == 0 || *height == 0 || *width > MAX_MASK_SIDE || *height > MAX_MASK_SIDE {
+                return Err(format!(
+                    "mask must be between 1x1 and {0}x{0} pixels",
+                    MAX_MASK_SIDE
+                ));
+            }
+            if counts.len() > MAX_MASK_RUNS {
+                return Err(format!("mask has more than {} runs", MAX_MASK_RUNS));
+            }
+            let pixels: u64 = counts.iter().map(|&c| c as u64).sum();
+            if pixels != *width as u64 * *height as u64 {
+                return Err(format!(
+                    "mask counts cover {} pixels, expected {}x{}",
+                    pixels, width, height
+                ));
+            }
+            if mask_runs(counts).next().is_none() {
+                return Err("mask has no foreground pixels".to_string());
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Check a class skeleton definition: unique names, edges within range
+pub fn validate_skeleton(skeleton: &Skeleton) -> Result<(), String> {
+    let mut names = std::collections::HashSet::new();
+    for name in &skeleton.keypoints {
+        if name.trim().is_empty() || !names.insert(name.as_str()) {
+            return Err(format!(
+                "skeleton keypoint names must be unique and non-empty ('{}')",
+                name
+            ));
+        }
+    }
+    let count = skeleton.keypoints.len();
+    if let Some([a, b]) = skeleton
+        .edges
+        .iter()
+        .find(|[a, b]| *a >= count || *b >= count || a == b)
+    {
+        return Err(format!(
+            "skeleton edge [{}, {}] is invalid for {} keypoints",
+            a, b, count
+        ));
+    }
+    Ok(())
+}
+
+/// Check a keypoints geometry against its class skeleton: every name must be defined
+pub fn validate_keypoints(geometry: &Geometry, skeleton: &Skeleton) -> Result<(), String> {
+    if let Geometry::Keypoints { keypoints } = geometry {
+        if let Some(unknown) = keypoints
+            .iter()
+            .find(|k| !skeleton.keypoints.contains(&k.name))
+        {
+            return Err(format!(
+                "keypoint '{}' is not in the class skeleton",
+                unknown.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Enclosed area of a geometry, in square pixels (zero for open shapes)
+pub fn area(geometry: &Geometry) -> f64 {
+    match geometry {
+        Geometry::Polygon { points } => ring_area(points),
+        Geometry::BBox { start, end } => ((end.x - start.x) * (end.y - start.y)).abs(),
+        Geometry::Point { .. } | Geometry::Polyline { .. } | Geometry::Keypoints { .. } => 0.0,
+        Geometry::Mask { counts, .. } => mask_runs(counts).map(|(_, run)| run as f64).sum(),
+    }
+}
+
+/// Outline length of a geometry (perimeter for closed shapes, path length
+/// for polylines; masks are not measured), in pixels
+pub fn length(geometry: &Geometry) -> f64 {
+    match geometry {
+        Geometry::Polygon { points } => {
+            if points.len() < 2 {
+                return 0.0;
+            }
+            path_length(points.iter().zip(points.iter().cycle().skip(1)))
+        }
+        Geometry::BBox { start, end } => 2.0 * ((end.x - start.x).abs() + (end.y - start.y).abs()),
+        Geometry::Point { .. } | Geometry::Keypoints { .. } | Geometry::Mask { .. } => 0.0,
+        Geometry::Polyline { points } => path_length(points.iter().zip(points.iter().skip(1))),
+    }
+}
+
+/// Axis-aligned bounds of a geometry as (min, max) corners, `None` when empty
+pub fn bounds(geometry: &Geometry) -> Option<(Point, Point)> {
+    let points = vertices(geometry);
+    let first = points.first()?;
+    let mut min = Point {
+        x: first.x,
+        y: first.y,
+    };
+    let mut max = Point {
+        x: first.x,
+        y: first.y,
+    };
+    for p in &points[1..] {
+        min.x = min.x.min(p.x);
+        min.y = min.y.min(p.y);
+        max.x = max.x.max(p.x);
+        max.y = max.y.max(p.y);
+    }
+    Some((min, max))
+}
+
+/// Bounds as an origin plus size, as stored on annotations
+pub fn bounding_box(geometry: &Geometry) -> Option<BoundingBox> {
+    let (min, max) = bounds(geometry)?;
+    Some(BoundingBox {
+        x: min.x,
+        y: min.y,
+        width: max.x - min.x,
+        height: max.y - min.y,
+    })
+}
+
+/// Project coordinate conventions: image pixels, or fractions of the image
+/// width/height (0–1)
+pub const COORDINATE_MODES: [&str; 2] = ["pixel", "normalized"];
+
+/// Encode a row-major bitmap as mask counts (runs alternating
+/// background/foreground, starting with background)
+pub fn mask_counts(bitmap: &[bool]) -> Vec<u32> {
+    let mut counts = vec![0];
+    let mut foreground = false;
+    for &pixel in bitmap {
+        if pixel != foreground {
+            counts.push(0);
+            foreground = pixel;
+        }
+        *counts.last_mut().unwrap() += 1;
+    }
+    counts
+}
+
+/// Resize a geometry for an image whose pixels were scaled by `sx`, `sy`.
+/// Like `scale`, but masks are resampled (nearest neighbour) onto the new grid.
+pub fn resize(geometry: &mut Geometry, sx: f64, sy: f64) -> Result<(), String> {
+    let Geometry::Mask {
+        origin,
+        width,
+        height,
+        counts,
+    } = geometry
+    else {
+        return scale(geometry, sx, sy);
+    };
+    if !(sx > 0.0 && sy > 0.0 && sx.is_finite() && sy.is_finite()) {
+        return Err("scale factors must be positive".to_string());
+    }
+    let new_width = ((*width as f64 * sx).round() as u32).clamp(1, MAX_MASK_SIDE);
+    let new_height = ((*height as f64 * sy).round() as u32).clamp(1, MAX_MASK_SIDE);
+    let source = mask_bitmap(*width, *height, counts);
+    let mut resized = Vec::with_capacity(new_width as usize * new_height as usize);
+    for y in 0..new_height {
+        let src_y = ((y as f64 + 0.5) / sy).floor().min(*height as f64 - 1.0) as usize;
+        for x in 0..new_width {
+            let src_x = ((x as f64 + 0.5) / sx).floor().min(*width as f64 - 1.0) as usize;
+            resized.push(source[src_y * *width as usize + src_x]);
+        }
+    }
+    origin.x = (origin.x * sx).round();
+    origin.y = (origin.y * sy).round();
+    *width = new_width;
+    *height = new_height;
+    *counts = mask_counts(&resized);
+    Ok(())
+}
+
+/// Multiply every x by `sx` and y by `sy` (e.g. to convert between pixel and
+/// normalized coordinates). Masks live on the pixel grid and can't be scaled.
+pub fn scale(geometry: &mut Geometry, sx: f64, sy: f64) -> Result<(), String> {
+    let scale_point = |p: &mut Point| {
+        p.x *= sx;
+        p.y *= sy;
+    };
+    match geometry {
+        Geometry::Polygon { points } | Geometry::Polyline { points } => {
+            points.iter_mut().for_each(scale_point)
+        }
+        Geometry::BBox { start, end } => {
+            scale_point(start);
+            scale_point(end);
+        }
+        Geometry::Point { point } => scale_point(point),
+        Geometry::Keypoints { keypoints } => {
+            for k in keypoints {
+                k.x *= sx;
+                k.y *= sy;
+            }
+        }
+        Geometry::Mask { .. } => {
+            return Err("mask geometry can only be used with pixel coordinates".to_string())
+        }
+    }
+    Ok(())
+}
+
+/// Shift every vertex by `dx`, `dy`. Masks move their origin, rounded to
+/// the pixel grid.
+pub fn translate(geometry: &mut Geometry, dx: f64, dy: f64) {
+    let shift = |p: &mut Point| {
+        p.x += dx;
+        p.y += dy;
+    };
+    match geometry {
+        Geometry::Polygon { points } | Geometry::Polyline { points } => {
+            points.iter_mut().for_each(shift)
+        }
+        Geometry::BBox { start, end } => {
+            shift(start);
+            shift(end);
+        }
+        Geometry::Point { point } => shift(point),
+        Geometry::Keypoints { keypoints } => {
+            for k in keypoints {
+                k.x += dx;
+                k.y += dy;
+            }
+        }
+        Geometry::Mask { origin, .. } => {
+            origin.x = (origin.x + dx).round();
+            origin.y = (origin.y + dy).round();
+        }
+    }
+}
+
+/// Check a geometry fits a normalized project: every vertex in 0–1, no masks
+pub fn check_normalized(geometry: &Geometry) -> Result<(), String> {
+    if matches!(geometry, Geometry::Mask { .. }) {
+        return Err("mask geometry can only be used with pixel coordinates".to_string());
+    }
+    let in_range = |v: f64| (0.0..=1.0).contains(&v);
+    if vertices(geometry)
+        .iter()
+        .any(|p| !in_range(p.x) || !in_range(p.y))
+    {
+        return Err(format!(
+            "{} coordinates must be normalized (0-1) in this project",
+            kind(geometry)
+        ));
+    }
+    Ok(())
+}
+
+/// Largest simplification tolerance accepted, in pixels
+pub const MAX_SIMPLIFY_TOLERANCE: f64 = 100.0;
+
+/// Distance from `p` to the segment a-b
+fn segment_distance(p: &Point, a: &Point, b: &Point) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let len_sq = dx * dx + dy * dy;
+    if len_sq == 0.0 {
+        return distance(p, a);
+    }
+    let t = (((p.x - a.x) * dx + (p.y - a.y) * dy) / len_sq).clamp(0.0, 1.0);
+    distance(
+        p,
+        &Point {
+            x: a.x + t * dx,
+            y: a.y + t * dy,
+        },
+    )
+}
+
+/// Douglas–Peucker over `points[first..=last]`, marking survivors in `keep`
+/// (the endpoints are always kept)
+fn douglas_peucker(points: &[Point], first: usize, last: usize, tolerance: f64, keep: &mut [bool]) {
+    keep[first] = true;
+    keep[last] = true;
+    let mut stack = vec![(first, last)];
+    while let Some((start, end)) = stack.pop() {
+        let mut farthest = (0.0, start);
+        for i in start + 1..end {
+            let d = segment_distance(&points[i], &points[start], &points[end]);
+            if d > farthest.0 {
+                farthest = (d, i);
+            }
+        }
+        if farthest.0 > tolerance {
+            keep[farthest.1] = true;
+            stack.push((start, farthest.1));
+            stack.push((farthest.1, end));
+        }
+    }
+}
+
+/// Drop vertices that deviate less than `tolerance` pixels from the simplified
+/// outline (Douglas–Peucker). Applies to polygons and polylines; other shapes
+/// are left alone. Returns how many vertices were removed.
+pub fn simplify(geometry: &mut Geometry, tolerance: f64) -> usize {
+    match geometry {
+        Geometry::Polygon { points } if points.len() > 3 => {
+            // Split the ring at the vertex farthest from the first one and
+            // simplify both halves; index n stands for vertex 0 again
+            let n = points.len();
+            let mut ring = points.clone();
+            ring.push(points[0].clone());
+            let split = (1..n)
+                .max_by(|&a, &b| {
+                    distance(&ring[0], &ring[a]).total_cmp(&distance(&ring[0], &ring[b]))
+                })
+                .unwrap_or(1);
+            let mut keep = vec![false; n + 1];
+            douglas_peucker(&ring, 0, split, tolerance, &mut keep);
+            douglas_peucker(&ring, split, n, tolerance, &mut keep);
+            if keep[..n].iter().filter(|&&k| k).count() < 3 {
+                return 0;
+            }
+            let before = points.len();
+            *points = ring
+                .into_iter()
+                .take(n)
+                .zip(keep)
+                .filter_map(|(p, k)| k.then_some(p))
+                .collect();
+            before - points.len()
+        }
+        Geometry::Polyline { points } if points.len() > 2 => {
+            let mut keep = vec![false; points.len()];
+            douglas_peucker(points, 0, points.len() - 1, tolerance, &mut keep);
+            let before = points.len();
+            let mut flags = keep.into_iter();
+            points.retain(|_| flags.next().unwrap_or(true));
+            before - points.len()
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    #[test]
+    fn test_polygon_area_and_length() {
+        let square = Geometry::Polygon {
+            points: vec![p(0.0, 0.0), p(10.0, 0.0), p(10.0, 10.0), p(0.0, 10.0)],
+        };
+        assert_eq!(area(&square), 100.0);
+        assert_eq!(length(&square), 40.0);
+
+        // Winding order does not change the area
+        let triangle = Geometry::Polygon {
+            points: vec![p(0.0, 0.0), p(0.0, 3.0), p(4.0, 0.0)],
+        };
+        assert_eq!(area(&triangle), 6.0);
+        assert_eq!(length(&triangle), 12.0);
+    }
+
+    #[test]
+    fn test_bbox_area_and_length() {
+        let bbox = Geometry::BBox {
+            start: p(5.0, 5.0),
+            end: p(1.0, 2.0),
+        };
+        assert_eq!(area(&bbox), 12.0);
+        assert_eq!(length(&bbox), 14.0);
+    }
+
+    #[test]
+    fn test_degenerate_polygon() {
+        let empty = Geometry::Polygon { points: vec![] };
+        assert_eq!(area(&empty), 0.0);
+        assert_eq!(length(&empty), 0.0);
+        assert!(bounds(&empty).is_none());
+    }
+
+    #[test]
+    fn test_point() {
+        let pin = Geometry::Point { point: p(3.0, 4.0) };
+        assert_eq!(area(&pin), 0.0);
+        assert_eq!(length(&pin), 0.0);
+        let (min, max) = bounds(&pin).unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (3.0, 4.0, 3.0, 4.0));
+        assert!(validate(&pin).is_ok());
+        assert!(validate(&Geometry::Point {
+            point: p(f64::NAN, 0.0)
+        })
+        .is_err());
+
+        // Tagged as {"type": "point", "point": {...}}
+        let json = serde_json::to_value(&pin).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"type": "point", "point": {"x": 3.0, "y": 4.0}})
+        );
+        let parsed: Geometry = serde_json::from_value(json).unwrap();
+        assert_eq!(kind(&parsed), "point");
+    }
+
+    #[test]
+    fn test_polyline() {
+        let pipe = Geometry::Polyline {
+            points: vec![p(0.0, 0.0), p(3.0, 4.0), p(3.0, 10.0)],
+        };
+        // Open path: no closing segment, no area
+        assert_eq!(length(&pipe), 11.0);
+        assert_eq!(area(&pipe), 0.0);
+        assert_eq!(kind(&pipe), "polyline");
+        assert!(KINDS.contains(&kind(&pipe)));
+        assert!(validate(&pipe).is_ok());
+
+        let short = Geometry::Polyline {
+            points: vec![p(1.0, 1.0)],
+        };
+        assert!(validate(&short).is_err());
+    }
+
+    #[test]
+    fn test_keypoints() {
+        use crate::types::Keypoint;
+        let kp = |name: &str, x: f64, y: f64, visibility: u8| Keypoint {
+            name: name.to_string(),
+            x,
+            y,
+            visibility,
+        };
+        let pose = Geometry::Keypoints {
+            keypoints: vec![
+                kp("head", 5.0, 1.0, 2),
+                kp("hip", 4.0, 9.0, 1),
+                kp("foot", 0.0, 0.0, 0),
+            ],
+        };
+        assert!(validate(&pose).is_ok());
+        // Unlabeled keypoints don't contribute to bounds
+        let (min, max) = bounds(&pose).unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (4.0, 1.0, 5.0, 9.0));
+
+        let duplicate = Geometry::Keypoints {
+            keypoints: vec![kp("head", 0.0, 0.0, 2), kp("head", 1.0, 1.0, 2)],
+        };
+        assert!(validate(&duplicate).is_err());
+        let bad_visibility = Geometry::Keypoints {
+            keypoints: vec![kp("head", 0.0, 0.0, 3)],
+        };
+        assert!(validate(&bad_visibility).is_err());
+
+        let skeleton = crate::types::Skeleton {
+            keypoints: vec!["head".to_string(), "hip".to_string(), "foot".to_string()],
+            edges: vec![[0, 1], [1, 2]],
+        };
+        assert!(validate_skeleton(&skeleton).is_ok());
+        assert!(validate_keypoints(&pose, &skeleton).is_ok());
+        let stray = Geometry::Keypoints {
+            keypoints: vec![kp("tail", 0.0, 0.0, 2)],
+        };
+        assert!(validate_keypoints(&stray, &skeleton).is_err());
+        let broken = crate::types::Skeleton {
+            keypoints: vec!["head".to_string()],
+            edges: vec![[0, 1]],
+        };
+        assert!(validate_skeleton(&broken).is_err());
+    }
+
+    #[test]
+    fn test_mask() {
+        // 4x3 mask:
+        // . # # .
+        // . # # #
+        // . . . .
+        let mask = Geometry::Mask {
+            origin: p(10.0, 20.0),
+            width: 4,
+            height: 3,
+            counts: vec![1, 2, 2, 3, 4],
+        };
+        assert!(validate(&mask).is_ok());
+        assert_eq!(kind(&mask), "mask");
+        assert_eq!(area(&mask), 5.0);
+        let (min, max) = bounds(&mask).unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (11.0, 20.0, 14.0, 22.0));
+        assert_eq!(
+            mask_bitmap(4, 3, &[1, 2, 2, 3, 4]),
+            vec![false, true, true, false, false, true, true, true, false, false, false, false]
+        );
+
+        let short = Geometry::Mask {
+            origin: p(0.0, 0.0),
+            width: 4,
+            height: 3,
+            counts: vec![1, 2],
+        };
+        assert!(validate(&short).is_err());
+        let empty = Geometry::Mask {
+            origin: p(0.0, 0.0),
+            width: 2,
+            height: 2,
+            counts: vec![4],
+        };
+        assert!(validate(&empty).is_err());
+        let huge = Geometry::Mask {
+            origin: p(0.0, 0.0),
+            width: MAX_MASK_SIDE + 1,
+            height: 1,
+            counts: vec![0, MAX_MASK_SIDE + 1],
+        };
+        assert!(validate(&huge).is_err());
+    }
+
+    #[test]
+    fn test_bounds() {
+        let triangle = Geometry::Polygon {
+            points: vec![p(4.0, 1.0), p(0.0, 3.0), p(2.0, -1.0)],
+        };
+        let (min, max) = bounds(&triangle).unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (0.0, -1.0, 4.0, 3.0));
+
+        // BBox corners may be given in any order
+        let bbox = Geometry::BBox {
+            start: p(5.0, 5.0),
+            end: p(1.0, 2.0),
+        };
+        let (min, max) = bounds(&bbox).unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (1.0, 2.0, 5.0, 5.0));
+        assert_eq!(
+            bounding_box(&bbox),
+            Some(BoundingBox {
+                x: 1.0,
+                y: 2.0,
+                width: 4.0,
+                height: 3.0
+            })
+        );
+    }
+
+    #[test]
+    fn test_translate() {
+        let mut polygon = Geometry::Polygon {
+            points: vec![p(0.0, 0.0), p(10.0, 0.0), p(10.0, 10.0)],
+        };
+        translate(&mut polygon, 5.0, -2.5);
+        assert_eq!(
+            vertices(&polygon)
+                .iter()
+                .map(|v| (v.x, v.y))
+                .collect::<Vec<_>>(),
+            vec![(5.0, -2.5), (15.0, -2.5), (15.0, 7.5)]
+        );
+
+        let mut mask = Geometry::Mask {
+            origin: p(3.0, 1.0),
+            width: 2,
+            height: 2,
+            counts: vec![0, 1, 2, 1],
+        };
+        translate(&mut mask, 1.4, 2.6);
+        match &mask {
+            Geometry::Mask { origin, counts, .. } => {
+                assert_eq!((origin.x, origin.y), (4.0, 4.0));
+                assert_eq!(counts, &vec![0, 1, 2, 1]);
+            }
+            other => panic!("expected mask, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut bbox = Geometry::BBox {
+            start: p(10.0, 10.0),
+            end: p(20.0, 40.0),
+        };
+        resize(&mut bbox, 2.0, 0.5).unwrap();
+        let (min, max) = bounds(&bbox).unwrap();
+        assert_eq!((min.x, min.y, max.x, max.y), (20.0, 5.0, 40.0, 20.0));
+
+        // 2x2 mask with a diagonal, doubled
+        let mut mask = Geometry::Mask {
+            origin: p(3.0, 1.0),
+            width: 2,
+            height: 2,
+            counts: vec![0, 1, 2, 1],
+        };
+        resize(&mut mask, 2.0, 2.0).unwrap();
+        match &mask {
+            Geometry::Mask {
+                origin,
+                width,
+                height,
+                counts,
+            } => {
+                assert_eq!((origin.x, origin.y, *width, *height), (6.0, 2.0, 4, 4));
+                assert_eq!(counts, &vec![0, 2, 2, 2, 4, 2, 2, 2]);
+            }
+            other => panic!("expected mask, got {:?}", other),
+        }
+        assert!(validate(&mask).is_ok());
+        assert_eq!(area(&mask), 8.0);
+        assert_eq!(mask_counts(&[false, false, true]), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_scale_and_check_normalized() {
+        let mut bbox = Geometry::BBox {
+            start: p(20.0, 10.0),
+            end: p(200.0, 50.0),
+        };
+        assert!(check_normalized(&bbox).is_err());
+        scale(&mut bbox, 1.0 / 200.0, 1.0 / 100.0).unwrap();
+        assert!(check_normalized(&bbox).is_ok());
+        assert_eq!(
+            bounding_box(&bbox),
+            Some(BoundingBox {
+                x: 0.1,
+                y: 0.1,
+                width: 0.9,
+                height: 0.4
+            })
+        );
+
+        let mut mask = Geometry::Mask {
+            origin: p(0.0, 0.0),
+            width: 2,
+            height: 1,
+            counts: vec![0, 2],
+        };
+        assert!(scale(&mut mask, 2.0, 2.0).is_err());
+        assert!(check_normalized(&mask).is_err());
+    }
+
+    #[test]
+    fn test_simplify_polygon() {
+        // Square with redundant points along its edges, one slightly off the line
+        let mut freehand = Geometry::Polygon {
+            points: vec![
+                p(0.0, 0.0),
+                p(5.0, 0.1),
+                p(10.0, 0.0),
+                p(10.0, 5.0),
+                p(10.0, 10.0),
+                p(5.0, 10.0),
+                p(0.0, 10.0),
+                p(0.0, 5.0),
+            ],
+        };
+        assert_eq!(simplify(&mut freehand, 0.5), 4);
+        assert_eq!(area(&freehand), 100.0);
+        let Geometry::Polygon { points } = &freehand else {
+            unreachable!()
+        };
+        assert_eq!(points.len(), 4);
+        assert_eq!((points[0].x, points[0].y), (0.0, 0.0));
+
+        // Below the tolerance nothing is dropped
+        let mut kept = Geometry::Polygon {
+            points: vec![
+                p(0.0, 0.0),
+                p(5.0, 2.0),
+                p(10.0, 0.0),
+                p(10.0, 10.0),
+                p(0.0, 10.0),
+            ],
+        };
+        assert_eq!(simplify(&mut kept, 1.0), 0);
+
+        // Never collapses a polygon below a triangle
+        let mut sliver = Geometry::Polygon {
+            points: vec![p(0.0, 0.0), p(5.0, 0.01), p(10.0, 0.0), p(5.0, -0.01)],
+        };
+        assert_eq!(simplify(&mut sliver, 1.0), 0);
+    }
+
+    #[test]
+    fn test_simplify_polyline() {
+        let mut line = Geometry::Polyline {
+            points: (0..=100).map(|i| p(i as f64, 0.0)).collect(),
+        };
+        assert_eq!(simplify(&mut line, 0.1), 99);
+        assert_eq!(length(&line), 100.0);
+
+        let mut bbox = Geometry::BBox {
+            start: p(0.0, 0.0),
+            end: p(1.0, 1.0),
+        };
+        assert_eq!(simplify(&mut bbox, 1.0), 0);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::annotations::{annotation_from_item, fetch_image_annotations};
+use crate::audit::AuditEntry;
+use crate::responses::json_response;
+use crate::types::{Annotation, CreateGroupRequest};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Most annotations one request can add to a group
+const MAX_GROUP_SIZE: usize = 500;
+
+/// Annotations sharing a `group_id` on one image
+#[derive(Debug, Serialize, PartialEq)]
+pub struct AnnotationGroup {
+    pub group_id: String,
+    pub annotation_ids: Vec<String>,
+}
+
+/// Groups formed by an image's annotations, ordered by group id
+pub fn groups_of(annotations: &[Annotation]) -> Vec<AnnotationGroup> {
+    let mut groups: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for annotation in annotations {
+        if let Some(group_id) = &annotation.group_id {
+            groups
+                .entry(group_id)
+                .or_default()
+                .push(annotation.annotation_id.clone());
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(group_id, annotation_ids)| AnnotationGroup {
+            group_id: group_id.to_string(),
+            annotation_ids,
+        })
+        .collect()
+}
+
+/// Distinct requested ids, in request order. A new group needs at least two
+/// members; adding to an existing one needs one.
+fn requested_members(req: &CreateGroupRequest) -> Result<Vec<String>, String> {
+    let mut ids: Vec<String> = Vec::with_capacity(req.annotation_ids.len());
+    for id in &req.annotation_ids {
+        let id = id.trim();
+        if !id.is_empty() && !ids.iter().any(|seen| seen == id) {
+            ids.push(id.to_string());
+        }
+    }
+    let minimum = if req.group_id.is_some() { 1 } else { 2 };
+    if ids.len() < minimum {
+        return Err(format!(
+            "A group needs at least {} distinct annotation ids",
+            minimum
+        ));
+    }
+    if ids.len() > MAX_GROUP_SIZE {
+        return Err(format!(
+            "At most {} annotations can be grouped at once",
+            MAX_GROUP_SIZE
+        ));
+    }
+    Ok(ids)
+}
+
+/// Set or clear a live annotation's group. Returns None when there's no live
+/// annotation. Grouping isn't an edit of the shape, so it doesn't bump the
+/// version or send the annotation back for review.
+async fn set_group(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    annotation_id: &str,
+    group_id: Option<&str>,
+) -> Result<Option<Annotation>, Error> {
+    let mut builder = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .key(
+            "SK",
+            AttributeValue::S(format!("ANNOTATION#{}", annotation_id)),
+        )
+        .condition_expression("attribute_exists(PK) AND attribute_not_exists(#deleted_at)")
+        .expression_attribute_names("#deleted_at", "deleted_at")
+        .expression_attribute_names("#group_id", "group_id")
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew);
+    builder = match group_id {
+        Some(group_id) => builder
+            .update_expression("SET #group_id = :group_id")
+            .expression_attribute_values(":group_id", AttributeValue::S(group_id.to_string())),
+        None => builder.update_expression("REMOVE #group_id"),
+    };
+    match builder.send().await {
+        Ok(output) => Ok(output
+            .attributes()
+            .map(|item| annotation_from_item(annotation_id, image_id, item))),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Live annotations of an image in one group
+async fn group_members(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    group_id: &str,
+) -> Result<Vec<Annotation>, Error> {
+    Ok(fetch_image_annotations(client, table_name, image_id)
+        .await?
+        .into_iter()
+        .filter(|a| a.group_id.as_deref() == Some(group_id))
+        .collect())
+}
+
+/// Audit a grouping change, filed under the image's project
+async fn audit(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    action: &str,
+    summary: &serde_json::Value,
+) -> Result<(), Error> {
+    let location = crate::images::image_location(client, table_name, image_id)
+        .await?
+        .unwrap_or_default();
+    let entry = AuditEntry::new(&location.project_id, user_id, action, "image", image_id)
+        .block(&location.block_id)
+        .after(summary);
+    crate::audit::record(client, table_name, entry).await;
+    Ok(())
+}
+
+fn group_not_found() -> Result<Response<Body>, Error> {
+    json_response(
+        StatusCode::NOT_FOUND,
+        serde_json::json!({"error": "Group not found"}),
+    )
+}
+
+/// Group annotations of an image (POST /images/{iid}/groups, body
+/// `{"annotation_ids", "group_id"}`). Without a group id a new group is
+/// started; annotations already in another group move to this one. Ids that
+/// aren't live annotations are reported as missing.
+pub async fn create_group(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: CreateGroupRequest = serde_json::from_slice(body)?;
+    let ids = match requested_members(&req) {
+        Ok(ids) => ids,
+        Err(message) => {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                serde_json::json!({ "error": message }),
+            )
+        }
+    };
+    if let Some(response) =
+        crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    let (group_id, status) = match req.group_id.as_deref().map(str::trim) {
+        Some(group_id) => {
+            if group_members(client, table_name, image_id, group_id)
+                .await?
+                .is_empty()
+            {
+                return group_not_found();
+            }
+            (group_id.to_string(), StatusCode::OK)
+        }
+        None => (uuid::Uuid::new_v4().to_string(), StatusCode::CREATED),
+    };
+
+    let mut added = Vec::new();
+    let mut missing = Vec::new();
+    for annotation_id in ids {
+        match set_group(
+            client,
+            table_name,
+            image_id,
+            &annotation_id,
+            Some(&group_id),
+        )
+        .await?
+        {
+            Some(_) => added.push(annotation_id),
+            None => missing.push(annotation_id),
+        }
+    }
+    if added.is_empty() {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "None of the annotations were found", "missing": missing}),
+        );
+    }
+
+    let members = group_members(client, table_name, image_id, &group_id).await?;
+    let summary = serde_json::json!({"group_id": group_id, "added": added});
+    audit(client, table_name, user_id, image_id, "grouped", &summary).await?;
+    json_response(
+        status,
+        serde_json::json!({
+            "group_id": group_id,
+            "image_id": image_id,
+            "annotation_ids": members.iter().map(|a| &a.annotation_id).collect::<Vec<_>>(),
+            "added": added,
+            "missing": missing,
+        }),
+    )
+}
+
+/// Groups on an image with their member ids (GET /images/{iid}/groups)
+pub async fn list_groups(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+) -> Result<Response<Body>, Error> {
+    let annotations = fetch_image_annotations(client, table_name, image_id).await?;
+    json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "image_id": image_id,
+            "groups": groups_of(&annotations),
+        }),
+    )
+}
+
+/// The annotations in a group (GET /images/{iid}/groups/{gid})
+pub async fn get_group(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    group_id: &str,
+) -> Result<Response<Body>, Error> {
+    let members = group_members(client, table_name, image_id, group_id).await?;
+    if members.is_empty() {
+        return group_not_found();
+    }
+    json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "group_id": group_id,
+            "image_id": image_id,
+            "annotations": members,
+        }),
+    )
+}
+
+/// Break a group up, leaving its annotations ungrouped
+/// (DELETE /images/{iid}/groups/{gid})
+pub async fn delete_group(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    image_id: &str,
+    group_id: &str,
+) -> Result<Response<Body>, Error> {
+    let members = group_members(client, table_name, image_id, group_id).await?;
+    if members.is_empty() {
+        return group_not_found();
+    }
+    if let Some(response) =
+        crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    let mut ungrouped = Vec::with_capacity(members.len());
+    for annotation in &members {
+        if set_group(
+            client,
+            table_name,
+            image_id,
+            &annotation.annotation_id,
+            None,
+        )
+        .await?
+        .is_some()
+        {
+            ungrouped.push(&annotation.annotation_id);
+        }
+    }
+    let summary = serde_json::json!({"group_id": group_id, "ungrouped": ungrouped});
+    audit(client, table_name, user_id, image_id, "ungrouped", &summary).await?;
+    json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "group_id": group_id,
+            "image_id": image_id,
+            "ungrouped": ungrouped,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(ids: &[&str], group_id: Option<&str>) -> CreateGroupRequest {
+        CreateGroupRequest {
+            annotation_ids: ids.iter().map(|id| id.to_string()).collect(),
+            group_id: group_id.map(|g| g.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_requested_members() {
+        assert_eq!(
+            requested_members(&request(&["a1", " a2 ", "a1", ""], None)).unwrap(),
+            vec!["a1", "a2"]
+        );
+        // A new group of one isn't a group
+        assert!(requested_members(&request(&["a1", "a1"], None)).is_err());
+        assert_eq!(
+            requested_members(&request(&["a3"], Some("g1"))).unwrap(),
+            vec!["a3"]
+        );
+        assert!(requested_members(&request(&[], Some("g1"))).is_err());
+    }
+
+    #[test]
+    fn test_groups_of() {
+        let annotation = |id: &str, group_id: Option<&str>| {
+            let mut annotation: Annotation = serde_json::from_value(serde_json::json!({
+                "annotation_id": id,
+                "image_id": "i1",
+                "class_id": "truss",
+                "geometry": {"type": "point", "point": {"x": 1.0, "y": 1.0}},
+                "created_by": "USER#u1",
+                "created_at": "2026-10-14T08:00:00Z",
+            }))
+            .unwrap();
+            annotation.group_id = group_id.map(|g| g.to_string());
+            annotation
+        };
+        let groups = groups_of(&[
+            annotation("a1", Some("g2")),
+            annotation("a2", None),
+            annotation("a3", Some("g1")),
+            annotation("a4", Some("g2")),
+        ]);
+        assert_eq!(
+            groups,
+            vec![
+                AnnotationGroup {
+                    group_id: "g1".to_string(),
+                    annotation_ids: vec!["a3".to_string()],
+                },
+                AnnotationGroup {
+                    group_id: "g2".to_string(),
+                    annotation_ids: vec!["a1".to_string(), "a4".to_string()],
+                },
+            ]
+        );
+    }
+}
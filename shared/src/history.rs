@@ -0,0 +1,288 @@
+use crate::types::{Annotation, Geometry};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Versions of an annotation live next to it in the image partition:
+/// PK=IMAGE#{iid}, SK=HISTORY#{aid}#V{n:06}
+pub const HISTORY_PREFIX: &str = "HISTORY#";
+
+fn history_prefix(annotation_id: &str) -> String {
+    format!("{}{}#V", HISTORY_PREFIX, annotation_id)
+}
+
+pub fn history_sk(annotation_id: &str, version: u32) -> String {
+    format!("{}{:06}", history_prefix(annotation_id), version)
+}
+
+/// One recorded state of an annotation
+#[derive(Debug, Serialize, Clone)]
+pub struct AnnotationVersion {
+    pub version: u32,
+    pub action: String, // snapshot | created | updated | rescaled | reverted
+    pub class_id: String,
+    pub geometry: Geometry,
+    pub changed_by: Option<String>, // USER#123
+    pub changed_at: String,
+}
+
+/// A version as returned by the history endpoint: the recorded state plus
+/// what changed from the version before it
+#[derive(Debug, Serialize)]
+pub struct VersionEntry {
+    #[serde(flatten)]
+    pub version: AnnotationVersion,
+    pub area: f64,
+    /// Field name -> {"from", "to"}; empty for the first recorded version
+    pub changes: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Item recording the annotation's current state as history version
+/// `annotation.version`
+pub fn history_item(
+    annotation: &Annotation,
+    action: &str,
+    changed_by: Option<&str>,
+) -> Result<Item, Error> {
+    let changed_at = annotation
+        .updated_at
+        .clone()
+        .unwrap_or_else(|| annotation.created_at.clone());
+    let mut item = HashMap::from([
+        (
+            "PK".to_string(),
+            AttributeValue::S(format!("IMAGE#{}", annotation.image_id)),
+        ),
+        (
+            "SK".to_string(),
+            AttributeValue::S(history_sk(&annotation.annotation_id, annotation.version)),
+        ),
+        (
+            "entity_type".to_string(),
+            AttributeValue::S("annotation_version".to_string()),
+        ),
+        (
+            "version".to_string(),
+            AttributeValue::N(annotation.version.to_string()),
+        ),
+        ("action".to_string(), AttributeValue::S(action.to_string())),
+        (
+            "class_id".to_string(),
+            AttributeValue::S(annotation.class_id.clone()),
+        ),
+        (
+            "geometry".to_string(),
+            AttributeValue::S(serde_json::to_string(&annotation.geometry)?),
+        ),
+        ("changed_at".to_string(), AttributeValue::S(changed_at)),
+    ]);
+    if let Some(user) = changed_by {
+        item.insert(
+            "changed_by".to_string(),
+            AttributeValue::S(user.to_string()),
+        );
+    }
+    Ok(item)
+}
+
+/// Write a history version. Best-effort like `activity::record_activity`:
+/// the annotation write has already happened, so failures are only logged.
+pub async fn record(
+    client: &DynamoClient,
+    table_name: &str,
+    annotation: &Annotation,
+    action: &str,
+    changed_by: Option<&str>,
+) {
+    let result = match history_item(annotation, action, changed_by) {
+        Ok(item) => client
+            .put_item()
+            .table_name(table_name)
+            .set_item(Some(item))
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(Error::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::warn!(
+            "Failed to record version {} of annotation {}: {}",
+            annotation.version,
+            annotation.annotation_id,
+            e
+        );
+    }
+}
+
+fn version_from_item(item: &Item) -> Option<AnnotationVersion> {
+    let text = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    Some(AnnotationVersion {
+        version: item.get("version")?.as_n().ok()?.parse().ok()?,
+        action: text("action").unwrap_or_default(),
+        class_id: text("class_id").unwrap_or_default(),
+        geometry: serde_json::from_str(&text("geometry")?).ok()?,
+        changed_by: text("changed_by"),
+        changed_at: text("changed_at").unwrap_or_default(),
+    })
+}
+
+/// All recorded versions of an annotation, oldest first
+pub async fn fetch_history(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    annotation_id: &str,
+) -> Result<Vec<AnnotationVersion>, Error> {
+    let page = crate::pagination::query_prefix(
+        client,
+        table_name,
+        &format!("IMAGE#{}", image_id),
+        &history_prefix(annotation_id),
+        None,
+        &Default::default(),
+    )
+    .await?;
+    Ok(page.items.iter().filter_map(version_from_item).collect())
+}
+
+/// One recorded version, if it exists
+pub async fn fetch_version(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    annotation_id: &str,
+    version: u32,
+) -> Result<Option<AnnotationVersion>, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .key("SK", AttributeValue::S(history_sk(annotation_id, version)))
+        .send()
+        .await?;
+    Ok(result.item().and_then(version_from_item))
+}
+
+/// Fields that differ between two versions, as {"from", "to"} pairs
+fn diff(
+    previous: Option<&AnnotationVersion>,
+    current: &AnnotationVersion,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut changes = serde_json::Map::new();
+    let Some(previous) = previous else {
+        return changes;
+    };
+    let mut change = |field: &str, from: serde_json::Value, to: serde_json::Value| {
+        if from != to {
+            changes.insert(
+                field.to_string(),
+                serde_json::json!({ "from": from, "to": to }),
+            );
+        }
+    };
+    change(
+        "class_id",
+        previous.class_id.clone().into(),
+        current.class_id.clone().into(),
+    );
+    change(
+        "geometry",
+        serde_json::to_value(&previous.geometry).unwrap_or_default(),
+        serde_json::to_value(&current.geometry).unwrap_or_default(),
+    );
+    changes
+}
+
+/// Versions with the changes each one made, oldest first
+pub fn with_changes(versions: Vec<AnnotationVersion>) -> Vec<VersionEntry> {
+    let mut entries: Vec<VersionEntry> = Vec::with_capacity(versions.len());
+    for version in versions {
+        let changes = diff(entries.last().map(|e| &e.version), &version);
+        entries.push(VersionEntry {
+            area: crate::geometry::area(&version.geometry),
+            version,
+            changes,
+        });
+    }
+    entries
+}
+
+/// Version history of an annotation (GET /images/{iid}/annotations/{aid}/history)
+pub async fn get_history(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    annotation_id: &str,
+) -> Result<Response<Body>, Error> {
+    let versions = fetch_history(client, table_name, image_id, annotation_id).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(
+            serde_json::json!({
+                "annotation_id": annotation_id,
+                "image_id": image_id,
+                "versions": with_changes(versions),
+            })
+            .to_string()
+            .into(),
+        )
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Point;
+
+    fn version(n: u32, class_id: &str, x: f64) -> AnnotationVersion {
+        AnnotationVersion {
+            version: n,
+            action: "updated".to_string(),
+            class_id: class_id.to_string(),
+            geometry: Geometry::Point {
+                point: Point { x, y: 1.0 },
+            },
+            changed_by: Some("USER#u1".to_string()),
+            changed_at: "2026-10-14T08:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_history_sk() {
+        assert_eq!(history_sk("a1", 12), "HISTORY#a1#V000012");
+        // Zero padding keeps versions in SK order
+        assert!(history_sk("a1", 9) < history_sk("a1", 10));
+        assert!(history_sk("a1", 3).starts_with(&history_prefix("a1")));
+        assert!(!history_sk("a10", 3).starts_with(&history_prefix("a1")));
+    }
+
+    #[test]
+    fn test_with_changes() {
+        let entries = with_changes(vec![
+            version(1, "walls", 1.0),
+            version(2, "walls", 5.0),
+            version(3, "doors", 5.0),
+        ]);
+        assert!(entries[0].changes.is_empty());
+        assert_eq!(
+            entries[1].changes.keys().collect::<Vec<_>>(),
+            vec!["geometry"]
+        );
+        assert_eq!(
+            entries[1].changes["geometry"]["to"]["point"]["x"],
+            serde_json::json!(5.0)
+        );
+        assert_eq!(
+            entries[2].changes["class_id"],
+            serde_json::json!({"from": "walls", "to": "doors"})
+        );
+        assert!(!entries[2].changes.contains_key("geometry"));
+    }
+}
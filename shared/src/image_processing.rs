@@ -1,10 +1,23 @@
-use image::{ImageFormat, imageops::FilterType};
+use crate::types::PhotoMetadata;
+use image::{DynamicImage, ImageFormat, imageops::FilterType};
 use std::io::Cursor;
 
 /// Thresholds for generating half-width previews
 const MIN_FILE_SIZE_BYTES: usize = 3_000_000; // 3MB
 const MIN_DIMENSION_PX: u32 = 3000;
 
+/// Longest side of the gallery thumbnail
+pub const THUMBNAIL_MAX_PX: u32 = 256;
+
+/// Pyramids a project can choose: a single half-width preview, or Deep Zoom
+/// tiles on top of it for gigapixel scans
+pub const PYRAMID_MODES: [&str; 2] = ["half", "dzi"];
+
+/// Deep Zoom tile edges a project may pick, and the usual default
+pub const TILE_SIZES: std::ops::RangeInclusive<u32> = 64..=1024;
+pub const DEFAULT_TILE_SIZE: u32 = 254;
+pub const TILE_OVERLAP: u32 = 1;
+
 /// Determine if image needs a half-width version
 pub fn needs_half_width(file_size: usize, width: u32, height: u32) -> bool {
     file_size >= MIN_FILE_SIZE_BYTES || width >= MIN_DIMENSION_PX || height >= MIN_DIMENSION_PX
@@ -34,6 +47,271 @@ pub fn generate_half_width(image_bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), St
     Ok((new_width, new_height, buf.into_inner()))
 }
 
+/// Generate a gallery thumbnail that fits in THUMBNAIL_MAX_PX on its longest side
+/// Returns (width, height, jpeg_bytes)
+pub fn generate_thumbnail(image_bytes: &[u8]) -> Result<(u32, u32, Vec<u8>), String> {
+    let img = image::load_from_memory(image_bytes)
+        .map_err(|e| format!("Failed to load image: {}", e))?;
+    
+    // Small images are not scaled up
+    let thumb = if img.width() <= THUMBNAIL_MAX_PX && img.height() <= THUMBNAIL_MAX_PX {
+        img
+    } else {
+        img.thumbnail(THUMBNAIL_MAX_PX, THUMBNAIL_MAX_PX)
+    };
+    
+    // JPEG has no alpha channel
+    let mut buf = Cursor::new(Vec::new());
+    thumb.to_rgb8().write_to(&mut buf, ImageFormat::Jpeg)
+        .map_err(|e| format!("Failed to encode JPEG: {}", e))?;
+    
+    Ok((thumb.width(), thumb.height(), buf.into_inner()))
+}
+
+/// Highest Deep Zoom level of an image, the one at full resolution; level 0
+/// is a single pixel and each level in between halves the one above
+pub fn dzi_max_level(width: u32, height: u32) -> u32 {
+    let longest = width.max(height).max(1);
+    32 - (longest - 1).leading_zeros()
+}
+
+/// Pixel size of a Deep Zoom level
+pub fn dzi_level_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    let scale = 1u64 << (dzi_max_level(width, height) - level);
+    let scaled = |side: u32| (side as u64).div_ceil(scale).max(1) as u32;
+    (scaled(width), scaled(height))
+}
+
+/// Pixel bounds (x, y, width, height) of a tile in its level, overlapping
+/// its neighbours by `overlap` on each inner edge
+pub fn tile_bounds(level_width: u32, level_height: u32, tile_size: u32, overlap: u32, col: u32, row: u32) -> (u32, u32, u32, u32) {
+    let x = (col * tile_size).saturating_sub(overlap);
+    let y = (row * tile_size).saturating_sub(overlap);
+    let right = ((col + 1) * tile_size + overlap).min(level_width);
+    let bottom = ((row + 1) * tile_size + overlap).min(level_height);
+    (x, y, right - x, bottom - y)
+}
+
+/// Cut one Deep Zoom level into JPEG tiles
+/// Returns (col, row, jpeg_bytes) per tile
+pub fn encode_level_tiles(level: &DynamicImage, tile_size: u32, overlap: u32) -> Result<Vec<(u32, u32, Vec<u8>)>, String> {
+    let (width, height) = (level.width(), level.height());
+    let mut tiles = Vec::new();
+    for row in 0..height.div_ceil(tile_size) {
+        for col in 0..width.div_ceil(tile_size) {
+            let (x, y, w, h) = tile_bounds(width, height, tile_size, overlap, col, row);
+            let mut buf = Cursor::new(Vec::new());
+            level.crop_imm(x, y, w, h).to_rgb8().write_to(&mut buf, ImageFormat::Jpeg)
+                .map_err(|e| format!("Failed to encode tile {}_{}: {}", col, row, e))?;
+            tiles.push((col, row, buf.into_inner()));
+        }
+    }
+    Ok(tiles)
+}
+
+/// The `.dzi` descriptor viewers (OpenSeadragon and the like) open
+pub fn dzi_descriptor(width: u32, height: u32, tile_size: u32, overlap: u32) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" Format="jpg" Overlap="{}" TileSize="{}">
+  <Size Width="{}" Height="{}"/>
+</Image>
+"#,
+        overlap, tile_size, width, height
+    )
+}
+
+/// Location, camera and orientation from a photo's EXIF; None without EXIF
+pub fn photo_metadata(image_bytes: &[u8]) -> Option<PhotoMetadata> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(image_bytes))
+        .ok()?;
+    let field = |tag: exif::Tag| exif.get_field(tag, exif::In::PRIMARY).map(|f| &f.value);
+    let text = |tag: exif::Tag| match field(tag)? {
+        exif::Value::Ascii(values) => {
+            let value = String::from_utf8_lossy(values.first()?).trim().to_string();
+            (!value.is_empty()).then_some(value)
+        }
+        _ => None,
+    };
+    let rationals = |tag: exif::Tag| match field(tag)? {
+        exif::Value::Rational(values) => Some(values.iter().map(|r| r.to_f64()).collect::<Vec<_>>()),
+        _ => None,
+    };
+    let coordinate = |tag: exif::Tag, reference: exif::Tag, negative: &str| {
+        let degrees = gps_degrees(&rationals(tag)?)?;
+        Some(if text(reference).as_deref() == Some(negative) { -degrees } else { degrees })
+    };
+    let below_sea_level = field(exif::Tag::GPSAltitudeRef).and_then(|v| v.get_uint(0)) == Some(1);
+    let altitude_m = rationals(exif::Tag::GPSAltitude)
+        .and_then(|values| values.first().copied())
+        .filter(|altitude| altitude.is_finite())
+        .map(|altitude| if below_sea_level { -altitude } else { altitude });
+
+    Some(PhotoMetadata {
+        latitude: coordinate(exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, "S"),
+        longitude: coordinate(exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, "W"),
+        altitude_m,
+        camera_make: text(exif::Tag::Make),
+        camera_model: text(exif::Tag::Model),
+        orientation: field(exif::Tag::Orientation).and_then(|v| v.get_uint(0)),
+    })
+}
+
+/// Degrees from EXIF's degrees, minutes and seconds
+fn gps_degrees(dms: &[f64]) -> Option<f64> {
+    let (degrees, minutes, seconds) = match dms {
+        [d, m, s, ..] => (*d, *m, *s),
+        [d, m] => (*d, *m, 0.0),
+        [d] => (*d, 0.0, 0.0),
+        [] => return None,
+    };
+    let value = degrees + minutes / 60.0 + seconds / 3600.0;
+    value.is_finite().then_some(value)
+}
+
+/// Turn decoded pixels upright for an EXIF orientation (1-8)
+pub fn orient(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+/// Re-encode an image upright, in its own format, when its EXIF orientation
+/// isn't the default. The copy has no EXIF left to rotate it twice.
+/// Returns None when it already is upright.
+pub fn upright(image_bytes: &[u8], orientation: Option<u32>) -> Result<Option<Vec<u8>>, String> {
+    let Some(orientation) = orientation.filter(|o| (2..=8).contains(o)) else {
+        return Ok(None);
+    };
+    let format = image::guess_format(image_bytes)
+        .map_err(|e| format!("Failed to detect image format: {}", e))?;
+    let img = image::load_from_memory_with_format(image_bytes, format)
+        .map_err(|e| format!("Failed to load image: {}", e))?;
+    let mut buf = Cursor::new(Vec::new());
+    orient(img, orientation).write_to(&mut buf, format)
+        .map_err(|e| format!("Failed to encode upright image: {}", e))?;
+    Ok(Some(buf.into_inner()))
+}
+
+/// Bytes read from the start of an upload to check what it is: enough to
+/// reach a JPEG's frame header behind its EXIF and ICC profile
+pub const SNIFF_BYTES: usize = 256 * 1024;
+
+/// Image format from a file's first bytes, named by its usual extension;
+/// None when they aren't an image we take
+pub fn sniff_format(head: &[u8]) -> Option<&'static str> {
+    // HEIF is an ISO media file: an `ftyp` box with an image brand
+    if head.get(4..8) == Some(b"ftyp") {
+        return match head.get(8..12)? {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"mif1" | b"msf1" => Some("heic"),
+            _ => None,
+        };
+    }
+    match image::guess_format(head).ok()? {
+        ImageFormat::Jpeg => Some("jpg"),
+        ImageFormat::Png => Some("png"),
+        ImageFormat::WebP => Some("webp"),
+        ImageFormat::Tiff => Some("tiff"),
+        ImageFormat::Gif => Some("gif"),
+        ImageFormat::Bmp => Some("bmp"),
+        _ => None,
+    }
+}
+
+/// Whether a file extension is one of a sniffed format's
+pub fn extension_matches(extension: &str, format: &str) -> bool {
+    let extension = extension.to_ascii_lowercase();
+    let extension = match extension.as_str() {
+        "jpeg" => "jpg",
+        "tif" => "tiff",
+        "heif" => "heic",
+        other => other,
+    };
+    extension == format
+}
+
+/// Dimensions from the header at the start of an image. None when they
+/// aren't there: corrupt, or a TIFF or HEIC keeping them further in.
+pub fn header_dimensions(head: &[u8]) -> Option<(u32, u32)> {
+    image::io::Reader::new(Cursor::new(head))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Format an upload browsers can't show is converted to: HEIC photos from
+/// iPhones become JPEG, TIFF scans lossless PNG. None for everything else.
+pub fn browser_format(extension: &str) -> Option<&'static str> {
+    match extension.to_ascii_lowercase().as_str() {
+        "heic" | "heif" => Some("jpg"),
+        "tif" | "tiff" => Some("png"),
+        _ => None,
+    }
+}
+
+/// Convert an upload to its browser format, returning the new extension and
+/// bytes; None when browsers show it as it is. A multi-page TIFF keeps its
+/// first page. HEIC comes out upright, libheif applying its rotation.
+pub fn convert_for_browser(image_bytes: &[u8], extension: &str) -> Result<Option<(&'static str, Vec<u8>)>, String> {
+    let Some(target) = browser_format(extension) else {
+        return Ok(None);
+    };
+    let img = if target == "jpg" {
+        decode_heic(image_bytes)?
+    } else {
+        image::load_from_memory_with_format(image_bytes, ImageFormat::Tiff)
+            .map_err(|e| format!("Failed to load TIFF: {}", e))?
+    };
+    let mut buf = Cursor::new(Vec::new());
+    let encoded = match (target, img.color()) {
+        ("jpg", _) => img.to_rgb8().write_to(&mut buf, ImageFormat::Jpeg),
+        // PNG has no floating point samples
+        (_, image::ColorType::Rgb32F | image::ColorType::Rgba32F) => img.to_rgba16().write_to(&mut buf, ImageFormat::Png),
+        _ => img.write_to(&mut buf, ImageFormat::Png),
+    };
+    encoded.map_err(|e| format!("Failed to encode {}: {}", target, e))?;
+    Ok(Some((target, buf.into_inner())))
+}
+
+#[cfg(feature = "heic")]
+fn decode_heic(image_bytes: &[u8]) -> Result<DynamicImage, String> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    let context = HeifContext::read_from_bytes(image_bytes)
+        .map_err(|e| format!("Failed to read HEIC: {}", e))?;
+    let handle = context.primary_image_handle()
+        .map_err(|e| format!("Failed to read HEIC: {}", e))?;
+    let decoded = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .map_err(|e| format!("Failed to decode HEIC: {}", e))?;
+    let planes = decoded.planes();
+    let plane = planes.interleaved.ok_or("HEIC decoded without pixels")?;
+
+    // Rows are padded out to the stride
+    let row = plane.width as usize * 3;
+    let mut pixels = Vec::with_capacity(row * plane.height as usize);
+    for line in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(line.get(..row).ok_or("HEIC row shorter than the image")?);
+    }
+    image::RgbImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| "HEIC pixels don't fill the image".to_string())
+}
+
+/// Without libheif HEIC uploads fail processing, keeping the file as uploaded
+#[cfg(not(feature = "heic"))]
+fn decode_heic(_image_bytes: &[u8]) -> Result<DynamicImage, String> {
+    Err("HEIC support is not built in (enable the heic feature)".to_string())
+}
+
 /// Get image dimensions without loading full image
 pub fn get_dimensions(image_bytes: &[u8]) -> Result<(u32, u32), String> {
     let img = image::load_from_memory(image_bytes)
@@ -41,6 +319,79 @@ pub fn get_dimensions(image_bytes: &[u8]) -> Result<(u32, u32), String> {
     Ok((img.width(), img.height()))
 }
 
+/// Cut a region out of an image and encode it as PNG.
+/// The region is clamped to the image; returns an error if nothing is left.
+pub fn crop_region(image_bytes: &[u8], x: f64, y: f64, width: f64, height: f64) -> Result<Vec<u8>, String> {
+    let img = image::load_from_memory(image_bytes)
+        .map_err(|e| format!("Failed to load image: {}", e))?;
+    crop_image(&img, x, y, width, height)
+}
+
+/// `crop_region` of an already decoded image, for cutting many regions out of one
+pub fn crop_image(img: &image::DynamicImage, x: f64, y: f64, width: f64, height: f64) -> Result<Vec<u8>, String> {
+    let left = x.floor().max(0.0) as u32;
+    let top = y.floor().max(0.0) as u32;
+    let right = ((x + width).ceil().max(0.0) as u32).min(img.width());
+    let bottom = ((y + height).ceil().max(0.0) as u32).min(img.height());
+    if right <= left || bottom <= top {
+        return Err("Region is outside the image".to_string());
+    }
+
+    let cropped = img.crop_imm(left, top, right - left, bottom - top);
+    let mut buf = Cursor::new(Vec::new());
+    cropped.write_to(&mut buf, ImageFormat::Png)
+        .map_err(|e| format!("Failed to encode PNG: {}", e))?;
+
+    Ok(buf.into_inner())
+}
+
+/// Formats the image proxy can resize into
+pub const VARIANT_FORMATS: [&str; 3] = ["jpg", "png", "webp"];
+
+/// Widest variant the image proxy makes; larger sizes come from the pyramid
+pub const MAX_VARIANT_WIDTH: u32 = 4096;
+
+/// MIME type of a variant format
+pub fn variant_content_type(format: &str) -> &'static str {
+    match format {
+        "png" => "image/png",
+        "webp" => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+/// Scale an image down to `width` (keeping its aspect ratio, never scaling
+/// up) and encode it as one of the `VARIANT_FORMATS`. WebP comes out
+/// lossless, the only WebP encoding available.
+/// Returns (width, height, encoded_bytes)
+pub fn resize_variant(image_bytes: &[u8], width: Option<u32>, format: &str) -> Result<(u32, u32, Vec<u8>), String> {
+    let img = image::load_from_memory(image_bytes)
+        .map_err(|e| format!("Failed to load image: {}", e))?;
+
+    let img = match width {
+        Some(width) if width < img.width() => {
+            let height = ((img.height() as u64 * width as u64) / img.width() as u64).max(1) as u32;
+            img.resize_exact(width, height, FilterType::Lanczos3)
+        }
+        _ => img,
+    };
+
+    let mut buf = Cursor::new(Vec::new());
+    let encoded = match format {
+        // JPEG has no alpha channel
+        "jpg" => img.to_rgb8().write_to(&mut buf, ImageFormat::Jpeg),
+        "webp" if img.color().has_alpha() => img.to_rgba8().write_to(&mut buf, ImageFormat::WebP),
+        "webp" => img.to_rgb8().write_to(&mut buf, ImageFormat::WebP),
+        // PNG has no floating point samples
+        "png" if matches!(img.color(), image::ColorType::Rgb32F | image::ColorType::Rgba32F) => img.to_rgba16().write_to(&mut buf, ImageFormat::Png),
+        "png" => img.write_to(&mut buf, ImageFormat::Png),
+        _ => return Err(format!("Unsupported variant format: {}", format)),
+    };
+    encoded.map_err(|e| format!("Failed to encode {}: {}", format, e))?;
+
+    Ok((img.width(), img.height(), buf.into_inner()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -48,15 +399,174 @@ mod tests {
     #[test]
     fn test_needs_half_width() {
         // Small file, small dimensions → No
-        assert_eq!(needs_half_width(2_000_000, 2048, 1536), false);
+        assert!(!needs_half_width(2_000_000, 2048, 1536));
         
         // Large file, small dimensions → Yes
-        assert_eq!(needs_half_width(4_000_000, 2048, 1536), true);
+        assert!(needs_half_width(4_000_000, 2048, 1536));
         
         // Small file, large dimensions → Yes
-        assert_eq!(needs_half_width(2_000_000, 4000, 3000), true);
+        assert!(needs_half_width(2_000_000, 4000, 3000));
         
         // Large file, large dimensions → Yes
-        assert_eq!(needs_half_width(4_000_000, 4000, 3000), true);
+        assert!(needs_half_width(4_000_000, 4000, 3000));
+    }
+
+    #[test]
+    fn test_generate_thumbnail() {
+        let encode = |width: u32, height: u32| {
+            let mut png = Cursor::new(Vec::new());
+            image::RgbaImage::new(width, height).write_to(&mut png, ImageFormat::Png).unwrap();
+            png.into_inner()
+        };
+
+        let (width, height, jpeg) = generate_thumbnail(&encode(1024, 512)).unwrap();
+        assert_eq!((width, height), (256, 128));
+        assert_eq!(get_dimensions(&jpeg).unwrap(), (256, 128));
+
+        // Already small enough
+        let (width, height, _) = generate_thumbnail(&encode(100, 40)).unwrap();
+        assert_eq!((width, height), (100, 40));
+    }
+
+    #[test]
+    fn test_dzi_levels() {
+        assert_eq!(dzi_max_level(1, 1), 0);
+        assert_eq!(dzi_max_level(2, 1), 1);
+        assert_eq!(dzi_max_level(1000, 700), 10);
+        assert_eq!(dzi_max_level(1024, 700), 10);
+        assert_eq!(dzi_level_size(1000, 700, 10), (1000, 700));
+        assert_eq!(dzi_level_size(1000, 700, 9), (500, 350));
+        assert_eq!(dzi_level_size(1000, 700, 8), (250, 175));
+        assert_eq!(dzi_level_size(1000, 700, 0), (1, 1));
+    }
+
+    #[test]
+    fn test_tiles() {
+        // Inner edges overlap, outer edges stop at the level
+        assert_eq!(tile_bounds(600, 300, 254, 1, 0, 0), (0, 0, 255, 255));
+        assert_eq!(tile_bounds(600, 300, 254, 1, 1, 0), (253, 0, 256, 255));
+        assert_eq!(tile_bounds(600, 300, 254, 1, 2, 1), (507, 253, 93, 47));
+
+        let level = DynamicImage::new_rgba8(600, 300);
+        let tiles = encode_level_tiles(&level, 254, 1).unwrap();
+        assert_eq!(tiles.len(), 6);
+        let (col, row, jpeg) = &tiles[5];
+        assert_eq!((*col, *row), (2, 1));
+        assert_eq!(get_dimensions(jpeg).unwrap(), (93, 47));
+
+        assert!(dzi_descriptor(600, 300, 254, 1).contains(r#"<Size Width="600" Height="300"/>"#));
+    }
+
+    #[test]
+    fn test_orient() {
+        // A 2x1 image with its left pixel marked
+        let mut img = image::RgbImage::new(2, 1);
+        img.put_pixel(0, 0, image::Rgb([255, 0, 0]));
+        let img = DynamicImage::ImageRgb8(img);
+        let marked = |img: &DynamicImage| {
+            let rgb = img.to_rgb8();
+            let (x, y, _) = rgb.enumerate_pixels().find(|(_, _, p)| p.0[0] == 255).unwrap();
+            (rgb.width(), rgb.height(), x, y)
+        };
+        assert_eq!(marked(&orient(img.clone(), 1)), (2, 1, 0, 0));
+        assert_eq!(marked(&orient(img.clone(), 2)), (2, 1, 1, 0));
+        assert_eq!(marked(&orient(img.clone(), 3)), (2, 1, 1, 0));
+        // Shot rotated: turned a quarter clockwise to stand upright
+        assert_eq!(marked(&orient(img.clone(), 6)), (1, 2, 0, 0));
+        assert_eq!(marked(&orient(img.clone(), 8)), (1, 2, 0, 1));
+        assert_eq!(marked(&orient(img, 5)), (1, 2, 0, 0));
+    }
+
+    #[test]
+    fn test_sniff_format() {
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(300, 200).write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+        assert_eq!(sniff_format(&png), Some("png"));
+        assert!(extension_matches("PNG", "png"));
+        assert!(!extension_matches("jpg", "png"));
+        assert_eq!(header_dimensions(&png[..64]), Some((300, 200)));
+
+        let mut heic = vec![0, 0, 0, 24];
+        heic.extend_from_slice(b"ftypheic");
+        assert_eq!(sniff_format(&heic), Some("heic"));
+        assert!(extension_matches("heif", "heic"));
+
+        // Video in the same container, and text
+        let mut mp4 = vec![0, 0, 0, 24];
+        mp4.extend_from_slice(b"ftypisom");
+        assert_eq!(sniff_format(&mp4), None);
+        assert_eq!(sniff_format(b"<html></html>"), None);
+        assert_eq!(header_dimensions(b"\x89PNG\r\n\x1a\n"), None);
+    }
+
+    #[test]
+    fn test_convert_for_browser() {
+        let mut tiff = Cursor::new(Vec::new());
+        image::RgbImage::new(30, 20).write_to(&mut tiff, ImageFormat::Tiff).unwrap();
+        let (extension, png) = convert_for_browser(tiff.get_ref(), "TIF").unwrap().unwrap();
+        assert_eq!(extension, "png");
+        assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+        assert_eq!(get_dimensions(&png).unwrap(), (30, 20));
+
+        // Browsers show it already
+        assert!(convert_for_browser(&png, "png").unwrap().is_none());
+        assert_eq!(browser_format("heic"), Some("jpg"));
+        assert_eq!(browser_format("jpeg"), None);
+    }
+
+    #[test]
+    fn test_upright() {
+        let mut png = Cursor::new(Vec::new());
+        image::RgbImage::new(4, 2).write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+        assert_eq!(upright(&png, None).unwrap(), None);
+        assert_eq!(upright(&png, Some(1)).unwrap(), None);
+        let turned = upright(&png, Some(6)).unwrap().unwrap();
+        assert_eq!(get_dimensions(&turned).unwrap(), (2, 4));
+        assert_eq!(image::guess_format(&turned).unwrap(), ImageFormat::Png);
+    }
+
+    #[test]
+    fn test_gps_degrees() {
+        assert!((gps_degrees(&[33.0, 51.0, 54.0]).unwrap() - 33.865).abs() < 1e-9);
+        assert_eq!(gps_degrees(&[151.5]), Some(151.5));
+        assert_eq!(gps_degrees(&[]), None);
+        assert_eq!(gps_degrees(&[f64::NAN, 0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_crop_region() {
+        let img = image::RgbImage::new(20, 10);
+        let mut png = Cursor::new(Vec::new());
+        img.write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        let crop = crop_region(&png, 2.5, 1.0, 5.0, 4.0).unwrap();
+        assert_eq!(get_dimensions(&crop).unwrap(), (6, 4));
+
+        // Clamped to the image edge
+        let crop = crop_region(&png, 15.0, 5.0, 100.0, 100.0).unwrap();
+        assert_eq!(get_dimensions(&crop).unwrap(), (5, 5));
+
+        assert!(crop_region(&png, 30.0, 0.0, 5.0, 5.0).is_err());
+    }
+
+    #[test]
+    fn test_resize_variant() {
+        let mut png = Cursor::new(Vec::new());
+        image::RgbaImage::new(400, 300).write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        let (width, height, webp) = resize_variant(&png, Some(100), "webp").unwrap();
+        assert_eq!((width, height), (100, 75));
+        assert_eq!(sniff_format(&webp), Some("webp"));
+
+        // Never scaled up
+        let (width, height, jpeg) = resize_variant(&png, Some(800), "jpg").unwrap();
+        assert_eq!((width, height), (400, 300));
+        assert_eq!(sniff_format(&jpeg), Some("jpg"));
+
+        assert!(resize_variant(&png, None, "gif").is_err());
     }
 }
@@ -1,27 +1,366 @@
-use lambda_http::{Body, Error, Response, http::StatusCode};
+use crate::image_processing::{self, MAX_VARIANT_WIDTH, VARIANT_FORMATS};
+use crate::{members, users};
+use lambda_http::{Body, Error, Response, http::{HeaderMap, StatusCode}};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
 use aws_sdk_s3::Client as S3Client;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// Cached for 1 year, but only by the browser that was authorised to fetch it
+const CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+
+const PROXY_TOKEN_SECRET: &str = "PROXY_TOKEN_SECRET"; // Set via env var
+
+/// How long an image token lasts, as long as the CloudFront cookies
+pub const TOKEN_TTL_SECS: i64 = 43200; // 12 hours
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn token_mac(secret: &str, project_id: &str, user_id: &str, expires_at: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(format!("{}:{}:{}", project_id, user_id, expires_at).as_bytes());
+    mac
+}
+
+/// Token letting `user_id` load one project's images through the proxy as
+/// `?token=`, for `<img>` tags that can't send an Authorization header:
+/// `{user_id}.{expires_at}.{signature}`
+fn sign_token(secret: &str, project_id: &str, user_id: &str, expires_at: i64) -> String {
+    let signature = token_mac(secret, project_id, user_id, expires_at).finalize().into_bytes();
+    format!("{}.{}.{}", user_id, expires_at, URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// The user a token was issued to, if it is for this project and unexpired
+fn verify_token<'a>(secret: &str, project_id: &str, token: &'a str, now: i64) -> Option<&'a str> {
+    let mut parts = token.splitn(3, '.');
+    let (user_id, expires_at, signature) = (parts.next()?, parts.next()?, parts.next()?);
+    let expires_at: i64 = expires_at.parse().ok()?;
+    if user_id.is_empty() || expires_at <= now {
+        return None;
+    }
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    token_mac(secret, project_id, user_id, expires_at)
+        .verify_slice(&signature)
+        .ok()
+        .map(|_| user_id)
+}
+
+/// POST /projects/{id}/image-token - issue a proxy token to a project member
+pub async fn issue_token(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    user_id: &str,
+) -> Result<Response<Body>, Error> {
+    if !members::is_member(client, table_name, project_id, user_id).await?
+        && !users::is_admin(client, table_name, user_id).await?
+    {
+        return error_response(StatusCode::FORBIDDEN, "Not a member of this project");
+    }
+    let secret = std::env::var(PROXY_TOKEN_SECRET)
+        .map_err(|_| "PROXY_TOKEN_SECRET not set")?;
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(TOKEN_TTL_SECS);
+    let token = sign_token(&secret, project_id, user_id, expires_at.timestamp());
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(
+            serde_json::json!({
+                "token": token,
+                "expires_at": expires_at.to_rfc3339(),
+            })
+            .to_string()
+            .into(),
+        )
+        .map_err(Box::new)?)
+}
+
+/// Check the requester may read the project an object belongs to. They are
+/// either the user API Gateway authenticated (`user_id`) or the holder of a
+/// `token`, and must still be a member (or an admin). Returns the response
+/// to send instead of the image when they may not.
+pub async fn authorize(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    user_id: Option<&str>,
+    token: Option<&str>,
+) -> Result<Option<Response<Body>>, Error> {
+    let secret = std::env::var(PROXY_TOKEN_SECRET).ok().filter(|s| !s.is_empty());
+    let now = chrono::Utc::now().timestamp();
+    let user_id = match (user_id, token) {
+        (Some(user_id), _) => user_id,
+        (None, Some(token)) => match secret.as_deref().and_then(|s| verify_token(s, project_id, token, now)) {
+            Some(user_id) => user_id,
+            None => return error_response(StatusCode::UNAUTHORIZED, "Invalid or expired image token").map(Some),
+        },
+        (None, None) => return error_response(StatusCode::UNAUTHORIZED, "Missing Authorization header or token").map(Some),
+    };
+    if members::is_member(client, table_name, project_id, user_id).await?
+        || users::is_admin(client, table_name, user_id).await?
+    {
+        return Ok(None);
+    }
+    tracing::warn!("User {} denied images of project {}", user_id, project_id);
+    error_response(StatusCode::FORBIDDEN, "Not a member of this project").map(Some)
+}
+
+/// Largest source the proxy decodes to make a variant; bigger scans have a
+/// pyramid whose preview can be resized instead
+const MAX_VARIANT_SOURCE_BYTES: i64 = 50_000_000; // 50MB
+
+/// A rendition asked for with `?w=` and/or `?format=`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub width: Option<u32>,
+    pub format: Option<&'static str>,
+}
+
+/// Read `?w=&format=`. None when neither is given, for the object as stored.
+pub fn parse_variant(width: Option<&str>, format: Option<&str>) -> Result<Option<Variant>, String> {
+    let width = match width {
+        Some(w) => match w.parse::<u32>() {
+            Ok(w) if (1..=MAX_VARIANT_WIDTH).contains(&w) => Some(w),
+            _ => return Err(format!("w must be a width from 1 to {}", MAX_VARIANT_WIDTH)),
+        },
+        None => None,
+    };
+    let format = match format.map(|f| f.to_ascii_lowercase()) {
+        Some(f) => {
+            let f = if f == "jpeg" { "jpg".to_string() } else { f };
+            match VARIANT_FORMATS.iter().find(|v| **v == f) {
+                Some(v) => Some(*v),
+                None => {
+                    return Err(format!(
+                        "Unknown format '{}', expected one of: {}",
+                        f,
+                        VARIANT_FORMATS.join(", ")
+                    ))
+                }
+            }
+        }
+        None => None,
+    };
+    Ok((width.is_some() || format.is_some()).then_some(Variant { width, format }))
+}
+
+/// Format a variant is made in when none is asked for: the source's own
+/// where it is one of the `VARIANT_FORMATS`, JPEG otherwise
+fn source_format(key: &str) -> &'static str {
+    let file = key.rsplit('/').next().unwrap_or(key);
+    let extension = file.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => "png",
+        Some("webp") => "webp",
+        _ => "jpg",
+    }
+}
+
+/// Where a variant of `key` is cached: `{key without extension}/variants/
+/// {etag}-{w}w.{format}`. For an upload that is inside the image's folder,
+/// so variants move, copy and get cleaned up with the image, and naming the
+/// source's ETag means a replaced source never serves stale variants.
+fn variant_key(key: &str, etag: &str, width: Option<u32>, format: &str) -> String {
+    let (folder, file) = key.rsplit_once('/').unwrap_or(("", key));
+    let stem = file.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(file);
+    let tag: String = etag.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+    let size = width.map(|w| format!("{}w", w)).unwrap_or_else(|| "full".to_string());
+    let base = if folder.is_empty() { stem.to_string() } else { format!("{}/{}", folder, stem) };
+    format!("{}/variants/{}-{}.{}", base, tag, size, format)
+}
+
+fn error_response(status: StatusCode, message: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({ "error": message }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Conditional and partial request headers forwarded to S3
+#[derive(Debug, Default, PartialEq)]
+struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<DateTime>,
+    range: Option<String>,
+}
+
+/// Read the caching headers of a request. If-Modified-Since only counts
+/// without If-None-Match (RFC 9110 13.1.3), and ranges in units other than
+/// bytes are ignored, so those requests get the whole image.
+fn conditions(headers: &HeaderMap) -> Conditions {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let if_none_match = header("If-None-Match").map(|v| v.to_string());
+    let if_modified_since = if if_none_match.is_none() {
+        header("If-Modified-Since")
+            .and_then(|v| DateTime::from_str(v, DateTimeFormat::HttpDate).ok())
+    } else {
+        None
+    };
+    let range = header("Range")
+        .filter(|v| v.starts_with("bytes="))
+        .map(|v| v.to_string());
+    Conditions { if_none_match, if_modified_since, range }
+}
 
 /// Proxy an image from S3 through Lambda
-/// This streams the image directly from S3 to the response
+/// This streams the image directly from S3 to the response. ETag and
+/// Last-Modified are passed on so clients can revalidate: a matching
+/// If-None-Match or If-Modified-Since gets a bodiless 304, and a byte Range
+/// gets a 206 with just those bytes. With a `variant` the image is resized
+/// and/or transcoded first, the result being kept in S3 for the next request.
 pub async fn proxy_image(
     s3_client: &S3Client,
     bucket: &str,
     key: &str,
+    headers: &HeaderMap,
+    variant: Option<Variant>,
 ) -> Result<Response<Body>, Error> {
-    // Fetch object from S3
-    let result = s3_client
+    let conditions = conditions(headers);
+    let Some(variant) = variant else {
+        return match serve_object(s3_client, bucket, key, &conditions).await? {
+            Some(response) => Ok(response),
+            None => error_response(StatusCode::NOT_FOUND, "Image not found"),
+        };
+    };
+
+    // The source's ETag names the cached variant
+    let head = match s3_client.head_object().bucket(bucket).key(key).send().await {
+        Ok(head) => head,
+        Err(e) if e.raw_response().map(|r| r.status().as_u16()) == Some(404) => {
+            return error_response(StatusCode::NOT_FOUND, "Image not found");
+        }
+        Err(e) => return Err(format!("Failed to read object from S3: {}", e).into()),
+    };
+    let format = variant.format.unwrap_or_else(|| source_format(key));
+    let cached = variant_key(key, head.e_tag().unwrap_or_default(), variant.width, format);
+    if let Some(response) = serve_object(s3_client, bucket, &cached, &conditions).await? {
+        return Ok(response);
+    }
+
+    if head.content_length().unwrap_or(0) > MAX_VARIANT_SOURCE_BYTES {
+        return error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Image is too large to resize; request its preview instead",
+        );
+    }
+    let source = s3_client
         .get_object()
         .bucket(bucket)
         .key(key)
         .send()
         .await
-        .map_err(|e| format!("Failed to get object from S3: {}", e))?;
+        .map_err(|e| format!("Failed to get object from S3: {}", e))?
+        .body
+        .collect()
+        .await
+        .map_err(|e| format!("Failed to read S3 body: {}", e))?
+        .into_bytes();
+    let (_, _, bytes) = match image_processing::resize_variant(&source, variant.width, format) {
+        Ok(resized) => resized,
+        Err(e) => return error_response(StatusCode::UNPROCESSABLE_ENTITY, &e),
+    };
+
+    // A failed write only costs the next request another resize
+    let content_type = image_processing::variant_content_type(format);
+    let stored = s3_client
+        .put_object()
+        .bucket(bucket)
+        .key(&cached)
+        .content_type(content_type)
+        .cache_control(CACHE_CONTROL)
+        .body(ByteStream::from(bytes.clone()))
+        .send()
+        .await;
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", content_type)
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Cache-Control", CACHE_CONTROL);
+    match stored {
+        Ok(output) => {
+            if let Some(etag) = output.e_tag() {
+                builder = builder.header("ETag", etag);
+            }
+        }
+        Err(e) => tracing::warn!("Failed to cache image variant {}: {}", cached, e),
+    }
+    Ok(builder.body(bytes.into()).map_err(Box::new)?)
+}
+
+/// Serve an object as stored, or None when there is no such key
+async fn serve_object(
+    s3_client: &S3Client,
+    bucket: &str,
+    key: &str,
+    conditions: &Conditions,
+) -> Result<Option<Response<Body>>, Error> {
+    // Fetch object from S3, which evaluates the conditions itself
+    let result = s3_client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .set_if_none_match(conditions.if_none_match.clone())
+        .set_if_modified_since(conditions.if_modified_since)
+        .set_range(conditions.range.clone())
+        .send()
+        .await;
+
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            let status = e.raw_response().map(|r| r.status().as_u16());
+            if status == Some(304) {
+                let mut builder = Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .header("Access-Control-Allow-Origin", "*")
+                    .header("Cache-Control", CACHE_CONTROL);
+                let raw = e.raw_response().map(|r| r.headers());
+                for name in ["ETag", "Last-Modified"] {
+                    if let Some(value) = raw.and_then(|h| h.get(name)) {
+                        builder = builder.header(name, value);
+                    }
+                }
+                return Ok(Some(builder.body(Body::Empty).map_err(Box::new)?));
+            }
+            if e.code() == Some("InvalidRange") || status == Some(416) {
+                return Ok(Some(Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header("Access-Control-Allow-Origin", "*")
+                    .header("Accept-Ranges", "bytes")
+                    .body(Body::Empty)
+                    .map_err(Box::new)?));
+            }
+            if e.code() == Some("NoSuchKey") || status == Some(404) {
+                return Ok(None);
+            }
+            return Err(format!("Failed to get object from S3: {}", e).into());
+        }
+    };
 
     // Get content type
     let content_type = result
         .content_type()
         .unwrap_or("application/octet-stream")
         .to_string();
+    let etag = result.e_tag().map(|t| t.to_string());
+    let last_modified = result
+        .last_modified()
+        .and_then(|t| t.fmt(DateTimeFormat::HttpDate).ok());
+    let content_range = result.content_range().map(|r| r.to_string());
 
     // Get the body bytes
     let body_bytes = result
@@ -32,11 +371,108 @@ pub async fn proxy_image(
         .into_bytes();
 
     // Return image with proper headers
-    Ok(Response::builder()
-        .status(StatusCode::OK)
+    let mut builder = Response::builder()
+        .status(if content_range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK })
         .header("Content-Type", content_type)
         .header("Access-Control-Allow-Origin", "*")
-        .header("Cache-Control", "public, max-age=31536000, immutable") // Cache for 1 year
-        .body(body_bytes.to_vec().into())
-        .map_err(Box::new)?)
+        .header("Cache-Control", CACHE_CONTROL)
+        .header("Accept-Ranges", "bytes");
+    if let Some(etag) = etag {
+        builder = builder.header("ETag", etag);
+    }
+    if let Some(last_modified) = last_modified {
+        builder = builder.header("Last-Modified", last_modified);
+    }
+    if let Some(content_range) = content_range {
+        builder = builder.header("Content-Range", content_range);
+    }
+    Ok(Some(builder.body(body_bytes.to_vec().into()).map_err(Box::new)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lambda_http::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_conditions() {
+        assert_eq!(conditions(&HeaderMap::new()), Conditions::default());
+
+        let since = conditions(&headers(&[("if-modified-since", "Wed, 21 Oct 2015 07:28:00 GMT")]));
+        assert_eq!(since.if_modified_since, Some(DateTime::from_secs(1445412480)));
+
+        // If-None-Match wins over If-Modified-Since
+        let both = conditions(&headers(&[
+            ("if-none-match", "\"abc\""),
+            ("if-modified-since", "Wed, 21 Oct 2015 07:28:00 GMT"),
+        ]));
+        assert_eq!(both.if_none_match.as_deref(), Some("\"abc\""));
+        assert_eq!(both.if_modified_since, None);
+
+        // Unparseable dates and non-byte ranges are ignored
+        assert_eq!(conditions(&headers(&[("if-modified-since", "yesterday")])), Conditions::default());
+        assert_eq!(conditions(&headers(&[("range", "items=0-5")])), Conditions::default());
+        assert_eq!(
+            conditions(&headers(&[("range", "bytes=0-1023")])).range.as_deref(),
+            Some("bytes=0-1023")
+        );
+    }
+
+    #[test]
+    fn test_verify_token() {
+        let token = sign_token("secret", "p1", "u1", 1000);
+        assert_eq!(verify_token("secret", "p1", &token, 999), Some("u1"));
+
+        // Expired, for another project, or signed with another secret
+        assert_eq!(verify_token("secret", "p1", &token, 1000), None);
+        assert_eq!(verify_token("secret", "p2", &token, 999), None);
+        assert_eq!(verify_token("other", "p1", &token, 999), None);
+
+        // Tampered with
+        let forged = token.replacen("u1", "u2", 1);
+        assert_eq!(verify_token("secret", "p1", &forged, 999), None);
+        let extended = token.replacen("1000", "9999", 1);
+        assert_eq!(verify_token("secret", "p1", &extended, 999), None);
+        assert_eq!(verify_token("secret", "p1", "garbage", 999), None);
+    }
+
+    #[test]
+    fn test_parse_variant() {
+        assert_eq!(parse_variant(None, None), Ok(None));
+        assert_eq!(
+            parse_variant(Some("800"), Some("WebP")),
+            Ok(Some(Variant { width: Some(800), format: Some("webp") }))
+        );
+        assert_eq!(
+            parse_variant(None, Some("jpeg")),
+            Ok(Some(Variant { width: None, format: Some("jpg") }))
+        );
+        assert!(parse_variant(Some("0"), None).is_err());
+        assert!(parse_variant(Some("99999"), None).is_err());
+        assert!(parse_variant(Some("wide"), None).is_err());
+        assert!(parse_variant(None, Some("gif")).is_err());
+    }
+
+    #[test]
+    fn test_variant_key() {
+        // Inside the image's folder, next to its pyramid
+        assert_eq!(
+            variant_key("projects/p/blocks/b/i.png", "\"abc123\"", Some(800), "webp"),
+            "projects/p/blocks/b/i/variants/abc123-800w.webp"
+        );
+        assert_eq!(
+            variant_key("projects/p/blocks/b/i/3000w.jpg", "\"d41d-2\"", None, "png"),
+            "projects/p/blocks/b/i/3000w/variants/d41d-2-full.png"
+        );
+        assert_eq!(source_format("projects/p/blocks/b/i.PNG"), "png");
+        assert_eq!(source_format("projects/p/blocks/b/i.heic"), "jpg");
+    }
 }
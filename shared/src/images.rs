@@ -1,16 +1,364 @@
-use crate::types::{CreateImageRequest, Image, UpdateImageRequest};
+use crate::audit::AuditEntry;
+use crate::pagination::{Filter, Page, PageParams, PageRequest};
+use crate::responses::json_response;
+use crate::types::{
+    Annotation, Calibration, CreateImageRequest, Geometry, Image, ImageLevel, ImageMetadata,
+    ReplaceImageRequest, UpdateImageRequest,
+};
 use aws_sdk_dynamodb::types::AttributeValue;
 use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
 use lambda_http::{http::StatusCode, Body, Error, Response};
 
-/// Create a new image in a block
+/// Labelling states of an image. `no_objects` is done with nothing to label
+/// (a negative sample); `skipped` is left out of the work altogether.
+pub const IMAGE_STATUSES: [&str; 5] = [
+    "unannotated",
+    "in_progress",
+    "annotated",
+    "skipped",
+    "no_objects",
+];
+
+/// Calibration is stored as a JSON string on the image item
+fn parse_calibration(
+    item: &std::collections::HashMap<String, AttributeValue>,
+) -> Option<Calibration> {
+    item.get("calibration")
+        .and_then(|v| v.as_s().ok())
+        .and_then(|s| serde_json::from_str(s).ok())
+}
+
+fn parse_levels(item: &std::collections::HashMap<String, AttributeValue>) -> Vec<ImageLevel> {
+    item.get("levels")
+        .and_then(|v| v.as_s().ok())
+        .and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or_default()
+}
+
+/// Reject a non-positive scale, returning the 400 response to send
+fn invalid_calibration(calibration: &Calibration) -> Result<Option<Response<Body>>, Error> {
+    if calibration.units_per_pixel.is_finite() && calibration.units_per_pixel > 0.0 {
+        return Ok(None);
+    }
+    Ok(Some(
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "calibration.units_per_pixel must be a positive number"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?,
+    ))
+}
+
+/// Build an image from its item (the URL as stored)
+pub(crate) fn image_from_item(
+    block_id: &str,
+    image_id: &str,
+    item: &std::collections::HashMap<String, AttributeValue>,
+) -> Image {
+    Image {
+        image_id: image_id.to_string(),
+        block_id: block_id.to_string(),
+        url: item
+            .get("url")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        locked: item
+            .get("locked")
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(false),
+        order: item
+            .get("order")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse().ok()),
+        uploaded_at: item
+            .get("uploaded_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        calibration: parse_calibration(item),
+        file_name: item
+            .get("file_name")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        captured_at: item
+            .get("captured_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        annotation_count: stored_annotation_count(item),
+        status: item
+            .get("status")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "unannotated".to_string()),
+        width: stored_size(item).map(|(width, _)| width),
+        height: stored_size(item).map(|(_, height)| height),
+        size_bytes: item
+            .get("size_bytes")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse().ok()),
+        content_type: item
+            .get("content_type")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        levels: parse_levels(item),
+        thumbnail_url: item
+            .get("thumbnail_url")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        tiles_url: item
+            .get("tiles_url")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        photo: item
+            .get("photo")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| serde_json::from_str(s).ok()),
+        original_format: item
+            .get("original_format")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        processing: item
+            .get("processing")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "ready".to_string()),
+        tags: item
+            .get("tags")
+            .and_then(|v| v.as_ss().ok())
+            .map(|tags| {
+                let mut tags = tags.clone();
+                tags.sort();
+                tags
+            })
+            .unwrap_or_default(),
+    }
+}
+
+/// Where an image lives. Stored at PK=IMAGE#{iid}, SK=METADATA so writes
+/// that only have the image id can find its project and block.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImageLocation {
+    pub project_id: String,
+    pub block_id: String,
+}
+
+const LOCATION_SK: &str = "METADATA";
+
+pub(crate) fn location_key(image_id: &str) -> (AttributeValue, AttributeValue) {
+    (
+        AttributeValue::S(format!("IMAGE#{}", image_id)),
+        AttributeValue::S(LOCATION_SK.to_string()),
+    )
+}
+
+/// Record where an image lives, overwriting any earlier location
+pub(crate) async fn put_image_location(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    location: &ImageLocation,
+) -> Result<(), Error> {
+    let (pk, sk) = location_key(image_id);
+    client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", pk)
+        .item("SK", sk)
+        .item(
+            "entity_type",
+            AttributeValue::S("image_location".to_string()),
+        )
+        .item("project_id", AttributeValue::S(location.project_id.clone()))
+        .item("block_id", AttributeValue::S(location.block_id.clone()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// The project and block of an image, if it has a location row
+pub async fn image_location(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+) -> Result<Option<ImageLocation>, Error> {
+    let (pk, sk) = location_key(image_id);
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", pk)
+        .key("SK", sk)
+        .send()
+        .await?;
+    let text = |item: &std::collections::HashMap<String, AttributeValue>, name: &str| {
+        item.get(name)
+            .and_then(|v| v.as_s().ok())
+            .filter(|s| !s.is_empty())
+            .cloned()
+    };
+    Ok(result.item().and_then(|item| {
+        Some(ImageLocation {
+            project_id: text(item, "project_id")?,
+            block_id: text(item, "block_id")?,
+        })
+    }))
+}
+
+/// Update moving an item's `annotation_count` by `delta`, conditional on the
+/// item existing so counting never creates a bare row
+pub(crate) fn annotation_count_update(
+    table_name: &str,
+    pk: String,
+    sk: String,
+    delta: i64,
+) -> Result<aws_sdk_dynamodb::types::TransactWriteItem, Error> {
+    let update = aws_sdk_dynamodb::types::Update::builder()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk))
+        .key("SK", AttributeValue::S(sk))
+        .update_expression("SET #count = if_not_exists(#count, :zero) + :delta")
+        .condition_expression("attribute_exists(PK)")
+        .expression_attribute_names("#count", "annotation_count")
+        .expression_attribute_values(":zero", AttributeValue::N("0".to_string()))
+        .expression_attribute_values(":delta", AttributeValue::N(delta.to_string()))
+        .build()?;
+    Ok(aws_sdk_dynamodb::types::TransactWriteItem::builder()
+        .update(update)
+        .build())
+}
+
+/// Changes to the live annotation counts of an image and its block, as
+/// transaction items to write together with the annotations. Empty when the
+/// image's location isn't known.
+pub(crate) fn annotation_count_updates(
+    table_name: &str,
+    image_id: &str,
+    location: &ImageLocation,
+    delta: i64,
+) -> Result<Vec<aws_sdk_dynamodb::types::TransactWriteItem>, Error> {
+    if location.project_id.is_empty() || location.block_id.is_empty() || delta == 0 {
+        return Ok(Vec::new());
+    }
+    Ok(vec![
+        annotation_count_update(
+            table_name,
+            format!("BLOCK#{}", location.block_id),
+            format!("IMAGE#{}", image_id),
+            delta,
+        )?,
+        annotation_count_update(
+            table_name,
+            format!("PROJECT#{}", location.project_id),
+            format!("BLOCK#{}", location.block_id),
+            delta,
+        )?,
+    ])
+}
+
+/// The same count changes on their own, for writes that can't share a
+/// transaction with them (batch writes). Best-effort like the class
+/// counts: the annotations are already written, so failures are only logged.
+pub(crate) async fn increment_annotation_counts(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+    location: &ImageLocation,
+    delta: i64,
+) {
+    let result = match annotation_count_updates(table_name, image_id, location, delta) {
+        Ok(updates) if updates.is_empty() => return,
+        Ok(updates) => client
+            .transact_write_items()
+            .set_transact_items(Some(updates))
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(Error::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        tracing::warn!(
+            "Failed to move annotation counts of image {} by {}: {}",
+            image_id,
+            delta,
+            e
+        );
+    }
+}
+
+/// Overwrite the `annotation_count` of an existing item; returns false when
+/// the item is gone
+pub(crate) async fn set_annotation_count(
+    client: &DynamoClient,
+    table_name: &str,
+    pk: String,
+    sk: String,
+    count: u64,
+) -> Result<bool, Error> {
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk))
+        .key("SK", AttributeValue::S(sk))
+        .update_expression("SET annotation_count = :count")
+        .condition_expression("attribute_exists(PK)")
+        .expression_attribute_values(":count", AttributeValue::N(count.to_string()))
+        .send()
+        .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Stored `annotation_count` of an image or block item
+pub(crate) fn stored_annotation_count(
+    item: &std::collections::HashMap<String, AttributeValue>,
+) -> u32 {
+    item.get("annotation_count")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<i64>().ok())
+        .map(|n| n.max(0) as u32)
+        .unwrap_or(0)
+}
+
+/// Create a new image in a block.
+/// Without an explicit `order`, the EXIF capture time is recorded and the
+/// block is re-ordered automatically (capture time, then file name).
 pub async fn create_image(
     client: &DynamoClient,
+    s3_client: &S3Client,
     table_name: &str,
+    user_id: &str,
+    project_id: &str,
     block_id: &str,
     body: &[u8],
 ) -> Result<Response<Body>, Error> {
-    let req: CreateImageRequest = serde_json::from_slice(body)?;
+    let mut req: CreateImageRequest = serde_json::from_slice(body)?;
+    if let Some(response) =
+        crate::blocks::check_assignee(client, table_name, project_id, block_id, user_id).await?
+    {
+        return Ok(response);
+    }
+
+    req.tags = match crate::annotations::normalize_tags(std::mem::take(&mut req.tags)) {
+        Ok(tags) => tags,
+        Err(e) => return json_response(StatusCode::BAD_REQUEST, serde_json::json!({"error": e})),
+    };
 
     let image_id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
@@ -34,17 +382,83 @@ pub async fn create_image(
         builder = builder.item("order", AttributeValue::N(order.to_string()));
     }
 
+    if let Some(calibration) = &req.calibration {
+        if let Some(resp) = invalid_calibration(calibration)? {
+            return Ok(resp);
+        }
+        builder = builder.item(
+            "calibration",
+            AttributeValue::S(serde_json::to_string(calibration)?),
+        );
+    }
+
+    if let Some(file_name) = &req.file_name {
+        builder = builder.item("file_name", AttributeValue::S(file_name.clone()));
+    }
+
+    if !req.tags.is_empty() {
+        builder = builder.item("tags", AttributeValue::Ss(req.tags.clone()));
+    }
+
+    let captured_at = match req.order {
+        Some(_) => None,
+        None => crate::ordering::read_capture_time(s3_client, &req.url).await,
+    };
+    if let Some(captured_at) = &captured_at {
+        builder = builder.item("captured_at", AttributeValue::S(captured_at.clone()));
+    }
+
     builder.send().await?;
+    put_image_location(
+        client,
+        table_name,
+        &image_id,
+        &ImageLocation {
+            project_id: project_id.to_string(),
+            block_id: block_id.to_string(),
+        },
+    )
+    .await?;
+    let counted = [(crate::block_stats::status_counter("unannotated"), 1)];
+    crate::block_stats::increment(client, table_name, block_id, &counted).await;
 
-    let image = Image {
+    let mut image = Image {
         image_id: image_id.clone(),
         block_id: block_id.to_string(),
         url: req.url,
         locked: false,
         order: req.order,
         uploaded_at: now,
+        calibration: req.calibration,
+        file_name: req.file_name,
+        captured_at,
+        annotation_count: 0,
+        status: "unannotated".to_string(),
+        width: None,
+        height: None,
+        size_bytes: None,
+        content_type: None,
+        levels: Vec::new(),
+        thumbnail_url: None,
+        tiles_url: None,
+        photo: None,
+        original_format: None,
+        processing: "ready".to_string(),
+        tags: req.tags,
     };
 
+    if image.order.is_none() {
+        let ordered = crate::ordering::apply_auto_order(client, table_name, block_id).await?;
+        image.order = ordered
+            .iter()
+            .find(|i| i.image_id == image_id)
+            .and_then(|i| i.order);
+    }
+    let entry = AuditEntry::new(project_id, user_id, "created", "image", &image_id)
+        .block(block_id)
+        .after(&image);
+    crate::audit::record(client, table_name, entry).await;
+
     Ok(Response::builder()
         .status(StatusCode::CREATED)
         .header("Content-Type", "application/json")
@@ -53,6 +467,292 @@ pub async fn create_image(
         .map_err(Box::new)?)
 }
 
+/// An object finished uploading through /annotate/upload, to record as an
+/// image of its block
+pub struct UploadedImage {
+    pub image_id: String,
+    pub url: String,
+    pub file_name: Option<String>,
+    pub content_type: Option<String>,
+    pub size_bytes: u64, // of the uploaded object, metered to the project
+}
+
+/// Record an uploaded object as an image of its block, so uploads don't
+/// depend on a second `POST .../images`. The image row, its location and the
+/// block's status counter are one transaction; the stream broadcasts the new
+/// row like any other. The image starts out `processing` until the upload
+/// worker has read its size and built its pyramid. Completing the same upload
+/// again returns the image already recorded.
+pub(crate) async fn register_upload(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    location: &ImageLocation,
+    upload: UploadedImage,
+) -> Result<Image, Error> {
+    let block_id = location.block_id.as_str();
+    let pk = format!("BLOCK#{}", block_id);
+    let sk = format!("IMAGE#{}", upload.image_id);
+    let now = chrono::Utc::now().to_rfc3339();
+    let captured_at = crate::ordering::read_capture_time(s3_client, &upload.url).await;
+
+    let mut item = std::collections::HashMap::from([
+        ("PK".to_string(), AttributeValue::S(pk.clone())),
+        ("SK".to_string(), AttributeValue::S(sk.clone())),
+        ("url".to_string(), AttributeValue::S(upload.url.clone())),
+        ("locked".to_string(), AttributeValue::Bool(false)),
+        ("uploaded_at".to_string(), AttributeValue::S(now.clone())),
+        (
+            "processing".to_string(),
+            AttributeValue::S("processing".to_string()),
+        ),
+    ]);
+    if let Some(file_name) = &upload.file_name {
+        item.insert(
+            "file_name".to_string(),
+            AttributeValue::S(file_name.clone()),
+        );
+    }
+    if let Some(captured_at) = &captured_at {
+        item.insert(
+            "captured_at".to_string(),
+            AttributeValue::S(captured_at.clone()),
+        );
+    }
+    if let Some(content_type) = &upload.content_type {
+        item.insert(
+            "content_type".to_string(),
+            AttributeValue::S(content_type.clone()),
+        );
+    }
+    let image_put = aws_sdk_dynamodb::types::Put::builder()
+        .table_name(table_name)
+        .set_item(Some(item))
+        .condition_expression("attribute_not_exists(PK)")
+        .build()?;
+    let (location_pk, location_sk) = location_key(&upload.image_id);
+    let location_put = aws_sdk_dynamodb::types::Put::builder()
+        .table_name(table_name)
+        .item("PK", location_pk)
+        .item("SK", location_sk)
+        .item(
+            "entity_type",
+            AttributeValue::S("image_location".to_string()),
+        )
+        .item("project_id", AttributeValue::S(location.project_id.clone()))
+        .item("block_id", AttributeValue::S(block_id.to_string()))
+        .build()?;
+    let mut transaction = client
+        .transact_write_items()
+        .transact_items(
+            aws_sdk_dynamodb::types::TransactWriteItem::builder()
+                .put(image_put)
+                .build(),
+        )
+        .transact_items(
+            aws_sdk_dynamodb::types::TransactWriteItem::builder()
+                .put(location_put)
+                .build(),
+        );
+    let counter = crate::block_stats::status_counter("unannotated");
+    if let Some(update) = crate::block_stats::counter_update(table_name, block_id, &counter, 1)? {
+        transaction = transaction.transact_items(update);
+    }
+    if let Err(e) = transaction.send().await {
+        if !e
+            .as_service_error()
+            .map(|se| se.is_transaction_canceled_exception())
+            .unwrap_or(false)
+        {
+            return Err(e.into());
+        }
+        let existing = client
+            .get_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(pk))
+            .key("SK", AttributeValue::S(sk))
+            .send()
+            .await?;
+        let Some(item) = existing.item() else {
+            return Err(format!("Image {} could not be recorded, retry", upload.image_id).into());
+        };
+        return Ok(image_from_item(block_id, &upload.image_id, item));
+    }
+    crate::storage_usage::record(
+        client,
+        table_name,
+        &location.project_id,
+        upload.size_bytes as i64,
+    )
+    .await;
+
+    let mut image = Image {
+        image_id: upload.image_id.clone(),
+        block_id: block_id.to_string(),
+        url: upload.url,
+        locked: false,
+        order: None,
+        uploaded_at: now,
+        calibration: None,
+        file_name: upload.file_name,
+        captured_at,
+        annotation_count: 0,
+        status: "unannotated".to_string(),
+        width: None,
+        height: None,
+        size_bytes: None,
+        content_type: upload.content_type,
+        levels: Vec::new(),
+        thumbnail_url: None,
+        tiles_url: None,
+        photo: None,
+        original_format: None,
+        processing: "processing".to_string(),
+        tags: Vec::new(),
+    };
+    let ordered = crate::ordering::apply_auto_order(client, table_name, block_id).await?;
+    image.order = ordered
+        .iter()
+        .find(|i| i.image_id == image.image_id)
+        .and_then(|i| i.order);
+    let entry = AuditEntry::new(
+        &location.project_id,
+        user_id,
+        "created",
+        "image",
+        &image.image_id,
+    )
+    .block(block_id)
+    .after(&image);
+    crate::audit::record(client, table_name, entry).await;
+    Ok(image)
+}
+
+/// What the upload worker made of an image: where its full resolution ended
+/// up, its size and pyramid, and its thumbnail
+pub struct ProcessedImage {
+    pub url: String,
+    pub thumbnail_url: Option<String>,
+    pub tiles_url: Option<String>,
+    pub metadata: ImageMetadata,
+}
+
+/// Flip a `processing` image to `ready` with what processing found, or to
+/// `failed` with the reason. False when the image isn't processing (already
+/// done by an earlier delivery of the same event, or deleted).
+pub(crate) async fn finish_processing(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+    image_id: &str,
+    outcome: Result<ProcessedImage, String>,
+) -> Result<bool, Error> {
+    let mut values = std::collections::HashMap::from([(
+        ":processing".to_string(),
+        AttributeValue::S("processing".to_string()),
+    )]);
+    let mut sets = vec!["#state = :state"];
+    let state = match outcome {
+        Ok(processed) => {
+            let metadata = processed.metadata;
+            for (name, value) in [
+                (":url", AttributeValue::S(processed.url)),
+                (
+                    ":width",
+                    AttributeValue::N(metadata.original_width.to_string()),
+                ),
+                (
+                    ":height",
+                    AttributeValue::N(metadata.original_height.to_string()),
+                ),
+                (
+                    ":size_bytes",
+                    AttributeValue::N(metadata.file_size.to_string()),
+                ),
+                (
+                    ":levels",
+                    AttributeValue::S(serde_json::to_string(&metadata.levels)?),
+                ),
+            ] {
+                values.insert(name.to_string(), value);
+            }
+            sets.extend([
+                "#url = :url",
+                "#width = :width",
+                "#height = :height",
+                "#size_bytes = :size_bytes",
+                "#levels = :levels",
+            ]);
+            if let Some(thumbnail_url) = processed.thumbnail_url {
+                values.insert(
+                    ":thumbnail_url".to_string(),
+                    AttributeValue::S(thumbnail_url),
+                );
+                sets.push("#thumbnail_url = :thumbnail_url");
+            }
+            if let Some(tiles_url) = processed.tiles_url {
+                values.insert(":tiles_url".to_string(), AttributeValue::S(tiles_url));
+                sets.push("#tiles_url = :tiles_url");
+            }
+            if let Some(photo) = &metadata.photo {
+                values.insert(
+                    ":photo".to_string(),
+                    AttributeValue::S(serde_json::to_string(photo)?),
+                );
+                sets.push("#photo = :photo");
+            }
+            // Converted uploads are served as what they became
+            if let Some(original_format) = &metadata.original_format {
+                values.insert(
+                    ":original_format".to_string(),
+                    AttributeValue::S(original_format.clone()),
+                );
+                sets.push("#original_format = :original_format");
+                if let Some(content_type) = crate::s3_multipart::content_type_for(&metadata.format)
+                {
+                    values.insert(
+                        ":content_type".to_string(),
+                        AttributeValue::S(content_type.to_string()),
+                    );
+                    sets.push("#content_type = :content_type");
+                }
+            }
+            "ready"
+        }
+        Err(reason) => {
+            values.insert(":processing_error".to_string(), AttributeValue::S(reason));
+            sets.push("#processing_error = :processing_error");
+            "failed"
+        }
+    };
+    values.insert(":state".to_string(), AttributeValue::S(state.to_string()));
+    let mut update = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("BLOCK#{}", block_id)))
+        .key("SK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .update_expression(format!("SET {}", sets.join(", ")))
+        .condition_expression("#state = :processing")
+        .expression_attribute_names("#state", "processing")
+        .set_expression_attribute_values(Some(values));
+    for set in &sets[1..] {
+        let name = set.split(' ').next().unwrap_or_default();
+        update = update.expression_attribute_names(name, &name[1..]);
+    }
+    match update.send().await {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
 /// Get a specific image
 pub async fn get_image(
     client: &DynamoClient,
@@ -72,29 +772,13 @@ pub async fn get_image(
         .await?;
 
     if let Some(item) = result.item() {
-        let image = Image {
-            image_id: image_id.to_string(),
-            block_id: block_id.to_string(),
-            url: item
-                .get("url")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-            locked: item
-                .get("locked")
-                .and_then(|v| v.as_bool().ok())
-                .copied()
-                .unwrap_or(false),
-            order: item
-                .get("order")
-                .and_then(|v| v.as_n().ok())
-                .and_then(|n| n.parse().ok()),
-            uploaded_at: item
-                .get("uploaded_at")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-        };
+        let mut image = image_from_item(block_id, image_id, item);
+        image.url = crate::storage::public_url(&image.url);
+        image.thumbnail_url = image
+            .thumbnail_url
+            .as_deref()
+            .map(crate::storage::public_url);
+        image.tiles_url = image.tiles_url.as_deref().map(crate::storage::public_url);
 
         Ok(Response::builder()
             .status(StatusCode::OK)
@@ -116,85 +800,176 @@ pub async fn get_image(
     }
 }
 
-/// List all images for a block
-pub async fn list_block_images(
+/// Sort images by order, unordered ones last
+fn sort_by_order(images: &mut [Image]) {
+    images.sort_by(|a, b| match (a.order, b.order) {
+        (Some(a_order), Some(b_order)) => a_order.cmp(&b_order),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+}
+
+/// `?tag=&status=` of a block's image listing
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImageFilter<'a> {
+    pub tag: Option<&'a str>,
+    pub status: Option<&'a str>,
+}
+
+impl ImageFilter<'_> {
+    /// FilterExpression matching images that pass every set field, if any is
+    /// set. Like annotation filters it applies after the block is read, so a
+    /// page can take several reads to fill.
+    fn expression(&self) -> Option<Filter> {
+        let mut filter = Filter::default();
+        if let Some(tag) = self.tag {
+            filter = filter.and("contains(#tags, :tags)", "tags", tag.trim());
+        }
+        if let Some(status) = self.status {
+            // Images from before statuses were recorded are unannotated
+            filter = match status {
+                "unannotated" => filter.and(
+                    "(#status = :status OR attribute_not_exists(#status))",
+                    "status",
+                    status,
+                ),
+                _ => filter.equals("status", status),
+            };
+        }
+        (!filter.expression.is_empty()).then_some(filter)
+    }
+}
+
+/// Fetch a page of a block's images matching `filter`, in storage (image id) order
+pub async fn fetch_block_images_page(
     client: &DynamoClient,
     table_name: &str,
     block_id: &str,
-) -> Result<Response<Body>, Error> {
+    filter: ImageFilter<'_>,
+    request: &PageRequest,
+) -> Result<Page<Image>, Error> {
     let pk = format!("BLOCK#{}", block_id);
-
-    let result = client
-        .query()
-        .table_name(table_name)
-        .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
-        .expression_attribute_values(":pk", AttributeValue::S(pk))
-        .expression_attribute_values(":sk_prefix", AttributeValue::S("IMAGE#".to_string()))
-        .send()
-        .await?;
+    let expression = filter.expression();
+    let page = crate::pagination::query_prefix(
+        client,
+        table_name,
+        &pk,
+        "IMAGE#",
+        expression.as_ref(),
+        request,
+    )
+    .await?;
 
     let mut images = Vec::new();
 
-    for item in result.items() {
+    for item in &page.items {
         if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
             if let Some(image_id) = sk.strip_prefix("IMAGE#") {
-                let image = Image {
-                    image_id: image_id.to_string(),
-                    block_id: block_id.to_string(),
-                    url: item
-                        .get("url")
-                        .and_then(|v| v.as_s().ok())
-                        .map(|s| s.to_string())
-                        .unwrap_or_default(),
-                    locked: item
-                        .get("locked")
-                        .and_then(|v| v.as_bool().ok())
-                        .copied()
-                        .unwrap_or(false),
-                    order: item
-                        .get("order")
-                        .and_then(|v| v.as_n().ok())
-                        .and_then(|n| n.parse().ok()),
-                    uploaded_at: item
-                        .get("uploaded_at")
-                        .and_then(|v| v.as_s().ok())
-                        .map(|s| s.to_string())
-                        .unwrap_or_default(),
-                };
+                let image = image_from_item(block_id, image_id, item);
                 images.push(image);
             }
         }
     }
 
-    // Sort by order
-    images.sort_by(|a, b| match (a.order, b.order) {
-        (Some(a_order), Some(b_order)) => a_order.cmp(&b_order),
-        (Some(_), None) => std::cmp::Ordering::Less,
-        (None, Some(_)) => std::cmp::Ordering::Greater,
-        (None, None) => std::cmp::Ordering::Equal,
-    });
+    Ok(Page {
+        items: images,
+        next_cursor: page.next_cursor,
+    })
+}
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
-        .body(serde_json::to_string(&images)?.into())
-        .map_err(Box::new)?)
+/// Fetch all images for a block, sorted by order
+pub async fn fetch_block_images(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+) -> Result<Vec<Image>, Error> {
+    let mut images = fetch_block_images_page(
+        client,
+        table_name,
+        block_id,
+        ImageFilter::default(),
+        &PageRequest::default(),
+    )
+    .await?
+    .items;
+    sort_by_order(&mut images);
+    Ok(images)
+}
+
+/// List a block's images matching `filter`, sorted by order. With
+/// `?limit=&cursor=` pages follow storage order and each page is sorted on
+/// its own. The block must be one of `project_id`'s.
+pub async fn list_block_images(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    block_id: &str,
+    filter: ImageFilter<'_>,
+    params: PageParams<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match crate::pagination::page_request(params, "IMAGE#") {
+        Ok(request) => request,
+        Err(e) => return crate::pagination::invalid_page(e),
+    };
+    if crate::blocks::fetch_block(client, table_name, project_id, block_id)
+        .await?
+        .is_none()
+    {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Block not found"}),
+        );
+    }
+    let mut page = fetch_block_images_page(client, table_name, block_id, filter, &request).await?;
+    sort_by_order(&mut page.items);
+    for image in &mut page.items {
+        image.url = crate::storage::public_url(&image.url);
+        image.thumbnail_url = image
+            .thumbnail_url
+            .as_deref()
+            .map(crate::storage::public_url);
+        image.tiles_url = image.tiles_url.as_deref().map(crate::storage::public_url);
+    }
+
+    crate::pagination::page_response(&page)
 }
 
 /// Update an image
 pub async fn update_image(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     block_id: &str,
     image_id: &str,
     body: &[u8],
 ) -> Result<Response<Body>, Error> {
-    let req: UpdateImageRequest = serde_json::from_slice(body)?;
+    let mut req: UpdateImageRequest = serde_json::from_slice(body)?;
+    if let Some(response) =
+        crate::locks::check_unlocked(client, table_name, "image", image_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    if let Some(response) =
+        crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await?
+    {
+        return Ok(response);
+    }
     let pk = format!("BLOCK#{}", block_id);
     let sk = format!("IMAGE#{}", image_id);
 
+    let tags = match req
+        .tags
+        .take()
+        .map(crate::annotations::normalize_tags)
+        .transpose()
+    {
+        Ok(tags) => tags,
+        Err(e) => return json_response(StatusCode::BAD_REQUEST, serde_json::json!({"error": e})),
+    };
+
     let mut update_expr = vec![];
+    let mut remove_expr = vec![];
     let mut expr_names = std::collections::HashMap::new();
     let mut expr_values = std::collections::HashMap::new();
 
@@ -213,15 +988,63 @@ pub async fn update_image(
         );
     }
 
-    if !update_expr.is_empty() {
-        let update_expression = format!("SET {}", update_expr.join(", "));
+    if let Some(calibration) = &req.calibration {
+        if let Some(resp) = invalid_calibration(calibration)? {
+            return Ok(resp);
+        }
+        update_expr.push("#calibration = :calibration");
+        expr_names.insert("#calibration".to_string(), "calibration".to_string());
+        expr_values.insert(
+            ":calibration".to_string(),
+            AttributeValue::S(serde_json::to_string(calibration)?),
+        );
+    }
+
+    if let Some(status) = &req.status {
+        if !IMAGE_STATUSES.contains(&status.as_str()) {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                serde_json::json!({
+                    "error": format!(
+                        "Unknown status '{}', expected one of: {}",
+                        status,
+                        IMAGE_STATUSES.join(", ")
+                    )
+                }),
+            );
+        }
+        update_expr.push("#status = :status");
+        expr_names.insert("#status".to_string(), "status".to_string());
+        expr_values.insert(":status".to_string(), AttributeValue::S(status.clone()));
+    }
+
+    if let Some(tags) = tags {
+        expr_names.insert("#tags".to_string(), "tags".to_string());
+        if tags.is_empty() {
+            remove_expr.push("#tags");
+        } else {
+            update_expr.push("#tags = :tags");
+            expr_values.insert(":tags".to_string(), AttributeValue::Ss(tags));
+        }
+    }
+
+    if !update_expr.is_empty() || !remove_expr.is_empty() {
+        let mut clauses = Vec::new();
+        if !update_expr.is_empty() {
+            clauses.push(format!("SET {}", update_expr.join(", ")));
+        }
+        if !remove_expr.is_empty() {
+            clauses.push(format!("REMOVE {}", remove_expr.join(", ")));
+        }
+        let update_expression = clauses.join(" ");
 
         let mut builder = client
             .update_item()
             .table_name(table_name)
             .key("PK", AttributeValue::S(pk))
             .key("SK", AttributeValue::S(sk))
-            .update_expression(&update_expression);
+            .update_expression(&update_expression)
+            .return_values(aws_sdk_dynamodb::types::ReturnValue::UpdatedOld);
 
         for (k, v) in expr_names {
             builder = builder.expression_attribute_names(k, v);
@@ -231,34 +1054,846 @@ pub async fn update_image(
             builder = builder.expression_attribute_values(k, v);
         }
 
-        builder.send().await?;
+        let output = builder.send().await?;
+
+        // Move the image between the block's status counters
+        if let Some(status) = &req.status {
+            let old_status = output
+                .attributes()
+                .and_then(|item| item.get("status"))
+                .and_then(|v| v.as_s().ok())
+                .map(|s| s.as_str())
+                .unwrap_or("unannotated");
+            if old_status != status {
+                let changes = [
+                    (crate::block_stats::status_counter(old_status), -1),
+                    (crate::block_stats::status_counter(status), 1),
+                ];
+                crate::block_stats::increment(client, table_name, block_id, &changes).await;
+            }
+        }
+
+        let project_id = image_location(client, table_name, image_id)
+            .await?
+            .map(|l| l.project_id)
+            .unwrap_or_default();
+        let entry = AuditEntry::new(&project_id, user_id, "updated", "image", image_id)
+            .block(block_id)
+            .changes(body);
+        crate::audit::record(client, table_name, entry).await;
     }
 
     get_image(client, table_name, block_id, image_id).await
 }
 
-/// Delete an image
+/// Annotations rewritten per transaction. Each takes two items (the update
+/// and its history version); DynamoDB allows 100 and the last transaction
+/// also carries the image row.
+const RESCALE_CHUNK: usize = 49;
+
+/// Pixel size recorded on the image item by an earlier replace
+fn stored_size(item: &std::collections::HashMap<String, AttributeValue>) -> Option<(u32, u32)> {
+    let dimension = |name: &str| item.get(name)?.as_n().ok()?.parse().ok();
+    dimension("width").zip(dimension("height"))
+}
+
+/// Whether two stored URLs name the same object
+fn same_object(a: &str, b: &str) -> bool {
+    match (crate::storage::object_key(a), crate::storage::object_key(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Transaction items rescaling one annotation: the update and its history
+/// version
+fn rescale_items(
+    table_name: &str,
+    old: &Annotation,
+    geometry: &Geometry,
+    updated_at: &str,
+) -> Result<Vec<aws_sdk_dynamodb::types::TransactWriteItem>, Error> {
+    let rescaled = Annotation {
+        geometry: geometry.clone(),
+        updated_at: Some(updated_at.to_string()),
+        version: old.version + 1,
+        ..old.clone()
+    };
+    let history = crate::history::history_item(&rescaled, "rescaled", None)?;
+    Ok(vec![
+        crate::annotations::geometry_update(
+            table_name,
+            old,
+            geometry,
+            updated_at,
+            rescaled.version,
+        )?,
+        aws_sdk_dynamodb::types::TransactWriteItem::builder()
+            .put(
+                aws_sdk_dynamodb::types::Put::builder()
+                    .table_name(table_name)
+                    .set_item(Some(history))
+                    .build()?,
+            )
+            .build(),
+    ])
+}
+
+/// Put rescaled annotations back to the geometry and version they had
+/// before a replace failed part-way, dropping the history versions the
+/// rescale added. Best-effort: failures are only logged.
+async fn undo_rescale(
+    client: &DynamoClient,
+    table_name: &str,
+    committed: &[(Annotation, Geometry)],
+    updated_at: &str,
+) {
+    for chunk in committed.chunks(RESCALE_CHUNK) {
+        let mut items = Vec::new();
+        for (old, _) in chunk {
+            let rescaled = Annotation {
+                updated_at: Some(updated_at.to_string()),
+                ..old.clone()
+            };
+            let history_key = std::collections::HashMap::from([
+                (
+                    "PK".to_string(),
+                    AttributeValue::S(format!("IMAGE#{}", old.image_id)),
+                ),
+                (
+                    "SK".to_string(),
+                    AttributeValue::S(crate::history::history_sk(
+                        &old.annotation_id,
+                        old.version + 1,
+                    )),
+                ),
+            ]);
+            let delete = aws_sdk_dynamodb::types::Delete::builder()
+                .table_name(table_name)
+                .set_key(Some(history_key))
+                .build();
+            match (
+                crate::annotations::geometry_update(
+                    table_name,
+                    &rescaled,
+                    &old.geometry,
+                    updated_at,
+                    old.version,
+                ),
+                delete,
+            ) {
+                (Ok(update), Ok(delete)) => {
+                    items.push(update);
+                    items.push(
+                        aws_sdk_dynamodb::types::TransactWriteItem::builder()
+                            .delete(delete)
+                            .build(),
+                    );
+                }
+                (Err(e), _) => tracing::warn!("Failed to restore annotation geometry: {}", e),
+                (_, Err(e)) => tracing::warn!("Failed to restore annotation geometry: {}", e),
+            }
+        }
+        let result = client
+            .transact_write_items()
+            .set_transact_items(Some(items))
+            .send()
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to restore annotation geometry: {}", e);
+        }
+    }
+}
+
+/// Replace an image's file, or record that it was resized in place
+/// (POST /images/{id}/replace). In pixel projects every annotation is
+/// rescaled to the new size along with the image row, in transactions of up
+/// to 100 items; normalized coordinates don't depend on the size. A
+/// calibration is rescaled too when the aspect ratio is kept.
+pub async fn replace_image(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    location: &ImageLocation,
+    image_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let ImageLocation {
+        project_id,
+        block_id,
+    } = location;
+    let bad_request = |message: String| {
+        json_response(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({ "error": message }),
+        )
+    };
+    let req: ReplaceImageRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(e) => return bad_request(format!("Invalid request body: {}", e)),
+    };
+    if req.url.trim().is_empty() {
+        return bad_request("url is required".to_string());
+    }
+    if let Some(response) =
+        crate::locks::check_unlocked(client, table_name, "image", image_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    if let Some(response) =
+        crate::blocks::check_assignee(client, table_name, project_id, block_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    let size = req.width.zip(req.height);
+    let given_previous = req.previous_width.zip(req.previous_height);
+    if req.width.is_some() != req.height.is_some()
+        || req.previous_width.is_some() != req.previous_height.is_some()
+    {
+        return bad_request("width and height must be given together".to_string());
+    }
+    if [size, given_previous]
+        .iter()
+        .flatten()
+        .any(|(w, h)| *w == 0 || *h == 0)
+    {
+        return bad_request("width and height must be positive".to_string());
+    }
+
+    let pk = format!("BLOCK#{}", block_id);
+    let sk = format!("IMAGE#{}", image_id);
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk.clone()))
+        .key("SK", AttributeValue::S(sk.clone()))
+        .send()
+        .await?;
+    let Some(item) = result.item() else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Image not found"}),
+        );
+    };
+    let old = image_from_item(block_id, image_id, item);
+    // What was recorded about the old object doesn't describe the new one
+    let replacement = Image {
+        url: req.url.clone(),
+        width: None,
+        height: None,
+        size_bytes: None,
+        content_type: None,
+        levels: Vec::new(),
+        thumbnail_url: None,
+        tiles_url: None,
+        photo: None,
+        original_format: None,
+        ..old.clone()
+    };
+
+    let size = match size {
+        Some(size) => size,
+        None => match crate::export::image_dimensions(s3_client, &replacement).await {
+            Ok(size) => size,
+            Err(e) => return bad_request(format!("Could not read the new image size: {}", e)),
+        },
+    };
+    // Once overwritten in place, the old file can't tell us its size
+    let previous = match stored_size(item).or(given_previous) {
+        Some(previous) => Some(previous),
+        None if !same_object(&old.url, &req.url) => {
+            crate::export::image_dimensions(s3_client, &old).await.ok()
+        }
+        None => None,
+    };
+
+    let project = crate::projects::fetch_project(client, table_name, project_id).await?;
+    let mode = crate::projects::coordinate_mode(project.as_ref()).to_string();
+    let factors = match previous {
+        Some((w, h)) => (size.0 as f64 / w as f64, size.1 as f64 / h as f64),
+        None if mode == "pixel" => {
+            return bad_request(
+                "The previous image size is unknown: pass previous_width and previous_height"
+                    .to_string(),
+            );
+        }
+        None => (1.0, 1.0),
+    };
+    let resized = factors != (1.0, 1.0);
+
+    let mut rewrites = Vec::new();
+    if resized && mode == "pixel" {
+        for annotation in
+            crate::annotations::fetch_image_annotations(client, table_name, image_id).await?
+        {
+            let mut geometry = annotation.geometry.clone();
+            crate::geometry::resize(&mut geometry, factors.0, factors.1)?;
+            rewrites.push((annotation, geometry));
+        }
+    }
+
+    let mut calibration = old.calibration.clone();
+    if let Some(c) = calibration.as_mut().filter(|_| resized) {
+        if ((factors.0 - factors.1) / factors.0).abs() < 0.01 {
+            c.units_per_pixel /= factors.0;
+        } else {
+            tracing::warn!(
+                "Image {} changed aspect ratio; its calibration was left as is",
+                image_id
+            );
+        }
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut sets = vec![
+        "#url = :url",
+        "#width = :width",
+        "#height = :height",
+        "#replaced_at = :replaced_at",
+    ];
+    let mut image_update = aws_sdk_dynamodb::types::Update::builder()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk))
+        .key("SK", AttributeValue::S(sk))
+        .condition_expression("#url = :old_url")
+        .expression_attribute_values(":url", AttributeValue::S(req.url.clone()))
+        .expression_attribute_values(":old_url", AttributeValue::S(old.url.clone()))
+        .expression_attribute_values(":width", AttributeValue::N(size.0.to_string()))
+        .expression_attribute_values(":height", AttributeValue::N(size.1.to_string()))
+        .expression_attribute_values(":replaced_at", AttributeValue::S(now.clone()));
+    for name in [
+        "url",
+        "width",
+        "height",
+        "replaced_at",
+        "size_bytes",
+        "content_type",
+        "levels",
+        "thumbnail_url",
+        "tiles_url",
+        "photo",
+        "original_format",
+    ] {
+        image_update = image_update.expression_attribute_names(format!("#{}", name), name);
+    }
+    if let Some(c) = &calibration {
+        sets.push("#calibration = :calibration");
+        image_update = image_update
+            .expression_attribute_names("#calibration", "calibration")
+            .expression_attribute_values(
+                ":calibration",
+                AttributeValue::S(serde_json::to_string(c)?),
+            );
+    }
+    let image_update = aws_sdk_dynamodb::types::TransactWriteItem::builder()
+        .update(
+            image_update
+                .update_expression(format!(
+                    "SET {} REMOVE #size_bytes, #content_type, #levels, #thumbnail_url, #tiles_url, #photo, #original_format",
+                    sets.join(", ")
+                ))
+                .build()?,
+        )
+        .build();
+
+    // Older annotations get the state they start from recorded first
+    for (annotation, _) in rewrites.iter().filter(|(a, _)| a.version == 0) {
+        crate::history::record(client, table_name, annotation, "snapshot", None).await;
+    }
+    let mut chunks = Vec::new();
+    for chunk in rewrites.chunks(RESCALE_CHUNK) {
+        let mut items = Vec::new();
+        for (annotation, geometry) in chunk {
+            items.extend(rescale_items(table_name, annotation, geometry, &now)?);
+        }
+        chunks.push(items);
+    }
+    match chunks.last_mut() {
+        Some(last) => last.push(image_update),
+        None => chunks.push(vec![image_update]),
+    }
+
+    for (i, items) in chunks.into_iter().enumerate() {
+        let result = client
+            .transact_write_items()
+            .set_transact_items(Some(items))
+            .send()
+            .await;
+        if let Err(e) = result {
+            undo_rescale(client, table_name, &rewrites[..i * RESCALE_CHUNK], &now).await;
+            if e.as_service_error()
+                .map(|se| se.is_transaction_canceled_exception())
+                .unwrap_or(false)
+            {
+                return json_response(
+                    StatusCode::CONFLICT,
+                    serde_json::json!({"error": "The image or its annotations changed during the replace; try again"}),
+                );
+            }
+            return Err(e.into());
+        }
+    }
+    crate::activity::record_activity(client, table_name, project_id, "updated", rewrites.len())
+        .await;
+    tracing::info!(
+        "Replaced image {}: {:?} -> {:?}, {} annotations rescaled",
+        image_id,
+        previous,
+        size,
+        rewrites.len()
+    );
+
+    let dimensions =
+        |(width, height): (u32, u32)| serde_json::json!({"width": width, "height": height});
+    let entry = AuditEntry::new(project_id, user_id, "replaced", "image", image_id)
+        .block(block_id)
+        .before(&serde_json::json!({"url": old.url, "size": previous.map(dimensions)}))
+        .after(&serde_json::json!({
+            "url": req.url,
+            "size": dimensions(size),
+            "rescaled": rewrites.len(),
+        }));
+    crate::audit::record(client, table_name, entry).await;
+    let image = Image {
+        url: crate::storage::public_url(&req.url),
+        calibration,
+        width: Some(size.0),
+        height: Some(size.1),
+        ..replacement
+    };
+    json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "image": image,
+            "previous_size": previous.map(dimensions),
+            "size": dimensions(size),
+            "rescaled": rewrites.len(),
+            "coordinate_mode": mode,
+        }),
+    )
+}
+
+/// Block counter changes no longer counting deleted annotations in their classes
+fn class_counter_changes<'a>(
+    annotations: impl IntoIterator<Item = &'a Annotation>,
+) -> Vec<(String, i64)> {
+    crate::annotations::class_deltas(
+        annotations
+            .into_iter()
+            .map(|a| crate::block_stats::class_counter(&a.class_id)),
+    )
+    .into_iter()
+    .map(|(counter, count)| (counter, -(count as i64)))
+    .collect()
+}
+
+/// Stop counting deleted annotations in their project's classes
+async fn uncount_classes<'a>(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    class_ids: impl IntoIterator<Item = &'a str>,
+) {
+    for (class_id, count) in crate::annotations::class_deltas(class_ids) {
+        if let Err(e) =
+            crate::classes::increment_class_count(client, table_name, project_id, class_id, -count)
+                .await
+        {
+            tracing::warn!("Failed to update the count of class {}: {}", class_id, e);
+        }
+    }
+}
+
+/// Delete an image and everything under it: its annotations, history and
+/// comments, its S3 objects (the upload and its folder of levels, thumbnail
+/// and tiles) and its place in the block and class counts
 pub async fn delete_image(
     client: &DynamoClient,
+    s3_client: &S3Client,
     table_name: &str,
+    user_id: &str,
     block_id: &str,
     image_id: &str,
 ) -> Result<Response<Body>, Error> {
+    if let Some(response) =
+        crate::locks::check_unlocked(client, table_name, "image", image_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    if let Some(response) =
+        crate::blocks::check_image_assignee(client, table_name, image_id, user_id).await?
+    {
+        return Ok(response);
+    }
     let pk = format!("BLOCK#{}", block_id);
     let sk = format!("IMAGE#{}", image_id);
 
+    // The block and classes stop counting the image's status and annotations
+    let image_annotations =
+        crate::annotations::fetch_image_annotations(client, table_name, image_id).await?;
+
+    // The image's own partition goes first, so a retried delete still finds
+    // the image
+    let image_pk = format!("IMAGE#{}", image_id);
+    let image_rows = crate::blocks::partition_keys(
+        client,
+        table_name,
+        &image_pk,
+        &crate::blocks::IMAGE_ROW_PREFIXES,
+    )
+    .await?;
+    let total = image_rows.len();
+    let rows = image_rows
+        .iter()
+        .map(|sk| crate::blocks::row_key(&image_pk, sk))
+        .collect();
+    let failed = crate::annotations::batch_delete_keys(client, table_name, rows).await?;
+    if !failed.is_empty() {
+        // The annotations that did go stop being counted now, and the retry
+        // uncounts the rest
+        let deleted = crate::annotations::deleted_annotations(&image_annotations, &failed);
+        if !deleted.is_empty() {
+            if let Some(location) = image_location(client, table_name, image_id).await? {
+                let changes = class_counter_changes(deleted.iter().copied());
+                crate::block_stats::increment(client, table_name, block_id, &changes).await;
+                uncount_classes(
+                    client,
+                    table_name,
+                    &location.project_id,
+                    deleted.iter().map(|a| a.class_id.as_str()),
+                )
+                .await;
+                increment_annotation_counts(
+                    client,
+                    table_name,
+                    image_id,
+                    &location,
+                    -(deleted.len() as i64),
+                )
+                .await;
+            }
+        }
+        tracing::error!(
+            "Deleting image {} left {} of {} records",
+            image_id,
+            failed.len(),
+            total
+        );
+        return json_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({
+                "error": "Image was only partly deleted, retry the delete",
+                "remaining": failed.len(),
+            }),
+        );
+    }
+
     // Delete BLOCK#→IMAGE# row
-    client
+    let deleted = client
         .delete_item()
         .table_name(table_name)
         .key("PK", AttributeValue::S(pk))
         .key("SK", AttributeValue::S(sk))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
+        .send()
+        .await?;
+    let annotation_count = deleted
+        .attributes()
+        .map(stored_annotation_count)
+        .unwrap_or(0);
+    if let Some(item) = deleted.attributes() {
+        let status = item
+            .get("status")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.as_str())
+            .unwrap_or("unannotated");
+        let mut changes = vec![(crate::block_stats::status_counter(status), -1)];
+        changes.extend(class_counter_changes(&image_annotations));
+        crate::block_stats::increment(client, table_name, block_id, &changes).await;
+    }
+
+    let (pk, sk) = location_key(image_id);
+    let location = client
+        .delete_item()
+        .table_name(table_name)
+        .key("PK", pk)
+        .key("SK", sk)
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
         .send()
         .await?;
 
+    // The block no longer counts the image's annotations
+    let project_id = location
+        .attributes()
+        .and_then(|item| item.get("project_id"))
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+    if !project_id.is_empty() {
+        uncount_classes(
+            client,
+            table_name,
+            &project_id,
+            image_annotations.iter().map(|a| a.class_id.as_str()),
+        )
+        .await;
+        let prefix = format!(
+            "{}{}",
+            crate::storage::block_prefix(&project_id, block_id),
+            image_id
+        );
+        let mut freed = 0;
+        for own in [".", "/"] {
+            let prefix = format!("{}{}", prefix, own);
+            match crate::blocks::delete_s3_prefix(s3_client, &prefix).await {
+                Ok(bytes) => freed += bytes,
+                Err(e) => {
+                    tracing::error!("Failed to delete S3 objects of image {}: {}", image_id, e)
+                }
+            }
+        }
+        crate::storage_usage::record(client, table_name, &project_id, -freed).await;
+    }
+    if !project_id.is_empty() && annotation_count > 0 {
+        let block = annotation_count_update(
+            table_name,
+            format!("PROJECT#{}", project_id),
+            format!("BLOCK#{}", block_id),
+            -(annotation_count as i64),
+        )?;
+        let result = client
+            .transact_write_items()
+            .transact_items(block)
+            .send()
+            .await;
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to update the annotation count of block {}: {}",
+                block_id,
+                e
+            );
+        }
+    }
+    let entry = AuditEntry::new(&project_id, user_id, "deleted", "image", image_id).block(block_id);
+    crate::audit::record(client, table_name, entry).await;
+
     Ok(Response::builder()
         .status(StatusCode::NO_CONTENT)
         .header("Access-Control-Allow-Origin", "*")
         .body(Body::Empty)
         .map_err(Box::new)?)
 }
+
+/// How long a presigned image download stays valid
+const DOWNLOAD_URL_TTL_SECS: u64 = 300;
+
+/// Sizes an image can be downloaded at
+pub const DOWNLOAD_LEVELS: [&str; 3] = ["full", "preview", "thumb"];
+
+/// Object key of an image at one of the `DOWNLOAD_LEVELS`. Images without a
+/// pyramid are their own preview; only processed uploads have a thumbnail.
+fn download_key(image: &Image, level: &str) -> Result<String, String> {
+    let full = crate::storage::object_key(&image.url)
+        .ok_or_else(|| format!("Unrecognised image URL: {}", image.url))?;
+    match level {
+        "full" => Ok(full),
+        // Pyramid levels sit next to the full resolution file
+        "preview" => match image.levels.iter().find(|l| l.purpose == "preview") {
+            Some(preview) => {
+                let folder = full
+                    .rsplit_once('/')
+                    .map(|(folder, _)| folder)
+                    .unwrap_or("");
+                Ok(format!("{}/{}", folder, preview.path))
+            }
+            None => Ok(full),
+        },
+        _ => image
+            .thumbnail_url
+            .as_deref()
+            .and_then(crate::storage::object_key)
+            .ok_or_else(|| "Image has no thumbnail".to_string()),
+    }
+}
+
+/// Presign a short-lived GET of an image at `level` (full unless given), for
+/// clients that can't use the CDN cookies or the proxy
+pub async fn get_download_url(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    image_id: &str,
+    level: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let level = level.unwrap_or("full");
+    if !DOWNLOAD_LEVELS.contains(&level) {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({
+                "error": format!(
+                    "Unknown level '{}', expected one of: {}",
+                    level,
+                    DOWNLOAD_LEVELS.join(", ")
+                )
+            }),
+        );
+    }
+    let not_found = || {
+        json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Image not found"}),
+        )
+    };
+    let Some(location) = image_location(client, table_name, image_id).await? else {
+        return not_found();
+    };
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key(
+            "PK",
+            AttributeValue::S(format!("BLOCK#{}", location.block_id)),
+        )
+        .key("SK", AttributeValue::S(format!("IMAGE#{}", image_id)))
+        .send()
+        .await?;
+    let Some(item) = result.item() else {
+        return not_found();
+    };
+    let image = image_from_item(&location.block_id, image_id, item);
+    let key = match download_key(&image, level) {
+        Ok(key) => key,
+        Err(e) => return json_response(StatusCode::NOT_FOUND, serde_json::json!({"error": e})),
+    };
+
+    let expires_in = std::time::Duration::from_secs(DOWNLOAD_URL_TTL_SECS);
+    let presigned = s3_client
+        .get_object()
+        .bucket(crate::region::bucket_name())
+        .key(&key)
+        .presigned(
+            aws_sdk_s3::presigning::PresigningConfig::expires_in(expires_in)
+                .map_err(|e| format!("Failed to create presigning config: {}", e))?,
+        )
+        .await
+        .map_err(|e| format!("Failed to presign image download: {}", e))?;
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(DOWNLOAD_URL_TTL_SECS as i64);
+    json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "url": presigned.uri(),
+            "level": level,
+            "expires_at": expires_at.to_rfc3339(),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_filter_expression() {
+        assert!(ImageFilter::default().expression().is_none());
+        let filter = ImageFilter {
+            tag: Some(" weather:rain "),
+            status: Some("annotated"),
+        }
+        .expression()
+        .unwrap();
+        assert_eq!(
+            filter.expression,
+            "contains(#tags, :tags) AND #status = :status"
+        );
+        assert_eq!(
+            filter.values[":tags"],
+            AttributeValue::S("weather:rain".to_string())
+        );
+        // Images without a stored status count as unannotated
+        let filter = ImageFilter {
+            tag: None,
+            status: Some("unannotated"),
+        }
+        .expression()
+        .unwrap();
+        assert!(filter.expression.contains("attribute_not_exists(#status)"));
+    }
+
+    #[test]
+    fn test_download_key() {
+        let level = |width: u32, purpose: &str| ImageLevel {
+            width,
+            height: width / 2,
+            path: format!("{}w.jpg", width),
+            size: 0,
+            purpose: purpose.to_string(),
+        };
+        let mut image: Image = serde_json::from_value(serde_json::json!({
+            "image_id": "i",
+            "block_id": "b",
+            "url": "https://bucket.s3.us-east-1.amazonaws.com/projects/p/blocks/b/i.png",
+            "locked": false,
+            "order": null,
+            "uploaded_at": "",
+            "calibration": null,
+            "file_name": null,
+            "captured_at": null,
+        }))
+        .unwrap();
+        // Nothing smaller than the upload yet
+        assert_eq!(
+            download_key(&image, "preview").unwrap(),
+            "projects/p/blocks/b/i.png"
+        );
+        assert!(download_key(&image, "thumb").is_err());
+
+        image.url =
+            "https://bucket.s3.us-east-1.amazonaws.com/projects/p/blocks/b/i/6000w.jpg".to_string();
+        image.levels = vec![level(6000, "full"), level(3000, "preview")];
+        image.thumbnail_url =
+            Some("https://cdn.example.com/projects/p/blocks/b/i/thumb.jpg".to_string());
+        assert_eq!(
+            download_key(&image, "full").unwrap(),
+            "projects/p/blocks/b/i/6000w.jpg"
+        );
+        assert_eq!(
+            download_key(&image, "preview").unwrap(),
+            "projects/p/blocks/b/i/3000w.jpg"
+        );
+        assert_eq!(
+            download_key(&image, "thumb").unwrap(),
+            "projects/p/blocks/b/i/thumb.jpg"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_image_respects_lock() {
+        let (client, calls) =
+            crate::test_util::fake_dynamo(vec![crate::test_util::lock("image", "img", "u2")]);
+        let body = br#"{"locked": true}"#;
+        let response = update_image(&client, "table", "u1", "b", "img", body)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(*calls.lock().unwrap(), ["GetItem"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_block_images_checks_project() {
+        let (client, calls) =
+            crate::test_util::fake_dynamo(vec![crate::test_util::assigned_block("p", "b", "u1")]);
+        let response = list_block_images(
+            &client,
+            "table",
+            "other",
+            "b",
+            ImageFilter::default(),
+            PageParams::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(*calls.lock().unwrap(), ["GetItem"]);
+    }
+}
@@ -0,0 +1,640 @@
+use crate::types::{Class, CreateAnnotationRequest, CreateClassRequest, Geometry, Image, Point};
+use crate::{activity, annotations, blocks, classes, geometry, images};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// ========== CVAT 1.1 ==========
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CvatLabel {
+    pub name: String,
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CvatShape {
+    pub label: String,
+    pub geometry: Geometry,
+}
+
+#[derive(Debug, Clone)]
+pub struct CvatImage {
+    pub id: Option<String>,
+    pub name: String,
+    pub size: Option<(f64, f64)>, // width, height in pixels
+    pub shapes: Vec<CvatShape>,
+}
+
+#[derive(Debug, Default)]
+pub struct CvatDocument {
+    pub labels: Vec<CvatLabel>,
+    pub images: Vec<CvatImage>,
+    /// Shapes with no Doxle geometry equivalent (ellipses, cuboids, tracks, ...)
+    pub skipped_shapes: usize,
+}
+
+fn attributes(element: &BytesStart) -> Result<HashMap<String, String>, String> {
+    let mut attrs = HashMap::new();
+    for attr in element.attributes() {
+        let attr = attr.map_err(|e| format!("Invalid attribute: {}", e))?;
+        let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+        let value = attr
+            .unescape_value()
+            .map_err(|e| format!("Invalid attribute value: {}", e))?;
+        attrs.insert(key, value.to_string());
+    }
+    Ok(attrs)
+}
+
+fn number(attrs: &HashMap<String, String>, name: &str) -> Result<f64, String> {
+    attrs
+        .get(name)
+        .ok_or_else(|| format!("Missing '{}' attribute", name))?
+        .parse()
+        .map_err(|_| format!("Invalid '{}' attribute", name))
+}
+
+/// Parse CVAT's "x1,y1;x2,y2;..." point list
+fn parse_points(points: &str) -> Result<Vec<Point>, String> {
+    points
+        .split(';')
+        .filter(|p| !p.trim().is_empty())
+        .map(|pair| {
+            let (x, y) = pair
+                .split_once(',')
+                .ok_or_else(|| format!("Invalid point '{}'", pair))?;
+            Ok(Point {
+                x: x.trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid point '{}'", pair))?,
+                y: y.trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid point '{}'", pair))?,
+            })
+        })
+        .collect()
+}
+
+/// Convert a CVAT shape element; unsupported kinds yield no shapes.
+/// A CVAT `points` shape becomes one point annotation per point.
+fn parse_shape(kind: &str, attrs: &HashMap<String, String>) -> Result<Vec<CvatShape>, String> {
+    let label = attrs.get("label").cloned().unwrap_or_default();
+    let points = || parse_points(attrs.get("points").map(|s| s.as_str()).unwrap_or(""));
+    let geometry = match kind {
+        "box" => Geometry::BBox {
+            start: Point {
+                x: number(attrs, "xtl")?,
+                y: number(attrs, "ytl")?,
+            },
+            end: Point {
+                x: number(attrs, "xbr")?,
+                y: number(attrs, "ybr")?,
+            },
+        },
+        "polygon" => Geometry::Polygon { points: points()? },
+        "polyline" => Geometry::Polyline { points: points()? },
+        // CVAT mask RLE is also row-major and starts with background
+        "mask" => {
+            let counts = attrs
+                .get("rle")
+                .map(|s| s.as_str())
+                .unwrap_or("")
+                .split(',')
+                .filter(|c| !c.trim().is_empty())
+                .map(|c| {
+                    c.trim()
+                        .parse()
+                        .map_err(|_| format!("Invalid mask run '{}'", c))
+                })
+                .collect::<Result<Vec<u32>, String>>()?;
+            let mask = Geometry::Mask {
+                origin: Point {
+                    x: number(attrs, "left")?,
+                    y: number(attrs, "top")?,
+                },
+                width: number(attrs, "width")? as u32,
+                height: number(attrs, "height")? as u32,
+                counts,
+            };
+            geometry::validate(&mask).map_err(|e| format!("Invalid mask: {}", e))?;
+            mask
+        }
+        "points" => {
+            return Ok(points()?
+                .into_iter()
+                .map(|point| CvatShape {
+                    label: label.clone(),
+                    geometry: Geometry::Point { point },
+                })
+                .collect())
+        }
+        _ => return Ok(vec![]),
+    };
+    Ok(vec![CvatShape { label, geometry }])
+}
+
+/// Shape elements CVAT can place inside `<image>`
+const CVAT_SHAPES: [&str; 9] = [
+    "box", "polygon", "polyline", "points", "cuboid", "ellipse", "mask", "skeleton", "tag",
+];
+
+/// Parse a CVAT for images 1.1 annotation file
+pub fn parse_cvat(xml: &str) -> Result<CvatDocument, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut doc = CvatDocument::default();
+    let mut path: Vec<String> = Vec::new();
+    let mut label: Option<CvatLabel> = None;
+    let mut image: Option<CvatImage> = None;
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("XML error at byte {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                let is_empty = matches!(event, Event::Empty(_));
+                let parent = path.last().map(|s| s.as_str());
+
+                match name.as_str() {
+                    "label" if parent == Some("labels") => {
+                        label = Some(CvatLabel {
+                            name: String::new(),
+                            color: None,
+                        });
+                    }
+                    "image" => {
+                        let attrs = attributes(e)?;
+                        image = Some(CvatImage {
+                            id: attrs.get("id").cloned(),
+                            name: attrs.get("name").cloned().unwrap_or_default(),
+                            size: number(&attrs, "width")
+                                .and_then(|w| Ok((w, number(&attrs, "height")?)))
+                                .ok()
+                                .filter(|(w, h)| *w > 0.0 && *h > 0.0),
+                            shapes: Vec::new(),
+                        });
+                    }
+                    // Video-style tracks have no per-image geometry
+                    "track" => doc.skipped_shapes += 1,
+                    kind if parent == Some("image") && CVAT_SHAPES.contains(&kind) => {
+                        let shapes = parse_shape(kind, &attributes(e)?)?;
+                        if shapes.is_empty() {
+                            doc.skipped_shapes += 1;
+                        } else if let Some(image) = image.as_mut() {
+                            image.shapes.extend(shapes);
+                        }
+                    }
+                    _ => {}
+                }
+
+                if is_empty {
+                    if name == "image" {
+                        doc.images.extend(image.take());
+                    }
+                } else {
+                    path.push(name);
+                }
+            }
+            Event::Text(e) => {
+                let text = e.unescape().map_err(|e| format!("Invalid text: {}", e))?;
+                let n = path.len();
+                if n >= 2 && path[n - 2] == "label" {
+                    if let Some(label) = label.as_mut() {
+                        match path[n - 1].as_str() {
+                            "name" => label.name = text.to_string(),
+                            "color" => label.color = Some(text.to_string()),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Event::End(e) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+                match name.as_str() {
+                    "label" => {
+                        if let Some(label) = label.take().filter(|l| !l.name.is_empty()) {
+                            if !doc.labels.iter().any(|l| l.name == label.name) {
+                                doc.labels.push(label);
+                            }
+                        }
+                    }
+                    "image" => doc.images.extend(image.take()),
+                    _ => {}
+                }
+                path.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if path.first().map(|s| s.as_str()) == Some("annotations") {
+        return Err("Unexpected end of document".to_string());
+    }
+    Ok(doc)
+}
+
+/// Last path segment of a URL or file path, without query string
+fn file_name(path: &str) -> &str {
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Resolve a CVAT image to a Doxle image id: explicit map, then file name
+/// (or image id) match. CVAT's frame index says nothing about which upload
+/// it was, so anything else is reported unmatched for `image_map` to fix.
+fn match_image(
+    cvat: &CvatImage,
+    block_images: &[Image],
+    image_map: &HashMap<String, String>,
+) -> Option<String> {
+    if let Some(image_id) = image_map.get(&cvat.name) {
+        return Some(image_id.clone());
+    }
+
+    let cvat_file = file_name(&cvat.name);
+    let cvat_stem = cvat_file.split('.').next().unwrap_or(cvat_file);
+    block_images
+        .iter()
+        .find(|i| file_name(&i.url) == cvat_file || i.image_id == cvat_stem)
+        .map(|image| image.image_id.clone())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CvatImportRequest {
+    pub xml: String,
+    /// CVAT image name -> Doxle image_id, for names that don't match uploads
+    #[serde(default)]
+    pub image_map: HashMap<String, String>,
+    #[serde(default = "default_true")]
+    pub create_missing_classes: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub classes_created: Vec<String>,
+    pub unmapped_labels: Vec<String>,
+    pub unmatched_images: Vec<String>,
+    pub skipped_shapes: usize,
+    pub capped_images: Vec<String>, // skipped: would exceed the per-image annotation cap
+}
+
+/// Import a CVAT 1.1 XML export into a block
+/// (POST /projects/{pid}/blocks/{bid}/import/cvat).
+/// Accepts either the raw XML or `{"xml": ..., "image_map": {...}}`.
+pub async fn import_cvat(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let bad_request = |message: String| -> Result<Response<Body>, Error> {
+        Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": message}).to_string().into())
+            .map_err(Box::new)?)
+    };
+    if let Some(response) =
+        blocks::check_assignee(client, table_name, project_id, block_id, user_id).await?
+    {
+        return Ok(response);
+    }
+
+    let raw = String::from_utf8_lossy(body);
+    let req = if raw.trim_start().starts_with('<') {
+        CvatImportRequest {
+            xml: raw.to_string(),
+            image_map: HashMap::new(),
+            create_missing_classes: true,
+        }
+    } else {
+        match serde_json::from_slice::<CvatImportRequest>(body) {
+            Ok(req) => req,
+            Err(e) => return bad_request(format!("Invalid request body: {}", e)),
+        }
+    };
+
+    let doc = match parse_cvat(&req.xml) {
+        Ok(doc) => doc,
+        Err(e) => return bad_request(format!("Invalid CVAT XML: {}", e)),
+    };
+
+    // Map CVAT labels onto project classes by case-insensitive name
+    let mut project_classes: Vec<Class> =
+        classes::fetch_project_classes(client, table_name, project_id).await?;
+    let mut class_ids: HashMap<String, String> = HashMap::new();
+    let mut classes_created = Vec::new();
+    let mut unmapped_labels = Vec::new();
+
+    let mut labels = doc.labels.clone();
+    for shape in doc.images.iter().flat_map(|i| &i.shapes) {
+        if !labels.iter().any(|l| l.name == shape.label) {
+            labels.push(CvatLabel {
+                name: shape.label.clone(),
+                color: None,
+            });
+        }
+    }
+
+    for label in labels {
+        if let Some(class) = project_classes
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(&label.name))
+        {
+            class_ids.insert(label.name, class.class_id.clone());
+        } else if req.create_missing_classes && !label.name.is_empty() {
+            let class = classes::put_class(
+                client,
+                table_name,
+                project_id,
+                CreateClassRequest {
+                    name: label.name.clone(),
+                    color: label.color,
+                    properties: None,
+                    sort_order: None,
+                    hotkey: None,
+                },
+            )
+            .await?;
+            class_ids.insert(label.name.clone(), class.class_id.clone());
+            classes_created.push(label.name);
+            project_classes.push(class);
+        } else {
+            unmapped_labels.push(label.name);
+        }
+    }
+
+    // CVAT geometry is in pixels; normalized projects scale it by the image size
+    let project = crate::projects::fetch_project(client, table_name, project_id).await?;
+    let normalized = crate::projects::coordinate_mode(project.as_ref()) == "normalized";
+
+    let block_images = images::fetch_block_images(client, table_name, block_id).await?;
+    let location = images::ImageLocation {
+        project_id: project_id.to_string(),
+        block_id: block_id.to_string(),
+    };
+    let mut imported = 0;
+    let mut skipped_shapes = doc.skipped_shapes;
+    let mut unmatched_images = Vec::new();
+    let mut capped_images = Vec::new();
+    let annotation_cap = annotations::max_annotations_per_image();
+
+    for cvat_image in &doc.images {
+        let Some(image_id) = match_image(cvat_image, &block_images, &req.image_map) else {
+            unmatched_images.push(cvat_image.name.clone());
+            continue;
+        };
+        let existing = annotations::count_image_annotations(client, table_name, &image_id).await?;
+        let adding = cvat_image
+            .shapes
+            .iter()
+            .filter(|s| class_ids.contains_key(&s.label))
+            .count();
+        if let Err(e) = annotations::check_annotation_cap(existing, adding, annotation_cap) {
+            tracing::warn!("CVAT import: skipping {}: {}", cvat_image.name, e);
+            skipped_shapes += adding;
+            capped_images.push(cvat_image.name.clone());
+            continue;
+        }
+        for shape in &cvat_image.shapes {
+            let Some(class_id) = class_ids.get(&shape.label) else {
+                continue;
+            };
+            let mut shape_geometry = shape.geometry.clone();
+            if normalized {
+                let scaled = match cvat_image.size {
+                    Some((width, height)) => {
+                        geometry::scale(&mut shape_geometry, 1.0 / width, 1.0 / height)
+                    }
+                    None => Err("image has no width/height".to_string()),
+                };
+                if let Err(e) = scaled {
+                    tracing::warn!("CVAT import: skipping shape on {}: {}", cvat_image.name, e);
+                    skipped_shapes += 1;
+                    continue;
+                }
+            }
+            annotations::put_annotation(
+                client,
+                table_name,
+                user_id,
+                &image_id,
+                &location,
+                CreateAnnotationRequest {
+                    class_id: class_id.clone(),
+                    geometry: shape_geometry,
+                    tags: Vec::new(),
+                    annotation_id: None,
+                    source: None,
+                    confidence: None,
+                    z_index: None,
+                    attributes: Default::default(),
+                },
+            )
+            .await?;
+            imported += 1;
+        }
+    }
+    activity::record_activity(client, table_name, project_id, "created", imported).await;
+
+    tracing::info!(
+        "CVAT import into block {}: {} annotations, {} unmatched images",
+        block_id,
+        imported,
+        unmatched_images.len()
+    );
+
+    let report = ImportReport {
+        imported,
+        classes_created,
+        unmapped_labels,
+        unmatched_images,
+        skipped_shapes,
+        capped_images,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&report)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<annotations>
+  <version>1.1</version>
+  <meta>
+    <task>
+      <labels>
+        <label><name>wall</name><color>#ff0000</color><attributes></attributes></label>
+        <label><name>door</name><color>#00ff00</color></label>
+      </labels>
+    </task>
+  </meta>
+  <image id="0" name="plans/level1.png" width="100" height="100">
+    <box label="door" occluded="0" xtl="10.5" ytl="20" xbr="30" ybr="40.25" z_order="0"/>
+    <polygon label="wall" occluded="0" points="0,0;10,0;10,10" z_order="0">
+      <attribute name="material">brick</attribute>
+    </polygon>
+    <polyline label="wall" points="0,0;5,5"/>
+    <points label="door" points="1,2;3,4"/>
+    <ellipse label="door" cx="5" cy="5" rx="1" ry="1"/>
+    <mask label="wall" rle="1, 2, 2, 3, 4" left="10" top="20" width="4" height="3" z_order="0"/>
+  </image>
+  <image id="1" name="level2.png" width="100" height="100"/>
+</annotations>"##;
+
+    #[test]
+    fn test_parse_cvat() {
+        let doc = parse_cvat(SAMPLE).unwrap();
+
+        assert_eq!(doc.labels.len(), 2);
+        assert_eq!(doc.labels[0].name, "wall");
+        assert_eq!(doc.labels[0].color.as_deref(), Some("#ff0000"));
+        assert_eq!(doc.images.len(), 2);
+        assert_eq!(doc.images[0].name, "plans/level1.png");
+        assert_eq!(doc.images[0].size, Some((100.0, 100.0)));
+        assert_eq!(doc.images[0].shapes.len(), 6);
+        assert_eq!(doc.images[1].shapes.len(), 0);
+        assert_eq!(doc.skipped_shapes, 1);
+
+        match &doc.images[0].shapes[0].geometry {
+            Geometry::BBox { start, end } => {
+                assert_eq!((start.x, start.y, end.x, end.y), (10.5, 20.0, 30.0, 40.25));
+            }
+            other => panic!("expected bbox, got {:?}", other),
+        }
+        match &doc.images[0].shapes[1].geometry {
+            Geometry::Polygon { points } => assert_eq!(points.len(), 3),
+            other => panic!("expected polygon, got {:?}", other),
+        }
+        match &doc.images[0].shapes[2].geometry {
+            Geometry::Polyline { points } => assert_eq!(points.len(), 2),
+            other => panic!("expected polyline, got {:?}", other),
+        }
+        match &doc.images[0].shapes[4].geometry {
+            Geometry::Point { point } => assert_eq!((point.x, point.y), (3.0, 4.0)),
+            other => panic!("expected point, got {:?}", other),
+        }
+        match &doc.images[0].shapes[5].geometry {
+            Geometry::Mask {
+                origin,
+                width,
+                height,
+                counts,
+            } => {
+                assert_eq!((origin.x, origin.y, *width, *height), (10.0, 20.0, 4, 3));
+                assert_eq!(counts, &vec![1, 2, 2, 3, 4]);
+            }
+            other => panic!("expected mask, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_cvat_rejects_bad_input() {
+        assert!(parse_cvat("<annotations><image id=\"0\" name=\"a\">").is_err());
+        assert!(parse_cvat(
+            "<annotations><image id=\"0\" name=\"a\"><box label=\"x\" xtl=\"a\"/></image></annotations>"
+        )
+        .is_err());
+        // Runs must cover the mask exactly
+        assert!(parse_cvat(
+            "<annotations><image id=\"0\" name=\"a\"><mask label=\"x\" rle=\"1, 2\" left=\"0\" top=\"0\" width=\"2\" height=\"2\"/></image></annotations>"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_match_image() {
+        let image = |id: &str, url: &str| Image {
+            image_id: id.to_string(),
+            block_id: "b".to_string(),
+            url: url.to_string(),
+            locked: false,
+            order: None,
+            uploaded_at: String::new(),
+            calibration: None,
+            file_name: None,
+            captured_at: None,
+            annotation_count: 0,
+            status: "unannotated".to_string(),
+            width: None,
+            height: None,
+            size_bytes: None,
+            content_type: None,
+            levels: Vec::new(),
+            thumbnail_url: None,
+            tiles_url: None,
+            photo: None,
+            original_format: None,
+            processing: "ready".to_string(),
+            tags: Vec::new(),
+        };
+        let block_images = vec![
+            image("img-a", "https://cdn/projects/p/blocks/b/level2.png?v=1"),
+            image("img-b", "https://cdn/projects/p/blocks/b/img-b.png"),
+        ];
+        let cvat = |id: &str, name: &str| CvatImage {
+            id: Some(id.to_string()),
+            name: name.to_string(),
+            size: None,
+            shapes: vec![],
+        };
+        let mut map = HashMap::new();
+        map.insert("custom.png".to_string(), "img-b".to_string());
+
+        assert_eq!(
+            match_image(&cvat("9", "custom.png"), &block_images, &map).as_deref(),
+            Some("img-b")
+        );
+        assert_eq!(
+            match_image(&cvat("9", "level2.png"), &block_images, &map).as_deref(),
+            Some("img-a")
+        );
+        assert_eq!(
+            match_image(&cvat("9", "img-b.jpg"), &block_images, &map).as_deref(),
+            Some("img-b")
+        );
+        // A frame index that happens to fit the block is not a match
+        assert_eq!(
+            match_image(&cvat("1", "other.png"), &block_images, &map),
+            None
+        );
+        assert_eq!(
+            match_image(&cvat("5", "other.png"), &block_images, &map),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_cvat_requires_assignee() {
+        let (client, calls) =
+            crate::test_util::fake_dynamo(vec![crate::test_util::assigned_block("p", "b", "u2")]);
+        let response = import_cvat(&client, "table", "u1", "p", "b", SAMPLE.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(*calls.lock().unwrap(), ["GetItem", "GetItem"]);
+    }
+}
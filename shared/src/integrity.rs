@@ -0,0 +1,727 @@
+use crate::region;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Single-table key layout: (entity, PK, SK, notes)
+const KEY_SCHEMA: [(&str, &str, &str, &str); 30] = [
+    (
+        "project",
+        "PROJECT#{pid}",
+        "PROJECT#{pid}",
+        "project metadata; `storage_bytes` meters its S3 usage, reset with `storage_objects` and `storage_reconciled_at` by the cleanup job",
+    ),
+    (
+        "project member",
+        "PROJECT#{pid}",
+        "USER#{uid}",
+        "mirror of the user's project link",
+    ),
+    (
+        "user project link",
+        "USER#{uid}",
+        "PROJECT#{pid}",
+        "lists a user's projects",
+    ),
+    (
+        "block",
+        "PROJECT#{pid}",
+        "BLOCK#{bid}",
+        "state, lock, annotator (`assigned_to`) and `reviewer`; `annotation_count` tracks live annotations on its images",
+    ),
+    (
+        "class",
+        "PROJECT#{pid}",
+        "CLASS#{cid}",
+        "`count` tracks annotations using the class; `properties` may declare a keypoint `skeleton` and an `attributes` schema",
+    ),
+    (
+        "activity",
+        "PROJECT#{pid}",
+        "ACTIVITY#{yyyy-mm-ddThh}",
+        "hourly counters, TTL",
+    ),
+    (
+        "audit entry",
+        "PROJECT#{pid}",
+        "AUDIT#{at}#{id}",
+        "who changed which entity, with before/after summaries; `block_id` files it in a block's activity; kept after the project is deleted",
+    ),
+    (
+        "image",
+        "BLOCK#{bid}",
+        "IMAGE#{iid}",
+        "`url` points at the S3 upload; `annotation_count` tracks its live annotations; `status` is its labelling state; `content_type` comes with the upload; `width`, `height`, `size_bytes`, `levels`, `thumbnail_url`, `tiles_url`, the EXIF `photo` details and the `original_format` of converted HEIC and TIFF uploads are recorded by the upload worker as `processing` turns `ready`",
+    ),
+    (
+        "export job",
+        "PROJECT#{pid}",
+        "EXPORT#{eid}",
+        "`status` and `result` of an archive the image worker builds from exports/{pid}/{eid}.job.json, TTL",
+    ),
+    (
+        "block event",
+        "BLOCK#{bid}",
+        "EVENT#{ts}#{id}",
+        "block history feed",
+    ),
+    (
+        "block counter",
+        "BLOCK#{bid}",
+        "COUNT#status#{status} | COUNT#class#{cid}",
+        "`count` of the block's images in a labelling status or live annotations of a class",
+    ),
+    (
+        "image location",
+        "IMAGE#{iid}",
+        "METADATA",
+        "`project_id` and `block_id` of the image",
+    ),
+    (
+        "annotation",
+        "IMAGE#{iid}",
+        "ANNOTATION#{aid}",
+        "`project_id`/`block_id` copied from the image at create; `class_id` refers to a project class; `area`/`bounding_box` derived from `geometry`; `deleted_at` marks a restorable delete; optional `group_id` is shared by related annotations of the image; `z_index` orders drawing; `attributes` holds values for the class's attribute schema as JSON; GSI1PK=PROJECT#{pid}, GSI1SK=ANNOTATION#{created_at}#{aid} index it by project",
+    ),
+    (
+        "annotation version",
+        "IMAGE#{iid}",
+        "HISTORY#{aid}#V{n:06}",
+        "state of the annotation at `version` n",
+    ),
+    ("comment", "IMAGE#{iid}", "COMMENT#{cid}", ""),
+    ("user", "USER#{uid}", "USER#{uid}", "profile and role"),
+    (
+        "session",
+        "USER#{uid}",
+        "SESSION#{sid}",
+        "refresh-token session, TTL",
+    ),
+    (
+        "refresh lookup",
+        "REFRESH#{sha256}",
+        "METADATA",
+        "token hash to session, TTL",
+    ),
+    ("lock", "LOCK#{type}#{id}", "LOCK", "edit lock, TTL"),
+    (
+        "connection",
+        "CONNECTION#{cid}",
+        "CONNECTION#{cid} | LOCK#{type}#{id} | VIEWING | SUBSCRIBED",
+        "WebSocket connection and the locks it holds; `ttl` 2h after connecting",
+    ),
+    (
+        "connection index",
+        "CONNECTIONS#{shard}",
+        "USER#{uid}#CONNECTION#{cid}",
+        "every connection by user, in one of 16 partitions picked from the user id; same `ttl`",
+    ),
+    (
+        "project subscriber",
+        "SUBSCRIBERS#{pid}",
+        "CONNECTION#{cid}",
+        "connection following a project, looked up by broadcasts; SUBSCRIBED points back; same `ttl`",
+    ),
+    (
+        "image viewer",
+        "VIEWERS#{iid}",
+        "CONNECTION#{cid}",
+        "connection viewing an image, for cursor relays; VIEWING points back; same `ttl`",
+    ),
+    ("invite", "INVITE#{code}", "METADATA", ""),
+    (
+        "class template",
+        "CLASS_TEMPLATES",
+        "TEMPLATE#{tid}",
+        "reusable label set; `classes` is JSON, applied with /projects/{pid}/classes/apply-template/{tid}",
+    ),
+    ("org settings", "SETTINGS#ORG", "METADATA", ""),
+    (
+        "ops metrics",
+        "METRICS",
+        "MINUTE#{yyyy-mm-ddThh:mm}",
+        "per-minute counters for /admin/metrics/summary, TTL 24h",
+    ),
+    (
+        "migration",
+        "MIGRATION#{version:04}",
+        "METADATA",
+        "progress of a data migration, see /admin/migrations",
+    ),
+    (
+        "captured email",
+        "EMAIL_CAPTURE",
+        "EMAIL#{ts}#{id}",
+        "EMAIL_MODE=capture only",
+    ),
+    (
+        "legacy self rows",
+        "BLOCK#{bid} | IMAGE#{iid}",
+        "same as PK",
+        "no longer written; removed by cascade deletes",
+    ),
+];
+
+/// Document the single-table key layout
+pub fn get_key_schema() -> Result<Response<Body>, Error> {
+    let entities: Vec<_> = KEY_SCHEMA
+        .iter()
+        .map(|(entity, pk, sk, notes)| serde_json::json!({"entity": entity, "pk": pk, "sk": sk, "notes": notes}))
+        .collect();
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"entities": entities}).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// The attributes of an item the checker looks at
+#[derive(Debug, Clone, Default)]
+pub struct Row {
+    pub pk: String,
+    pub sk: String,
+    pub class_id: Option<String>,
+    pub count: Option<i64>,
+    pub url: Option<String>,
+    pub deleted: bool, // annotation tombstone
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Repair {
+    DeleteItem { pk: String, sk: String },
+    PutLink { pk: String, sk: String },
+    SetClassCount { pk: String, sk: String, count: i64 },
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Issue {
+    pub kind: String, // orphaned_item | missing_link | dangling_link | count_drift | unknown_class | dangling_s3_reference
+    pub project_id: Option<String>, // None when the owning project can't be determined
+    pub pk: String,
+    pub sk: String,
+    pub detail: String,
+    pub repair: Option<Repair>, // None = needs a human
+}
+
+#[derive(Debug, Serialize)]
+pub struct IntegrityReport {
+    pub project_id: Option<String>,
+    pub scanned_items: usize,
+    pub s3_checked: bool,
+    pub issues: Vec<Issue>,
+    pub repairs: usize,
+    pub applied: bool,
+}
+
+fn id<'a>(key: &'a str, prefix: &str) -> Option<&'a str> {
+    key.strip_prefix(prefix)
+}
+
+fn orphan(project_id: Option<&str>, row: &Row, detail: String) -> Issue {
+    Issue {
+        kind: "orphaned_item".to_string(),
+        project_id: project_id.map(|p| p.to_string()),
+        pk: row.pk.clone(),
+        sk: row.sk.clone(),
+        detail,
+        repair: Some(Repair::DeleteItem {
+            pk: row.pk.clone(),
+            sk: row.sk.clone(),
+        }),
+    }
+}
+
+/// Find structural problems in a set of table items (pure; see `run_integrity_check`)
+pub fn analyze(rows: &[Row]) -> Vec<Issue> {
+    let keys: HashSet<(&str, &str)> = rows
+        .iter()
+        .map(|r| (r.pk.as_str(), r.sk.as_str()))
+        .collect();
+    let projects: HashSet<&str> = rows
+        .iter()
+        .filter(|r| r.pk == r.sk)
+        .filter_map(|r| id(&r.pk, "PROJECT#"))
+        .collect();
+    // block -> project, image -> block
+    let block_project: HashMap<&str, &str> = rows
+        .iter()
+        .filter_map(|r| Some((id(&r.sk, "BLOCK#")?, id(&r.pk, "PROJECT#")?)))
+        .collect();
+    let image_block: HashMap<&str, &str> = rows
+        .iter()
+        .filter_map(|r| Some((id(&r.sk, "IMAGE#")?, id(&r.pk, "BLOCK#")?)))
+        .collect();
+    let image_project = |image_id: &str| {
+        image_block
+            .get(image_id)
+            .and_then(|b| block_project.get(b))
+            .copied()
+    };
+
+    let mut issues = Vec::new();
+    // (project, class) -> annotations counted
+    let mut class_usage: HashMap<(&str, &str), i64> = HashMap::new();
+
+    for row in rows {
+        if let Some(project_id) = id(&row.pk, "PROJECT#") {
+            // The audit trail is kept on purpose once the project is gone
+            if row.pk == row.sk || row.sk.starts_with(crate::audit::AUDIT_PREFIX) {
+                continue;
+            }
+            if !projects.contains(project_id) {
+                issues.push(orphan(
+                    Some(project_id),
+                    row,
+                    format!("project {} no longer exists", project_id),
+                ));
+            } else if let Some(user_id) = id(&row.sk, "USER#") {
+                let mirror = (format!("USER#{}", user_id), row.pk.clone());
+                if !keys.contains(&(mirror.0.as_str(), mirror.1.as_str())) {
+                    issues.push(Issue {
+                        kind: "missing_link".to_string(),
+                        project_id: Some(project_id.to_string()),
+                        pk: mirror.0.clone(),
+                        sk: mirror.1.clone(),
+                        detail: format!(
+                            "user {} is a member but the project is missing from their list",
+                            user_id
+                        ),
+                        repair: Some(Repair::PutLink {
+                            pk: mirror.0,
+                            sk: mirror.1,
+                        }),
+                    });
+                }
+            }
+        } else if let (Some(user_id), Some(project_id)) =
+            (id(&row.pk, "USER#"), id(&row.sk, "PROJECT#"))
+        {
+            if !projects.contains(project_id) {
+                issues.push(Issue {
+                    kind: "dangling_link".to_string(),
+                    ..orphan(
+                        Some(project_id),
+                        row,
+                        format!("user {} links to deleted project {}", user_id, project_id),
+                    )
+                });
+            } else {
+                let mirror = (row.sk.clone(), row.pk.clone());
+                if !keys.contains(&(mirror.0.as_str(), mirror.1.as_str())) {
+                    issues.push(Issue {
+                        kind: "missing_link".to_string(),
+                        project_id: Some(project_id.to_string()),
+                        pk: mirror.0.clone(),
+                        sk: mirror.1.clone(),
+                        detail: format!("project lists no member row for user {}", user_id),
+                        repair: Some(Repair::PutLink {
+                            pk: mirror.0,
+                            sk: mirror.1,
+                        }),
+                    });
+                }
+            }
+        } else if let Some(block_id) = id(&row.pk, "BLOCK#") {
+            if !block_project.contains_key(block_id) {
+                issues.push(orphan(
+                    None,
+                    row,
+                    format!("block {} no longer exists", block_id),
+                ));
+            }
+        } else if let Some(image_id) = id(&row.pk, "IMAGE#") {
+            let Some(project_id) = image_project(image_id) else {
+                issues.push(orphan(
+                    None,
+                    row,
+                    format!("image {} no longer exists", image_id),
+                ));
+                continue;
+            };
+            // Deleted annotations no longer count towards their class
+            if id(&row.sk, "ANNOTATION#").is_none() || row.deleted {
+                continue;
+            }
+            let Some(class_id) = row.class_id.as_deref() else {
+                continue;
+            };
+            let class_key = (
+                format!("PROJECT#{}", project_id),
+                format!("CLASS#{}", class_id),
+            );
+            if !keys.contains(&(class_key.0.as_str(), class_key.1.as_str())) {
+                issues.push(Issue {
+                    kind: "unknown_class".to_string(),
+                    project_id: Some(project_id.to_string()),
+                    pk: row.pk.clone(),
+                    sk: row.sk.clone(),
+                    detail: format!(
+                        "annotation uses class {} which is not in the project",
+                        class_id
+                    ),
+                    repair: None,
+                });
+            }
+            *class_usage.entry((project_id, class_id)).or_default() += 1;
+        }
+    }
+
+    for row in rows {
+        let (Some(project_id), Some(class_id)) = (id(&row.pk, "PROJECT#"), id(&row.sk, "CLASS#"))
+        else {
+            continue;
+        };
+        if !projects.contains(project_id) {
+            continue;
+        }
+        let actual = class_usage
+            .get(&(project_id, class_id))
+            .copied()
+            .unwrap_or(0);
+        let stored = row.count.unwrap_or(0);
+        if stored != actual {
+            issues.push(Issue {
+                kind: "count_drift".to_string(),
+                project_id: Some(project_id.to_string()),
+                pk: row.pk.clone(),
+                sk: row.sk.clone(),
+                detail: format!(
+                    "class count is {} but {} annotations use it",
+                    stored, actual
+                ),
+                repair: Some(Repair::SetClassCount {
+                    pk: row.pk.clone(),
+                    sk: row.sk.clone(),
+                    count: actual,
+                }),
+            });
+        }
+    }
+
+    issues
+}
+
+fn row_from_item(item: &HashMap<String, AttributeValue>) -> Option<Row> {
+    let text = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    Some(Row {
+        pk: text("PK")?,
+        sk: text("SK")?,
+        class_id: text("class_id"),
+        count: item
+            .get("count")
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse().ok()),
+        url: text("url"),
+        deleted: item.contains_key("deleted_at"),
+    })
+}
+
+async fn scan_rows(client: &DynamoClient, table_name: &str) -> Result<Vec<Row>, Error> {
+    let mut rows = Vec::new();
+    let mut last_key = None;
+    loop {
+        let result = client
+            .scan()
+            .table_name(table_name)
+            .projection_expression("PK, SK, class_id, #count, #url, deleted_at")
+            .expression_attribute_names("#count", "count")
+            .expression_attribute_names("#url", "url")
+            .set_exclusive_start_key(last_key)
+            .send()
+            .await?;
+        rows.extend(result.items().iter().filter_map(row_from_item));
+        last_key = result.last_evaluated_key().cloned();
+        if last_key.is_none() {
+            break;
+        }
+    }
+    Ok(rows)
+}
+
+/// Images in the project whose S3 upload is gone
+async fn dangling_s3_references(
+    s3_client: &S3Client,
+    rows: &[Row],
+    project_id: &str,
+) -> Result<Vec<Issue>, Error> {
+    let blocks: HashSet<&str> = rows
+        .iter()
+        .filter(|r| r.pk == format!("PROJECT#{}", project_id))
+        .filter_map(|r| id(&r.sk, "BLOCK#"))
+        .collect();
+
+    let mut issues = Vec::new();
+    for row in rows {
+        let (Some(block_id), Some(_)) = (id(&row.pk, "BLOCK#"), id(&row.sk, "IMAGE#")) else {
+            continue;
+        };
+        if !blocks.contains(block_id) {
+            continue;
+        }
+        let url = row.url.as_deref().unwrap_or_default();
+        let Some(key) = crate::storage::object_key(url) else {
+            continue; // not one of our uploads
+        };
+        match s3_client
+            .head_object()
+            .bucket(region::bucket_name())
+            .key(&key)
+            .send()
+            .await
+        {
+            Ok(_) => {}
+            Err(e)
+                if e.as_service_error()
+                    .map(|se| se.is_not_found())
+                    .unwrap_or(false) =>
+            {
+                issues.push(Issue {
+                    kind: "dangling_s3_reference".to_string(),
+                    project_id: Some(project_id.to_string()),
+                    pk: row.pk.clone(),
+                    sk: row.sk.clone(),
+                    detail: format!("s3://{}/{} does not exist", region::bucket_name(), key),
+                    repair: None,
+                });
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(issues)
+}
+
+fn key(pk: &str, sk: &str) -> HashMap<String, AttributeValue> {
+    HashMap::from([
+        ("PK".to_string(), AttributeValue::S(pk.to_string())),
+        ("SK".to_string(), AttributeValue::S(sk.to_string())),
+    ])
+}
+
+async fn apply_repairs(
+    client: &DynamoClient,
+    table_name: &str,
+    repairs: &[&Repair],
+) -> Result<(), Error> {
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut deletes = Vec::new();
+    for repair in repairs {
+        match repair {
+            Repair::DeleteItem { pk, sk } => deletes.push(key(pk, sk)),
+            Repair::PutLink { pk, sk } => {
+                client
+                    .put_item()
+                    .table_name(table_name)
+                    .set_item(Some(key(pk, sk)))
+                    .item("joined_at", AttributeValue::S(now.clone()))
+                    .send()
+                    .await?;
+            }
+            Repair::SetClassCount { pk, sk, count } => {
+                client
+                    .update_item()
+                    .table_name(table_name)
+                    .set_key(Some(key(pk, sk)))
+                    .update_expression("SET #count = :count")
+                    .expression_attribute_names("#count", "count")
+                    .expression_attribute_values(":count", AttributeValue::N(count.to_string()))
+                    .send()
+                    .await?;
+            }
+        }
+    }
+
+    // Batch delete with retries (25 items per request)
+    for chunk in deletes.chunks(25) {
+        let mut requests = Vec::new();
+        for k in chunk {
+            requests.push(
+                aws_sdk_dynamodb::types::WriteRequest::builder()
+                    .delete_request(
+                        aws_sdk_dynamodb::types::DeleteRequest::builder()
+                            .set_key(Some(k.clone()))
+                            .build()?,
+                    )
+                    .build(),
+            );
+        }
+        let mut unprocessed = Some(requests);
+        let mut attempts = 0;
+        while let Some(requests) = unprocessed.filter(|r| !r.is_empty()) {
+            attempts += 1;
+            if attempts > 5 {
+                tracing::warn!(
+                    "Integrity repair: {} deletes left unprocessed",
+                    requests.len()
+                );
+                break;
+            }
+            let result = client
+                .batch_write_item()
+                .request_items(table_name, requests)
+                .send()
+                .await?;
+            unprocessed = result
+                .unprocessed_items()
+                .and_then(|items| items.get(table_name))
+                .cloned();
+        }
+    }
+    Ok(())
+}
+
+/// Scan the table for structural problems and build a repair plan; with
+/// `apply` the plan is executed. Scoped to one project when `project_id`
+/// is given (orphans whose project can't be determined are then left out,
+/// and S3 references are checked). This reads the whole table, so run it
+/// off-peak.
+pub async fn run_integrity_check(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    project_id: Option<&str>,
+    apply: bool,
+) -> Result<Response<Body>, Error> {
+    let rows = scan_rows(client, table_name).await?;
+    let mut issues: Vec<Issue> = analyze(&rows)
+        .into_iter()
+        .filter(|issue| project_id.is_none() || issue.project_id.as_deref() == project_id)
+        .collect();
+    if let Some(project_id) = project_id {
+        issues.extend(dangling_s3_references(s3_client, &rows, project_id).await?);
+    }
+
+    let repairs: Vec<&Repair> = issues.iter().filter_map(|i| i.repair.as_ref()).collect();
+    if apply {
+        apply_repairs(client, table_name, &repairs).await?;
+    }
+    tracing::info!(
+        "Integrity check ({}): {} items, {} issues, {} repairs{}",
+        project_id.unwrap_or("all projects"),
+        rows.len(),
+        issues.len(),
+        repairs.len(),
+        if apply { " applied" } else { "" }
+    );
+
+    let report = IntegrityReport {
+        project_id: project_id.map(|p| p.to_string()),
+        scanned_items: rows.len(),
+        s3_checked: project_id.is_some(),
+        repairs: repairs.len(),
+        applied: apply,
+        issues,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&report)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(pk: &str, sk: &str) -> Row {
+        Row {
+            pk: pk.to_string(),
+            sk: sk.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn annotation(image: &str, id: &str, class_id: &str) -> Row {
+        Row {
+            class_id: Some(class_id.to_string()),
+            ..row(&format!("IMAGE#{}", image), &format!("ANNOTATION#{}", id))
+        }
+    }
+
+    fn kinds(issues: &[Issue]) -> Vec<(&str, &str, &str)> {
+        issues
+            .iter()
+            .map(|i| (i.kind.as_str(), i.pk.as_str(), i.sk.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_analyze_clean_project() {
+        let rows = vec![
+            row("PROJECT#p", "PROJECT#p"),
+            row("USER#u", "PROJECT#p"),
+            row("PROJECT#p", "USER#u"),
+            row("PROJECT#p", "BLOCK#b"),
+            Row {
+                count: Some(1),
+                ..row("PROJECT#p", "CLASS#c")
+            },
+            row("BLOCK#b", "IMAGE#i"),
+            row("BLOCK#b", "EVENT#2026#e"),
+            annotation("i", "a", "c"),
+            // Deleted, so not part of the class count
+            Row {
+                deleted: true,
+                ..annotation("i", "old", "c")
+            },
+            row("IMAGE#i", "COMMENT#x"),
+            row("USER#u", "USER#u"),
+            row("LOCK#image#i", "LOCK"),
+        ];
+        assert!(analyze(&rows).is_empty());
+    }
+
+    #[test]
+    fn test_analyze_finds_problems() {
+        let rows = vec![
+            row("PROJECT#p", "PROJECT#p"),
+            // Only one side of the membership link
+            row("PROJECT#p", "USER#u"),
+            row("PROJECT#p", "BLOCK#b"),
+            Row {
+                count: Some(5),
+                ..row("PROJECT#p", "CLASS#c")
+            },
+            row("BLOCK#b", "IMAGE#i"),
+            annotation("i", "a1", "c"),
+            annotation("i", "a2", "gone"),
+            // Image deleted without its annotations
+            annotation("deleted", "a3", "c"),
+            // Block deleted without its history
+            row("BLOCK#old", "EVENT#2026#e"),
+            // Partially deleted project
+            row("PROJECT#q", "CLASS#c"),
+            row("USER#u", "PROJECT#q"),
+            row("PROJECT#q", "AUDIT#2026#e"),
+        ];
+        let issues = analyze(&rows);
+        let found = kinds(&issues);
+        assert!(found.contains(&("missing_link", "USER#u", "PROJECT#p")));
+        assert!(found.contains(&("unknown_class", "IMAGE#i", "ANNOTATION#a2")));
+        assert!(found.contains(&("orphaned_item", "IMAGE#deleted", "ANNOTATION#a3")));
+        assert!(found.contains(&("orphaned_item", "BLOCK#old", "EVENT#2026#e")));
+        assert!(found.contains(&("orphaned_item", "PROJECT#q", "CLASS#c")));
+        assert!(!found.contains(&("orphaned_item", "PROJECT#q", "AUDIT#2026#e")));
+        assert!(found.contains(&("dangling_link", "USER#u", "PROJECT#q")));
+        assert_eq!(issues.len(), 7);
+
+        let drift = issues.iter().find(|i| i.kind == "count_drift").unwrap();
+        assert_eq!(
+            drift.repair,
+            Some(Repair::SetClassCount {
+                pk: "PROJECT#p".to_string(),
+                sk: "CLASS#c".to_string(),
+                count: 1,
+            })
+        );
+        let orphan = issues.iter().find(|i| i.pk == "IMAGE#deleted").unwrap();
+        assert_eq!(orphan.project_id, None);
+    }
+}
@@ -61,52 +61,34 @@ pub async fn create_invite(
         }
     };
 
-    let invite_code = Uuid::new_v4().to_string();
-    let now = Utc::now();
-    let expires_at = now + chrono::Duration::days(request.expires_days);
-
-    // Store invite in DynamoDB
-    let result = dynamo_client
-        .put_item()
-        .table_name(table_name)
-        .item("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("INVITE#{}", invite_code)))
-        .item("SK", aws_sdk_dynamodb::types::AttributeValue::S("METADATA".to_string()))
-        .item("invite_code", aws_sdk_dynamodb::types::AttributeValue::S(invite_code.clone()))
-        .item("email", aws_sdk_dynamodb::types::AttributeValue::S(request.email.clone()))
-        .item("status", aws_sdk_dynamodb::types::AttributeValue::S("pending".to_string()))
-        .item("created_by", aws_sdk_dynamodb::types::AttributeValue::S(admin_user_id.to_string()))
-        .item("created_at", aws_sdk_dynamodb::types::AttributeValue::S(now.to_rfc3339()))
-        .item("expires_at", aws_sdk_dynamodb::types::AttributeValue::S(expires_at.to_rfc3339()))
-        .send()
-        .await;
-
-    match result {
-        Ok(_) => {
-            // Send invite email
-            let frontend_url = env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
-            
-            if let Err(e) = crate::email::send_invite_email(
-                ses_client,
-                &request.email,
-                &invite_code,
-                &frontend_url,
-            )
-            .await
-            {
-                tracing::error!("Failed to send invite email: {}", e);
-                // Don't fail the invite creation if email fails
-                // The invite code is still valid and can be shared manually
-            } else {
-                tracing::info!("Invite email sent successfully to {}", request.email);
-            }
-            
-            let response = InviteResponse {
-                invite_code,
-                email: request.email,
-                expires_at: expires_at.to_rfc3339(),
-                status: "pending".to_string(),
-            };
+    // Enforce the org's recipient domain allowlist
+    let settings = crate::settings::load_org_settings(dynamo_client, table_name).await?;
+    if !crate::settings::email_domain_allowed(&request.email, &settings.allowed_invite_domains) {
+        tracing::warn!("Invite rejected for {}: domain not in allowlist", request.email);
+        let error = ErrorResponse {
+            error: "DomainNotAllowed".to_string(),
+            message: "Invites can only be sent to approved email domains".to_string(),
+        };
+        return Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::to_string(&error)?.into())
+            .map_err(Box::new)?);
+    }
 
+    match issue_invite(
+        dynamo_client,
+        ses_client,
+        table_name,
+        admin_user_id,
+        &request.email,
+        request.expires_days,
+        None,
+    )
+    .await
+    {
+        Ok(response) => {
             Ok(Response::builder()
                 .status(StatusCode::CREATED)
                 .header("Content-Type", "application/json")
@@ -130,6 +112,68 @@ pub async fn create_invite(
     }
 }
 
+/// Store a pending invite and email its code. With `project` (project id,
+/// project role) the invitee joins that project when they sign up.
+pub(crate) async fn issue_invite(
+    dynamo_client: &DynamoClient,
+    ses_client: &SesClient,
+    table_name: &str,
+    admin_user_id: &str,
+    email: &str,
+    expires_days: i64,
+    project: Option<(&str, &str)>,
+) -> Result<InviteResponse, Error> {
+    let invite_code = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let expires_at = now + chrono::Duration::days(expires_days);
+
+    // Store invite in DynamoDB
+    let mut put_request = dynamo_client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("INVITE#{}", invite_code)))
+        .item("SK", aws_sdk_dynamodb::types::AttributeValue::S("METADATA".to_string()))
+        .item("invite_code", aws_sdk_dynamodb::types::AttributeValue::S(invite_code.clone()))
+        .item("email", aws_sdk_dynamodb::types::AttributeValue::S(email.to_string()))
+        .item("status", aws_sdk_dynamodb::types::AttributeValue::S("pending".to_string()))
+        .item("created_by", aws_sdk_dynamodb::types::AttributeValue::S(admin_user_id.to_string()))
+        .item("created_at", aws_sdk_dynamodb::types::AttributeValue::S(now.to_rfc3339()))
+        .item("expires_at", aws_sdk_dynamodb::types::AttributeValue::S(expires_at.to_rfc3339()));
+    if let Some((project_id, project_role)) = project {
+        put_request = put_request
+            .item("project_id", aws_sdk_dynamodb::types::AttributeValue::S(project_id.to_string()))
+            .item("project_role", aws_sdk_dynamodb::types::AttributeValue::S(project_role.to_string()));
+    }
+    put_request.send().await?;
+
+    // Send invite email
+    let frontend_url = env::var("FRONTEND_URL").unwrap_or_else(|_| "http://localhost:8080".to_string());
+    
+    if let Err(e) = crate::email::send_invite_email(
+        ses_client,
+        dynamo_client,
+        table_name,
+        email,
+        &invite_code,
+        &frontend_url,
+    )
+    .await
+    {
+        tracing::error!("Failed to send invite email: {}", e);
+        // Don't fail the invite creation if email fails
+        // The invite code is still valid and can be shared manually
+    } else {
+        tracing::info!("Invite email sent successfully to {}", email);
+    }
+    
+    Ok(InviteResponse {
+        invite_code,
+        email: email.to_string(),
+        expires_at: expires_at.to_rfc3339(),
+        status: "pending".to_string(),
+    })
+}
+
 /// Validate an invite code
 pub async fn validate_invite(
     client: &DynamoClient,
@@ -168,6 +212,14 @@ pub async fn validate_invite(
         return Err("Email does not match invite".to_string());
     }
 
+    // Re-check the allowlist in case it was tightened after the invite was sent
+    let settings = crate::settings::load_org_settings(client, table_name)
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    if !crate::settings::email_domain_allowed(email, &settings.allowed_invite_domains) {
+        return Err("Email domain is not permitted for this organisation".to_string());
+    }
+
     // Check expiry
     let expires_at = item
         .get("expires_at")
@@ -184,25 +236,39 @@ pub async fn validate_invite(
     Ok(true)
 }
 
-/// Mark invite as used
+/// Mark invite as used by the account it created (`user_id` is its Cognito sub).
+/// The stream lambda turns the status change into an `invite_accepted`
+/// event for the inviter. Project invites add the new user to the project.
 pub async fn mark_invite_used(
     client: &DynamoClient,
     table_name: &str,
     invite_code: &str,
+    user_id: &str,
 ) -> Result<(), String> {
-    client
+    let result = client
         .update_item()
         .table_name(table_name)
         .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("INVITE#{}", invite_code)))
         .key("SK", aws_sdk_dynamodb::types::AttributeValue::S("METADATA".to_string()))
-        .update_expression("SET #status = :used, used_at = :now")
+        .update_expression("SET #status = :used, used_at = :now, user_id = :user_id")
         .expression_attribute_names("#status", "status")
         .expression_attribute_values(":used", aws_sdk_dynamodb::types::AttributeValue::S("used".to_string()))
         .expression_attribute_values(":now", aws_sdk_dynamodb::types::AttributeValue::S(Utc::now().to_rfc3339()))
+        .expression_attribute_values(":user_id", aws_sdk_dynamodb::types::AttributeValue::S(user_id.to_string()))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllNew)
         .send()
         .await
         .map_err(|e| format!("Failed to mark invite as used: {:?}", e))?;
 
+    let invite = result.attributes();
+    let field = |name: &str| invite.and_then(|i| i.get(name)).and_then(|v| v.as_s().ok());
+    if let Some(project_id) = field("project_id") {
+        let role = field("project_role").map(|r| r.as_str()).unwrap_or(crate::members::DEFAULT_ROLE);
+        crate::members::add_member(client, table_name, project_id, user_id, role)
+            .await
+            .map_err(|e| format!("Failed to add invited user to project {}: {}", project_id, e))?;
+    }
+
     Ok(())
 }
 
@@ -1,24 +1,61 @@
 pub mod types;
 pub mod auth;
 pub mod users;
+pub mod sessions;
 pub mod projects;
 pub mod blocks;
+pub mod block_stats;
+pub mod block_move;
+pub mod duplicate;
 pub mod images;
+pub mod ordering;
+pub mod import;
 pub mod annotations;
 pub mod classes;
+pub mod class_stats;
+pub mod locks;
+pub mod comments;
+pub mod feed;
 pub mod sockets;
 pub mod s3;
 pub mod s3_multipart;
 pub mod invites;
+pub mod settings;
 pub mod email;
 pub mod cloudfront;
 pub mod image_proxy;
 pub mod image_processing;
+pub mod geometry;
+pub mod takeoff;
+pub mod export;
+pub mod export_jobs;
+pub mod activity;
+pub mod schema;
+pub mod integrity;
+pub mod cleanup;
+pub mod region;
+pub mod reimport;
+pub mod maintenance;
+pub mod members;
+pub mod metrics;
+pub mod migrations;
+pub mod sample;
+pub mod storage;
+pub mod storage_usage;
+pub mod pagination;
+pub mod responses;
+pub mod history;
+pub mod groups;
+pub mod audit;
+pub mod templates;
+#[cfg(test)]
+mod test_util;
 
 use aws_sdk_apigatewaymanagement::Client as ApiGatewayManagementClient;
 use aws_sdk_cognitoidentityprovider::Client as CognitoClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
 use aws_sdk_s3::Client as S3Client;
+use aws_sdk_secretsmanager::Client as SecretsClient;
 use aws_sdk_sesv2::Client as SesClient;
 use std::sync::Arc;
 
@@ -28,7 +65,9 @@ pub struct AppState {
     pub dynamo_client: DynamoClient,
     pub s3_client: S3Client,
     pub ses_client: SesClient,
+    pub secrets_client: SecretsClient,
     pub api_gateway_client: Option<ApiGatewayManagementClient>,
+    pub s3_config: storage::S3Config,
 }
 
 impl AppState {
@@ -37,6 +76,7 @@ impl AppState {
         dynamo_client: DynamoClient,
         s3_client: S3Client,
         ses_client: SesClient,
+        secrets_client: SecretsClient,
         api_gateway_client: Option<ApiGatewayManagementClient>,
     ) -> Arc<Self> {
         Arc::new(Self {
@@ -44,7 +84,9 @@ impl AppState {
             dynamo_client,
             s3_client,
             ses_client,
+            secrets_client,
             api_gateway_client,
+            s3_config: storage::s3_config().clone(),
         })
     }
 }
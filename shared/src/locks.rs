@@ -0,0 +1,450 @@
+use crate::responses::json_response;
+use crate::types::Lock;
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use std::collections::HashMap;
+
+/// Locks lapse unless the holder re-acquires (heartbeats) within this window.
+/// DynamoDB TTL deletes lapsed items eventually; `expires_epoch` is what is enforced.
+pub const LOCK_TTL_SECONDS: i64 = 120;
+
+pub const RESOURCE_TYPES: [&str; 2] = ["image", "annotation"];
+
+fn lock_pk(resource_type: &str, resource_id: &str) -> String {
+    format!("LOCK#{}#{}", resource_type, resource_id)
+}
+
+/// Outcome of an acquire attempt
+pub enum LockOutcome {
+    Acquired(Lock),
+    /// Someone else holds an unexpired lock
+    Held(Lock),
+}
+
+pub fn lock_from_item(item: &HashMap<String, AttributeValue>) -> Option<Lock> {
+    let get = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    Some(Lock {
+        resource_type: get("resource_type")?,
+        resource_id: get("resource_id")?,
+        holder: get("holder")?,
+        connection_id: get("connection_id"),
+        acquired_at: get("acquired_at").unwrap_or_default(),
+        expires_at: get("expires_at").unwrap_or_default(),
+    })
+}
+
+/// Fetch the current lock on a resource, ignoring lapsed locks TTL hasn't removed yet
+pub async fn get_lock(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_id: &str,
+) -> Result<Option<Lock>, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(lock_pk(resource_type, resource_id)))
+        .key("SK", AttributeValue::S("LOCK".to_string()))
+        .consistent_read(true)
+        .send()
+        .await?;
+
+    let now = chrono::Utc::now().timestamp();
+    Ok(result
+        .item()
+        .filter(|item| unexpired(item, now))
+        .and_then(lock_from_item))
+}
+
+/// Whether a lock item is still in force at `now`
+fn unexpired(item: &HashMap<String, AttributeValue>, now: i64) -> bool {
+    item.get("expires_epoch")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<i64>().ok())
+        .map(|expires| expires > now)
+        .unwrap_or(false)
+}
+
+/// Acquire or refresh a lock. Succeeds if the resource is unlocked, the
+/// existing lock has lapsed, or the caller already holds it.
+pub async fn acquire_lock(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_id: &str,
+    user_id: &str,
+    connection_id: Option<&str>,
+) -> Result<LockOutcome, Error> {
+    let now = chrono::Utc::now();
+    let expires = now + chrono::Duration::seconds(LOCK_TTL_SECONDS);
+    let pk = lock_pk(resource_type, resource_id);
+
+    let mut builder = client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(pk.clone()))
+        .item("SK", AttributeValue::S("LOCK".to_string()))
+        .item(
+            "resource_type",
+            AttributeValue::S(resource_type.to_string()),
+        )
+        .item("resource_id", AttributeValue::S(resource_id.to_string()))
+        .item("holder", AttributeValue::S(user_id.to_string()))
+        .item("acquired_at", AttributeValue::S(now.to_rfc3339()))
+        .item("expires_at", AttributeValue::S(expires.to_rfc3339()))
+        .item(
+            "expires_epoch",
+            AttributeValue::N(expires.timestamp().to_string()),
+        )
+        .item("ttl", AttributeValue::N(expires.timestamp().to_string()))
+        .condition_expression(
+            "attribute_not_exists(PK) OR holder = :holder OR expires_epoch < :now",
+        )
+        .expression_attribute_values(":holder", AttributeValue::S(user_id.to_string()))
+        .expression_attribute_values(":now", AttributeValue::N(now.timestamp().to_string()));
+
+    if let Some(connection_id) = connection_id {
+        builder = builder.item(
+            "connection_id",
+            AttributeValue::S(connection_id.to_string()),
+        );
+    }
+
+    match builder.send().await {
+        Ok(_) => {}
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            let current = get_lock(client, table_name, resource_type, resource_id).await?;
+            return match current {
+                Some(lock) => Ok(LockOutcome::Held(lock)),
+                // Released between our write and read; let the caller retry
+                None => Err("Lock contention, retry".into()),
+            };
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    // Back-reference so $disconnect can release everything this socket held
+    if let Some(connection_id) = connection_id {
+        client
+            .put_item()
+            .table_name(table_name)
+            .item(
+                "PK",
+                AttributeValue::S(format!("CONNECTION#{}", connection_id)),
+            )
+            .item("SK", AttributeValue::S(pk))
+            .item("ttl", AttributeValue::N(expires.timestamp().to_string()))
+            .send()
+            .await?;
+    }
+
+    Ok(LockOutcome::Acquired(Lock {
+        resource_type: resource_type.to_string(),
+        resource_id: resource_id.to_string(),
+        holder: user_id.to_string(),
+        connection_id: connection_id.map(|s| s.to_string()),
+        acquired_at: now.to_rfc3339(),
+        expires_at: expires.to_rfc3339(),
+    }))
+}
+
+/// Release a lock held by the caller. Returns false if the caller didn't hold it.
+pub async fn release_lock(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_id: &str,
+    user_id: &str,
+) -> Result<bool, Error> {
+    let pk = lock_pk(resource_type, resource_id);
+
+    let result = client
+        .delete_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(pk.clone()))
+        .key("SK", AttributeValue::S("LOCK".to_string()))
+        .condition_expression("holder = :holder")
+        .expression_attribute_values(":holder", AttributeValue::S(user_id.to_string()))
+        .return_values(aws_sdk_dynamodb::types::ReturnValue::AllOld)
+        .send()
+        .await;
+
+    match result {
+        Ok(output) => {
+            let connection_id = output
+                .attributes()
+                .and_then(|attrs| attrs.get("connection_id"))
+                .and_then(|v| v.as_s().ok());
+            if let Some(connection_id) = connection_id {
+                client
+                    .delete_item()
+                    .table_name(table_name)
+                    .key(
+                        "PK",
+                        AttributeValue::S(format!("CONNECTION#{}", connection_id)),
+                    )
+                    .key("SK", AttributeValue::S(pk))
+                    .send()
+                    .await?;
+            }
+            Ok(true)
+        }
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes to a locked resource are only allowed for the lock holder.
+/// Returns the 409 to send when someone else holds an unexpired lock, or
+/// None when the caller may write.
+pub async fn check_unlocked(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_id: &str,
+    user_id: &str,
+) -> Result<Option<Response<Body>>, Error> {
+    match get_lock(client, table_name, resource_type, resource_id).await? {
+        Some(lock) if lock.holder != user_id => Ok(Some(locked_response(&lock)?)),
+        _ => Ok(None),
+    }
+}
+
+fn locked_response(lock: &Lock) -> Result<Response<Body>, Error> {
+    json_response(
+        StatusCode::CONFLICT,
+        serde_json::json!({"error": "Resource is locked by another user", "lock": lock}),
+    )
+}
+
+/// Most keys one BatchGetItem accepts
+const BATCH_GET_LIMIT: usize = 100;
+
+/// `check_unlocked` for many resources of one type, for writes that touch
+/// several at once. The first lock someone else holds decides the 409.
+pub async fn check_all_unlocked(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_ids: &[&str],
+    user_id: &str,
+) -> Result<Option<Response<Body>>, Error> {
+    let mut seen = std::collections::HashSet::new();
+    let resource_ids: Vec<&str> = resource_ids
+        .iter()
+        .copied()
+        .filter(|id| seen.insert(*id))
+        .collect();
+    let now = chrono::Utc::now().timestamp();
+    for chunk in resource_ids.chunks(BATCH_GET_LIMIT) {
+        let keys = chunk
+            .iter()
+            .map(|id| {
+                HashMap::from([
+                    (
+                        "PK".to_string(),
+                        AttributeValue::S(lock_pk(resource_type, id)),
+                    ),
+                    ("SK".to_string(), AttributeValue::S("LOCK".to_string())),
+                ])
+            })
+            .collect();
+        let mut request = KeysAndAttributes::builder()
+            .set_keys(Some(keys))
+            .consistent_read(true)
+            .build()?;
+        loop {
+            let result = client
+                .batch_get_item()
+                .request_items(table_name, request)
+                .send()
+                .await?;
+            let items = result.responses().and_then(|r| r.get(table_name));
+            for item in items.into_iter().flatten() {
+                if !unexpired(item, now) {
+                    continue;
+                }
+                if let Some(lock) = lock_from_item(item).filter(|lock| lock.holder != user_id) {
+                    return Ok(Some(locked_response(&lock)?));
+                }
+            }
+            match result.unprocessed_keys().and_then(|u| u.get(table_name)) {
+                Some(rest) if !rest.keys().is_empty() => request = rest.clone(),
+                _ => break,
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Release every lock a WebSocket connection still holds (called on $disconnect).
+/// The stream handler broadcasts `lock_released` for each deleted lock item.
+pub async fn release_connection_locks(
+    client: &DynamoClient,
+    table_name: &str,
+    connection_id: &str,
+) -> Result<usize, Error> {
+    let connection_pk = format!("CONNECTION#{}", connection_id);
+
+    let result = client
+        .query()
+        .table_name(table_name)
+        .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
+        .expression_attribute_values(":pk", AttributeValue::S(connection_pk.clone()))
+        .expression_attribute_values(":sk_prefix", AttributeValue::S("LOCK#".to_string()))
+        .send()
+        .await?;
+
+    let mut released = 0;
+    for item in result.items() {
+        let Some(lock_pk) = item.get("SK").and_then(|v| v.as_s().ok()) else {
+            continue;
+        };
+
+        // Only delete if this connection still owns it (the user may have re-locked elsewhere)
+        let deleted = client
+            .delete_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(lock_pk.clone()))
+            .key("SK", AttributeValue::S("LOCK".to_string()))
+            .condition_expression("connection_id = :connection_id")
+            .expression_attribute_values(
+                ":connection_id",
+                AttributeValue::S(connection_id.to_string()),
+            )
+            .send()
+            .await;
+        match deleted {
+            Ok(_) => released += 1,
+            Err(e)
+                if e.as_service_error()
+                    .map(|se| se.is_conditional_check_failed_exception())
+                    .unwrap_or(false) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        client
+            .delete_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(connection_pk.clone()))
+            .key("SK", AttributeValue::S(lock_pk.clone()))
+            .send()
+            .await?;
+    }
+
+    if released > 0 {
+        tracing::info!(
+            "Released {} locks held by connection {}",
+            released,
+            connection_id
+        );
+    }
+    Ok(released)
+}
+
+/// POST .../lock - acquire or heartbeat a lock (409 with the current holder if taken).
+/// `image_id` is the image the resource is on (the resource itself for image
+/// locks); only members of its project and admins may lock it.
+pub async fn acquire_lock_response(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_id: &str,
+    image_id: &str,
+    user_id: &str,
+    connection_id: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let Some(location) = crate::images::image_location(client, table_name, image_id).await? else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Image not found"}),
+        );
+    };
+    if !crate::members::is_member(client, table_name, &location.project_id, user_id).await?
+        && !crate::users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(
+            StatusCode::FORBIDDEN,
+            serde_json::json!({"error": "Not a member of this project"}),
+        );
+    }
+    match acquire_lock(
+        client,
+        table_name,
+        resource_type,
+        resource_id,
+        user_id,
+        connection_id,
+    )
+    .await?
+    {
+        LockOutcome::Acquired(lock) => json_response(StatusCode::OK, serde_json::to_value(&lock)?),
+        LockOutcome::Held(lock) => locked_response(&lock),
+    }
+}
+
+/// GET .../lock - current lock, or 404 when unlocked
+pub async fn get_lock_response(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_id: &str,
+) -> Result<Response<Body>, Error> {
+    match get_lock(client, table_name, resource_type, resource_id).await? {
+        Some(lock) => json_response(StatusCode::OK, serde_json::to_value(&lock)?),
+        None => json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Not locked"}),
+        ),
+    }
+}
+
+/// DELETE .../lock - release a lock the caller holds
+pub async fn release_lock_response(
+    client: &DynamoClient,
+    table_name: &str,
+    resource_type: &str,
+    resource_id: &str,
+    user_id: &str,
+) -> Result<Response<Body>, Error> {
+    if release_lock(client, table_name, resource_type, resource_id, user_id).await? {
+        Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header("Access-Control-Allow-Origin", "*")
+            .body(Body::Empty)
+            .map_err(Box::new)?)
+    } else {
+        json_response(
+            StatusCode::CONFLICT,
+            serde_json::json!({"error": "Lock is not held by you"}),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire_lock_requires_membership() {
+        let (client, calls) =
+            crate::test_util::fake_dynamo(vec![crate::test_util::image_in_block("p", "b", "img")]);
+        let response = acquire_lock_response(&client, "table", "image", "img", "img", "u1", None)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // Location, membership and role were read; no lock was written
+        assert_eq!(*calls.lock().unwrap(), ["GetItem", "GetItem", "GetItem"]);
+    }
+}
@@ -0,0 +1,67 @@
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use std::env;
+
+/// Suggested wait when MAINTENANCE_RETRY_AFTER is not set
+const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+
+/// Parse MAINTENANCE_MODE / MAINTENANCE_RETRY_AFTER values into the
+/// Retry-After to send, or `None` when maintenance mode is off
+pub fn parse(mode: Option<&str>, retry_after: Option<&str>) -> Option<u64> {
+    let mode = mode?.trim().to_ascii_lowercase();
+    if !matches!(mode.as_str(), "1" | "true" | "on" | "yes") {
+        return None;
+    }
+    Some(
+        retry_after
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_RETRY_AFTER_SECS),
+    )
+}
+
+/// Read-only maintenance mode, switched on through the MAINTENANCE_MODE
+/// environment variable (e.g. while a migration or GSI backfill runs).
+/// Returns the Retry-After in seconds while it is on.
+pub fn retry_after() -> Option<u64> {
+    parse(
+        env::var("MAINTENANCE_MODE").ok().as_deref(),
+        env::var("MAINTENANCE_RETRY_AFTER").ok().as_deref(),
+    )
+}
+
+/// 503 for a write attempted during maintenance
+pub fn unavailable(retry_after: u64) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Retry-After", retry_after.to_string())
+        .header("Access-Control-Expose-Headers", "Retry-After")
+        .body(
+            serde_json::json!({
+                "error": "Read-only maintenance in progress, please retry later",
+                "code": "maintenance",
+                "retry_after_secs": retry_after,
+            })
+            .to_string()
+            .into(),
+        )
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(None, None), None);
+        assert_eq!(parse(Some("false"), Some("60")), None);
+        assert_eq!(parse(Some(""), None), None);
+        assert_eq!(parse(Some("true"), None), Some(DEFAULT_RETRY_AFTER_SECS));
+        assert_eq!(parse(Some(" ON "), Some("60")), Some(60));
+        assert_eq!(
+            parse(Some("1"), Some("soon")),
+            Some(DEFAULT_RETRY_AFTER_SECS)
+        );
+    }
+}
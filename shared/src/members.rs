@@ -0,0 +1,328 @@
+use crate::responses::json_response;
+use crate::types::{BatchAddMembersRequest, MemberEntry};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_sesv2::Client as SesClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Roles a member can hold within a project
+pub const PROJECT_ROLES: [&str; 3] = ["manager", "reviewer", "annotator"];
+pub const DEFAULT_ROLE: &str = "annotator";
+/// The role a project's creator joins with; managers run the project
+pub const OWNER_ROLE: &str = "manager";
+
+/// Entries accepted per batch request
+const MAX_BATCH: usize = 100;
+
+/// Invites sent by a batch import expire after this unless the request says otherwise
+const DEFAULT_INVITE_DAYS: i64 = 7;
+
+/// What happened to one entry of a batch import
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MemberOutcome {
+    pub email: String,
+    pub status: String, // added | already_member | invited | invalid | duplicate | domain_not_allowed | failed
+    pub role: Option<String>,
+    pub user_id: Option<String>,
+    pub invite_code: Option<String>,
+    pub error: Option<String>,
+}
+
+impl MemberOutcome {
+    fn new(email: &str, status: &str) -> Self {
+        MemberOutcome {
+            email: email.to_string(),
+            status: status.to_string(),
+            role: None,
+            user_id: None,
+            invite_code: None,
+            error: None,
+        }
+    }
+}
+
+/// A batch entry after validation
+#[derive(Debug, PartialEq)]
+pub enum Planned {
+    Accepted { email: String, role: String },
+    Rejected(MemberOutcome),
+}
+
+/// Validate entries in order, normalising emails to lower case
+pub fn plan_entries(entries: &[MemberEntry]) -> Vec<Planned> {
+    let mut seen = HashSet::new();
+    entries
+        .iter()
+        .map(|entry| {
+            let email = entry.email.trim().to_lowercase();
+            let valid_email = email
+                .split_once('@')
+                .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.'));
+            if !valid_email {
+                let mut outcome = MemberOutcome::new(&entry.email, "invalid");
+                outcome.error = Some("Invalid email address".to_string());
+                return Planned::Rejected(outcome);
+            }
+            let role = entry.role.as_deref().unwrap_or(DEFAULT_ROLE);
+            if !PROJECT_ROLES.contains(&role) {
+                let mut outcome = MemberOutcome::new(&email, "invalid");
+                outcome.error = Some(format!("role must be one of: {}", PROJECT_ROLES.join(", ")));
+                return Planned::Rejected(outcome);
+            }
+            if !seen.insert(email.clone()) {
+                return Planned::Rejected(MemberOutcome::new(&email, "duplicate"));
+            }
+            Planned::Accepted {
+                email,
+                role: role.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Whether the user is a member of the project (PROJECT#{pid}/USER#{uid} exists)
+pub async fn is_member(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    user_id: &str,
+) -> Result<bool, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("PROJECT#{}", project_id)))
+        .key("SK", AttributeValue::S(format!("USER#{}", user_id)))
+        .projection_expression("PK")
+        .send()
+        .await?;
+    Ok(result.item().is_some())
+}
+
+/// Whether the user manages the project (its creator, or a member added as
+/// "manager")
+pub async fn is_manager(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    user_id: &str,
+) -> Result<bool, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("PROJECT#{}", project_id)))
+        .key("SK", AttributeValue::S(format!("USER#{}", user_id)))
+        .projection_expression("#role")
+        .expression_attribute_names("#role", "role")
+        .send()
+        .await?;
+    Ok(result
+        .item()
+        .and_then(|item| item.get("role"))
+        .and_then(|v| v.as_s().ok())
+        .is_some_and(|role| role == OWNER_ROLE))
+}
+
+/// Write both membership rows for a user. Returns false when the user
+/// already belongs to the project (their role is left unchanged).
+pub async fn add_member(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    user_id: &str,
+    role: &str,
+) -> Result<bool, Error> {
+    let project_pk = format!("PROJECT#{}", project_id);
+    let user_pk = format!("USER#{}", user_id);
+    let project_name = crate::projects::fetch_project(client, table_name, project_id)
+        .await?
+        .map(|p| p.name)
+        .unwrap_or_default();
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let link = |pk: &str, sk: &str| {
+        client
+            .put_item()
+            .table_name(table_name)
+            .item("PK", AttributeValue::S(pk.to_string()))
+            .item("SK", AttributeValue::S(sk.to_string()))
+            .item("joined_at", AttributeValue::S(now.clone()))
+            .item("role", AttributeValue::S(role.to_string()))
+            .item("project_name", AttributeValue::S(project_name.clone()))
+    };
+
+    let result = link(&project_pk, &user_pk)
+        .condition_expression("attribute_not_exists(PK)")
+        .send()
+        .await;
+    match result {
+        Ok(_) => {}
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            return Ok(false);
+        }
+        Err(e) => return Err(e.into()),
+    }
+    link(&user_pk, &project_pk).send().await?;
+    Ok(true)
+}
+
+/// Add many people to a project at once (POST /projects/{id}/members/batch).
+/// Emails of existing users become members straight away; unknown addresses
+/// get a project invite and join when they sign up. Each entry gets its own
+/// outcome, so one bad address doesn't fail the batch.
+pub async fn batch_add_members(
+    client: &DynamoClient,
+    ses_client: &SesClient,
+    table_name: &str,
+    project_id: &str,
+    user_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: BatchAddMembersRequest = match serde_json::from_slice(body) {
+        Ok(req) => req,
+        Err(e) => {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                serde_json::json!({"error": format!("Invalid request body: {}", e)}),
+            );
+        }
+    };
+    if req.members.is_empty() || req.members.len() > MAX_BATCH {
+        return json_response(
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({"error": format!("members must list 1 to {} entries", MAX_BATCH)}),
+        );
+    }
+    if crate::projects::fetch_project(client, table_name, project_id)
+        .await?
+        .is_none()
+    {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Project not found"}),
+        );
+    }
+    if !is_member(client, table_name, project_id, user_id).await?
+        && !crate::users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(
+            StatusCode::FORBIDDEN,
+            serde_json::json!({"error": "Only project members can add members"}),
+        );
+    }
+
+    let planned = plan_entries(&req.members);
+    let emails: Vec<String> = planned
+        .iter()
+        .filter_map(|p| match p {
+            Planned::Accepted { email, .. } => Some(email.clone()),
+            Planned::Rejected(_) => None,
+        })
+        .collect();
+    let existing = crate::users::find_users_by_email(client, table_name, &emails).await?;
+    let settings = crate::settings::load_org_settings(client, table_name).await?;
+    let expires_days = req.invite_expires_days.unwrap_or(DEFAULT_INVITE_DAYS);
+
+    let mut results = Vec::new();
+    for entry in planned {
+        let (email, role) = match entry {
+            Planned::Accepted { email, role } => (email, role),
+            Planned::Rejected(outcome) => {
+                results.push(outcome);
+                continue;
+            }
+        };
+        let mut outcome = MemberOutcome::new(&email, "failed");
+        outcome.role = Some(role.clone());
+
+        if let Some(member_id) = existing.get(&email) {
+            outcome.user_id = Some(member_id.clone());
+            match add_member(client, table_name, project_id, member_id, &role).await {
+                Ok(true) => outcome.status = "added".to_string(),
+                Ok(false) => outcome.status = "already_member".to_string(),
+                Err(e) => outcome.error = Some(e.to_string()),
+            }
+        } else if !crate::settings::email_domain_allowed(&email, &settings.allowed_invite_domains) {
+            outcome.status = "domain_not_allowed".to_string();
+        } else {
+            match crate::invites::issue_invite(
+                client,
+                ses_client,
+                table_name,
+                user_id,
+                &email,
+                expires_days,
+                Some((project_id, &role)),
+            )
+            .await
+            {
+                Ok(invite) => {
+                    outcome.status = "invited".to_string();
+                    outcome.invite_code = Some(invite.invite_code);
+                }
+                Err(e) => outcome.error = Some(e.to_string()),
+            }
+        }
+        results.push(outcome);
+    }
+
+    let count = |status: &str| results.iter().filter(|r| r.status == status).count();
+    tracing::info!(
+        "Batch members for project {}: {} added, {} invited, {} entries",
+        project_id,
+        count("added"),
+        count("invited"),
+        results.len()
+    );
+    json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "project_id": project_id,
+            "added": count("added"),
+            "invited": count("invited"),
+            "results": results,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(email: &str, role: Option<&str>) -> MemberEntry {
+        MemberEntry {
+            email: email.to_string(),
+            role: role.map(|r| r.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_plan_entries() {
+        let planned = plan_entries(&[
+            entry(" Ann@Example.com ", None),
+            entry("bob@example.com", Some("reviewer")),
+            entry("ann@example.com", Some("manager")),
+            entry("not-an-email", None),
+            entry("cat@example.com", Some("owner")),
+        ]);
+        let accepted = |email: &str, role: &str| Planned::Accepted {
+            email: email.to_string(),
+            role: role.to_string(),
+        };
+        assert_eq!(planned[0], accepted("ann@example.com", "annotator"));
+        assert_eq!(planned[1], accepted("bob@example.com", "reviewer"));
+        let rejected: Vec<&str> = planned[2..]
+            .iter()
+            .map(|p| match p {
+                Planned::Rejected(outcome) => outcome.status.as_str(),
+                Planned::Accepted { .. } => "accepted",
+            })
+            .collect();
+        assert_eq!(rejected, vec!["duplicate", "invalid", "invalid"]);
+    }
+}
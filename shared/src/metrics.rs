@@ -0,0 +1,240 @@
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes, Select};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Partition holding the ops counters
+const METRICS_PK: &str = "METRICS";
+
+/// Counter rows only need to cover the summary window; TTL removes them after this
+const RETENTION_HOURS: i64 = 24;
+
+/// The summary sums this many minute buckets
+const WINDOW_MINUTES: i64 = 60;
+
+/// Messages fanned out to WebSocket clients
+pub const EVENTS_BROADCAST: &str = "events_broadcast";
+/// Deliveries to a WebSocket connection that failed (usually a stale connection)
+pub const FAILED_DELIVERIES: &str = "failed_deliveries";
+/// Annotation writes that brought an image close to its annotation cap
+pub const ANNOTATION_CAP_WARNINGS: &str = "annotation_cap_warnings";
+/// Annotation writes refused because the image was at its cap
+pub const ANNOTATION_CAP_REJECTIONS: &str = "annotation_cap_rejections";
+
+/// Minute bucket key, e.g. "MINUTE#2026-10-14T08:15"
+fn minute_key(at: DateTime<Utc>) -> String {
+    format!("MINUTE#{}", at.format("%Y-%m-%dT%H:%M"))
+}
+
+/// Keys of the minute buckets making up the hour that ends at `now`
+fn window_keys(now: DateTime<Utc>) -> Vec<String> {
+    let last = now.duration_trunc(Duration::minutes(1)).unwrap_or(now);
+    (0..WINDOW_MINUTES)
+        .map(|i| minute_key(last - Duration::minutes(i)))
+        .collect()
+}
+
+/// Add to ops counters in the current minute bucket
+/// (PK=METRICS, SK=MINUTE#{yyyy-mm-ddThh:mm}). Zero counts are skipped.
+/// Best-effort like `activity::record_activity`: failures are only logged.
+pub async fn record(client: &DynamoClient, table_name: &str, counts: &[(&str, usize)]) {
+    let counts: Vec<_> = counts.iter().filter(|(_, n)| *n > 0).collect();
+    if counts.is_empty() {
+        return;
+    }
+    let now = Utc::now();
+    let expires = (now + Duration::hours(RETENTION_HOURS)).timestamp();
+    let mut builder = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(METRICS_PK.to_string()))
+        .key("SK", AttributeValue::S(minute_key(now)))
+        .expression_attribute_names("#ttl", "ttl")
+        .expression_attribute_values(":ttl", AttributeValue::N(expires.to_string()));
+    let mut adds = Vec::new();
+    for (i, (name, n)) in counts.iter().enumerate() {
+        adds.push(format!("#c{0} :c{0}", i));
+        builder = builder
+            .expression_attribute_names(format!("#c{}", i), *name)
+            .expression_attribute_values(format!(":c{}", i), AttributeValue::N(n.to_string()));
+    }
+    let result = builder
+        .update_expression(format!(
+            "ADD {} SET #ttl = if_not_exists(#ttl, :ttl)",
+            adds.join(", ")
+        ))
+        .send()
+        .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to record metrics: {}", e);
+    }
+}
+
+/// Total of one counter across bucket items
+fn sum_counter(items: &[HashMap<String, AttributeValue>], name: &str) -> u64 {
+    items
+        .iter()
+        .filter_map(|item| item.get(name)?.as_n().ok()?.parse::<u64>().ok())
+        .sum()
+}
+
+/// Counter buckets for the last hour
+async fn fetch_window(
+    client: &DynamoClient,
+    table_name: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+    let keys: Vec<_> = window_keys(now)
+        .into_iter()
+        .map(|sk| {
+            HashMap::from([
+                ("PK".to_string(), AttributeValue::S(METRICS_PK.to_string())),
+                ("SK".to_string(), AttributeValue::S(sk)),
+            ])
+        })
+        .collect();
+
+    let mut items = Vec::new();
+    let mut pending = Some(KeysAndAttributes::builder().set_keys(Some(keys)).build()?);
+    while let Some(request) = pending.take() {
+        let result = client
+            .batch_get_item()
+            .request_items(table_name, request)
+            .send()
+            .await?;
+        if let Some(found) = result.responses().and_then(|r| r.get(table_name)) {
+            items.extend(found.iter().cloned());
+        }
+        pending = result
+            .unprocessed_keys()
+            .and_then(|u| u.get(table_name))
+            .filter(|k| !k.keys().is_empty())
+            .cloned();
+    }
+    Ok(items)
+}
+
+/// Connection rows currently stored (stale ones linger until a broadcast
+/// finds them gone or DynamoDB TTL removes them), counted in every partition
+/// of their index
+async fn count_connections(client: &DynamoClient, table_name: &str) -> Result<u64, Error> {
+    let mut count = 0;
+    for shard in 0..crate::sockets::connections::INDEX_SHARDS {
+        let mut last_key = None;
+        loop {
+            let result = client
+                .query()
+                .table_name(table_name)
+                .key_condition_expression("PK = :pk")
+                .expression_attribute_values(
+                    ":pk",
+                    AttributeValue::S(crate::sockets::connections::shard_pk(shard)),
+                )
+                .select(Select::Count)
+                .set_exclusive_start_key(last_key)
+                .send()
+                .await?;
+            count += result.count() as u64;
+            last_key = result.last_evaluated_key().cloned();
+            if last_key.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Multipart uploads started but neither completed nor aborted
+async fn count_uploads_in_flight(s3_client: &S3Client) -> Result<u64, Error> {
+    let mut count = 0;
+    let mut key_marker = None;
+    let mut upload_id_marker = None;
+    loop {
+        let result = s3_client
+            .list_multipart_uploads()
+            .bucket(crate::region::bucket_name())
+            .prefix(crate::storage::s3_config().projects_root())
+            .set_key_marker(key_marker)
+            .set_upload_id_marker(upload_id_marker)
+            .send()
+            .await?;
+        count += result.uploads().len() as u64;
+        if !result.is_truncated().unwrap_or(false) {
+            return Ok(count);
+        }
+        key_marker = result.next_key_marker().map(|s| s.to_string());
+        upload_id_marker = result.next_upload_id_marker().map(|s| s.to_string());
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricsSummary {
+    pub generated_at: String,
+    pub region: String,
+    pub active_connections: u64,
+    pub events_broadcast_last_hour: u64,
+    pub failed_deliveries_last_hour: u64,
+    pub annotation_cap_warnings_last_hour: u64,
+    pub annotation_cap_rejections_last_hour: u64,
+    pub uploads_in_flight: u64,
+}
+
+/// Key operational numbers for the ops dashboard (GET /admin/metrics/summary)
+pub async fn get_metrics_summary(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+) -> Result<Response<Body>, Error> {
+    let now = Utc::now();
+    let window = fetch_window(client, table_name, now).await?;
+
+    let summary = MetricsSummary {
+        generated_at: now.to_rfc3339(),
+        region: crate::region::current(),
+        active_connections: count_connections(client, table_name).await?,
+        events_broadcast_last_hour: sum_counter(&window, EVENTS_BROADCAST),
+        failed_deliveries_last_hour: sum_counter(&window, FAILED_DELIVERIES),
+        annotation_cap_warnings_last_hour: sum_counter(&window, ANNOTATION_CAP_WARNINGS),
+        annotation_cap_rejections_last_hour: sum_counter(&window, ANNOTATION_CAP_REJECTIONS),
+        uploads_in_flight: count_uploads_in_flight(s3_client).await?,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Cache-Control", "no-store")
+        .body(serde_json::to_string(&summary)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_window_keys() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 14, 8, 15, 42).unwrap();
+        let keys = window_keys(now);
+        assert_eq!(keys.len(), 60);
+        assert_eq!(keys[0], "MINUTE#2026-10-14T08:15");
+        assert_eq!(keys[59], "MINUTE#2026-10-14T07:16");
+    }
+
+    #[test]
+    fn test_sum_counter() {
+        let bucket = |n: &str| {
+            HashMap::from([(
+                EVENTS_BROADCAST.to_string(),
+                AttributeValue::N(n.to_string()),
+            )])
+        };
+        let items = vec![bucket("3"), bucket("4"), HashMap::new()];
+        assert_eq!(sum_counter(&items, EVENTS_BROADCAST), 7);
+        assert_eq!(sum_counter(&items, FAILED_DELIVERIES), 0);
+    }
+}
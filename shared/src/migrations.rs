@@ -0,0 +1,604 @@
+use crate::responses::json_response;
+use crate::sockets::connections;
+use crate::{annotations, block_stats, projects};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use chrono::Utc;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+type Item = HashMap<String, AttributeValue>;
+
+/// A versioned backfill over every item in the table
+pub struct Migration {
+    pub version: u32,
+    pub id: &'static str,
+    pub description: &'static str,
+}
+
+/// Applied in order: a migration can only run once every earlier one has completed
+pub const MIGRATIONS: [Migration; 7] = [
+    Migration {
+        version: 1,
+        id: "annotation_derived_fields",
+        description:
+            "Backfill area and bounding_box on annotations written before they were derived",
+    },
+    Migration {
+        version: 2,
+        id: "project_name_on_links",
+        description: "Copy each project's name onto its user/project membership rows",
+    },
+    Migration {
+        version: 3,
+        id: "annotation_project_index",
+        description:
+            "Add project index keys (GSI1PK/GSI1SK) to annotations written before the index",
+    },
+    Migration {
+        version: 4,
+        id: "image_locations",
+        description:
+            "Write image location rows and copy project_id/block_id onto older annotations",
+    },
+    Migration {
+        version: 5,
+        id: "annotation_counts",
+        description: "Count live annotations onto image and block items",
+    },
+    Migration {
+        version: 6,
+        id: "block_counters",
+        description: "Count each block's images by status and annotations by class",
+    },
+    Migration {
+        version: 7,
+        id: "connection_index",
+        description: "Index WebSocket connections opened before the CONNECTIONS partition",
+    },
+];
+
+/// Items per Scan page
+const PAGE_SIZE: i32 = 100;
+/// Pages per run unless ?max_pages= says otherwise
+const DEFAULT_MAX_PAGES: usize = 20;
+const MAX_PAGES: usize = 200;
+/// Stop starting new pages after this long so a run fits inside one invocation
+const TIME_BUDGET: Duration = Duration::from_secs(20);
+/// How long a run holds the migration before another caller may take over
+const LEASE_SECS: i64 = 60;
+
+/// Progress of a migration, stored at MIGRATION#{version:04}/METADATA
+#[derive(Debug, Serialize, Clone)]
+pub struct MigrationState {
+    pub version: u32,
+    pub id: String,
+    pub description: String,
+    pub status: String, // pending | running | completed | failed
+    pub scanned: u64,
+    pub updated: u64,
+    pub started_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub completed_at: Option<String>,
+    pub last_error: Option<String>,
+    #[serde(skip)]
+    pub cursor: Option<Item>, // LastEvaluatedKey to resume from
+}
+
+fn state_key(version: u32) -> Item {
+    HashMap::from([
+        (
+            "PK".to_string(),
+            AttributeValue::S(format!("MIGRATION#{:04}", version)),
+        ),
+        ("SK".to_string(), AttributeValue::S("METADATA".to_string())),
+    ])
+}
+
+fn cursor_to_json(cursor: &Item) -> String {
+    let keys: HashMap<&str, &str> = cursor
+        .iter()
+        .filter_map(|(k, v)| Some((k.as_str(), v.as_s().ok()?.as_str())))
+        .collect();
+    serde_json::to_string(&keys).unwrap_or_default()
+}
+
+fn cursor_from_json(json: &str) -> Option<Item> {
+    let keys: HashMap<String, String> = serde_json::from_str(json).ok()?;
+    Some(
+        keys.into_iter()
+            .map(|(k, v)| (k, AttributeValue::S(v)))
+            .collect(),
+    )
+}
+
+async fn load_state(
+    client: &DynamoClient,
+    table_name: &str,
+    migration: &Migration,
+) -> Result<MigrationState, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .set_key(Some(state_key(migration.version)))
+        .send()
+        .await?;
+    let item = result.item();
+    let text = |name: &str| {
+        item.and_then(|i| i.get(name))
+            .and_then(|v| v.as_s().ok())
+            .cloned()
+    };
+    let number = |name: &str| {
+        item.and_then(|i| i.get(name))
+            .and_then(|v| v.as_n().ok())
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(0)
+    };
+    Ok(MigrationState {
+        version: migration.version,
+        id: migration.id.to_string(),
+        description: migration.description.to_string(),
+        status: text("status").unwrap_or_else(|| "pending".to_string()),
+        scanned: number("scanned"),
+        updated: number("updated"),
+        started_at: text("started_at"),
+        updated_at: text("updated_at"),
+        completed_at: text("completed_at"),
+        last_error: text("last_error"),
+        cursor: text("cursor").and_then(|c| cursor_from_json(&c)),
+    })
+}
+
+/// Take the lease on a migration. Fails (returns false) while another run
+/// holds an unexpired lease.
+async fn claim(client: &DynamoClient, table_name: &str, version: u32) -> Result<bool, Error> {
+    let now = Utc::now();
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .set_key(Some(state_key(version)))
+        .update_expression(
+            "SET #status = :running, lease_until = :until, started_at = if_not_exists(started_at, :now)",
+        )
+        .condition_expression("attribute_not_exists(lease_until) OR lease_until < :epoch")
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_values(":running", AttributeValue::S("running".to_string()))
+        .expression_attribute_values(
+            ":until",
+            AttributeValue::N((now.timestamp() + LEASE_SECS).to_string()),
+        )
+        .expression_attribute_values(":epoch", AttributeValue::N(now.timestamp().to_string()))
+        .expression_attribute_values(":now", AttributeValue::S(now.to_rfc3339()))
+        .send()
+        .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Persist progress after a page (and release the lease once finished or failed)
+async fn save_state(
+    client: &DynamoClient,
+    table_name: &str,
+    state: &MigrationState,
+) -> Result<(), Error> {
+    let now = Utc::now();
+    let mut set = vec![
+        "#status = :status",
+        "scanned = :scanned",
+        "updated = :updated",
+        "updated_at = :now",
+    ];
+    let mut remove = Vec::new();
+    let mut values = HashMap::from([
+        (
+            ":status".to_string(),
+            AttributeValue::S(state.status.clone()),
+        ),
+        (
+            ":scanned".to_string(),
+            AttributeValue::N(state.scanned.to_string()),
+        ),
+        (
+            ":updated".to_string(),
+            AttributeValue::N(state.updated.to_string()),
+        ),
+        (":now".to_string(), AttributeValue::S(now.to_rfc3339())),
+    ]);
+    match &state.cursor {
+        Some(cursor) => {
+            set.push("#cursor = :cursor");
+            values.insert(
+                ":cursor".to_string(),
+                AttributeValue::S(cursor_to_json(cursor)),
+            );
+        }
+        None => remove.push("#cursor"),
+    }
+    if state.status == "running" {
+        set.push("lease_until = :until");
+        values.insert(
+            ":until".to_string(),
+            AttributeValue::N((now.timestamp() + LEASE_SECS).to_string()),
+        );
+    } else {
+        remove.push("lease_until");
+    }
+    if let Some(completed_at) = &state.completed_at {
+        set.push("completed_at = :completed_at");
+        values.insert(
+            ":completed_at".to_string(),
+            AttributeValue::S(completed_at.clone()),
+        );
+    }
+    match &state.last_error {
+        Some(error) => {
+            set.push("last_error = :error");
+            values.insert(":error".to_string(), AttributeValue::S(error.clone()));
+        }
+        None => remove.push("last_error"),
+    }
+
+    let mut expression = format!("SET {}", set.join(", "));
+    if !remove.is_empty() {
+        expression.push_str(&format!(" REMOVE {}", remove.join(", ")));
+    }
+    client
+        .update_item()
+        .table_name(table_name)
+        .set_key(Some(state_key(state.version)))
+        .update_expression(expression)
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_names("#cursor", "cursor")
+        .set_expression_attribute_values(Some(values))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// Attributes an item needs under `annotation_derived_fields`, or `None` when
+/// it is not an annotation or already has them
+pub fn derived_fields_update(item: &Item) -> Option<Vec<(&'static str, AttributeValue)>> {
+    let sk = item.get("SK")?.as_s().ok()?;
+    if !sk.starts_with("ANNOTATION#") || item.contains_key("area") {
+        return None;
+    }
+    let geometry = serde_json::from_str(item.get("geometry")?.as_s().ok()?).ok()?;
+    annotations::derived_attributes(&geometry).ok()
+}
+
+/// Project id of a PROJECT#{pid}/PROJECT#{pid} metadata item
+fn project_metadata_id(item: &Item) -> Option<&str> {
+    let pk = item.get("PK")?.as_s().ok()?;
+    let sk = item.get("SK")?.as_s().ok()?;
+    if pk != sk {
+        return None;
+    }
+    pk.strip_prefix("PROJECT#")
+}
+
+/// Project and block ids of a PROJECT#{pid}/BLOCK#{bid} block item
+fn block_ids(item: &Item) -> Option<(&str, &str)> {
+    let pk = item.get("PK")?.as_s().ok()?;
+    let sk = item.get("SK")?.as_s().ok()?;
+    Some((pk.strip_prefix("PROJECT#")?, sk.strip_prefix("BLOCK#")?))
+}
+
+/// SET attributes on an existing item; items deleted mid-migration are skipped
+async fn set_attributes(
+    client: &DynamoClient,
+    table_name: &str,
+    item: &Item,
+    attributes: Vec<(&'static str, AttributeValue)>,
+) -> Result<bool, Error> {
+    let (Some(pk), Some(sk)) = (item.get("PK"), item.get("SK")) else {
+        return Ok(false);
+    };
+    let mut builder = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", pk.clone())
+        .key("SK", sk.clone())
+        .condition_expression("attribute_exists(PK)");
+    let mut set = Vec::new();
+    for (i, (name, value)) in attributes.into_iter().enumerate() {
+        set.push(format!("#a{0} = :a{0}", i));
+        builder = builder
+            .expression_attribute_names(format!("#a{}", i), name)
+            .expression_attribute_values(format!(":a{}", i), value);
+    }
+    let result = builder
+        .update_expression(format!("SET {}", set.join(", ")))
+        .send()
+        .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Apply one migration to a page of items, returning how many items changed
+async fn migrate_page(
+    client: &DynamoClient,
+    table_name: &str,
+    version: u32,
+    items: &[Item],
+) -> Result<u64, Error> {
+    let mut updated = 0;
+    for item in items {
+        match version {
+            1 => {
+                if let Some(attributes) = derived_fields_update(item) {
+                    if set_attributes(client, table_name, item, attributes).await? {
+                        updated += 1;
+                    }
+                }
+            }
+            2 => {
+                let Some(project_id) = project_metadata_id(item) else {
+                    continue;
+                };
+                let name = item
+                    .get("name")
+                    .and_then(|v| v.as_s().ok())
+                    .cloned()
+                    .unwrap_or_default();
+                updated +=
+                    projects::set_project_name_on_links(client, table_name, project_id, &name)
+                        .await? as u64;
+            }
+            3 => {
+                // Annotations don't record their project; reach them through the block
+                let Some((project_id, block_id)) = block_ids(item) else {
+                    continue;
+                };
+                updated +=
+                    annotations::index_block_annotations(client, table_name, project_id, block_id)
+                        .await?;
+            }
+            4 => {
+                let Some((project_id, block_id)) = block_ids(item) else {
+                    continue;
+                };
+                updated +=
+                    annotations::locate_block_annotations(client, table_name, project_id, block_id)
+                        .await?;
+            }
+            5 => {
+                let Some((project_id, block_id)) = block_ids(item) else {
+                    continue;
+                };
+                updated +=
+                    annotations::count_block_annotations(client, table_name, project_id, block_id)
+                        .await?;
+            }
+            6 => {
+                let Some((_, block_id)) = block_ids(item) else {
+                    continue;
+                };
+                updated += block_stats::recount_block(client, table_name, block_id).await?;
+            }
+            7 => {
+                updated += connections::index_connection(client, table_name, item).await? as u64;
+            }
+            _ => {}
+        }
+    }
+    Ok(updated)
+}
+
+/// List migrations and their progress (GET /admin/migrations)
+pub async fn list_migrations(
+    client: &DynamoClient,
+    table_name: &str,
+) -> Result<Response<Body>, Error> {
+    let mut states = Vec::new();
+    for migration in &MIGRATIONS {
+        states.push(load_state(client, table_name, migration).await?);
+    }
+    json_response(StatusCode::OK, serde_json::json!({ "migrations": states }))
+}
+
+/// Run (or resume) a migration for a bounded number of Scan pages
+/// (POST /admin/migrations/{version}/run?max_pages=N). Progress is saved after
+/// every page, so calling again continues where the last run stopped; the
+/// response's `status` stays `running` until the scan reaches the end.
+pub async fn run_migration(
+    client: &DynamoClient,
+    table_name: &str,
+    version: &str,
+    max_pages: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let Some(migration) = MIGRATIONS
+        .iter()
+        .find(|m| m.version.to_string() == version || m.id == version)
+    else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": format!("Unknown migration '{}'", version)}),
+        );
+    };
+    let max_pages = max_pages
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PAGES)
+        .clamp(1, MAX_PAGES);
+
+    for earlier in MIGRATIONS.iter().filter(|m| m.version < migration.version) {
+        if load_state(client, table_name, earlier).await?.status != "completed" {
+            return json_response(
+                StatusCode::CONFLICT,
+                serde_json::json!({
+                    "error": format!("Migration {} ({}) must complete first", earlier.version, earlier.id)
+                }),
+            );
+        }
+    }
+
+    let state = load_state(client, table_name, migration).await?;
+    if state.status == "completed" {
+        return json_response(StatusCode::OK, serde_json::to_value(&state)?);
+    }
+    if !claim(client, table_name, migration.version).await? {
+        return json_response(
+            StatusCode::CONFLICT,
+            serde_json::json!({"error": "Migration is already running"}),
+        );
+    }
+    // Re-read after claiming so the cursor is the latest saved one
+    let mut state = load_state(client, table_name, migration).await?;
+    state.last_error = None;
+
+    let started = Instant::now();
+    for _ in 0..max_pages {
+        let page = client
+            .scan()
+            .table_name(table_name)
+            .limit(PAGE_SIZE)
+            .set_exclusive_start_key(state.cursor.clone())
+            .send()
+            .await;
+        let result = match page {
+            Ok(page) => {
+                let updated =
+                    migrate_page(client, table_name, migration.version, page.items()).await;
+                updated.map(|updated| {
+                    (
+                        page.items().len() as u64,
+                        updated,
+                        page.last_evaluated_key().cloned(),
+                    )
+                })
+            }
+            Err(e) => Err(e.into()),
+        };
+        match result {
+            Ok((scanned, updated, cursor)) => {
+                state.scanned += scanned;
+                state.updated += updated;
+                state.cursor = cursor;
+                if state.cursor.is_none() {
+                    state.status = "completed".to_string();
+                    state.completed_at = Some(Utc::now().to_rfc3339());
+                }
+                save_state(client, table_name, &state).await?;
+            }
+            Err(e) => {
+                // The cursor still points at the failed page, so a rerun retries it
+                tracing::error!("Migration {} failed: {}", migration.id, e);
+                state.status = "failed".to_string();
+                state.last_error = Some(e.to_string());
+                save_state(client, table_name, &state).await?;
+                return json_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    serde_json::to_value(&state)?,
+                );
+            }
+        }
+        if state.status == "completed" || started.elapsed() > TIME_BUDGET {
+            break;
+        }
+    }
+
+    if state.status == "running" {
+        // Let the next call resume straight away
+        state.status = "pending".to_string();
+        save_state(client, table_name, &state).await?;
+    }
+    tracing::info!(
+        "Migration {}: {} scanned, {} updated, status {}",
+        migration.id,
+        state.scanned,
+        state.updated,
+        state.status
+    );
+    json_response(StatusCode::OK, serde_json::to_value(&state)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(pairs: &[(&str, &str)]) -> Item {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), AttributeValue::S(v.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, i + 1);
+        }
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = item(&[("PK", "IMAGE#i"), ("SK", "ANNOTATION#a")]);
+        assert_eq!(cursor_from_json(&cursor_to_json(&cursor)), Some(cursor));
+        assert_eq!(cursor_from_json("not json"), None);
+    }
+
+    #[test]
+    fn test_derived_fields_update() {
+        let annotation = item(&[
+            ("PK", "IMAGE#i"),
+            ("SK", "ANNOTATION#a"),
+            (
+                "geometry",
+                r#"{"type":"bbox","start":{"x":0,"y":0},"end":{"x":4,"y":2}}"#,
+            ),
+        ]);
+        let update = derived_fields_update(&annotation).unwrap();
+        assert_eq!(update[0], ("area", AttributeValue::N("8".to_string())));
+        assert_eq!(update[1].0, "bounding_box");
+
+        // Already migrated, or not an annotation
+        let mut done = annotation.clone();
+        done.insert("area".to_string(), AttributeValue::N("8".to_string()));
+        assert!(derived_fields_update(&done).is_none());
+        assert!(derived_fields_update(&item(&[("PK", "IMAGE#i"), ("SK", "COMMENT#c")])).is_none());
+    }
+
+    #[test]
+    fn test_block_ids() {
+        assert_eq!(
+            block_ids(&item(&[("PK", "PROJECT#p"), ("SK", "BLOCK#b")])),
+            Some(("p", "b"))
+        );
+        assert_eq!(
+            block_ids(&item(&[("PK", "PROJECT#p"), ("SK", "CLASS#c")])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_project_metadata_id() {
+        assert_eq!(
+            project_metadata_id(&item(&[("PK", "PROJECT#p"), ("SK", "PROJECT#p")])),
+            Some("p")
+        );
+        assert_eq!(
+            project_metadata_id(&item(&[("PK", "PROJECT#p"), ("SK", "USER#u")])),
+            None
+        );
+    }
+}
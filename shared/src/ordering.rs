@@ -0,0 +1,449 @@
+use crate::audit::AuditEntry;
+use crate::images;
+use crate::region;
+use crate::responses::json_response;
+use crate::types::{Image, ReorderImagesRequest};
+use aws_sdk_dynamodb::{types::AttributeValue, Client as DynamoClient};
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use std::cmp::Ordering;
+use std::io::Cursor;
+
+/// EXIF lives in the first APP1 segment, well within the first 128KB
+const EXIF_RANGE_BYTES: usize = 128 * 1024;
+
+/// Order updates per transaction, DynamoDB's limit
+const ORDER_CHUNK: usize = 100;
+
+/// EXIF capture time (DateTimeOriginal, then DateTime) as "YYYY-MM-DDTHH:MM:SS"
+pub fn capture_time(image_bytes: &[u8]) -> Option<String> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(image_bytes))
+        .ok()?;
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .iter()
+        .find_map(|tag| {
+            let field = exif.get_field(*tag, exif::In::PRIMARY)?;
+            let exif::Value::Ascii(ref values) = field.value else {
+                return None;
+            };
+            let dt = exif::DateTime::from_ascii(values.first()?).ok()?;
+            Some(format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+            ))
+        })
+}
+
+/// Read an image's capture time from the start of its S3 object
+pub async fn read_capture_time(s3_client: &S3Client, url: &str) -> Option<String> {
+    let key = crate::storage::object_key(url)?;
+    let result = s3_client
+        .get_object()
+        .bucket(region::bucket_name())
+        .key(&key)
+        .range(format!("bytes=0-{}", EXIF_RANGE_BYTES - 1))
+        .send()
+        .await
+        .ok()?;
+    let bytes = result.body.collect().await.ok()?.into_bytes();
+    capture_time(&bytes)
+}
+
+/// Compare strings so that embedded numbers sort numerically ("p2" < "p10")
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                        chars.next();
+                    }
+                    digits
+                };
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (xt, yt) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = xt.len().cmp(&yt.len()).then_with(|| xt.cmp(yt));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_ascii_lowercase().cmp(&y.to_ascii_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Name used for the filename fallback: the original upload name if known
+fn display_name(image: &Image) -> String {
+    image.file_name.clone().unwrap_or_else(|| {
+        let url = image.url.split(['?', '#']).next().unwrap_or(&image.url);
+        url.rsplit('/').next().unwrap_or(url).to_string()
+    })
+}
+
+/// Capture time first (images without EXIF go last), then natural filename order
+pub fn auto_order_cmp(a: &Image, b: &Image) -> Ordering {
+    match (&a.captured_at, &b.captured_at) {
+        (Some(x), Some(y)) => x.cmp(y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+    .then_with(|| natural_cmp(&display_name(a), &display_name(b)))
+    .then_with(|| a.uploaded_at.cmp(&b.uploaded_at))
+}
+
+/// Re-sort a block's images and write `order` (0-based) where it changed
+pub async fn apply_auto_order(
+    client: &DynamoClient,
+    table_name: &str,
+    block_id: &str,
+) -> Result<Vec<Image>, Error> {
+    let mut block_images = images::fetch_block_images(client, table_name, block_id).await?;
+    block_images.sort_by(auto_order_cmp);
+
+    for (index, image) in block_images.iter_mut().enumerate() {
+        let order = index as i32;
+        if image.order == Some(order) {
+            continue;
+        }
+        client
+            .update_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(format!("BLOCK#{}", block_id)))
+            .key("SK", AttributeValue::S(format!("IMAGE#{}", image.image_id)))
+            .update_expression("SET #order = :order")
+            .condition_expression("attribute_exists(PK)")
+            .expression_attribute_names("#order", "order")
+            .expression_attribute_values(":order", AttributeValue::N(order.to_string()))
+            .send()
+            .await?;
+        image.order = Some(order);
+    }
+
+    Ok(block_images)
+}
+
+/// Re-apply automatic ordering to a block
+/// (POST /projects/{pid}/blocks/{bid}/images/reorder).
+/// Images ingested before capture times were recorded are read from S3 first.
+pub async fn reorder_block_images(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+) -> Result<Response<Body>, Error> {
+    if crate::blocks::fetch_block(client, table_name, project_id, block_id)
+        .await?
+        .is_none()
+    {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Block not found"}),
+        );
+    }
+    if let Some(response) =
+        crate::blocks::check_assignee(client, table_name, project_id, block_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    for image in images::fetch_block_images(client, table_name, block_id).await? {
+        if image.captured_at.is_some() {
+            continue;
+        }
+        if let Some(captured_at) = read_capture_time(s3_client, &image.url).await {
+            client
+                .update_item()
+                .table_name(table_name)
+                .key("PK", AttributeValue::S(format!("BLOCK#{}", block_id)))
+                .key("SK", AttributeValue::S(format!("IMAGE#{}", image.image_id)))
+                .update_expression("SET captured_at = :captured_at")
+                .expression_attribute_values(":captured_at", AttributeValue::S(captured_at))
+                .send()
+                .await?;
+        }
+    }
+
+    let ordered = apply_auto_order(client, table_name, block_id).await?;
+    tracing::info!("Re-ordered {} images in block {}", ordered.len(), block_id);
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&ordered)?.into())
+        .map_err(Box::new)?)
+}
+
+/// A block's images in a dragged order: those listed first, as listed, then
+/// the rest as they were. Errors name an ID listed twice or not in the block.
+fn manual_order(mut block_images: Vec<Image>, image_ids: &[String]) -> Result<Vec<Image>, String> {
+    if image_ids.is_empty() {
+        return Err("image_ids must not be empty".to_string());
+    }
+    let mut positions = std::collections::HashMap::new();
+    for (position, image_id) in image_ids.iter().enumerate() {
+        if positions.insert(image_id.as_str(), position).is_some() {
+            return Err(format!("Image '{}' is listed more than once", image_id));
+        }
+    }
+    if let Some(unknown) = image_ids
+        .iter()
+        .find(|id| !block_images.iter().any(|image| &image.image_id == *id))
+    {
+        return Err(format!("Image '{}' is not in this block", unknown));
+    }
+    // Listed images first, by position; the rest after, as they were
+    block_images.sort_by_key(|image| {
+        (
+            positions
+                .get(image.image_id.as_str())
+                .map_or((1, 0), |position| (0, *position)),
+            image.order.unwrap_or(i32::MAX),
+            image.uploaded_at.clone(),
+        )
+    });
+    Ok(block_images)
+}
+
+/// Set a block's image order in one go
+/// (PATCH /projects/{pid}/blocks/{bid}/images/order, body `{"image_ids"}`
+/// first to last). Images left out follow the listed ones in their current
+/// order; every `order` that changed is written in transactions.
+pub async fn set_image_order(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    block_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: ReorderImagesRequest = serde_json::from_slice(body)?;
+    if let Some(response) =
+        crate::blocks::check_assignee(client, table_name, project_id, block_id, user_id).await?
+    {
+        return Ok(response);
+    }
+    let block_images = images::fetch_block_images(client, table_name, block_id).await?;
+    let mut ordered = match manual_order(block_images, &req.image_ids) {
+        Ok(ordered) => ordered,
+        Err(message) => {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                serde_json::json!({ "error": message }),
+            )
+        }
+    };
+
+    let mut updates = Vec::new();
+    for (index, image) in ordered.iter_mut().enumerate() {
+        let order = index as i32;
+        if image.order == Some(order) {
+            continue;
+        }
+        let update = aws_sdk_dynamodb::types::Update::builder()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(format!("BLOCK#{}", block_id)))
+            .key("SK", AttributeValue::S(format!("IMAGE#{}", image.image_id)))
+            .update_expression("SET #order = :order")
+            .condition_expression("attribute_exists(PK)")
+            .expression_attribute_names("#order", "order")
+            .expression_attribute_values(":order", AttributeValue::N(order.to_string()))
+            .build()?;
+        updates.push(
+            aws_sdk_dynamodb::types::TransactWriteItem::builder()
+                .update(update)
+                .build(),
+        );
+        image.order = Some(order);
+    }
+    for chunk in updates.chunks(ORDER_CHUNK) {
+        let result = client
+            .transact_write_items()
+            .set_transact_items(Some(chunk.to_vec()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => {}
+            // An image was deleted since the block was read
+            Err(e)
+                if e.as_service_error()
+                    .map(|se| se.is_transaction_canceled_exception())
+                    .unwrap_or(false) =>
+            {
+                return json_response(
+                    StatusCode::CONFLICT,
+                    serde_json::json!({ "error": "The block's images changed while reordering" }),
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if !updates.is_empty() {
+        let summary = serde_json::json!({
+            "image_ids": ordered.iter().map(|image| &image.image_id).collect::<Vec<_>>(),
+        });
+        let entry = AuditEntry::new(project_id, user_id, "reordered", "block", block_id)
+            .block(block_id)
+            .after(&summary);
+        crate::audit::record(client, table_name, entry).await;
+    }
+    tracing::info!(
+        "Set order of {} images in block {} ({} changed)",
+        ordered.len(),
+        block_id,
+        updates.len()
+    );
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&ordered)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(file_name: &str, captured_at: Option<&str>) -> Image {
+        Image {
+            image_id: file_name.to_string(),
+            block_id: "b".to_string(),
+            url: format!("https://cdn/projects/p/blocks/b/{}.jpg", file_name),
+            locked: false,
+            order: None,
+            uploaded_at: String::new(),
+            calibration: None,
+            file_name: Some(file_name.to_string()),
+            captured_at: captured_at.map(|s| s.to_string()),
+            annotation_count: 0,
+            status: "unannotated".to_string(),
+            width: None,
+            height: None,
+            size_bytes: None,
+            content_type: None,
+            levels: Vec::new(),
+            thumbnail_url: None,
+            tiles_url: None,
+            photo: None,
+            original_format: None,
+            processing: "ready".to_string(),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = vec![
+            "page10.png",
+            "Page2.png",
+            "page1.png",
+            "page02b.png",
+            "cover.png",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "cover.png",
+                "page1.png",
+                "Page2.png",
+                "page02b.png",
+                "page10.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_order_cmp() {
+        let mut images = [
+            image("IMG_10", None),
+            image("IMG_2", None),
+            image("late", Some("2026-03-01T10:00:00")),
+            image("early", Some("2026-03-01T09:00:00")),
+        ];
+        images.sort_by(auto_order_cmp);
+        let names: Vec<&str> = images.iter().map(|i| i.image_id.as_str()).collect();
+        assert_eq!(names, vec!["early", "late", "IMG_2", "IMG_10"]);
+    }
+
+    #[test]
+    fn test_manual_order() {
+        let block_images = || {
+            ["a", "b", "c", "d"]
+                .iter()
+                .enumerate()
+                .map(|(i, name)| Image {
+                    order: Some(i as i32),
+                    ..image(name, None)
+                })
+                .collect::<Vec<_>>()
+        };
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+        let names = |images: Vec<Image>| {
+            images
+                .into_iter()
+                .map(|image| image.image_id)
+                .collect::<Vec<_>>()
+        };
+
+        let ordered = manual_order(block_images(), &ids(&["d", "b", "a", "c"])).unwrap();
+        assert_eq!(names(ordered), vec!["d", "b", "a", "c"]);
+        // Left out keep their place relative to each other, after the rest
+        let ordered = manual_order(block_images(), &ids(&["c"])).unwrap();
+        assert_eq!(names(ordered), vec!["c", "a", "b", "d"]);
+
+        assert_eq!(
+            manual_order(block_images(), &ids(&["a", "a"])).unwrap_err(),
+            "Image 'a' is listed more than once"
+        );
+        assert_eq!(
+            manual_order(block_images(), &ids(&["z"])).unwrap_err(),
+            "Image 'z' is not in this block"
+        );
+        assert!(manual_order(block_images(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_capture_time_without_exif() {
+        assert_eq!(capture_time(b"not an image"), None);
+    }
+
+    #[tokio::test]
+    async fn test_reorder_block_images_requires_assignee() {
+        let (client, calls) =
+            crate::test_util::fake_dynamo(vec![crate::test_util::assigned_block("p", "b", "u2")]);
+        let s3_client = crate::test_util::offline_s3();
+        let response = reorder_block_images(&client, &s3_client, "table", "u1", "p", "b")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // The block, its assignee and the caller's role were read; nothing
+        // was written
+        assert_eq!(*calls.lock().unwrap(), ["GetItem", "GetItem", "GetItem"]);
+
+        // Another project's block isn't reordered through this one
+        let response = reorder_block_images(&client, &s3_client, "table", "u2", "other", "b")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,335 @@
+use aws_sdk_dynamodb::operation::query::builders::QueryFluentBuilder;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::HashMap;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Largest `limit` a listing accepts
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Response header carrying the cursor of the next page (absent on the last one)
+pub const NEXT_CURSOR_HEADER: &str = "X-Next-Cursor";
+
+/// Raw `?limit=&cursor=` query params of a listing
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PageParams<'a> {
+    pub limit: Option<&'a str>,
+    pub cursor: Option<&'a str>,
+}
+
+/// Which part of a listing to read. The default follows every page.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PageRequest {
+    pub limit: Option<usize>,
+    pub start_sk: Option<String>, // SK of the last item already returned
+}
+
+/// Items of one page plus the cursor to continue from
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// A FilterExpression with the names and values it refers to
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    pub expression: String,
+    pub names: HashMap<String, String>,
+    pub values: Item,
+}
+
+impl Filter {
+    pub fn new(expression: &str) -> Self {
+        Filter {
+            expression: expression.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Also require `#{attribute} = :{attribute}`
+    pub fn equals(self, attribute: &str, value: &str) -> Self {
+        let clause = format!("#{0} = :{0}", attribute);
+        self.and(&clause, attribute, value)
+    }
+
+    /// Also require `clause`, which refers to `#{attribute}` and `:{attribute}`
+    pub fn and(self, clause: &str, attribute: &str, value: &str) -> Self {
+        self.and_value(clause, attribute, AttributeValue::S(value.to_string()))
+    }
+
+    /// `and` for a number attribute
+    pub fn and_number(self, clause: &str, attribute: &str, value: f64) -> Self {
+        self.and_value(clause, attribute, AttributeValue::N(value.to_string()))
+    }
+
+    fn and_value(mut self, clause: &str, attribute: &str, value: AttributeValue) -> Self {
+        self.expression = if self.expression.is_empty() {
+            clause.to_string()
+        } else {
+            format!("{} AND {}", self.expression, clause)
+        };
+        self.names
+            .insert(format!("#{}", attribute), attribute.to_string());
+        self.values.insert(format!(":{}", attribute), value);
+        self
+    }
+}
+
+/// Cursors are the base64url SK of the last item returned; the partition
+/// comes from the listing itself, so a cursor can't move a query elsewhere
+pub fn encode_cursor(sk: &str) -> String {
+    URL_SAFE_NO_PAD.encode(sk)
+}
+
+fn decode_cursor(cursor: &str) -> Option<String> {
+    String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()
+}
+
+/// Validate the params of a listing whose sort keys start with `sk_prefix`
+pub fn page_request(params: PageParams, sk_prefix: &str) -> Result<PageRequest, String> {
+    let limit = match params.limit {
+        None => None,
+        Some(raw) => match raw.parse::<usize>() {
+            Ok(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => Some(limit),
+            _ => {
+                return Err(format!(
+                    "limit must be a whole number from 1 to {}",
+                    MAX_PAGE_SIZE
+                ))
+            }
+        },
+    };
+    let start_sk = match params.cursor {
+        None => None,
+        Some(cursor) => match decode_cursor(cursor) {
+            Some(sk) if sk.starts_with(sk_prefix) => Some(sk),
+            _ => return Err("Invalid cursor".to_string()),
+        },
+    };
+    Ok(PageRequest { limit, start_sk })
+}
+
+/// Add a filter's expression, names and values to a query
+fn with_filter(mut query: QueryFluentBuilder, filter: Option<&Filter>) -> QueryFluentBuilder {
+    if let Some(filter) = filter {
+        query = query.filter_expression(&filter.expression);
+        for (name, attribute) in &filter.names {
+            query = query.expression_attribute_names(name, attribute);
+        }
+        for (name, value) in &filter.values {
+            query = query.expression_attribute_values(name, value.clone());
+        }
+    }
+    query
+}
+
+/// Query the items of partition `pk` whose SK starts with `sk_prefix` (and
+/// that match `filter`, if given), following LastEvaluatedKey across
+/// DynamoDB's 1MB pages until the request's limit (or the end of the
+/// partition) is reached
+pub async fn query_prefix(
+    client: &DynamoClient,
+    table_name: &str,
+    pk: &str,
+    sk_prefix: &str,
+    filter: Option<&Filter>,
+    request: &PageRequest,
+) -> Result<Page<Item>, Error> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut last_key = request.start_sk.as_ref().map(|sk| {
+        HashMap::from([
+            ("PK".to_string(), AttributeValue::S(pk.to_string())),
+            ("SK".to_string(), AttributeValue::S(sk.clone())),
+        ])
+    });
+    loop {
+        let remaining = request.limit.map(|limit| (limit - items.len()) as i32);
+        let query = client
+            .query()
+            .table_name(table_name)
+            .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
+            .expression_attribute_values(":pk", AttributeValue::S(pk.to_string()))
+            .expression_attribute_values(":sk_prefix", AttributeValue::S(sk_prefix.to_string()))
+            .set_limit(remaining)
+            .set_exclusive_start_key(last_key);
+        let result = with_filter(query, filter).send().await?;
+        items.extend(result.items().iter().cloned());
+        last_key = result.last_evaluated_key().cloned();
+        let full = request.limit.is_some_and(|limit| items.len() >= limit);
+        if last_key.is_none() || full {
+            break;
+        }
+    }
+    // A follow-all read only stops once there's no key left, so only limited
+    // reads return a cursor
+    let next_cursor = last_key
+        .as_ref()
+        .and_then(|key| key.get("SK")?.as_s().ok())
+        .map(|sk| encode_cursor(sk));
+    Ok(Page { items, next_cursor })
+}
+
+/// Global secondary index over every annotation of a project:
+/// GSI1PK=PROJECT#{pid}, GSI1SK=ANNOTATION#{created_at}#{aid}
+pub const PROJECT_INDEX: &str = "GSI1";
+
+/// Join of the index sort key and table key an index cursor resumes from
+const KEY_SEPARATOR: char = '\n';
+
+/// Validate the params of a project index listing
+pub fn project_index_request(params: PageParams) -> Result<PageRequest, String> {
+    let request = page_request(params, "ANNOTATION#")?;
+    let parts = request
+        .start_sk
+        .as_ref()
+        .map(|start| start.split(KEY_SEPARATOR).count());
+    if parts.is_some_and(|parts| parts != 3) {
+        return Err("Invalid cursor".to_string());
+    }
+    Ok(request)
+}
+
+/// Query the project index partition `gsi1pk` from sort key `sk_from` on (and
+/// matching `filter`), oldest first. `request.start_sk` and the returned
+/// cursor carry GSI1SK, PK and SK of the last item, since index keys aren't
+/// unique on their own.
+pub async fn query_project_index(
+    client: &DynamoClient,
+    table_name: &str,
+    gsi1pk: &str,
+    sk_from: &str,
+    filter: Option<&Filter>,
+    request: &PageRequest,
+) -> Result<Page<Item>, Error> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut last_key = request.start_sk.as_ref().and_then(|start| {
+        let mut parts = start.split(KEY_SEPARATOR);
+        let (gsi1sk, pk, sk) = (parts.next()?, parts.next()?, parts.next()?);
+        Some(HashMap::from([
+            ("GSI1PK".to_string(), AttributeValue::S(gsi1pk.to_string())),
+            ("GSI1SK".to_string(), AttributeValue::S(gsi1sk.to_string())),
+            ("PK".to_string(), AttributeValue::S(pk.to_string())),
+            ("SK".to_string(), AttributeValue::S(sk.to_string())),
+        ]))
+    });
+    loop {
+        let remaining = request.limit.map(|limit| (limit - items.len()) as i32);
+        let query = client
+            .query()
+            .table_name(table_name)
+            .index_name(PROJECT_INDEX)
+            .key_condition_expression("GSI1PK = :gsi1pk AND GSI1SK >= :sk_from")
+            .expression_attribute_values(":gsi1pk", AttributeValue::S(gsi1pk.to_string()))
+            .expression_attribute_values(":sk_from", AttributeValue::S(sk_from.to_string()))
+            .set_limit(remaining)
+            .set_exclusive_start_key(last_key);
+        let result = with_filter(query, filter).send().await?;
+        items.extend(result.items().iter().cloned());
+        last_key = result.last_evaluated_key().cloned();
+        let full = request.limit.is_some_and(|limit| items.len() >= limit);
+        if last_key.is_none() || full {
+            break;
+        }
+    }
+    let text = |key: &Item, name: &str| key.get(name)?.as_s().ok().cloned();
+    let next_cursor = last_key.as_ref().and_then(|key| {
+        let parts = [text(key, "GSI1SK")?, text(key, "PK")?, text(key, "SK")?];
+        Some(encode_cursor(&parts.join(&KEY_SEPARATOR.to_string())))
+    });
+    Ok(Page { items, next_cursor })
+}
+
+/// 400 for bad paging params
+pub fn invalid_page(message: String) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({ "error": message }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// A listing response: the page's items as a JSON array (the shape lists
+/// have always had) and the next cursor in a header
+pub fn page_response<T: Serialize>(page: &Page<T>) -> Result<Response<Body>, Error> {
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Expose-Headers", NEXT_CURSOR_HEADER);
+    if let Some(cursor) = &page.next_cursor {
+        builder = builder.header(NEXT_CURSOR_HEADER, cursor);
+    }
+    Ok(builder
+        .body(serde_json::to_string(&page.items)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_request() {
+        let params = |limit, cursor| PageParams { limit, cursor };
+        assert_eq!(
+            page_request(params(None, None), "IMAGE#"),
+            Ok(PageRequest::default())
+        );
+
+        let cursor = encode_cursor("ANNOTATION#a1");
+        assert_eq!(
+            page_request(params(Some("50"), Some(&cursor)), "ANNOTATION#"),
+            Ok(PageRequest {
+                limit: Some(50),
+                start_sk: Some("ANNOTATION#a1".to_string()),
+            })
+        );
+        // A cursor from another listing
+        assert!(page_request(params(None, Some(&cursor)), "CLASS#").is_err());
+        assert!(page_request(params(None, Some("!!")), "CLASS#").is_err());
+        assert!(page_request(params(Some("0"), None), "CLASS#").is_err());
+        assert!(page_request(params(Some("1001"), None), "CLASS#").is_err());
+        assert!(page_request(params(Some("ten"), None), "CLASS#").is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::new("attribute_not_exists(deleted_at)")
+            .equals("class_id", "walls")
+            .and("contains(#tags, :tags)", "tags", "review");
+        assert_eq!(
+            filter.expression,
+            "attribute_not_exists(deleted_at) AND #class_id = :class_id AND contains(#tags, :tags)"
+        );
+        assert_eq!(filter.names["#tags"], "tags");
+        assert_eq!(
+            filter.values[":class_id"],
+            AttributeValue::S("walls".to_string())
+        );
+        assert_eq!(Filter::default().equals("a", "b").expression, "#a = :a");
+    }
+
+    #[test]
+    fn test_project_index_request() {
+        let params = |cursor| PageParams {
+            limit: None,
+            cursor,
+        };
+        let cursor =
+            encode_cursor("ANNOTATION#2026-01-01T00:00:00+00:00#a1\nIMAGE#i1\nANNOTATION#a1");
+        let request = project_index_request(params(Some(&cursor))).unwrap();
+        assert_eq!(
+            request.start_sk.unwrap().split(KEY_SEPARATOR).nth(1),
+            Some("IMAGE#i1")
+        );
+        // A cursor from an image's annotation listing
+        let image_cursor = encode_cursor("ANNOTATION#a1");
+        assert!(project_index_request(params(Some(&image_cursor))).is_err());
+    }
+}
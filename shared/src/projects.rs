@@ -1,12 +1,11 @@
 async fn delete_project_s3_prefix(s3_client: &S3Client, project_id: &str) -> Result<(), Error> {
-    const BUCKET_NAME: &str = "doxle-annotations";
-    let prefix = format!("projects/{}/", project_id);
+    let prefix = crate::storage::s3_config().project_prefix(project_id);
 
     let mut continuation: Option<String> = None;
     loop {
         let mut req = s3_client
             .list_objects_v2()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .prefix(&prefix);
         if let Some(token) = continuation.as_ref() {
             req = req.continuation_token(token);
@@ -43,7 +42,7 @@ async fn delete_project_s3_prefix(s3_client: &S3Client, project_id: &str) -> Res
 
         let _ = s3_client
             .delete_objects()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .delete(delete_payload)
             .send()
             .await;
@@ -57,11 +56,143 @@ async fn delete_project_s3_prefix(s3_client: &S3Client, project_id: &str) -> Res
     Ok(())
 }
 
-use crate::types::{CreateProjectRequest, Project, UpdateProjectRequest};
+use crate::audit::AuditEntry;
+use crate::members::OWNER_ROLE;
+use crate::region;
+use crate::types::{CreateProjectRequest, Project, ProjectSettings, UpdateProjectRequest};
 use aws_sdk_dynamodb::Client as DynamoClient;
 use aws_sdk_s3::Client as S3Client;
 use lambda_http::{http::StatusCode, Body, Error, Response};
 
+/// Check settings values, returning an error message for a 400
+fn validate_settings(settings: &ProjectSettings) -> Option<String> {
+    let defaults = &settings.annotation_defaults;
+    if let Some(geometry_type) = &defaults.default_geometry_type {
+        if !crate::geometry::KINDS.contains(&geometry_type.as_str()) {
+            return Some(format!(
+                "default_geometry_type must be one of: {}",
+                crate::geometry::KINDS.join(", ")
+            ));
+        }
+    }
+    if !defaults.snap.tolerance_px.is_finite() || defaults.snap.tolerance_px < 0.0 {
+        return Some("snap.tolerance_px must be a non-negative number".to_string());
+    }
+    let pyramid = &settings.pyramid;
+    if let Some(mode) = &pyramid.mode {
+        if !crate::image_processing::PYRAMID_MODES.contains(&mode.as_str()) {
+            return Some(format!(
+                "pyramid.mode must be one of: {}",
+                crate::image_processing::PYRAMID_MODES.join(", ")
+            ));
+        }
+    }
+    if let Some(tile_size) = pyramid.tile_size {
+        let sizes = crate::image_processing::TILE_SIZES;
+        if !sizes.contains(&tile_size) {
+            return Some(format!(
+                "pyramid.tile_size must be between {} and {}",
+                sizes.start(),
+                sizes.end()
+            ));
+        }
+    }
+    let uploads = &settings.uploads;
+    if uploads.max_file_size == Some(0) {
+        return Some("uploads.max_file_size must be positive".to_string());
+    }
+    if uploads.max_width == Some(0) || uploads.max_height == Some(0) {
+        return Some("uploads.max_width and uploads.max_height must be positive".to_string());
+    }
+    if let Some(mode) = &settings.coordinate_mode {
+        if !crate::geometry::COORDINATE_MODES.contains(&mode.as_str()) {
+            return Some(format!(
+                "coordinate_mode must be one of: {}",
+                crate::geometry::COORDINATE_MODES.join(", ")
+            ));
+        }
+    }
+    None
+}
+
+/// Coordinate convention of a project's annotations; projects that predate
+/// the setting (or don't exist) use pixels
+pub fn coordinate_mode(project: Option<&Project>) -> &str {
+    project
+        .and_then(|p| p.settings.coordinate_mode.as_deref())
+        .unwrap_or("pixel")
+}
+
+fn bad_request(message: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": message}).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Build a Project from its PROJECT#/PROJECT# item
+fn project_from_item(
+    project_id: &str,
+    item: &std::collections::HashMap<String, aws_sdk_dynamodb::types::AttributeValue>,
+) -> Project {
+    Project {
+        project_id: project_id.to_string(),
+        name: item
+            .get("name")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        project_type: item
+            .get("project_type")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        locked: item
+            .get("locked")
+            .and_then(|v| v.as_bool().ok())
+            .copied()
+            .unwrap_or(false),
+        labels: item
+            .get("labels")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| serde_json::from_str(s).unwrap_or_default())
+            .unwrap_or_default(),
+        created_at: item
+            .get("created_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        settings: item
+            .get("settings")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| serde_json::from_str(s).ok())
+            .unwrap_or_default(),
+    }
+}
+
+/// Fetch a project record, if it exists
+pub async fn fetch_project(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+) -> Result<Option<Project>, Error> {
+    let pk = format!("PROJECT#{}", project_id);
+
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .send()
+        .await?;
+
+    Ok(result
+        .item()
+        .map(|item| project_from_item(project_id, item)))
+}
+
 /// Create a new project
 pub async fn create_project(
     client: &DynamoClient,
@@ -108,6 +239,11 @@ pub async fn create_project(
             .map_err(Box::new)?);
     }
 
+    let settings = req.settings.unwrap_or_default();
+    if let Some(message) = validate_settings(&settings) {
+        return bad_request(&message);
+    }
+
     let project_id = uuid::Uuid::new_v4().to_string();
     let now = chrono::Utc::now().to_rfc3339();
     let pk = format!("PROJECT#{}", project_id);
@@ -151,6 +287,10 @@ pub async fn create_project(
         "created_at".to_string(),
         aws_sdk_dynamodb::types::AttributeValue::S(now.clone()),
     );
+    project_item.insert(
+        "settings".to_string(),
+        aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&settings)?),
+    );
 
     // 2. USER -> PROJECT link
     let mut user_to_project = HashMap::new();
@@ -166,6 +306,14 @@ pub async fn create_project(
         "joined_at".to_string(),
         aws_sdk_dynamodb::types::AttributeValue::S(now.clone()),
     );
+    user_to_project.insert(
+        "project_name".to_string(),
+        aws_sdk_dynamodb::types::AttributeValue::S(req.name.clone()),
+    );
+    user_to_project.insert(
+        "role".to_string(),
+        aws_sdk_dynamodb::types::AttributeValue::S(OWNER_ROLE.to_string()),
+    );
 
     // 3. PROJECT -> USER link
     let mut project_to_user = HashMap::new();
@@ -181,6 +329,14 @@ pub async fn create_project(
         "joined_at".to_string(),
         aws_sdk_dynamodb::types::AttributeValue::S(now.clone()),
     );
+    project_to_user.insert(
+        "project_name".to_string(),
+        aws_sdk_dynamodb::types::AttributeValue::S(req.name.clone()),
+    );
+    project_to_user.insert(
+        "role".to_string(),
+        aws_sdk_dynamodb::types::AttributeValue::S(OWNER_ROLE.to_string()),
+    );
 
     // Write all 3 items in a single batch operation
     client
@@ -229,7 +385,14 @@ pub async fn create_project(
         locked: false,
         labels: req.labels,
         created_at: now,
+        settings,
     };
+    crate::audit::record(
+        client,
+        table_name,
+        AuditEntry::new(&project_id, user_id, "created", "project", &project_id).after(&project),
+    )
+    .await;
 
     Ok(Response::builder()
         .status(StatusCode::CREATED)
@@ -245,46 +408,7 @@ pub async fn get_project(
     table_name: &str,
     project_id: &str,
 ) -> Result<Response<Body>, Error> {
-    let pk = format!("PROJECT#{}", project_id);
-
-    let result = client
-        .get_item()
-        .table_name(table_name)
-        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
-        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .send()
-        .await?;
-
-    if let Some(item) = result.item() {
-        let project = Project {
-            project_id: project_id.to_string(),
-            name: item
-                .get("name")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-            project_type: item
-                .get("project_type")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-            locked: item
-                .get("locked")
-                .and_then(|v| v.as_bool().ok())
-                .copied()
-                .unwrap_or(false),
-            labels: item
-                .get("labels")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| serde_json::from_str(s).unwrap_or_default())
-                .unwrap_or_default(),
-            created_at: item
-                .get("created_at")
-                .and_then(|v| v.as_s().ok())
-                .map(|s| s.to_string())
-                .unwrap_or_default(),
-        };
-
+    if let Some(project) = fetch_project(client, table_name, project_id).await? {
         Ok(Response::builder()
             .status(StatusCode::OK)
             .header("Content-Type", "application/json")
@@ -381,35 +505,7 @@ pub async fn list_user_projects(
                 for item in items {
                     if let Some(project_id_attr) = item.get("PK").and_then(|v| v.as_s().ok()) {
                         if let Some(project_id) = project_id_attr.strip_prefix("PROJECT#") {
-                            let project = Project {
-                                project_id: project_id.to_string(),
-                                name: item
-                                    .get("name")
-                                    .and_then(|v| v.as_s().ok())
-                                    .map(|s| s.to_string())
-                                    .unwrap_or_default(),
-                                project_type: item
-                                    .get("project_type")
-                                    .and_then(|v| v.as_s().ok())
-                                    .map(|s| s.to_string())
-                                    .unwrap_or_default(),
-                                locked: item
-                                    .get("locked")
-                                    .and_then(|v| v.as_bool().ok())
-                                    .copied()
-                                    .unwrap_or(false),
-                                labels: item
-                                    .get("labels")
-                                    .and_then(|v| v.as_s().ok())
-                                    .map(|s| serde_json::from_str(s).unwrap_or_default())
-                                    .unwrap_or_default(),
-                                created_at: item
-                                    .get("created_at")
-                                    .and_then(|v| v.as_s().ok())
-                                    .map(|s| s.to_string())
-                                    .unwrap_or_default(),
-                            };
-                            projects.push(project);
+                            projects.push(project_from_item(project_id, item));
                         }
                     }
                 }
@@ -429,6 +525,7 @@ pub async fn list_user_projects(
 pub async fn update_project(
     client: &DynamoClient,
     table_name: &str,
+    user_id: &str,
     project_id: &str,
     body: &[u8],
 ) -> Result<Response<Body>, Error> {
@@ -440,12 +537,12 @@ pub async fn update_project(
     let mut expr_names = std::collections::HashMap::new();
     let mut expr_values = std::collections::HashMap::new();
 
-    if let Some(name) = req.name {
+    if let Some(name) = &req.name {
         update_expr.push("#name = :name");
         expr_names.insert("#name".to_string(), "name".to_string());
         expr_values.insert(
             ":name".to_string(),
-            aws_sdk_dynamodb::types::AttributeValue::S(name),
+            aws_sdk_dynamodb::types::AttributeValue::S(name.clone()),
         );
     }
 
@@ -458,6 +555,45 @@ pub async fn update_project(
         );
     }
 
+    if let Some(settings) = req.settings {
+        if let Some(message) = validate_settings(&settings) {
+            return bad_request(&message);
+        }
+        // Stored geometries are in the project's convention, so the mode is
+        // fixed once anything has been annotated
+        let current = fetch_project(client, table_name, project_id).await?;
+        let requested = settings.coordinate_mode.as_deref().unwrap_or("pixel");
+        if requested != coordinate_mode(current.as_ref()) {
+            let annotated: u32 =
+                crate::classes::fetch_project_classes(client, table_name, project_id)
+                    .await?
+                    .iter()
+                    .map(|c| c.count)
+                    .sum();
+            if annotated > 0 {
+                return Ok(Response::builder()
+                    .status(StatusCode::CONFLICT)
+                    .header("Content-Type", "application/json")
+                    .header("Access-Control-Allow-Origin", "*")
+                    .body(
+                        serde_json::json!({
+                            "error": "coordinate_mode can't change after annotations exist",
+                            "annotation_count": annotated,
+                        })
+                        .to_string()
+                        .into(),
+                    )
+                    .map_err(Box::new)?);
+            }
+        }
+        update_expr.push("#settings = :settings");
+        expr_names.insert("#settings".to_string(), "settings".to_string());
+        expr_values.insert(
+            ":settings".to_string(),
+            aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(&settings)?),
+        );
+    }
+
     if !update_expr.is_empty() {
         let mut builder = client
             .update_item()
@@ -475,12 +611,95 @@ pub async fn update_project(
         }
 
         builder.send().await?;
+        if let Some(name) = &req.name {
+            set_project_name_on_links(client, table_name, project_id, name).await?;
+        }
         println!("[UPDATE] Success: {}", project_id);
+        crate::audit::record(
+            client,
+            table_name,
+            AuditEntry::new(project_id, user_id, "updated", "project", project_id).changes(body),
+        )
+        .await;
     }
 
     get_project(client, table_name, project_id).await
 }
 
+/// Copy the project name onto both membership rows of every member, so a
+/// user's project list reads without fetching each project. Returns the
+/// number of rows written.
+pub(crate) async fn set_project_name_on_links(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    name: &str,
+) -> Result<usize, Error> {
+    let project_pk = format!("PROJECT#{}", project_id);
+    let mut member_pks = Vec::new();
+    let mut last_key = None;
+    loop {
+        let result = client
+            .query()
+            .table_name(table_name)
+            .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
+            .expression_attribute_values(
+                ":pk",
+                aws_sdk_dynamodb::types::AttributeValue::S(project_pk.clone()),
+            )
+            .expression_attribute_values(
+                ":sk_prefix",
+                aws_sdk_dynamodb::types::AttributeValue::S("USER#".to_string()),
+            )
+            .projection_expression("SK")
+            .set_exclusive_start_key(last_key)
+            .send()
+            .await?;
+        member_pks.extend(
+            result
+                .items()
+                .iter()
+                .filter_map(|item| item.get("SK")?.as_s().ok().cloned()),
+        );
+        last_key = result.last_evaluated_key().cloned();
+        if last_key.is_none() {
+            break;
+        }
+    }
+
+    let mut written = 0;
+    for user_pk in member_pks {
+        for (pk, sk) in [
+            (project_pk.clone(), user_pk.clone()),
+            (user_pk.clone(), project_pk.clone()),
+        ] {
+            let result = client
+                .update_item()
+                .table_name(table_name)
+                .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+                .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(sk))
+                .update_expression("SET project_name = :name")
+                .condition_expression("attribute_exists(PK)")
+                .expression_attribute_values(
+                    ":name",
+                    aws_sdk_dynamodb::types::AttributeValue::S(name.to_string()),
+                )
+                .send()
+                .await;
+            match result {
+                Ok(_) => written += 1,
+                // Membership removed concurrently
+                Err(e)
+                    if e.as_service_error()
+                        .map(|se| se.is_conditional_check_failed_exception())
+                        .unwrap_or(false) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+    Ok(written)
+}
+
 /// Delete a project and all associated resources (blocks, images, annotations, classes)
 pub async fn delete_project(
     client: &DynamoClient,
@@ -571,34 +790,36 @@ pub async fn delete_project(
         for image_id in &image_ids {
             let image_pk = format!("IMAGE#{}", image_id);
 
-            let annotations_result = client
-                .query()
-                .table_name(table_name)
-                .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
-                .expression_attribute_values(
-                    ":pk",
-                    aws_sdk_dynamodb::types::AttributeValue::S(image_pk.clone()),
-                )
-                .expression_attribute_values(
-                    ":sk_prefix",
-                    aws_sdk_dynamodb::types::AttributeValue::S("ANNOTATION#".to_string()),
-                )
-                .send()
-                .await?;
-
-            for item in annotations_result.items() {
-                if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
-                    // Add IMAGE# -> ANNOTATION# record to delete
-                    let mut key = HashMap::new();
-                    key.insert(
-                        "PK".to_string(),
+            for sk_prefix in ["ANNOTATION#", crate::history::HISTORY_PREFIX] {
+                let annotations_result = client
+                    .query()
+                    .table_name(table_name)
+                    .key_condition_expression("PK = :pk AND begins_with(SK, :sk_prefix)")
+                    .expression_attribute_values(
+                        ":pk",
                         aws_sdk_dynamodb::types::AttributeValue::S(image_pk.clone()),
-                    );
-                    key.insert(
-                        "SK".to_string(),
-                        aws_sdk_dynamodb::types::AttributeValue::S(sk.to_string()),
-                    );
-                    all_delete_keys.push(key);
+                    )
+                    .expression_attribute_values(
+                        ":sk_prefix",
+                        aws_sdk_dynamodb::types::AttributeValue::S(sk_prefix.to_string()),
+                    )
+                    .send()
+                    .await?;
+
+                for item in annotations_result.items() {
+                    if let Some(sk) = item.get("SK").and_then(|v| v.as_s().ok()) {
+                        // Add IMAGE# -> ANNOTATION#/HISTORY# record to delete
+                        let mut key = HashMap::new();
+                        key.insert(
+                            "PK".to_string(),
+                            aws_sdk_dynamodb::types::AttributeValue::S(image_pk.clone()),
+                        );
+                        key.insert(
+                            "SK".to_string(),
+                            aws_sdk_dynamodb::types::AttributeValue::S(sk.to_string()),
+                        );
+                        all_delete_keys.push(key);
+                    }
                 }
             }
 
@@ -613,6 +834,13 @@ pub async fn delete_project(
                 aws_sdk_dynamodb::types::AttributeValue::S(image_pk),
             );
             all_delete_keys.push(key);
+
+            // Add IMAGE# -> METADATA location record to delete
+            let (location_pk, location_sk) = crate::images::location_key(image_id);
+            all_delete_keys.push(HashMap::from([
+                ("PK".to_string(), location_pk),
+                ("SK".to_string(), location_sk),
+            ]));
         }
 
         // Add PROJECT# -> BLOCK# record to delete
@@ -714,63 +942,22 @@ pub async fn delete_project(
         all_delete_keys.len()
     );
 
-    // Step 5: Batch delete all records (DynamoDB allows max 25 items per batch)
+    // Step 5: Batch delete all records, retrying unprocessed ones
     let batch_start = std::time::Instant::now();
-    for chunk in all_delete_keys.chunks(25) {
-        let delete_requests: Vec<_> = chunk
-            .iter()
-            .map(|key| {
-                aws_sdk_dynamodb::types::WriteRequest::builder()
-                    .delete_request(
-                        aws_sdk_dynamodb::types::DeleteRequest::builder()
-                            .set_key(Some(key.clone()))
-                            .build()
-                            .unwrap(),
-                    )
-                    .build()
-            })
-            .collect();
-
-        let mut attempts = 0;
-        let mut unprocessed = Some(delete_requests);
-
-        while let Some(requests) = unprocessed {
-            attempts += 1;
-            if attempts > 5 {
-                println!(
-                    "[DELETE] Warning: Max retry attempts reached, {} items may not be deleted",
-                    requests.len()
-                );
-                break;
-            }
-
-            let result = client
-                .batch_write_item()
-                .request_items(table_name, requests)
-                .send()
-                .await?;
-
-            unprocessed = result
-                .unprocessed_items()
-                .and_then(|items| items.get(table_name))
-                .map(|items| items.clone());
-
-            if unprocessed.is_some() {
-                println!(
-                    "[DELETE] Retrying {} unprocessed items (attempt {})",
-                    unprocessed.as_ref().unwrap().len(),
-                    attempts
-                );
-                tokio::time::sleep(tokio::time::Duration::from_millis(100 * attempts as u64)).await;
-            }
-        }
+    let total = all_delete_keys.len();
+    let failed = crate::annotations::batch_delete_keys(client, table_name, all_delete_keys).await?;
+    if !failed.is_empty() {
+        println!(
+            "[DELETE] Warning: Max retry attempts reached, {} items may not be deleted",
+            failed.len()
+        );
     }
 
     let batch_time = batch_start.elapsed();
     let total_time = start.elapsed();
     println!(
         "[DELETE] Cascade delete complete: {} records (batch: {:?}, total: {:?})",
-        all_delete_keys.len(),
+        total - failed.len(),
         batch_time,
         total_time
     );
@@ -778,9 +965,80 @@ pub async fn delete_project(
     // Step 6: Delete S3 objects under project prefix: projects/{project_id}/
     delete_project_s3_prefix(s3_client, project_id).await.ok();
 
+    // The cascade leaves the audit trail in place; this entry closes it
+    crate::audit::record(
+        client,
+        table_name,
+        AuditEntry::new(project_id, user_id, "deleted", "project", project_id),
+    )
+    .await;
+
     Ok(Response::builder()
         .status(StatusCode::NO_CONTENT)
         .header("Access-Control-Allow-Origin", "*")
         .body(Body::Empty)
         .map_err(Box::new)?)
 }
+
+/// Project with its settings, classes, blocks and images in one response,
+/// used by the annotation client on load (GET /projects/{id}/tree)
+pub async fn get_project_tree(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+) -> Result<Response<Body>, Error> {
+    let Some(project) = fetch_project(client, table_name, project_id).await? else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "Project not found"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?);
+    };
+
+    let classes = crate::classes::fetch_project_classes(client, table_name, project_id).await?;
+
+    let mut blocks = Vec::new();
+    for block in crate::blocks::fetch_project_blocks(client, table_name, project_id).await? {
+        let images = crate::images::fetch_block_images(client, table_name, &block.block_id).await?;
+        let mut node = serde_json::to_value(&block)?;
+        node["images"] = serde_json::to_value(&images)?;
+        blocks.push(node);
+    }
+
+    let tree = serde_json::json!({
+        "project": project,
+        "settings": project.settings,
+        "classes": classes,
+        "blocks": blocks,
+    });
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(tree.to_string().into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_default_geometry_type() {
+        let with_default = |geometry_type: &str| {
+            let mut settings = ProjectSettings::default();
+            settings.annotation_defaults.default_geometry_type = Some(geometry_type.to_string());
+            settings
+        };
+        for geometry_type in crate::geometry::KINDS {
+            assert_eq!(validate_settings(&with_default(geometry_type)), None);
+        }
+        assert!(validate_settings(&with_default("ellipse")).is_some());
+    }
+}
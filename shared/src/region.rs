@@ -0,0 +1,118 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::env;
+
+/// Home region of the original deployment
+const DEFAULT_REGION: &str = "ap-southeast-2";
+
+/// Region this lambda is running in (set by the Lambda runtime)
+pub fn current() -> String {
+    env::var("AWS_REGION").unwrap_or_else(|_| DEFAULT_REGION.to_string())
+}
+
+/// Region that normally serves traffic. A deployment in any other region is
+/// the passive standby: same Global Table, replicated bucket.
+pub fn primary() -> String {
+    env::var("PRIMARY_REGION").unwrap_or_else(|_| current())
+}
+
+/// "active" in the primary region, "passive" elsewhere
+pub fn role(current: &str, primary: &str) -> &'static str {
+    if current == primary {
+        "active"
+    } else {
+        "passive"
+    }
+}
+
+/// Image bucket for this region. Bucket names are global, so the replica in a
+/// second region has its own name, supplied through BUCKET_NAME (see
+/// `storage::S3Config`).
+pub fn bucket_name() -> &'static str {
+    &crate::storage::s3_config().bucket
+}
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub status: String, // ok | degraded
+    pub region: String,
+    pub primary_region: String,
+    pub role: String, // active | passive
+    pub table: String,
+    pub bucket: String,
+    pub table_reachable: bool,
+    pub bucket_reachable: bool,
+    pub maintenance: bool, // read-only mode, see `maintenance`
+}
+
+/// Report which region is serving and whether its table replica and bucket
+/// respond. Returns 503 when either is unreachable so failover health checks
+/// can key off the status code.
+pub async fn get_health(
+    dynamo_client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+) -> Result<Response<Body>, Error> {
+    let table_reachable = match dynamo_client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S("SETTINGS#ORG".to_string()))
+        .key("SK", AttributeValue::S("METADATA".to_string()))
+        .send()
+        .await
+    {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::error!("Health check: table {} unreachable: {}", table_name, e);
+            false
+        }
+    };
+    let bucket_reachable = match s3_client.head_bucket().bucket(bucket_name()).send().await {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::error!("Health check: bucket {} unreachable: {}", bucket_name(), e);
+            false
+        }
+    };
+
+    let region = current();
+    let primary_region = primary();
+    let healthy = table_reachable && bucket_reachable;
+    let health = Health {
+        status: if healthy { "ok" } else { "degraded" }.to_string(),
+        role: role(&region, &primary_region).to_string(),
+        region,
+        primary_region,
+        table: table_name.to_string(),
+        bucket: bucket_name().to_string(),
+        table_reachable,
+        bucket_reachable,
+        maintenance: crate::maintenance::retry_after().is_some(),
+    };
+
+    Ok(Response::builder()
+        .status(if healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        })
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Cache-Control", "no-store")
+        .body(serde_json::to_string(&health)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role() {
+        assert_eq!(role("ap-southeast-2", "ap-southeast-2"), "active");
+        assert_eq!(role("us-west-2", "ap-southeast-2"), "passive");
+    }
+}
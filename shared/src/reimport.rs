@@ -0,0 +1,889 @@
+use crate::types::{Annotation, Class, CreateAnnotationRequest, Geometry, Image, Keypoint, Point};
+use crate::{activity, annotations, blocks, classes, export, geometry, images};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+/// Supported values for `?format=`; detected from the body when omitted
+pub const REIMPORT_FORMATS: [&str; 2] = ["coco", "csv"];
+
+/// Coordinates closer than this count as unchanged, so a pixel/normalized
+/// round trip doesn't turn every annotation into an update
+const GEOMETRY_TOLERANCE: f64 = 1e-6;
+
+/// Query options of a re-import request
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReimportOptions<'a> {
+    pub format: Option<&'a str>,         // coco | csv
+    pub dry_run: Option<&'a str>,        // true: report the diff without writing
+    pub delete_missing: Option<&'a str>, // false: keep annotations missing from the file
+}
+
+/// An annotation as it appears in an edited export, in pixels
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub label: String, // identifies the row in the report
+    pub annotation_id: Option<String>,
+    pub image_id: String,
+    pub class_id: Option<String>,
+    pub class_name: Option<String>,
+    pub geometry: Option<Geometry>, // None: the format can't carry it; the stored geometry is kept
+}
+
+/// Everything read from an edited export
+#[derive(Debug, Default)]
+pub struct EditedExport {
+    pub images: Vec<String>, // image ids the file covers, in file order
+    pub sizes: HashMap<String, (f64, f64)>, // pixel sizes, where the file records them
+    pub rows: Vec<Row>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+impl EditedExport {
+    fn cover(&mut self, image_id: &str) {
+        if !self.images.iter().any(|i| i == image_id) {
+            self.images.push(image_id.to_string());
+        }
+    }
+
+    fn skip(&mut self, row: String, reason: impl Into<String>) {
+        self.skipped.push(SkippedRow {
+            row,
+            reason: reason.into(),
+        });
+    }
+}
+
+/// A row left out of the re-import
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SkippedRow {
+    pub row: String,
+    pub reason: String,
+}
+
+fn file_stem(file_name: &str) -> &str {
+    let name = file_name.rsplit(['/', '\\']).next().unwrap_or(file_name);
+    name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name)
+}
+
+fn numbers(value: &serde_json::Value) -> Option<Vec<f64>> {
+    value.as_array()?.iter().map(|v| v.as_f64()).collect()
+}
+
+/// Geometry of a COCO annotation. `doxle_geometry_type` (written by our
+/// exports) picks the shape; without it polygons win over keypoints over
+/// boxes. Shapes COCO can't carry (points, polylines, masks) come back as
+/// `None` so the stored geometry is kept.
+fn coco_geometry(
+    annotation: &serde_json::Value,
+    keypoint_names: &[String],
+) -> Result<Option<Geometry>, String> {
+    let p = |x: f64, y: f64| Point { x, y };
+    let bbox = || match numbers(&annotation["bbox"]).as_deref() {
+        Some(&[x, y, w, h]) => Ok(Geometry::BBox {
+            start: p(x, y),
+            end: p(x + w, y + h),
+        }),
+        _ => Err("bbox must be [x, y, width, height]".to_string()),
+    };
+    let polygon = annotation["segmentation"]
+        .as_array()
+        .and_then(|s| s.first())
+        .and_then(numbers)
+        .filter(|coords| coords.len() >= 6)
+        .map(|coords| Geometry::Polygon {
+            points: coords.chunks_exact(2).map(|c| p(c[0], c[1])).collect(),
+        });
+    let keypoints = || -> Result<Geometry, String> {
+        let flat = numbers(&annotation["keypoints"]).unwrap_or_default();
+        if !flat.len().is_multiple_of(3) || flat.len() / 3 > keypoint_names.len() {
+            return Err("keypoints don't match the category's keypoint names".to_string());
+        }
+        Ok(Geometry::Keypoints {
+            keypoints: flat
+                .chunks_exact(3)
+                .zip(keypoint_names)
+                .filter(|(k, _)| k[2] > 0.0)
+                .map(|(k, name)| Keypoint {
+                    name: name.clone(),
+                    x: k[0],
+                    y: k[1],
+                    visibility: k[2] as u8,
+                })
+                .collect(),
+        })
+    };
+    let has_keypoints = annotation["keypoints"]
+        .as_array()
+        .is_some_and(|k| !k.is_empty());
+
+    match annotation["doxle_geometry_type"].as_str() {
+        Some("bbox") => bbox().map(Some),
+        Some("polygon") => polygon
+            .map(Some)
+            .ok_or_else(|| "polygon needs a segmentation of at least 3 points".to_string()),
+        Some("keypoints") => keypoints().map(Some),
+        Some(_) => Ok(None),
+        None if polygon.is_some() => Ok(polygon),
+        None if has_keypoints => keypoints().map(Some),
+        None => bbox().map(Some),
+    }
+}
+
+/// Read a COCO file produced by `format=coco`. Images are matched by
+/// `doxle_image_id` (or a `{image_id}.{ext}` file name), annotations by
+/// `doxle_annotation_id`, classes by category name. Images listed without
+/// annotations are covered too, so emptying an image deletes its annotations.
+pub fn parse_coco(text: &str) -> Result<EditedExport, String> {
+    let doc: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("Invalid COCO JSON: {}", e))?;
+    let (Some(coco_images), Some(coco_annotations)) =
+        (doc["images"].as_array(), doc["annotations"].as_array())
+    else {
+        return Err("COCO JSON needs images and annotations arrays".to_string());
+    };
+
+    let mut edited = EditedExport::default();
+    let mut image_ids: HashMap<i64, String> = HashMap::new();
+    for image in coco_images {
+        let image_id = image["doxle_image_id"]
+            .as_str()
+            .or_else(|| image["file_name"].as_str().map(file_stem));
+        let (Some(coco_id), Some(image_id)) = (image["id"].as_i64(), image_id) else {
+            edited.skip(format!("image {}", image["id"]), "Image has no id");
+            continue;
+        };
+        if let (Some(w), Some(h)) = (image["width"].as_f64(), image["height"].as_f64()) {
+            edited.sizes.insert(image_id.to_string(), (w, h));
+        }
+        image_ids.insert(coco_id, image_id.to_string());
+        edited.cover(image_id);
+    }
+
+    let mut categories: HashMap<i64, (String, Vec<String>)> = HashMap::new();
+    for category in doc["categories"].as_array().into_iter().flatten() {
+        if let (Some(id), Some(name)) = (category["id"].as_i64(), category["name"].as_str()) {
+            let keypoints = category["keypoints"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|k| k.as_str().map(|s| s.to_string()))
+                .collect();
+            categories.insert(id, (name.to_string(), keypoints));
+        }
+    }
+
+    for annotation in coco_annotations {
+        let annotation_id = annotation["doxle_annotation_id"]
+            .as_str()
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_string());
+        let label = annotation_id
+            .clone()
+            .unwrap_or_else(|| format!("annotation {}", annotation["id"]));
+        let Some(image_id) = annotation["image_id"]
+            .as_i64()
+            .and_then(|id| image_ids.get(&id))
+        else {
+            edited.skip(label, "Unknown image_id");
+            continue;
+        };
+        let Some((name, keypoint_names)) = annotation["category_id"]
+            .as_i64()
+            .and_then(|id| categories.get(&id))
+        else {
+            edited.skip(label, "Unknown category_id");
+            continue;
+        };
+        let geometry = match coco_geometry(annotation, keypoint_names) {
+            Ok(geometry) => geometry,
+            Err(e) => {
+                edited.skip(label, e);
+                continue;
+            }
+        };
+        edited.rows.push(Row {
+            label,
+            annotation_id,
+            image_id: image_id.clone(),
+            class_id: None,
+            class_name: Some(name.clone()),
+            geometry,
+        });
+    }
+    Ok(edited)
+}
+
+/// Split CSV text into records (RFC 4180 quoting, CRLF or LF line ends)
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Read a CSV file produced by `format=csv`. The `geometry` column is
+/// authoritative; a blank `annotation_id` adds an annotation. `class_name`
+/// is matched first so renaming a row's class reassigns it, then `class_id`.
+pub fn parse_csv(text: &str) -> Result<EditedExport, String> {
+    let mut records = csv_records(text)?.into_iter();
+    let header = records.next().ok_or("CSV file is empty")?;
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let (Some(image_col), Some(geometry_col)) = (column("image_id"), column("geometry")) else {
+        return Err("CSV needs image_id and geometry columns".to_string());
+    };
+    let (id_col, class_id_col, class_name_col) = (
+        column("annotation_id"),
+        column("class_id"),
+        column("class_name"),
+    );
+
+    let mut edited = EditedExport::default();
+    for (i, record) in records.enumerate() {
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        let field = |col: Option<usize>| {
+            col.and_then(|c| record.get(c))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+                .map(|f| f.to_string())
+        };
+        let annotation_id = field(id_col);
+        // Header is line 1
+        let label = annotation_id
+            .clone()
+            .unwrap_or_else(|| format!("line {}", i + 2));
+        let Some(image_id) = field(Some(image_col)) else {
+            edited.skip(label, "Missing image_id");
+            continue;
+        };
+        edited.cover(&image_id);
+        let geometry = match field(Some(geometry_col)).map(|g| serde_json::from_str(&g)) {
+            Some(Ok(geometry)) => geometry,
+            Some(Err(e)) => {
+                edited.skip(label, format!("Invalid geometry: {}", e));
+                continue;
+            }
+            None => {
+                edited.skip(label, "Missing geometry");
+                continue;
+            }
+        };
+        edited.rows.push(Row {
+            label,
+            annotation_id,
+            image_id,
+            class_id: field(class_id_col),
+            class_name: field(class_name_col),
+            geometry: Some(geometry),
+        });
+    }
+    Ok(edited)
+}
+
+fn close_values(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    use serde_json::Value;
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_f64(), y.as_f64()) {
+            (Some(x), Some(y)) => (x - y).abs() <= GEOMETRY_TOLERANCE * x.abs().max(1.0),
+            _ => x == y,
+        },
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| close_values(x, y))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len()
+                && x.iter()
+                    .all(|(k, v)| y.get(k).is_some_and(|w| close_values(v, w)))
+        }
+        _ => a == b,
+    }
+}
+
+/// Whether two geometries match up to floating point noise
+pub fn same_geometry(a: &Geometry, b: &Geometry) -> bool {
+    match (serde_json::to_value(a), serde_json::to_value(b)) {
+        (Ok(a), Ok(b)) => close_values(&a, &b),
+        _ => false,
+    }
+}
+
+/// A row with its class resolved and geometry in project coordinates
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub label: String,
+    pub annotation_id: Option<String>,
+    pub class_id: String,
+    pub geometry: Option<Geometry>,
+}
+
+/// Writes that bring one image in line with the file
+#[derive(Debug, Default)]
+pub struct ImagePlan<'a> {
+    pub create: Vec<(String, Geometry)>,
+    pub update: Vec<(&'a Annotation, String, Geometry)>,
+    pub delete: Vec<&'a Annotation>,
+    pub unchanged: usize,
+}
+
+/// Diff one image's rows against its stored annotations. Rows whose id is
+/// stored are updated when class or geometry changed; rows without a known
+/// id are created; stored annotations the file dropped are deleted when
+/// `delete_missing` is set.
+pub fn plan_image<'a>(
+    existing: &'a [Annotation],
+    rows: Vec<Resolved>,
+    delete_missing: bool,
+    skipped: &mut Vec<SkippedRow>,
+) -> ImagePlan<'a> {
+    let stored: HashMap<&str, &Annotation> = existing
+        .iter()
+        .map(|a| (a.annotation_id.as_str(), a))
+        .collect();
+    let mut seen = HashSet::new();
+    let mut plan = ImagePlan::default();
+
+    for row in rows {
+        match row.annotation_id.as_deref().and_then(|id| stored.get(id)) {
+            Some(old) if !seen.insert(old.annotation_id.as_str()) => skipped.push(SkippedRow {
+                row: row.label,
+                reason: "Duplicate annotation id".to_string(),
+            }),
+            Some(old) => {
+                let geometry = row.geometry.unwrap_or_else(|| old.geometry.clone());
+                if old.class_id == row.class_id && same_geometry(&old.geometry, &geometry) {
+                    plan.unchanged += 1;
+                } else {
+                    plan.update.push((old, row.class_id, geometry));
+                }
+            }
+            None => match row.geometry {
+                Some(geometry) => plan.create.push((row.class_id, geometry)),
+                None => skipped.push(SkippedRow {
+                    row: row.label,
+                    reason: "This geometry type can't be created from COCO; use CSV".to_string(),
+                }),
+            },
+        }
+    }
+    if delete_missing {
+        plan.delete = existing
+            .iter()
+            .filter(|a| !seen.contains(a.annotation_id.as_str()))
+            .collect();
+    }
+    plan
+}
+
+/// Class for a row: by name first, then by id
+fn resolve_class(classes: &[Class], row: &Row) -> Option<String> {
+    row.class_name
+        .as_deref()
+        .and_then(|name| classes.iter().find(|c| c.name.eq_ignore_ascii_case(name)))
+        .or_else(|| {
+            row.class_id
+                .as_deref()
+                .and_then(|id| classes.iter().find(|c| c.class_id == id))
+        })
+        .map(|c| c.class_id.clone())
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ReimportReport {
+    pub format: String,
+    pub dry_run: bool,
+    pub images: usize, // images brought in line with the file
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+    pub unmatched_images: Vec<String>, // not in this project
+    pub unmapped_classes: Vec<String>,
+    pub capped_images: Vec<String>, // skipped: would exceed the per-image annotation cap
+    pub skipped: Vec<SkippedRow>,
+}
+
+fn bad_request(message: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({ "error": message }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+fn flag(value: Option<&str>, name: &str, default: bool) -> Result<bool, String> {
+    match value {
+        None => Ok(default),
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(_) => Err(format!("{} must be true or false", name)),
+    }
+}
+
+/// Re-import an edited COCO or CSV export (POST /projects/{id}/reimport
+/// ?format=coco|csv&dry_run=&delete_missing=). Annotations keep their ids:
+/// each image in the file is diffed against what is stored and brought in
+/// line with creates, updates and deletes. Images the file doesn't mention
+/// are left alone, so a block export only touches that block. With a
+/// filtered export (`classes=`, `created_after=`), pass `delete_missing=false`.
+pub async fn reimport_export(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    body: &[u8],
+    options: ReimportOptions<'_>,
+) -> Result<Response<Body>, Error> {
+    let text = String::from_utf8_lossy(body);
+    let text = text.trim_start_matches('\u{feff}');
+    let format = match options.format {
+        Some(format) if REIMPORT_FORMATS.contains(&format) => format,
+        Some(_) => {
+            return bad_request(&format!(
+                "format must be one of: {}",
+                REIMPORT_FORMATS.join(", ")
+            ))
+        }
+        None if text.trim_start().starts_with('{') => "coco",
+        None => "csv",
+    };
+    let (dry_run, delete_missing) = match (
+        flag(options.dry_run, "dry_run", false),
+        flag(options.delete_missing, "delete_missing", true),
+    ) {
+        (Ok(dry_run), Ok(delete_missing)) => (dry_run, delete_missing),
+        (Err(message), _) | (_, Err(message)) => return bad_request(&message),
+    };
+    let parsed = match format {
+        "coco" => parse_coco(text),
+        _ => parse_csv(text),
+    };
+    let mut edited = match parsed {
+        Ok(edited) => edited,
+        Err(message) => return bad_request(&message),
+    };
+
+    let Some(project) = crate::projects::fetch_project(client, table_name, project_id).await?
+    else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "Project not found"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?);
+    };
+    let normalized = crate::projects::coordinate_mode(Some(&project)) == "normalized";
+    let project_classes = classes::fetch_project_classes(client, table_name, project_id).await?;
+    let mut project_images: HashMap<String, Image> = HashMap::new();
+    for block in blocks::fetch_project_blocks(client, table_name, project_id).await? {
+        for image in images::fetch_block_images(client, table_name, &block.block_id).await? {
+            project_images.insert(image.image_id.clone(), image);
+        }
+    }
+    if !dry_run {
+        let touched: Vec<&str> = edited
+            .images
+            .iter()
+            .filter_map(|image_id| project_images.get(image_id))
+            .map(|image| image.block_id.as_str())
+            .collect();
+        if let Some(response) =
+            blocks::check_blocks_assignee(client, table_name, project_id, &touched, user_id).await?
+        {
+            return Ok(response);
+        }
+    }
+
+    let mut rows_by_image: HashMap<String, Vec<Row>> = HashMap::new();
+    for row in std::mem::take(&mut edited.rows) {
+        rows_by_image
+            .entry(row.image_id.clone())
+            .or_default()
+            .push(row);
+    }
+    let mut report = ReimportReport {
+        format: format.to_string(),
+        dry_run,
+        skipped: std::mem::take(&mut edited.skipped),
+        ..Default::default()
+    };
+    let annotation_cap = annotations::max_annotations_per_image();
+
+    for image_id in &edited.images {
+        let rows = rows_by_image.remove(image_id).unwrap_or_default();
+        let Some(image) = project_images.get(image_id) else {
+            report.unmatched_images.push(image_id.clone());
+            continue;
+        };
+        // Exports are in pixels; normalized projects store 0-1
+        let size = match edited.sizes.get(image_id) {
+            _ if !normalized => None,
+            Some(&size) => Some(size),
+            None => match export::image_dimensions(s3_client, image).await {
+                Ok((w, h)) => Some((w as f64, h as f64)),
+                Err(e) => {
+                    for row in rows {
+                        report.skipped.push(SkippedRow {
+                            row: row.label,
+                            reason: format!("Image size unavailable: {}", e),
+                        });
+                    }
+                    continue;
+                }
+            },
+        };
+
+        let mut resolved = Vec::new();
+        for row in rows {
+            let Some(class_id) = resolve_class(&project_classes, &row) else {
+                let class = row
+                    .class_name
+                    .clone()
+                    .or(row.class_id.clone())
+                    .unwrap_or_default();
+                if !report.unmapped_classes.contains(&class) {
+                    report.unmapped_classes.push(class);
+                }
+                report.skipped.push(SkippedRow {
+                    row: row.label,
+                    reason: "Unknown class".to_string(),
+                });
+                continue;
+            };
+            let mut geometry = row.geometry;
+            if let Some(g) = geometry.as_mut() {
+                let converted = match size {
+                    Some((w, h)) if w > 0.0 && h > 0.0 => geometry::scale(g, 1.0 / w, 1.0 / h),
+                    Some(_) => Err("Image has no width/height".to_string()),
+                    None => Ok(()),
+                }
+                .and_then(|_| geometry::validate(g));
+                if let Err(e) = converted {
+                    report.skipped.push(SkippedRow {
+                        row: row.label,
+                        reason: e,
+                    });
+                    continue;
+                }
+            }
+            resolved.push(Resolved {
+                label: row.label,
+                annotation_id: row.annotation_id,
+                class_id,
+                geometry,
+            });
+        }
+
+        let existing = annotations::fetch_image_annotations(client, table_name, image_id).await?;
+        let plan = plan_image(&existing, resolved, delete_missing, &mut report.skipped);
+        let remaining = existing.len() - plan.delete.len();
+        if let Err(e) =
+            annotations::check_annotation_cap(remaining, plan.create.len(), annotation_cap)
+        {
+            tracing::warn!("Re-import: skipping image {}: {}", image_id, e);
+            report.capped_images.push(image_id.clone());
+            continue;
+        }
+        report.images += 1;
+        report.created += plan.create.len();
+        report.updated += plan.update.len();
+        report.deleted += plan.delete.len();
+        report.unchanged += plan.unchanged;
+        if dry_run {
+            continue;
+        }
+
+        for old in plan.delete {
+            annotations::remove_annotation(client, table_name, user_id, project_id, old).await?;
+        }
+        for (old, class_id, geometry) in plan.update {
+            annotations::replace_annotation(
+                client, table_name, user_id, project_id, old, &class_id, &geometry,
+            )
+            .await?;
+        }
+        let location = images::ImageLocation {
+            project_id: project_id.to_string(),
+            block_id: image.block_id.clone(),
+        };
+        for (class_id, geometry) in plan.create {
+            let req = CreateAnnotationRequest {
+                class_id,
+                geometry,
+                tags: Vec::new(),
+                annotation_id: None,
+                source: None,
+                confidence: None,
+                z_index: None,
+                attributes: Default::default(),
+            };
+            annotations::put_annotation(client, table_name, user_id, image_id, &location, req)
+                .await?;
+        }
+    }
+
+    if !dry_run {
+        activity::record_activity(client, table_name, project_id, "created", report.created).await;
+        activity::record_activity(client, table_name, project_id, "updated", report.updated).await;
+        activity::record_activity(client, table_name, project_id, "deleted", report.deleted).await;
+    }
+    tracing::info!(
+        "Re-import ({}) into project {}{}: {} created, {} updated, {} deleted, {} unchanged",
+        format,
+        project_id,
+        if dry_run { " (dry run)" } else { "" },
+        report.created,
+        report.updated,
+        report.deleted,
+        report.unchanged
+    );
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&report)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotation(id: &str, class_id: &str, geometry: Geometry) -> Annotation {
+        Annotation {
+            annotation_id: id.to_string(),
+            image_id: "img".to_string(),
+            project_id: "p1".to_string(),
+            block_id: "b1".to_string(),
+            class_id: class_id.to_string(),
+            area: geometry::area(&geometry),
+            bounding_box: geometry::bounding_box(&geometry),
+            geometry,
+            created_by: "USER#u1".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            updated_at: None,
+            version: 1,
+            tags: Vec::new(),
+            review_status: "pending".to_string(),
+            reviewed_by: None,
+            reviewed_at: None,
+            review_reason: None,
+            source: "human".to_string(),
+            confidence: None,
+            group_id: None,
+            z_index: 0,
+            attributes: Default::default(),
+        }
+    }
+
+    fn bbox(x0: f64, y0: f64, x1: f64, y1: f64) -> Geometry {
+        Geometry::BBox {
+            start: Point { x: x0, y: y0 },
+            end: Point { x: x1, y: y1 },
+        }
+    }
+
+    #[test]
+    fn test_csv_records() {
+        let records = csv_records("a,b\r\n\"x, \"\"y\"\"\",\"multi\nline\"\nlast,").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                vec!["a", "b"],
+                vec!["x, \"y\"", "multi\nline"],
+                vec!["last", ""]
+            ]
+        );
+        assert!(csv_records("a,\"open").is_err());
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let csv = "annotation_id,image_id,block_id,class_id,class_name,geometry\n\
+            a1,img,b1,c-door,door,\"{\"\"type\"\":\"\"bbox\"\",\"\"start\"\":{\"\"x\"\":1,\"\"y\"\":2},\"\"end\"\":{\"\"x\"\":3,\"\"y\"\":4}}\"\n\
+            ,img,b1,,wall,\"{\"\"type\"\":\"\"point\"\",\"\"point\"\":{\"\"x\"\":5,\"\"y\"\":6}}\"\n\
+            a3,img2,b1,c-door,door,not json\n";
+        let edited = parse_csv(csv).unwrap();
+        assert_eq!(edited.images, vec!["img", "img2"]);
+        assert_eq!(edited.rows.len(), 2);
+        assert_eq!(edited.rows[0].annotation_id.as_deref(), Some("a1"));
+        assert_eq!(edited.rows[1].annotation_id, None);
+        assert_eq!(edited.rows[1].label, "line 3");
+        assert_eq!(edited.rows[1].class_name.as_deref(), Some("wall"));
+        assert_eq!(edited.skipped[0].row, "a3");
+        assert!(parse_csv("annotation_id,class_id\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_coco() {
+        let coco = serde_json::json!({
+            "images": [
+                {"id": 1, "file_name": "img.jpg", "width": 200, "height": 100, "doxle_image_id": "img"},
+                {"id": 2, "file_name": "empty.png", "width": 10, "height": 10},
+            ],
+            "categories": [
+                {"id": 1, "name": "door"},
+                {"id": 2, "name": "pose", "keypoints": ["head", "neck"]},
+            ],
+            "annotations": [
+                {"id": 1, "image_id": 1, "category_id": 1, "bbox": [10.0, 20.0, 20.0, 40.0],
+                 "segmentation": [[10.0, 20.0, 30.0, 20.0, 30.0, 60.0, 10.0, 60.0]],
+                 "doxle_annotation_id": "a1", "doxle_geometry_type": "bbox"},
+                {"id": 2, "image_id": 1, "category_id": 1, "bbox": [0.0, 0.0, 1.0, 1.0],
+                 "segmentation": [[0.0, 0.0, 4.0, 0.0, 4.0, 4.0]]},
+                {"id": 3, "image_id": 1, "category_id": 2, "bbox": [0.0, 0.0, 1.0, 1.0],
+                 "keypoints": [4.0, 1.0, 2.0, 0.0, 0.0, 0.0], "doxle_annotation_id": "a3",
+                 "doxle_geometry_type": "keypoints"},
+                {"id": 4, "image_id": 1, "category_id": 1, "bbox": [0.0, 0.0, 1.0, 1.0],
+                 "doxle_annotation_id": "a4", "doxle_geometry_type": "polyline"},
+                {"id": 5, "image_id": 9, "category_id": 1, "bbox": [0.0, 0.0, 1.0, 1.0]},
+            ],
+        });
+        let edited = parse_coco(&coco.to_string()).unwrap();
+
+        // The second image has no annotations left but is still covered
+        assert_eq!(edited.images, vec!["img", "empty"]);
+        assert_eq!(edited.sizes.get("img"), Some(&(200.0, 100.0)));
+        assert_eq!(edited.rows.len(), 4);
+        assert!(same_geometry(
+            edited.rows[0].geometry.as_ref().unwrap(),
+            &bbox(10.0, 20.0, 30.0, 60.0)
+        ));
+        assert!(matches!(
+            edited.rows[1].geometry,
+            Some(Geometry::Polygon { ref points }) if points.len() == 3
+        ));
+        match &edited.rows[2].geometry {
+            Some(Geometry::Keypoints { keypoints }) => {
+                assert_eq!(keypoints.len(), 1);
+                assert_eq!(keypoints[0].name, "head");
+            }
+            other => panic!("expected keypoints, got {:?}", other),
+        }
+        // COCO can't carry polylines: keep what is stored
+        assert!(edited.rows[3].geometry.is_none());
+        assert_eq!(edited.skipped[0].reason, "Unknown image_id");
+    }
+
+    #[test]
+    fn test_plan_image() {
+        let existing = vec![
+            annotation("a1", "c-door", bbox(0.1, 0.2, 0.3, 0.4)),
+            annotation("a2", "c-door", bbox(0.0, 0.0, 0.5, 0.5)),
+            annotation("a3", "c-wall", bbox(0.0, 0.0, 1.0, 1.0)),
+            annotation("a4", "c-wall", bbox(0.0, 0.0, 1.0, 1.0)),
+        ];
+        let row = |id: Option<&str>, class_id: &str, geometry: Option<Geometry>| Resolved {
+            label: id.unwrap_or("line 9").to_string(),
+            annotation_id: id.map(|i| i.to_string()),
+            class_id: class_id.to_string(),
+            geometry,
+        };
+        let rows = vec![
+            // Round-trip noise only
+            row(Some("a1"), "c-door", Some(bbox(0.1 + 1e-9, 0.2, 0.3, 0.4))),
+            // Reclassified
+            row(Some("a2"), "c-wall", Some(bbox(0.0, 0.0, 0.5, 0.5))),
+            // Geometry kept, class unchanged
+            row(Some("a3"), "c-wall", None),
+            row(Some("a3"), "c-wall", None),
+            row(None, "c-door", Some(bbox(0.6, 0.6, 0.7, 0.7))),
+            row(Some("gone"), "c-door", Some(bbox(0.6, 0.6, 0.8, 0.8))),
+            row(None, "c-door", None),
+        ];
+        let mut skipped = Vec::new();
+        let plan = plan_image(&existing, rows.clone(), true, &mut skipped);
+        assert_eq!(plan.unchanged, 2);
+        assert_eq!(plan.update.len(), 1);
+        assert_eq!(plan.update[0].0.annotation_id, "a2");
+        assert_eq!(plan.update[0].1, "c-wall");
+        assert_eq!(plan.create.len(), 2);
+        let deleted: Vec<&str> = plan
+            .delete
+            .iter()
+            .map(|a| a.annotation_id.as_str())
+            .collect();
+        assert_eq!(deleted, vec!["a4"]);
+        let reasons: Vec<&str> = skipped.iter().map(|s| s.reason.as_str()).collect();
+        assert_eq!(reasons.len(), 2);
+        assert_eq!(reasons[0], "Duplicate annotation id");
+
+        let plan = plan_image(&existing, rows, false, &mut Vec::new());
+        assert!(plan.delete.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reimport_requires_assignee() {
+        let (client, calls) = crate::test_util::fake_dynamo(vec![
+            serde_json::json!({"PK": {"S": "PROJECT#p"}, "SK": {"S": "PROJECT#p"}, "name": {"S": "Site"}}),
+            crate::test_util::assigned_block("p", "b1", "u2"),
+            serde_json::json!({"PK": {"S": "BLOCK#b1"}, "SK": {"S": "IMAGE#img"}, "url": {"S": "https://cdn/img.jpg"}}),
+        ]);
+        let csv = "annotation_id,image_id,block_id,class_id,class_name,geometry\n\
+            ,img,b1,,wall,\"{\"\"type\"\":\"\"point\"\",\"\"point\"\":{\"\"x\"\":5,\"\"y\"\":6}}\"\n";
+        let options = ReimportOptions {
+            format: Some("csv"),
+            ..Default::default()
+        };
+        let s3_client = crate::test_util::offline_s3();
+        let response = reimport_export(
+            &client,
+            &s3_client,
+            "table",
+            "u1",
+            "p",
+            csv.as_bytes(),
+            options,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(calls
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|call| call == "GetItem" || call == "Query"));
+    }
+}
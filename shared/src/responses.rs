@@ -0,0 +1,11 @@
+use lambda_http::{http::StatusCode, Body, Error, Response};
+
+/// A JSON response, with the CORS header every endpoint sends
+pub fn json_response(status: StatusCode, body: serde_json::Value) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(body.to_string().into())
+        .map_err(Box::new)?)
+}
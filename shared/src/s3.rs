@@ -1,87 +1,10 @@
 use lambda_http::{Body, Error, Response, http::StatusCode};
 use aws_sdk_s3::Client as S3Client;
-use aws_sdk_s3::primitives::ByteStream;
+use crate::region;
 
-const BUCKET_NAME: &str = "doxle-annotations";
-const _MULTIPART_THRESHOLD: usize = 5 * 1024 * 1024; // 5MB
-const _CHUNK_SIZE: usize = 5 * 1024 * 1024; // 5MB chunks
-
-#[derive(serde::Deserialize)]
-pub struct UploadImageRequest {
-    pub project_id: String,
-    pub block_id: String,
-    pub file_name: String,
-    pub content_type: String,
-    pub file_data: String, // base64 encoded
-}
-
-#[derive(serde::Serialize)]
-pub struct UploadImageResponse {
-    pub image_id: String,
-    pub url: String,
-}
-
-/// Upload an image to S3 and return the URL
-pub async fn upload_image(
-    s3_client: &S3Client,
-    request: UploadImageRequest,
-) -> Result<Response<Body>, Error> {
-    // Generate unique image ID
-    let image_id = uuid::Uuid::new_v4().to_string();
-    
-    // Get file extension from filename
-    let extension = request.file_name
-        .split('.')
-        .last()
-        .unwrap_or("jpg");
-    
-    // S3 key: projects/{project_id}/blocks/{block_id}/{image_id}.{ext}
-    let s3_key = format!(
-        "projects/{}/blocks/{}/{}.{}",
-        request.project_id,
-        request.block_id,
-        image_id,
-        extension
-    );
-    
-    // Decode base64 file data
-    use base64::Engine;
-    let file_bytes = base64::engine::general_purpose::STANDARD
-        .decode(&request.file_data)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
-    
-    // Upload to S3
-    s3_client
-        .put_object()
-        .bucket(BUCKET_NAME)
-        .key(&s3_key)
-        .body(ByteStream::from(file_bytes))
-        .content_type(&request.content_type)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to upload to S3: {}", e))?;
-    
-    // Generate public URL
-    let url = format!(
-        "https://{}.s3.amazonaws.com/{}",
-        BUCKET_NAME,
-        s3_key
-    );
-    
-    let response = UploadImageResponse {
-        image_id: image_id.clone(),
-        url,
-    };
-    
-    Ok(Response::builder()
-        .status(StatusCode::CREATED)
-        .header("Content-Type", "application/json")
-        .header("Access-Control-Allow-Origin", "*")
-        .body(serde_json::to_string(&response)?.into())
-        .map_err(Box::new)?)
-}
-
-/// Generate a presigned URL for direct upload (alternative approach)
+/// Generate a presigned URL for a single PUT straight to S3. Uploads never
+/// pass through the lambda, whose 6MB payload limit they would hit; large
+/// files use the multipart flow in `s3_multipart`.
 pub async fn generate_presigned_upload_url(
     s3_client: &S3Client,
     project_id: String,
@@ -93,21 +16,15 @@ pub async fn generate_presigned_upload_url(
     
     let extension = file_name
         .split('.')
-        .last()
+        .next_back()
         .unwrap_or("jpg");
     
-    let s3_key = format!(
-        "projects/{}/blocks/{}/{}.{}",
-        project_id,
-        block_id,
-        image_id,
-        extension
-    );
+    let s3_key = crate::storage::upload_key(&project_id, &block_id, &image_id, extension);
     
     // Generate presigned URL (expires in 1 hour)
     let presigned_request = s3_client
         .put_object()
-        .bucket(BUCKET_NAME)
+        .bucket(region::bucket_name())
         .key(&s3_key)
         .content_type(&content_type)
         .presigned(
@@ -1,10 +1,13 @@
 use lambda_http::{Body, Error, Response, http::StatusCode};
+use aws_sdk_s3::error::ProvideErrorMetadata;
 use aws_sdk_s3::Client as S3Client;
 use serde::{Deserialize, Serialize};
-use crate::types::{ImageMetadata, ImageLevel};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use crate::types::{Image, ImageMetadata, ImageLevel, TileSource, UploadLimits};
+use crate::images::{ImageLocation, ProcessedImage, UploadedImage};
 use crate::image_processing;
+use crate::region;
 
-const BUCKET_NAME: &str = "doxle-annotations";
 const MULTIPART_THRESHOLD: usize = 5 * 1024 * 1024; // 5MB
 
 #[derive(Deserialize)]
@@ -25,6 +28,33 @@ pub struct InitiateUploadResponse {
     pub extension: String,
 }
 
+/// One file of an upload manifest
+#[derive(Deserialize)]
+pub struct ManifestFile {
+    pub file_name: String,
+    pub content_type: String,
+    pub file_size: usize,
+}
+
+#[derive(Deserialize)]
+pub struct InitiateBatchRequest {
+    pub project_id: String,
+    pub block_id: String,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize)]
+pub struct BatchUpload {
+    pub file_name: String, // as given in the manifest
+    #[serde(flatten)]
+    pub upload: InitiateUploadResponse,
+}
+
+#[derive(Serialize)]
+pub struct InitiateBatchResponse {
+    pub uploads: Vec<BatchUpload>,
+}
+
 #[derive(Serialize)]
 pub struct UploadPart {
     pub part_number: i32,
@@ -39,6 +69,25 @@ pub struct CompleteMultipartRequest {
     pub upload_id: String,
     pub extension: String,
     pub parts: Vec<CompletedPart>,
+    #[serde(default)]
+    pub file_name: Option<String>, // original upload name, kept on the image
+    #[serde(default)]
+    pub content_type: Option<String>, // as given to initiate; guessed from the extension otherwise
+}
+
+/// A part S3 already holds for a multipart upload
+#[derive(Serialize)]
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: i64,
+}
+
+#[derive(Serialize)]
+pub struct UploadPartsResponse {
+    pub upload_id: String,
+    pub parts: Vec<UploadedPart>,
+    pub missing: Vec<UploadPart>, // presigned again, for the parts still to send
 }
 
 #[derive(Deserialize, Serialize)]
@@ -51,6 +100,7 @@ pub struct CompletedPart {
 pub struct UploadCompleteResponse {
     pub image_id: String,
     pub url: String,
+    pub image: Image, // the image recorded for the upload
 }
 
 /// Initiate upload - returns single or multipart presigned URLs
@@ -58,34 +108,77 @@ pub async fn initiate_upload(
     s3_client: &S3Client,
     request: InitiateUploadRequest,
 ) -> Result<Response<Body>, Error> {
+    let response = presign_upload(s3_client, &request.project_id, &request.block_id, &request.file_name, &request.content_type, request.file_size).await?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&response)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Presign the upload of one part of a multipart upload
+async fn presign_part(s3_client: &S3Client, s3_key: &str, upload_id: &str, part_number: i32) -> Result<UploadPart, Error> {
+    let presigned = s3_client
+        .upload_part()
+        .bucket(region::bucket_name())
+        .key(s3_key)
+        .upload_id(upload_id)
+        .part_number(part_number)
+        .presigned(
+            aws_sdk_s3::presigning::PresigningConfig::expires_in(
+                std::time::Duration::from_secs(3600)
+            )?
+        )
+        .await
+        .map_err(|e| format!("Failed to generate presigned URL for part {}: {}", part_number, e))?;
+    Ok(UploadPart {
+        part_number,
+        upload_url: presigned.uri().to_string(),
+    })
+}
+
+/// Presigned URLs uploading one file: a single PUT, or one per part for
+/// files at or over the multipart threshold
+fn url_count(file_size: usize) -> usize {
+    if file_size >= MULTIPART_THRESHOLD {
+        file_size.div_ceil(MULTIPART_THRESHOLD)
+    } else {
+        1
+    }
+}
+
+/// Start one file's upload and presign its URLs
+async fn presign_upload(
+    s3_client: &S3Client,
+    project_id: &str,
+    block_id: &str,
+    file_name: &str,
+    content_type: &str,
+    file_size: usize,
+) -> Result<InitiateUploadResponse, Error> {
     let image_id = uuid::Uuid::new_v4().to_string();
     
-    let extension = request.file_name
+    let extension = file_name
         .split('.')
-        .last()
+        .next_back()
         .unwrap_or("jpg")
         .to_string();
     
-    let s3_key = format!(
-        "projects/{}/blocks/{}/{}.{}",
-        request.project_id,
-        request.block_id,
-        image_id,
-        extension
-    );
+    let s3_key = crate::storage::upload_key(project_id, block_id, &image_id, &extension);
     
-    let is_multipart = request.file_size >= MULTIPART_THRESHOLD;
+    let is_multipart = file_size >= MULTIPART_THRESHOLD;
     
     if is_multipart {
         // Multipart upload for files >= 5MB
-        let num_parts = (request.file_size as f64 / MULTIPART_THRESHOLD as f64).ceil() as i32;
+        let num_parts = url_count(file_size) as i32;
         
         // Initiate multipart upload
         let create_result = s3_client
             .create_multipart_upload()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .key(&s3_key)
-            .content_type(&request.content_type)
+            .content_type(content_type)
             .send()
             .await
             .map_err(|e| format!("Failed to initiate multipart upload: {}", e))?;
@@ -98,48 +191,23 @@ pub async fn initiate_upload(
         let mut upload_parts = Vec::new();
         
         for part_number in 1..=num_parts {
-            let presigned = s3_client
-                .upload_part()
-                .bucket(BUCKET_NAME)
-                .key(&s3_key)
-                .upload_id(&upload_id)
-                .part_number(part_number)
-                .presigned(
-                    aws_sdk_s3::presigning::PresigningConfig::expires_in(
-                        std::time::Duration::from_secs(3600)
-                    )?
-                )
-                .await
-                .map_err(|e| format!("Failed to generate presigned URL for part {}: {}", part_number, e))?;
-            
-            upload_parts.push(UploadPart {
-                part_number,
-                upload_url: presigned.uri().to_string(),
-            });
+            upload_parts.push(presign_part(s3_client, &s3_key, &upload_id, part_number).await?);
         }
         
-        let response = InitiateUploadResponse {
-            image_id: image_id.clone(),
+        Ok(InitiateUploadResponse {
+            image_id,
             upload_id: Some(upload_id),
             upload_urls: upload_parts,
             is_multipart: true,
-            extension: extension.clone(),
-        };
-        
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header("Content-Type", "application/json")
-            .header("Access-Control-Allow-Origin", "*")
-            .body(serde_json::to_string(&response)?.into())
-            .map_err(Box::new)?)
-            
+            extension,
+        })
     } else {
         // Single part upload for files < 5MB
         let presigned = s3_client
             .put_object()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .key(&s3_key)
-            .content_type(&request.content_type)
+            .content_type(content_type)
             .presigned(
                 aws_sdk_s3::presigning::PresigningConfig::expires_in(
                     std::time::Duration::from_secs(3600)
@@ -148,38 +216,247 @@ pub async fn initiate_upload(
             .await
             .map_err(|e| format!("Failed to generate presigned URL: {}", e))?;
         
-        let response = InitiateUploadResponse {
-            image_id: image_id.clone(),
+        Ok(InitiateUploadResponse {
+            image_id,
             upload_id: None,
             upload_urls: vec![UploadPart {
                 part_number: 1,
                 upload_url: presigned.uri().to_string(),
             }],
             is_multipart: false,
-            extension: extension.clone(),
+            extension,
+        })
+    }
+}
+
+/// Most files one batch initiate takes
+pub const MAX_BATCH_FILES: usize = 500;
+
+/// Most presigned URLs one batch response carries, keeping it well inside
+/// Lambda's 6MB response limit
+const MAX_BATCH_URLS: usize = 2000;
+
+/// Check a manifest fits in one batch; the error says why not
+fn check_manifest(files: &[ManifestFile]) -> Result<(), String> {
+    if files.is_empty() {
+        return Err("files must not be empty".to_string());
+    }
+    if files.len() > MAX_BATCH_FILES {
+        return Err(format!("A batch can initiate at most {} uploads", MAX_BATCH_FILES));
+    }
+    let urls: usize = files.iter().map(|f| url_count(f.file_size)).sum();
+    if urls > MAX_BATCH_URLS {
+        return Err(format!(
+            "The manifest needs {} upload URLs, more than the {} one batch can return; split it",
+            urls, MAX_BATCH_URLS
+        ));
+    }
+    Ok(())
+}
+
+/// Abandon the multipart uploads a failed batch already started
+async fn abort_started(s3_client: &S3Client, project_id: &str, block_id: &str, started: &[BatchUpload]) {
+    for upload in started {
+        let Some(upload_id) = &upload.upload.upload_id else {
+            continue;
         };
-        
-        Ok(Response::builder()
-            .status(StatusCode::OK)
+        let key = crate::storage::upload_key(project_id, block_id, &upload.upload.image_id, &upload.upload.extension);
+        if let Err(e) = s3_client
+            .abort_multipart_upload()
+            .bucket(region::bucket_name())
+            .key(&key)
+            .upload_id(upload_id)
+            .send()
+            .await
+        {
+            tracing::warn!("Failed to abort multipart upload of {}: {}", key, e);
+        }
+    }
+}
+
+/// Initiate the uploads of a whole manifest in one call. Each file gets the
+/// same single or multipart URLs `initiate_upload` would give it, in
+/// manifest order.
+pub async fn initiate_batch_upload(
+    s3_client: &S3Client,
+    request: InitiateBatchRequest,
+) -> Result<Response<Body>, Error> {
+    if let Err(message) = check_manifest(&request.files) {
+        return Ok(Response::builder()
+            .status(StatusCode::BAD_REQUEST)
             .header("Content-Type", "application/json")
             .header("Access-Control-Allow-Origin", "*")
-            .body(serde_json::to_string(&response)?.into())
-            .map_err(Box::new)?)
+            .body(serde_json::json!({"error": message}).to_string().into())
+            .map_err(Box::new)?);
+    }
+
+    let mut uploads = Vec::with_capacity(request.files.len());
+    for file in request.files {
+        match presign_upload(s3_client, &request.project_id, &request.block_id, &file.file_name, &file.content_type, file.file_size).await {
+            Ok(upload) => uploads.push(BatchUpload { file_name: file.file_name, upload }),
+            Err(e) => {
+                abort_started(s3_client, &request.project_id, &request.block_id, &uploads).await;
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&InitiateBatchResponse { uploads })?.into())
+        .map_err(Box::new)?)
+}
+
+/// Key of the full resolution object once processing is done: pyramids and
+/// images turned upright move it into the image's folder
+fn full_resolution_key(flat_key: &str, image_id: &str, metadata: Option<&ImageMetadata>) -> String {
+    let (folder, file) = flat_key.rsplit_once('/').unwrap_or(("", flat_key));
+    match metadata.and_then(|m| m.levels.first()) {
+        Some(full) if full.path != file => format!("{}/{}/{}", folder, image_id, full.path),
+        _ => flat_key.to_string(),
+    }
+}
+
+/// Content type of an upload from its file extension, for clients that don't
+/// send one
+pub(crate) fn content_type_for(extension: &str) -> Option<&'static str> {
+    match extension.to_ascii_lowercase().as_str() {
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "png" => Some("image/png"),
+        "webp" => Some("image/webp"),
+        "tif" | "tiff" => Some("image/tiff"),
+        "heic" => Some("image/heic"),
+        "heif" => Some("image/heif"),
+        "gif" => Some("image/gif"),
+        "bmp" => Some("image/bmp"),
+        _ => None,
     }
 }
 
-/// Complete multipart upload
+/// Check an upload from its first bytes and size against what its name and
+/// content type claim and the project's limits; the error says why not
+fn check_upload(head: &[u8], extension: &str, content_type: Option<&str>, file_size: u64, limits: &UploadLimits) -> Result<(), String> {
+    let Some(format) = image_processing::sniff_format(head) else {
+        return Err("File is not an image".to_string());
+    };
+    if !image_processing::extension_matches(extension, format) {
+        return Err(format!("File is a {} image, not .{}", format, extension));
+    }
+    // Browsers send octet-stream for types they don't know, HEIC among them
+    if let Some(content_type) = content_type.filter(|t| !t.is_empty() && *t != "application/octet-stream") {
+        if !content_type.starts_with("image/") {
+            return Err(format!("Content type {} is not an image", content_type));
+        }
+    }
+    if let Some(max) = limits.max_file_size.filter(|max| file_size > *max) {
+        return Err(format!("File is {} bytes, over the project's {} byte limit", file_size, max));
+    }
+    // TIFF and HEIC may keep their size past the first bytes; the worker
+    // fails those that don't open
+    let dimensions = image_processing::header_dimensions(head);
+    if dimensions.is_none() && !matches!(format, "tiff" | "heic") {
+        return Err(format!("File is not a readable {} image", format));
+    }
+    if let Some((width, height)) = dimensions {
+        if limits.max_width.is_some_and(|max| width > max) || limits.max_height.is_some_and(|max| height > max) {
+            return Err(format!(
+                "Image is {}x{}, over the project's {}x{} limit",
+                width,
+                height,
+                limits.max_width.map(|w| w.to_string()).unwrap_or_else(|| "any".to_string()),
+                limits.max_height.map(|h| h.to_string()).unwrap_or_else(|| "any".to_string()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Validate a completed upload against its project, returning its size, or
+/// deleting it when it's rejected and returning the response to send
+async fn validate_upload(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    request: &CompleteMultipartRequest,
+    s3_key: &str,
+) -> Result<Result<u64, Response<Body>>, Error> {
+    let head = match s3_client.head_object().bucket(region::bucket_name()).key(s3_key).send().await {
+        Ok(head) => head,
+        Err(e) if e.as_service_error().map(|se| se.is_not_found()).unwrap_or(false) => {
+            return Ok(Err(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(serde_json::json!({"error": "Upload not found"}).to_string().into())
+                .map_err(Box::new)?));
+        }
+        Err(e) => return Err(format!("Failed to read upload: {}", e).into()),
+    };
+    let file_size = head.content_length().unwrap_or_default().max(0) as u64;
+    let start = s3_client
+        .get_object()
+        .bucket(region::bucket_name())
+        .key(s3_key)
+        .range(format!("bytes=0-{}", image_processing::SNIFF_BYTES - 1))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to read upload: {}", e))?
+        .body
+        .collect()
+        .await
+        .map_err(|e| format!("Failed to read upload: {}", e))?
+        .into_bytes();
+    let limits = crate::projects::fetch_project(client, table_name, &request.project_id)
+        .await?
+        .map(|project| project.settings.uploads)
+        .unwrap_or_default();
+
+    let content_type = request.content_type.as_deref().or(head.content_type());
+    let Err(message) = check_upload(&start, &request.extension, content_type, file_size, &limits) else {
+        return Ok(Ok(file_size));
+    };
+    tracing::warn!("Rejected upload {}: {}", s3_key, message);
+    s3_client
+        .delete_object()
+        .bucket(region::bucket_name())
+        .key(s3_key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to delete rejected upload: {}", e))?;
+    Ok(Err(Response::builder()
+        .status(StatusCode::UNPROCESSABLE_ENTITY)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": message, "code": "invalid_upload"}).to_string().into())
+        .map_err(Box::new)?))
+}
+
+/// Complete an upload (multipart, or single-part with no parts) and record
+/// the image in its block, so no separate `POST .../images` is needed.
+/// Files that aren't the image they claim to be, or are over the project's
+/// limits, are deleted and rejected with 422.
 pub async fn complete_multipart_upload(
+    client: &DynamoClient,
     s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
     request: CompleteMultipartRequest,
 ) -> Result<Response<Body>, Error> {
-    let s3_key = format!(
-        "projects/{}/blocks/{}/{}.{}",
-        request.project_id,
-        request.block_id,
-        request.image_id,
-        request.extension
-    );
+    if crate::blocks::fetch_block(client, table_name, &request.project_id, &request.block_id).await?.is_none() {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(serde_json::json!({"error": "Block not found"}).to_string().into())
+            .map_err(Box::new)?);
+    }
+    if let Some(response) = crate::blocks::check_assignee(client, table_name, &request.project_id, &request.block_id, user_id).await? {
+        return Ok(response);
+    }
+
+    let s3_key = crate::storage::upload_key(&request.project_id, &request.block_id, &request.image_id, &request.extension);
     
     // Only complete multipart if there are parts (multipart upload)
     // For single-part uploads, parts will be empty and upload_id will be empty
@@ -201,7 +478,7 @@ pub async fn complete_multipart_upload(
         // Complete the multipart upload
         s3_client
             .complete_multipart_upload()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .key(&s3_key)
             .upload_id(&request.upload_id)
             .multipart_upload(completed_upload)
@@ -210,33 +487,30 @@ pub async fn complete_multipart_upload(
             .map_err(|e| format!("Failed to complete multipart upload: {}", e))?;
     }
     
-    // Process image asynchronously (generate pyramid if needed)
-    tracing::info!("🔄 Starting post-upload processing for image: {}", request.image_id);
-    match process_uploaded_image(
-        s3_client,
-        &request.project_id,
-        &request.block_id,
-        &request.image_id,
-        &request.extension,
-    ).await {
-        Ok(metadata) => {
-            tracing::info!("✅ Image processing complete: {} levels", metadata.levels.len());
-        }
-        Err(e) => {
-            tracing::error!("⚠️ Image processing failed (continuing anyway): {}", e);
-        }
-    }
+    let size_bytes = match validate_upload(client, s3_client, table_name, &request, &s3_key).await? {
+        Ok(size_bytes) => size_bytes,
+        Err(response) => return Ok(response),
+    };
     
-    // Generate public URL (use first level path)
-    let url = format!(
-        "https://{}.s3.amazonaws.com/{}",
-        BUCKET_NAME,
-        s3_key
-    );
+    // Size, pyramid and thumbnail come from the upload worker, which the
+    // object's S3 event starts; the image is `processing` until then
+    let location = ImageLocation {
+        project_id: request.project_id.clone(),
+        block_id: request.block_id.clone(),
+    };
+    let upload = UploadedImage {
+        image_id: request.image_id.clone(),
+        url: crate::storage::object_url(&s3_key),
+        file_name: request.file_name.clone(),
+        content_type: request.content_type.clone().or_else(|| content_type_for(&request.extension).map(|s| s.to_string())),
+        size_bytes,
+    };
+    let image = crate::images::register_upload(client, s3_client, table_name, user_id, &location, upload).await?;
     
     let response = UploadCompleteResponse {
         image_id: request.image_id.clone(),
-        url,
+        url: image.url.clone(),
+        image,
     };
     
     Ok(Response::builder()
@@ -247,6 +521,132 @@ pub async fn complete_multipart_upload(
         .map_err(Box::new)?)
 }
 
+/// An upload's flat object key, `projects/{pid}/blocks/{bid}/{iid}.{ext}`.
+/// Keys inside an image's folder (pyramid levels, thumbnails) are not uploads.
+#[derive(Debug, PartialEq)]
+pub struct UploadKey {
+    pub project_id: String,
+    pub block_id: String,
+    pub image_id: String,
+    pub extension: String,
+}
+
+pub fn parse_upload_key(key: &str) -> Option<UploadKey> {
+    let parts: Vec<&str> = crate::storage::s3_config().project_path(key)?.split('/').collect();
+    let [project_id, "blocks", block_id, file] = parts.as_slice() else {
+        return None;
+    };
+    let (image_id, extension) = file.rsplit_once('.')?;
+    if [*project_id, *block_id, image_id, extension].iter().any(|s| s.is_empty()) {
+        return None;
+    }
+    Some(UploadKey {
+        project_id: project_id.to_string(),
+        block_id: block_id.to_string(),
+        image_id: image_id.to_string(),
+        extension: extension.to_string(),
+    })
+}
+
+/// How long the worker waits for the image row of an object whose upload
+/// hasn't been completed yet (single-part uploads land before the client
+/// calls complete)
+const REGISTER_WAIT_ATTEMPTS: u32 = 5;
+const REGISTER_WAIT: std::time::Duration = std::time::Duration::from_secs(2);
+
+async fn object_exists(s3_client: &S3Client, key: &str) -> Result<bool, Error> {
+    match s3_client.head_object().bucket(region::bucket_name()).key(key).send().await {
+        Ok(_) => Ok(true),
+        Err(e) if e.as_service_error().map(|se| se.is_not_found()).unwrap_or(false) => Ok(false),
+        Err(e) => Err(format!("Failed to read upload: {}", e).into()),
+    }
+}
+
+/// Process an uploaded object for the upload worker: read its size, build
+/// its pyramid and thumbnail, and flip its image from `processing` to
+/// `ready` (or `failed`). Errors when the image was never recorded, so the
+/// event is retried; images that aren't processing are left alone.
+pub async fn process_upload(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    key: &str,
+) -> Result<(), Error> {
+    let Some(upload) = parse_upload_key(key) else {
+        tracing::info!("Skipping {}: not an upload", key);
+        return Ok(());
+    };
+    let mut attempt = 0;
+    let image = loop {
+        let item = client
+            .get_item()
+            .table_name(table_name)
+            .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(format!("BLOCK#{}", upload.block_id)))
+            .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(format!("IMAGE#{}", upload.image_id)))
+            .send()
+            .await?
+            .item()
+            .cloned();
+        match item {
+            Some(item) => break item,
+            None if attempt + 1 < REGISTER_WAIT_ATTEMPTS => {
+                attempt += 1;
+                tokio::time::sleep(REGISTER_WAIT).await;
+            }
+            // Uploads rejected on completion are deleted rather than recorded
+            None if !object_exists(s3_client, key).await? => {
+                tracing::info!("Skipping {}: upload was rejected", key);
+                return Ok(());
+            }
+            None => return Err(format!("Image {} is not recorded yet", upload.image_id).into()),
+        }
+    };
+    if image.get("processing").and_then(|v| v.as_s().ok()).map(|s| s.as_str()) != Some("processing") {
+        tracing::info!("Skipping {}: image {} is not processing", key, upload.image_id);
+        return Ok(());
+    }
+
+    let pyramid = crate::projects::fetch_project(client, table_name, &upload.project_id)
+        .await?
+        .map(|project| project.settings.pyramid)
+        .unwrap_or_default();
+    let tile_size = match pyramid.mode.as_deref() {
+        Some("dzi") => Some(pyramid.tile_size.unwrap_or(image_processing::DEFAULT_TILE_SIZE)),
+        _ => None,
+    };
+    
+    tracing::info!("🔄 Starting post-upload processing for image: {}", upload.image_id);
+    let outcome = process_uploaded_image(
+        s3_client,
+        &upload.project_id,
+        &upload.block_id,
+        &upload.image_id,
+        &upload.extension,
+        tile_size,
+    )
+    .await
+    .map(|metadata| {
+        tracing::info!("✅ Image processing complete: {} levels", metadata.levels.len());
+        let url = crate::storage::object_url(&full_resolution_key(key, &upload.image_id, Some(&metadata)));
+        let thumbnail_url = metadata
+            .thumbnail
+            .as_ref()
+            .map(|_| crate::storage::object_url(&thumbnail_key(&upload.project_id, &upload.block_id, &upload.image_id)));
+        let tiles_url = metadata.tiles.as_ref().map(|tiles| {
+            crate::storage::object_url(&format!(
+                "{}{}/{}",
+                crate::storage::block_prefix(&upload.project_id, &upload.block_id), upload.image_id, tiles.path
+            ))
+        });
+        ProcessedImage { url, thumbnail_url, tiles_url, metadata }
+    })
+    .inspect_err(|e| tracing::error!("⚠️ Image processing failed for {}: {}", upload.image_id, e));
+    if !crate::images::finish_processing(client, table_name, &upload.block_id, &upload.image_id, outcome).await? {
+        tracing::info!("Image {} finished processing elsewhere", upload.image_id);
+    }
+    Ok(())
+}
+
 /// Abort multipart upload (cleanup on failure)
 pub async fn abort_multipart_upload(
     s3_client: &S3Client,
@@ -256,17 +656,11 @@ pub async fn abort_multipart_upload(
     upload_id: String,
     extension: String,
 ) -> Result<Response<Body>, Error> {
-    let s3_key = format!(
-        "projects/{}/blocks/{}/{}.{}",
-        project_id,
-        block_id,
-        image_id,
-        extension
-    );
+    let s3_key = crate::storage::upload_key(&project_id, &block_id, &image_id, &extension);
     
     s3_client
         .abort_multipart_upload()
-        .bucket(BUCKET_NAME)
+        .bucket(region::bucket_name())
         .key(&s3_key)
         .upload_id(&upload_id)
         .send()
@@ -280,24 +674,106 @@ pub async fn abort_multipart_upload(
         .map_err(Box::new)?)
 }
 
-/// Process uploaded image: generate half-width if needed and create metadata
+/// Part numbers still to upload: those up to `total` that aren't uploaded,
+/// or without a total, the gaps below the highest part uploaded
+fn missing_parts(uploaded: &[i32], total: Option<i32>) -> Vec<i32> {
+    let last = total.unwrap_or_else(|| uploaded.iter().copied().max().unwrap_or(0));
+    (1..=last).filter(|n| !uploaded.contains(n)).collect()
+}
+
+/// Parts already in S3 (with the etags completing needs) and fresh URLs for
+/// the rest, so an interrupted multipart upload can resume. `file_size`
+/// gives the part count; without it only gaps are re-issued.
+pub async fn list_upload_parts(
+    s3_client: &S3Client,
+    project_id: &str,
+    block_id: &str,
+    image_id: &str,
+    upload_id: &str,
+    extension: &str,
+    file_size: Option<usize>,
+) -> Result<Response<Body>, Error> {
+    let s3_key = crate::storage::upload_key(project_id, block_id, image_id, extension);
+
+    // S3 returns at most 1000 parts per page
+    let mut parts = Vec::new();
+    let mut marker: Option<String> = None;
+    loop {
+        let result = s3_client
+            .list_parts()
+            .bucket(region::bucket_name())
+            .key(&s3_key)
+            .upload_id(upload_id)
+            .set_part_number_marker(marker.take())
+            .send()
+            .await;
+        let output = match result {
+            Ok(output) => output,
+            Err(e) if e.as_service_error().and_then(|se| se.code()) == Some("NoSuchUpload") => {
+                return Ok(Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .header("Content-Type", "application/json")
+                    .header("Access-Control-Allow-Origin", "*")
+                    .body(serde_json::json!({"error": "Upload not found"}).to_string().into())
+                    .map_err(Box::new)?);
+            }
+            Err(e) => return Err(format!("Failed to list uploaded parts: {}", e).into()),
+        };
+        for part in output.parts() {
+            let (Some(part_number), Some(etag)) = (part.part_number(), part.e_tag()) else {
+                continue;
+            };
+            parts.push(UploadedPart {
+                part_number,
+                etag: etag.to_string(),
+                size: part.size().unwrap_or(0),
+            });
+        }
+        marker = output.next_part_number_marker().map(|m| m.to_string());
+        if !output.is_truncated().unwrap_or(false) || marker.is_none() {
+            break;
+        }
+    }
+
+    let uploaded: Vec<i32> = parts.iter().map(|p| p.part_number).collect();
+    let total = file_size.map(|size| url_count(size) as i32);
+    let mut missing = Vec::new();
+    for part_number in missing_parts(&uploaded, total) {
+        missing.push(presign_part(s3_client, &s3_key, upload_id, part_number).await?);
+    }
+
+    let response = UploadPartsResponse {
+        upload_id: upload_id.to_string(),
+        parts,
+        missing,
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&response)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Process uploaded image: generate half-width if needed and create metadata.
+/// With a `tile_size` (projects whose pyramid mode is dzi), images big enough
+/// for a half-width copy get Deep Zoom tiles as well. HEIC and TIFF uploads
+/// are converted to JPEG and PNG first.
 pub async fn process_uploaded_image(
     s3_client: &S3Client,
     project_id: &str,
     block_id: &str,
     image_id: &str,
     extension: &str,
+    tile_size: Option<u32>,
 ) -> Result<ImageMetadata, String> {
-    let original_key = format!(
-        "projects/{}/blocks/{}/{}.{}",
-        project_id, block_id, image_id, extension
-    );
+    let original_key = crate::storage::upload_key(project_id, block_id, image_id, extension);
     
     // Download original image from S3
     tracing::info!("📥 Downloading image from S3: {}", original_key);
     let result = s3_client
         .get_object()
-        .bucket(BUCKET_NAME)
+        .bucket(region::bucket_name())
         .key(&original_key)
         .send()
         .await
@@ -311,6 +787,49 @@ pub async fn process_uploaded_image(
         .into_bytes()
         .to_vec();
     
+    // Upload structure: projects/{pid}/blocks/{bid}/{img_id}/
+    let base_path = format!("{}{}", crate::storage::block_prefix(project_id, block_id), image_id);
+    
+    let photo = image_processing::photo_metadata(&image_bytes);
+    
+    // Browsers can't show HEIC or TIFF: the rest works on the converted
+    // image, and the file as uploaded is kept beside it
+    let original_format = image_processing::browser_format(extension).map(|_| extension.to_ascii_lowercase());
+    let (image_bytes, extension) = match image_processing::convert_for_browser(&image_bytes, extension)? {
+        Some((converted_extension, converted)) => {
+            tracing::info!("🔄 Converted {} upload to {}", extension, converted_extension);
+            let mut upload = s3_client
+                .put_object()
+                .bucket(region::bucket_name())
+                .key(format!("{}/original.{}", base_path, extension))
+                .body(image_bytes.into());
+            if let Some(content_type) = content_type_for(extension) {
+                upload = upload.content_type(content_type);
+            }
+            upload
+                .send()
+                .await
+                .map_err(|e| format!("Failed to keep original upload: {}", e))?;
+            (converted, converted_extension)
+        }
+        None => (image_bytes, extension),
+    };
+    
+    // Site photos often arrive sideways: everything from here on uses the
+    // upright pixels, and the flat upload is replaced by them below. libheif
+    // has already turned HEIC.
+    let orientation = photo
+        .as_ref()
+        .and_then(|p| p.orientation)
+        .filter(|_| !matches!(original_format.as_deref(), Some("heic" | "heif")));
+    let (image_bytes, turned) = match image_processing::upright(&image_bytes, orientation)? {
+        Some(upright) => {
+            tracing::info!("🔄 Turned image upright from EXIF orientation {:?}", orientation);
+            (upright, true)
+        }
+        None => (image_bytes, false),
+    };
+    
     let file_size = image_bytes.len();
     
     // Get dimensions
@@ -318,6 +837,15 @@ pub async fn process_uploaded_image(
     
     tracing::info!("📐 Image dimensions: {}x{}, size: {} bytes", width, height, file_size);
     
+    // Galleries fall back to the image itself without a thumbnail
+    let thumbnail = match upload_thumbnail(s3_client, &base_path, &image_bytes).await {
+        Ok(thumbnail) => Some(thumbnail),
+        Err(e) => {
+            tracing::warn!("⚠️ Thumbnail generation failed for {}: {}", image_id, e);
+            None
+        }
+    };
+    
     // Check if we need half-width version
     let needs_pyramid = image_processing::needs_half_width(file_size, width, height);
     
@@ -330,17 +858,30 @@ pub async fn process_uploaded_image(
         let (half_width, half_height, half_bytes) = image_processing::generate_half_width(&image_bytes)?;
         let half_size = half_bytes.len();
         
-        // Upload structure: projects/{pid}/blocks/{bid}/{img_id}/
-        let base_path = format!("projects/{}/blocks/{}/{}", project_id, block_id, image_id);
+        // Viewers fall back to the half-width preview without tiles
+        let tiles = match tile_size {
+            Some(tile_size) => match upload_tiles(s3_client, &base_path, &image_bytes, tile_size).await {
+                Ok(tiles) => Some(tiles),
+                Err(e) => {
+                    tracing::warn!("⚠️ Tile generation failed for {}: {}", image_id, e);
+                    None
+                }
+            },
+            None => None,
+        };
         
         // Upload full resolution (move original to folder)
         let full_key = format!("{}/{}w.{}", base_path, width, extension);
         tracing::info!("📤 Uploading full resolution to: {}", full_key);
-        s3_client
+        let mut upload = s3_client
             .put_object()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .key(&full_key)
-            .body(image_bytes.into())
+            .body(image_bytes.into());
+        if let Some(content_type) = content_type_for(extension) {
+            upload = upload.content_type(content_type);
+        }
+        upload
             .send()
             .await
             .map_err(|e| format!("Failed to upload full resolution: {}", e))?;
@@ -348,7 +889,7 @@ pub async fn process_uploaded_image(
         // Delete old flat file
         s3_client
             .delete_object()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .key(&original_key)
             .send()
             .await
@@ -359,7 +900,7 @@ pub async fn process_uploaded_image(
         tracing::info!("📤 Uploading half-width to: {}", half_key);
         s3_client
             .put_object()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .key(&half_key)
             .body(half_bytes.into())
             .content_type("image/jpeg")
@@ -391,6 +932,10 @@ pub async fn process_uploaded_image(
             file_size,
             format: extension.to_string(),
             levels: levels.clone(),
+            thumbnail,
+            tiles,
+            photo,
+            original_format,
         };
         
         let metadata_json = serde_json::to_string(&metadata)
@@ -400,7 +945,7 @@ pub async fn process_uploaded_image(
         tracing::info!("📤 Uploading metadata to: {}", metadata_key);
         s3_client
             .put_object()
-            .bucket(BUCKET_NAME)
+            .bucket(region::bucket_name())
             .key(&metadata_key)
             .body(metadata_json.into_bytes().into())
             .content_type("application/json")
@@ -414,11 +959,40 @@ pub async fn process_uploaded_image(
     } else {
         tracing::info!("✅ Image is small enough, no pyramid needed");
         
+        // A turned or converted image moves into its folder like a pyramid's
+        // full level: rewriting the flat upload would start the worker on it
+        // again, and a converted one has a new extension anyway
+        let path = if turned || original_format.is_some() {
+            let path = format!("{}w.{}", width, extension);
+            let mut upload = s3_client
+                .put_object()
+                .bucket(region::bucket_name())
+                .key(format!("{}/{}", base_path, path))
+                .body(image_bytes.into());
+            if let Some(content_type) = content_type_for(extension) {
+                upload = upload.content_type(content_type);
+            }
+            upload
+                .send()
+                .await
+                .map_err(|e| format!("Failed to upload upright image: {}", e))?;
+            s3_client
+                .delete_object()
+                .bucket(region::bucket_name())
+                .key(&original_key)
+                .send()
+                .await
+                .ok(); // Ignore errors
+            path
+        } else {
+            format!("{}.{}", image_id, extension)
+        };
+        
         // Single level metadata
         levels.push(ImageLevel {
             width,
             height,
-            path: format!("{}.{}", image_id, extension),
+            path,
             size: file_size,
             purpose: "full".to_string(),
         });
@@ -429,6 +1003,230 @@ pub async fn process_uploaded_image(
             file_size,
             format: extension.to_string(),
             levels,
+            thumbnail,
+            tiles: None,
+            photo,
+            original_format,
         })
     }
 }
+
+/// Key of an image's gallery thumbnail, in its folder whether or not it has a pyramid
+pub(crate) fn thumbnail_key(project_id: &str, block_id: &str, image_id: &str) -> String {
+    format!("{}{}/thumb.jpg", crate::storage::block_prefix(project_id, block_id), image_id)
+}
+
+/// Generate Deep Zoom tiles level by level, each level scaled from the one
+/// above, and upload them with their `{base}/tiles.dzi` descriptor
+async fn upload_tiles(
+    s3_client: &S3Client,
+    base_path: &str,
+    image_bytes: &[u8],
+    tile_size: u32,
+) -> Result<TileSource, String> {
+    let overlap = image_processing::TILE_OVERLAP;
+    let mut level_image = image::load_from_memory(image_bytes)
+        .map_err(|e| format!("Failed to load image: {}", e))?;
+    let (width, height) = (level_image.width(), level_image.height());
+    let max_level = image_processing::dzi_max_level(width, height);
+    let mut tile_count = 0;
+    for level in (0..=max_level).rev() {
+        let (level_width, level_height) = image_processing::dzi_level_size(width, height, level);
+        if (level_image.width(), level_image.height()) != (level_width, level_height) {
+            level_image = level_image.resize_exact(level_width, level_height, image::imageops::FilterType::Triangle);
+        }
+        for (col, row, bytes) in image_processing::encode_level_tiles(&level_image, tile_size, overlap)? {
+            s3_client
+                .put_object()
+                .bucket(region::bucket_name())
+                .key(format!("{}/tiles_files/{}/{}_{}.jpg", base_path, level, col, row))
+                .body(bytes.into())
+                .content_type("image/jpeg")
+                .send()
+                .await
+                .map_err(|e| format!("Failed to upload tile {}/{}_{}: {}", level, col, row, e))?;
+            tile_count += 1;
+        }
+    }
+    
+    // The descriptor goes last, so a viewer never finds it before its tiles
+    let descriptor = image_processing::dzi_descriptor(width, height, tile_size, overlap);
+    s3_client
+        .put_object()
+        .bucket(region::bucket_name())
+        .key(format!("{}/tiles.dzi", base_path))
+        .body(descriptor.into_bytes().into())
+        .content_type("application/xml")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to upload tiles.dzi: {}", e))?;
+    tracing::info!("✅ Uploaded {} tiles over {} levels", tile_count, max_level + 1);
+    
+    Ok(TileSource {
+        path: "tiles.dzi".to_string(),
+        tile_size,
+        overlap,
+        max_level,
+        tile_count,
+    })
+}
+
+/// Generate the gallery thumbnail and upload it as `{base}/thumb.jpg`
+async fn upload_thumbnail(
+    s3_client: &S3Client,
+    base_path: &str,
+    image_bytes: &[u8],
+) -> Result<ImageLevel, String> {
+    let (width, height, bytes) = image_processing::generate_thumbnail(image_bytes)?;
+    let size = bytes.len();
+    let key = format!("{}/thumb.jpg", base_path);
+    tracing::info!("📤 Uploading thumbnail to: {}", key);
+    s3_client
+        .put_object()
+        .bucket(region::bucket_name())
+        .key(&key)
+        .body(bytes.into())
+        .content_type("image/jpeg")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to upload thumbnail: {}", e))?;
+    Ok(ImageLevel {
+        width,
+        height,
+        path: "thumb.jpg".to_string(),
+        size,
+        purpose: "thumbnail".to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_parts() {
+        assert_eq!(missing_parts(&[1, 2, 4], Some(5)), vec![3, 5]);
+        assert_eq!(missing_parts(&[1, 2, 4], None), vec![3]);
+        assert_eq!(missing_parts(&[], Some(2)), vec![1, 2]);
+        assert!(missing_parts(&[], None).is_empty());
+    }
+
+    #[test]
+    fn test_check_manifest() {
+        let file = |file_size: usize| ManifestFile {
+            file_name: "a.jpg".to_string(),
+            content_type: "image/jpeg".to_string(),
+            file_size,
+        };
+        assert_eq!(url_count(0), 1);
+        assert_eq!(url_count(MULTIPART_THRESHOLD), 1);
+        assert_eq!(url_count(MULTIPART_THRESHOLD * 2 + 1), 3);
+
+        assert!(check_manifest(&[]).is_err());
+        assert!(check_manifest(&[file(1024), file(MULTIPART_THRESHOLD * 3)]).is_ok());
+        let too_many: Vec<_> = (0..=MAX_BATCH_FILES).map(|_| file(1024)).collect();
+        assert!(check_manifest(&too_many).is_err());
+        // Few files, but too many parts for one response
+        let huge: Vec<_> = (0..5).map(|_| file(MULTIPART_THRESHOLD * MAX_BATCH_URLS / 4)).collect();
+        assert!(check_manifest(&huge).is_err());
+    }
+
+    #[test]
+    fn test_check_upload() {
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::new(400, 300).write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+        let size = png.len() as u64;
+        let any = UploadLimits::default();
+
+        assert_eq!(check_upload(&png, "png", Some("image/png"), size, &any), Ok(()));
+        assert_eq!(check_upload(&png, "png", Some("application/octet-stream"), size, &any), Ok(()));
+        assert_eq!(check_upload(b"%PDF-1.7", "png", None, 8, &any), Err("File is not an image".to_string()));
+        assert_eq!(check_upload(&png, "jpg", None, size, &any), Err("File is a png image, not .jpg".to_string()));
+        assert!(check_upload(&png, "png", Some("text/html"), size, &any).is_err());
+        // The right magic but nothing after it
+        assert!(check_upload(&png[..8], "png", None, 8, &any).is_err());
+
+        let small = UploadLimits { max_file_size: Some(size - 1), ..UploadLimits::default() };
+        assert!(check_upload(&png, "png", None, size, &small).is_err());
+        let narrow = UploadLimits { max_width: Some(399), ..UploadLimits::default() };
+        assert_eq!(
+            check_upload(&png, "png", None, size, &narrow),
+            Err("Image is 400x300, over the project's 399xany limit".to_string())
+        );
+        let fits = UploadLimits { max_file_size: Some(size), max_width: Some(400), max_height: Some(300) };
+        assert_eq!(check_upload(&png, "png", None, size, &fits), Ok(()));
+    }
+
+    #[test]
+    fn test_content_type_for() {
+        assert_eq!(content_type_for("JPG"), Some("image/jpeg"));
+        assert_eq!(content_type_for("png"), Some("image/png"));
+        assert_eq!(content_type_for("pdf"), None);
+    }
+
+    #[test]
+    fn test_parse_upload_key() {
+        assert_eq!(
+            parse_upload_key("projects/p/blocks/b/i.png"),
+            Some(UploadKey {
+                project_id: "p".to_string(),
+                block_id: "b".to_string(),
+                image_id: "i".to_string(),
+                extension: "png".to_string(),
+            })
+        );
+        // Objects processing writes into the image's folder
+        assert_eq!(parse_upload_key("projects/p/blocks/b/i/thumb.jpg"), None);
+        assert_eq!(parse_upload_key("projects/p/blocks/b/i/6000w.png"), None);
+        assert_eq!(parse_upload_key("projects/p/blocks/b/noextension"), None);
+        assert_eq!(parse_upload_key("exports/p/blocks/b/i.png"), None);
+    }
+
+    #[test]
+    fn test_thumbnail_key() {
+        assert_eq!(thumbnail_key("p", "b", "i"), "projects/p/blocks/b/i/thumb.jpg");
+    }
+
+    #[test]
+    fn test_full_resolution_key() {
+        let level = |width: u32, path: &str| ImageLevel {
+            width,
+            height: width / 2,
+            path: path.to_string(),
+            size: 0,
+            purpose: "full".to_string(),
+        };
+        let flat = "projects/p/blocks/b/i.png";
+        let single = ImageMetadata {
+            original_width: 800,
+            original_height: 400,
+            file_size: 0,
+            format: "png".to_string(),
+            levels: vec![level(800, "i.png")],
+            thumbnail: None,
+            tiles: None,
+            photo: None,
+            original_format: None,
+        };
+        assert_eq!(full_resolution_key(flat, "i", Some(&single)), flat);
+        assert_eq!(full_resolution_key(flat, "i", None), flat);
+        let pyramid = ImageMetadata {
+            levels: vec![level(6000, "6000w.png"), level(3000, "3000w.jpg")],
+            ..single
+        };
+        assert_eq!(
+            full_resolution_key(flat, "i", Some(&pyramid)),
+            "projects/p/blocks/b/i/6000w.png"
+        );
+        // Turned upright, a single level moves too
+        let turned = ImageMetadata {
+            levels: vec![level(400, "400w.png")],
+            ..pyramid
+        };
+        assert_eq!(
+            full_resolution_key(flat, "i", Some(&turned)),
+            "projects/p/blocks/b/i/400w.png"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use crate::types::Annotation;
+use crate::{annotations, blocks, images, storage};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+
+const DEFAULT_SAMPLE_SIZE: usize = 50;
+const MAX_SAMPLE_SIZE: usize = 500;
+
+/// An annotation picked for review, with where to find it
+#[derive(Debug, Serialize, Clone)]
+pub struct SampledAnnotation {
+    pub block_id: String,
+    pub image_url: String, // through /proxy-image/
+    #[serde(flatten)]
+    pub annotation: Annotation,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnotationSample {
+    pub project_id: String,
+    pub class_id: Option<String>,
+    pub seed: u64,  // pass back as ?seed= to draw the same sample again
+    pub total: u64, // annotations matching the filter
+    pub annotations: Vec<SampledAnnotation>,
+}
+
+/// xorshift64*: plenty for picking spot checks, and reproducible from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift never leaves zero
+        Rng(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in 0..bound
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+/// Uniform random sample of up to `n` items from a stream of unknown length.
+/// Algorithm R: the reservoir only ever holds `n` items.
+struct Reservoir<T> {
+    n: usize,
+    seen: u64,
+    items: Vec<T>,
+    rng: Rng,
+}
+
+impl<T> Reservoir<T> {
+    fn new(n: usize, seed: u64) -> Self {
+        Reservoir {
+            n,
+            seen: 0,
+            items: Vec::with_capacity(n),
+            rng: Rng::new(seed),
+        }
+    }
+
+    fn offer(&mut self, item: T) {
+        self.seen += 1;
+        if self.items.len() < self.n {
+            self.items.push(item);
+        } else {
+            let slot = self.rng.below(self.seen) as usize;
+            if slot < self.n {
+                self.items[slot] = item;
+            }
+        }
+    }
+}
+
+fn bad_request(message: &str) -> Result<Response<Body>, Error> {
+    Ok(Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({ "error": message }).to_string().into())
+        .map_err(Box::new)?)
+}
+
+/// Random sample of a project's annotations for QA spot checks
+/// (GET /projects/{id}/annotations/sample?n=50&class_id=&seed=)
+pub async fn sample_project_annotations(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    n: Option<&str>,
+    class_id: Option<&str>,
+    seed: Option<&str>,
+) -> Result<Response<Body>, Error> {
+    let n = match n {
+        None => DEFAULT_SAMPLE_SIZE,
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) if (1..=MAX_SAMPLE_SIZE).contains(&n) => n,
+            _ => {
+                return bad_request(&format!("n must be between 1 and {}", MAX_SAMPLE_SIZE));
+            }
+        },
+    };
+    let seed = match seed {
+        None => uuid::Uuid::new_v4().as_u64_pair().0,
+        Some(seed) => match seed.parse::<u64>() {
+            Ok(seed) => seed,
+            Err(_) => return bad_request("seed must be a non-negative integer"),
+        },
+    };
+
+    let mut reservoir = Reservoir::new(n, seed);
+    for block in blocks::fetch_project_blocks(client, table_name, project_id).await? {
+        for image in images::fetch_block_images(client, table_name, &block.block_id).await? {
+            let image_url = match storage::object_key(&image.url) {
+                Some(key) => storage::proxy_url(&key),
+                None => image.url.clone(),
+            };
+            for annotation in
+                annotations::fetch_image_annotations(client, table_name, &image.image_id).await?
+            {
+                if class_id.is_some_and(|c| c != annotation.class_id) {
+                    continue;
+                }
+                reservoir.offer(SampledAnnotation {
+                    block_id: block.block_id.clone(),
+                    image_url: image_url.clone(),
+                    annotation,
+                });
+            }
+        }
+    }
+
+    let sample = AnnotationSample {
+        project_id: project_id.to_string(),
+        class_id: class_id.map(|c| c.to_string()),
+        seed,
+        total: reservoir.seen,
+        annotations: reservoir.items,
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&sample)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(n: usize, len: u32, seed: u64) -> Vec<u32> {
+        let mut reservoir = Reservoir::new(n, seed);
+        for i in 0..len {
+            reservoir.offer(i);
+        }
+        reservoir.items
+    }
+
+    #[test]
+    fn test_reservoir() {
+        // Everything fits
+        assert_eq!(draw(10, 4, 7), vec![0, 1, 2, 3]);
+
+        let sample = draw(5, 1000, 7);
+        assert_eq!(sample.len(), 5);
+        let mut unique = sample.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 5);
+
+        // Same seed, same sample; the tail of the stream gets picked too
+        assert_eq!(draw(5, 1000, 7), sample);
+        assert_ne!(draw(5, 1000, 8), sample);
+        assert!(sample.iter().any(|&i| i >= 5));
+    }
+}
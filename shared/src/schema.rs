@@ -0,0 +1,538 @@
+use crate::types::*;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use schemars::gen::SchemaSettings;
+use serde_json::{Map, Value};
+
+/// JSON Schema (draft-07) with a definition for every API model in `types.rs`
+pub fn schema() -> Value {
+    let mut gen = SchemaSettings::draft07().into_generator();
+    // Registering each model as a subschema puts it (and everything it
+    // references) into the generator's definitions
+    macro_rules! models {
+        ($($t:ty),* $(,)?) => {
+            $( gen.subschema_for::<$t>(); )*
+        };
+    }
+    models!(
+        User,
+        CreateUserRequest,
+        UpdateUserRequest,
+        Onboarding,
+        Session,
+        RevokeSessionRequest,
+        OrgSettings,
+        UpdateOrgSettingsRequest,
+        Label,
+        SnapSettings,
+        AnnotationDefaults,
+        PyramidSettings,
+        UploadLimits,
+        PhotoMetadata,
+        ProjectSettings,
+        Project,
+        CreateProjectRequest,
+        UpdateProjectRequest,
+        MemberEntry,
+        BatchAddMembersRequest,
+        Class,
+        Skeleton,
+        AttributeDef,
+        CreateClassRequest,
+        BatchCreateClassesRequest,
+        ReorderClassesRequest,
+        ClassTemplate,
+        TemplateClass,
+        CreateClassTemplateRequest,
+        UpdateClassTemplateRequest,
+        UpdateClassRequest,
+        Block,
+        CreateBlockRequest,
+        UpdateBlockRequest,
+        SetReviewerRequest,
+        MoveBlockRequest,
+        MoveImageRequest,
+        BlockEvent,
+        Calibration,
+        Image,
+        CreateImageRequest,
+        UpdateImageRequest,
+        ReplaceImageRequest,
+        ReorderImagesRequest,
+        Lock,
+        ImageMetadata,
+        ImageLevel,
+        Point,
+        Keypoint,
+        Geometry,
+        BoundingBox,
+        Annotation,
+        CreateAnnotationRequest,
+        UpdateAnnotationRequest,
+        BatchCreateAnnotationsRequest,
+        PropagateAnnotationsRequest,
+        ReassignClassRequest,
+        CreateGroupRequest,
+        ReorderAnnotationsRequest,
+        ReviewAnnotationRequest,
+        BulkReviewRequest,
+        Comment,
+        CreateCommentRequest,
+        UpdateCommentRequest,
+    );
+    let definitions: Map<String, Value> = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or_default()))
+        .collect();
+
+    serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Doxle Annotations API models",
+        "definitions": definitions,
+    })
+}
+
+fn definitions(schema: &Value) -> Vec<(&String, &Value)> {
+    schema
+        .get("definitions")
+        .and_then(|d| d.as_object())
+        .map(|d| d.iter().collect())
+        .unwrap_or_default()
+}
+
+fn ref_name(schema: &Value) -> Option<&str> {
+    schema.get("$ref")?.as_str()?.strip_prefix("#/definitions/")
+}
+
+/// Schema types, without "null"
+fn types(schema: &Value) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(ts)) => ts
+            .iter()
+            .filter_map(|t| t.as_str())
+            .filter(|t| *t != "null")
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn nullable(schema: &Value) -> bool {
+    let null_type = |s: &Value| match s.get("type") {
+        Some(Value::String(t)) => t == "null",
+        Some(Value::Array(ts)) => ts.iter().any(|t| t == "null"),
+        _ => false,
+    };
+    null_type(schema)
+        || schema
+            .get("anyOf")
+            .and_then(|a| a.as_array())
+            .map(|a| a.iter().any(null_type))
+            .unwrap_or(false)
+}
+
+fn variants(schema: &Value) -> Vec<&Value> {
+    ["oneOf", "anyOf"]
+        .iter()
+        .filter_map(|k| schema.get(*k).and_then(|v| v.as_array()))
+        .flatten()
+        .filter(|v| v.get("type").and_then(|t| t.as_str()) != Some("null"))
+        .collect()
+}
+
+fn string_enum(schema: &Value) -> Option<Vec<&str>> {
+    let values = schema.get("enum")?.as_array()?;
+    Some(values.iter().filter_map(|v| v.as_str()).collect())
+}
+
+fn properties(schema: &Value) -> Vec<(&String, &Value, bool)> {
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    schema
+        .get("properties")
+        .and_then(|p| p.as_object())
+        .map(|p| {
+            p.iter()
+                .map(|(name, prop)| (name, prop, required.contains(&name.as_str())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn description(schema: &Value) -> Option<&str> {
+    schema.get("description").and_then(|d| d.as_str())
+}
+
+/// Tag value of an internally tagged enum variant (`#[serde(tag = "type")]`)
+fn tag(variant: &Value) -> Option<&str> {
+    let tag = variant.get("properties")?.get("type")?;
+    string_enum(tag)?.first().copied()
+}
+
+fn pascal_case(s: &str) -> String {
+    s.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+// ---------- TypeScript ----------
+
+fn ts_type(schema: &Value) -> String {
+    if let Some(name) = ref_name(schema) {
+        return name.to_string();
+    }
+    if let Some(values) = string_enum(schema) {
+        return values
+            .iter()
+            .map(|v| format!("\"{}\"", v))
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+    let options = variants(schema);
+    if !options.is_empty() {
+        return options
+            .iter()
+            .map(|v| ts_type(v))
+            .collect::<Vec<_>>()
+            .join(" | ");
+    }
+    let ts: Vec<String> = types(schema)
+        .iter()
+        .map(|t| match *t {
+            "string" => "string".to_string(),
+            "integer" | "number" => "number".to_string(),
+            "boolean" => "boolean".to_string(),
+            "array" => {
+                let item = schema
+                    .get("items")
+                    .map(ts_type)
+                    .unwrap_or_else(|| "unknown".to_string());
+                if item.contains(' ') {
+                    format!("({})[]", item)
+                } else {
+                    format!("{}[]", item)
+                }
+            }
+            "object" if schema.get("properties").is_some() => ts_object(schema, ""),
+            _ => "Record<string, unknown>".to_string(),
+        })
+        .collect();
+    if ts.is_empty() {
+        "unknown".to_string()
+    } else {
+        ts.join(" | ")
+    }
+}
+
+fn ts_field(name: &str, prop: &Value, required: bool) -> String {
+    let mut ty = ts_type(prop);
+    if nullable(prop) {
+        ty.push_str(" | null");
+    }
+    format!("{}{}: {};", name, if required { "" } else { "?" }, ty)
+}
+
+fn ts_object(schema: &Value, indent: &str) -> String {
+    let fields: Vec<String> = properties(schema)
+        .into_iter()
+        .map(|(name, prop, required)| ts_field(name, prop, required))
+        .collect();
+    if indent.is_empty() {
+        format!("{{ {} }}", fields.join(" "))
+    } else {
+        fields
+            .iter()
+            .map(|f| format!("{}{}\n", indent, f))
+            .collect()
+    }
+}
+
+/// TypeScript interfaces for every model in the schema
+pub fn typescript(schema: &Value) -> String {
+    let mut out = String::from("// Generated from doxle-shared types.rs. Do not edit by hand.\n");
+    for (name, def) in definitions(schema) {
+        out.push('\n');
+        if let Some(doc) = description(def) {
+            out.push_str(&format!("/** {} */\n", doc));
+        }
+        if def.get("properties").is_some() {
+            out.push_str(&format!(
+                "export interface {} {{\n{}}}\n",
+                name,
+                ts_object(def, "  ")
+            ));
+        } else {
+            out.push_str(&format!("export type {} = {};\n", name, ts_type(def)));
+        }
+    }
+    out
+}
+
+// ---------- Python ----------
+
+fn py_type(schema: &Value) -> String {
+    if let Some(name) = ref_name(schema) {
+        return name.to_string();
+    }
+    if let Some(values) = string_enum(schema) {
+        let literals: Vec<String> = values.iter().map(|v| format!("\"{}\"", v)).collect();
+        return format!("Literal[{}]", literals.join(", "));
+    }
+    let options = variants(schema);
+    if options.len() == 1 {
+        return py_type(options[0]);
+    }
+    if !options.is_empty() {
+        let types: Vec<String> = options.iter().map(|v| py_type(v)).collect();
+        return format!("Union[{}]", types.join(", "));
+    }
+    let py: Vec<String> = types(schema)
+        .iter()
+        .map(|t| match *t {
+            "string" => "str".to_string(),
+            "integer" => "int".to_string(),
+            "number" => "float".to_string(),
+            "boolean" => "bool".to_string(),
+            "array" => format!(
+                "List[{}]",
+                schema
+                    .get("items")
+                    .map(py_type)
+                    .unwrap_or_else(|| "Any".to_string())
+            ),
+            _ => "Dict[str, Any]".to_string(),
+        })
+        .collect();
+    match py.len() {
+        0 => "Any".to_string(),
+        1 => py[0].clone(),
+        _ => format!("Union[{}]", py.join(", ")),
+    }
+}
+
+const PY_KEYWORDS: [&str; 35] = [
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+fn py_class(name: &str, def: &Value) -> String {
+    let fields: Vec<(&String, String)> = properties(def)
+        .into_iter()
+        .map(|(field, prop, required)| {
+            let mut ty = py_type(prop);
+            if nullable(prop) {
+                ty = format!("Optional[{}]", ty);
+            }
+            if !required {
+                ty = format!("NotRequired[{}]", ty);
+            }
+            (field, ty)
+        })
+        .collect();
+
+    // Fields such as `from` can't be class attributes; use the functional
+    // form, where types are evaluated eagerly and so must be quoted
+    if fields
+        .iter()
+        .any(|(f, _)| PY_KEYWORDS.contains(&f.as_str()))
+    {
+        let mut out = String::new();
+        if let Some(doc) = description(def) {
+            out.push_str(&format!("# {}\n", doc));
+        }
+        out.push_str(&format!("{} = TypedDict(\"{}\", {{\n", name, name));
+        for (field, ty) in fields {
+            out.push_str(&format!(
+                "    \"{}\": \"{}\",\n",
+                field,
+                ty.replace('"', "'")
+            ));
+        }
+        out.push_str("})\n");
+        return out;
+    }
+
+    let mut out = format!("class {}(TypedDict):\n", name);
+    if let Some(doc) = description(def) {
+        out.push_str(&format!("    \"\"\"{}\"\"\"\n", doc));
+    }
+    if fields.is_empty() {
+        out.push_str("    pass\n");
+    }
+    for (field, ty) in fields {
+        out.push_str(&format!("    {}: {}\n", field, ty));
+    }
+    out
+}
+
+const PY_CLIENT: &str = r#"
+class DoxleClient:
+    """Minimal JSON client for the Doxle Annotations API."""
+
+    def __init__(self, base_url: str, token: Optional[str] = None, timeout: float = 30.0):
+        self.base_url = base_url.rstrip("/")
+        self.token = token
+        self.timeout = timeout
+
+    def request(self, method: str, path: str, body: Any = None, params: Optional[Dict[str, str]] = None) -> Any:
+        url = self.base_url + path
+        if params:
+            url += "?" + urllib.parse.urlencode(params)
+        data = json.dumps(body).encode() if body is not None else None
+        req = urllib.request.Request(url, data=data, method=method)
+        req.add_header("Content-Type", "application/json")
+        if self.token:
+            req.add_header("Authorization", f"Bearer {self.token}")
+        with urllib.request.urlopen(req, timeout=self.timeout) as resp:
+            payload = resp.read()
+        return json.loads(payload) if payload else None
+
+    def list_projects(self) -> List[Project]:
+        return self.request("GET", "/projects")
+
+    def get_project(self, project_id: str) -> Project:
+        return self.request("GET", f"/projects/{project_id}")
+
+    def list_blocks(self, project_id: str) -> List[Block]:
+        return self.request("GET", f"/projects/{project_id}/blocks")
+
+    def list_classes(self, project_id: str) -> List[Class]:
+        return self.request("GET", f"/projects/{project_id}/classes")
+
+    def list_images(self, project_id: str, block_id: str) -> List[Image]:
+        return self.request("GET", f"/projects/{project_id}/blocks/{block_id}/images")
+
+    def list_annotations(self, image_id: str) -> List[Annotation]:
+        return self.request("GET", f"/images/{image_id}/annotations")
+
+    def create_annotation(self, project_id: str, image_id: str, annotation: CreateAnnotationRequest) -> Annotation:
+        return self.request("POST", f"/images/{image_id}/annotations", annotation, {"project_id": project_id})
+"#;
+
+/// TypedDict models for every schema definition plus a small urllib client
+pub fn python(schema: &Value) -> String {
+    let mut out = String::from(
+        "# Generated from doxle-shared types.rs. Do not edit by hand.\n\
+         # Requires Python 3.11+.\n\
+         from __future__ import annotations\n\n\
+         import json\n\
+         import urllib.parse\n\
+         import urllib.request\n\
+         from typing import Any, Dict, List, Literal, NotRequired, Optional, TypedDict, Union\n",
+    );
+    for (name, def) in definitions(schema) {
+        out.push_str("\n\n");
+        if def.get("properties").is_some() {
+            out.push_str(&py_class(name, def));
+            continue;
+        }
+        let options = variants(def);
+        if options.iter().all(|v| tag(v).is_some()) && !options.is_empty() {
+            // Tagged enum: one TypedDict per variant, then the union
+            let mut members = Vec::new();
+            for variant in options {
+                let member = format!("{}{}", name, pascal_case(tag(variant).unwrap_or_default()));
+                out.push_str(&py_class(&member, variant));
+                out.push_str("\n\n");
+                members.push(member);
+            }
+            out.push_str(&format!("{} = Union[{}]\n", name, members.join(", ")));
+        } else {
+            out.push_str(&format!("{} = {}\n", name, py_type(def)));
+        }
+    }
+    out.push('\n');
+    out.push_str(PY_CLIENT);
+    out
+}
+
+/// The model schema as JSON Schema, TypeScript or Python (`?format=`, default json)
+pub fn get_schema(format: Option<&str>) -> Result<Response<Body>, Error> {
+    let schema = schema();
+    let (content_type, body) = match format.unwrap_or("json") {
+        "json" => ("application/json", serde_json::to_string_pretty(&schema)?),
+        "typescript" | "ts" => ("text/plain; charset=utf-8", typescript(&schema)),
+        "python" | "py" => ("text/plain; charset=utf-8", python(&schema)),
+        other => {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(
+                    serde_json::json!({
+                        "error": format!("Unsupported format '{}', expected json, typescript or python", other)
+                    })
+                    .to_string()
+                    .into(),
+                )
+                .map_err(Box::new)?);
+        }
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", content_type)
+        .header("Cache-Control", "public, max-age=300")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(body.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_definitions() {
+        let schema = schema();
+        let definitions = schema["definitions"].as_object().unwrap();
+        for name in [
+            "Project",
+            "Annotation",
+            "Geometry",
+            "Keypoint",
+            "Skeleton",
+            "AttributeDef",
+            "CreateAnnotationRequest",
+        ] {
+            assert!(definitions.contains_key(name), "missing {}", name);
+        }
+    }
+
+    #[test]
+    fn test_typescript() {
+        let ts = typescript(&schema());
+        assert!(ts.contains("export interface Annotation {\n"));
+        assert!(ts.contains("  geometry: Geometry;\n"));
+        assert!(ts.contains("  updated_at?: string | null;\n"));
+        assert!(ts.contains("export type Geometry = { "));
+        assert!(ts.contains("type: \"polygon\";"));
+        assert!(ts.contains("  visibility: number;\n"));
+    }
+
+    #[test]
+    fn test_python() {
+        let py = python(&schema());
+        assert!(py.contains("class Annotation(TypedDict):\n"));
+        assert!(py.contains("    geometry: Geometry\n"));
+        assert!(py.contains("    updated_at: NotRequired[Optional[str]]\n"));
+        assert!(py.contains("class GeometryPolygon(TypedDict):\n"));
+        assert!(py.contains("    type: Literal[\"polygon\"]\n"));
+        assert!(py.contains("Geometry = Union[GeometryPolygon, GeometryBbox, GeometryPoint, "));
+        assert!(py.contains("BlockEvent = TypedDict(\"BlockEvent\", {\n"));
+        assert!(py.contains("    \"from\": \"NotRequired[Optional[str]]\",\n"));
+        assert!(py.contains("class DoxleClient:"));
+    }
+}
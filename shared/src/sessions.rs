@@ -0,0 +1,407 @@
+use crate::pagination::PageRequest;
+use crate::responses::json_response;
+use crate::types::{RevokeSessionRequest, Session};
+use aws_sdk_cognitoidentityprovider::Client as CognitoClient;
+use aws_sdk_dynamodb::{
+    types::{AttributeValue, ReturnValue},
+    Client as DynamoClient,
+};
+use base64::{engine::general_purpose, Engine as _};
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use sha2::{Digest, Sha256};
+
+/// Cognito's default refresh token validity; session rows expire with it
+const SESSION_TTL_DAYS: i64 = 30;
+
+/// Extract the `sub` claim from a Cognito ID token.
+/// The token comes straight from Cognito's InitiateAuth response, so the
+/// signature is not re-verified here.
+pub fn subject_from_id_token(id_token: &str) -> Option<String> {
+    let payload = id_token.split('.').nth(1)?;
+    let bytes = general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    claims.get("sub")?.as_str().map(|s| s.to_string())
+}
+
+/// Refresh tokens are never stored, only their SHA-256 hash
+fn token_hash(refresh_token: &str) -> String {
+    format!("{:x}", Sha256::digest(refresh_token.as_bytes()))
+}
+
+/// Record a new refresh-token session for a user after login.
+/// Writes USER#{uid} -> SESSION#{sid} plus a REFRESH#{hash} lookup item
+/// so `/refresh` can find the session from the token alone.
+pub async fn record_session(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    refresh_token: &str,
+    device_name: Option<&str>,
+) -> Result<String, Error> {
+    use std::collections::HashMap;
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now();
+    let expires = (now + chrono::Duration::days(SESSION_TTL_DAYS)).timestamp();
+    let hash = token_hash(refresh_token);
+
+    let mut session_item = HashMap::new();
+    session_item.insert(
+        "PK".to_string(),
+        AttributeValue::S(format!("USER#{}", user_id)),
+    );
+    session_item.insert(
+        "SK".to_string(),
+        AttributeValue::S(format!("SESSION#{}", session_id)),
+    );
+    session_item.insert(
+        "status".to_string(),
+        AttributeValue::S("active".to_string()),
+    );
+    session_item.insert("token_hash".to_string(), AttributeValue::S(hash.clone()));
+    session_item.insert("issued_at".to_string(), AttributeValue::S(now.to_rfc3339()));
+    session_item.insert("last_used".to_string(), AttributeValue::S(now.to_rfc3339()));
+    session_item.insert("ttl".to_string(), AttributeValue::N(expires.to_string()));
+    if let Some(device_name) = device_name.filter(|d| !d.trim().is_empty()) {
+        session_item.insert(
+            "device_name".to_string(),
+            AttributeValue::S(device_name.trim().to_string()),
+        );
+    }
+
+    let mut lookup_item = HashMap::new();
+    lookup_item.insert(
+        "PK".to_string(),
+        AttributeValue::S(format!("REFRESH#{}", hash)),
+    );
+    lookup_item.insert("SK".to_string(), AttributeValue::S("METADATA".to_string()));
+    lookup_item.insert(
+        "user_id".to_string(),
+        AttributeValue::S(user_id.to_string()),
+    );
+    lookup_item.insert(
+        "session_id".to_string(),
+        AttributeValue::S(session_id.clone()),
+    );
+    lookup_item.insert("ttl".to_string(), AttributeValue::N(expires.to_string()));
+
+    // Unprocessed items are retried; a session missing either row can't be revoked
+    let unwritten =
+        crate::annotations::batch_put_items(client, table_name, vec![session_item, lookup_item])
+            .await?;
+    if !unwritten.is_empty() {
+        return Err(format!("Session {} could not be recorded", session_id).into());
+    }
+
+    tracing::info!("Session recorded: {} (user: {})", session_id, user_id);
+    Ok(session_id)
+}
+
+/// Outcome of looking up the session behind a refresh token
+pub enum SessionCheck {
+    /// Token was issued before session tracking existed
+    Untracked,
+    Active {
+        session_id: String,
+        user_id: String,
+    },
+    Revoked,
+}
+
+/// Check that a refresh token's session has not been revoked and bump last_used
+pub async fn touch_session(
+    client: &DynamoClient,
+    table_name: &str,
+    refresh_token: &str,
+) -> Result<SessionCheck, Error> {
+    let lookup = client
+        .get_item()
+        .table_name(table_name)
+        .key(
+            "PK",
+            AttributeValue::S(format!("REFRESH#{}", token_hash(refresh_token))),
+        )
+        .key("SK", AttributeValue::S("METADATA".to_string()))
+        .send()
+        .await?;
+
+    let Some(item) = lookup.item() else {
+        return Ok(SessionCheck::Untracked);
+    };
+    let user_id = item
+        .get("user_id")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+    let session_id = item
+        .get("session_id")
+        .and_then(|v| v.as_s().ok())
+        .cloned()
+        .unwrap_or_default();
+
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("USER#{}", user_id)))
+        .key("SK", AttributeValue::S(format!("SESSION#{}", session_id)))
+        .update_expression("SET last_used = :now")
+        .condition_expression("attribute_exists(PK) AND #status = :active")
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_values(":now", AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+        .expression_attribute_values(":active", AttributeValue::S("active".to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(SessionCheck::Active {
+            session_id,
+            user_id,
+        }),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            tracing::warn!(
+                "Refresh attempted on revoked session {} (user: {})",
+                session_id,
+                user_id
+            );
+            Ok(SessionCheck::Revoked)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn session_from_item(
+    session_id: &str,
+    item: &std::collections::HashMap<String, AttributeValue>,
+) -> Session {
+    Session {
+        session_id: session_id.to_string(),
+        device_name: item
+            .get("device_name")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        status: item
+            .get("status")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "active".to_string()),
+        issued_at: item
+            .get("issued_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default(),
+        last_used: item
+            .get("last_used")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        revoked_at: item
+            .get("revoked_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+    }
+}
+
+/// Every session row of a user, across as many Query pages as they take
+async fn user_sessions(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<Vec<Session>, Error> {
+    let page = crate::pagination::query_prefix(
+        client,
+        table_name,
+        &format!("USER#{}", user_id),
+        "SESSION#",
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+    Ok(page
+        .items
+        .iter()
+        .filter_map(|item| {
+            let sk = item.get("SK").and_then(|v| v.as_s().ok())?;
+            let session_id = sk.strip_prefix("SESSION#")?;
+            Some(session_from_item(session_id, item))
+        })
+        .collect())
+}
+
+/// List the active sessions (devices) for the current user, most recently used first
+pub async fn list_sessions(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<Response<Body>, Error> {
+    let mut sessions: Vec<Session> = user_sessions(client, table_name, user_id)
+        .await?
+        .into_iter()
+        .filter(|s| s.status == "active")
+        .collect();
+
+    sessions.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&sessions)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Cognito app client credentials, for calls made on a user's behalf
+pub struct AppClient<'a> {
+    pub client_id: &'a str,
+    pub client_secret: &'a str,
+}
+
+/// Mark a session revoked. Returns the hash of its refresh token, or None
+/// when the user has no such session.
+async fn mark_revoked(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    session_id: &str,
+) -> Result<Option<String>, Error> {
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("USER#{}", user_id)))
+        .key("SK", AttributeValue::S(format!("SESSION#{}", session_id)))
+        .update_expression("SET #status = :revoked, revoked_at = :now")
+        .condition_expression("attribute_exists(PK)")
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_values(":revoked", AttributeValue::S("revoked".to_string()))
+        .expression_attribute_values(":now", AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+        .return_values(ReturnValue::AllOld)
+        .send()
+        .await;
+
+    match result {
+        Ok(output) => Ok(Some(
+            output
+                .attributes()
+                .and_then(|old| old.get("token_hash"))
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .unwrap_or_default(),
+        )),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Revoke a session so its refresh token can no longer be exchanged
+/// (DELETE /users/me/sessions/{sid}). Signing out the device itself, the
+/// client sends its refresh token (`{"refresh_token"}`) and it is revoked
+/// in Cognito too, taking the access tokens issued from it with it.
+/// Otherwise access tokens already issued remain valid until they expire
+/// (1 hour); DELETE /users/me/sessions ends them everywhere.
+pub async fn revoke_session(
+    cognito_client: &CognitoClient,
+    client: &DynamoClient,
+    table_name: &str,
+    app: &AppClient<'_>,
+    user_id: &str,
+    session_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: RevokeSessionRequest = if body.is_empty() {
+        RevokeSessionRequest::default()
+    } else {
+        serde_json::from_slice(body)?
+    };
+    let Some(stored_hash) = mark_revoked(client, table_name, user_id, session_id).await? else {
+        return json_response(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({"error": "Session not found"}),
+        );
+    };
+    // Only the session's own token is revoked, never one it was handed by mistake
+    if let Some(refresh_token) = req
+        .refresh_token
+        .filter(|token| token_hash(token) == stored_hash)
+    {
+        cognito_client
+            .revoke_token()
+            .client_id(app.client_id)
+            .client_secret(app.client_secret)
+            .token(refresh_token)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to revoke refresh token: {}", e))?;
+    }
+
+    tracing::info!("Session revoked: {} (user: {})", session_id, user_id);
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Access-Control-Allow-Origin", "*")
+        .body(Body::Empty)
+        .map_err(Box::new)?)
+}
+
+/// Sign a user out everywhere (DELETE /users/me/sessions): every session is
+/// revoked and Cognito's global sign-out invalidates all of the user's
+/// refresh and access tokens, including ones from before session tracking.
+pub async fn revoke_all_sessions(
+    cognito_client: &CognitoClient,
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<Response<Body>, Error> {
+    let user_pool_id =
+        std::env::var("COGNITO_USER_POOL_ID").map_err(|_| "COGNITO_USER_POOL_ID must be set")?;
+    cognito_client
+        .admin_user_global_sign_out()
+        .user_pool_id(user_pool_id)
+        .username(user_id)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to sign out user {}: {}", user_id, e))?;
+
+    let mut revoked = 0;
+    for session in user_sessions(client, table_name, user_id).await? {
+        if session.status == "active"
+            && mark_revoked(client, table_name, user_id, &session.session_id)
+                .await?
+                .is_some()
+        {
+            revoked += 1;
+        }
+    }
+
+    tracing::info!(
+        "Signed out everywhere: {} sessions (user: {})",
+        revoked,
+        user_id
+    );
+    json_response(StatusCode::OK, serde_json::json!({"revoked": revoked}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_from_id_token() {
+        let payload =
+            general_purpose::URL_SAFE_NO_PAD.encode(r#"{"sub":"abc-123","email":"a@b.com"}"#);
+        let token = format!("header.{}.signature", payload);
+        assert_eq!(subject_from_id_token(&token), Some("abc-123".to_string()));
+
+        // Malformed tokens yield no subject
+        assert_eq!(subject_from_id_token("not-a-jwt"), None);
+        assert_eq!(subject_from_id_token(""), None);
+    }
+}
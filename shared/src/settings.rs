@@ -0,0 +1,160 @@
+use crate::types::{OrgSettings, UpdateOrgSettingsRequest};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+
+/// Org-wide settings live in a single item
+const SETTINGS_PK: &str = "SETTINGS#ORG";
+const SETTINGS_SK: &str = "METADATA";
+
+/// Normalise a domain entry: lowercase, no leading '@' or whitespace
+fn normalize_domain(domain: &str) -> String {
+    domain.trim().trim_start_matches('@').to_lowercase()
+}
+
+/// Whether an email's domain is permitted. An empty allowlist permits everyone.
+pub fn email_domain_allowed(email: &str, allowed_domains: &[String]) -> bool {
+    if allowed_domains.is_empty() {
+        return true;
+    }
+    let Some((_, domain)) = email.trim().rsplit_once('@') else {
+        return false;
+    };
+    let domain = domain.to_lowercase();
+    allowed_domains
+        .iter()
+        .any(|allowed| normalize_domain(allowed) == domain)
+}
+
+/// Load org settings, falling back to defaults when none have been saved
+pub async fn load_org_settings(
+    client: &DynamoClient,
+    table_name: &str,
+) -> Result<OrgSettings, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(SETTINGS_PK.to_string()))
+        .key("SK", AttributeValue::S(SETTINGS_SK.to_string()))
+        .send()
+        .await?;
+
+    let Some(item) = result.item() else {
+        return Ok(OrgSettings::default());
+    };
+
+    Ok(OrgSettings {
+        allowed_invite_domains: item
+            .get("allowed_invite_domains")
+            .and_then(|v| v.as_s().ok())
+            .and_then(|s| serde_json::from_str(s).ok())
+            .unwrap_or_default(),
+        updated_at: item
+            .get("updated_at")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+        updated_by: item
+            .get("updated_by")
+            .and_then(|v| v.as_s().ok())
+            .map(|s| s.to_string()),
+    })
+}
+
+/// Get org settings (GET /admin/settings)
+pub async fn get_org_settings(
+    client: &DynamoClient,
+    table_name: &str,
+) -> Result<Response<Body>, Error> {
+    let settings = load_org_settings(client, table_name).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&settings)?.into())
+        .map_err(Box::new)?)
+}
+
+/// Update org settings (PATCH /admin/settings)
+pub async fn update_org_settings(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: UpdateOrgSettingsRequest = serde_json::from_slice(body)?;
+    let mut settings = load_org_settings(client, table_name).await?;
+
+    if let Some(domains) = req.allowed_invite_domains {
+        let mut domains: Vec<String> = domains
+            .iter()
+            .map(|d| normalize_domain(d))
+            .filter(|d| !d.is_empty())
+            .collect();
+        domains.sort();
+        domains.dedup();
+
+        if let Some(bad) = domains.iter().find(|d| !d.contains('.') || d.contains('@')) {
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("Content-Type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(
+                    serde_json::json!({"error": format!("Invalid domain '{}'", bad)})
+                        .to_string()
+                        .into(),
+                )
+                .map_err(Box::new)?);
+        }
+        settings.allowed_invite_domains = domains;
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    settings.updated_at = Some(now.clone());
+    settings.updated_by = Some(user_id.to_string());
+
+    client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(SETTINGS_PK.to_string()))
+        .item("SK", AttributeValue::S(SETTINGS_SK.to_string()))
+        .item(
+            "allowed_invite_domains",
+            AttributeValue::S(serde_json::to_string(&settings.allowed_invite_domains)?),
+        )
+        .item("updated_at", AttributeValue::S(now))
+        .item("updated_by", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+
+    tracing::info!("Org settings updated by {}", user_id);
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&settings)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_domain_allowed() {
+        let domains = vec!["builderco.com".to_string(), "@Partner.io".to_string()];
+
+        assert!(email_domain_allowed("jo@builderco.com", &domains));
+        assert!(email_domain_allowed("Jo@BUILDERCO.COM", &domains));
+        assert!(email_domain_allowed("sam@partner.io", &domains));
+        assert!(!email_domain_allowed("eve@gmail.com", &domains));
+        // Subdomains and lookalikes are not implicitly allowed
+        assert!(!email_domain_allowed("x@evil-builderco.com", &domains));
+        assert!(!email_domain_allowed("x@mail.builderco.com", &domains));
+        assert!(!email_domain_allowed("not-an-email", &domains));
+
+        // No allowlist configured
+        assert!(email_domain_allowed("anyone@anywhere.com", &[]));
+    }
+}
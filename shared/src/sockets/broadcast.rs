@@ -2,62 +2,126 @@ use aws_sdk_apigatewaymanagement::Client as ApiGatewayManagementClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
 use lambda_http::Error;
 use super::messages::BroadcastMessage;
-use super::connections::_get_all_connections;
+use super::connections::{get_user_connections, remove_connection};
 
-/// Broadcast a message to all connected WebSocket clients
-pub async fn _broadcast_to_all(
+/// Send a message to one connection, returning whether it was delivered.
+/// Connections API Gateway reports gone (closed without a $disconnect
+/// reaching us) are deleted so later broadcasts skip them.
+async fn post_to_connection(
     dynamo_client: &DynamoClient,
     api_gateway_client: &ApiGatewayManagementClient,
     table_name: &str,
+    connection_id: &str,
+    message_json: &str,
+) -> bool {
+    let result = api_gateway_client
+        .post_to_connection()
+        .connection_id(connection_id)
+        .data(message_json.as_bytes().to_vec().into())
+        .send()
+        .await;
+
+    let Err(e) = result else {
+        return true;
+    };
+    let gone = e
+        .as_service_error()
+        .map(|se| se.is_gone_exception())
+        .unwrap_or(false);
+    if !gone {
+        tracing::warn!("Failed to send to connection {}: {}", connection_id, e);
+        return false;
+    }
+    // As on $disconnect: free its locks, viewer and subscriber rows, then the connection
+    tracing::info!("Connection {} is gone, removing it", connection_id);
+    if let Err(e) = crate::locks::release_connection_locks(dynamo_client, table_name, connection_id).await {
+        tracing::warn!("Failed to release locks for {}: {}", connection_id, e);
+    }
+    if let Err(e) = super::presence::leave_image(dynamo_client, table_name, connection_id).await {
+        tracing::warn!("Failed to leave image for {}: {}", connection_id, e);
+    }
+    if let Err(e) = super::subscriptions::leave_project(dynamo_client, table_name, connection_id).await {
+        tracing::warn!("Failed to leave project for {}: {}", connection_id, e);
+    }
+    if let Err(e) = remove_connection(dynamo_client, table_name, connection_id).await {
+        tracing::warn!("Failed to remove stale connection {}: {}", connection_id, e);
+    }
+    false
+}
+
+/// Broadcast a message to the connections following a project, and to every
+/// connection of `member` (a user just added to it, who may not follow it yet)
+pub async fn _broadcast_to_project(
+    dynamo_client: &DynamoClient,
+    api_gateway_client: &ApiGatewayManagementClient,
+    table_name: &str,
+    project_id: &str,
+    member: Option<&str>,
     message: &BroadcastMessage,
 ) -> Result<(), Error> {
-    let connections = _get_all_connections(dynamo_client, table_name).await?;
+    let mut connection_ids =
+        super::subscriptions::project_subscribers(dynamo_client, table_name, project_id).await?;
+    if let Some(user_id) = member {
+        connection_ids.extend(
+            get_user_connections(dynamo_client, table_name, user_id)
+                .await?
+                .into_iter()
+                .map(|conn| conn.connection_id),
+        );
+        connection_ids.sort();
+        connection_ids.dedup();
+    }
     let message_json = serde_json::to_string(message)?;
-    
-    tracing::info!("Broadcasting to {} connections", connections.len());
-    
-    for conn in connections {
-        let result = api_gateway_client
-            .post_to_connection()
-            .connection_id(&conn.connection_id)
-            .data(message_json.as_bytes().to_vec().into())
-            .send()
-            .await;
-        
-        if let Err(e) = result {
-            tracing::warn!(
-                "Failed to send to connection {}: {}. Connection may be stale.",
-                conn.connection_id,
-                e
-            );
-            // Optionally: remove stale connection from DynamoDB
-            // remove_connection(dynamo_client, table_name, &conn.connection_id).await.ok();
+
+    tracing::info!("Broadcasting to {} connections of project {}", connection_ids.len(), project_id);
+
+    let mut failed = 0;
+    for connection_id in connection_ids {
+        if !post_to_connection(dynamo_client, api_gateway_client, table_name, &connection_id, &message_json).await {
+            failed += 1;
         }
     }
-    
+
+    crate::metrics::record(
+        dynamo_client,
+        table_name,
+        &[(crate::metrics::EVENTS_BROADCAST, 1), (crate::metrics::FAILED_DELIVERIES, failed)],
+    )
+    .await;
     Ok(())
 }
 
+/// Send a message to every connection of one user (e.g. the admin who sent an invite)
+pub async fn _broadcast_to_user(
+    dynamo_client: &DynamoClient,
+    api_gateway_client: &ApiGatewayManagementClient,
+    table_name: &str,
+    user_id: &str,
+    message: &BroadcastMessage,
+) -> Result<(), Error> {
+    let connection_ids: Vec<String> = get_user_connections(dynamo_client, table_name, user_id)
+        .await?
+        .into_iter()
+        .map(|conn| conn.connection_id)
+        .collect();
+
+    tracing::info!("Sending {} to {} connections of user {}", message.r#type, connection_ids.len(), user_id);
+    _broadcast_to_connections(dynamo_client, api_gateway_client, table_name, connection_ids, message).await
+}
+
 /// Broadcast to specific connections (e.g., by user_id or project_id)
 pub async fn _broadcast_to_connections(
+    dynamo_client: &DynamoClient,
     api_gateway_client: &ApiGatewayManagementClient,
+    table_name: &str,
     connection_ids: Vec<String>,
     message: &BroadcastMessage,
 ) -> Result<(), Error> {
     let message_json = serde_json::to_string(message)?;
-    
+
     for connection_id in connection_ids {
-        let result = api_gateway_client
-            .post_to_connection()
-            .connection_id(&connection_id)
-            .data(message_json.as_bytes().to_vec().into())
-            .send()
-            .await;
-        
-        if let Err(e) = result {
-            tracing::warn!("Failed to send to connection {}: {}", connection_id, e);
-        }
+        post_to_connection(dynamo_client, api_gateway_client, table_name, &connection_id, &message_json).await;
     }
-    
+
     Ok(())
 }
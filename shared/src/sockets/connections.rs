@@ -1,6 +1,25 @@
+use aws_sdk_dynamodb::types::{AttributeValue, Put, ReturnValue, TransactWriteItem};
 use aws_sdk_dynamodb::Client as DynamoClient;
+use crate::pagination::{self, PageRequest};
 use lambda_http::Error;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Partitions indexing every connection by user, so notifications find a
+/// user's sockets with a Query instead of scanning the table:
+/// CONNECTIONS#{shard} / USER#{uid}#CONNECTION#{cid}. Rows written before the
+/// index was sharded sit under plain CONNECTIONS until their `ttl`.
+pub const INDEX_PREFIX: &str = "CONNECTIONS";
+
+/// Index partitions; a user's rows all land in the one picked from their id,
+/// and connects and disconnects spread across the rest
+pub const INDEX_SHARDS: u32 = 16;
+
+/// API Gateway closes WebSocket connections after 2 hours, so a connection
+/// row older than that is dead even if its $disconnect never arrived. Rows
+/// carry a `ttl` this long after connecting; DynamoDB TTL deletes them
+/// eventually and reads skip them until it does.
+pub const CONNECTION_TTL_SECONDS: i64 = 2 * 3600;
 
 /// WebSocket connection stored in DynamoDB
 #[derive(Debug, Serialize, Deserialize)]
@@ -10,85 +29,249 @@ pub struct Connection {
     pub connected_at: String,
 }
 
-/// Save a WebSocket connection to DynamoDB
+/// Index partition of a user's connections (FNV-1a of the id, so it stays
+/// put across deploys)
+fn index_pk(user_id: &str) -> String {
+    let hash = user_id
+        .bytes()
+        .fold(0x811c9dc5_u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+    shard_pk(hash % INDEX_SHARDS)
+}
+
+pub(crate) fn shard_pk(shard: u32) -> String {
+    format!("{}#{}", INDEX_PREFIX, shard)
+}
+
+/// Sort key prefix of a user's connections in the index
+fn user_prefix(user_id: &str) -> String {
+    format!("USER#{}#", user_id)
+}
+
+fn index_sk(user_id: &str, connection_id: &str) -> String {
+    format!("{}CONNECTION#{}", user_prefix(user_id), connection_id)
+}
+
+/// Epoch second a connection made at `connected_at` has certainly closed by
+fn expires_epoch(connected_at: &str) -> Option<i64> {
+    let connected = chrono::DateTime::parse_from_rfc3339(connected_at).ok()?;
+    Some(connected.timestamp() + CONNECTION_TTL_SECONDS)
+}
+
+/// Whether a row's `ttl` has passed (rows without one never expire here)
+pub(crate) fn expired(item: &HashMap<String, AttributeValue>, now: i64) -> bool {
+    item.get("ttl")
+        .and_then(|v| v.as_n().ok())
+        .and_then(|n| n.parse::<i64>().ok())
+        .is_some_and(|ttl| ttl <= now)
+}
+
+fn ttl_value(connection: &Connection) -> AttributeValue {
+    let expires = expires_epoch(&connection.connected_at)
+        .unwrap_or_else(|| chrono::Utc::now().timestamp() + CONNECTION_TTL_SECONDS);
+    AttributeValue::N(expires.to_string())
+}
+
+/// Index row of a connection, pointing back at CONNECTION#{cid}
+fn index_item(connection: &Connection) -> HashMap<String, AttributeValue> {
+    HashMap::from([
+        ("PK".to_string(), AttributeValue::S(index_pk(&connection.user_id))),
+        ("SK".to_string(), AttributeValue::S(index_sk(&connection.user_id, &connection.connection_id))),
+        ("connection_id".to_string(), AttributeValue::S(connection.connection_id.clone())),
+        ("user_id".to_string(), AttributeValue::S(connection.user_id.clone())),
+        ("connected_at".to_string(), AttributeValue::S(connection.connected_at.clone())),
+        ("entity_type".to_string(), AttributeValue::S("connection_index".to_string())),
+        ("ttl".to_string(), ttl_value(connection)),
+    ])
+}
+
+/// Save a WebSocket connection to DynamoDB, with its index row
 pub async fn save_connection(
     client: &DynamoClient,
     table_name: &str,
     connection_id: &str,
     user_id: &str,
 ) -> Result<(), Error> {
-    let now = chrono::Utc::now().to_rfc3339();
+    let connection = Connection {
+        connection_id: connection_id.to_string(),
+        user_id: user_id.to_string(),
+        connected_at: chrono::Utc::now().to_rfc3339(),
+    };
     let pk = format!("CONNECTION#{}", connection_id);
-    
-    client
-        .put_item()
+    let put = Put::builder()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(pk.clone()))
+        .item("SK", AttributeValue::S(pk))
+        .item("connection_id", AttributeValue::S(connection.connection_id.clone()))
+        .item("user_id", AttributeValue::S(connection.user_id.clone()))
+        .item("connected_at", AttributeValue::S(connection.connected_at.clone()))
+        .item("entity_type", AttributeValue::S("connection".to_string()))
+        .item("ttl", ttl_value(&connection))
+        .build()?;
+
+    let index = Put::builder()
         .table_name(table_name)
-        .item("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
-        .item("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
-        .item("connection_id", aws_sdk_dynamodb::types::AttributeValue::S(connection_id.to_string()))
-        .item("user_id", aws_sdk_dynamodb::types::AttributeValue::S(user_id.to_string()))
-        .item("connected_at", aws_sdk_dynamodb::types::AttributeValue::S(now))
-        .item("entity_type", aws_sdk_dynamodb::types::AttributeValue::S("connection".to_string()))
+        .set_item(Some(index_item(&connection)))
+        .build()?;
+
+    client
+        .transact_write_items()
+        .transact_items(TransactWriteItem::builder().put(put).build())
+        .transact_items(TransactWriteItem::builder().put(index).build())
         .send()
         .await?;
-    
+
     tracing::info!("Connection saved: {} (user: {})", connection_id, user_id);
     Ok(())
 }
 
-/// Remove a WebSocket connection from DynamoDB
+/// Index a connection stored before the index existed, or delete it when it
+/// is older than any live connection can be; false for items that aren't
+/// connection rows
+pub async fn index_connection(
+    client: &DynamoClient,
+    table_name: &str,
+    item: &HashMap<String, AttributeValue>,
+) -> Result<bool, Error> {
+    let text = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    let (Some(pk), Some(sk)) = (text("PK"), text("SK")) else {
+        return Ok(false);
+    };
+    if pk != sk || !pk.starts_with("CONNECTION#") {
+        return Ok(false);
+    }
+    let (Some(connection_id), Some(user_id)) = (text("connection_id"), text("user_id")) else {
+        return Ok(false);
+    };
+    let connection = Connection {
+        connection_id,
+        user_id,
+        connected_at: text("connected_at").unwrap_or_default(),
+    };
+    let now = chrono::Utc::now().timestamp();
+    if expires_epoch(&connection.connected_at).is_some_and(|expires| expires <= now) {
+        remove_connection(client, table_name, &connection.connection_id).await?;
+        return Ok(true);
+    }
+    client
+        .put_item()
+        .table_name(table_name)
+        .set_item(Some(index_item(&connection)))
+        .send()
+        .await?;
+    Ok(true)
+}
+
+/// Remove a WebSocket connection from DynamoDB, with its index row
 pub async fn remove_connection(
     client: &DynamoClient,
     table_name: &str,
     connection_id: &str,
 ) -> Result<(), Error> {
     let pk = format!("CONNECTION#{}", connection_id);
-    
-    client
+
+    let removed = client
         .delete_item()
         .table_name(table_name)
-        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
-        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .key("PK", AttributeValue::S(pk.clone()))
+        .key("SK", AttributeValue::S(pk))
+        .return_values(ReturnValue::AllOld)
         .send()
         .await?;
-    
+
+    // The index row is keyed by the user the connection belonged to
+    if let Some(user_id) = removed
+        .attributes()
+        .and_then(|old| old.get("user_id"))
+        .and_then(|v| v.as_s().ok())
+    {
+        client
+            .delete_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(index_pk(user_id)))
+            .key("SK", AttributeValue::S(index_sk(user_id, connection_id)))
+            .send()
+            .await?;
+    }
+
     tracing::info!("Connection removed: {}", connection_id);
     Ok(())
 }
 
-/// Get all active WebSocket connections
-pub async fn _get_all_connections(
+/// Connections in an index partition whose sort key starts with
+/// `sk_prefix`, every page of them
+async fn query_connections(
     client: &DynamoClient,
     table_name: &str,
+    pk: &str,
+    sk_prefix: &str,
 ) -> Result<Vec<Connection>, Error> {
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        pk,
+        sk_prefix,
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+
+    let now = chrono::Utc::now().timestamp();
     let mut connections = Vec::new();
-    
-    let result = client
-        .scan()
-        .table_name(table_name)
-        .filter_expression("entity_type = :type")
-        .expression_attribute_values(
-            ":type",
-            aws_sdk_dynamodb::types::AttributeValue::S("connection".to_string()),
-        )
-        .send()
-        .await?;
-    
-    if let Some(items) = result.items {
-        for item in items {
-            if let (Some(conn_id), Some(user_id), Some(connected_at)) = (
-                item.get("connection_id").and_then(|v| v.as_s().ok()),
-                item.get("user_id").and_then(|v| v.as_s().ok()),
-                item.get("connected_at").and_then(|v| v.as_s().ok()),
-            ) {
-                connections.push(Connection {
-                    connection_id: conn_id.clone(),
-                    user_id: user_id.clone(),
-                    connected_at: connected_at.clone(),
-                });
-            }
+    for item in page.items.iter().filter(|item| !expired(item, now)) {
+        if let (Some(conn_id), Some(user_id), Some(connected_at)) = (
+            item.get("connection_id").and_then(|v| v.as_s().ok()),
+            item.get("user_id").and_then(|v| v.as_s().ok()),
+            item.get("connected_at").and_then(|v| v.as_s().ok()),
+        ) {
+            connections.push(Connection {
+                connection_id: conn_id.clone(),
+                user_id: user_id.clone(),
+                connected_at: connected_at.clone(),
+            });
         }
     }
-    
+
     Ok(connections)
 }
+
+/// Get the WebSocket connections of one user
+pub async fn get_user_connections(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<Vec<Connection>, Error> {
+    query_connections(client, table_name, &index_pk(user_id), &user_prefix(user_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_sk() {
+        assert_eq!(index_sk("u1", "c1"), "USER#u1#CONNECTION#c1");
+        // A user's prefix doesn't match users whose id merely starts with it
+        assert!(index_sk("u1", "c1").starts_with(&user_prefix("u1")));
+        assert!(!index_sk("u10", "c1").starts_with(&user_prefix("u1")));
+    }
+
+    #[test]
+    fn test_index_pk() {
+        // A user always lands in the same partition, and users spread out
+        assert_eq!(index_pk("u1"), index_pk("u1"));
+        let shards: std::collections::HashSet<_> = (0..100).map(|i| index_pk(&format!("user-{}", i))).collect();
+        assert!(shards.len() > INDEX_SHARDS as usize / 2);
+        assert!(shards.iter().all(|pk| (0..INDEX_SHARDS).any(|shard| *pk == shard_pk(shard))));
+    }
+
+    #[test]
+    fn test_expiry() {
+        assert_eq!(expires_epoch("2026-01-01T00:00:00+00:00"), Some(1767225600 + CONNECTION_TTL_SECONDS));
+        assert_eq!(expires_epoch("yesterday"), None);
+
+        let item = |ttl: &str| HashMap::from([("ttl".to_string(), AttributeValue::N(ttl.to_string()))]);
+        assert!(expired(&item("100"), 100));
+        assert!(!expired(&item("101"), 100));
+        assert!(!expired(&HashMap::new(), 100));
+    }
+}
@@ -1,7 +1,9 @@
 use super::connections::{remove_connection, save_connection};
 use super::messages::WebSocketMessage;
+use super::presence;
+use super::subscriptions;
 use crate::AppState;
-use crate::{annotations, blocks, classes, images, projects};
+use crate::{annotations, blocks, classes, images, locks, projects};
 use lambda_http::{http::StatusCode, Body, Error, Request, RequestExt, Response};
 use std::{env, sync::Arc};
 
@@ -90,6 +92,21 @@ async fn handle_disconnect(
 ) -> Result<Response<Body>, Error> {
     tracing::info!("WebSocket disconnect: {}", connection_id);
 
+    // Release locks held by this socket so a crashed browser doesn't strand them
+    if let Err(e) =
+        locks::release_connection_locks(&state.dynamo_client, table_name, connection_id).await
+    {
+        tracing::error!("Failed to release locks for {}: {}", connection_id, e);
+    }
+
+    if let Err(e) = presence::leave_image(&state.dynamo_client, table_name, connection_id).await {
+        tracing::error!("Failed to leave image for {}: {}", connection_id, e);
+    }
+
+    if let Err(e) = subscriptions::leave_project(&state.dynamo_client, table_name, connection_id).await {
+        tracing::error!("Failed to leave project for {}: {}", connection_id, e);
+    }
+
     // Remove connection from DynamoDB
     remove_connection(&state.dynamo_client, table_name, connection_id).await?;
 
@@ -99,12 +116,51 @@ async fn handle_disconnect(
         .map_err(Box::new)?)
 }
 
+/// Optional write option from message data, sent as a number or a string
+fn option_param(data: &serde_json::Value, name: &str) -> Option<String> {
+    data.get(name).map(|v| match v.as_str() {
+        Some(s) => s.to_string(),
+        None => v.to_string(),
+    })
+}
+
+/// Annotation write options (simplify, coordinates, image size, idempotency key) carried in message data
+struct SocketWriteOptions {
+    simplify: Option<String>,
+    coordinates: Option<String>,
+    image_width: Option<String>,
+    image_height: Option<String>,
+    idempotency_key: Option<String>,
+}
+
+impl SocketWriteOptions {
+    fn from_data(data: &serde_json::Value) -> Self {
+        Self {
+            simplify: option_param(data, "simplify"),
+            coordinates: option_param(data, "coordinates"),
+            image_width: option_param(data, "image_width"),
+            image_height: option_param(data, "image_height"),
+            idempotency_key: option_param(data, "idempotency_key"),
+        }
+    }
+
+    fn get(&self) -> annotations::WriteOptions<'_> {
+        annotations::WriteOptions {
+            simplify: self.simplify.as_deref(),
+            coordinates: self.coordinates.as_deref(),
+            image_width: self.image_width.as_deref(),
+            image_height: self.image_height.as_deref(),
+            idempotency_key: self.idempotency_key.as_deref(),
+        }
+    }
+}
+
 /// Handle $default event (incoming messages)
 async fn handle_message(
     event: Request,
     state: Arc<AppState>,
     table_name: &str,
-    _connection_id: &str,
+    connection_id: &str,
 ) -> Result<Response<Body>, Error> {
     let body = event.body();
 
@@ -141,8 +197,63 @@ async fn handle_message(
         })
         .unwrap_or_else(|| "test-user-123".to_string());
 
+    // Cursors and drawings in progress are relayed to the other viewers, never
+    // stored, so they keep working during maintenance
+    if presence::RELAY_ACTIONS.contains(&message.action.as_str()) {
+        return presence::relay(
+            &state.dynamo_client,
+            state.api_gateway_client.as_ref(),
+            table_name,
+            connection_id,
+            &user_id,
+            &message.action,
+            &message.data,
+        )
+        .await;
+    }
+
+    // Every other socket action writes, subscriptions included; connecting,
+    // disconnecting and notifications keep working
+    if let Some(retry_after) = crate::maintenance::retry_after() {
+        tracing::warn!("Rejected socket action {}: maintenance mode", message.action);
+        return crate::maintenance::unavailable(retry_after);
+    }
+
     // Route message to appropriate handler
     match message.action.as_str() {
+        // Changes are broadcast to the connections following their project
+        "subscribe_project" => {
+            let project_id = message
+                .data
+                .get("project_id")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing project_id")?;
+            subscriptions::subscribe_project(&state.dynamo_client, table_name, connection_id, &user_id, project_id).await
+        }
+        "unsubscribe_project" => {
+            subscriptions::leave_project(&state.dynamo_client, table_name, connection_id).await?;
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::Empty)
+                .map_err(Box::new)?)
+        }
+        // Who views which image, for cursor and drawing relays
+        "subscribe_image" => {
+            let image_id = message
+                .data
+                .get("image_id")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing image_id")?;
+            presence::view_image(&state.dynamo_client, table_name, connection_id, &user_id, image_id).await
+        }
+        "unsubscribe_image" => {
+            presence::leave_image(&state.dynamo_client, table_name, connection_id).await?;
+            Ok(Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::Empty)
+                .map_err(Box::new)?)
+        }
+
         // Project actions
         "create_project" => {
             let body_bytes = serde_json::to_vec(&message.data)?;
@@ -155,7 +266,7 @@ async fn handle_message(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing project_id")?;
             let body_bytes = serde_json::to_vec(&message.data)?;
-            projects::update_project(&state.dynamo_client, table_name, project_id, &body_bytes)
+            projects::update_project(&state.dynamo_client, table_name, &user_id, project_id, &body_bytes)
                 .await
         }
         "delete_project" => {
@@ -182,7 +293,7 @@ async fn handle_message(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing project_id")?;
             let body_bytes = serde_json::to_vec(&message.data)?;
-            blocks::create_block(&state.dynamo_client, table_name, project_id, &body_bytes).await
+            blocks::create_block(&state.dynamo_client, table_name, &user_id, project_id, &body_bytes).await
         }
         "update_block" => {
             let project_id = message
@@ -199,6 +310,7 @@ async fn handle_message(
             blocks::update_block(
                 &state.dynamo_client,
                 table_name,
+                &user_id,
                 project_id,
                 block_id,
                 &body_bytes,
@@ -220,6 +332,7 @@ async fn handle_message(
                 &state.dynamo_client,
                 &state.s3_client,
                 table_name,
+                &user_id,
                 project_id,
                 block_id,
             )
@@ -228,13 +341,27 @@ async fn handle_message(
 
         // Image actions
         "create_image" => {
+            let project_id = message
+                .data
+                .get("project_id")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing project_id")?;
             let block_id = message
                 .data
                 .get("block_id")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing block_id")?;
             let body_bytes = serde_json::to_vec(&message.data)?;
-            images::create_image(&state.dynamo_client, table_name, block_id, &body_bytes).await
+            images::create_image(
+                &state.dynamo_client,
+                &state.s3_client,
+                table_name,
+                &user_id,
+                project_id,
+                block_id,
+                &body_bytes,
+            )
+            .await
         }
         "update_image" => {
             let block_id = message
@@ -251,6 +378,7 @@ async fn handle_message(
             images::update_image(
                 &state.dynamo_client,
                 table_name,
+                &user_id,
                 block_id,
                 image_id,
                 &body_bytes,
@@ -268,7 +396,7 @@ async fn handle_message(
                 .get("image_id")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing image_id")?;
-            images::delete_image(&state.dynamo_client, table_name, block_id, image_id).await
+            images::delete_image(&state.dynamo_client, &state.s3_client, table_name, &user_id, block_id, image_id).await
         }
 
         // Class actions
@@ -279,7 +407,7 @@ async fn handle_message(
                 .and_then(|v| v.as_str())
                 .ok_or("Missing project_id")?;
             let body_bytes = serde_json::to_vec(&message.data)?;
-            classes::create_class(&state.dynamo_client, table_name, project_id, &body_bytes).await
+            classes::create_class(&state.dynamo_client, table_name, &user_id, project_id, &body_bytes).await
         }
         "update_class" => {
             let project_id = message
@@ -296,6 +424,7 @@ async fn handle_message(
             classes::update_class(
                 &state.dynamo_client,
                 table_name,
+                &user_id,
                 project_id,
                 class_id,
                 &body_bytes,
@@ -313,7 +442,7 @@ async fn handle_message(
                 .get("class_id")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing class_id")?;
-            classes::delete_class(&state.dynamo_client, table_name, project_id, class_id).await
+            classes::delete_class(&state.dynamo_client, table_name, &user_id, project_id, class_id).await
         }
 
         // Annotation actions
@@ -323,19 +452,14 @@ async fn handle_message(
                 .get("image_id")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing image_id")?;
-            let project_id = message
-                .data
-                .get("project_id")
-                .and_then(|v| v.as_str())
-                .ok_or("Missing project_id")?;
             let body_bytes = serde_json::to_vec(&message.data)?;
             annotations::create_annotation(
                 &state.dynamo_client,
                 table_name,
                 &user_id,
                 image_id,
-                project_id,
                 &body_bytes,
+                SocketWriteOptions::from_data(&message.data).get(),
             )
             .await
         }
@@ -350,19 +474,15 @@ async fn handle_message(
                 .get("annotation_id")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing annotation_id")?;
-            let project_id = message
-                .data
-                .get("project_id")
-                .and_then(|v| v.as_str())
-                .ok_or("Missing project_id")?;
             let body_bytes = serde_json::to_vec(&message.data)?;
             annotations::update_annotation(
                 &state.dynamo_client,
                 table_name,
+                &user_id,
                 image_id,
                 annotation_id,
-                project_id,
                 &body_bytes,
+                SocketWriteOptions::from_data(&message.data).get(),
             )
             .await
         }
@@ -377,21 +497,61 @@ async fn handle_message(
                 .get("annotation_id")
                 .and_then(|v| v.as_str())
                 .ok_or("Missing annotation_id")?;
-            let project_id = message
-                .data
-                .get("project_id")
-                .and_then(|v| v.as_str())
-                .ok_or("Missing project_id")?;
             annotations::delete_annotation(
                 &state.dynamo_client,
                 table_name,
+                &user_id,
                 image_id,
                 annotation_id,
-                project_id,
             )
             .await
         }
 
+        // Lock actions (acquire doubles as heartbeat)
+        "acquire_lock" | "release_lock" => {
+            let resource_type = message
+                .data
+                .get("resource_type")
+                .and_then(|v| v.as_str())
+                .filter(|t| locks::RESOURCE_TYPES.contains(t))
+                .ok_or("Missing or invalid resource_type")?;
+            let resource_id = message
+                .data
+                .get("resource_id")
+                .and_then(|v| v.as_str())
+                .ok_or("Missing resource_id")?;
+            if message.action == "acquire_lock" {
+                // Annotation locks name the image they're on, for the membership check
+                let image_id = match resource_type {
+                    "image" => resource_id,
+                    _ => message
+                        .data
+                        .get("image_id")
+                        .and_then(|v| v.as_str())
+                        .ok_or("Missing image_id")?,
+                };
+                locks::acquire_lock_response(
+                    &state.dynamo_client,
+                    table_name,
+                    resource_type,
+                    resource_id,
+                    image_id,
+                    &user_id,
+                    Some(connection_id),
+                )
+                .await
+            } else {
+                locks::release_lock_response(
+                    &state.dynamo_client,
+                    table_name,
+                    resource_type,
+                    resource_id,
+                    &user_id,
+                )
+                .await
+            }
+        }
+
         _ => {
             tracing::warn!("Unknown action: {}", message.action);
             Ok(Response::builder()
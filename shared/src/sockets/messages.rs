@@ -37,6 +37,20 @@ pub enum WebSocketAction {
     CreateClass,
     UpdateClass,
     DeleteClass,
+
+    // Lock actions
+    AcquireLock,
+    ReleaseLock,
+
+    // Subscription actions
+    SubscribeProject,
+    UnsubscribeProject,
+
+    // Presence actions (viewers are stored; cursors and drawings relayed, never stored)
+    SubscribeImage,
+    UnsubscribeImage,
+    CursorMove,
+    DrawingProgress,
 }
 
 /// Broadcast message sent to all clients
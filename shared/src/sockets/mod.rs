@@ -2,5 +2,7 @@ mod handler;
 pub mod connections;
 pub mod messages;
 pub mod broadcast;
+pub mod presence;
+pub mod subscriptions;
 
 pub use handler::handle_websocket_event;
@@ -0,0 +1,367 @@
+use super::broadcast::_broadcast_to_connections;
+use super::connections::{expired, CONNECTION_TTL_SECONDS};
+use super::messages::BroadcastMessage;
+use crate::pagination::{self, PageRequest};
+use crate::responses::json_response;
+use crate::{images, members, users};
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayManagementClient;
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Ephemeral actions relayed to the other viewers of an image and never
+/// stored: live cursors and shapes still being drawn
+pub const RELAY_ACTIONS: [&str; 2] = ["cursor_move", "drawing_progress"];
+
+/// Largest relayed payload; a point every frame of a freehand stroke stays
+/// well under it, and API Gateway frames top out at 128KB
+const MAX_RELAY_BYTES: usize = 32 * 1024;
+
+/// How long a Lambda instance trusts the viewers it knows of an image.
+/// Subscribes and unsubscribes handled here update the list as they happen,
+/// so relays fan out from memory; viewers who subscribed through another
+/// instance are read in when one of them relays through this one, or at the
+/// latest once the list is this old.
+const VIEWER_REFRESH: Duration = Duration::from_secs(30);
+
+/// Relays of one action from a connection closer together than this are
+/// dropped (about 30 a second): pointer events fire far faster than anyone
+/// watching can follow
+const MIN_RELAY_INTERVAL: Duration = Duration::from_millis(33);
+
+/// What a Lambda instance remembers between relays
+#[derive(Default)]
+struct RelayCache {
+    /// Image → when its viewers were last read from the table, and who views
+    /// it since
+    viewers: HashMap<String, (Instant, HashSet<String>)>,
+    /// (connection, action) → when it last relayed
+    last_relayed: HashMap<(String, String), Instant>,
+}
+
+impl RelayCache {
+    /// Whether a relay comes too soon after the connection's last one of the
+    /// same action; records it when it doesn't
+    fn throttled(&mut self, connection_id: &str, action: &str, now: Instant) -> bool {
+        let key = (connection_id.to_string(), action.to_string());
+        if let Some(last) = self.last_relayed.get(&key) {
+            if now.duration_since(*last) < MIN_RELAY_INTERVAL {
+                return true;
+            }
+        }
+        self.last_relayed
+            .retain(|_, last| now.duration_since(*last) < MIN_RELAY_INTERVAL);
+        self.last_relayed.insert(key, now);
+        false
+    }
+
+    /// Viewers of an image read recently enough, when the sender is among them
+    fn viewers(&self, image_id: &str, connection_id: &str, now: Instant) -> Option<Vec<String>> {
+        self.viewers
+            .get(image_id)
+            .filter(|(queried, _)| now.duration_since(*queried) < VIEWER_REFRESH)
+            .filter(|(_, viewers)| viewers.contains(connection_id))
+            .map(|(_, viewers)| viewers.iter().cloned().collect())
+    }
+
+    fn store_viewers(&mut self, image_id: &str, viewers: Vec<String>, now: Instant) {
+        self.viewers
+            .retain(|_, (queried, _)| now.duration_since(*queried) < VIEWER_REFRESH);
+        self.viewers.insert(image_id.to_string(), (now, viewers.into_iter().collect()));
+    }
+
+    /// A connection started viewing an image (and stopped viewing any other)
+    fn joined(&mut self, image_id: &str, connection_id: &str) {
+        self.left(connection_id);
+        if let Some((_, viewers)) = self.viewers.get_mut(image_id) {
+            viewers.insert(connection_id.to_string());
+        }
+    }
+
+    /// A connection stopped viewing whichever image it was
+    fn left(&mut self, connection_id: &str) {
+        for (_, viewers) in self.viewers.values_mut() {
+            viewers.remove(connection_id);
+        }
+    }
+}
+
+fn relay_cache() -> &'static Mutex<RelayCache> {
+    static CACHE: OnceLock<Mutex<RelayCache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// A connection views one image at a time; CONNECTION#{cid} / VIEWING
+/// points at it so $disconnect can find the viewer row
+const VIEWING_SK: &str = "VIEWING";
+
+/// Viewers of an image: VIEWERS#{iid} / CONNECTION#{cid}
+fn viewers_pk(image_id: &str) -> String {
+    format!("VIEWERS#{}", image_id)
+}
+
+/// Stop relaying to a connection (on unsubscribe, $disconnect, or once API
+/// Gateway reports it gone)
+pub async fn leave_image(
+    client: &DynamoClient,
+    table_name: &str,
+    connection_id: &str,
+) -> Result<(), Error> {
+    relay_cache().lock().unwrap_or_else(|e| e.into_inner()).left(connection_id);
+    let removed = client
+        .delete_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+        .key("SK", AttributeValue::S(VIEWING_SK.to_string()))
+        .return_values(ReturnValue::AllOld)
+        .send()
+        .await?;
+
+    if let Some(image_id) = removed
+        .attributes()
+        .and_then(|old| old.get("image_id"))
+        .and_then(|v| v.as_s().ok())
+    {
+        client
+            .delete_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(viewers_pk(image_id)))
+            .key("SK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Start relaying an image's cursors and drawings to a connection, leaving
+/// whichever image it was viewing before. Only members of the image's
+/// project (or admins) may view it; both rows expire with the connection.
+pub async fn view_image(
+    client: &DynamoClient,
+    table_name: &str,
+    connection_id: &str,
+    user_id: &str,
+    image_id: &str,
+) -> Result<Response<Body>, Error> {
+    let Some(location) = images::image_location(client, table_name, image_id).await? else {
+        return json_response(StatusCode::NOT_FOUND, serde_json::json!({"error": "Image not found"}));
+    };
+    if !members::is_member(client, table_name, &location.project_id, user_id).await?
+        && !users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(StatusCode::FORBIDDEN, serde_json::json!({"error": "Not a member of this project"}));
+    }
+
+    leave_image(client, table_name, connection_id).await?;
+
+    let ttl = AttributeValue::N((chrono::Utc::now().timestamp() + CONNECTION_TTL_SECONDS).to_string());
+    client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(viewers_pk(image_id)))
+        .item("SK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+        .item("connection_id", AttributeValue::S(connection_id.to_string()))
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item("entity_type", AttributeValue::S("image_viewer".to_string()))
+        .item("ttl", ttl.clone())
+        .send()
+        .await?;
+    client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+        .item("SK", AttributeValue::S(VIEWING_SK.to_string()))
+        .item("image_id", AttributeValue::S(image_id.to_string()))
+        .item("ttl", ttl)
+        .send()
+        .await?;
+    relay_cache().lock().unwrap_or_else(|e| e.into_inner()).joined(image_id, connection_id);
+
+    json_response(StatusCode::OK, serde_json::json!({"image_id": image_id}))
+}
+
+/// Message relayed for an action: the sender's payload, with who sent it
+/// filled in here so it can't be spoofed
+fn relay_message(
+    action: &str,
+    user_id: &str,
+    connection_id: &str,
+    data: &serde_json::Value,
+) -> Result<BroadcastMessage, String> {
+    let Some(fields) = data.as_object() else {
+        return Err("Message data must be an object".to_string());
+    };
+    if fields.get("image_id").and_then(|v| v.as_str()).is_none() {
+        return Err("Missing image_id".to_string());
+    }
+    let size = data.to_string().len();
+    if size > MAX_RELAY_BYTES {
+        return Err(format!("{} is {} bytes, at most {} are relayed", action, size, MAX_RELAY_BYTES));
+    }
+
+    let mut payload = fields.clone();
+    payload.insert("user_id".to_string(), user_id.into());
+    payload.insert("connection_id".to_string(), connection_id.into());
+    Ok(BroadcastMessage::_new(action, serde_json::Value::Object(payload)))
+}
+
+/// Connections viewing an image, skipping rows past their `ttl`
+async fn query_viewers(
+    client: &DynamoClient,
+    table_name: &str,
+    image_id: &str,
+) -> Result<Vec<String>, Error> {
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        &viewers_pk(image_id),
+        "CONNECTION#",
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+
+    let now = chrono::Utc::now().timestamp();
+    Ok(page
+        .items
+        .iter()
+        .filter(|item| !expired(item, now))
+        .filter_map(|item| item.get("connection_id").and_then(|v| v.as_s().ok()).cloned())
+        .collect())
+}
+
+/// Relay a `RELAY_ACTIONS` message to every other connection viewing its
+/// image; the sender must be among the viewers. Nothing is written, and the
+/// viewers come from this instance's cache, only queried when the sender
+/// isn't in it or it is older than `VIEWER_REFRESH`. Relays faster than
+/// `MIN_RELAY_INTERVAL` are dropped.
+pub async fn relay(
+    dynamo_client: &DynamoClient,
+    api_gateway_client: Option<&ApiGatewayManagementClient>,
+    table_name: &str,
+    connection_id: &str,
+    user_id: &str,
+    action: &str,
+    data: &serde_json::Value,
+) -> Result<Response<Body>, Error> {
+    let message = match relay_message(action, user_id, connection_id, data) {
+        Ok(message) => message,
+        Err(e) => return json_response(StatusCode::BAD_REQUEST, serde_json::json!({"error": e})),
+    };
+    let Some(api_gateway_client) = api_gateway_client else {
+        return json_response(StatusCode::SERVICE_UNAVAILABLE, serde_json::json!({"error": "WebSocket API not configured"}));
+    };
+    let image_id = message.data["image_id"].as_str().unwrap_or_default();
+
+    let now = Instant::now();
+    let cached = {
+        let mut cache = relay_cache().lock().unwrap_or_else(|e| e.into_inner());
+        if cache.throttled(connection_id, action, now) {
+            return Ok(Response::builder()
+                .status(StatusCode::OK)
+                .body(Body::Empty)
+                .map_err(Box::new)?);
+        }
+        cache.viewers(image_id, connection_id, now)
+    };
+    let viewers = match cached {
+        Some(viewers) => viewers,
+        None => {
+            let viewers = query_viewers(dynamo_client, table_name, image_id).await?;
+            relay_cache()
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .store_viewers(image_id, viewers.clone(), now);
+            viewers
+        }
+    };
+    if !viewers.iter().any(|id| id == connection_id) {
+        return json_response(StatusCode::FORBIDDEN, serde_json::json!({"error": "Subscribe to the image first"}));
+    }
+
+    let others: Vec<String> = viewers.into_iter().filter(|id| id != connection_id).collect();
+    _broadcast_to_connections(dynamo_client, api_gateway_client, table_name, others, &message).await?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::Empty)
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relay_message() {
+        let data = serde_json::json!({"image_id": "i1", "x": 0.5, "y": 0.25, "user_id": "spoofed"});
+        let message = relay_message("cursor_move", "u1", "c1", &data).unwrap();
+        assert_eq!(message.r#type, "cursor_move");
+        assert_eq!(message.data["x"], 0.5);
+        // The sender is whoever the socket belongs to
+        assert_eq!(message.data["user_id"], "u1");
+        assert_eq!(message.data["connection_id"], "c1");
+
+        assert!(relay_message("cursor_move", "u1", "c1", &serde_json::json!({"x": 1})).is_err());
+        assert!(relay_message("cursor_move", "u1", "c1", &serde_json::json!("i1")).is_err());
+
+        let points = vec![[0.123456789_f64, 0.987654321_f64]; 2000];
+        let large = serde_json::json!({"image_id": "i1", "points": points});
+        assert!(relay_message("drawing_progress", "u1", "c1", &large).is_err());
+    }
+
+    #[test]
+    fn test_relay_throttle() {
+        let mut cache = RelayCache::default();
+        let start = Instant::now();
+        assert!(!cache.throttled("c1", "cursor_move", start));
+        assert!(cache.throttled("c1", "cursor_move", start + Duration::from_millis(10)));
+        // Other actions and connections keep their own pace
+        assert!(!cache.throttled("c1", "drawing_progress", start + Duration::from_millis(10)));
+        assert!(!cache.throttled("c2", "cursor_move", start + Duration::from_millis(10)));
+        assert!(!cache.throttled("c1", "cursor_move", start + MIN_RELAY_INTERVAL));
+    }
+
+    #[test]
+    fn test_cached_viewers() {
+        let mut cache = RelayCache::default();
+        let start = Instant::now();
+        assert_eq!(cache.viewers("i1", "c1", start), None);
+
+        cache.store_viewers("i1", vec!["c1".to_string(), "c2".to_string()], start);
+        assert_eq!(cache.viewers("i1", "c1", start + Duration::from_millis(500)).map(|v| v.len()), Some(2));
+        // A sender that subscribed through another instance is looked up afresh
+        assert_eq!(cache.viewers("i1", "c3", start), None);
+        assert_eq!(cache.viewers("i1", "c1", start + VIEWER_REFRESH), None);
+
+        // Subscribes and unsubscribes here keep the list current
+        cache.joined("i1", "c3");
+        assert_eq!(cache.viewers("i1", "c3", start).map(|v| v.len()), Some(3));
+        cache.left("c2");
+        assert_eq!(cache.viewers("i1", "c1", start).map(|v| v.len()), Some(2));
+        cache.store_viewers("i2", Vec::new(), start);
+        cache.joined("i2", "c1");
+        assert_eq!(cache.viewers("i1", "c3", start), Some(vec!["c3".to_string()]));
+        assert_eq!(cache.viewers("i2", "c1", start), Some(vec!["c1".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_relay_from_cache() {
+        let (dynamo, calls) = crate::test_util::fake_dynamo(Vec::new());
+        let (api_gateway, posts) = crate::test_util::accepting_api_gateway();
+        relay_cache()
+            .lock()
+            .unwrap()
+            .store_viewers("relay-img", vec!["relay-c1".to_string(), "relay-c2".to_string()], Instant::now());
+
+        let data = serde_json::json!({"image_id": "relay-img", "x": 1.0, "y": 2.0});
+        let response = relay(&dynamo, Some(&api_gateway), "table", "relay-c1", "u1", "cursor_move", &data).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(calls.lock().unwrap().is_empty());
+        assert_eq!(posts.lock().unwrap().len(), 1);
+        assert!(posts.lock().unwrap()[0].ends_with("/@connections/relay-c2"));
+    }
+}
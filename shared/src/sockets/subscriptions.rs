@@ -0,0 +1,141 @@
+use super::connections::{expired, CONNECTION_TTL_SECONDS};
+use crate::pagination::{self, PageRequest};
+use crate::responses::json_response;
+use crate::{members, users};
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+
+/// Connections following a project's changes: SUBSCRIBERS#{pid} /
+/// CONNECTION#{cid}. Broadcasts of a change query only its project's rows.
+pub const SUBSCRIBERS_PREFIX: &str = "SUBSCRIBERS#";
+
+/// A connection follows one project at a time; CONNECTION#{cid} / SUBSCRIBED
+/// points at it so $disconnect can find the subscriber row
+const SUBSCRIBED_SK: &str = "SUBSCRIBED";
+
+fn subscribers_pk(project_id: &str) -> String {
+    format!("{}{}", SUBSCRIBERS_PREFIX, project_id)
+}
+
+/// The project a connection follows, if any
+pub async fn subscribed_project(
+    client: &DynamoClient,
+    table_name: &str,
+    connection_id: &str,
+) -> Result<Option<String>, Error> {
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+        .key("SK", AttributeValue::S(SUBSCRIBED_SK.to_string()))
+        .send()
+        .await?;
+    let now = chrono::Utc::now().timestamp();
+    Ok(result
+        .item()
+        .filter(|item| !expired(item, now))
+        .and_then(|item| item.get("project_id"))
+        .and_then(|v| v.as_s().ok())
+        .cloned())
+}
+
+/// Stop sending a connection its project's changes (on unsubscribe,
+/// $disconnect, or once API Gateway reports it gone)
+pub async fn leave_project(
+    client: &DynamoClient,
+    table_name: &str,
+    connection_id: &str,
+) -> Result<(), Error> {
+    let removed = client
+        .delete_item()
+        .table_name(table_name)
+        .key("PK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+        .key("SK", AttributeValue::S(SUBSCRIBED_SK.to_string()))
+        .return_values(ReturnValue::AllOld)
+        .send()
+        .await?;
+
+    if let Some(project_id) = removed
+        .attributes()
+        .and_then(|old| old.get("project_id"))
+        .and_then(|v| v.as_s().ok())
+    {
+        client
+            .delete_item()
+            .table_name(table_name)
+            .key("PK", AttributeValue::S(subscribers_pk(project_id)))
+            .key("SK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+/// Start sending a connection the changes of a project, leaving whichever
+/// project it followed before. Only members of the project (or admins) may
+/// follow it; both rows expire with the connection.
+pub async fn subscribe_project(
+    client: &DynamoClient,
+    table_name: &str,
+    connection_id: &str,
+    user_id: &str,
+    project_id: &str,
+) -> Result<Response<Body>, Error> {
+    if !members::is_member(client, table_name, project_id, user_id).await?
+        && !users::is_admin(client, table_name, user_id).await?
+    {
+        return json_response(StatusCode::FORBIDDEN, serde_json::json!({"error": "Not a member of this project"}));
+    }
+
+    leave_project(client, table_name, connection_id).await?;
+
+    let ttl = AttributeValue::N((chrono::Utc::now().timestamp() + CONNECTION_TTL_SECONDS).to_string());
+    client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(subscribers_pk(project_id)))
+        .item("SK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+        .item("connection_id", AttributeValue::S(connection_id.to_string()))
+        .item("user_id", AttributeValue::S(user_id.to_string()))
+        .item("entity_type", AttributeValue::S("project_subscriber".to_string()))
+        .item("ttl", ttl.clone())
+        .send()
+        .await?;
+    client
+        .put_item()
+        .table_name(table_name)
+        .item("PK", AttributeValue::S(format!("CONNECTION#{}", connection_id)))
+        .item("SK", AttributeValue::S(SUBSCRIBED_SK.to_string()))
+        .item("project_id", AttributeValue::S(project_id.to_string()))
+        .item("ttl", ttl)
+        .send()
+        .await?;
+
+    json_response(StatusCode::OK, serde_json::json!({"project_id": project_id}))
+}
+
+/// Connections following a project, skipping rows past their `ttl`
+pub async fn project_subscribers(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+) -> Result<Vec<String>, Error> {
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        &subscribers_pk(project_id),
+        "CONNECTION#",
+        None,
+        &PageRequest::default(),
+    )
+    .await?;
+
+    let now = chrono::Utc::now().timestamp();
+    Ok(page
+        .items
+        .iter()
+        .filter(|item| !expired(item, now))
+        .filter_map(|item| item.get("connection_id").and_then(|v| v.as_s().ok()).cloned())
+        .collect())
+}
@@ -0,0 +1,370 @@
+use crate::region;
+use std::env;
+use std::sync::OnceLock;
+
+/// Every object this service writes lives under this prefix (after the
+/// deployment's own key prefix, if it has one)
+const KEY_PREFIX: &str = "projects/";
+
+const DEFAULT_BUCKET: &str = "doxle-annotations";
+
+/// Where this deployment keeps its objects: the bucket (BUCKET_NAME), an
+/// optional key prefix so several tenants or stages can share one bucket
+/// (S3_KEY_PREFIX), and the bucket's region (S3_REGION, defaulting to the
+/// lambda's own)
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3Config {
+    pub bucket: String,
+    pub key_prefix: String, // empty, or ending in '/'
+    pub region: String,
+}
+
+impl S3Config {
+    pub fn from_env() -> Self {
+        Self::from_vars(
+            env::var("BUCKET_NAME").ok().as_deref(),
+            env::var("S3_KEY_PREFIX").ok().as_deref(),
+            env::var("S3_REGION").ok().as_deref(),
+        )
+    }
+
+    fn from_vars(bucket: Option<&str>, key_prefix: Option<&str>, region: Option<&str>) -> Self {
+        fn non_empty(value: Option<&str>) -> Option<&str> {
+            value.map(str::trim).filter(|v| !v.is_empty())
+        }
+        let key_prefix = non_empty(key_prefix)
+            .map(|p| p.trim_matches('/'))
+            .filter(|p| !p.is_empty())
+            .map(|p| format!("{}/", p))
+            .unwrap_or_default();
+        S3Config {
+            bucket: non_empty(bucket).unwrap_or(DEFAULT_BUCKET).to_string(),
+            key_prefix,
+            region: non_empty(region)
+                .map(|r| r.to_string())
+                .unwrap_or_else(region::current),
+        }
+    }
+
+    /// Folder holding every project, `{key_prefix}projects/`
+    pub fn projects_root(&self) -> String {
+        format!("{}{}", self.key_prefix, KEY_PREFIX)
+    }
+
+    /// Folder of a project's objects, `{key_prefix}projects/{pid}/`
+    pub fn project_prefix(&self, project_id: &str) -> String {
+        format!("{}{}/", self.projects_root(), project_id)
+    }
+
+    /// Folder of a block's images, `{key_prefix}projects/{pid}/blocks/{bid}/`
+    pub fn block_prefix(&self, project_id: &str, block_id: &str) -> String {
+        format!("{}blocks/{}/", self.project_prefix(project_id), block_id)
+    }
+
+    /// A key relative to the projects root (`{pid}/...`), or None for keys
+    /// outside it
+    pub fn project_path<'a>(&self, key: &'a str) -> Option<&'a str> {
+        key.strip_prefix(self.key_prefix.as_str())?
+            .strip_prefix(KEY_PREFIX)
+    }
+}
+
+/// S3 configuration of this deployment, read from the environment once
+pub fn s3_config() -> &'static S3Config {
+    static CONFIG: OnceLock<S3Config> = OnceLock::new();
+    CONFIG.get_or_init(S3Config::from_env)
+}
+
+/// `S3Config::block_prefix` of this deployment
+pub fn block_prefix(project_id: &str, block_id: &str) -> String {
+    s3_config().block_prefix(project_id, block_id)
+}
+
+/// Key of an upload, `{block prefix}{iid}.{ext}`; anything derived from it
+/// goes in the image's folder, `{block prefix}{iid}/`
+pub fn upload_key(project_id: &str, block_id: &str, image_id: &str, extension: &str) -> String {
+    format!(
+        "{}{}.{}",
+        block_prefix(project_id, block_id),
+        image_id,
+        extension
+    )
+}
+
+/// Path of the image proxy route, relative to the API root
+const PROXY_PATH: &str = "proxy-image/";
+
+const DEFAULT_PROXY_BASE: &str = "https://api.doxle.ai/proxy-image/";
+
+/// How object URLs handed to clients are built (STORAGE_URL_MODE)
+#[derive(Debug, Clone, PartialEq)]
+pub enum UrlMode {
+    /// Virtual-hosted URL of this region's bucket (the default)
+    Direct,
+    /// `https://{CLOUDFRONT_DOMAIN}/{key}`, readable with the signed cookies
+    Cdn(String),
+    /// Through the API's /proxy-image/ route (PROXY_BASE_URL)
+    Proxy(String),
+}
+
+/// Resolve the URL mode from its settings. "cdn" without a domain falls
+/// back to direct URLs rather than producing unusable ones.
+fn mode_from(mode: Option<&str>, cdn_domain: Option<&str>, proxy_base: Option<&str>) -> UrlMode {
+    match mode.map(|m| m.trim().to_lowercase()).as_deref() {
+        Some("cdn") => match cdn_domain.map(|d| d.trim_end_matches('/')) {
+            Some(domain) if !domain.is_empty() => UrlMode::Cdn(domain.to_string()),
+            _ => {
+                tracing::warn!(
+                    "STORAGE_URL_MODE=cdn but CLOUDFRONT_DOMAIN is not set; using direct URLs"
+                );
+                UrlMode::Direct
+            }
+        },
+        Some("proxy") => {
+            let base = proxy_base
+                .unwrap_or(DEFAULT_PROXY_BASE)
+                .trim_end_matches('/');
+            UrlMode::Proxy(format!("{}/", base))
+        }
+        _ => UrlMode::Direct,
+    }
+}
+
+/// URL mode for this deployment
+pub fn url_mode() -> UrlMode {
+    mode_from(
+        env::var("STORAGE_URL_MODE").ok().as_deref(),
+        env::var("CLOUDFRONT_DOMAIN").ok().as_deref(),
+        env::var("PROXY_BASE_URL").ok().as_deref(),
+    )
+}
+
+/// Virtual-hosted URL of an object in a regional bucket
+pub fn s3_url(bucket: &str, region: &str, key: &str) -> String {
+    format!("https://{}.s3.{}.amazonaws.com/{}", bucket, region, key)
+}
+
+fn render(mode: &UrlMode, key: &str) -> String {
+    match mode {
+        UrlMode::Direct => {
+            let config = s3_config();
+            s3_url(&config.bucket, &config.region, key)
+        }
+        UrlMode::Cdn(domain) => format!("https://{}/{}", domain, key),
+        UrlMode::Proxy(base) => format!("{}{}", base, key),
+    }
+}
+
+/// URL of an object in this region's bucket, in the deployment's URL mode.
+/// Whatever form gets stored, readers resolve it with `parse_object_url`,
+/// so the key always maps onto the local bucket.
+pub fn object_url(key: &str) -> String {
+    render(&url_mode(), key)
+}
+
+/// URL serving an object through the image proxy, whatever the URL mode
+pub fn proxy_url(key: &str) -> String {
+    let base = env::var("PROXY_BASE_URL").ok();
+    render(&mode_from(Some("proxy"), None, base.as_deref()), key)
+}
+
+/// Where a stored URL points
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectRef {
+    pub bucket: Option<String>, // only S3 URLs name their bucket
+    pub key: String,
+}
+
+/// Recognise any URL form this service has handed out: S3 virtual-hosted
+/// (`{bucket}.s3[.{region}].amazonaws.com/{key}`), S3 path-style
+/// (`s3[.{region}].amazonaws.com/{bucket}/{key}`), the image proxy (absolute
+/// or as a bare `/proxy-image/{key}` path) and CDN URLs of project objects.
+pub fn parse_object_url(url: &str) -> Option<ObjectRef> {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let (host, path) = match url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+    {
+        Some(rest) => rest.split_once('/')?,
+        None => ("", url.strip_prefix('/')?),
+    };
+
+    let object = |bucket: Option<&str>, key: &str| {
+        (!key.is_empty()).then(|| ObjectRef {
+            bucket: bucket.map(|b| b.to_string()),
+            key: key.to_string(),
+        })
+    };
+
+    if let Some(key) = path.strip_prefix(PROXY_PATH) {
+        return object(None, key);
+    }
+    if let Some(name) = host.strip_suffix(".amazonaws.com") {
+        // The "s3" label (or "s3-{region}") separates the bucket from the endpoint
+        let labels: Vec<&str> = name.split('.').collect();
+        let s3 = labels
+            .iter()
+            .position(|l| *l == "s3" || l.starts_with("s3-"))?;
+        if s3 == 0 {
+            let (bucket, key) = path.split_once('/')?;
+            return object(Some(bucket), key);
+        }
+        return object(Some(&labels[..s3].join(".")), path);
+    }
+    if s3_config().project_path(path).is_some() {
+        return object(None, path);
+    }
+    None
+}
+
+/// S3 key behind a stored URL
+pub fn object_key(url: &str) -> Option<String> {
+    parse_object_url(url).map(|o| o.key)
+}
+
+/// `CopySource` of an object for `copy_object`: its bucket and key, with the
+/// key URL-encoded except for its slashes
+pub fn copy_source(bucket: &str, key: &str) -> String {
+    let mut encoded = String::new();
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    format!("{}/{}", bucket, encoded)
+}
+
+/// The URL to hand a client for a stored reference: re-rendered in the
+/// current URL mode, or unchanged when it isn't one of ours
+pub fn public_url(stored: &str) -> String {
+    match object_key(stored) {
+        Some(key) => object_url(&key),
+        None => stored.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_config() {
+        let config =
+            S3Config::from_vars(Some("tenant-bucket"), Some("/staging/"), Some("us-west-2"));
+        assert_eq!(config.bucket, "tenant-bucket");
+        assert_eq!(config.region, "us-west-2");
+        assert_eq!(
+            config.block_prefix("p", "b"),
+            "staging/projects/p/blocks/b/"
+        );
+        assert_eq!(
+            config.project_path("staging/projects/p/blocks/b/i.png"),
+            Some("p/blocks/b/i.png")
+        );
+        assert_eq!(config.project_path("projects/p/blocks/b/i.png"), None);
+
+        let config = S3Config::from_vars(None, Some(" "), None);
+        assert_eq!(config.bucket, DEFAULT_BUCKET);
+        assert_eq!(config.key_prefix, "");
+        assert_eq!(config.projects_root(), "projects/");
+        assert_eq!(config.project_path("projects/p/i.png"), Some("p/i.png"));
+    }
+
+    #[test]
+    fn test_s3_url() {
+        assert_eq!(
+            s3_url(
+                "doxle-annotations-usw2",
+                "us-west-2",
+                "projects/p/blocks/b/i.png"
+            ),
+            "https://doxle-annotations-usw2.s3.us-west-2.amazonaws.com/projects/p/blocks/b/i.png"
+        );
+    }
+
+    #[test]
+    fn test_copy_source() {
+        assert_eq!(
+            copy_source(
+                "doxle-annotations",
+                "projects/p/blocks/b/plan level 1+é.png"
+            ),
+            "doxle-annotations/projects/p/blocks/b/plan%20level%201%2B%C3%A9.png"
+        );
+    }
+
+    #[test]
+    fn test_mode_from() {
+        assert_eq!(mode_from(None, Some("cdn.doxle.ai"), None), UrlMode::Direct);
+        assert_eq!(
+            mode_from(Some("CDN"), Some("cdn.doxle.ai/"), None),
+            UrlMode::Cdn("cdn.doxle.ai".to_string())
+        );
+        assert_eq!(mode_from(Some("cdn"), None, None), UrlMode::Direct);
+        assert_eq!(
+            mode_from(Some("proxy"), None, None),
+            UrlMode::Proxy(DEFAULT_PROXY_BASE.to_string())
+        );
+        assert_eq!(
+            mode_from(
+                Some("proxy"),
+                None,
+                Some("https://staging.doxle.ai/proxy-image")
+            ),
+            UrlMode::Proxy("https://staging.doxle.ai/proxy-image/".to_string())
+        );
+        assert_eq!(
+            render(
+                &UrlMode::Cdn("cdn.doxle.ai".to_string()),
+                "projects/p/i.png"
+            ),
+            "https://cdn.doxle.ai/projects/p/i.png"
+        );
+    }
+
+    #[test]
+    fn test_parse_object_url() {
+        let parsed = |url: &str| parse_object_url(url).map(|o| (o.bucket, o.key));
+        let s3 = |bucket: &str, key: &str| Some((Some(bucket.to_string()), key.to_string()));
+        let keyed = |key: &str| Some((None, key.to_string()));
+
+        assert_eq!(
+            parsed("https://doxle-annotations.s3.amazonaws.com/projects/p/blocks/b/i.png"),
+            s3("doxle-annotations", "projects/p/blocks/b/i.png")
+        );
+        // Replica bucket in a second region
+        assert_eq!(
+            parsed("https://doxle-annotations-usw2.s3.us-west-2.amazonaws.com/projects/p/blocks/b/i.png"),
+            s3("doxle-annotations-usw2", "projects/p/blocks/b/i.png")
+        );
+        assert_eq!(
+            parsed("https://doxle.annotations.s3-ap-southeast-2.amazonaws.com/projects/p/i.png"),
+            s3("doxle.annotations", "projects/p/i.png")
+        );
+        // Path-style
+        assert_eq!(
+            parsed("https://s3.ap-southeast-2.amazonaws.com/doxle-annotations/projects/p/i.png"),
+            s3("doxle-annotations", "projects/p/i.png")
+        );
+        assert_eq!(
+            parsed("https://api.doxle.ai/proxy-image/projects/p/blocks/b/i.jpg?v=2"),
+            keyed("projects/p/blocks/b/i.jpg")
+        );
+        assert_eq!(
+            parsed("/proxy-image/projects/p/i.jpg"),
+            keyed("projects/p/i.jpg")
+        );
+        assert_eq!(
+            parsed("https://cdn.doxle.ai/projects/p/i.png#frag"),
+            keyed("projects/p/i.png")
+        );
+
+        assert_eq!(parsed("https://example.com/other.png"), None);
+        assert_eq!(parsed("https://bucket.s3.amazonaws.com/"), None);
+        assert_eq!(parsed("https://ec2.amazonaws.com/projects/p/i.png"), None);
+        assert_eq!(parsed("projects/p/i.png"), None);
+    }
+}
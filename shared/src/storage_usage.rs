@@ -0,0 +1,136 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+
+/// S3 storage a project uses, kept on its project row: `storage_bytes` moves
+/// with uploads and deletes, and the cleanup job resets it (with
+/// `storage_objects` and `storage_reconciled_at`) from a listing of the
+/// bucket, which also catches the pyramids, thumbnails and copies the
+/// running count leaves out.
+#[derive(Debug, Serialize)]
+pub struct StorageUsage {
+    pub project_id: String,
+    pub bytes: i64,
+    pub objects: Option<u64>,          // as of the last reconcile
+    pub reconciled_at: Option<String>, // never, for projects not listed yet
+}
+
+/// Move a project's byte count by `delta`. Best effort, like the block
+/// counters: a failure is logged and the next reconcile corrects it.
+pub async fn record(client: &DynamoClient, table_name: &str, project_id: &str, delta: i64) {
+    if delta == 0 || project_id.is_empty() {
+        return;
+    }
+    let key = AttributeValue::S(format!("PROJECT#{}", project_id));
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", key.clone())
+        .key("SK", key)
+        .update_expression("ADD storage_bytes :delta")
+        .condition_expression("attribute_exists(PK)")
+        .expression_attribute_values(":delta", AttributeValue::N(delta.to_string()))
+        .send()
+        .await;
+    if let Err(e) = result {
+        let gone = e
+            .as_service_error()
+            .map(|se| se.is_conditional_check_failed_exception())
+            .unwrap_or(false);
+        if !gone {
+            tracing::warn!(
+                "Failed to update the storage of project {}: {}",
+                project_id,
+                e
+            );
+        }
+    }
+}
+
+/// Set a project's usage from a listing of its objects. Projects deleted
+/// since the listing are left deleted.
+pub async fn reconcile(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+    bytes: i64,
+    objects: u64,
+) -> Result<(), Error> {
+    let key = AttributeValue::S(format!("PROJECT#{}", project_id));
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", key.clone())
+        .key("SK", key)
+        .update_expression(
+            "SET storage_bytes = :bytes, storage_objects = :objects, storage_reconciled_at = :now",
+        )
+        .condition_expression("attribute_exists(PK)")
+        .expression_attribute_values(":bytes", AttributeValue::N(bytes.to_string()))
+        .expression_attribute_values(":objects", AttributeValue::N(objects.to_string()))
+        .expression_attribute_values(":now", AttributeValue::S(chrono::Utc::now().to_rfc3339()))
+        .send()
+        .await;
+    match result {
+        Ok(_) => Ok(()),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// GET /projects/{id}/storage
+pub async fn get_project_storage(
+    client: &DynamoClient,
+    table_name: &str,
+    project_id: &str,
+) -> Result<Response<Body>, Error> {
+    let key = AttributeValue::S(format!("PROJECT#{}", project_id));
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", key.clone())
+        .key("SK", key)
+        .projection_expression("PK, storage_bytes, storage_objects, storage_reconciled_at")
+        .send()
+        .await?;
+    let Some(item) = result.item() else {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(
+                serde_json::json!({"error": "Project not found"})
+                    .to_string()
+                    .into(),
+            )
+            .map_err(Box::new)?);
+    };
+    let number = |name: &str| item.get(name).and_then(|v| v.as_n().ok());
+    let usage = StorageUsage {
+        project_id: project_id.to_string(),
+        // Deletes metered before the first reconcile can undershoot
+        bytes: number("storage_bytes")
+            .and_then(|n| n.parse::<i64>().ok())
+            .unwrap_or(0)
+            .max(0),
+        objects: number("storage_objects").and_then(|n| n.parse().ok()),
+        reconciled_at: item
+            .get("storage_reconciled_at")
+            .and_then(|v| v.as_s().ok())
+            .cloned(),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&usage)?.into())
+        .map_err(Box::new)?)
+}
@@ -0,0 +1,274 @@
+use crate::responses::json_response;
+use crate::types::{Annotation, Calibration, Class};
+use crate::{annotations, blocks, classes, geometry, images, members, users};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_sdk_s3::Client as S3Client;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Unit reported for images that have no calibration
+const PIXEL_UNIT: &str = "px";
+
+/// One line of a quantity takeoff: a class measured in a single unit
+#[derive(Debug, Serialize, Clone)]
+pub struct TakeoffLine {
+    pub class_id: String,
+    pub class_name: String,
+    pub color: Option<String>,
+    pub unit: String,
+    pub count: u32,
+    pub total_area: f64,   // unit²
+    pub total_length: f64, // unit
+}
+
+#[derive(Debug, Serialize)]
+pub struct TakeoffSummary {
+    pub project_id: String,
+    pub generated_at: String,
+    pub image_count: usize,
+    pub uncalibrated_image_count: usize,
+    pub lines: Vec<TakeoffLine>,
+}
+
+fn round3(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// Accumulate measured quantities per (class, unit).
+/// Annotations on uncalibrated images are reported in pixels.
+fn summarize(
+    classes: &[Class],
+    measured: &[(Option<Calibration>, Vec<Annotation>)],
+) -> Vec<TakeoffLine> {
+    let mut lines: BTreeMap<(String, String), TakeoffLine> = BTreeMap::new();
+
+    for (calibration, annotations) in measured {
+        let (scale, unit) = match calibration {
+            Some(c) => (c.units_per_pixel, c.unit.clone()),
+            None => (1.0, PIXEL_UNIT.to_string()),
+        };
+
+        for annotation in annotations {
+            let line = lines
+                .entry((annotation.class_id.clone(), unit.clone()))
+                .or_insert_with(|| {
+                    let class = classes.iter().find(|c| c.class_id == annotation.class_id);
+                    TakeoffLine {
+                        class_id: annotation.class_id.clone(),
+                        class_name: class
+                            .map(|c| c.name.clone())
+                            .unwrap_or_else(|| "Unknown".to_string()),
+                        color: class.and_then(|c| c.color.clone()),
+                        unit: unit.clone(),
+                        count: 0,
+                        total_area: 0.0,
+                        total_length: 0.0,
+                    }
+                });
+            line.count += 1;
+            line.total_area += geometry::area(&annotation.geometry) * scale * scale;
+            line.total_length += geometry::length(&annotation.geometry) * scale;
+        }
+    }
+
+    let mut lines: Vec<TakeoffLine> = lines
+        .into_values()
+        .map(|mut line| {
+            line.total_area = round3(line.total_area);
+            line.total_length = round3(line.total_length);
+            line
+        })
+        .collect();
+    lines.sort_by(|a, b| a.class_name.cmp(&b.class_name).then(a.unit.cmp(&b.unit)));
+    lines
+}
+
+/// Read-only quantity takeoff for a project (GET /projects/{id}/takeoff),
+/// for members of the project (builders included) and admins. Normalized
+/// projects are measured in pixels of each image; images that can't be sized
+/// are left out.
+pub async fn get_project_takeoff(
+    client: &DynamoClient,
+    s3_client: &S3Client,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+) -> Result<Response<Body>, Error> {
+    if !members::is_member(client, table_name, project_id, user_id).await?
+        && !users::is_admin(client, table_name, user_id).await?
+    {
+        tracing::warn!(
+            "User {} denied the takeoff of project {}",
+            user_id,
+            project_id
+        );
+        return json_response(
+            StatusCode::FORBIDDEN,
+            serde_json::json!({"error": "Not a member of this project"}),
+        );
+    }
+
+    let project_classes = classes::fetch_project_classes(client, table_name, project_id).await?;
+    let project = crate::projects::fetch_project(client, table_name, project_id).await?;
+    let normalized = crate::projects::coordinate_mode(project.as_ref()) == "normalized";
+
+    let mut measured = Vec::new();
+    for block in blocks::fetch_project_blocks(client, table_name, project_id).await? {
+        for image in images::fetch_block_images(client, table_name, &block.block_id).await? {
+            let mut image_annotations =
+                annotations::fetch_image_annotations(client, table_name, &image.image_id).await?;
+            if normalized {
+                if let Err(e) =
+                    crate::export::denormalize(s3_client, &image, &mut image_annotations).await
+                {
+                    tracing::warn!("Takeoff: skipping image {}: {}", image.image_id, e);
+                    continue;
+                }
+            }
+            measured.push((image.calibration, image_annotations));
+        }
+    }
+
+    let summary = TakeoffSummary {
+        project_id: project_id.to_string(),
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        image_count: measured.len(),
+        uncalibrated_image_count: measured.iter().filter(|(c, _)| c.is_none()).count(),
+        lines: summarize(&project_classes, &measured),
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(&summary)?.into())
+        .map_err(Box::new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Geometry, Point};
+
+    fn annotation(class_id: &str, geometry: Geometry) -> Annotation {
+        Annotation {
+            annotation_id: "a".to_string(),
+            image_id: "img".to_string(),
+            project_id: "p1".to_string(),
+            block_id: "b1".to_string(),
+            class_id: class_id.to_string(),
+            area: geometry::area(&geometry),
+            bounding_box: geometry::bounding_box(&geometry),
+            geometry,
+            created_by: "USER#u1".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            updated_at: None,
+            version: 1,
+            tags: Vec::new(),
+            review_status: "pending".to_string(),
+            reviewed_by: None,
+            reviewed_at: None,
+            review_reason: None,
+            source: "human".to_string(),
+            confidence: None,
+            group_id: None,
+            z_index: 0,
+            attributes: Default::default(),
+        }
+    }
+
+    fn class(class_id: &str, name: &str) -> Class {
+        Class {
+            class_id: class_id.to_string(),
+            project_id: "p1".to_string(),
+            name: name.to_string(),
+            color: Some("#ff0000".to_string()),
+            properties: None,
+            count: 0,
+            sort_order: None,
+            hotkey: None,
+        }
+    }
+
+    /// 10 x 20 box: area 200, perimeter 60
+    fn room() -> Geometry {
+        Geometry::BBox {
+            start: Point { x: 0.0, y: 0.0 },
+            end: Point { x: 10.0, y: 20.0 },
+        }
+    }
+
+    /// Open path 3-4-5 triangle legs: length 7
+    fn pipe() -> Geometry {
+        Geometry::Polyline {
+            points: vec![
+                Point { x: 0.0, y: 0.0 },
+                Point { x: 3.0, y: 0.0 },
+                Point { x: 3.0, y: 4.0 },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_summarize_uncalibrated() {
+        let classes = [class("c1", "Rooms"), class("c2", "Pipes")];
+        let measured = vec![(
+            None,
+            vec![
+                annotation("c1", room()),
+                annotation("c1", room()),
+                annotation("c2", pipe()),
+            ],
+        )];
+        let lines = summarize(&classes, &measured);
+        assert_eq!(lines.len(), 2);
+        // Sorted by class name
+        assert_eq!(lines[0].class_name, "Pipes");
+        assert_eq!(lines[0].unit, PIXEL_UNIT);
+        assert_eq!(lines[0].count, 1);
+        assert_eq!(lines[0].total_area, 0.0);
+        assert_eq!(lines[0].total_length, 7.0);
+        assert_eq!(lines[1].class_name, "Rooms");
+        assert_eq!(lines[1].color.as_deref(), Some("#ff0000"));
+        assert_eq!(lines[1].count, 2);
+        assert_eq!(lines[1].total_area, 400.0);
+        assert_eq!(lines[1].total_length, 120.0);
+    }
+
+    #[test]
+    fn test_summarize_calibrated() {
+        let classes = [class("c1", "Rooms")];
+        let calibration = Calibration {
+            units_per_pixel: 0.5,
+            unit: "m".to_string(),
+        };
+        let measured = vec![
+            (
+                Some(calibration),
+                vec![annotation("c1", room()), annotation("c1", pipe())],
+            ),
+            // The same class on an uncalibrated image stays a separate line
+            (None, vec![annotation("c1", room())]),
+            (None, vec![annotation("gone", room())]),
+        ];
+        let lines = summarize(&classes, &measured);
+        assert_eq!(lines.len(), 3);
+        let metres = lines
+            .iter()
+            .find(|l| l.class_id == "c1" && l.unit == "m")
+            .unwrap();
+        assert_eq!(metres.count, 2);
+        // Areas scale by the square of units per pixel, lengths linearly
+        assert_eq!(metres.total_area, 50.0);
+        assert_eq!(metres.total_length, 33.5);
+        let pixels = lines
+            .iter()
+            .find(|l| l.class_id == "c1" && l.unit == PIXEL_UNIT)
+            .unwrap();
+        assert_eq!(pixels.total_area, 200.0);
+        let unknown = lines.iter().find(|l| l.class_id == "gone").unwrap();
+        assert_eq!(unknown.class_name, "Unknown");
+        assert_eq!(unknown.color, None);
+    }
+}
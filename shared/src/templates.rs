@@ -0,0 +1,416 @@
+use crate::classes;
+use crate::pagination::{self, PageParams};
+use crate::responses::json_response;
+use crate::types::{
+    ClassTemplate, CreateClassRequest, CreateClassTemplateRequest, TemplateClass,
+    UpdateClassTemplateRequest,
+};
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_http::{http::StatusCode, Body, Error, Response};
+use std::collections::HashMap;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Templates are shared across the org, so they live in one partition:
+/// PK=CLASS_TEMPLATES, SK=TEMPLATE#{tid}
+const TEMPLATES_PK: &str = "CLASS_TEMPLATES";
+const TEMPLATE_PREFIX: &str = "TEMPLATE#";
+
+fn template_key(template_id: &str) -> (AttributeValue, AttributeValue) {
+    (
+        AttributeValue::S(TEMPLATES_PK.to_string()),
+        AttributeValue::S(format!("{}{}", TEMPLATE_PREFIX, template_id)),
+    )
+}
+
+fn template_item(template: &ClassTemplate) -> Result<Item, Error> {
+    let (pk, sk) = template_key(&template.template_id);
+    let mut item = HashMap::from([
+        ("PK".to_string(), pk),
+        ("SK".to_string(), sk),
+        (
+            "entity_type".to_string(),
+            AttributeValue::S("class_template".to_string()),
+        ),
+        ("name".to_string(), AttributeValue::S(template.name.clone())),
+        (
+            "classes".to_string(),
+            AttributeValue::S(serde_json::to_string(&template.classes)?),
+        ),
+        (
+            "created_by".to_string(),
+            AttributeValue::S(template.created_by.clone()),
+        ),
+        (
+            "created_at".to_string(),
+            AttributeValue::S(template.created_at.clone()),
+        ),
+    ]);
+    for (name, value) in [
+        ("description", &template.description),
+        ("updated_at", &template.updated_at),
+    ] {
+        if let Some(value) = value {
+            item.insert(name.to_string(), AttributeValue::S(value.clone()));
+        }
+    }
+    Ok(item)
+}
+
+fn template_from_item(item: &Item) -> Option<ClassTemplate> {
+    let text = |name: &str| item.get(name).and_then(|v| v.as_s().ok()).cloned();
+    Some(ClassTemplate {
+        template_id: text("SK")?.strip_prefix(TEMPLATE_PREFIX)?.to_string(),
+        name: text("name").unwrap_or_default(),
+        description: text("description"),
+        classes: text("classes")
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default(),
+        created_by: text("created_by").unwrap_or_default(),
+        created_at: text("created_at").unwrap_or_default(),
+        updated_at: text("updated_at"),
+    })
+}
+
+/// A template needs a name and between 1 and `MAX_CLASS_BATCH` valid classes
+/// with distinct names
+fn check_template(name: &str, template_classes: &[TemplateClass]) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("name is required".to_string());
+    }
+    if template_classes.is_empty() || template_classes.len() > classes::MAX_CLASS_BATCH {
+        return Err(format!(
+            "A template needs between 1 and {} classes",
+            classes::MAX_CLASS_BATCH
+        ));
+    }
+    for (i, class) in template_classes.iter().enumerate() {
+        classes::check_definition(
+            &class.name,
+            class.color.as_deref(),
+            class.properties.as_ref(),
+        )
+        .map_err(|e| format!("classes[{}]: {}", i, e))?;
+    }
+    let names: Vec<&str> = template_classes.iter().map(|c| c.name.as_str()).collect();
+    let duplicates = classes::duplicate_names(&[], &names);
+    if !duplicates.is_empty() {
+        return Err(format!(
+            "Class names must be unique in a template: {}",
+            duplicates.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Load a template, if it exists
+pub async fn fetch_template(
+    client: &DynamoClient,
+    table_name: &str,
+    template_id: &str,
+) -> Result<Option<ClassTemplate>, Error> {
+    let (pk, sk) = template_key(template_id);
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", pk)
+        .key("SK", sk)
+        .send()
+        .await?;
+    Ok(result.item().and_then(template_from_item))
+}
+
+fn bad_request(message: String) -> Result<Response<Body>, Error> {
+    json_response(
+        StatusCode::BAD_REQUEST,
+        serde_json::json!({ "error": message }),
+    )
+}
+
+fn template_not_found() -> Result<Response<Body>, Error> {
+    json_response(
+        StatusCode::NOT_FOUND,
+        serde_json::json!({"error": "Template not found"}),
+    )
+}
+
+/// Templates can be changed by whoever created them, or an admin
+async fn may_edit(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    template: &ClassTemplate,
+) -> Result<bool, Error> {
+    Ok(template.created_by == format!("USER#{}", user_id)
+        || crate::users::is_admin(client, table_name, user_id).await?)
+}
+
+fn forbidden() -> Result<Response<Body>, Error> {
+    json_response(
+        StatusCode::FORBIDDEN,
+        serde_json::json!({"error": "Only the template's creator or an admin can change it"}),
+    )
+}
+
+/// Create a template (POST /class-templates)
+pub async fn create_template(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: CreateClassTemplateRequest = serde_json::from_slice(body)?;
+    if let Err(e) = check_template(&req.name, &req.classes) {
+        return bad_request(e);
+    }
+    let template = ClassTemplate {
+        template_id: uuid::Uuid::new_v4().to_string(),
+        name: req.name,
+        description: req.description,
+        classes: req.classes,
+        created_by: format!("USER#{}", user_id),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        updated_at: None,
+    };
+    client
+        .put_item()
+        .table_name(table_name)
+        .set_item(Some(template_item(&template)?))
+        .send()
+        .await?;
+    json_response(StatusCode::CREATED, serde_json::to_value(&template)?)
+}
+
+/// Templates, paged with `?limit=&cursor=` (GET /class-templates)
+pub async fn list_templates(
+    client: &DynamoClient,
+    table_name: &str,
+    params: PageParams<'_>,
+) -> Result<Response<Body>, Error> {
+    let request = match pagination::page_request(params, TEMPLATE_PREFIX) {
+        Ok(request) => request,
+        Err(e) => return pagination::invalid_page(e),
+    };
+    let page = pagination::query_prefix(
+        client,
+        table_name,
+        TEMPLATES_PK,
+        TEMPLATE_PREFIX,
+        None,
+        &request,
+    )
+    .await?;
+    pagination::page_response(&pagination::Page {
+        items: page
+            .items
+            .iter()
+            .filter_map(template_from_item)
+            .collect::<Vec<_>>(),
+        next_cursor: page.next_cursor,
+    })
+}
+
+/// One template (GET /class-templates/{tid})
+pub async fn get_template(
+    client: &DynamoClient,
+    table_name: &str,
+    template_id: &str,
+) -> Result<Response<Body>, Error> {
+    match fetch_template(client, table_name, template_id).await? {
+        Some(template) => json_response(StatusCode::OK, serde_json::to_value(&template)?),
+        None => template_not_found(),
+    }
+}
+
+/// Rename a template or replace its classes (PATCH /class-templates/{tid}).
+/// Projects it was applied to keep their classes.
+pub async fn update_template(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    template_id: &str,
+    body: &[u8],
+) -> Result<Response<Body>, Error> {
+    let req: UpdateClassTemplateRequest = serde_json::from_slice(body)?;
+    let Some(mut template) = fetch_template(client, table_name, template_id).await? else {
+        return template_not_found();
+    };
+    if !may_edit(client, table_name, user_id, &template).await? {
+        return forbidden();
+    }
+    if let Some(name) = req.name {
+        template.name = name;
+    }
+    if let Some(description) = req.description {
+        template.description = Some(description).filter(|d| !d.is_empty());
+    }
+    if let Some(template_classes) = req.classes {
+        template.classes = template_classes;
+    }
+    if let Err(e) = check_template(&template.name, &template.classes) {
+        return bad_request(e);
+    }
+    template.updated_at = Some(chrono::Utc::now().to_rfc3339());
+
+    let result = client
+        .put_item()
+        .table_name(table_name)
+        .set_item(Some(template_item(&template)?))
+        .condition_expression("attribute_exists(PK)")
+        .send()
+        .await;
+    match result {
+        Ok(_) => json_response(StatusCode::OK, serde_json::to_value(&template)?),
+        Err(e)
+            if e.as_service_error()
+                .map(|se| se.is_conditional_check_failed_exception())
+                .unwrap_or(false) =>
+        {
+            template_not_found()
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Delete a template (DELETE /class-templates/{tid})
+pub async fn delete_template(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    template_id: &str,
+) -> Result<Response<Body>, Error> {
+    let Some(template) = fetch_template(client, table_name, template_id).await? else {
+        return template_not_found();
+    };
+    if !may_edit(client, table_name, user_id, &template).await? {
+        return forbidden();
+    }
+    let (pk, sk) = template_key(template_id);
+    client
+        .delete_item()
+        .table_name(table_name)
+        .key("PK", pk)
+        .key("SK", sk)
+        .send()
+        .await?;
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Access-Control-Allow-Origin", "*")
+        .body(Body::Empty)
+        .map_err(Box::new)?)
+}
+
+/// Template classes a project doesn't have yet, by name ignoring case, and
+/// the names of those it already has
+fn missing_classes(
+    existing: &[crate::types::Class],
+    template_classes: &[TemplateClass],
+) -> (Vec<CreateClassRequest>, Vec<String>) {
+    let mut missing = Vec::new();
+    let mut skipped = Vec::new();
+    for class in template_classes {
+        if classes::duplicate_names(existing, &[&class.name]).is_empty() {
+            missing.push(CreateClassRequest {
+                name: class.name.clone(),
+                color: class.color.clone(),
+                properties: class.properties.clone(),
+                sort_order: None,
+                hotkey: None,
+            });
+        } else {
+            skipped.push(class.name.clone());
+        }
+    }
+    (missing, skipped)
+}
+
+/// Add a template's classes to a project
+/// (POST /projects/{pid}/classes/apply-template/{tid}). Classes the project
+/// already has, by name, are skipped, so applying again adds only what the
+/// template gained since. Colors already taken in the project are swapped
+/// for free palette colors.
+pub async fn apply_template(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    project_id: &str,
+    template_id: &str,
+) -> Result<Response<Body>, Error> {
+    let Some(template) = fetch_template(client, table_name, template_id).await? else {
+        return template_not_found();
+    };
+    let existing = classes::fetch_project_classes(client, table_name, project_id).await?;
+    let (missing, skipped) = missing_classes(&existing, &template.classes);
+    let created = if missing.is_empty() {
+        Vec::new()
+    } else {
+        classes::insert_classes(client, table_name, user_id, project_id, &existing, missing).await?
+    };
+    json_response(
+        if created.is_empty() {
+            StatusCode::OK
+        } else {
+            StatusCode::CREATED
+        },
+        serde_json::json!({
+            "template_id": template_id,
+            "project_id": project_id,
+            "created": created,
+            "skipped": skipped,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(name: &str) -> TemplateClass {
+        TemplateClass {
+            name: name.to_string(),
+            color: None,
+            properties: None,
+        }
+    }
+
+    #[test]
+    fn test_template_item_round_trip() {
+        let template = ClassTemplate {
+            template_id: "t1".to_string(),
+            name: "Framing".to_string(),
+            description: Some("Timber framing takeoff".to_string()),
+            classes: vec![class("Stud"), class("Nogging")],
+            created_by: "USER#u1".to_string(),
+            created_at: "2026-10-14T08:00:00Z".to_string(),
+            updated_at: None,
+        };
+        let item = template_item(&template).unwrap();
+        assert_eq!(item["SK"].as_s().unwrap(), "TEMPLATE#t1");
+        let parsed = template_from_item(&item).unwrap();
+        assert_eq!(parsed.classes, template.classes);
+        assert_eq!(parsed.description, template.description);
+    }
+
+    #[test]
+    fn test_check_template() {
+        assert!(check_template("Framing", &[class("Stud"), class("Nogging")]).is_ok());
+        assert!(check_template(" ", &[class("Stud")]).is_err());
+        assert!(check_template("Framing", &[]).is_err());
+        assert!(check_template("Framing", &[class("Stud"), class("stud ")]).is_err());
+    }
+
+    #[test]
+    fn test_missing_classes() {
+        let existing = crate::types::Class {
+            name: "Stud".to_string(),
+            ..Default::default()
+        };
+        let (missing, skipped) = missing_classes(&[existing], &[class("stud"), class("Nogging")]);
+        assert_eq!(
+            missing.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
+            vec!["Nogging"]
+        );
+        assert_eq!(skipped, vec!["stud"]);
+    }
+}
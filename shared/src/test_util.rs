@@ -0,0 +1,178 @@
+//! A DynamoDB client for unit tests that never leaves the process. GetItem,
+//! BatchGetItem and prefix queries (`query_prefix`, filters ignored) are
+//! answered from a
+//! fixed set of items; any other operation fails, so a test reaching one
+//! knows a write got past the checks it covers.
+
+use aws_sdk_dynamodb::config::{BehaviorVersion, Credentials, Region};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use aws_smithy_http_client::test_util::infallible_client_fn;
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+/// Operations the fake client was asked for, in order ("GetItem", ...)
+pub type Calls = Arc<Mutex<Vec<String>>>;
+
+/// A client serving `items` (DynamoDB JSON, e.g. `{"PK": {"S": "USER#u1"}, ...}`)
+/// by PK/SK, and the log of every operation sent to it
+pub fn fake_dynamo(items: Vec<Value>) -> (DynamoClient, Calls) {
+    let calls: Calls = Arc::default();
+    let log = calls.clone();
+    let http_client = infallible_client_fn(move |request| {
+        let operation = request
+            .headers()
+            .get("x-amz-target")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|target| target.rsplit('.').next())
+            .unwrap_or_default()
+            .to_string();
+        log.lock().unwrap().push(operation.clone());
+
+        let body: Value = request
+            .body()
+            .bytes()
+            .and_then(|bytes| serde_json::from_slice(bytes).ok())
+            .unwrap_or_default();
+        let (status, response) = match operation.as_str() {
+            "GetItem" => {
+                let item = items.iter().find(|item| {
+                    item["PK"] == body["Key"]["PK"] && item["SK"] == body["Key"]["SK"]
+                });
+                let response = match item {
+                    Some(item) => serde_json::json!({ "Item": item }),
+                    None => serde_json::json!({}),
+                };
+                (200, response)
+            }
+            "BatchGetItem" => {
+                let mut responses = serde_json::Map::new();
+                for (table, request) in body["RequestItems"].as_object().into_iter().flatten() {
+                    let keys = request["Keys"].as_array().cloned().unwrap_or_default();
+                    let found: Vec<&Value> = items
+                        .iter()
+                        .filter(|item| {
+                            keys.iter()
+                                .any(|key| item["PK"] == key["PK"] && item["SK"] == key["SK"])
+                        })
+                        .collect();
+                    responses.insert(table.clone(), serde_json::json!(found));
+                }
+                (200, serde_json::json!({ "Responses": responses }))
+            }
+            "Query" => {
+                let values = &body["ExpressionAttributeValues"];
+                let prefix = values[":sk_prefix"]["S"].as_str().unwrap_or_default();
+                let matching: Vec<&Value> = items
+                    .iter()
+                    .filter(|item| item["PK"] == values[":pk"])
+                    .filter(|item| {
+                        item["SK"]["S"]
+                            .as_str()
+                            .is_some_and(|sk| sk.starts_with(prefix))
+                    })
+                    .collect();
+                (
+                    200,
+                    serde_json::json!({ "Items": matching, "Count": matching.len() }),
+                )
+            }
+            _ => (
+                400,
+                serde_json::json!({
+                    "__type": "com.amazon.coral.validate#ValidationException",
+                    "message": format!("{} is not served by the test client", operation),
+                }),
+            ),
+        };
+        http::Response::builder()
+            .status(status)
+            .header("Content-Type", "application/x-amz-json-1.0")
+            .body(response.to_string())
+            .unwrap()
+    });
+
+    let config = aws_sdk_dynamodb::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+        .http_client(http_client)
+        .build();
+    (DynamoClient::from_conf(config), calls)
+}
+
+/// A block assigned to `assignee`, as `check_assignee` reads it
+pub fn assigned_block(project_id: &str, block_id: &str, assignee: &str) -> Value {
+    serde_json::json!({
+        "PK": { "S": format!("PROJECT#{}", project_id) },
+        "SK": { "S": format!("BLOCK#{}", block_id) },
+        "assigned_to": { "S": format!("USER#{}", assignee) },
+    })
+}
+
+/// A lock `holder` holds on a resource for the next minute
+pub fn lock(resource_type: &str, resource_id: &str, holder: &str) -> Value {
+    let expires = chrono::Utc::now().timestamp() + 60;
+    serde_json::json!({
+        "PK": { "S": format!("LOCK#{}#{}", resource_type, resource_id) },
+        "SK": { "S": "LOCK" },
+        "resource_type": { "S": resource_type },
+        "resource_id": { "S": resource_id },
+        "holder": { "S": holder },
+        "expires_epoch": { "N": expires.to_string() },
+    })
+}
+
+/// An image's location row, as `image_location` reads it
+pub fn image_in_block(project_id: &str, block_id: &str, image_id: &str) -> Value {
+    serde_json::json!({
+        "PK": { "S": format!("IMAGE#{}", image_id) },
+        "SK": { "S": "METADATA" },
+        "project_id": { "S": project_id },
+        "block_id": { "S": block_id },
+    })
+}
+
+/// An API Gateway management client accepting every post, and the path of
+/// each request sent to it
+pub fn accepting_api_gateway() -> (aws_sdk_apigatewaymanagement::Client, Calls) {
+    let posts: Calls = Arc::default();
+    let log = posts.clone();
+    let http_client = infallible_client_fn(move |request| {
+        log.lock().unwrap().push(request.uri().to_string());
+        http::Response::builder().status(200).body("").unwrap()
+    });
+    let config = aws_sdk_apigatewaymanagement::Config::builder()
+        .behavior_version(aws_sdk_apigatewaymanagement::config::BehaviorVersion::latest())
+        .region(aws_sdk_apigatewaymanagement::config::Region::new(
+            "us-east-1",
+        ))
+        .endpoint_url("https://sockets.example.com/test")
+        .credentials_provider(aws_sdk_apigatewaymanagement::config::Credentials::new(
+            "test", "test", None, None, "test",
+        ))
+        .http_client(http_client)
+        .build();
+    (
+        aws_sdk_apigatewaymanagement::Client::from_conf(config),
+        posts,
+    )
+}
+
+/// An S3 client refusing every request, for code that must not reach S3
+pub fn offline_s3() -> aws_sdk_s3::Client {
+    let http_client = infallible_client_fn(|_| {
+        http::Response::builder()
+            .status(403)
+            .body("<Error><Code>AccessDenied</Code></Error>")
+            .unwrap()
+    });
+    let config = aws_sdk_s3::Config::builder()
+        .behavior_version(aws_sdk_s3::config::BehaviorVersion::latest())
+        .region(aws_sdk_s3::config::Region::new("us-east-1"))
+        .credentials_provider(aws_sdk_s3::config::Credentials::new(
+            "test", "test", None, None, "test",
+        ))
+        .http_client(http_client)
+        .build();
+    aws_sdk_s3::Client::from_conf(config)
+}
@@ -1,7 +1,8 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // ========== USER ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct User {
     pub user_id: String,
     pub name: String,
@@ -10,9 +11,11 @@ pub struct User {
     pub role: String, // admin | annotator | builder
     pub created_at: String,
     pub last_login: Option<String>,
+    #[serde(default)]
+    pub onboarding: Onboarding,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateUserRequest {
     pub name: String,
     pub email: String,
@@ -20,21 +23,110 @@ pub struct CreateUserRequest {
     pub role: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateUserRequest {
     pub name: Option<String>,
     pub company: Option<String>,
     pub role: Option<String>,
 }
 
+// ========== ONBOARDING ==========
+/// Guided setup progress; steps must be completed in order
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct Onboarding {
+    pub profile_completed: bool,
+    pub first_project_created: bool,
+    pub tutorial_done: bool,
+    pub completed_at: Option<String>,
+}
+
+// ========== SESSION ==========
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Session {
+    pub session_id: String,
+    pub device_name: Option<String>,
+    pub status: String, // active | revoked
+    pub issued_at: String,
+    pub last_used: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default, JsonSchema)]
+pub struct RevokeSessionRequest {
+    pub refresh_token: Option<String>, // this device's token, to revoke it in Cognito too
+}
+
+// ========== ORG SETTINGS ==========
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct OrgSettings {
+    pub allowed_invite_domains: Vec<String>, // empty = any domain
+    pub updated_at: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UpdateOrgSettingsRequest {
+    pub allowed_invite_domains: Option<Vec<String>>,
+}
+
 // ========== PROJECT ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Label {
     pub name: String,
     pub color: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct SnapSettings {
+    pub enabled: bool,
+    pub tolerance_px: f64,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            tolerance_px: 8.0,
+        }
+    }
+}
+
+/// Client defaults applied when an annotator opens the project
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct AnnotationDefaults {
+    pub default_class_id: Option<String>,
+    pub default_geometry_type: Option<String>, // polygon | bbox
+    #[serde(default)]
+    pub snap: SnapSettings,
+}
+
+/// How uploads of a project are prepared for the viewer
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct PyramidSettings {
+    pub mode: Option<String>, // half (default): one half-width preview | dzi: Deep Zoom tiles too
+    pub tile_size: Option<u32>, // dzi tile edge in pixels, 254 by default
+}
+
+/// What a project accepts as an upload; unset limits don't apply
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct UploadLimits {
+    pub max_file_size: Option<u64>, // bytes
+    pub max_width: Option<u32>, // pixels
+    pub max_height: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, JsonSchema)]
+pub struct ProjectSettings {
+    #[serde(default)]
+    pub annotation_defaults: AnnotationDefaults,
+    pub coordinate_mode: Option<String>, // pixel (default) | normalized
+    #[serde(default)]
+    pub pyramid: PyramidSettings,
+    #[serde(default)]
+    pub uploads: UploadLimits,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Project {
     pub project_id: String,
     pub name: String,
@@ -42,23 +134,39 @@ pub struct Project {
     pub locked: bool,
     pub labels: Vec<Label>,
     pub created_at: String,
+    #[serde(default)]
+    pub settings: ProjectSettings,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateProjectRequest {
     pub name: String,
     pub project_type: String,
     pub labels: Vec<Label>,
+    pub settings: Option<ProjectSettings>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateProjectRequest {
     pub name: Option<String>,
     pub locked: Option<bool>,
+    pub settings: Option<ProjectSettings>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MemberEntry {
+    pub email: String,
+    pub role: Option<String>, // manager | reviewer | annotator (default)
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchAddMembersRequest {
+    pub members: Vec<MemberEntry>,
+    pub invite_expires_days: Option<i64>, // for invites sent to unknown addresses
 }
 
 // ========== CLASS ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Class {
     pub class_id: String,
     pub project_id: String,
@@ -66,24 +174,102 @@ pub struct Class {
     pub color: Option<String>,
     pub properties: Option<serde_json::Value>,
     pub count: u32,
+    pub sort_order: Option<i32>, // display position, ascending; unordered classes go last
+    pub hotkey: Option<String>,  // single key, unique in the project
 }
 
-#[derive(Debug, Deserialize)]
+/// Keypoint layout for pose/landmark classes, stored as `properties.skeleton`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct Skeleton {
+    pub keypoints: Vec<String>, // names, in COCO order
+    #[serde(default)]
+    pub edges: Vec<[usize; 2]>, // pairs of 0-based keypoint indices
+}
+
+/// One typed attribute the annotations of a class carry, declared in the
+/// class's `properties.attributes` list so frontends can render a form
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct AttributeDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String, // enum | bool | number | text
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>, // allowed values of an enum
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateClassRequest {
     pub name: String,
     pub color: Option<String>,
     pub properties: Option<serde_json::Value>,
+    #[serde(default)]
+    pub sort_order: Option<i32>, // default: after every existing class
+    #[serde(default)]
+    pub hotkey: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BatchCreateClassesRequest {
+    pub classes: Vec<CreateClassRequest>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateClassRequest {
     pub name: Option<String>,
     pub color: Option<String>,
     pub properties: Option<serde_json::Value>,
+    pub sort_order: Option<i32>,
+    pub hotkey: Option<String>, // "" clears it
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReorderImagesRequest {
+    pub image_ids: Vec<String>, // block order; each gets its position as order
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReorderClassesRequest {
+    pub class_ids: Vec<String>, // display order; each gets its position as sort_order
+}
+
+/// A reusable label set, applied to projects as classes
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct ClassTemplate {
+    pub template_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub classes: Vec<TemplateClass>,
+    pub created_by: String, // USER#123
+    pub created_at: String,
+    pub updated_at: Option<String>,
+}
+
+/// A class as a template defines it
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct TemplateClass {
+    pub name: String,
+    pub color: Option<String>,
+    pub properties: Option<serde_json::Value>, // skeleton, attributes...
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateClassTemplateRequest {
+    pub name: String,
+    pub description: Option<String>,
+    pub classes: Vec<TemplateClass>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UpdateClassTemplateRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub classes: Option<Vec<TemplateClass>>, // replaces the template's classes
 }
 
 // ========== BLOCK ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Block {
     pub block_id: String,
     pub project_id: String,
@@ -91,15 +277,18 @@ pub struct Block {
     pub state: String, // draft | current | review | complete | paid
     pub locked: bool,
     pub assigned_to: Option<String>, // USER#123
+    pub reviewer: Option<String>,    // USER#123, approves or rejects its annotations
     pub created_at: String,
+    #[serde(default)]
+    pub annotation_count: u32, // live annotations across the block's images
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateBlockRequest {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateBlockRequest {
     pub name: Option<String>,
     pub state: Option<String>,
@@ -107,8 +296,45 @@ pub struct UpdateBlockRequest {
     pub assigned_to: Option<String>,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SetReviewerRequest {
+    pub reviewer: Option<String>, // user id, with or without USER#; null or "" unassigns
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MoveBlockRequest {
+    pub target_project_id: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct MoveImageRequest {
+    pub target_block_id: String,
+    pub target_project_id: Option<String>, // the image's own project by default
+    #[serde(default)]
+    pub copy: bool, // leave the image where it is and add a copy
+}
+
+/// History entry for a block (state transition or assignment change)
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct BlockEvent {
+    pub event_id: String,
+    pub block_id: String,
+    pub kind: String, // state_changed | assigned | reviewer_assigned
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub user_id: String,
+    pub created_at: String,
+}
+
 // ========== IMAGE ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Drawing scale used to turn pixel measurements into real-world quantities
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Calibration {
+    pub units_per_pixel: f64,
+    pub unit: String, // m | mm | ft | in
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Image {
     pub image_id: String,
     pub block_id: String,
@@ -116,31 +342,128 @@ pub struct Image {
     pub locked: bool,
     pub order: Option<i32>,
     pub uploaded_at: String,
+    pub calibration: Option<Calibration>,
+    pub file_name: Option<String>,   // original upload name
+    pub captured_at: Option<String>, // EXIF capture time
+    #[serde(default)]
+    pub annotation_count: u32, // live annotations on the image
+    #[serde(default = "unannotated")]
+    pub status: String, // unannotated | in_progress | annotated | skipped | no_objects
+    #[serde(default)]
+    pub width: Option<u32>, // pixels, recorded at upload or replace
+    #[serde(default)]
+    pub height: Option<u32>,
+    #[serde(default)]
+    pub size_bytes: Option<u64>, // of the uploaded original
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub levels: Vec<ImageLevel>, // pyramid levels from upload processing
+    #[serde(default)]
+    pub thumbnail_url: Option<String>, // small JPEG for galleries
+    #[serde(default)]
+    pub tiles_url: Option<String>, // Deep Zoom descriptor, when the project tiles its uploads
+    #[serde(default)]
+    pub photo: Option<PhotoMetadata>, // EXIF location and camera, from upload processing
+    #[serde(default)]
+    pub original_format: Option<String>, // extension of an upload converted for browsers, e.g. "heic"
+    #[serde(default = "ready")]
+    pub processing: String, // processing | ready | failed, of an upload's size and pyramid
+    #[serde(default)]
+    pub tags: Vec<String>, // free-form labels for slicing a block, e.g. "weather:rain"
+}
+
+fn unannotated() -> String {
+    "unannotated".to_string()
+}
+
+fn ready() -> String {
+    "ready".to_string()
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateImageRequest {
     pub url: String,
-    pub order: Option<i32>,
+    pub order: Option<i32>, // omit to order automatically
+    pub calibration: Option<Calibration>,
+    pub file_name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateImageRequest {
     pub locked: Option<bool>,
     pub order: Option<i32>,
+    pub calibration: Option<Calibration>,
+    pub status: Option<String>,
+    pub tags: Option<Vec<String>>, // replaces the image's tags; empty clears them
+}
+
+/// Swap an image for a new file (or record that it was resized in place).
+/// Sizes are read from the files when omitted.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReplaceImageRequest {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub previous_width: Option<u32>, // size the annotations were drawn against
+    pub previous_height: Option<u32>,
+}
+
+// ========== LOCK ==========
+/// Edit lock on an image or annotation; expires unless refreshed
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Lock {
+    pub resource_type: String, // image | annotation
+    pub resource_id: String,
+    pub holder: String, // user_id
+    pub connection_id: Option<String>,
+    pub acquired_at: String,
+    pub expires_at: String,
 }
 
 // ========== IMAGE METADATA (Pyramid) ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct ImageMetadata {
     pub original_width: u32,
     pub original_height: u32,
     pub file_size: usize,
     pub format: String,
     pub levels: Vec<ImageLevel>,
+    #[serde(default)]
+    pub thumbnail: Option<ImageLevel>, // "thumb.jpg" in the image's folder
+    #[serde(default)]
+    pub tiles: Option<TileSource>, // Deep Zoom tiles, for projects that tile
+    #[serde(default)]
+    pub photo: Option<PhotoMetadata>,
+    #[serde(default)]
+    pub original_format: Option<String>, // kept as "original.{ext}" in the folder; `format` is what it became
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// What a photo's EXIF says about where and how it was taken
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct PhotoMetadata {
+    pub latitude: Option<f64>, // degrees, south negative
+    pub longitude: Option<f64>, // degrees, west negative
+    pub altitude_m: Option<f64>, // below sea level negative
+    pub camera_make: Option<String>,
+    pub camera_model: Option<String>,
+    pub orientation: Option<u32>, // as shot (1-8); the stored image is upright
+}
+
+/// Deep Zoom tiles of an image: the `tiles.dzi` descriptor in its folder,
+/// with level `n` of them under `tiles_files/{n}/{col}_{row}.jpg`
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct TileSource {
+    pub path: String, // "tiles.dzi"
+    pub tile_size: u32,
+    pub overlap: u32,
+    pub max_level: u32, // full resolution; each level below halves it
+    pub tile_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct ImageLevel {
     pub width: u32,
     pub height: u32,
@@ -150,56 +473,190 @@ pub struct ImageLevel {
 }
 
 // ========== ANNOTATION ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Named landmark; visibility follows COCO (0 = not labeled, 1 = occluded, 2 = visible)
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+pub struct Keypoint {
+    pub name: String,
+    pub x: f64,
+    pub y: f64,
+    pub visibility: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(tag = "type")]
 pub enum Geometry {
     #[serde(rename = "polygon")]
     Polygon { points: Vec<Point> },
     #[serde(rename = "bbox")]
     BBox { start: Point, end: Point },
+    #[serde(rename = "point")]
+    Point { point: Point }, // single-click pin
+    #[serde(rename = "polyline")]
+    Polyline { points: Vec<Point> }, // open path: pipes, cables, wall lines
+    #[serde(rename = "keypoints")]
+    Keypoints { keypoints: Vec<Keypoint> }, // pose/landmarks, see Skeleton
+    /// Brush/segmentation mask: `width` x `height` pixels placed at `origin`.
+    /// `counts` are row-major run lengths alternating background/foreground,
+    /// starting with background (CVAT-style uncompressed RLE)
+    #[serde(rename = "mask")]
+    Mask {
+        origin: Point,
+        width: u32,
+        height: u32,
+        counts: Vec<u32>,
+    },
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Axis-aligned extent in image pixels, top-left origin
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+pub struct BoundingBox {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Annotation {
     pub annotation_id: String,
     pub image_id: String,
+    #[serde(default)]
+    pub project_id: String, // stored at create; empty on items from before it was
+    #[serde(default)]
+    pub block_id: String,
     pub class_id: String,
     pub geometry: Geometry,
+    #[serde(default)]
+    pub area: f64, // square pixels, derived from geometry on write
+    pub bounding_box: Option<BoundingBox>, // derived from geometry on write
     pub created_by: String, // USER#123
     pub created_at: String,
     pub updated_at: Option<String>,
+    #[serde(default)]
+    pub version: u32, // history version of this state; 0 before history was kept
+    #[serde(default)]
+    pub tags: Vec<String>, // free-form labels, sorted and unique
+    #[serde(default = "pending_review")]
+    pub review_status: String, // pending | approved | rejected
+    pub reviewed_by: Option<String>, // USER#123
+    pub reviewed_at: Option<String>,
+    pub review_reason: Option<String>,
+    #[serde(default = "human_source")]
+    pub source: String, // human | model
+    pub confidence: Option<f32>, // 0..=1, from the model that proposed it
+    pub group_id: Option<String>, // shared by related annotations on the same image
+    #[serde(default)]
+    pub z_index: i32, // draw order on the image: higher is drawn on top, ties by created_at
+    #[serde(default)]
+    pub attributes: std::collections::BTreeMap<String, serde_json::Value>, // values for the class's attribute schema
 }
 
-#[derive(Debug, Deserialize)]
+fn pending_review() -> String {
+    "pending".to_string()
+}
+
+fn human_source() -> String {
+    "human".to_string()
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct CreateAnnotationRequest {
     pub class_id: String,
     pub geometry: Geometry,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Client-generated UUID; creating the same id again returns the
+    /// existing annotation instead of a duplicate
+    #[serde(default)]
+    pub annotation_id: Option<String>,
+    pub source: Option<String>, // human (default) | model
+    pub confidence: Option<f32>,
+    pub z_index: Option<i32>, // default 0
+    #[serde(default)]
+    pub attributes: std::collections::BTreeMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct UpdateAnnotationRequest {
     pub class_id: Option<String>,
     pub geometry: Option<Geometry>,
+    pub tags: Option<Vec<String>>, // replaces the annotation's tags; [] clears them
+    pub z_index: Option<i32>,
+    pub attributes: Option<std::collections::BTreeMap<String, serde_json::Value>>, // replaces them; {} clears them
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct BatchCreateAnnotationsRequest {
     pub annotations: Vec<CreateAnnotationRequest>,
 }
 
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct PropagateAnnotationsRequest {
+    pub annotation_ids: Option<Vec<String>>, // default: every annotation on the source image
+    pub image_ids: Option<Vec<String>>, // target images in the same block...
+    pub count: Option<usize>,           // ...or the next `count` images in block order
+    pub offset: Option<Point>,          // shift applied after scaling, in project coordinates
+    pub scale: Option<f64>,             // about the image origin; default 1
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReassignClassRequest {
+    pub from_class_id: String,
+    pub to_class_id: String,
+    pub image_ids: Option<Vec<String>>, // default: every image in the project
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReorderAnnotationsRequest {
+    pub annotation_ids: Vec<String>, // bottom to top; each gets its position as z_index
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateGroupRequest {
+    pub annotation_ids: Vec<String>,
+    pub group_id: Option<String>, // add to this existing group instead of starting one
+}
+
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+pub struct ReviewAnnotationRequest {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct BulkReviewRequest {
+    pub annotation_ids: Vec<String>,
+    pub status: String, // pending | approved | rejected
+    pub reason: Option<String>,
+}
+
 // ========== COMMENT ==========
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Comment {
     pub comment_id: String,
     pub image_id: String,
+    pub annotation_id: Option<String>, // shape the feedback points at
+    pub anchor: Option<Point>,         // image coordinates of the pin
     pub user_id: String,
     pub text: String,
     pub resolved: bool,
     pub created_at: String,
 }
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateCommentRequest {
+    pub text: String,
+    pub annotation_id: Option<String>,
+    pub anchor: Option<Point>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct UpdateCommentRequest {
+    pub text: Option<String>,
+    pub resolved: Option<bool>,
+}
@@ -1,6 +1,45 @@
 use lambda_http::{Body, Error, Response};
 use aws_sdk_dynamodb::Client as DynamoClient;
-use crate::types::{User, CreateUserRequest, UpdateUserRequest};
+use crate::types::{User, CreateUserRequest, UpdateUserRequest, Onboarding};
+
+/// Onboarding steps in the order the guided setup walks through them
+pub const ONBOARDING_STEPS: [&str; 3] = ["profile_completed", "first_project_created", "tutorial_done"];
+
+enum StepError {
+    Unknown,
+    OutOfOrder(&'static str),
+}
+
+fn parse_onboarding(item: &std::collections::HashMap<String, aws_sdk_dynamodb::types::AttributeValue>) -> Onboarding {
+    item.get("onboarding")
+        .and_then(|v| v.as_s().ok())
+        .and_then(|s| serde_json::from_str(s).ok())
+        .unwrap_or_default()
+}
+
+/// Mark a step complete. Re-completing a finished step is a no-op;
+/// completing a step before its predecessors is rejected.
+fn complete_step(onboarding: &mut Onboarding, step: &str, now: &str) -> Result<(), StepError> {
+    let index = ONBOARDING_STEPS.iter().position(|s| *s == step).ok_or(StepError::Unknown)?;
+    let done = [
+        onboarding.profile_completed,
+        onboarding.first_project_created,
+        onboarding.tutorial_done,
+    ];
+    if let Some(pending) = (0..index).find(|i| !done[*i]) {
+        return Err(StepError::OutOfOrder(ONBOARDING_STEPS[pending]));
+    }
+
+    match index {
+        0 => onboarding.profile_completed = true,
+        1 => onboarding.first_project_created = true,
+        _ => onboarding.tutorial_done = true,
+    }
+    if onboarding.tutorial_done && onboarding.completed_at.is_none() {
+        onboarding.completed_at = Some(now.to_string());
+    }
+    Ok(())
+}
 
 /// Create user in DynamoDB after Cognito signup
 /// This is called once after user signs up in Cognito
@@ -40,6 +79,7 @@ pub async fn create_user(
         role: req.role,
         created_at: now,
         last_login: None,
+        onboarding: Onboarding::default(),
     };
 
     let resp = Response::builder()
@@ -77,6 +117,7 @@ pub async fn get_user(
         let role = item.get("role").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default();
         let created_at = item.get("created_at").and_then(|v| v.as_s().ok()).map(|s| s.to_string()).unwrap_or_default();
         let _last_login = item.get("last_login").and_then(|v| v.as_s().ok()).map(|s| s.to_string());
+        let onboarding = parse_onboarding(item);
         
         // Update last_login on every get
         let now = chrono::Utc::now().to_rfc3339();
@@ -98,6 +139,7 @@ pub async fn get_user(
             role: role.clone(),
             created_at: created_at.clone(),
             last_login: Some(now.clone()),
+            onboarding,
         };
         
         tracing::info!("User object: user_id={}, name='{}', email={}, company={:?}, role={}, created_at={}, last_login={:?}", 
@@ -124,6 +166,80 @@ pub async fn get_user(
     }
 }
 
+/// Resolve emails (compared case-insensitively) to user ids. There is no
+/// email index, so this scans the profile items once for the whole list.
+pub async fn find_users_by_email(
+    client: &DynamoClient,
+    table_name: &str,
+    emails: &[String],
+) -> Result<std::collections::HashMap<String, String>, Error> {
+    let wanted: std::collections::HashSet<String> = emails.iter().map(|e| e.trim().to_lowercase()).collect();
+    let mut found = std::collections::HashMap::new();
+    let mut last_key = None;
+    loop {
+        let result = client
+            .scan()
+            .table_name(table_name)
+            .filter_expression("begins_with(PK, :user) AND begins_with(SK, :user) AND attribute_exists(email)")
+            .expression_attribute_values(":user", aws_sdk_dynamodb::types::AttributeValue::S("USER#".to_string()))
+            .projection_expression("PK, SK, email")
+            .set_exclusive_start_key(last_key)
+            .send()
+            .await?;
+        for item in result.items() {
+            let (Some(pk), Some(sk), Some(email)) = (
+                item.get("PK").and_then(|v| v.as_s().ok()),
+                item.get("SK").and_then(|v| v.as_s().ok()),
+                item.get("email").and_then(|v| v.as_s().ok()),
+            ) else {
+                continue;
+            };
+            let email = email.trim().to_lowercase();
+            if pk == sk && wanted.contains(&email) {
+                found.insert(email, pk.trim_start_matches("USER#").to_string());
+            }
+        }
+        last_key = result.last_evaluated_key().cloned();
+        if last_key.is_none() {
+            return Ok(found);
+        }
+    }
+}
+
+/// Look up a user's role from their profile item
+pub async fn get_user_role(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<Option<String>, Error> {
+    let pk = format!("USER#{}", user_id);
+
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .projection_expression("#role")
+        .expression_attribute_names("#role", "role")
+        .send()
+        .await?;
+
+    Ok(result
+        .item()
+        .and_then(|item| item.get("role"))
+        .and_then(|v| v.as_s().ok())
+        .map(|s| s.to_string()))
+}
+
+/// Whether the user has the admin role
+pub async fn is_admin(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<bool, Error> {
+    Ok(get_user_role(client, table_name, user_id).await?.as_deref() == Some("admin"))
+}
+
 /// Update user
 pub async fn update_user(
     client: &DynamoClient,
@@ -177,3 +293,165 @@ pub async fn update_user(
     // Return updated user
     get_user(client, table_name, user_id).await
 }
+
+fn user_not_found() -> Result<Response<Body>, Error> {
+    let resp = Response::builder()
+        .status(404)
+        .header("content-type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::json!({"error": "User not found"}).to_string().into())
+        .map_err(Box::new)?;
+    Ok(resp)
+}
+
+fn onboarding_response(onboarding: &Onboarding) -> Result<Response<Body>, Error> {
+    let resp = Response::builder()
+        .status(200)
+        .header("content-type", "application/json")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(serde_json::to_string(onboarding)?.into())
+        .map_err(Box::new)?;
+    Ok(resp)
+}
+
+/// Store the onboarding object as a JSON string on the user profile item
+async fn save_onboarding(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    onboarding: &Onboarding,
+) -> Result<bool, Error> {
+    let pk = format!("USER#{}", user_id);
+
+    let result = client
+        .update_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .update_expression("SET onboarding = :onboarding")
+        .condition_expression("attribute_exists(PK)")
+        .expression_attribute_values(":onboarding", aws_sdk_dynamodb::types::AttributeValue::S(serde_json::to_string(onboarding)?))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(true),
+        Err(e) if e.as_service_error().map(|se| se.is_conditional_check_failed_exception()).unwrap_or(false) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get onboarding progress for the current user
+pub async fn get_onboarding(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<Response<Body>, Error> {
+    let pk = format!("USER#{}", user_id);
+
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .send()
+        .await?;
+
+    match result.item() {
+        Some(item) => onboarding_response(&parse_onboarding(item)),
+        None => user_not_found(),
+    }
+}
+
+/// Complete an onboarding step (POST /users/me/onboarding/{step})
+pub async fn complete_onboarding_step(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+    step: &str,
+) -> Result<Response<Body>, Error> {
+    let pk = format!("USER#{}", user_id);
+
+    let result = client
+        .get_item()
+        .table_name(table_name)
+        .key("PK", aws_sdk_dynamodb::types::AttributeValue::S(pk.clone()))
+        .key("SK", aws_sdk_dynamodb::types::AttributeValue::S(pk))
+        .send()
+        .await?;
+
+    let Some(item) = result.item() else {
+        return user_not_found();
+    };
+    let mut onboarding = parse_onboarding(item);
+
+    let now = chrono::Utc::now().to_rfc3339();
+    match complete_step(&mut onboarding, step, &now) {
+        Ok(()) => {}
+        Err(StepError::Unknown) => {
+            let resp = Response::builder()
+                .status(400)
+                .header("content-type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(serde_json::json!({
+                    "error": format!("Unknown onboarding step '{}'", step),
+                    "steps": ONBOARDING_STEPS,
+                }).to_string().into())
+                .map_err(Box::new)?;
+            return Ok(resp);
+        }
+        Err(StepError::OutOfOrder(pending)) => {
+            let resp = Response::builder()
+                .status(409)
+                .header("content-type", "application/json")
+                .header("Access-Control-Allow-Origin", "*")
+                .body(serde_json::json!({
+                    "error": format!("Step '{}' must be completed first", pending),
+                }).to_string().into())
+                .map_err(Box::new)?;
+            return Ok(resp);
+        }
+    }
+
+    if !save_onboarding(client, table_name, user_id, &onboarding).await? {
+        return user_not_found();
+    }
+
+    tracing::info!("Onboarding step '{}' completed for user {}", step, user_id);
+    onboarding_response(&onboarding)
+}
+
+/// Reset onboarding so the guided setup can be replayed
+pub async fn reset_onboarding(
+    client: &DynamoClient,
+    table_name: &str,
+    user_id: &str,
+) -> Result<Response<Body>, Error> {
+    let onboarding = Onboarding::default();
+    if !save_onboarding(client, table_name, user_id, &onboarding).await? {
+        return user_not_found();
+    }
+    onboarding_response(&onboarding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onboarding_steps_in_order() {
+        let mut onboarding = Onboarding::default();
+
+        assert!(matches!(complete_step(&mut onboarding, "tutorial_done", "t0"), Err(StepError::OutOfOrder("profile_completed"))));
+        assert!(matches!(complete_step(&mut onboarding, "bogus", "t0"), Err(StepError::Unknown)));
+
+        assert!(complete_step(&mut onboarding, "profile_completed", "t1").is_ok());
+        assert!(complete_step(&mut onboarding, "profile_completed", "t1").is_ok());
+        assert!(matches!(complete_step(&mut onboarding, "tutorial_done", "t2"), Err(StepError::OutOfOrder("first_project_created"))));
+        assert!(complete_step(&mut onboarding, "first_project_created", "t2").is_ok());
+        assert!(onboarding.completed_at.is_none());
+
+        assert!(complete_step(&mut onboarding, "tutorial_done", "t3").is_ok());
+        assert_eq!(onboarding.completed_at.as_deref(), Some("t3"));
+    }
+}